@@ -0,0 +1,42 @@
+#!/usr/bin/env python3
+"""Regenerate tests/fixtures/books.db, the database src/sqlite.rs is tested against.
+
+    python3 scripts/sqlite_fixture.py tests/fixtures/books.db
+
+Small pages make a few dozen rows enough for interior table pages, and one long
+description spills across several overflow pages.
+"""
+import os
+import sqlite3
+import sys
+
+path = sys.argv[1]
+if os.path.exists(path):
+    os.remove(path)
+db = sqlite3.connect(path)
+db.execute("PRAGMA page_size = 512")
+db.execute(
+    'CREATE TABLE books (id INTEGER PRIMARY KEY, title TEXT NOT NULL, "sort key" TEXT, '
+    "rating REAL, pages INTEGER, cover BLOB, CONSTRAINT positive CHECK (rating >= 0))"
+)
+for n in range(1, 61):
+    db.execute(
+        'INSERT INTO books (id, title, "sort key", rating, pages, cover) VALUES (?, ?, ?, ?, ?, ?)',
+        (n, "Book %d" % n, None if n % 7 == 0 else "book %03d" % n, n / 4, n * 10, bytes([n, 0, 255])),
+    )
+# Integers of every stored width, 0 and 1 as their own serial types, and a large rowid
+for rowid, pages in [(100, 0), (101, 1), (102, -129), (103, 70000), (104, -(2**31)), (105, 2**40), (106, -(2**62))]:
+    db.execute("INSERT INTO books (id, title, pages) VALUES (?, ?, ?)", (rowid, "Width %d" % rowid, pages))
+db.execute("INSERT INTO books (id, title) VALUES (?, ?)", (2**40 + 7, "Far rowid"))
+# Rows written before a column was added read it as NULL
+db.execute("ALTER TABLE books ADD COLUMN description TEXT")
+db.execute(
+    "INSERT INTO books (id, title, description) VALUES (?, ?, ?)",
+    (200, "Long", "".join("%04d " % i for i in range(600))),
+)
+db.execute("CREATE TABLE authors (name TEXT)")
+db.execute("INSERT INTO authors VALUES ('Ünïcödé Äuthor')")
+db.execute("CREATE INDEX books_title ON books (title)")
+db.commit()
+db.execute("VACUUM")
+db.close()
@@ -0,0 +1,65 @@
+#!/usr/bin/env python3
+"""Regenerate src/normalize/tables.rs from Python's Unicode database.
+
+    python3 scripts/unicode_tables.py > src/normalize/tables.rs
+"""
+import sys
+import unicodedata
+
+HANGUL = range(0xAC00, 0xD7A4)
+
+
+def esc(s):
+    return "".join("\\u{%X}" % ord(c) for c in s)
+
+
+def chars():
+    for cp in range(sys.maxunicode + 1):
+        if 0xD800 <= cp <= 0xDFFF or cp in HANGUL:
+            continue
+        yield chr(cp)
+
+
+canonical, compat, ccc, compose = [], [], [], []
+for c in chars():
+    nfd = unicodedata.normalize("NFD", c)
+    nfkd = unicodedata.normalize("NFKD", c)
+    if nfd != c:
+        canonical.append((c, nfd))
+    if nfkd != nfd:
+        compat.append((c, nfkd))
+    cls = unicodedata.combining(c)
+    if cls:
+        if ccc and ccc[-1][1] == ord(c) - 1 and ccc[-1][2] == cls:
+            ccc[-1][1] = ord(c)
+        else:
+            ccc.append([ord(c), ord(c), cls])
+    raw = unicodedata.decomposition(c)
+    if raw and not raw.startswith("<"):
+        parts = [chr(int(p, 16)) for p in raw.split()]
+        if len(parts) == 2 and unicodedata.normalize("NFC", nfd) == c:
+            compose.append((parts[0], parts[1], c))
+compose.sort(key=lambda t: (ord(t[0]), ord(t[1])))
+
+out = sys.stdout
+out.write("// Generated by scripts/unicode_tables.py from Unicode %s; do not edit.\n\n" % unicodedata.unidata_version)
+out.write("/// Full canonical decompositions, excluding Hangul syllables\n")
+out.write("pub const CANONICAL: &[(char, &str)] = &[\n")
+for c, d in canonical:
+    out.write("    ('%s', \"%s\"),\n" % (esc(c), esc(d)))
+out.write("];\n\n")
+out.write("/// Full compatibility decompositions that differ from the canonical one\n")
+out.write("pub const COMPATIBILITY: &[(char, &str)] = &[\n")
+for c, d in compat:
+    out.write("    ('%s', \"%s\"),\n" % (esc(c), esc(d)))
+out.write("];\n\n")
+out.write("/// Ranges of characters with a non-zero canonical combining class\n")
+out.write("pub const COMBINING_CLASS: &[(char, char, u8)] = &[\n")
+for lo, hi, cls in ccc:
+    out.write("    ('%s', '%s', %d),\n" % (esc(chr(lo)), esc(chr(hi)), cls))
+out.write("];\n\n")
+out.write("/// Primary composites: (starter, combining character, composed), sorted by the pair\n")
+out.write("pub const COMPOSITION: &[(char, char, char)] = &[\n")
+for a, b, c in compose:
+    out.write("    ('%s', '%s', '%s'),\n" % (esc(a), esc(b), esc(c)))
+out.write("];\n")
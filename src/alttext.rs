@@ -0,0 +1,165 @@
+use crate::check::ScratchDir;
+use crate::images::next_image;
+use crate::toc;
+use anyhow::{bail, Context, Result};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+/// Placeholder in an `--alt-text-cmd` template for the image's file
+const FILE: &str = "{file}";
+
+/// `--alt-text-cmd`: a program run for each image that has no alt text, whose standard
+/// output becomes the description. The template is split into words as a shell would
+/// (quotes group, backslash escapes) but is not run by a shell; `{file}` in any word is
+/// replaced by the image's path, which is added as a last argument when no word has it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AltTextCommand {
+    words: Vec<String>,
+}
+
+impl AltTextCommand {
+    pub fn parse(template: &str) -> Result<Self, String> {
+        let words = split_words(template)?;
+        if words.is_empty() {
+            return Err("the alt text command is empty".to_string());
+        }
+        Ok(Self { words })
+    }
+
+    /// Describe the image in `file`
+    fn describe(&self, file: &Path) -> Result<String> {
+        let path = file.to_string_lossy();
+        let mut args: Vec<String> = self.words[1..].iter().map(|w| w.replace(FILE, &path)).collect();
+        if !self.words.iter().any(|w| w.contains(FILE)) {
+            args.push(path.into_owned());
+        }
+        let program = &self.words[0];
+        let output = Command::new(program).args(&args).output().with_context(|| format!("Failed to run {}", program))?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+            bail!("{} failed: {}", program, if stderr.is_empty() { output.status.to_string() } else { stderr });
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).split_whitespace().collect::<Vec<_>>().join(" "))
+    }
+}
+
+/// Fills in the alt text of a book's images with an `AltTextCommand`. Each image is
+/// described once, however many chapters show it.
+pub struct AltTextWriter<'a> {
+    command: &'a AltTextCommand,
+    scratch: Option<ScratchDir>,
+    /// Description of each archive path asked about; empty when there was none to give
+    described: HashMap<String, String>,
+    /// Images whose command failed, with why
+    pub failures: Vec<(String, String)>,
+}
+
+impl<'a> AltTextWriter<'a> {
+    pub fn new(command: &'a AltTextCommand) -> Self {
+        Self { command, scratch: None, described: HashMap::new(), failures: Vec::new() }
+    }
+
+    /// Give each `![](target)` of a chapter the description of its image. Targets are
+    /// resolved against `source`, the chapter's path inside the EPUB, and their bytes
+    /// looked up with `resource`. Images the command can't describe keep their empty alt.
+    pub fn fill(&mut self, markdown: &str, source: &str, mut resource: impl FnMut(&str) -> Result<Option<(Vec<u8>, String)>>) -> Result<String> {
+        let base = source.rsplit_once('/').map(|(dir, _)| dir).unwrap_or_default();
+        let mut out = String::with_capacity(markdown.len());
+        let mut rest = markdown;
+        while let Some((before, alt, target, after)) = next_image(rest) {
+            out.push_str(before);
+            let consumed = rest.len() - after.len();
+            let image = &rest[before.len()..consumed];
+            rest = after;
+            if !alt.trim().is_empty() {
+                out.push_str(image);
+                continue;
+            }
+            let path = toc::target_path(Path::new(&format!("{}/{}", base, target)));
+            if !self.described.contains_key(&path) {
+                let description = match resource(&path)? {
+                    Some((data, _)) => self.describe(&path, &data)?,
+                    None => String::new(),
+                };
+                self.described.insert(path.clone(), description);
+            }
+            match self.described.get(&path).filter(|d| !d.is_empty()) {
+                Some(description) => out.push_str(&format!("![{}]{}", escape(description), &image[alt.len() + 3..])),
+                None => out.push_str(image),
+            }
+        }
+        out.push_str(rest);
+        Ok(out)
+    }
+
+    /// Write the image out for the command and run it; a failure is recorded, not returned
+    fn describe(&mut self, path: &str, data: &[u8]) -> Result<String> {
+        if self.scratch.is_none() {
+            self.scratch = Some(ScratchDir::new("alt-text")?);
+        }
+        let dir = self.scratch.as_ref().map(ScratchDir::path).unwrap_or(Path::new("."));
+        // Keep the file name, since captioning tools often go by the extension
+        let name = path.rsplit('/').next().unwrap_or(path);
+        let file = dir.join(format!("{}-{}", self.described.len(), name));
+        fs::write(&file, data).with_context(|| format!("Failed to write {}", file.display()))?;
+        let description = match self.command.describe(&file) {
+            Ok(description) => description,
+            Err(e) => {
+                self.failures.push((path.to_string(), format!("{:#}", e)));
+                String::new()
+            }
+        };
+        let _ = fs::remove_file(&file);
+        Ok(description)
+    }
+}
+
+/// A description as alt text: brackets escaped, so it can't end the alt early
+fn escape(description: &str) -> String {
+    description.replace('\\', "\\\\").replace('[', "\\[").replace(']', "\\]")
+}
+
+/// Split a command line into words: whitespace separates them, single quotes keep
+/// everything literal, double quotes keep whitespace, and a backslash escapes the next
+/// character outside single quotes
+fn split_words(line: &str) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut quote: Option<char> = None;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some('\''), '\'') | (Some('"'), '"') => quote = None,
+            (Some('\''), c) => word.push(c),
+            (_, '\\') => {
+                word.push(chars.next().ok_or("the alt text command ends in a backslash")?);
+                in_word = true;
+            }
+            (Some(_), c) => word.push(c),
+            (None, '\'' | '"') => {
+                quote = Some(c);
+                in_word = true;
+            }
+            (None, c) if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            (None, c) => {
+                word.push(c);
+                in_word = true;
+            }
+        }
+    }
+    if quote.is_some() {
+        return Err("the alt text command has an unclosed quote".to_string());
+    }
+    if in_word {
+        words.push(word);
+    }
+    Ok(words)
+}
@@ -0,0 +1,351 @@
+use clap::ValueEnum;
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+/// Analyses `--analyze` can add to a book's analysis.json
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Analysis {
+    /// Word frequencies, the book's and each chapter's keywords, and named-entity candidates
+    Keywords,
+}
+
+/// Keywords and entity candidates listed for the whole book
+const BOOK_TERMS: usize = 50;
+
+/// Keywords listed for each chapter
+const CHAPTER_TERMS: usize = 10;
+
+/// Shortest word counted as a keyword
+const MIN_KEYWORD_LEN: usize = 3;
+
+/// Words that connect the parts of a name ("Bank of England", "Ludwig van Beethoven")
+const NAME_CONNECTORS: &[&str] = &["of", "the", "de", "du", "la", "le", "van", "von", "der", "den", "da", "di", "del", "al", "bin"];
+
+/// Abbreviated titles, whose full stop doesn't end a sentence
+const TITLES: &[&str] = &["Mr", "Mrs", "Ms", "Dr", "St", "Prof", "Rev", "Capt", "Col", "Gen", "Lt", "Sgt", "Mt", "Jr", "Sr"];
+
+/// Common English words, never keywords
+const STOP_WORDS: &[&str] = &[
+    "a", "about", "above", "after", "again", "against", "all", "also", "am", "an", "and", "any", "are", "as", "at", "be",
+    "because", "been", "before", "being", "below", "between", "both", "but", "by", "can", "could", "did", "do", "does",
+    "doing", "down", "during", "each", "even", "ever", "every", "few", "for", "from", "further", "had", "has", "have",
+    "having", "he", "her", "here", "hers", "herself", "him", "himself", "his", "how", "however", "i", "if", "in", "into",
+    "is", "it", "its", "itself", "just", "like", "made", "make", "many", "may", "me", "might", "more", "most", "much",
+    "must", "my", "myself", "never", "no", "nor", "not", "now", "of", "off", "on", "once", "one", "only", "or", "other",
+    "our", "ours", "ourselves", "out", "over", "own", "said", "same", "say", "see", "shall", "she", "should", "so",
+    "some", "still", "such", "than", "that", "the", "their", "theirs", "them", "themselves", "then", "there", "these",
+    "they", "this", "those", "though", "through", "thus", "to", "too", "two", "under", "until", "up", "upon", "us",
+    "very", "was", "way", "we", "well", "were", "what", "when", "where", "whether", "which", "while", "who", "whom",
+    "whose", "why", "will", "with", "within", "without", "would", "yet", "you", "your", "yours", "yourself", "yourselves",
+];
+
+/// analysis.json: what `--analyze` found in one book
+#[derive(Debug, Default, Serialize)]
+pub struct BookAnalysis {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keywords: Option<KeywordReport>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct KeywordReport {
+    /// Words in the converted text
+    pub words: usize,
+    /// Distinct words, ignoring case
+    pub unique_words: usize,
+    /// Most frequent words other than common English ones, most frequent first
+    pub keywords: Vec<Term>,
+    /// Capitalized words and phrases that look like names of people, places or
+    /// organizations, most frequent first
+    pub entities: Vec<Term>,
+    pub chapters: Vec<ChapterKeywords>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Term {
+    pub term: String,
+    pub count: usize,
+    /// Chapter numbers the term appears in
+    pub chapters: Vec<usize>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChapterKeywords {
+    pub chapter: usize,
+    pub words: usize,
+    /// Words frequent in this chapter but not throughout the book, the most distinctive first
+    pub keywords: Vec<String>,
+}
+
+#[derive(Default)]
+struct Counts {
+    total: usize,
+    chapters: BTreeSet<usize>,
+}
+
+impl Counts {
+    fn add(&mut self, chapter: usize) {
+        self.total += 1;
+        self.chapters.insert(chapter);
+    }
+}
+
+/// Collects word and name counts chapter by chapter, for `--analyze keywords`
+#[derive(Default)]
+pub struct KeywordAnalyzer {
+    words: usize,
+    /// Every distinct word, in lower case
+    vocabulary: HashSet<String>,
+    /// Words written in lower case
+    lower: HashMap<String, Counts>,
+    /// Capitalized words opening a sentence, which are only common words if they also
+    /// appear in lower case
+    openers: HashMap<String, Counts>,
+    /// Candidate names, and how often each appeared mid-sentence
+    entities: HashMap<String, (Counts, usize)>,
+    /// Lower-case word counts of each chapter
+    chapters: Vec<(usize, usize, HashMap<String, usize>)>,
+}
+
+impl KeywordAnalyzer {
+    pub fn add_chapter(&mut self, number: usize, markdown: &str) {
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        let mut words = 0;
+        for line in prose_lines(markdown) {
+            let tokens = tokens(&line);
+            words += tokens.len();
+            self.vocabulary.extend(tokens.iter().map(|t| t.word.to_lowercase()));
+            let mut i = 0;
+            while i < tokens.len() {
+                let token = &tokens[i];
+                if !is_capitalized(&token.word) {
+                    let word = token.word.to_lowercase();
+                    if is_keyword(&word) {
+                        *counts.entry(word.clone()).or_default() += 1;
+                        self.lower.entry(word).or_default().add(number);
+                    }
+                    i += 1;
+                    continue;
+                }
+
+                // A run of capitalized words, possibly joined by connectors, separated only by spaces
+                let start = i;
+                let mut end = i + 1;
+                while end < tokens.len() && tokens[end].joined {
+                    if is_capitalized(&tokens[end].word) {
+                        end += 1;
+                    } else if NAME_CONNECTORS.contains(&tokens[end].word.as_str())
+                        && tokens.get(end + 1).is_some_and(|next| next.joined && is_capitalized(&next.word))
+                    {
+                        end += 2;
+                    } else {
+                        break;
+                    }
+                }
+                i = end;
+
+                let mut run = &tokens[start..end];
+                let opener = run[0].sentence_start;
+                if opener {
+                    let first = run[0].word.to_lowercase();
+                    if is_keyword(&first) {
+                        self.openers.entry(first).or_default().add(number);
+                    }
+                    // "The Hague" at the start of a sentence is still "Hague"-like, but "The" is not part of a name
+                    if STOP_WORDS.contains(&run[0].word.to_lowercase().as_str()) {
+                        run = &run[1..];
+                        while run.first().is_some_and(|t| NAME_CONNECTORS.contains(&t.word.as_str())) {
+                            run = &run[1..];
+                        }
+                    }
+                }
+                if run.is_empty() || (run.len() == 1 && run[0].word.chars().count() < 2) {
+                    continue;
+                }
+                let name = run.iter().map(|t| t.word.as_str()).collect::<Vec<_>>().join(" ");
+                let entry = self.entities.entry(name).or_insert_with(|| (Counts::default(), 0));
+                entry.0.add(number);
+                if !opener {
+                    entry.1 += 1;
+                }
+            }
+        }
+        self.words += words;
+        self.chapters.push((number, words, counts));
+    }
+
+    pub fn finish(mut self) -> KeywordReport {
+        // Sentence openers that also occur in lower case are ordinary words
+        for (word, counts) in std::mem::take(&mut self.openers) {
+            if let Some(lower) = self.lower.get_mut(&word) {
+                lower.total += counts.total;
+                lower.chapters.extend(counts.chapters);
+            }
+        }
+
+        let mut keywords: Vec<Term> = self
+            .lower
+            .iter()
+            .map(|(word, counts)| Term { term: word.clone(), count: counts.total, chapters: counts.chapters.iter().copied().collect() })
+            .collect();
+        sort_terms(&mut keywords);
+        keywords.truncate(BOOK_TERMS);
+
+        let mut entities: Vec<Term> = self
+            .entities
+            .iter()
+            .filter(|(name, (counts, mid_sentence))| {
+                // A single word seen only at the start of sentences is most likely an ordinary word
+                let ordinary = !name.contains(' ') && (*mid_sentence == 0 || self.lower.contains_key(&name.to_lowercase()));
+                counts.total >= 2 && !ordinary
+            })
+            .map(|(name, (counts, _))| Term { term: name.clone(), count: counts.total, chapters: counts.chapters.iter().copied().collect() })
+            .collect();
+        sort_terms(&mut entities);
+        entities.truncate(BOOK_TERMS);
+
+        // Weigh each chapter's counts by how few chapters share the word
+        let chapter_count = self.chapters.len() as f64;
+        let chapters = self
+            .chapters
+            .iter()
+            .map(|(number, words, counts)| {
+                let mut scored: Vec<(&String, f64)> = counts
+                    .iter()
+                    .filter(|(_, &count)| count >= 2)
+                    .map(|(word, &count)| {
+                        let spread = self.lower.get(word).map(|c| c.chapters.len()).unwrap_or(1) as f64;
+                        (word, count as f64 * (1.0 + chapter_count / spread).ln())
+                    })
+                    .collect();
+                scored.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+                ChapterKeywords {
+                    chapter: *number,
+                    words: *words,
+                    keywords: scored.into_iter().take(CHAPTER_TERMS).map(|(word, _)| word.clone()).collect(),
+                }
+            })
+            .collect();
+
+        KeywordReport { words: self.words, unique_words: self.vocabulary.len(), keywords, entities, chapters }
+    }
+}
+
+fn sort_terms(terms: &mut [Term]) {
+    terms.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.term.cmp(&b.term)));
+}
+
+fn is_keyword(word: &str) -> bool {
+    word.chars().count() >= MIN_KEYWORD_LEN
+        && word.chars().any(char::is_alphabetic)
+        && !word.contains(['\'', '’'])
+        && !STOP_WORDS.contains(&word)
+}
+
+/// Whether a word could be part of a name. Contractions such as "I'm" can't.
+fn is_capitalized(word: &str) -> bool {
+    word.chars().next().is_some_and(char::is_uppercase) && !word.contains(['\'', '’'])
+}
+
+/// Words of a line of prose, as written
+pub fn words(line: &str) -> impl Iterator<Item = String> {
+    tokens(line).into_iter().map(|t| t.word)
+}
+
+struct Token {
+    word: String,
+    /// Whether the word opens a sentence
+    sentence_start: bool,
+    /// Whether only spaces separate it from the word before
+    joined: bool,
+}
+
+/// Words of a line of prose. Possessive `'s` is dropped, so "Holmes's" counts as "Holmes".
+fn tokens(line: &str) -> Vec<Token> {
+    fn push(tokens: &mut Vec<Token>, word: &mut String, sentence_start: bool, joined: bool) {
+        let text = word.strip_suffix("'s").or_else(|| word.strip_suffix("’s")).unwrap_or(word).to_string();
+        tokens.push(Token { word: text, sentence_start, joined });
+        word.clear();
+    }
+
+    let mut tokens: Vec<Token> = Vec::new();
+    let mut sentence_start = true;
+    let mut joined = false;
+    let mut word = String::new();
+    let chars: Vec<char> = line.chars().collect();
+    for (i, &c) in chars.iter().enumerate() {
+        let inner_apostrophe =
+            matches!(c, '\'' | '’') && !word.is_empty() && chars.get(i + 1).is_some_and(|n| n.is_alphabetic());
+        if c.is_alphanumeric() || inner_apostrophe {
+            word.push(c);
+            continue;
+        }
+        let ends_word = !word.is_empty();
+        if ends_word {
+            push(&mut tokens, &mut word, sentence_start, joined);
+            sentence_start = false;
+            joined = true;
+        }
+        // "Dr. Watson" doesn't start a sentence at "Watson", and is one name
+        let title = c == '.' && ends_word && tokens.last().is_some_and(|t| TITLES.contains(&t.word.as_str()));
+        if matches!(c, '.' | '!' | '?' | '…') && !title {
+            sentence_start = true;
+        }
+        if !c.is_whitespace() && !title {
+            joined = false;
+        }
+    }
+    if !word.is_empty() {
+        push(&mut tokens, &mut word, sentence_start, joined);
+    }
+    tokens
+}
+
+/// Lines of a chapter's text, without fenced code, HTML tags, link targets, images or
+/// the Markdown markers that open headings, quotes and list items
+pub fn prose_lines(markdown: &str) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut in_fence = false;
+    for line in markdown.lines() {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_fence = !in_fence;
+            continue;
+        }
+        if in_fence {
+            continue;
+        }
+        let mut text = String::with_capacity(line.len());
+        let mut rest = trimmed.trim_start_matches(['#', '>', ' ', '-', '*', '+', '|']);
+        while let Some(c) = rest.chars().next() {
+            if rest.starts_with("![") {
+                // Alt text describes an image, it isn't part of the text
+                match rest.find(')') {
+                    Some(end) => rest = &rest[end + 1..],
+                    None => rest = "",
+                }
+                continue;
+            }
+            if rest.starts_with("](") {
+                match rest.find(')') {
+                    Some(end) => rest = &rest[end + 1..],
+                    None => rest = "",
+                }
+                continue;
+            }
+            if c == '<' {
+                if let Some(end) = rest.find('>') {
+                    rest = &rest[end + 1..];
+                    text.push(' ');
+                    continue;
+                }
+            }
+            // Emphasis markers would otherwise split or hide words
+            if !matches!(c, '*' | '_' | '`' | '\\' | '[' | ']') {
+                text.push(c);
+            }
+            rest = &rest[c.len_utf8()..];
+        }
+        lines.push(text);
+    }
+    lines
+}
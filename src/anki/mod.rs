@@ -0,0 +1,341 @@
+use crate::digest::{self, Sha256};
+use crate::html;
+use crate::output;
+use crate::toc;
+use anyhow::{Context, Result};
+use markup5ever_rcdom::Handle;
+use serde_json::json;
+use sqlite::{Table, Value};
+use std::collections::HashSet;
+use std::io::{Cursor, Write};
+use std::path::Path;
+use std::time::SystemTime;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+mod sqlite;
+
+/// Headings that open a glossary when the markup doesn't say so
+const GLOSSARY_HEADINGS: &[&str] = &["glossary", "vocabulary", "word list", "key terms", "definitions", "terms"];
+
+/// Separates the fields of an Anki note
+const FIELD_SEPARATOR: char = '\u{1f}';
+
+/// A term and its definition, one flashcard
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub term: String,
+    pub definition: String,
+}
+
+/// What `--anki` writes, chosen by the file's extension
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeckFormat {
+    /// An Anki package, imported with File > Import as a deck of its own
+    Apkg,
+    /// Tab-separated notes with the headers Anki's text import reads
+    Tsv,
+}
+
+impl DeckFormat {
+    pub fn from_name(name: &str) -> Option<Self> {
+        let extension = name.rsplit_once('.')?.1.to_ascii_lowercase();
+        match extension.as_str() {
+            "apkg" => Some(DeckFormat::Apkg),
+            "tsv" | "txt" => Some(DeckFormat::Tsv),
+            _ => None,
+        }
+    }
+}
+
+/// Collect the term/definition pairs of a chapter's glossaries: definition lists inside
+/// elements marked `epub:type="glossary"` (or `role="doc-glossary"`), in a document or
+/// after a heading such as "Glossary", and `glossterm`/`glossdef` pairs.
+pub fn extract_entries(content: &str) -> Vec<Entry> {
+    let lower = content.to_ascii_lowercase();
+    if !lower.contains("gloss") && !GLOSSARY_HEADINGS.iter().any(|h| lower.contains(h)) {
+        return Vec::new();
+    }
+    let dom = html::parse(content);
+    let mut first_heading = None;
+    html::walk(&dom.document, &mut |node| {
+        if first_heading.is_none() && html::tag_name(node).is_some_and(|t| matches!(t.as_str(), "h1" | "h2" | "h3")) {
+            first_heading = Some(html::text_content(node));
+        }
+    });
+    let mut scan = Scan::default();
+    scan.visit(&dom.document, first_heading.as_deref().is_some_and(is_glossary_heading));
+    scan.entries
+}
+
+#[derive(Default)]
+struct Scan {
+    entries: Vec<Entry>,
+    /// A `glossterm` still waiting for its `glossdef`
+    term: Option<String>,
+}
+
+impl Scan {
+    fn visit(&mut self, node: &Handle, in_glossary: bool) {
+        // A matching heading makes the lists after it, up to the next heading, a glossary
+        let mut headed = false;
+        for child in node.children.borrow().iter() {
+            let Some(tag) = html::tag_name(child) else { continue };
+            if matches!(tag.as_str(), "h1" | "h2" | "h3" | "h4" | "h5" | "h6") {
+                headed = is_glossary_heading(&html::text_content(child));
+                continue;
+            }
+            let types = glossary_types(child);
+            let glossary = in_glossary || headed || types.iter().any(|t| t == "glossary");
+            if types.iter().any(|t| t == "glossterm") {
+                self.term = Some(html::text_content(child)).filter(|t| !t.is_empty());
+            } else if types.iter().any(|t| t == "glossdef") {
+                let definition = html::text_content(child);
+                if let Some(term) = self.term.take().filter(|_| !definition.is_empty()) {
+                    self.entries.push(Entry { term, definition });
+                }
+            } else if glossary && tag == "dl" {
+                self.definition_list(child);
+            } else {
+                self.visit(child, glossary);
+            }
+        }
+    }
+
+    /// Pair each `<dt>` with the `<dd>`s after it; several terms in a row share a definition
+    fn definition_list(&mut self, dl: &Handle) {
+        let mut items = Vec::new();
+        collect_items(dl, &mut items);
+        let mut terms: Vec<String> = Vec::new();
+        let mut definitions: Vec<String> = Vec::new();
+        for (is_term, text) in items.into_iter().chain([(true, String::new())]) {
+            if is_term && !definitions.is_empty() {
+                // Several senses are numbered
+                let definition = if definitions.len() == 1 {
+                    definitions[0].clone()
+                } else {
+                    definitions.iter().enumerate().map(|(n, d)| format!("{}. {}", n + 1, d)).collect::<Vec<_>>().join(" ")
+                };
+                for term in terms.drain(..) {
+                    self.entries.push(Entry { term, definition: definition.clone() });
+                }
+                definitions.clear();
+            }
+            if text.is_empty() {
+                continue;
+            }
+            if is_term {
+                terms.push(text);
+            } else if !terms.is_empty() {
+                definitions.push(text);
+            }
+        }
+    }
+}
+
+/// `<dt>` and `<dd>` children of a list, looking through the `<div>`s HTML allows around them
+fn collect_items(node: &Handle, items: &mut Vec<(bool, String)>) {
+    for child in node.children.borrow().iter() {
+        match html::tag_name(child).as_deref() {
+            Some("dt") => items.push((true, html::text_content(child))),
+            Some("dd") => items.push((false, html::text_content(child))),
+            Some("div") => collect_items(child, items),
+            _ => {}
+        }
+    }
+}
+
+/// Glossary-related `epub:type` and `role` values of an element, without the `doc-` prefix
+fn glossary_types(node: &Handle) -> Vec<String> {
+    [html::epub_type(node), html::attr(node, "role")]
+        .into_iter()
+        .flatten()
+        .flat_map(|v| v.split_whitespace().map(|t| t.trim_start_matches("doc-").to_string()).collect::<Vec<_>>())
+        .filter(|t| t.starts_with("gloss"))
+        .collect()
+}
+
+fn is_glossary_heading(text: &str) -> bool {
+    let text = text.trim().trim_end_matches([':', '.']).to_lowercase();
+    GLOSSARY_HEADINGS.contains(&text.as_str())
+}
+
+/// Write the entries as flashcards in a deck named `deck`, tagged with `tag`. Repeated
+/// terms keep their first definition. Note ids and timestamps derive from the deck name
+/// and `modified`, so reimporting a reconverted book updates its notes instead of
+/// duplicating them.
+pub fn write_deck(path: &Path, format: DeckFormat, deck: &str, tag: &str, entries: &[Entry], modified: SystemTime) -> Result<usize> {
+    let mut seen = HashSet::new();
+    let entries: Vec<&Entry> = entries.iter().filter(|e| seen.insert(e.term.to_lowercase())).collect();
+    let tag = toc::github_slug(tag);
+    let tag = if tag.is_empty() { "epub".to_string() } else { tag };
+    let contents = match format {
+        DeckFormat::Tsv => tsv(deck, &tag, &entries),
+        DeckFormat::Apkg => apkg(deck, &tag, &entries, modified)?,
+    };
+    output::write_atomic(path, contents).with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(entries.len())
+}
+
+fn tsv(deck: &str, tag: &str, entries: &[&Entry]) -> Vec<u8> {
+    let field = |text: &str| text.replace(['\t', '\n', '\r'], " ");
+    let mut out = format!("#separator:tab\n#html:false\n#notetype:Basic\n#deck:{}\n#tags column:3\n", field(deck));
+    for entry in entries {
+        out.push_str(&format!("{}\t{}\t{}\n", field(&entry.term), field(&entry.definition), tag));
+    }
+    out.into_bytes()
+}
+
+/// The schema of an Anki 2.1 collection (schema version 11), which current versions
+/// still import
+const COL_SQL: &str = "CREATE TABLE col (id integer primary key, crt integer not null, mod integer not null, scm integer not null, ver integer not null, dty integer not null, usn integer not null, ls integer not null, conf text not null, models text not null, decks text not null, dconf text not null, tags text not null)";
+const NOTES_SQL: &str = "CREATE TABLE notes (id integer primary key, guid text not null, mid integer not null, mod integer not null, usn integer not null, tags text not null, flds text not null, sfld integer not null, csum integer not null, flags integer not null, data text not null)";
+const CARDS_SQL: &str = "CREATE TABLE cards (id integer primary key, nid integer not null, did integer not null, ord integer not null, mod integer not null, usn integer not null, type integer not null, queue integer not null, due integer not null, ivl integer not null, factor integer not null, reps integer not null, lapses integer not null, left integer not null, odue integer not null, odid integer not null, flags integer not null, data text not null)";
+const REVLOG_SQL: &str = "CREATE TABLE revlog (id integer primary key, cid integer not null, usn integer not null, ivl integer not null, lastIvl integer not null, factor integer not null, time integer not null, type integer not null)";
+const GRAVES_SQL: &str = "CREATE TABLE graves (usn integer not null, oid integer not null, type integer not null)";
+
+/// Name of the note type the cards use
+const MODEL_NAME: &str = "epub-to-md Glossary";
+
+fn apkg(deck: &str, tag: &str, entries: &[&Entry], modified: SystemTime) -> Result<Vec<u8>> {
+    let secs = modified.duration_since(SystemTime::UNIX_EPOCH).map(|d| d.as_secs() as i64).unwrap_or(0);
+    let millis = secs * 1000;
+    let model_id = id(MODEL_NAME);
+    let deck_id = id(deck);
+
+    let mut notes = Vec::with_capacity(entries.len());
+    let mut cards = Vec::with_capacity(entries.len());
+    for (n, entry) in entries.iter().enumerate() {
+        let term = escape(&entry.term);
+        let note_id = deck_id + n as i64 + 1;
+        let checksum = digest::sha1(entry.term.as_bytes());
+        notes.push((
+            note_id,
+            vec![
+                Value::Null,
+                Value::Text(guid(deck, &entry.term)),
+                Value::Integer(model_id),
+                Value::Integer(secs),
+                Value::Integer(-1),
+                Value::Text(format!(" {} ", tag)),
+                Value::Text(format!("{}{}{}", term, FIELD_SEPARATOR, escape(&entry.definition))),
+                Value::Text(term),
+                Value::Integer(i64::from(u32::from_be_bytes(checksum[..4].try_into().unwrap()))),
+                Value::Integer(0),
+                Value::Text(String::new()),
+            ],
+        ));
+        let mut card = vec![Value::Null, Value::Integer(note_id), Value::Integer(deck_id), Value::Integer(0), Value::Integer(secs), Value::Integer(-1)];
+        // New cards (type 0, queue 0), introduced in list order
+        card.extend([0, 0, n as i64 + 1, 0, 0, 0, 0, 0, 0, 0, 0].map(Value::Integer));
+        card.push(Value::Text(String::new()));
+        cards.push((note_id, card));
+    }
+
+    let fields: Vec<_> = ["Term", "Definition"]
+        .iter()
+        .enumerate()
+        .map(|(ord, name)| json!({ "name": name, "ord": ord, "sticky": false, "rtl": false, "font": "Arial", "size": 20, "media": [] }))
+        .collect();
+    let model = json!({
+        "id": model_id,
+        "name": MODEL_NAME,
+        "type": 0,
+        "mod": secs,
+        "usn": -1,
+        "sortf": 0,
+        "did": deck_id,
+        "tmpls": [{
+            "name": "Card 1",
+            "ord": 0,
+            "qfmt": "{{Term}}",
+            "afmt": "{{FrontSide}}\n\n<hr id=answer>\n\n{{Definition}}",
+            "bqfmt": "",
+            "bafmt": "",
+            "did": null,
+            "bfont": "",
+            "bsize": 0,
+        }],
+        "flds": fields,
+        "css": ".card {\n font-family: arial;\n font-size: 20px;\n text-align: center;\n color: black;\n background-color: white;\n}\n",
+        "latexPre": "\\documentclass[12pt]{article}\n\\special{papersize=3in,5in}\n\\usepackage[utf8]{inputenc}\n\\usepackage{amssymb,amsmath}\n\\pagestyle{empty}\n\\setlength{\\parindent}{0in}\n\\begin{document}\n",
+        "latexPost": "\\end{document}",
+        "latexsvg": false,
+        "req": [[0, "any", [0]]],
+        "tags": [],
+        "vers": [],
+    });
+    let deck_json = |id: i64, name: &str| {
+        json!({
+            "id": id, "name": name, "desc": "", "mod": secs, "usn": -1, "conf": 1, "dyn": 0, "collapsed": false,
+            "extendNew": 10, "extendRev": 50, "newToday": [0, 0], "revToday": [0, 0], "lrnToday": [0, 0], "timeToday": [0, 0],
+        })
+    };
+    let decks = json!({ "1": deck_json(1, "Default"), deck_id.to_string(): deck_json(deck_id, deck) });
+    let dconf = json!({ "1": {
+        "id": 1, "name": "Default", "mod": 0, "usn": 0, "maxTaken": 60, "autoplay": true, "timer": 0, "replayq": true, "dyn": false,
+        "new": { "bury": true, "delays": [1, 10], "initialFactor": 2500, "ints": [1, 4, 7], "order": 1, "perDay": 20, "separate": true },
+        "lapse": { "delays": [10], "leechAction": 0, "leechFails": 8, "minInt": 1, "mult": 0 },
+        "rev": { "bury": true, "ease4": 1.3, "fuzz": 0.05, "ivlFct": 1, "maxIvl": 36500, "minSpace": 1, "perDay": 100 },
+    }});
+    let conf = json!({
+        "nextPos": entries.len() + 1, "estTimes": true, "activeDecks": [1], "sortType": "noteFld", "timeLim": 0,
+        "sortBackwards": false, "addToCur": true, "curDeck": 1, "newSpread": 0, "dueCounts": true,
+        "curModel": model_id.to_string(), "collapseTime": 1200,
+    });
+    let col = vec![
+        Value::Null,
+        Value::Integer(secs - secs.rem_euclid(86_400)),
+        Value::Integer(millis),
+        Value::Integer(millis),
+        Value::Integer(11),
+        Value::Integer(0),
+        Value::Integer(0),
+        Value::Integer(0),
+        Value::Text(conf.to_string()),
+        Value::Text(json!({ model_id.to_string(): model }).to_string()),
+        Value::Text(decks.to_string()),
+        Value::Text(dconf.to_string()),
+        Value::Text("{}".to_string()),
+    ];
+
+    let collection = sqlite::database(vec![
+        Table { name: "col", sql: COL_SQL, rows: vec![(1, col)] },
+        Table { name: "notes", sql: NOTES_SQL, rows: notes },
+        Table { name: "cards", sql: CARDS_SQL, rows: cards },
+        Table { name: "revlog", sql: REVLOG_SQL, rows: Vec::new() },
+        Table { name: "graves", sql: GRAVES_SQL, rows: Vec::new() },
+    ]);
+
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    let mut add = |name: &str, content: &[u8]| -> Result<()> {
+        zip.start_file(name, options).with_context(|| format!("Failed to add {} to the deck", name))?;
+        zip.write_all(content).with_context(|| format!("Failed to add {} to the deck", name))
+    };
+    add("collection.anki2", &collection)?;
+    add("media", b"{}")?;
+    Ok(zip.finish().context("Failed to finish the deck")?.into_inner())
+}
+
+/// A positive id in the range of the millisecond timestamps Anki uses, stable for `name`
+fn id(name: &str) -> i64 {
+    let mut hasher = Sha256::default();
+    hasher.update(name.as_bytes());
+    let hash = hasher.finalize();
+    let value = u64::from_be_bytes(hash[..8].try_into().unwrap()) % 1_000_000_000_000;
+    1_000_000_000_000 + value as i64
+}
+
+/// Note guid, stable for the same deck and term so reimports update the note
+fn guid(deck: &str, term: &str) -> String {
+    let mut hasher = Sha256::default();
+    hasher.update(deck.as_bytes());
+    hasher.update(&[0]);
+    hasher.update(term.to_lowercase().as_bytes());
+    hasher.finalize_hex()[..16].to_string()
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
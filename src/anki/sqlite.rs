@@ -0,0 +1,238 @@
+const PAGE_SIZE: usize = 4096;
+
+/// Largest payload kept entirely on a table leaf page
+const MAX_LOCAL: usize = PAGE_SIZE - 35;
+
+/// Smallest part of an overflowing payload kept on the leaf page
+const MIN_LOCAL: usize = (PAGE_SIZE - 12) * 32 / 255 - 23;
+
+/// A column value
+pub enum Value {
+    Null,
+    Integer(i64),
+    Text(String),
+}
+
+/// A table to write: its `CREATE TABLE` statement and its rows, as (rowid, columns). A
+/// column declared `integer primary key` is the rowid and must be given as `Null`.
+pub struct Table {
+    pub name: &'static str,
+    pub sql: &'static str,
+    pub rows: Vec<(i64, Vec<Value>)>,
+}
+
+/// Serialize the tables into a database file. This is just enough of the SQLite file
+/// format for the collection inside an `.apkg`: tables only, no indexes, rows given in
+/// rowid order, written in one go with no free pages.
+pub fn database(tables: Vec<Table>) -> Vec<u8> {
+    // Page 1 holds the header and sqlite_master, so tables start at page 2
+    let mut pages: Vec<Vec<u8>> = vec![Vec::new()];
+    let mut schema = Vec::with_capacity(tables.len());
+    for (n, table) in tables.into_iter().enumerate() {
+        let root = write_tree(&mut pages, &table.rows);
+        schema.push((
+            n as i64 + 1,
+            vec![
+                Value::Text("table".to_string()),
+                Value::Text(table.name.to_string()),
+                Value::Text(table.name.to_string()),
+                Value::Integer(root as i64),
+                Value::Text(table.sql.to_string()),
+            ],
+        ));
+    }
+
+    let cells: Vec<Vec<u8>> = schema.iter().map(|(rowid, values)| leaf_cell(&mut pages, *rowid, values)).collect();
+    let mut first = vec![0u8; PAGE_SIZE];
+    write_page(&mut first, 100, 0x0d, &cells, None);
+    first[..100].copy_from_slice(&header(pages.len()));
+    pages[0] = first;
+    pages.concat()
+}
+
+fn header(page_count: usize) -> [u8; 100] {
+    let mut header = [0u8; 100];
+    header[..16].copy_from_slice(b"SQLite format 3\0");
+    header[16..18].copy_from_slice(&(PAGE_SIZE as u16).to_be_bytes());
+    // File format versions (legacy journal), no reserved bytes, fixed payload fractions
+    header[18..24].copy_from_slice(&[1, 1, 0, 64, 32, 32]);
+    header[24..28].copy_from_slice(&1u32.to_be_bytes());
+    header[28..32].copy_from_slice(&(page_count as u32).to_be_bytes());
+    // Schema cookie, schema format 4, UTF-8 text
+    header[40..44].copy_from_slice(&1u32.to_be_bytes());
+    header[44..48].copy_from_slice(&4u32.to_be_bytes());
+    header[56..60].copy_from_slice(&1u32.to_be_bytes());
+    header[92..96].copy_from_slice(&1u32.to_be_bytes());
+    header[96..100].copy_from_slice(&3_045_000u32.to_be_bytes());
+    header
+}
+
+/// Write a table's B-tree and return its root page number
+fn write_tree(pages: &mut Vec<Vec<u8>>, rows: &[(i64, Vec<Value>)]) -> usize {
+    // Leaves first, then as many interior levels as it takes to reach a single root
+    let mut level: Vec<(usize, i64)> = Vec::new();
+    let mut cells: Vec<Vec<u8>> = Vec::new();
+    let mut used = 8;
+    let mut last_rowid = 0;
+    for (rowid, values) in rows {
+        let cell = leaf_cell(pages, *rowid, values);
+        if !cells.is_empty() && used + cell.len() + 2 > PAGE_SIZE {
+            level.push((leaf(pages, &cells), last_rowid));
+            cells.clear();
+            used = 8;
+        }
+        used += cell.len() + 2;
+        cells.push(cell);
+        last_rowid = *rowid;
+    }
+    level.push((leaf(pages, &cells), last_rowid));
+
+    while level.len() > 1 {
+        let mut parents = Vec::new();
+        let mut children: Vec<(usize, i64)> = Vec::new();
+        let mut used = 12;
+        for &(page, key) in &level {
+            let size = 4 + varint(key as u64).len() + 2;
+            if children.len() > 1 && used + size > PAGE_SIZE {
+                parents.push(interior(pages, &children));
+                children.clear();
+                used = 12;
+            }
+            used += size;
+            children.push((page, key));
+        }
+        parents.push(interior(pages, &children));
+        level = parents;
+    }
+    level[0].0
+}
+
+fn leaf(pages: &mut Vec<Vec<u8>>, cells: &[Vec<u8>]) -> usize {
+    let mut page = vec![0u8; PAGE_SIZE];
+    write_page(&mut page, 0, 0x0d, cells, None);
+    pages.push(page);
+    pages.len()
+}
+
+/// An interior page over `children`, given as (page, largest rowid in it); the last
+/// child is the page's right-most pointer. Returns its page number and largest rowid.
+fn interior(pages: &mut Vec<Vec<u8>>, children: &[(usize, i64)]) -> (usize, i64) {
+    let (last, last_key) = children[children.len() - 1];
+    let cells: Vec<Vec<u8>> = children[..children.len() - 1]
+        .iter()
+        .map(|&(page, key)| {
+            let mut cell = (page as u32).to_be_bytes().to_vec();
+            cell.extend(varint(key as u64));
+            cell
+        })
+        .collect();
+    let mut page = vec![0u8; PAGE_SIZE];
+    write_page(&mut page, 0, 0x05, &cells, Some(last));
+    pages.push(page);
+    (pages.len(), last_key)
+}
+
+/// Lay out a B-tree page: header at `offset`, cell pointers after it, cells packed at the end
+fn write_page(page: &mut [u8], offset: usize, kind: u8, cells: &[Vec<u8>], right_most: Option<usize>) {
+    let header_len = if right_most.is_some() { 12 } else { 8 };
+    let mut content = PAGE_SIZE;
+    let mut pointer = offset + header_len;
+    for cell in cells {
+        content -= cell.len();
+        page[content..content + cell.len()].copy_from_slice(cell);
+        page[pointer..pointer + 2].copy_from_slice(&(content as u16).to_be_bytes());
+        pointer += 2;
+    }
+    page[offset] = kind;
+    page[offset + 3..offset + 5].copy_from_slice(&(cells.len() as u16).to_be_bytes());
+    page[offset + 5..offset + 7].copy_from_slice(&(content as u16).to_be_bytes());
+    if let Some(right_most) = right_most {
+        page[offset + 8..offset + 12].copy_from_slice(&(right_most as u32).to_be_bytes());
+    }
+}
+
+/// A table leaf cell, spilling the end of a large record onto overflow pages
+fn leaf_cell(pages: &mut Vec<Vec<u8>>, rowid: i64, values: &[Value]) -> Vec<u8> {
+    let payload = record(values);
+    let mut cell = varint(payload.len() as u64);
+    cell.extend(varint(rowid as u64));
+    if payload.len() <= MAX_LOCAL {
+        cell.extend(&payload);
+        return cell;
+    }
+
+    let spill = MIN_LOCAL + (payload.len() - MIN_LOCAL) % (PAGE_SIZE - 4);
+    let local = if spill <= MAX_LOCAL { spill } else { MIN_LOCAL };
+    cell.extend(&payload[..local]);
+    let chunks: Vec<&[u8]> = payload[local..].chunks(PAGE_SIZE - 4).collect();
+    let first = pages.len() + 1;
+    for (i, chunk) in chunks.iter().enumerate() {
+        let next = if i + 1 < chunks.len() { first + i + 1 } else { 0 };
+        let mut page = vec![0u8; PAGE_SIZE];
+        page[..4].copy_from_slice(&(next as u32).to_be_bytes());
+        page[4..4 + chunk.len()].copy_from_slice(chunk);
+        pages.push(page);
+    }
+    cell.extend((first as u32).to_be_bytes());
+    cell
+}
+
+/// A row in SQLite's record format: a header of serial types, then the values
+fn record(values: &[Value]) -> Vec<u8> {
+    let mut types = Vec::new();
+    let mut body = Vec::new();
+    for value in values {
+        match value {
+            Value::Null => types.extend(varint(0)),
+            Value::Integer(0) => types.extend(varint(8)),
+            Value::Integer(1) => types.extend(varint(9)),
+            Value::Integer(n) => {
+                let (serial, len) = match *n {
+                    -0x80..=0x7f => (1, 1),
+                    -0x8000..=0x7fff => (2, 2),
+                    -0x80_0000..=0x7f_ffff => (3, 3),
+                    -0x8000_0000..=0x7fff_ffff => (4, 4),
+                    -0x8000_0000_0000..=0x7fff_ffff_ffff => (5, 6),
+                    _ => (6, 8),
+                };
+                types.extend(varint(serial));
+                body.extend_from_slice(&n.to_be_bytes()[8 - len..]);
+            }
+            Value::Text(text) => {
+                types.extend(varint(13 + 2 * text.len() as u64));
+                body.extend_from_slice(text.as_bytes());
+            }
+        }
+    }
+    // The header's length counts the varint that holds it
+    let mut header_len = types.len() + 1;
+    if varint(header_len as u64).len() > 1 {
+        header_len += varint(header_len as u64 + 1).len() - 1;
+    }
+    let mut out = varint(header_len as u64);
+    out.extend(types);
+    out.extend(body);
+    out
+}
+
+/// SQLite's big-endian variable-length integer: 7 bits per byte, all 8 in a ninth
+fn varint(mut value: u64) -> Vec<u8> {
+    if value >> 56 != 0 {
+        let mut out = vec![0u8; 9];
+        out[8] = value as u8;
+        value >>= 8;
+        for byte in out[..8].iter_mut().rev() {
+            *byte = (value & 0x7f) as u8 | 0x80;
+            value >>= 7;
+        }
+        return out;
+    }
+    let mut out = vec![(value & 0x7f) as u8];
+    value >>= 7;
+    while value != 0 {
+        out.push((value & 0x7f) as u8 | 0x80);
+        value >>= 7;
+    }
+    out.reverse();
+    out
+}
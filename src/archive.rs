@@ -0,0 +1,558 @@
+use crate::error::ConvertError;
+use crate::obfuscation;
+use anyhow::{Context, Result};
+use epub::doc::EpubDoc;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek};
+use std::path::Path;
+use zip::ZipArchive;
+
+/// Caps on what a single EPUB may make us read, for untrusted input
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ResourceLimits {
+    /// Largest uncompressed size allowed for any single archive entry
+    pub max_resource_bytes: Option<u64>,
+    /// Largest combined uncompressed size of the archive
+    pub max_total_bytes: Option<u64>,
+    /// Largest number of spine items
+    pub max_chapters: Option<usize>,
+    /// Rough ceiling on the memory one book's conversion may use
+    pub max_memory_bytes: Option<u64>,
+}
+
+/// Estimated peak memory per byte of markup while it is parsed and rendered: the DOM,
+/// html2md's tree and the Markdown strings all exist at once
+const MEMORY_PER_BYTE: u64 = 12;
+
+/// Text entries that get read into memory whole when a book is opened or converted
+fn is_markup(name: &str) -> bool {
+    let lower = name.to_ascii_lowercase();
+    [".opf", ".ncx", ".xhtml", ".html", ".htm", ".xml", ".css"].iter().any(|ext| lower.ends_with(ext))
+}
+
+impl ResourceLimits {
+    /// Reject archives whose central directory already declares sizes over the limits,
+    /// before any entry is decompressed
+    pub fn check_archive(&self, epub_path: &Path) -> Result<()> {
+        if self.max_resource_bytes.is_none() && self.max_total_bytes.is_none() && self.max_memory_bytes.is_none() {
+            return Ok(());
+        }
+
+        let mut zip = open_zip(epub_path)?;
+
+        let mut total: u64 = 0;
+        for i in 0..zip.len() {
+            let entry = zip.by_index_raw(i).map_err(ConvertError::from).context("Failed to read EPUB archive")?;
+            let size = entry.size();
+            if let Some(max) = self.max_resource_bytes {
+                if size > max {
+                    anyhow::bail!("Archive entry {} is {} bytes uncompressed, over the {} byte limit", entry.name(), size, max);
+                }
+            }
+            if let Some(max) = self.max_markup_bytes() {
+                if size > max && is_markup(entry.name()) {
+                    anyhow::bail!(
+                        "Archive entry {} is {} bytes uncompressed, too large to convert within the memory limit",
+                        entry.name(),
+                        size
+                    );
+                }
+            }
+            total = total.saturating_add(size);
+        }
+
+        if let Some(max) = self.max_total_bytes {
+            if total > max {
+                anyhow::bail!("Archive is {} bytes uncompressed, over the {} byte limit", total, max);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Largest amount of markup that fits in memory at once under `max_memory_bytes`
+    pub fn max_markup_bytes(&self) -> Option<u64> {
+        self.max_memory_bytes.map(|max| max / MEMORY_PER_BYTE)
+    }
+
+    pub fn check_chapter_count(&self, count: usize) -> Result<()> {
+        if let Some(max) = self.max_chapters {
+            if count > max {
+                anyhow::bail!("Spine has {} items, over the limit of {}", count, max);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The DRM scheme an archive is locked with, if any. Encrypted content shows up in
+/// `META-INF/encryption.xml`; the vendor files next to it say whose scheme it is. Fonts
+/// obfuscated with the IDPF or Adobe algorithms are listed there too, but aren't DRM.
+fn drm_scheme(resources: &mut Resources) -> Result<Option<String>> {
+    let Some(xml) = resources.read_string(obfuscation::ENCRYPTION_FILE)? else { return Ok(None) };
+    let encrypted = xml::reader::EventReader::new(xml.as_bytes()).into_iter().flatten().any(|event| match event {
+        xml::reader::XmlEvent::StartElement { name, attributes, .. } if name.local_name == "EncryptionMethod" => attributes
+            .iter()
+            .any(|a| a.name.local_name == "Algorithm" && !obfuscation::FONT_ALGORITHMS.contains(&a.value.trim())),
+        _ => false,
+    });
+    if !encrypted {
+        return Ok(None);
+    }
+    let has = |name: &str| resources.contains(name);
+    let scheme = if has("META-INF/rights.xml") {
+        "Adobe ADEPT"
+    } else if has("META-INF/sinf.xml") {
+        "Apple FairPlay"
+    } else if has("META-INF/license.lcpl") {
+        "Readium LCP"
+    } else {
+        "unknown scheme"
+    };
+    Ok(Some(scheme.to_string()))
+}
+
+/// Characters escaped in the file name of an image shown as a spine page
+const IMAGE_SRC: &percent_encoding::AsciiSet = &percent_encoding::CONTROLS.add(b' ').add(b'"').add(b'<').add(b'>').add(b'#').add(b'%').add(b'?');
+
+/// A page linking to the attachment copied out of the archive from `path`, for a chapter
+/// file `up` (`../`) from the output directory
+pub fn attachment_page(path: &str, up: &str) -> String {
+    let href = path.split('/').map(|segment| percent_encoding::utf8_percent_encode(segment, IMAGE_SRC).to_string()).collect::<Vec<_>>().join("/");
+    let name = path.rsplit('/').next().unwrap_or(path);
+    format!(
+        "<html><body><p><a href=\"{}{}/{}\">{}</a></p></body></html>",
+        up,
+        ATTACHMENTS_DIR,
+        href.replace('&', "&amp;"),
+        name.replace('&', "&amp;").replace('<', "&lt;")
+    )
+}
+
+/// Most we pre-allocate for a spine document based on its declared size
+const MAX_RESERVE: usize = 64 << 20;
+
+/// Directory under the book's output directory that `--extract-mime` spine items are
+/// copied into, keeping their paths inside the archive
+pub const ATTACHMENTS_DIR: &str = "attachments";
+
+/// Media types the spine can render as a chapter
+const DOCUMENT_TYPES: &[&str] = &["application/xhtml+xml", "text/html"];
+
+/// Which spine media is used, from `--allow-mime` and `--deny-mime`, and which is copied
+/// out as it is rather than converted, from `--extract-mime`. Types are matched exactly or
+/// by a `type/*` pattern.
+#[derive(Debug, Clone, Default)]
+pub struct MimePolicy {
+    /// When not empty, only media of these types is used
+    pub allow: Vec<String>,
+    pub deny: Vec<String>,
+    pub extract: Vec<String>,
+}
+
+impl MimePolicy {
+    fn permits(&self, mime: &str) -> bool {
+        (self.allow.is_empty() || self.allow.iter().any(|p| mime_matches(p, mime))) && !self.deny.iter().any(|p| mime_matches(p, mime))
+    }
+
+    fn extracts(&self, mime: &str) -> bool {
+        self.extract.iter().any(|p| mime_matches(p, mime))
+    }
+}
+
+fn mime_matches(pattern: &str, mime: &str) -> bool {
+    match pattern.strip_suffix("/*") {
+        Some(kind) => mime.split('/').next().is_some_and(|m| m.eq_ignore_ascii_case(kind)),
+        None => pattern.eq_ignore_ascii_case(mime),
+    }
+}
+
+/// What a spine item holds once its manifest fallbacks are followed
+#[derive(Debug, Clone, PartialEq)]
+pub enum SpineContent {
+    /// A document to convert
+    Document,
+    /// An image with no document fallback, shown as a chapter of its own
+    Image,
+    /// Media `--extract-mime` asks for, copied out with a chapter linking to it
+    Extract,
+    /// Media of this type the MIME filters leave out, with nothing allowed to fall back on
+    Denied(String),
+    /// Media of this type with nothing renderable to fall back on
+    Unsupported(String),
+}
+
+/// A spine item resolved to its location inside the archive
+pub struct SpineEntry {
+    /// Full path of the document inside the zip
+    pub path: String,
+    pub content: SpineContent,
+}
+
+/// Manifest `fallback` attributes, by item id. The epub crate drops them, so they are
+/// read from the package document directly.
+fn manifest_fallbacks(resources: &mut Resources, root_file: &Path) -> Result<HashMap<String, String>> {
+    let Some(opf) = resources.read_string(&root_file.to_string_lossy())? else { return Ok(HashMap::new()) };
+    Ok(xml::reader::EventReader::new(opf.as_bytes())
+        .into_iter()
+        .flatten()
+        .filter_map(|event| match event {
+            xml::reader::XmlEvent::StartElement { name, attributes, .. } if name.local_name == "item" => {
+                let attr = |key: &str| attributes.iter().find(|a| a.name.local_name == key).map(|a| a.value.clone());
+                Some((attr("id")?, attr("fallback")?))
+            }
+            _ => None,
+        })
+        .collect())
+}
+
+/// Follow `idref`'s fallback chain to the first item that is a document or media to
+/// extract, or failing that to the first image, passing over media the policy leaves out;
+/// cycles and dangling references end the chain
+fn resolve_fallbacks<R: Read + Seek>(doc: &EpubDoc<R>, idref: &str, fallbacks: &HashMap<String, String>, policy: &MimePolicy) -> SpineEntry {
+    let Some(first) = doc.resources.get(idref) else {
+        return SpineEntry { path: String::new(), content: SpineContent::Document };
+    };
+    let mut image = None;
+    let mut denied = None;
+    let mut seen = HashSet::new();
+    let mut id = Some(idref);
+    while let Some(current) = id.filter(|id| seen.insert(*id)) {
+        let Some(item) = doc.resources.get(current) else { break };
+        id = fallbacks.get(current).map(String::as_str);
+        // An item without a declared type is taken for a document
+        let mime = if item.mime.is_empty() { DOCUMENT_TYPES[0] } else { item.mime.as_str() };
+        let path = item.path.to_string_lossy().into_owned();
+        if !policy.permits(mime) {
+            denied.get_or_insert(mime);
+            continue;
+        }
+        if policy.extracts(mime) {
+            return SpineEntry { path, content: SpineContent::Extract };
+        }
+        if DOCUMENT_TYPES.contains(&mime) {
+            return SpineEntry { path, content: SpineContent::Document };
+        }
+        if image.is_none() && mime.starts_with("image/") {
+            image = Some(path);
+        }
+    }
+    let content = match (&image, denied) {
+        (Some(_), _) => SpineContent::Image,
+        (None, Some(mime)) => SpineContent::Denied(mime.to_string()),
+        (None, None) => SpineContent::Unsupported(first.mime.clone()),
+    };
+    SpineEntry { path: image.unwrap_or_else(|| first.path.to_string_lossy().into_owned()), content }
+}
+
+/// Reader for a book's archive entries that counts every byte it decompresses against
+/// the resource limits, whatever sizes the central directory declares. A conversion reads
+/// everything through one, so `max_total_bytes` covers the spine, images, stylesheets,
+/// fonts and audio together.
+pub struct Resources {
+    zip: ZipArchive<BufReader<File>>,
+    limits: ResourceLimits,
+    /// Decompressed bytes read so far, enforced against `max_total_bytes`
+    bytes_read: u64,
+}
+
+fn open_zip(epub_path: &Path) -> Result<ZipArchive<BufReader<File>>> {
+    let file = File::open(epub_path).context("Failed to open EPUB file")?;
+    ZipArchive::new(BufReader::new(file)).map_err(ConvertError::from).context("Failed to read EPUB archive")
+}
+
+impl Resources {
+    pub fn open(epub_path: &Path, limits: ResourceLimits) -> Result<Self> {
+        Ok(Self { zip: open_zip(epub_path)?, limits, bytes_read: 0 })
+    }
+
+    /// Read from another copy of the book (the one made for a rendition), keeping the
+    /// running total
+    pub fn reopen(&mut self, epub_path: &Path) -> Result<()> {
+        self.zip = open_zip(epub_path)?;
+        Ok(())
+    }
+
+    /// Every entry name in the archive
+    pub fn names(&self) -> HashSet<String> {
+        self.zip.file_names().map(str::to_string).collect()
+    }
+
+    pub fn contains(&self, path: &str) -> bool {
+        self.index(path).is_some()
+    }
+
+    /// The bytes of the entry at `path`, None if the archive has none there, or an error
+    /// if reading it would exceed the resource limits
+    pub fn read(&mut self, path: &str) -> Result<Option<Vec<u8>>> {
+        let Some(index) = self.index(path) else { return Ok(None) };
+        let mut bytes = Vec::new();
+        self.read_with(index, path, u64::MAX, |file, hint| {
+            bytes.reserve(hint);
+            file.read_to_end(&mut bytes)
+        })?
+        .with_context(|| format!("Failed to read {}", path))?;
+        Ok(Some(bytes))
+    }
+
+    /// As `read`, for text; an entry that isn't valid UTF-8 is None as well
+    pub fn read_string(&mut self, path: &str) -> Result<Option<String>> {
+        Ok(self.read(path)?.and_then(|bytes| String::from_utf8(bytes).ok()))
+    }
+
+    /// Index in the zip of the entry at `path`. Manifest hrefs may be percent-encoded
+    /// while zip names are not.
+    fn index(&self, path: &str) -> Option<usize> {
+        self.zip.index_for_name(path).or_else(|| self.zip.index_for_name(&percent_encoding::percent_decode_str(path).decode_utf8_lossy()))
+    }
+
+    /// Decompress entry `index` with `read`, given a reader cut off past the limits (and
+    /// past `cap`) and a capacity hint. Going over is an error; `read`'s own result is
+    /// returned as it is.
+    fn read_with<T>(&mut self, index: usize, path: &str, cap: u64, read: impl FnOnce(&mut dyn Read, usize) -> io::Result<T>) -> Result<io::Result<T>> {
+        let cap = self.read_cap().min(cap);
+        let file = self.zip.by_index(index).map_err(ConvertError::from)?;
+        // Zip64 entries can declare sizes past 4 GB (and past usize on 32-bit targets),
+        // so treat the declared size only as a bounded capacity hint
+        let hint = usize::try_from(file.size().min(cap)).unwrap_or(usize::MAX).min(MAX_RESERVE);
+        let budget = cap.saturating_add(1);
+        let mut limited = file.take(budget);
+        let result = read(&mut limited, hint);
+        let read = budget - limited.limit();
+        if read > cap {
+            anyhow::bail!("{} exceeds the configured resource limits", path);
+        }
+        self.bytes_read += read;
+        Ok(result)
+    }
+
+    /// Most bytes the next read may decompress. Declared sizes can lie, so the bytes
+    /// actually decompressed are capped as well.
+    fn read_cap(&self) -> u64 {
+        let mut cap = u64::MAX;
+        if let Some(max) = self.limits.max_resource_bytes {
+            cap = cap.min(max);
+        }
+        if let Some(max) = self.limits.max_total_bytes {
+            cap = cap.min(max.saturating_sub(self.bytes_read));
+        }
+        cap
+    }
+}
+
+/// Direct reader for spine documents that fills caller-owned buffers,
+/// so chapter text can be read without per-chapter allocations
+pub struct SpineReader {
+    /// The archive, for the book's other resources
+    pub resources: Resources,
+    pub entries: Vec<SpineEntry>,
+}
+
+impl SpineReader {
+    /// Resolve every spine idref against the manifest, refusing books locked with DRM
+    pub fn open<R: Read + Seek>(mut resources: Resources, doc: &EpubDoc<R>, policy: &MimePolicy) -> Result<Self> {
+        if let Some(scheme) = drm_scheme(&mut resources)? {
+            return Err(ConvertError::DrmProtected { scheme }.into());
+        }
+
+        let fallbacks = manifest_fallbacks(&mut resources, &doc.root_file)?;
+        let entries = doc.spine.iter().map(|item| resolve_fallbacks(doc, &item.idref, &fallbacks, policy)).collect();
+
+        Ok(Self { resources, entries })
+    }
+
+    /// Every entry name in the archive
+    pub fn names(&self) -> HashSet<String> {
+        self.resources.names()
+    }
+
+    /// Uncompressed size spine item `index` declares in the archive, or 0 if it is missing
+    pub fn declared_size(&mut self, index: usize) -> u64 {
+        let Some(zip_index) = self.entries.get(index).and_then(|entry| self.resources.index(&entry.path)) else {
+            return 0;
+        };
+        self.resources.zip.by_index_raw(zip_index).map(|f| f.size()).unwrap_or(0)
+    }
+
+    /// Read spine item `index` into `buf`, replacing its contents but keeping its capacity.
+    /// An image is given as a page showing it. Returns false if the item is missing from
+    /// the archive (see `is_missing`), not valid UTF-8 or unsupported media, and an error if
+    /// reading it would exceed the resource limits.
+    pub fn read_into(&mut self, index: usize, buf: &mut String) -> Result<bool> {
+        buf.clear();
+        let Some(entry) = self.entries.get(index) else {
+            return Ok(false);
+        };
+        if entry.path.is_empty() {
+            return Ok(false);
+        }
+        match &entry.content {
+            SpineContent::Document => {}
+            SpineContent::Image => {
+                // The page sits next to the image, so its file name is the reference
+                let name = entry.path.rsplit('/').next().unwrap_or(&entry.path);
+                let src = percent_encoding::utf8_percent_encode(name, IMAGE_SRC).to_string();
+                buf.push_str(&format!("<html><body><p><img src=\"{}\" alt=\"\"/></p></body></html>", src.replace('&', "&amp;")));
+                return Ok(true);
+            }
+            SpineContent::Extract | SpineContent::Denied(_) | SpineContent::Unsupported(_) => return Ok(false),
+        }
+
+        // A dangling spine entry is left out with a warning rather than failing the book
+        let Some(zip_index) = self.resources.index(&entry.path) else {
+            return Ok(false);
+        };
+        let cap = self.resources.limits.max_markup_bytes().unwrap_or(u64::MAX);
+        let ok = self
+            .resources
+            .read_with(zip_index, &entry.path, cap, |file, hint| {
+                buf.reserve(hint);
+                file.read_to_string(buf)
+            })?
+            .is_ok();
+        if !ok {
+            buf.clear();
+        }
+        Ok(ok)
+    }
+
+    /// The raw bytes of spine item `index`, for media that is extracted rather than
+    /// converted, or an error if reading it would exceed the resource limits
+    pub fn read_bytes(&mut self, index: usize) -> Result<Vec<u8>> {
+        let path = &self.entries[index].path;
+        self.resources.read(path)?.ok_or_else(|| ConvertError::MissingResource { href: path.clone() }.into())
+    }
+
+    /// Whether spine item `index` names a file the archive doesn't have
+    pub fn is_missing(&self, index: usize) -> bool {
+        self.entries.get(index).is_some_and(|entry| !entry.path.is_empty() && !self.resources.contains(&entry.path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::check::ScratchDir;
+    use std::fs;
+    use std::io::Write;
+    use zip::write::SimpleFileOptions;
+    use zip::ZipWriter;
+
+    const CONTAINER: &str = r#"<?xml version="1.0"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+  <rootfiles><rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/></rootfiles>
+</container>"#;
+
+    const PACKAGE: &str = r#"<?xml version="1.0"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="3.0" unique-identifier="id">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+    <dc:identifier id="id">zip64-test</dc:identifier>
+    <dc:title>Zip64</dc:title>
+    <dc:language>en</dc:language>
+  </metadata>
+  <manifest>
+    <item id="ch1" href="ch1.xhtml" media-type="application/xhtml+xml"/>
+    <item id="cover" href="images/cover.png" media-type="image/png"/>
+  </manifest>
+  <spine><itemref idref="ch1"/></spine>
+</package>"#;
+
+    const CHAPTER: &str = r#"<html xmlns="http://www.w3.org/1999/xhtml"><body><h1>One</h1><p>Text.</p><img src="images/cover.png" alt=""/></body></html>"#;
+
+    /// Bytes standing in for an image
+    fn image() -> Vec<u8> {
+        (0..4096u32).map(|i| (i * 7 % 251) as u8).collect()
+    }
+
+    /// An EPUB whose every entry carries Zip64 size and offset fields, and whose central
+    /// directory is found through the Zip64 end records, as in an archive past 4 GB
+    fn write_zip64_epub(path: &Path) {
+        let mut zip = ZipWriter::new(io::Cursor::new(Vec::new()));
+        let stored = SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored).large_file(true);
+        let deflated = SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated).large_file(true);
+        zip.start_file("mimetype", stored).unwrap();
+        zip.write_all(b"application/epub+zip").unwrap();
+        for (name, data) in [
+            ("META-INF/container.xml", CONTAINER.as_bytes().to_vec()),
+            ("OEBPS/content.opf", PACKAGE.as_bytes().to_vec()),
+            ("OEBPS/ch1.xhtml", CHAPTER.as_bytes().to_vec()),
+            ("OEBPS/images/cover.png", image()),
+        ] {
+            zip.start_file(name, deflated).unwrap();
+            zip.write_all(&data).unwrap();
+        }
+        let bytes = zip.finish().unwrap().into_inner();
+        fs::write(path, with_zip64_end(bytes)).unwrap();
+    }
+
+    /// Replace the end of central directory record with a Zip64 one, a locator, and a
+    /// classic record whose counts and offsets all say "see Zip64"
+    fn with_zip64_end(mut bytes: Vec<u8>) -> Vec<u8> {
+        let end = bytes.windows(4).rposition(|w| w == b"PK\x05\x06").unwrap();
+        let field = |at: usize, len: usize| bytes[end + at..end + at + len].iter().rev().fold(0u64, |n, &b| n << 8 | u64::from(b));
+        let (entries, size, offset) = (field(10, 2), field(12, 4), field(16, 4));
+        bytes.truncate(end);
+
+        let mut record = b"PK\x06\x06".to_vec();
+        record.extend(44u64.to_le_bytes());
+        record.extend(45u16.to_le_bytes());
+        record.extend(45u16.to_le_bytes());
+        record.extend([0; 8]);
+        record.extend(entries.to_le_bytes());
+        record.extend(entries.to_le_bytes());
+        record.extend(size.to_le_bytes());
+        record.extend(offset.to_le_bytes());
+        record.extend(b"PK\x06\x07");
+        record.extend(0u32.to_le_bytes());
+        record.extend((end as u64).to_le_bytes());
+        record.extend(1u32.to_le_bytes());
+        record.extend(b"PK\x05\x06");
+        record.extend([0; 4]);
+        record.extend([0xff; 12]);
+        record.extend([0; 2]);
+        bytes.extend(record);
+        bytes
+    }
+
+    #[test]
+    fn reads_a_zip64_archive() {
+        let scratch = ScratchDir::new("zip64-test").unwrap();
+        let epub_path = scratch.path().join("zip64.epub");
+        write_zip64_epub(&epub_path);
+
+        let limits = ResourceLimits { max_resource_bytes: Some(1 << 20), max_total_bytes: Some(1 << 20), ..Default::default() };
+        limits.check_archive(&epub_path).unwrap();
+        let doc = EpubDoc::new(&epub_path).unwrap();
+        let mut reader = SpineReader::open(Resources::open(&epub_path, limits).unwrap(), &doc, &MimePolicy::default()).unwrap();
+        let mut chapter = String::new();
+        assert!(reader.read_into(0, &mut chapter).unwrap());
+        assert_eq!(chapter, CHAPTER);
+        assert_eq!(reader.resources.read("OEBPS/images/cover.png").unwrap(), Some(image()));
+    }
+
+    #[test]
+    fn extracts_resources_from_a_zip64_archive() {
+        let scratch = ScratchDir::new("zip64-test").unwrap();
+        let epub_path = scratch.path().join("zip64.epub");
+        write_zip64_epub(&epub_path);
+
+        let output_dir = scratch.path().join("zip64_markdown");
+        crate::extract::extract_resources(&epub_path, &output_dir, false, ResourceLimits::default()).unwrap();
+        let resources = output_dir.join(crate::extract::RESOURCES_DIR);
+        assert_eq!(fs::read(resources.join("OEBPS/images/cover.png")).unwrap(), image());
+        assert!(!resources.join("OEBPS/ch1.xhtml").exists());
+    }
+
+    #[test]
+    fn counts_lying_entries_against_the_total() {
+        let scratch = ScratchDir::new("zip64-test").unwrap();
+        let epub_path = scratch.path().join("zip64.epub");
+        write_zip64_epub(&epub_path);
+
+        let limits = ResourceLimits { max_total_bytes: Some(image().len() as u64 + 100), ..Default::default() };
+        let mut resources = Resources::open(&epub_path, limits).unwrap();
+        assert!(resources.read("OEBPS/images/cover.png").unwrap().is_some());
+        assert!(resources.read("OEBPS/content.opf").is_err());
+    }
+}
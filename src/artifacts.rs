@@ -0,0 +1,170 @@
+use serde::Serialize;
+use std::collections::HashSet;
+
+/// A short line that appears this many times in one chapter is taken to be a running header
+const REPEAT_THRESHOLD: usize = 3;
+
+/// Lines longer than this are never treated as running headers or footers
+const MAX_ARTIFACT_LEN: usize = 80;
+
+/// Why a line was removed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ArtifactKind {
+    /// A standalone page number between paragraphs
+    PageNumber,
+    /// A short line repeated throughout the text, such as a running header
+    RepeatedLine,
+}
+
+/// One line removed by `--clean-artifacts`, as listed in artifacts.json
+#[derive(Debug, Clone, Serialize)]
+pub struct RemovedLine {
+    /// Output chapter number the line was removed from
+    pub chapter: usize,
+    pub kind: ArtifactKind,
+    pub text: String,
+}
+
+/// Removes the running headers, footers and page numbers left behind in EPUBs made from
+/// scanned print books. Chapters are cleaned in reading order; a header found in one
+/// chapter is also removed from the chapters after it.
+#[derive(Debug, Default)]
+pub struct ArtifactCleaner {
+    /// Normalized text of lines identified as running headers or footers
+    repeated: HashSet<String>,
+    pub removed: Vec<RemovedLine>,
+}
+
+impl ArtifactCleaner {
+    /// Remove artifacts from one chapter's Markdown
+    pub fn clean(&mut self, markdown: &str, chapter: usize) -> String {
+        let lines: Vec<&str> = markdown.lines().collect();
+        let blocks = single_line_blocks(&lines);
+
+        // Running headers: short lines repeated within this chapter
+        let mut counts: std::collections::HashMap<String, usize> = Default::default();
+        for block in &blocks {
+            if let Some(key) = repeat_key(lines[block.line]) {
+                *counts.entry(key).or_default() += 1;
+            }
+        }
+        self.repeated.extend(counts.into_iter().filter(|&(_, n)| n >= REPEAT_THRESHOLD).map(|(key, _)| key));
+
+        let mut drop = vec![false; lines.len()];
+        for block in &blocks {
+            let line = lines[block.line];
+            let kind = if block.between_paragraphs && is_page_number(line) {
+                ArtifactKind::PageNumber
+            } else if repeat_key(line).is_some_and(|key| self.repeated.contains(&key)) {
+                ArtifactKind::RepeatedLine
+            } else {
+                continue;
+            };
+            self.removed.push(RemovedLine { chapter, kind, text: line.trim().to_string() });
+            drop[block.line] = true;
+            // Take the blank lines after it too, so paragraphs stay one blank line apart
+            for i in block.line + 1..lines.len() {
+                if !lines[i].trim().is_empty() {
+                    break;
+                }
+                drop[i] = true;
+            }
+        }
+
+        if !drop.contains(&true) {
+            return markdown.to_string();
+        }
+        let mut out = String::with_capacity(markdown.len());
+        for (line, _) in lines.iter().zip(&drop).filter(|(_, &d)| !d) {
+            out.push_str(line);
+            out.push('\n');
+        }
+        if !markdown.ends_with('\n') {
+            out.pop();
+        }
+        out
+    }
+
+    /// One-line summary of what was removed
+    pub fn summary(&self) -> String {
+        let pages = self.removed.iter().filter(|r| r.kind == ArtifactKind::PageNumber).count();
+        let repeated = self.removed.len() - pages;
+        let mut headers: Vec<&str> = Vec::new();
+        for r in self.removed.iter().filter(|r| r.kind == ArtifactKind::RepeatedLine) {
+            if !headers.contains(&r.text.as_str()) {
+                headers.push(&r.text);
+            }
+        }
+        let mut line = format!("Removed {} page number(s) and {} repeated line(s)", pages, repeated);
+        if !headers.is_empty() {
+            let shown: Vec<String> = headers.iter().take(3).map(|h| format!("\"{}\"", h)).collect();
+            line.push_str(&format!(" ({}{})", shown.join(", "), if headers.len() > 3 { ", ..." } else { "" }));
+        }
+        line
+    }
+}
+
+/// A paragraph consisting of a single line
+struct Block {
+    line: usize,
+    /// Whether there is text both before and after it in the chapter
+    between_paragraphs: bool,
+}
+
+/// Single-line paragraphs outside code fences
+fn single_line_blocks(lines: &[&str]) -> Vec<Block> {
+    let mut blocks = Vec::new();
+    let mut in_fence = false;
+    let blank = |i: usize| lines.get(i).is_none_or(|l| l.trim().is_empty());
+    let last_text = lines.iter().rposition(|l| !l.trim().is_empty()).unwrap_or(0);
+    let mut seen_text = false;
+    for (i, line) in lines.iter().enumerate() {
+        let trimmed = line.trim();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_fence = !in_fence;
+        }
+        if trimmed.is_empty() {
+            continue;
+        }
+        if !in_fence && (i == 0 || blank(i - 1)) && blank(i + 1) {
+            blocks.push(Block { line: i, between_paragraphs: seen_text && i < last_text });
+        }
+        seen_text = true;
+    }
+    blocks
+}
+
+/// `12`, `- 12 -`, `[12]`, `Page 12` or `p. 12` on its own
+fn is_page_number(line: &str) -> bool {
+    let trimmed = line.trim().trim_matches(|c: char| matches!(c, '-' | '–' | '—' | '[' | ']' | '(' | ')' | '\\' | ' '));
+    let lower = trimmed.to_lowercase();
+    let digits = lower
+        .strip_prefix("page")
+        .or_else(|| lower.strip_prefix("p."))
+        .unwrap_or(&lower)
+        .trim();
+    !digits.is_empty() && digits.len() <= 4 && digits.chars().all(|c| c.is_ascii_digit())
+}
+
+/// What a line is compared by when looking for repeats: lowercased, with page numbers and
+/// separators stripped, so "The Title | 45" and "The Title | 46" match. None for lines that
+/// can't be a header: headings, list items, tables, long lines, sentences and bare punctuation.
+fn repeat_key(line: &str) -> Option<String> {
+    let trimmed = line.trim();
+    if trimmed.chars().count() > MAX_ARTIFACT_LEN
+        || trimmed.starts_with(['#', '|', '>', '!', '['])
+        || ["- ", "* ", "+ "].iter().any(|m| trimmed.starts_with(m))
+        || trimmed.contains("](")
+    {
+        return None;
+    }
+    let core = trimmed.trim_matches(|c: char| {
+        c.is_ascii_digit() || c.is_whitespace() || matches!(c, '|' | '-' | '–' | '—' | '·' | '•' | '\\' | '*' | '_')
+    });
+    // Dialogue and prose end in punctuation; running headers almost never do
+    if !core.chars().any(char::is_alphabetic) || core.ends_with(['.', '?', '!', '"', '\u{201D}', '\u{2019}', '\u{2026}', ':', ',']) {
+        return None;
+    }
+    Some(core.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase())
+}
@@ -0,0 +1,245 @@
+use crate::normalize;
+use clap::ValueEnum;
+
+/// ASCII spellings of punctuation, spaces and symbols, for the characters whose
+/// compatibility decomposition doesn't already give one
+const PUNCTUATION: &[(char, &str)] = &[
+    ('\u{A0}', " "),
+    ('\u{A6}', "|"),
+    ('\u{A7}', "Sec."),
+    ('\u{A9}', "(C)"),
+    ('\u{AB}', "<<"),
+    ('\u{AC}', "!"),
+    ('\u{AE}', "(R)"),
+    ('\u{B0}', " deg"),
+    ('\u{B1}', "+/-"),
+    ('\u{B6}', "P."),
+    ('\u{B7}', "."),
+    ('\u{BB}', ">>"),
+    ('\u{BC}', "1/4"),
+    ('\u{BD}', "1/2"),
+    ('\u{BE}', "3/4"),
+    ('\u{D7}', "x"),
+    ('\u{F7}', "/"),
+    ('\u{2010}', "-"),
+    ('\u{2011}', "-"),
+    ('\u{2012}', "-"),
+    ('\u{2013}', "-"),
+    ('\u{2014}', "--"),
+    ('\u{2015}', "--"),
+    ('\u{2016}', "||"),
+    ('\u{2018}', "'"),
+    ('\u{2019}', "'"),
+    ('\u{201A}', "'"),
+    ('\u{201B}', "'"),
+    ('\u{201C}', "\""),
+    ('\u{201D}', "\""),
+    ('\u{201E}', "\""),
+    ('\u{201F}', "\""),
+    ('\u{2020}', "+"),
+    ('\u{2021}', "++"),
+    ('\u{2022}', "*"),
+    ('\u{2027}', "-"),
+    ('\u{2030}', "%o"),
+    ('\u{2032}', "'"),
+    ('\u{2033}', "\""),
+    ('\u{2039}', "<"),
+    ('\u{203A}', ">"),
+    ('\u{2044}', "/"),
+    ('\u{2052}', "%"),
+    ('\u{20AC}', "EUR"),
+    ('\u{2190}', "<-"),
+    ('\u{2192}', "->"),
+    ('\u{2194}', "<->"),
+    ('\u{21D0}', "<="),
+    ('\u{21D2}', "=>"),
+    ('\u{21D4}', "<=>"),
+    ('\u{2212}', "-"),
+    ('\u{2217}', "*"),
+    ('\u{2248}', "~"),
+    ('\u{2260}', "!="),
+    ('\u{2264}', "<="),
+    ('\u{2265}', ">="),
+    ('\u{2500}', "-"),
+    ('\u{25CF}', "*"),
+    ('\u{2605}', "*"),
+    ('\u{2606}', "*"),
+    ('\u{2713}', "v"),
+    ('\u{2717}', "x"),
+    ('\u{2E3A}', "----"),
+    ('\u{2E3B}', "------"),
+    ('\u{3001}', ","),
+    ('\u{3002}', "."),
+    ('\u{300C}', "\""),
+    ('\u{300D}', "\""),
+];
+
+/// Letters with no decomposition to a Latin base, lowercase; capitals are looked up by
+/// their lowercase form. Greek and Cyrillic follow common romanizations.
+const LETTERS: &[(char, &str)] = &[
+    ('\u{DF}', "ss"),
+    ('\u{E6}', "ae"),
+    ('\u{F0}', "d"),
+    ('\u{F8}', "o"),
+    ('\u{FE}', "th"),
+    ('\u{111}', "d"),
+    ('\u{127}', "h"),
+    ('\u{131}', "i"),
+    ('\u{138}', "k"),
+    ('\u{142}', "l"),
+    ('\u{14B}', "ng"),
+    ('\u{153}', "oe"),
+    ('\u{167}', "t"),
+    ('\u{17F}', "s"),
+    ('\u{3B1}', "a"),
+    ('\u{3B2}', "v"),
+    ('\u{3B3}', "g"),
+    ('\u{3B4}', "d"),
+    ('\u{3B5}', "e"),
+    ('\u{3B6}', "z"),
+    ('\u{3B7}', "i"),
+    ('\u{3B8}', "th"),
+    ('\u{3B9}', "i"),
+    ('\u{3BA}', "k"),
+    ('\u{3BB}', "l"),
+    ('\u{3BC}', "m"),
+    ('\u{3BD}', "n"),
+    ('\u{3BE}', "x"),
+    ('\u{3BF}', "o"),
+    ('\u{3C0}', "p"),
+    ('\u{3C1}', "r"),
+    ('\u{3C2}', "s"),
+    ('\u{3C3}', "s"),
+    ('\u{3C4}', "t"),
+    ('\u{3C5}', "y"),
+    ('\u{3C6}', "f"),
+    ('\u{3C7}', "ch"),
+    ('\u{3C8}', "ps"),
+    ('\u{3C9}', "o"),
+    ('\u{430}', "a"),
+    ('\u{431}', "b"),
+    ('\u{432}', "v"),
+    ('\u{433}', "g"),
+    ('\u{434}', "d"),
+    ('\u{435}', "e"),
+    ('\u{436}', "zh"),
+    ('\u{437}', "z"),
+    ('\u{438}', "i"),
+    ('\u{439}', "y"),
+    ('\u{43A}', "k"),
+    ('\u{43B}', "l"),
+    ('\u{43C}', "m"),
+    ('\u{43D}', "n"),
+    ('\u{43E}', "o"),
+    ('\u{43F}', "p"),
+    ('\u{440}', "r"),
+    ('\u{441}', "s"),
+    ('\u{442}', "t"),
+    ('\u{443}', "u"),
+    ('\u{444}', "f"),
+    ('\u{445}', "kh"),
+    ('\u{446}', "ts"),
+    ('\u{447}', "ch"),
+    ('\u{448}', "sh"),
+    ('\u{449}', "shch"),
+    ('\u{44A}', ""),
+    ('\u{44B}', "y"),
+    ('\u{44C}', ""),
+    ('\u{44D}', "e"),
+    ('\u{44E}', "yu"),
+    ('\u{44F}', "ya"),
+    ('\u{451}', "yo"),
+    ('\u{454}', "ye"),
+    ('\u{456}', "i"),
+    ('\u{457}', "yi"),
+    ('\u{45E}', "u"),
+    ('\u{491}', "g"),
+];
+
+/// `--ascii-only`: how much of the output is rewritten to ASCII
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum AsciiOnly {
+    /// Punctuation, spaces and symbols: curly quotes, dashes, ellipses and arrows are
+    /// spelled in ASCII and emoji dropped, while letters of every script are kept
+    Punctuation,
+    /// All text: accents are dropped, Greek and Cyrillic romanized, and any other
+    /// character becomes `?`
+    All,
+}
+
+impl AsciiOnly {
+    pub fn apply(self, text: &str) -> String {
+        if text.is_ascii() {
+            return text.to_string();
+        }
+        let mut out = String::with_capacity(text.len());
+        for c in text.chars() {
+            if c.is_ascii() {
+                out.push(c);
+            } else if let Some(ascii) = lookup(PUNCTUATION, c) {
+                out.push_str(ascii);
+            } else if c.is_whitespace() {
+                out.push(' ');
+            } else if is_invisible(c) {
+                // Soft hyphens, zero-width joiners and emoji variation selectors
+            } else if self == AsciiOnly::Punctuation && (c.is_alphanumeric() || normalize::fold(c).is_empty()) {
+                // A letter, digit or combining mark
+                out.push(c);
+            } else {
+                self.transliterate(c, &mut out);
+            }
+        }
+        out
+    }
+
+    pub fn apply_mut(self, text: &mut String) {
+        if !text.is_ascii() {
+            *text = self.apply(text);
+        }
+    }
+
+    /// A character with no listed spelling: its decomposition where that is ASCII (`é` is
+    /// `e`, `™` is `TM`), the romanization of a letter, or nothing (`?` for all text)
+    fn transliterate(self, c: char, out: &mut String) {
+        // Looked up before decomposing, so `й` is `y` rather than a bare `и`
+        if let Some(ascii) = letter(c) {
+            out.push_str(&ascii);
+            return;
+        }
+        let mut unknown = false;
+        for part in normalize::fold(c).chars() {
+            if part.is_ascii() {
+                out.push(part);
+            } else if let Some(ascii) = lookup(PUNCTUATION, part) {
+                out.push_str(ascii);
+            } else if let Some(ascii) = letter(part) {
+                out.push_str(&ascii);
+            } else {
+                unknown = true;
+            }
+        }
+        if unknown && self == AsciiOnly::All {
+            out.push('?');
+        }
+    }
+}
+
+fn lookup(table: &'static [(char, &'static str)], c: char) -> Option<&'static str> {
+    table.binary_search_by_key(&c, |&(k, _)| k).ok().map(|i| table[i].1)
+}
+
+/// A letter's romanization, capitalized for a capital (`Ж` is `Zh`)
+fn letter(c: char) -> Option<String> {
+    let lower = c.to_lowercase().next()?;
+    let ascii = lookup(LETTERS, lower)?;
+    if lower == c {
+        return Some(ascii.to_string());
+    }
+    let mut chars = ascii.chars();
+    Some(chars.next().map(|first| first.to_ascii_uppercase().to_string() + chars.as_str()).unwrap_or_default())
+}
+
+/// Format characters that are dropped rather than spelled
+fn is_invisible(c: char) -> bool {
+    matches!(c, '\u{AD}' | '\u{200B}'..='\u{200F}' | '\u{2060}'..='\u{2064}' | '\u{FE00}'..='\u{FE0F}' | '\u{FEFF}' | '\u{E0100}'..='\u{E01EF}')
+}
@@ -0,0 +1,747 @@
+use crate::toc::SlugSet;
+
+/// Void elements, which XHTML requires to be self-closed
+const VOID_TAGS: &[&str] = &["br", "hr", "img", "wbr", "col", "source"];
+
+/// Block-level tags that may stand on a line of their own, as `--rtl-markup html` writes them
+const BLOCK_TAGS: &[&str] = &[
+    "div", "section", "aside", "article", "header", "footer", "nav", "figure", "figcaption", "details", "summary",
+];
+
+/// Common HTML named entities, which XHTML without a DTD does not define
+const ENTITIES: &[(&str, char)] = &[
+    ("nbsp", '\u{A0}'),
+    ("ensp", '\u{2002}'),
+    ("emsp", '\u{2003}'),
+    ("thinsp", '\u{2009}'),
+    ("shy", '\u{AD}'),
+    ("ndash", '–'),
+    ("mdash", '—'),
+    ("hellip", '…'),
+    ("lsquo", '‘'),
+    ("rsquo", '’'),
+    ("ldquo", '“'),
+    ("rdquo", '”'),
+    ("laquo", '«'),
+    ("raquo", '»'),
+    ("copy", '©'),
+    ("reg", '®'),
+    ("trade", '™'),
+    ("deg", '°'),
+    ("times", '×'),
+    ("middot", '·'),
+    ("bull", '•'),
+    ("sect", '§'),
+    ("para", '¶'),
+    ("dagger", '†'),
+    ("Dagger", '‡'),
+];
+
+/// A chapter's Markdown rendered as the body of an XHTML document
+pub struct Rendered {
+    pub body: String,
+    /// Text of the first heading, for the navigation document
+    pub title: Option<String>,
+    /// Local image sources the body refers to
+    pub images: Vec<String>,
+}
+
+/// Render Markdown as XHTML. Covers what this tool writes: ATX and setext headings,
+/// paragraphs, emphasis, code, links and images, lists, blockquotes, rules and pipe
+/// tables. With `raw_html`, inline HTML such as `<sub>` or page anchors is kept;
+/// without it, it is escaped and shows as text. Links to `.md` files are pointed at
+/// the matching `.xhtml` documents.
+pub fn render(markdown: &str, raw_html: bool) -> Rendered {
+    let mut renderer = Renderer { raw_html, slugs: SlugSet::default(), title: None, images: Vec::new() };
+    let lines: Vec<String> = markdown.lines().map(|l| l.replace('\t', "    ")).collect();
+    let mut body = String::with_capacity(markdown.len() * 2);
+    renderer.blocks(&lines, false, &mut body);
+    Rendered { body, title: renderer.title, images: renderer.images }
+}
+
+struct Renderer {
+    raw_html: bool,
+    slugs: SlugSet,
+    title: Option<String>,
+    images: Vec<String>,
+}
+
+impl Renderer {
+    /// Render a run of block-level lines. In a tight list item, paragraphs get no `<p>`.
+    fn blocks(&mut self, lines: &[String], tight: bool, out: &mut String) {
+        let mut i = 0;
+        while i < lines.len() {
+            let line = lines[i].as_str();
+            let trimmed = line.trim();
+            if trimmed.is_empty() {
+                i += 1;
+                continue;
+            }
+
+            if let Some((fence, indent)) = fence(line) {
+                // Everything up to the closing fence (or the end) is code
+                let mut code = String::new();
+                i += 1;
+                while i < lines.len() && !lines[i].trim_start().starts_with(fence.as_str()) {
+                    let content = &lines[i];
+                    let strip = content.len() - content.trim_start().len();
+                    code.push_str(&content[strip.min(indent)..]);
+                    code.push('\n');
+                    i += 1;
+                }
+                i += 1;
+                out.push_str(&format!("<pre><code>{}</code></pre>\n", escape(&code)));
+                continue;
+            }
+
+            if let Some((level, text)) = atx_heading(line) {
+                self.heading(level, text, out);
+                i += 1;
+                continue;
+            }
+
+            if trimmed.starts_with("<!--") {
+                // Comments (such as license headers) are dropped: `--` inside one is invalid XML
+                while i < lines.len() && !lines[i].contains("-->") {
+                    i += 1;
+                }
+                i += 1;
+                continue;
+            }
+
+            if is_rule(line) {
+                out.push_str("<hr/>\n");
+                i += 1;
+                continue;
+            }
+
+            if trimmed.starts_with('>') {
+                let mut inner = Vec::new();
+                while i < lines.len() && lines[i].trim_start().starts_with('>') {
+                    let rest = &lines[i].trim_start()[1..];
+                    inner.push(rest.strip_prefix(' ').unwrap_or(rest).to_string());
+                    i += 1;
+                }
+                out.push_str("<blockquote>\n");
+                self.blocks(&inner, false, out);
+                out.push_str("</blockquote>\n");
+                continue;
+            }
+
+            if list_marker(line).is_some() {
+                i = self.list(lines, i, out);
+                continue;
+            }
+
+            if i + 1 < lines.len() && is_table_row(line) && is_table_separator(&lines[i + 1]) {
+                i = self.table(lines, i, out);
+                continue;
+            }
+
+            if self.raw_html && is_block_tag(trimmed) {
+                out.push_str(trimmed);
+                out.push('\n');
+                i += 1;
+                continue;
+            }
+
+            // A paragraph runs until a blank line or the start of another block
+            let start = i;
+            let mut heading = None;
+            i += 1;
+            while i < lines.len() {
+                let next = lines[i].as_str();
+                if let Some(level) = setext_underline(next) {
+                    heading = Some(level);
+                    break;
+                }
+                if next.trim().is_empty()
+                    || fence(next).is_some()
+                    || atx_heading(next).is_some()
+                    || is_rule(next)
+                    || next.trim_start().starts_with('>')
+                    || list_marker(next).is_some()
+                {
+                    break;
+                }
+                i += 1;
+            }
+            let text: Vec<&str> = lines[start..i].iter().map(|l| l.trim_start()).collect();
+            let text = text.join("\n");
+            if let Some(level) = heading {
+                self.heading(level, text.trim(), out);
+                i += 1;
+            } else if tight {
+                self.inline(text.trim_end(), out);
+                out.push('\n');
+            } else {
+                out.push_str("<p>");
+                self.inline(text.trim_end(), out);
+                out.push_str("</p>\n");
+            }
+        }
+    }
+
+    fn heading(&mut self, level: usize, text: &str, out: &mut String) {
+        let mut html = String::new();
+        self.inline(text, &mut html);
+        let plain = unescape(&strip_tags(&html));
+        let slug = self.slugs.claim(&plain);
+        if self.title.is_none() && !plain.trim().is_empty() {
+            self.title = Some(plain.trim().to_string());
+        }
+        out.push_str(&format!("<h{} id=\"{}\">{}</h{}>\n", level, escape(&slug), html, level));
+    }
+
+    /// Render the list starting at `start`, returning the index of the line after it
+    fn list(&mut self, lines: &[String], start: usize, out: &mut String) -> usize {
+        let first = list_marker(&lines[start]).expect("list starts with a marker");
+        let mut items: Vec<Vec<String>> = Vec::new();
+        let mut loose = false;
+        let mut blank_before = false;
+        let mut i = start;
+        while i < lines.len() {
+            let line = &lines[i];
+            if line.trim().is_empty() {
+                blank_before = true;
+                i += 1;
+                continue;
+            }
+            let indent = line.len() - line.trim_start().len();
+            match list_marker(line) {
+                Some(marker) if marker.ordered == first.ordered && indent < first.content => {
+                    if !items.is_empty() && blank_before {
+                        loose = true;
+                    }
+                    items.push(vec![line[marker.content.min(line.len())..].to_string()]);
+                }
+                _ if indent >= first.content => {
+                    let item = items.last_mut().expect("list has an item");
+                    if blank_before {
+                        item.push(String::new());
+                    }
+                    item.push(line[first.content..].to_string());
+                }
+                // A lazy continuation of the item's paragraph
+                _ if !blank_before && list_marker(line).is_none() && !is_rule(line) && atx_heading(line).is_none() => {
+                    items.last_mut().expect("list has an item").push(line.trim_start().to_string());
+                }
+                _ => break,
+            }
+            blank_before = false;
+            i += 1;
+        }
+
+        let tag = if first.ordered { "ol" } else { "ul" };
+        match first.start.filter(|&n| n != 1) {
+            Some(n) => out.push_str(&format!("<{} start=\"{}\">\n", tag, n)),
+            None => out.push_str(&format!("<{}>\n", tag)),
+        }
+        for item in items {
+            out.push_str("<li>");
+            let mut content = String::new();
+            self.blocks(&item, !loose, &mut content);
+            out.push_str(content.trim_end());
+            out.push_str("</li>\n");
+        }
+        out.push_str(&format!("</{}>\n", tag));
+        // Leave the blank lines that ended the list for the caller
+        i
+    }
+
+    /// Render the pipe table starting at `start`, returning the index of the line after it
+    fn table(&mut self, lines: &[String], start: usize, out: &mut String) -> usize {
+        let align: Vec<Option<&str>> = table_cells(&lines[start + 1])
+            .iter()
+            .map(|cell| match (cell.starts_with(':'), cell.ends_with(':')) {
+                (true, true) => Some("center"),
+                (false, true) => Some("right"),
+                (true, false) => Some("left"),
+                _ => None,
+            })
+            .collect();
+        let row = |renderer: &mut Self, line: &str, cell_tag: &str, out: &mut String| {
+            out.push_str("<tr>");
+            for (n, cell) in table_cells(line).iter().enumerate() {
+                match align.get(n).copied().flatten() {
+                    Some(a) => out.push_str(&format!("<{} style=\"text-align: {}\">", cell_tag, a)),
+                    None => out.push_str(&format!("<{}>", cell_tag)),
+                }
+                renderer.inline(cell, out);
+                out.push_str(&format!("</{}>", cell_tag));
+            }
+            out.push_str("</tr>\n");
+        };
+
+        out.push_str("<table>\n<thead>\n");
+        row(self, &lines[start], "th", out);
+        out.push_str("</thead>\n<tbody>\n");
+        let mut i = start + 2;
+        while i < lines.len() && is_table_row(&lines[i]) {
+            row(self, &lines[i], "td", out);
+            i += 1;
+        }
+        out.push_str("</tbody>\n</table>\n");
+        i
+    }
+
+    /// Render inline Markdown: emphasis, code spans, links, images and, with `raw_html`, tags
+    fn inline(&mut self, text: &str, out: &mut String) {
+        let mut i = 0;
+        while i < text.len() {
+            let rest = &text[i..];
+            let c = rest.chars().next().expect("non-empty");
+            match c {
+                '\\' => {
+                    let next = rest[1..].chars().next();
+                    match next {
+                        Some(p) if p.is_ascii_punctuation() => {
+                            out.push_str(&escape(&p.to_string()));
+                            i += 2;
+                        }
+                        Some('\n') => {
+                            out.push_str("<br/>\n");
+                            i += 2;
+                        }
+                        _ => {
+                            out.push('\\');
+                            i += 1;
+                        }
+                    }
+                }
+                '\n' => {
+                    // Two trailing spaces make a hard line break
+                    if out.ends_with("  ") {
+                        out.truncate(out.trim_end_matches(' ').len());
+                        out.push_str("<br/>");
+                    }
+                    out.push('\n');
+                    i += 1;
+                }
+                '`' => {
+                    let run = rest.len() - rest.trim_start_matches('`').len();
+                    let fence = &rest[..run];
+                    match rest[run..].find(fence).filter(|&end| !rest[run + end + run..].starts_with('`')) {
+                        Some(end) => {
+                            let code = &rest[run..run + end];
+                            let code = if code.len() > 1 && code.starts_with(' ') && code.ends_with(' ') {
+                                &code[1..code.len() - 1]
+                            } else {
+                                code
+                            };
+                            out.push_str(&format!("<code>{}</code>", escape(code)));
+                            i += run + end + run;
+                        }
+                        None => {
+                            out.push_str(fence);
+                            i += run;
+                        }
+                    }
+                }
+                '!' if rest.starts_with("![") => match link_at(&rest[1..]) {
+                    Some(link) => {
+                        let src = local_href(link.destination);
+                        if !src.contains(':') && !src.is_empty() {
+                            self.images.push(src.clone());
+                        }
+                        let alt = unescape(&strip_tags(&{
+                            let mut alt = String::new();
+                            self.inline(link.label, &mut alt);
+                            alt
+                        }));
+                        out.push_str(&format!("<img src=\"{}\" alt=\"{}\"", escape(&src), escape(&alt)));
+                        if let Some(title) = link.title {
+                            out.push_str(&format!(" title=\"{}\"", escape(title)));
+                        }
+                        out.push_str("/>");
+                        i += 1 + link.consumed;
+                    }
+                    None => {
+                        out.push('!');
+                        i += 1;
+                    }
+                },
+                '[' => match link_at(rest) {
+                    Some(link) => {
+                        out.push_str(&format!("<a href=\"{}\"", escape(&local_href(link.destination))));
+                        if let Some(title) = link.title {
+                            out.push_str(&format!(" title=\"{}\"", escape(title)));
+                        }
+                        out.push('>');
+                        self.inline(link.label, out);
+                        out.push_str("</a>");
+                        i += link.consumed;
+                    }
+                    None => {
+                        out.push('[');
+                        i += 1;
+                    }
+                },
+                '*' | '_' | '~' | '=' => {
+                    let consumed = self.delimited(text, i, out);
+                    if consumed == 0 {
+                        out.push(c);
+                        i += 1;
+                    } else {
+                        i += consumed;
+                    }
+                }
+                '<' => {
+                    let consumed = self.angle(rest, out);
+                    if consumed == 0 {
+                        out.push_str("&lt;");
+                        i += 1;
+                    } else {
+                        i += consumed;
+                    }
+                }
+                '&' => {
+                    let (replacement, consumed) = entity(rest);
+                    out.push_str(&replacement);
+                    i += consumed;
+                }
+                '>' => {
+                    out.push_str("&gt;");
+                    i += 1;
+                }
+                '"' => {
+                    out.push_str("&quot;");
+                    i += 1;
+                }
+                c => {
+                    out.push(c);
+                    i += c.len_utf8();
+                }
+            }
+        }
+    }
+
+    /// Emphasis (`*em*`, `**strong**`, `_em_`), strikethrough (`~~del~~`) or highlight
+    /// (`==mark==`) starting at byte `at`. Returns the bytes consumed, or 0 if the
+    /// delimiter is literal text.
+    fn delimited(&mut self, text: &str, at: usize, out: &mut String) -> usize {
+        let rest = &text[at..];
+        let c = rest.chars().next().expect("non-empty");
+        let run = rest.len() - rest.trim_start_matches(c).len();
+        let (delimiter, tag) = match (c, run) {
+            ('*' | '_', 1) => (&rest[..1], "em"),
+            ('*' | '_', _) => (&rest[..2], "strong"),
+            ('~', 2) => (&rest[..2], "del"),
+            ('=', 2) => (&rest[..2], "mark"),
+            _ => return 0,
+        };
+        let opens = rest[delimiter.len()..].chars().next().is_some_and(|n| !n.is_whitespace());
+        // `snake_case_names` are not emphasis
+        let intraword = c == '_' && text[..at].chars().next_back().is_some_and(char::is_alphanumeric);
+        if !opens || intraword {
+            return 0;
+        }
+
+        // Find the closing delimiter, stepping over longer runs of the same character
+        let body = &rest[delimiter.len()..];
+        let mut search = 0;
+        while let Some(found) = body[search..].find(delimiter).map(|n| search + n) {
+            let closing_run = body[found..].len() - body[found..].trim_start_matches(c).len();
+            let before_ok = body[..found].chars().next_back().is_some_and(|b| !b.is_whitespace());
+            let after = body[found + closing_run..].chars().next();
+            let after_ok = c != '_' || after.is_none_or(|a| !a.is_alphanumeric());
+            let run_ok = closing_run == delimiter.len() || (delimiter.len() == 2 && closing_run == 3);
+            if found > 0 && before_ok && after_ok && run_ok {
+                // `***both***` closes the inner emphasis first
+                let end = if closing_run == 3 { found + 1 } else { found };
+                out.push_str(&format!("<{}>", tag));
+                self.inline(&body[..end], out);
+                out.push_str(&format!("</{}>", tag));
+                return delimiter.len() + end + delimiter.len();
+            }
+            search = found + closing_run.max(1);
+        }
+        0
+    }
+
+    /// An autolink (`<https://...>`), comment or, with `raw_html`, an HTML tag at the start
+    /// of `rest`. Returns the bytes consumed, or 0 if the `<` is literal text.
+    fn angle(&mut self, rest: &str, out: &mut String) -> usize {
+        if rest.starts_with("<!--") {
+            return rest.find("-->").map_or(0, |end| end + 3);
+        }
+        let Some(end) = rest.find('>') else { return 0 };
+        let inner = &rest[1..end];
+        if inner.contains(['<', '\n']) {
+            return 0;
+        }
+        if (inner.starts_with("http://") || inner.starts_with("https://") || inner.starts_with("mailto:"))
+            && !inner.contains(' ')
+        {
+            out.push_str(&format!("<a href=\"{0}\">{0}</a>", escape(inner)));
+            return end + 1;
+        }
+        if !self.raw_html {
+            return 0;
+        }
+        let name: String = inner
+            .trim_start_matches('/')
+            .chars()
+            .take_while(|c| c.is_ascii_alphanumeric() || *c == '-')
+            .collect();
+        if name.is_empty() || !name.chars().next().is_some_and(|c| c.is_ascii_alphabetic()) {
+            return 0;
+        }
+        let lower = name.to_ascii_lowercase();
+        if VOID_TAGS.contains(&lower.as_str()) && !inner.ends_with('/') {
+            out.push_str(&format!("<{}/>", inner.trim_end()));
+        } else {
+            out.push_str(&rest[..=end]);
+        }
+        end + 1
+    }
+}
+
+/// A parsed `[label](destination "title")`
+struct Link<'a> {
+    label: &'a str,
+    destination: &'a str,
+    title: Option<&'a str>,
+    consumed: usize,
+}
+
+fn link_at(text: &str) -> Option<Link<'_>> {
+    // The label ends at the matching bracket
+    let mut depth = 0;
+    let mut label_end = None;
+    let mut escaped = false;
+    for (n, c) in text.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '[' => depth += 1,
+            ']' => {
+                depth -= 1;
+                if depth == 0 {
+                    label_end = Some(n);
+                    break;
+                }
+            }
+            _ => {}
+        }
+    }
+    let label_end = label_end?;
+    let after = &text[label_end + 1..];
+    if !after.starts_with('(') {
+        return None;
+    }
+
+    // The destination ends at the matching parenthesis
+    let mut depth = 0;
+    let mut close = None;
+    for (n, c) in after.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    close = Some(n);
+                    break;
+                }
+            }
+            '\n' if depth > 0 && after[..n].contains("\n") => return None,
+            _ => {}
+        }
+    }
+    let close = close?;
+    let inside = after[1..close].trim();
+    let (destination, title) = match inside.find([' ', '\n']) {
+        Some(space) => {
+            let title = inside[space..].trim();
+            let title = title
+                .strip_prefix('"')
+                .and_then(|t| t.strip_suffix('"'))
+                .or_else(|| title.strip_prefix('\'').and_then(|t| t.strip_suffix('\'')));
+            (&inside[..space], title)
+        }
+        None => (inside, None),
+    };
+    let destination = destination.strip_prefix('<').and_then(|d| d.strip_suffix('>')).unwrap_or(destination);
+    Some(Link { label: &text[1..label_end], destination, title, consumed: label_end + 1 + close + 1 })
+}
+
+/// Point links to converted chapters at their XHTML documents
+fn local_href(href: &str) -> String {
+    if href.contains("://") || href.starts_with("mailto:") || href.starts_with('#') {
+        return href.to_string();
+    }
+    let (path, fragment) = match href.split_once('#') {
+        Some((path, fragment)) => (path, Some(fragment)),
+        None => (href, None),
+    };
+    let path = match path.strip_suffix(".md") {
+        Some(stem) => format!("{}.xhtml", stem),
+        None => path.to_string(),
+    };
+    match fragment {
+        Some(fragment) => format!("{}#{}", path, fragment),
+        None => path,
+    }
+}
+
+/// An entity reference at the start of `text`: XML's own and numeric ones are kept,
+/// common HTML ones become their characters, and anything else is an escaped `&`
+fn entity(text: &str) -> (String, usize) {
+    let name_end = text[1..].find(';').map(|n| n + 1).filter(|&n| n > 1 && n <= 10);
+    if let Some(end) = name_end {
+        let name = &text[1..end];
+        let numeric = name.strip_prefix('#').is_some_and(|n| {
+            n.strip_prefix(['x', 'X']).map_or(!n.is_empty() && n.chars().all(|c| c.is_ascii_digit()), |h| {
+                !h.is_empty() && h.chars().all(|c| c.is_ascii_hexdigit())
+            })
+        });
+        if numeric || matches!(name, "amp" | "lt" | "gt" | "quot" | "apos") {
+            return (text[..=end].to_string(), end + 1);
+        }
+        if let Some((_, c)) = ENTITIES.iter().find(|(n, _)| *n == name) {
+            return (c.to_string(), end + 1);
+        }
+    }
+    ("&amp;".to_string(), 1)
+}
+
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<").replace("&gt;", ">").replace("&quot;", "\"").replace("&amp;", "&")
+}
+
+fn strip_tags(html: &str) -> String {
+    let mut out = String::with_capacity(html.len());
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            c if !in_tag => out.push(c),
+            _ => {}
+        }
+    }
+    out
+}
+
+/// Opening fence (``` or ~~~, three or more) and its indentation
+fn fence(line: &str) -> Option<(String, usize)> {
+    let trimmed = line.trim_start();
+    let indent = line.len() - trimmed.len();
+    let c = trimmed.chars().next().filter(|c| matches!(c, '`' | '~'))?;
+    let run = trimmed.len() - trimmed.trim_start_matches(c).len();
+    (indent <= 3 && run >= 3).then(|| (trimmed[..run].to_string(), indent))
+}
+
+fn atx_heading(line: &str) -> Option<(usize, &str)> {
+    let trimmed = line.trim_start();
+    if line.len() - trimmed.len() > 3 {
+        return None;
+    }
+    let level = trimmed.len() - trimmed.trim_start_matches('#').len();
+    let rest = &trimmed[level..];
+    if !(1..=6).contains(&level) || !(rest.is_empty() || rest.starts_with(' ')) {
+        return None;
+    }
+    let text = rest.trim().trim_end_matches('#').trim_end();
+    Some((level, text))
+}
+
+fn setext_underline(line: &str) -> Option<usize> {
+    let trimmed = line.trim();
+    if trimmed.len() < 2 {
+        return None;
+    }
+    if trimmed.chars().all(|c| c == '=') {
+        Some(1)
+    } else if trimmed.chars().all(|c| c == '-') {
+        Some(2)
+    } else {
+        None
+    }
+}
+
+/// `***`, `---`, `___` or spaced variants such as `* * *`
+fn is_rule(line: &str) -> bool {
+    let compact: String = line.chars().filter(|c| !c.is_whitespace()).collect();
+    compact.len() >= 3
+        && ['*', '-', '_'].iter().any(|&c| compact.chars().all(|x| x == c))
+        && line.len() - line.trim_start().len() <= 3
+}
+
+struct ListMarker {
+    ordered: bool,
+    start: Option<u64>,
+    /// Column where the item's content begins
+    content: usize,
+}
+
+fn list_marker(line: &str) -> Option<ListMarker> {
+    if is_rule(line) {
+        return None;
+    }
+    let trimmed = line.trim_start();
+    let indent = line.len() - trimmed.len();
+    let (ordered, start, marker_len) = if trimmed.starts_with(['*', '+', '-']) {
+        (false, None, 1)
+    } else {
+        let digits = trimmed.len() - trimmed.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+        if digits == 0 || digits > 9 || !trimmed[digits..].starts_with(['.', ')']) {
+            return None;
+        }
+        (true, trimmed[..digits].parse().ok(), digits + 1)
+    };
+    let after = &trimmed[marker_len..];
+    if !(after.is_empty() || after.starts_with(' ')) {
+        return None;
+    }
+    let spaces = (after.len() - after.trim_start().len()).clamp(1, 4);
+    Some(ListMarker { ordered, start, content: indent + marker_len + spaces })
+}
+
+fn is_block_tag(line: &str) -> bool {
+    let Some(inner) = line.strip_prefix('<').and_then(|l| l.strip_suffix('>')) else {
+        return false;
+    };
+    let name: String = inner.trim_start_matches('/').chars().take_while(|c| c.is_ascii_alphanumeric()).collect();
+    BLOCK_TAGS.contains(&name.to_ascii_lowercase().as_str()) && !inner.contains(['<', '>'])
+}
+
+fn is_table_row(line: &str) -> bool {
+    let trimmed = line.trim();
+    trimmed.starts_with('|') || (trimmed.contains('|') && !trimmed.is_empty())
+}
+
+fn is_table_separator(line: &str) -> bool {
+    let cells = table_cells(line);
+    !cells.is_empty()
+        && cells.iter().all(|cell| {
+            let core = cell.trim_matches(':');
+            !core.is_empty() && core.chars().all(|c| c == '-')
+        })
+}
+
+/// Cells of a pipe-table row, split on unescaped `|`
+fn table_cells(line: &str) -> Vec<String> {
+    let trimmed = line.trim();
+    let trimmed = trimmed.strip_prefix('|').unwrap_or(trimmed);
+    let trimmed = trimmed.strip_suffix('|').filter(|t| !t.ends_with('\\')).unwrap_or(trimmed);
+    let mut cells = Vec::new();
+    let mut cell = String::new();
+    let mut chars = trimmed.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.peek() == Some(&'|') => {
+                cell.push('|');
+                chars.next();
+            }
+            '|' => cells.push(std::mem::take(&mut cell).trim().to_string()),
+            c => cell.push(c),
+        }
+    }
+    cells.push(cell.trim().to_string());
+    cells
+}
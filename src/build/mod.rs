@@ -0,0 +1,403 @@
+use crate::digest::Sha256;
+use crate::output;
+use crate::saved::SavedBook;
+use crate::styles::STYLES_DIR;
+use crate::units;
+use anyhow::{bail, Context, Result};
+use std::collections::HashSet;
+use std::fs;
+use std::io::{Cursor, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use walkdir::WalkDir;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+mod markdown;
+
+/// Directory inside the archive that holds the package document and content
+const CONTENT_DIR: &str = "OEBPS";
+
+const CONTAINER_XML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+  <rootfiles>
+    <rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+  </rootfiles>
+</container>
+"#;
+
+/// A chapter rendered to XHTML, ready to be packaged
+struct Chapter {
+    /// Path inside the content directory, e.g. `chapter_001.xhtml`
+    href: String,
+    title: String,
+    xhtml: String,
+}
+
+/// A non-chapter file carried into the EPUB
+struct Resource {
+    /// Path inside the content directory
+    href: String,
+    media_type: &'static str,
+    content: Vec<u8>,
+}
+
+/// Reassemble an EPUB3 from a directory of Markdown chapters and the metadata.json that
+/// `convert` wrote alongside them. Chapters follow the order recorded in metadata.json,
+/// falling back to the sorted `*.md` files; images they link to and any stylesheets and
+/// fonts under `styles/` are packaged with them.
+pub fn build_epub(input_dir: &Path, output_path: &Path) -> Result<()> {
+    let book = SavedBook::load(input_dir)?;
+    let language = book.language.clone().unwrap_or_else(|| "en".to_string());
+
+    let styles = collect_styles(input_dir)?;
+    let stylesheets: Vec<&str> =
+        styles.iter().filter(|r| r.media_type == "text/css").map(|r| r.href.as_str()).collect();
+
+    let mut chapters = Vec::new();
+    let mut resources: Vec<Resource> = Vec::new();
+    let mut packaged: HashSet<String> = HashSet::new();
+    for (n, file) in chapter_files(input_dir, &book)?.iter().enumerate() {
+        let path = output::safe_join(input_dir, file).with_context(|| format!("Refusing to read {}", file))?;
+        let text = fs::read_to_string(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+        let markdown = strip_front_matter(&text);
+
+        let href = match file.strip_suffix(".md") {
+            Some(stem) => format!("{}.xhtml", stem),
+            None => format!("{}.xhtml", file),
+        };
+        let depth = href.matches('/').count();
+        let css: Vec<String> = stylesheets.iter().map(|s| format!("{}{}", "../".repeat(depth), s)).collect();
+
+        let mut rendered = markdown::render(markdown, true);
+        let title = rendered.title.clone().unwrap_or_else(|| format!("Chapter {}", n + 1));
+        let mut xhtml = xhtml_document(&title, &language, &css, &rendered.body);
+        if let Err(e) = well_formed(&xhtml) {
+            // Hand-written HTML that isn't valid XML would make the whole EPUB unreadable
+            eprintln!("  Warning: {} has HTML that isn't well-formed XML ({}); escaping it", file, e);
+            rendered = markdown::render(markdown, false);
+            xhtml = xhtml_document(&title, &language, &css, &rendered.body);
+        }
+
+        let chapter_dir = Path::new(file).parent().unwrap_or(Path::new("")).to_string_lossy().replace('\\', "/");
+        for src in &rendered.images {
+            let Some(image_href) = resolve(&chapter_dir, src) else {
+                eprintln!("  Warning: {} refers to {}, outside {}", file, src, input_dir.display());
+                continue;
+            };
+            if packaged.contains(&image_href) {
+                continue;
+            }
+            let Some(found) = find_image(input_dir, &image_href) else {
+                eprintln!("  Warning: {} refers to {}, which isn't in {}", file, src, input_dir.display());
+                continue;
+            };
+            let content = fs::read(&found).with_context(|| format!("Failed to read {}", found.display()))?;
+            packaged.insert(image_href.clone());
+            resources.push(Resource { media_type: media_type(&image_href), href: image_href, content });
+        }
+
+        chapters.push(Chapter { href, title, xhtml });
+    }
+    if chapters.is_empty() {
+        bail!("No Markdown chapters found in {}", input_dir.display());
+    }
+    resources.extend(styles);
+
+    let identifier = book.identifier.clone().unwrap_or_else(|| derived_uuid(&book));
+    let opf = package_document(&book, &identifier, &language, &chapters, &resources);
+    let nav = nav_document(&book, &language, &chapters);
+    let ncx = ncx_document(&book, &identifier, &chapters);
+
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    // The mimetype entry must come first and be stored uncompressed
+    let stored = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+    let deflated = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    let mut add = |name: &str, content: &[u8], options: SimpleFileOptions| -> Result<()> {
+        zip.start_file(name, options).with_context(|| format!("Failed to add {} to the archive", name))?;
+        zip.write_all(content).with_context(|| format!("Failed to add {} to the archive", name))
+    };
+    add("mimetype", b"application/epub+zip", stored)?;
+    add("META-INF/container.xml", CONTAINER_XML.as_bytes(), deflated)?;
+    add(&format!("{}/content.opf", CONTENT_DIR), opf.as_bytes(), deflated)?;
+    add(&format!("{}/nav.xhtml", CONTENT_DIR), nav.as_bytes(), deflated)?;
+    add(&format!("{}/toc.ncx", CONTENT_DIR), ncx.as_bytes(), deflated)?;
+    for chapter in &chapters {
+        add(&format!("{}/{}", CONTENT_DIR, chapter.href), chapter.xhtml.as_bytes(), deflated)?;
+    }
+    for resource in &resources {
+        add(&format!("{}/{}", CONTENT_DIR, resource.href), &resource.content, deflated)?;
+    }
+    let archive = zip.finish().context("Failed to finish the archive")?.into_inner();
+
+    output::write_atomic(output_path, &archive)
+        .with_context(|| format!("Failed to write {}", output_path.display()))?;
+    println!(
+        "Built {} with {} chapter(s) and {} resource(s)",
+        output_path.display(),
+        chapters.len(),
+        resources.len()
+    );
+    Ok(())
+}
+
+/// Chapter files in reading order: those metadata.json lists, else every `*.md` file
+fn chapter_files(input_dir: &Path, book: &SavedBook) -> Result<Vec<String>> {
+    let mut files: Vec<String> = Vec::new();
+    for chapter in &book.chapters {
+        // With --single-file every chapter points at the same file
+        if !files.contains(&chapter.file) {
+            files.push(chapter.file.clone());
+        }
+    }
+    if !files.is_empty() {
+        return Ok(files);
+    }
+    for entry in fs::read_dir(input_dir).with_context(|| format!("Failed to read {}", input_dir.display()))? {
+        let name = entry?.file_name().to_string_lossy().into_owned();
+        if name.ends_with(".md") && name != "README.md" {
+            files.push(name);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Stylesheets and fonts from `styles/`, as written by `--extract-styles`
+fn collect_styles(input_dir: &Path) -> Result<Vec<Resource>> {
+    let root = input_dir.join(STYLES_DIR);
+    if !root.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut resources = Vec::new();
+    for entry in WalkDir::new(&root).sort_by_file_name() {
+        let entry = entry?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let relative = entry.path().strip_prefix(input_dir).unwrap_or(entry.path());
+        let href = relative.to_string_lossy().replace('\\', "/");
+        let content = fs::read(entry.path()).with_context(|| format!("Failed to read {}", entry.path().display()))?;
+        resources.push(Resource { media_type: media_type(&href), href, content });
+    }
+    Ok(resources)
+}
+
+/// Path below the input directory of `src` as seen from a chapter in `chapter_dir`, or
+/// None if it climbs out
+fn resolve(chapter_dir: &str, src: &str) -> Option<String> {
+    let decoded = percent_encoding::percent_decode_str(src).decode_utf8_lossy();
+    let mut parts: Vec<&str> = chapter_dir.split('/').filter(|p| !p.is_empty()).collect();
+    for segment in decoded.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                parts.pop()?;
+            }
+            s => parts.push(s),
+        }
+    }
+    (!parts.is_empty()).then(|| parts.join("/"))
+}
+
+/// An image a chapter links to: at that path below the input directory, or else where
+/// `extract` unpacked it, under `resources/` at its path inside the original archive
+fn find_image(input_dir: &Path, relative: &str) -> Option<PathBuf> {
+    if let Ok(path) = output::safe_join(input_dir, relative) {
+        if path.is_file() {
+            return Some(path);
+        }
+    }
+    let suffix = format!("/{}", relative);
+    WalkDir::new(input_dir.join(crate::extract::RESOURCES_DIR))
+        .sort_by_file_name()
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .find(|e| e.file_type().is_file() && e.path().to_string_lossy().replace('\\', "/").ends_with(&suffix))
+        .map(|e| e.into_path())
+}
+
+/// Drop a leading YAML front-matter block, such as `--license-header front-matter` writes
+fn strip_front_matter(text: &str) -> &str {
+    let Some(rest) = text.strip_prefix("---\n") else { return text };
+    match rest.find("\n---\n") {
+        Some(end) => &rest[end + 5..],
+        None => text,
+    }
+}
+
+fn media_type(href: &str) -> &'static str {
+    let extension = href.rsplit('.').next().unwrap_or("").to_ascii_lowercase();
+    match extension.as_str() {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "svg" => "image/svg+xml",
+        "webp" => "image/webp",
+        "css" => "text/css",
+        "ttf" => "font/ttf",
+        "otf" => "font/otf",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        _ => "application/octet-stream",
+    }
+}
+
+fn xhtml_document(title: &str, language: &str, stylesheets: &[String], body: &str) -> String {
+    let mut out = String::with_capacity(body.len() + 512);
+    out.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<!DOCTYPE html>\n");
+    out.push_str(&format!(
+        "<html xmlns=\"http://www.w3.org/1999/xhtml\" xmlns:epub=\"http://www.idpf.org/2007/ops\" xml:lang=\"{0}\" lang=\"{0}\">\n",
+        markdown::escape(language)
+    ));
+    out.push_str(&format!("<head>\n<meta charset=\"UTF-8\"/>\n<title>{}</title>\n", markdown::escape(title)));
+    for href in stylesheets {
+        out.push_str(&format!("<link rel=\"stylesheet\" type=\"text/css\" href=\"{}\"/>\n", markdown::escape(href)));
+    }
+    out.push_str("</head>\n<body>\n");
+    out.push_str(body);
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+/// Check that a document parses as XML, as reading systems require of XHTML
+fn well_formed(xhtml: &str) -> Result<()> {
+    for event in xml::reader::EventReader::new(xhtml.as_bytes()) {
+        event?;
+    }
+    Ok(())
+}
+
+fn package_document(
+    book: &SavedBook,
+    identifier: &str,
+    language: &str,
+    chapters: &[Chapter],
+    resources: &[Resource],
+) -> String {
+    let escape = markdown::escape;
+    let element = |name: &str, value: &str| format!("    <dc:{0}>{1}</dc:{0}>\n", name, escape(value));
+
+    let mut metadata = format!("    <dc:identifier id=\"book-id\">{}</dc:identifier>\n", escape(identifier));
+    metadata.push_str(&element("title", book.title.as_deref().unwrap_or("Untitled")));
+    metadata.push_str(&element("language", language));
+    for creator in &book.creators {
+        metadata.push_str(&element("creator", creator));
+    }
+    for contributor in &book.contributors {
+        metadata.push_str(&element("contributor", contributor));
+    }
+    let optional = [
+        ("publisher", &book.publisher),
+        ("date", &book.date),
+        ("description", &book.description),
+        ("rights", &book.rights),
+    ];
+    for (name, value) in optional {
+        if let Some(value) = value {
+            metadata.push_str(&element(name, value));
+        }
+    }
+    for subject in &book.subjects {
+        metadata.push_str(&element("subject", subject));
+    }
+    if let Some(series) = &book.series {
+        metadata.push_str(&format!("    <meta property=\"belongs-to-collection\" id=\"series\">{}</meta>\n", escape(series)));
+        metadata.push_str("    <meta refines=\"#series\" property=\"collection-type\">series</meta>\n");
+        if let Some(index) = book.series_index {
+            metadata.push_str(&format!("    <meta refines=\"#series\" property=\"group-position\">{}</meta>\n", index));
+        }
+    }
+    metadata.push_str(&format!("    <meta property=\"dcterms:modified\">{}</meta>\n", units::utc_timestamp(SystemTime::now())));
+
+    let href = |h: &str| escape(&h.replace(' ', "%20"));
+    let mut manifest =
+        "    <item id=\"nav\" href=\"nav.xhtml\" media-type=\"application/xhtml+xml\" properties=\"nav\"/>\n\
+         \x20   <item id=\"ncx\" href=\"toc.ncx\" media-type=\"application/x-dtbncx+xml\"/>\n"
+            .to_string();
+    let mut spine = String::new();
+    for (n, chapter) in chapters.iter().enumerate() {
+        manifest.push_str(&format!(
+            "    <item id=\"ch{:03}\" href=\"{}\" media-type=\"application/xhtml+xml\"/>\n",
+            n + 1,
+            href(&chapter.href)
+        ));
+        spine.push_str(&format!("    <itemref idref=\"ch{:03}\"/>\n", n + 1));
+    }
+    for (n, resource) in resources.iter().enumerate() {
+        manifest.push_str(&format!(
+            "    <item id=\"res{:03}\" href=\"{}\" media-type=\"{}\"/>\n",
+            n + 1,
+            href(&resource.href),
+            resource.media_type
+        ));
+    }
+
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <package xmlns=\"http://www.idpf.org/2007/opf\" version=\"3.0\" unique-identifier=\"book-id\" xml:lang=\"{}\">\n\
+         \x20 <metadata xmlns:dc=\"http://purl.org/dc/elements/1.1/\">\n{}  </metadata>\n\
+         \x20 <manifest>\n{}  </manifest>\n\
+         \x20 <spine toc=\"ncx\">\n{}  </spine>\n\
+         </package>\n",
+        escape(language),
+        metadata,
+        manifest,
+        spine
+    )
+}
+
+/// The EPUB3 navigation document, listing each chapter by its first heading
+fn nav_document(book: &SavedBook, language: &str, chapters: &[Chapter]) -> String {
+    let mut list = String::new();
+    for chapter in chapters {
+        list.push_str(&format!(
+            "      <li><a href=\"{}\">{}</a></li>\n",
+            markdown::escape(&chapter.href.replace(' ', "%20")),
+            markdown::escape(&chapter.title)
+        ));
+    }
+    let body = format!(
+        "<nav epub:type=\"toc\" id=\"toc\">\n  <h1>Contents</h1>\n  <ol>\n{}  </ol>\n</nav>\n",
+        list
+    );
+    xhtml_document(book.title.as_deref().unwrap_or("Contents"), language, &[], &body)
+}
+
+/// The EPUB2 NCX table of contents, for reading systems and tools that don't read the nav
+fn ncx_document(book: &SavedBook, identifier: &str, chapters: &[Chapter]) -> String {
+    let escape = markdown::escape;
+    let mut points = String::new();
+    for (n, chapter) in chapters.iter().enumerate() {
+        points.push_str(&format!(
+            "    <navPoint id=\"nav{0}\" playOrder=\"{0}\">\n      <navLabel><text>{1}</text></navLabel>\n      <content src=\"{2}\"/>\n    </navPoint>\n",
+            n + 1,
+            escape(&chapter.title),
+            escape(&chapter.href.replace(' ', "%20"))
+        ));
+    }
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <ncx xmlns=\"http://www.daisy.org/z3986/2005/ncx/\" version=\"2005-1\">\n\
+         \x20 <head>\n    <meta name=\"dtb:uid\" content=\"{}\"/>\n  </head>\n\
+         \x20 <docTitle><text>{}</text></docTitle>\n\
+         \x20 <navMap>\n{}  </navMap>\n\
+         </ncx>\n",
+        escape(identifier),
+        escape(book.title.as_deref().unwrap_or("Untitled")),
+        points
+    )
+}
+
+/// A stable `urn:uuid:` for books without an identifier, from their title and authors
+fn derived_uuid(book: &SavedBook) -> String {
+    let mut hasher = Sha256::default();
+    hasher.update(book.title.as_deref().unwrap_or("").as_bytes());
+    for creator in &book.creators {
+        hasher.update(b"\0");
+        hasher.update(creator.as_bytes());
+    }
+    let hex = hasher.finalize_hex();
+    format!("urn:uuid:{}-{}-{}-{}-{}", &hex[0..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..32])
+}
+
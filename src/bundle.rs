@@ -0,0 +1,193 @@
+use crate::error::ConvertError;
+use crate::input::{self, EpubExtensions};
+use anyhow::{Context, Result};
+use flate2::read::MultiGzDecoder;
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use zip::ZipArchive;
+
+/// Bytes of an entry read to tell an EPUB without a known extension by its contents
+const SNIFF_LEN: u64 = 512;
+
+/// Archive formats searched for EPUBs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Zip,
+    Tar,
+    TarGz,
+}
+
+impl Format {
+    fn of(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_string_lossy().to_lowercase();
+        if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(Format::TarGz)
+        } else if name.ends_with(".tar") {
+            Some(Format::Tar)
+        } else if name.ends_with(".zip") {
+            Some(Format::Zip)
+        } else {
+            None
+        }
+    }
+}
+
+/// Whether a file is a zip or tar archive (optionally gzipped) that may hold EPUBs. An
+/// EPUB is a zip too, so a file taken for one is never a bundle.
+pub fn is_bundle(path: &Path, extensions: &EpubExtensions) -> bool {
+    Format::of(path).is_some() && !extensions.is_epub(path)
+}
+
+/// Where an EPUB unpacked from an archive came from
+#[derive(Debug, Clone)]
+pub struct Unpacked {
+    pub archive: PathBuf,
+    /// Its path inside the archive
+    pub entry: PathBuf,
+}
+
+impl Unpacked {
+    /// How the book is named in reports, since the scratch copy is gone once the batch is
+    /// done: `library.zip!shelf/book.epub`
+    pub fn name(&self) -> String {
+        let entry: Vec<_> = self.entry.components().map(|c| c.as_os_str().to_string_lossy()).collect();
+        format!("{}!{}", self.archive.display(), entry.join("/"))
+    }
+}
+
+/// Extract the EPUBs an archive holds into `dest`, at their paths inside it, and return
+/// where each was written. Entries are taken for EPUBs by their extension or by their
+/// first bytes; everything else is skipped without being unpacked.
+pub fn unpack(bundle: &Path, extensions: &EpubExtensions, dest: &Path) -> Result<Vec<PathBuf>> {
+    let format = Format::of(bundle).with_context(|| format!("Not a zip or tar archive: {}", bundle.display()))?;
+    let file = File::open(bundle).with_context(|| format!("Failed to open {}", bundle.display()))?;
+    let mut extracted = Vec::new();
+    match format {
+        Format::Zip => read_zip(BufReader::new(file), extensions, dest, &mut extracted),
+        Format::Tar => read_tar(BufReader::new(file), extensions, dest, &mut extracted),
+        Format::TarGz => read_tar(MultiGzDecoder::new(BufReader::new(file)), extensions, dest, &mut extracted),
+    }
+    .with_context(|| format!("Failed to unpack {}", bundle.display()))?;
+    Ok(extracted)
+}
+
+/// Extract the EPUBs among a zip archive's files
+fn read_zip(reader: BufReader<File>, extensions: &EpubExtensions, dest: &Path, extracted: &mut Vec<PathBuf>) -> Result<()> {
+    let mut zip = ZipArchive::new(reader).map_err(ConvertError::from)?;
+    for i in 0..zip.len() {
+        let mut entry = zip.by_index(i).map_err(ConvertError::from)?;
+        let Some(name) = entry.enclosed_name().filter(|_| entry.is_file()) else { continue };
+        extracted.extend(extract(&mut entry, &name, extensions, dest)?);
+    }
+    Ok(())
+}
+
+/// Write an entry out under `dest` if it is an EPUB
+fn extract(entry: &mut impl Read, name: &Path, extensions: &EpubExtensions, dest: &Path) -> Result<Option<PathBuf>> {
+    let mut head = Vec::new();
+    entry.take(SNIFF_LEN).read_to_end(&mut head)?;
+    if !extensions.matches(name) && !input::is_epub_header(&head) {
+        return Ok(None);
+    }
+    let path = dest.join(name);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut out = File::create(&path).with_context(|| format!("Failed to create {}", path.display()))?;
+    out.write_all(&head)?;
+    io::copy(entry, &mut out)?;
+    Ok(Some(path))
+}
+
+/// Walk a tar stream, extracting the EPUBs among its regular files. GNU long names and
+/// pax `path` records are honored; links, devices and directories are skipped.
+fn read_tar(mut reader: impl Read, extensions: &EpubExtensions, dest: &Path, extracted: &mut Vec<PathBuf>) -> Result<()> {
+    let mut header = [0u8; 512];
+    // Name given for the next entry by a GNU `L` or pax `x` entry before it
+    let mut long_name: Option<String> = None;
+    loop {
+        if !read_block(&mut reader, &mut header)? || header.iter().all(|&b| b == 0) {
+            return Ok(());
+        }
+        let size = number(&header[124..136]).context("Malformed tar header")?;
+        {
+            let mut data = (&mut reader).take(size);
+            match header[156] {
+                b'L' => {
+                    let mut name = Vec::new();
+                    data.read_to_end(&mut name)?;
+                    long_name = Some(c_string(&name));
+                }
+                b'x' => {
+                    let mut records = Vec::new();
+                    data.read_to_end(&mut records)?;
+                    long_name = pax_path(&String::from_utf8_lossy(&records)).or(long_name.take());
+                }
+                b'0' | 0 => {
+                    let name = long_name.take().unwrap_or_else(|| ustar_name(&header));
+                    if let Some(name) = enclosed(&name) {
+                        extracted.extend(extract(&mut data, &name, extensions, dest)?);
+                    }
+                }
+                _ => long_name = None,
+            }
+            io::copy(&mut data, &mut io::sink())?;
+        }
+        let padding = (512 - size % 512) % 512;
+        io::copy(&mut (&mut reader).take(padding), &mut io::sink())?;
+    }
+}
+
+/// Fill a 512-byte block; false at the end of the stream
+fn read_block(reader: &mut impl Read, block: &mut [u8; 512]) -> io::Result<bool> {
+    let mut read = 0;
+    while read < block.len() {
+        match reader.read(&mut block[read..])? {
+            0 if read == 0 => return Ok(false),
+            0 => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "truncated tar header")),
+            n => read += n,
+        }
+    }
+    Ok(true)
+}
+
+/// A numeric header field: octal text, or big-endian binary when its top bit is set
+fn number(field: &[u8]) -> Option<u64> {
+    if field.first().is_some_and(|&b| b & 0x80 != 0) {
+        return Some(field[1..].iter().fold(0u64, |n, &b| (n << 8) | u64::from(b)));
+    }
+    let text = std::str::from_utf8(field).ok()?.trim_matches(|c: char| c == '\0' || c == ' ');
+    if text.is_empty() {
+        return Some(0);
+    }
+    u64::from_str_radix(text, 8).ok()
+}
+
+fn c_string(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).into_owned()
+}
+
+/// An entry's name from its header, with the ustar prefix when there is one
+fn ustar_name(header: &[u8; 512]) -> String {
+    let name = c_string(&header[0..100]);
+    let prefix = if header[257..262] == *b"ustar" { c_string(&header[345..500]) } else { String::new() };
+    if prefix.is_empty() {
+        name
+    } else {
+        format!("{}/{}", prefix, name)
+    }
+}
+
+/// The `path` record of a pax extended header
+fn pax_path(records: &str) -> Option<String> {
+    records.lines().find_map(|record| record.split_once(' ')?.1.strip_prefix("path=").map(str::to_string))
+}
+
+/// An entry name as a relative path that stays inside the destination
+fn enclosed(name: &str) -> Option<PathBuf> {
+    let path = Path::new(name);
+    let path: PathBuf = path.components().filter(|c| *c != Component::CurDir).collect();
+    (!path.as_os_str().is_empty() && path.components().all(|c| matches!(c, Component::Normal(_)))).then_some(path)
+}
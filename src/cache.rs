@@ -0,0 +1,98 @@
+use crate::digest::{self, Sha256};
+use crate::output;
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// On-disk store of previous conversions, keyed by EPUB content and options
+#[derive(Debug, Clone)]
+pub struct ConversionCache {
+    root: PathBuf,
+}
+
+impl ConversionCache {
+    /// Use `dir` if given, otherwise `$XDG_CACHE_HOME/epub-to-md` or `~/.cache/epub-to-md`
+    pub fn new(dir: Option<&Path>) -> Result<Self> {
+        let root = match dir {
+            Some(dir) => dir.to_path_buf(),
+            None => default_cache_dir().context("Could not determine a cache directory; pass --cache-dir")?,
+        };
+        Ok(Self { root })
+    }
+
+    /// Cache key: SHA-256 of the EPUB bytes combined with the option fingerprint and tool version
+    pub fn key(&self, epub_path: &Path, options_fingerprint: &str) -> Result<String> {
+        let content_hash = digest::sha256_file(epub_path)
+            .with_context(|| format!("Failed to hash {}", epub_path.display()))?;
+
+        let mut hasher = Sha256::default();
+        hasher.update(content_hash.as_bytes());
+        hasher.update(env!("CARGO_PKG_VERSION").as_bytes());
+        hasher.update(options_fingerprint.as_bytes());
+        Ok(hasher.finalize_hex())
+    }
+
+    /// Copy a cached conversion into `output_dir`, returning the restored files,
+    /// or None on a cache miss
+    pub fn restore(&self, key: &str, output_dir: &Path) -> Result<Option<Vec<PathBuf>>> {
+        let entry = self.root.join(key);
+        let manifest = match fs::read_to_string(entry.join("manifest.json")) {
+            Ok(manifest) => manifest,
+            Err(_) => return Ok(None),
+        };
+        let files: Vec<PathBuf> = serde_json::from_str(&manifest)
+            .context("Corrupt cache manifest")?;
+
+        fs::create_dir_all(output_dir).context("Failed to create output directory")?;
+        output::mark_incomplete(output_dir).context("Failed to clear completion marker")?;
+        let mut restored = Vec::with_capacity(files.len());
+        for relative in files {
+            // The manifest is read from disk, so treat its paths as untrusted
+            let href = relative.to_string_lossy();
+            let target = output::resource_path(output_dir, &href)
+                .context("Refusing to restore from cache")?;
+            output::copy_atomic(&entry.join("files").join(&relative), &target)
+                .with_context(|| format!("Failed to restore {} from cache", relative.display()))?;
+            restored.push(target);
+        }
+
+        Ok(Some(restored))
+    }
+
+    /// Save the files produced by a conversion under `key`
+    pub fn store(&self, key: &str, output_dir: &Path, files: &[PathBuf]) -> Result<()> {
+        let entry = self.root.join(key);
+        // Build the entry beside its final location, then rename so readers never see a partial entry
+        let staging = self.root.join(format!("{}.tmp-{}", key, std::process::id()));
+        let _ = fs::remove_dir_all(&staging);
+
+        let mut relative_files = Vec::with_capacity(files.len());
+        for file in files {
+            let relative = file.strip_prefix(output_dir).unwrap_or(file).to_path_buf();
+            let target = staging.join("files").join(&relative);
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent).context("Failed to create cache directory")?;
+            }
+            fs::copy(file, &target)
+                .with_context(|| format!("Failed to cache {}", relative.display()))?;
+            relative_files.push(relative);
+        }
+
+        fs::create_dir_all(&staging).context("Failed to create cache directory")?;
+        let manifest = serde_json::to_string_pretty(&relative_files)
+            .context("Failed to serialize cache manifest")?;
+        fs::write(staging.join("manifest.json"), manifest)
+            .context("Failed to write cache manifest")?;
+
+        let _ = fs::remove_dir_all(&entry);
+        fs::rename(&staging, &entry).context("Failed to finalize cache entry")?;
+        Ok(())
+    }
+}
+
+fn default_cache_dir() -> Option<PathBuf> {
+    if let Some(xdg) = std::env::var_os("XDG_CACHE_HOME").filter(|v| !v.is_empty()) {
+        return Some(PathBuf::from(xdg).join("epub-to-md"));
+    }
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache").join("epub-to-md"))
+}
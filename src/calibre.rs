@@ -0,0 +1,178 @@
+use crate::sqlite::{Database, Row, Value};
+use anyhow::{Context, Result};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+
+/// What a Calibre library's database says about a book, carried over into its output in
+/// place of the EPUB's own metadata
+#[derive(Debug, Clone, Default)]
+pub struct Book {
+    pub title: String,
+    /// In the order Calibre lists them
+    pub authors: Vec<String>,
+    pub tags: Vec<String>,
+    pub series: Option<String>,
+    pub series_index: Option<f64>,
+    /// Custom columns by lookup name (`#genre`); series columns add `#name_index`
+    pub custom_columns: BTreeMap<String, serde_json::Value>,
+    /// The book's folder in the library, `Author/Title (id)`
+    pub path: PathBuf,
+    /// Its EPUB format's file
+    pub epub: PathBuf,
+}
+
+/// The books of a Calibre library that have an EPUB format
+#[derive(Debug)]
+pub struct Library {
+    /// In the order of their ids, the order they were added
+    pub books: Vec<Book>,
+    /// Books left out for having no EPUB format, or a missing file
+    pub without_epub: usize,
+}
+
+impl Library {
+    /// Read a library's `metadata.db`
+    pub fn open(dir: &Path) -> Result<Self> {
+        let db_path = dir.join("metadata.db");
+        let db = Database::open(&db_path).with_context(|| format!("Failed to read Calibre library {}", dir.display()))?;
+        let read = |table: &str| db.rows(table).with_context(|| format!("Failed to read {} from {}", table, db_path.display()));
+
+        let authors = links(&read("books_authors_link")?, "author", &names(&read("authors")?));
+        let tags = links(&read("books_tags_link")?, "tag", &names(&read("tags")?));
+        let series = links(&read("books_series_link")?, "series", &names(&read("series")?));
+        let custom = custom_columns(&db)?;
+        let mut epubs: HashMap<i64, String> = HashMap::new();
+        for row in read("data")? {
+            if text(&row, "format").is_some_and(|f| f.eq_ignore_ascii_case("epub")) {
+                if let (Some(book), Some(name)) = (int(&row, "book"), text(&row, "name")) {
+                    epubs.entry(book).or_insert_with(|| name.to_string());
+                }
+            }
+        }
+
+        let mut library = Library { books: Vec::new(), without_epub: 0 };
+        for row in read("books")? {
+            let (Some(id), Some(path)) = (int(&row, "id"), text(&row, "path")) else { continue };
+            let path = PathBuf::from(path);
+            let epub = epubs.get(&id).map(|name| dir.join(&path).join(format!("{}.epub", name))).filter(|p| p.is_file());
+            let Some(epub) = epub else {
+                library.without_epub += 1;
+                continue;
+            };
+            let mut tags = tags.get(&id).cloned().unwrap_or_default();
+            tags.sort_by_key(|t| t.to_lowercase());
+            library.books.push(Book {
+                title: text(&row, "title").unwrap_or_default().to_string(),
+                authors: authors.get(&id).cloned().unwrap_or_default(),
+                tags,
+                series: series.get(&id).and_then(|s| s.first()).cloned(),
+                series_index: series.contains_key(&id).then(|| row.get("series_index").and_then(Value::as_f64)).flatten(),
+                custom_columns: custom.get(&id).cloned().unwrap_or_default(),
+                path,
+                epub,
+            });
+        }
+        Ok(library)
+    }
+}
+
+fn int(row: &Row, column: &str) -> Option<i64> {
+    row.get(column).and_then(Value::as_i64)
+}
+
+fn text<'a>(row: &'a Row, column: &str) -> Option<&'a str> {
+    row.get(column).and_then(Value::as_str).map(str::trim).filter(|t| !t.is_empty())
+}
+
+/// The `name` of each row of a lookup table such as `tags`, by id
+fn names(rows: &[Row]) -> HashMap<i64, String> {
+    rows.iter().filter_map(|row| Some((int(row, "id")?, text(row, "name")?.to_string()))).collect()
+}
+
+/// The values a link table such as `books_tags_link` gives each book, in link order
+fn links(rows: &[Row], column: &str, values: &HashMap<i64, String>) -> HashMap<i64, Vec<String>> {
+    let mut linked: HashMap<i64, Vec<String>> = HashMap::new();
+    for row in rows {
+        if let (Some(book), Some(value)) = (int(row, "book"), int(row, column).and_then(|v| values.get(&v))) {
+            linked.entry(book).or_default().push(value.clone());
+        }
+    }
+    linked
+}
+
+/// Every book's custom column values. Normalized columns keep their values in
+/// `custom_column_N` and link them to books in `books_custom_column_N_link`; the rest
+/// store one value per book directly. Composite columns are computed by Calibre and not
+/// stored at all.
+fn custom_columns(db: &Database) -> Result<HashMap<i64, BTreeMap<String, serde_json::Value>>> {
+    let mut books: HashMap<i64, BTreeMap<String, serde_json::Value>> = HashMap::new();
+    if !db.has_table("custom_columns") {
+        return Ok(books);
+    }
+    for column in db.rows("custom_columns")? {
+        let (Some(id), Some(label), Some(datatype)) = (int(&column, "id"), text(&column, "label"), text(&column, "datatype")) else { continue };
+        let flag = |name: &str| int(&column, name).is_some_and(|n| n != 0);
+        let table = format!("custom_column_{}", id);
+        if datatype == "composite" || flag("mark_for_delete") || !db.has_table(&table) {
+            continue;
+        }
+        let key = format!("#{}", label);
+
+        // Each book's values, with the index a series column gives each
+        let mut values: HashMap<i64, Vec<(Value, Option<f64>)>> = HashMap::new();
+        if flag("normalized") {
+            let link_table = format!("books_custom_column_{}_link", id);
+            let lookup: HashMap<i64, Value> =
+                db.rows(&table)?.into_iter().filter_map(|row| Some((int(&row, "id")?, row.get("value")?.clone()))).collect();
+            for link in db.rows(&link_table)? {
+                let (Some(book), Some(value)) = (int(&link, "book"), int(&link, "value").and_then(|v| lookup.get(&v))) else { continue };
+                values.entry(book).or_default().push((value.clone(), link.get("extra").and_then(Value::as_f64)));
+            }
+        } else {
+            for row in db.rows(&table)? {
+                let (Some(book), Some(value)) = (int(&row, "book"), row.get("value")) else { continue };
+                values.entry(book).or_default().push((value.clone(), None));
+            }
+        }
+
+        for (book, mut entries) in values {
+            let fields = books.entry(book).or_default();
+            if datatype == "series" {
+                if let Some(index) = entries.first().and_then(|(_, index)| *index) {
+                    fields.insert(format!("{}_index", key), json_number(index));
+                }
+            }
+            if flag("is_multiple") {
+                entries.sort_by_key(|(value, _)| value.as_str().unwrap_or_default().to_lowercase());
+                fields.insert(key.clone(), entries.iter().map(|(value, _)| json_value(value, datatype)).collect());
+            } else if let Some((value, _)) = entries.first() {
+                fields.insert(key.clone(), json_value(value, datatype));
+            }
+        }
+    }
+    Ok(books)
+}
+
+/// A custom column value as JSON: booleans and numbers as such, ratings in stars (Calibre
+/// stores half-stars, 0 to 10), everything else as text
+fn json_value(value: &Value, datatype: &str) -> serde_json::Value {
+    match (datatype, value) {
+        (_, Value::Null) => serde_json::Value::Null,
+        ("bool", value) => serde_json::Value::Bool(value.as_i64().is_some_and(|n| n != 0)),
+        ("rating", value) => value.as_f64().map_or(serde_json::Value::Null, |n| json_number(n / 2.0)),
+        ("int" | "float", value) => value.as_f64().map_or(serde_json::Value::Null, json_number),
+        (_, Value::Text(text)) => serde_json::Value::String(text.clone()),
+        (_, Value::Integer(n)) => serde_json::Value::from(*n),
+        (_, Value::Real(f)) => json_number(*f),
+        (_, Value::Blob(_)) => serde_json::Value::Null,
+    }
+}
+
+/// A number as JSON, whole numbers without a fraction
+fn json_number(n: f64) -> serde_json::Value {
+    if n.fract() == 0.0 && n.abs() < 1e15 {
+        serde_json::Value::from(n as i64)
+    } else {
+        serde_json::Value::from(n)
+    }
+}
@@ -0,0 +1,111 @@
+use clap::ValueEnum;
+
+/// Syntax used for notes, tips and warnings
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum CalloutStyle {
+    /// `> [!warning]` blockquote callouts (Obsidian, GitHub)
+    Obsidian,
+    /// `!!! warning` admonitions with indented content (MkDocs Material, Python-Markdown)
+    Mkdocs,
+    /// `::: warning` fenced divs (Pandoc, Docusaurus, VitePress)
+    Fenced,
+    /// `<aside>` blocks starting with an emoji, which Notion imports as callouts
+    Notion,
+}
+
+/// Built-in mapping from publisher class names and `epub:type` values to callout types
+const DEFAULT_TYPES: &[(&str, &str)] = &[
+    ("note", "note"),
+    ("notes", "note"),
+    ("notice", "note"),
+    ("tip", "tip"),
+    ("tips", "tip"),
+    ("hint", "tip"),
+    ("help", "tip"),
+    ("info", "info"),
+    ("information", "info"),
+    ("important", "important"),
+    ("warning", "warning"),
+    ("warn", "warning"),
+    ("caution", "caution"),
+    ("danger", "danger"),
+    ("example", "example"),
+];
+
+/// Parse a `--callout-class CLASS=TYPE` mapping
+pub fn parse_mapping(spec: &str) -> Result<(String, String), String> {
+    let (class, kind) = spec
+        .split_once('=')
+        .ok_or_else(|| format!("invalid callout mapping '{}' (expected CLASS=TYPE)", spec))?;
+    let (class, kind) = (class.trim(), kind.trim());
+    if class.is_empty() || kind.is_empty() || kind.contains(char::is_whitespace) {
+        return Err(format!("invalid callout mapping '{}' (expected CLASS=TYPE)", spec));
+    }
+    Ok((class.to_ascii_lowercase(), kind.to_ascii_lowercase()))
+}
+
+/// The callout type for an element's `class` and `epub:type` attributes, if any.
+/// Custom mappings win over the built-in table. A class matches as a whole
+/// (`warning`) or by its hyphen/underscore-separated parts (`box-warning`), so
+/// `footnote` is never taken for a note.
+pub fn callout_type(attributes: &[String], custom: &[(String, String)]) -> Option<String> {
+    let tokens: Vec<String> = attributes
+        .iter()
+        .flat_map(|a| a.split_whitespace())
+        .map(|t| t.to_ascii_lowercase())
+        .collect();
+    let lookup = |token: &str| {
+        custom
+            .iter()
+            .find(|(class, _)| class == token)
+            .map(|(_, kind)| kind.clone())
+            .or_else(|| DEFAULT_TYPES.iter().find(|(class, _)| *class == token).map(|(_, kind)| kind.to_string()))
+    };
+    // Whole tokens first, so a custom `sidebar-tip=note` beats the built-in `tip`
+    tokens
+        .iter()
+        .find_map(|t| lookup(t))
+        .or_else(|| tokens.iter().flat_map(|t| t.split(['-', '_'])).find_map(lookup))
+}
+
+/// Write `content` as a callout of `kind`
+pub fn format(style: CalloutStyle, kind: &str, content: &str) -> String {
+    let mut out = String::with_capacity(content.len() + 32);
+    match style {
+        CalloutStyle::Obsidian => {
+            out.push_str(&format!("> [!{}]\n", kind));
+            for line in content.lines() {
+                out.push_str(if line.is_empty() { ">" } else { "> " });
+                out.push_str(line);
+                out.push('\n');
+            }
+        }
+        CalloutStyle::Mkdocs => {
+            out.push_str(&format!("!!! {}\n\n", kind));
+            for line in content.lines() {
+                if !line.is_empty() {
+                    out.push_str("    ");
+                    out.push_str(line);
+                }
+                out.push('\n');
+            }
+        }
+        CalloutStyle::Fenced => {
+            out.push_str(&format!("::: {}\n", kind));
+            out.push_str(content);
+            out.push_str("\n:::\n");
+        }
+        CalloutStyle::Notion => {
+            let icon = match kind {
+                "warning" | "caution" | "danger" => "⚠️",
+                "important" => "❗",
+                "info" => "ℹ️",
+                "note" => "📝",
+                "example" => "📌",
+                _ => "💡",
+            };
+            out.push_str(&format!("<aside>\n{} {}\n</aside>\n", icon, content.trim()));
+        }
+    }
+    out
+}
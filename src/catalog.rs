@@ -0,0 +1,103 @@
+use crate::output;
+use crate::saved::SavedBook;
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+/// One row of the library catalog
+#[derive(Debug, Serialize)]
+struct CatalogEntry {
+    title: Option<String>,
+    authors: Vec<String>,
+    language: Option<String>,
+    subjects: Vec<String>,
+    tags: Vec<String>,
+    identifier: Option<String>,
+    publisher: Option<String>,
+    date: Option<String>,
+    series: Option<String>,
+    series_index: Option<f64>,
+    word_count: Option<usize>,
+    /// EPUB the entry was converted from
+    source: String,
+    /// Output directory, relative to the catalog when possible
+    output: String,
+}
+
+/// Write `catalog.json` and `catalog.csv` into `base`, one entry per converted book, given
+/// by its source's name and output directory, ordered by output path so reruns produce
+/// identical files. Returns the files written.
+pub fn write_catalog(books: &[(String, PathBuf)], base: &Path) -> Result<Vec<PathBuf>> {
+    let mut entries = Vec::with_capacity(books.len());
+    for (source, output_dir) in books {
+        let book = SavedBook::load(output_dir)?;
+        let relative = |p: &Path| p.strip_prefix(base).unwrap_or(p).to_string_lossy().into_owned();
+        entries.push(CatalogEntry {
+            title: book.title,
+            authors: book.creators,
+            language: book.language,
+            subjects: book.subjects,
+            tags: book.tags,
+            identifier: book.identifier,
+            publisher: book.publisher,
+            date: book.date,
+            series: book.series,
+            series_index: book.series_index,
+            word_count: book.word_count,
+            source: source.clone(),
+            output: relative(output_dir),
+        });
+    }
+
+    entries.sort_by(|a, b| a.output.cmp(&b.output));
+
+    let json_path = base.join("catalog.json");
+    let json = serde_json::to_string_pretty(&entries).context("Failed to serialize catalog")?;
+    output::write_atomic(&json_path, json).context("Failed to write catalog.json")?;
+
+    let csv_path = base.join("catalog.csv");
+    output::write_atomic(&csv_path, to_csv(&entries)).context("Failed to write catalog.csv")?;
+
+    Ok(vec![json_path, csv_path])
+}
+
+const CSV_COLUMNS: &[&str] = &[
+    "title", "authors", "language", "subjects", "tags", "identifier", "publisher", "date",
+    "series", "series_index", "word_count", "source", "output",
+];
+
+fn to_csv(entries: &[CatalogEntry]) -> String {
+    let mut csv = CSV_COLUMNS.join(",");
+    csv.push('\n');
+    for e in entries {
+        let opt = |v: &Option<String>| v.clone().unwrap_or_default();
+        let fields = [
+            opt(&e.title),
+            e.authors.join("; "),
+            opt(&e.language),
+            e.subjects.join("; "),
+            e.tags.join("; "),
+            opt(&e.identifier),
+            opt(&e.publisher),
+            opt(&e.date),
+            opt(&e.series),
+            e.series_index.map(|i| i.to_string()).unwrap_or_default(),
+            e.word_count.map(|w| w.to_string()).unwrap_or_default(),
+            e.source.clone(),
+            e.output.clone(),
+        ];
+        let row: Vec<String> = fields.iter().map(|f| csv_field(f)).collect();
+        csv.push_str(&row.join(","));
+        csv.push('\n');
+    }
+    csv
+}
+
+/// Quote a CSV field when it contains a separator, quote or line break (RFC 4180)
+pub fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
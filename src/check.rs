@@ -0,0 +1,84 @@
+use crate::provenance;
+use anyhow::{Context, Result};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use walkdir::WalkDir;
+
+static SCRATCH_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// A scratch directory under the system temp dir, removed when dropped
+pub struct ScratchDir {
+    path: PathBuf,
+}
+
+impl ScratchDir {
+    pub fn new(label: &str) -> Result<Self> {
+        let n = SCRATCH_COUNTER.fetch_add(1, Ordering::Relaxed);
+        let path = std::env::temp_dir().join(format!("epub-to-md-{}-{}-{}", label, std::process::id(), n));
+        fs::create_dir_all(&path).context("Failed to create scratch directory")?;
+        Ok(Self { path })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for ScratchDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+/// Compare two output trees and describe every difference, in path order
+pub fn diff_trees(existing: &Path, fresh: &Path) -> Result<Vec<String>> {
+    let existing_files = relative_files(existing);
+    let fresh_files = relative_files(fresh);
+    let mut drift = Vec::new();
+
+    for path in existing_files.union(&fresh_files) {
+        match (existing_files.contains(path), fresh_files.contains(path)) {
+            (true, false) => drift.push(format!("unexpected file: {}", path.display())),
+            (false, true) => drift.push(format!("missing file: {}", path.display())),
+            _ => {
+                let old = fs::read(existing.join(path))
+                    .with_context(|| format!("Failed to read {}", path.display()))?;
+                let new = fs::read(fresh.join(path))
+                    .with_context(|| format!("Failed to read {}", path.display()))?;
+                if old != new && !same_metadata(path, &old, &new) {
+                    drift.push(format!("content differs: {}", path.display()));
+                }
+            }
+        }
+    }
+
+    Ok(drift)
+}
+
+/// Whether two metadata.json files differ only in when and how the conversion ran
+fn same_metadata(path: &Path, old: &[u8], new: &[u8]) -> bool {
+    if path.file_name().is_none_or(|name| name != "metadata.json") {
+        return false;
+    }
+    let strip = |bytes: &[u8]| {
+        let mut value: serde_json::Value = serde_json::from_slice(bytes).ok()?;
+        if let Some(provenance) = value.get_mut("provenance").and_then(|p| p.as_object_mut()) {
+            for field in provenance::RUN_FIELDS {
+                provenance.remove(*field);
+            }
+        }
+        Some(value)
+    };
+    matches!((strip(old), strip(new)), (Some(a), Some(b)) if a == b)
+}
+
+fn relative_files(root: &Path) -> BTreeSet<PathBuf> {
+    WalkDir::new(root)
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_file())
+        .filter_map(|entry| entry.path().strip_prefix(root).ok().map(Path::to_path_buf))
+        .collect()
+}
@@ -0,0 +1,529 @@
+use crate::alttext::AltTextCommand;
+use crate::analysis::Analysis;
+use crate::ascii::AsciiOnly;
+use crate::callout::{self, CalloutStyle};
+use crate::completions::Shell;
+use crate::config;
+use crate::filter::MetadataFilter;
+use crate::layout::RtlMarkup;
+use crate::naming::Numbering;
+use crate::normalize::Normalization;
+use crate::organize::Layout;
+use crate::output::{Extension, Newline, OutputFormat};
+use crate::overlay::ReadAlong;
+use crate::overrides::MetadataOverride;
+use crate::parts::DirectoryLayout;
+use crate::quotes::QuotesFormat;
+use crate::progress::ProgressFormat;
+use crate::provenance::Settings;
+use crate::render::{Flavor, Highlight, Underline};
+use crate::diff::DiffFormat;
+use crate::frontmatter::FrontMatterFormat;
+use crate::license::LicenseHeader;
+use crate::lint::LintFormat;
+use crate::sections::Section;
+use crate::input;
+use crate::tags;
+use crate::toc::SlugStyle;
+use crate::units;
+use crate::vocab::VocabFormat;
+use clap::builder::FalseyValueParser;
+use clap::error::ErrorKind;
+use clap::{Args, CommandFactory, FromArgMatches, Parser, Subcommand};
+use std::ffi::OsString;
+use std::path::PathBuf;
+use std::time::Duration;
+
+#[derive(Parser)]
+#[command(name = "epub-to-md")]
+#[command(about = "Convert EPUB files to Markdown format", long_about = None)]
+#[command(after_help = "Running `epub-to-md <INPUT> [OPTIONS]` without a subcommand is the same as `epub-to-md convert`.")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Convert an EPUB file (or every EPUB in a directory) to Markdown
+    Convert(ConvertArgs),
+    /// Convert every EPUB found under a directory, in parallel
+    Batch(ConvertArgs),
+    /// Reconvert and compare against existing output, exiting non-zero on drift
+    Check(ConvertArgs),
+    /// Print a human-readable summary of an EPUB's structure
+    Inspect(InspectArgs),
+    /// Print an EPUB's metadata as JSON
+    Metadata(InspectArgs),
+    /// Convert two editions of a book and show what changed, chapter by chapter
+    Diff(DiffArgs),
+    /// Unpack an EPUB's manifest resources (images, CSS, fonts, and with --all everything) next to its Markdown
+    Extract(ExtractArgs),
+    /// Package a directory of converted Markdown back into an EPUB3
+    Build(BuildArgs),
+    /// Check converted Markdown for broken links, missing images, empty chapters and other defects
+    Lint(LintArgs),
+    /// Collect a book's blockquotes and epigraphs, and optionally its dialogue, with the chapter each is from
+    Quotes(QuotesArgs),
+    /// Stay running and take conversion jobs as JSON over a Unix socket
+    Daemon(DaemonArgs),
+    /// Print a shell completion script
+    Completions(CompletionsArgs),
+    /// Print a roff man page
+    Man,
+}
+
+impl Command {
+    const NAMES: &'static [&'static str] = &["convert", "batch", "check", "inspect", "metadata", "diff", "extract", "build", "lint", "quotes", "daemon", "completions", "man", "help"];
+}
+
+impl Cli {
+    /// Parse the command line, treating a bare `epub-to-md <INPUT> ...` as `convert`
+    pub fn parse_with_default() -> Self {
+        let mut args: Vec<OsString> = std::env::args_os().collect();
+        let first = args.get(1).and_then(|a| a.to_str()).unwrap_or("");
+        let top_level_flag = matches!(first, "-h" | "--help" | "-V" | "--version");
+        if args.len() > 1 && !top_level_flag && !Command::NAMES.contains(&first) {
+            args.insert(1, OsString::from("convert"));
+        }
+        let args = config::expand_profile(args).unwrap_or_else(|e| Self::command().error(ErrorKind::InvalidValue, format!("{:#}", e)).exit());
+        Self::try_parse_args(args).unwrap_or_else(|e| e.exit())
+    }
+
+    /// Parse `args`, recording the option values a conversion subcommand ran with
+    pub fn try_parse_args(args: Vec<OsString>) -> Result<Self, clap::Error> {
+        let command = Self::command();
+        let matches = command.clone().try_get_matches_from(args)?;
+        let mut cli = Self::from_arg_matches(&matches)?;
+        if let (Command::Convert(args) | Command::Batch(args) | Command::Check(args), Some((name, sub_matches))) = (&mut cli.command, matches.subcommand()) {
+            let subcommand = command.find_subcommand(name).expect("parsed subcommand exists");
+            args.settings = Settings::from_matches(subcommand, sub_matches);
+        }
+        Ok(cli)
+    }
+}
+
+#[derive(Args)]
+// Options from a --profile come first, and repeating one on the command line overrides them
+#[command(args_override_self = true)]
+pub struct ConvertArgs {
+    #[arg(required_unless_present_any = ["from_url_list", "opds", "files_from", "calibre_library"], help = "Path to an EPUB file, a directory containing EPUB files, or an http(s) URL")]
+    pub input: Option<PathBuf>,
+
+    #[arg(long, value_name = "FILE", conflicts_with = "input", help = "Download and convert every EPUB URL listed in FILE, one per line")]
+    pub from_url_list: Option<PathBuf>,
+
+    #[arg(long, value_name = "URL", conflicts_with_all = ["input", "from_url_list"], help = "Crawl an OPDS catalog feed and convert every EPUB it offers")]
+    pub opds: Option<String>,
+
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["input", "from_url_list", "opds"], help = "Convert every EPUB path listed in FILE (`-` for stdin), one per line or NUL-separated as from `find -print0`")]
+    pub files_from: Option<PathBuf>,
+
+    #[arg(long, value_name = "DIR", conflicts_with_all = ["input", "from_url_list", "opds", "files_from", "organize"], help = "Convert the EPUB format of every book in a Calibre library, read from its metadata.db: Calibre's title, authors, tags, series and custom columns replace the EPUB's, and the output mirrors the library's Author/Title folders")]
+    pub calibre_library: Option<PathBuf>,
+
+    #[arg(long, value_name = "FIELD=VALUE", value_parser = MetadataFilter::parse, help = "Only convert books whose metadata matches, e.g. language=en, subject~=history or author=Tolstoy (repeatable)")]
+    pub filter: Vec<MetadataFilter>,
+
+    #[arg(long, help = "Leave out retailer samples and previews (detected from the title, an \"end of sample\" final page, or a tiny spine), listing them separately in the summary")]
+    pub skip_samples: bool,
+
+    #[arg(long, value_name = "EXT", value_delimiter = ',', default_values = input::DEFAULT_EXTENSIONS, help = "File-name extensions EPUBs are recognized by, in any case; files without one are still taken when their zip container says it is an EPUB")]
+    pub extensions: Vec<String>,
+
+    #[command(flatten)]
+    pub output: OutputArgs,
+
+    #[command(flatten)]
+    pub run: RunArgs,
+
+    #[command(flatten)]
+    pub limits: LimitArgs,
+
+    /// Every option's value, recorded in each book's metadata.json
+    #[arg(skip)]
+    pub settings: Settings,
+}
+
+/// What gets written for each book
+#[derive(Args)]
+#[command(next_help_heading = "Output")]
+pub struct OutputArgs {
+    #[arg(long, value_name = "NAME", env = "EPUB_TO_MD_PROFILE", help = "Apply the options of [profile.NAME] from epub-to-md.toml or ~/.config/epub-to-md/config.toml; options given here override it")]
+    pub profile: Option<String>,
+
+    #[arg(short, long, env = "EPUB_TO_MD_OUTPUT", help = "Output directory for Markdown files, or an s3://, gs:// or azure:// URL to upload the output to")]
+    pub output: Option<PathBuf>,
+
+    #[arg(short, long, env = "EPUB_TO_MD_SINGLE", value_parser = FalseyValueParser::new(), help = "Create a single merged Markdown file instead of separate files")]
+    pub single: bool,
+
+    #[arg(long, value_enum, default_value_t = Extension::Md, env = "EPUB_TO_MD_EXTENSION", conflicts_with_all = ["logseq", "dendron"], help = "Extension of the Markdown files written: chapters, the single file and series files")]
+    pub extension: Extension,
+
+    #[arg(long, value_enum, default_value_t = Newline::Lf, env = "EPUB_TO_MD_NEWLINE", help = "Line endings of the Markdown and SSML files written, for tools that insist on Windows (crlf) line endings")]
+    pub newline: Newline,
+
+    #[arg(long, value_name = "SIZE", value_parser = units::parse_size, requires = "single", help = "Split the single file into numbered volumes (<title> - Volume 01.md) of at most this size, at chapter boundaries, e.g. 1M")]
+    pub max_file_size: Option<u64>,
+
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(usize), requires = "single", help = "Split the single file into numbered volumes of at most N words, at chapter boundaries")]
+    pub max_words: Option<usize>,
+
+    #[arg(long, value_name = "TYPE", help = "Only use spine items of this media type, or type/*, falling back past others (repeatable)")]
+    pub allow_mime: Vec<String>,
+
+    #[arg(long, value_name = "TYPE", help = "Leave out spine items of this media type, or type/*, using their fallbacks instead, e.g. image/* (repeatable)")]
+    pub deny_mime: Vec<String>,
+
+    #[arg(long, value_name = "TYPE", help = "Copy spine items of this media type, or type/*, into attachments/ with a chapter linking to each, instead of converting their fallbacks, e.g. application/pdf (repeatable)")]
+    pub extract_mime: Vec<String>,
+
+    #[arg(long, value_enum, default_value_t = DirectoryLayout::Flat, conflicts_with_all = ["single", "logseq", "dendron"], help = "How chapter files are arranged: all in the output directory, or in a subdirectory per part of the table of contents (01-part-one/chapter_003.md)")]
+    pub layout: DirectoryLayout,
+
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..), help = "Keep at most N levels of nesting in the table of contents (metadata.json and the combined and series files' contents); deeper entries are listed at level N")]
+    pub toc_depth: Option<u32>,
+
+    #[arg(long, value_enum, default_value_t = OutputFormat::Markdown, env = "EPUB_TO_MD_FORMAT", help = "Write Markdown, a Joplin export archive (<title>.jex) with a note per chapter, images as resources and subjects as tags, or an SSML document per chapter for speech synthesis")]
+    pub format: OutputFormat,
+
+    #[arg(long, conflicts_with_all = ["single", "merge_series", "logseq", "notion"], help = "Write hierarchical notes for a Dendron or org-roam vault: book.<title>.ch001.<section>.md, each with id and title front matter")]
+    pub dendron: bool,
+
+    #[arg(long, conflicts_with_all = ["single", "logseq"], help = "Keep to the Markdown Notion's importer understands (headings down to level 3, flat quotes, no inline HTML, <aside> callouts) and write notion.csv listing the books for import as a database")]
+    pub notion: bool,
+
+    #[arg(long, conflicts_with_all = ["single", "merge_series"], help = "Write a Logseq graph: chapter pages named Book/Chapter 001 under pages/, one block per paragraph, and a book page with the metadata as properties")]
+    pub logseq: bool,
+
+    #[arg(long, value_enum, default_value_t = Numbering::Sequential, env = "EPUB_TO_MD_NUMBERING", help = "Number chapter files consecutively, or by their position in the spine")]
+    pub numbering: Numbering,
+
+    #[arg(long, value_name = "N", default_value_t = 1, help = "Number of the first chapter file, e.g. to continue from a previous volume")]
+    pub number_start: usize,
+
+    #[arg(long, value_name = "DIGITS", help = "Zero-pad chapter numbers to this many digits (default: fits the book, at least 3)")]
+    pub number_width: Option<usize>,
+
+    #[arg(long, value_enum, value_name = "STYLE", help = "Write notes, tips and warnings as callouts: > [!warning] (obsidian), !!! warning (mkdocs), ::: warning (fenced) or <aside> (notion)")]
+    pub callouts: Option<CalloutStyle>,
+
+    #[arg(long, value_name = "CLASS=TYPE", value_parser = callout::parse_mapping, requires = "callouts", help = "Map a class name or epub:type to a callout type, e.g. sidebar=info (repeatable)")]
+    pub callout_class: Vec<(String, String)>,
+
+    #[arg(long, help = "Turn print page breaks into anchors, so index entries can link to their pages")]
+    pub page_markers: bool,
+
+    #[arg(long, value_enum, value_name = "SECTION", help = "Leave a section out of the output (repeatable)")]
+    pub skip: Vec<Section>,
+
+    #[arg(long, value_name = "FILE", help = "Load publisher cleanup rules from FILE, or every .toml file in it if it is a directory (repeatable)")]
+    pub rules: Vec<PathBuf>,
+
+    #[arg(long, help = "Don't load the rules in ~/.config/epub-to-md/rules/")]
+    pub no_default_rules: bool,
+
+    #[arg(long, help = "Convert table-of-contents pages (the navigation document, or the page the guide lists as the TOC) instead of skipping them")]
+    pub keep_toc_page: bool,
+
+    #[arg(long, help = "Convert spine documents whose Markdown is identical to an earlier chapter's instead of skipping them")]
+    pub keep_duplicates: bool,
+
+    #[arg(long, help = "Don't promote paragraphs styled as headings (a large font size or a class like chapterhead) to Markdown headings in chapters that have no real ones")]
+    pub no_heading_detection: bool,
+
+    #[arg(long, value_name = "RENDITION", help = "Which rendition of a multi-rendition EPUB to convert: its position (1, 2, ...), package path, label, language, reflowable or fixed [default: the first reflowable one]")]
+    pub rendition: Option<String>,
+
+    #[arg(long, help = "Remove running headers, footers and standalone page numbers, listing what was removed in artifacts.json")]
+    pub clean_artifacts: bool,
+
+    #[arg(long, overrides_with = "no_tidy", help = "Tidy the Markdown after rendering: collapse runs of blank lines and spaces, trim trailing whitespace and remove empty emphasis and list items left by hollow elements (the default)")]
+    pub tidy: bool,
+
+    #[arg(long, overrides_with = "tidy", help = "Write the Markdown exactly as rendered, without tidying it")]
+    pub no_tidy: bool,
+
+    #[arg(long, help = "Join words hyphenated across the line breaks of a print layout (\"con- tinued\"), keeping the hyphen of compounds the book uses, and paragraphs split mid-sentence by page breaks")]
+    pub dehyphenate: bool,
+
+    #[arg(long, value_enum, default_value_t = Flavor::Gfm, env = "EPUB_TO_MD_FLAVOR", help = "Markdown dialect to write; decides the syntax for superscript, subscript and other extensions")]
+    pub flavor: Flavor,
+
+    #[arg(long, value_enum, default_value_t = Underline::Auto, help = "How to write underlined text: as the flavor prefers, inline HTML, emphasis, or plain text")]
+    pub underline: Underline,
+
+    #[arg(long, value_enum, default_value_t = Highlight::Auto, help = "How to write highlighted text: as the flavor prefers, inline HTML, ==text==, or plain text")]
+    pub highlight: Highlight,
+
+    #[arg(long, value_name = "MARKER", num_args = 0..=1, default_missing_value = "***", help = "Replace scene breaks (rules, `* * *`, ornament images, empty headings) with one marker [default marker: ***]")]
+    pub scene_break: Option<String>,
+
+    #[arg(long, value_enum, value_name = "STYLE", num_args = 0..=1, default_missing_value = "comment", help = "Start every output file with the book's rights statement and detected license, as an HTML comment or YAML front matter [default style: comment]")]
+    pub license_header: Option<LicenseHeader>,
+
+    #[arg(long, value_enum, default_value_t = FrontMatterFormat::Yaml, help = "Syntax of the front matter --license-header front-matter writes: YAML between --- lines, TOML between +++ lines, or a JSON object")]
+    pub frontmatter_format: FrontMatterFormat,
+
+    #[arg(long, value_name = "SUBJECT=TAG", value_parser = tags::parse_mapping, help = "Rename a tag made from the book's subjects, or drop it with an empty TAG, e.g. science-fiction=sf (repeatable)")]
+    pub tag_map: Vec<(String, String)>,
+
+    #[arg(long, help = "Record when each book was converted (UTC) in the provenance block of metadata.json; off by default, since the time would make every rerun's output differ")]
+    pub record_time: bool,
+
+    #[arg(long = "set", value_name = "FIELD=VALUE", value_parser = MetadataOverride::parse, help = "Correct the book's metadata in metadata.json, front matter and headers: FIELD=VALUE replaces it (repeat for several authors or subjects), FIELD+=VALUE adds an author, contributor or subject, and an empty VALUE clears it (repeatable)")]
+    pub set: Vec<MetadataOverride>,
+
+    #[arg(long, value_enum, default_value_t = SlugStyle::Github, env = "EPUB_TO_MD_SLUG_STYLE", help = "How heading anchors in the contents of combined files are spelled, to match the viewer: GitHub's, GitLab's, Obsidian's (the heading text itself) or custom (lowercase words joined by --slug-separator)")]
+    pub slug_style: SlugStyle,
+
+    #[arg(long, value_name = "SEP", default_value = "-", help = "Separator between the words of --slug-style custom anchors")]
+    pub slug_separator: String,
+
+    #[arg(long, help = "Start each chapter with its table-of-contents label as a heading, unless it already has one")]
+    pub inject_title: bool,
+
+    #[arg(long, value_enum, default_value_t = RtlMarkup::None, help = "For right-to-left books, wrap chapters in dir=\"rtl\" markup or add directional marks")]
+    pub rtl_markup: RtlMarkup,
+
+    #[arg(long, value_enum, default_value_t = Normalization::None, env = "EPUB_TO_MD_NORMALIZE", help = "Unicode-normalize chapter text, metadata and derived file names")]
+    pub normalize: Normalization,
+
+    #[arg(
+        long,
+        value_enum,
+        value_name = "SCOPE",
+        num_args = 0..=1,
+        default_missing_value = "punctuation",
+        overrides_with = "keep_unicode",
+        env = "EPUB_TO_MD_ASCII_ONLY",
+        help = "Rewrite curly quotes, dashes, symbols and emoji as ASCII; 'all' also drops accents and romanizes Greek and Cyrillic"
+    )]
+    pub ascii_only: Option<AsciiOnly>,
+
+    #[arg(long, overrides_with = "ascii_only", help = "Keep non-ASCII text as it is (the default), overriding --ascii-only from a profile or the environment")]
+    pub keep_unicode: bool,
+
+    #[arg(long, help = "Put each sentence of a paragraph on its own line")]
+    pub sentence_per_line: bool,
+
+    #[arg(long, help = "Start each chapter with an HTML comment naming the spine document it came from, e.g. <!-- source: OEBPS/ch12.xhtml spine:14 -->, in combined files too")]
+    pub source_comments: bool,
+
+    #[arg(long, conflicts_with = "single", help = "In batch runs, also write one combined Markdown file per book series, in reading order")]
+    pub merge_series: bool,
+
+    #[arg(long, help = "In batch runs, also write catalog.json and catalog.csv listing every converted book")]
+    pub catalog: bool,
+
+    #[arg(long, value_name = "LAYOUT", value_parser = Layout::parse, help = "Write each book under directories named from its metadata, e.g. author/title for `Jane Doe/Book Title/`; books by the same author share one directory (fields: author, author-sort, title, series, language, publisher, year)")]
+    pub organize: Option<Layout>,
+
+    #[arg(long, help = "Keep the output root in a git repository, creating it if needed, with one commit per converted book")]
+    pub git: bool,
+
+    #[arg(long, help = "In batch runs, also write summary.csv with each book's conversion time, input and output sizes, chapter and image counts")]
+    pub summary_csv: bool,
+
+    #[arg(long, help = "Verify external links found in the book and record the results in links.json")]
+    pub check_links: bool,
+
+    #[arg(long, help = "Write the entries of bibliography and reference sections to references.json")]
+    pub references: bool,
+
+    #[arg(long, value_enum, value_name = "ANALYSIS", help = "Analyze the converted text and write the results to analysis.json (repeatable); keywords lists word frequencies, keywords and named-entity candidates")]
+    pub analyze: Vec<Analysis>,
+
+    #[arg(long, value_enum, value_name = "FORMAT", help = "Write a frequency-ranked vocabulary list, vocabulary.csv or vocabulary.json, with each lemma's count, forms and per-chapter counts")]
+    pub vocab: Option<VocabFormat>,
+
+    #[arg(long, value_name = "DECK", help = "Export the terms and definitions of the book's glossaries as Anki flashcards to this file in its output directory: an .apkg deck, or .tsv/.txt for Anki's text import")]
+    pub anki: Option<String>,
+
+    #[arg(long, help = "Copy the book's stylesheets and embedded fonts into styles/ and list them in metadata.json")]
+    pub extract_styles: bool,
+
+    #[arg(long, help = "Copy the audio of the book's media overlays (read-along narration) into audio/ and list it in metadata.json")]
+    pub extract_audio: bool,
+
+    #[arg(long, help = "Write the images the chapters show to images/, named by a hash of their contents, point the chapters at them and map each image's EPUB path to its file in metadata.json")]
+    pub extract_images: bool,
+
+    #[arg(long, value_name = "COMMAND", value_parser = AltTextCommand::parse, help = "Describe images that have no alt text with this command, e.g. \"describe-image {file}\": {file} is replaced by the image's path (or appended), and its output becomes the alt text")]
+    pub alt_text_cmd: Option<AltTextCommand>,
+
+    #[arg(long, value_enum, value_name = "WHERE", help = "Write the media overlays' read-along timing of each paragraph as HTML comments in the chapters, or to overlays.json")]
+    pub read_along: Option<ReadAlong>,
+}
+
+/// How a run is carried out and reported
+#[derive(Args, Default)]
+#[command(next_help_heading = "Processing")]
+pub struct RunArgs {
+    #[arg(long, hide = true, help = "Same as the `check` subcommand")]
+    pub check: bool,
+
+    #[arg(long, help = "Print per-book timing and size statistics, and write sizes and per-chapter readability scores (Flesch-Kincaid, SMOG, average sentence length) to stats.json")]
+    pub stats: bool,
+
+    #[arg(short, long, help = "List each book's warnings (missing images, undecodable entities, skipped documents) instead of only counting them")]
+    pub verbose: bool,
+
+    #[arg(long, value_enum, default_value_t = ProgressFormat::Text, env = "EPUB_TO_MD_PROGRESS", help = "Progress output: human-readable text or newline-delimited JSON events")]
+    pub progress: ProgressFormat,
+
+    #[arg(long, conflicts_with = "progress", help = "Print nothing but the paths of the files written, one per line, for piping into xargs or rsync")]
+    pub print_paths: bool,
+
+    #[arg(short = '0', long = "null", requires = "print_paths", help = "With --print-paths, end each path with a NUL byte instead of a newline")]
+    pub null: bool,
+
+    #[arg(long, env = "EPUB_TO_MD_CACHE", value_parser = FalseyValueParser::new(), help = "Reuse previous conversions of unchanged books from the on-disk cache")]
+    pub cache: bool,
+
+    #[arg(long, value_name = "DIR", env = "EPUB_TO_MD_CACHE_DIR", help = "Cache location (default: ~/.cache/epub-to-md)")]
+    pub cache_dir: Option<PathBuf>,
+
+    #[arg(long, value_name = "DURATION", value_parser = units::parse_duration, env = "EPUB_TO_MD_TIMEOUT", help = "Give up on a book after this long, e.g. 90s or 5m")]
+    pub timeout: Option<Duration>,
+
+    #[arg(long, value_name = "N", default_value_t = 0, env = "EPUB_TO_MD_RETRIES", help = "In directory runs, retry a book up to N times after a transient I/O error, e.g. on a flaky network share")]
+    pub retries: usize,
+
+    #[arg(long, value_name = "DURATION", value_parser = units::parse_duration, default_value = "2s", env = "EPUB_TO_MD_RETRY_DELAY", help = "Wait before the first retry, doubling for each retry after it")]
+    pub retry_delay: Duration,
+
+    #[arg(short, long, value_name = "N", env = "EPUB_TO_MD_JOBS", value_parser = clap::value_parser!(u32).range(1..), help = "Convert with at most N threads (default: one per CPU)")]
+    pub jobs: Option<u32>,
+
+    #[arg(long, help = "Choose chapters, toggle single-file output and preview chapters in a terminal UI before converting a single EPUB")]
+    pub interactive: bool,
+}
+
+/// Safety limits for untrusted input
+#[derive(Args)]
+#[command(next_help_heading = "Limits")]
+pub struct LimitArgs {
+    #[arg(long, value_name = "SIZE", value_parser = units::parse_size, env = "EPUB_TO_MD_MAX_RESOURCE_SIZE", help = "Reject EPUBs containing any entry larger than this uncompressed, e.g. 64M")]
+    pub max_resource_size: Option<u64>,
+
+    #[arg(long, value_name = "SIZE", value_parser = units::parse_size, env = "EPUB_TO_MD_MAX_TOTAL_SIZE", help = "Reject EPUBs whose total uncompressed size exceeds this, e.g. 1G")]
+    pub max_total_size: Option<u64>,
+
+    #[arg(long, value_name = "N", env = "EPUB_TO_MD_MAX_CHAPTERS", help = "Reject EPUBs with more spine items than this")]
+    pub max_chapters: Option<usize>,
+
+    #[arg(long, value_name = "SIZE", value_parser = units::parse_size, env = "EPUB_TO_MD_MAX_MEMORY", help = "Skip any book whose conversion would need more memory than this, e.g. 512M")]
+    pub max_memory: Option<u64>,
+}
+
+#[derive(Args)]
+pub struct InspectArgs {
+    #[arg(help = "Path to an EPUB file")]
+    pub input: PathBuf,
+}
+
+#[derive(Args)]
+#[command(args_override_self = true)]
+pub struct DiffArgs {
+    #[arg(help = "The earlier edition")]
+    pub old: PathBuf,
+
+    #[arg(help = "The later edition")]
+    pub new: PathBuf,
+
+    #[arg(id = "diff_format", long = "diff-format", value_enum, default_value_t = DiffFormat::Unified, help = "Print a unified diff of the Markdown, or a JSON summary of the changed chapters")]
+    pub format: DiffFormat,
+
+    #[arg(short = 'U', long, value_name = "LINES", default_value_t = 3, help = "Unchanged lines shown around each change")]
+    pub context: usize,
+
+    #[command(flatten)]
+    pub output: OutputArgs,
+
+    #[command(flatten)]
+    pub limits: LimitArgs,
+}
+
+#[derive(Args)]
+pub struct ExtractArgs {
+    #[arg(help = "Path to an EPUB file")]
+    pub input: PathBuf,
+
+    #[arg(short, long, help = "Output directory of the book's Markdown (default: <name>_markdown); resources go in its resources/ directory")]
+    pub output: Option<PathBuf>,
+
+    #[arg(long, help = "Also unpack the XHTML documents and the package document, i.e. every file the OPF declares")]
+    pub all: bool,
+
+    #[command(flatten)]
+    pub limits: LimitArgs,
+}
+
+#[derive(Args)]
+pub struct BuildArgs {
+    #[arg(help = "Directory of Markdown chapters and the metadata.json that convert wrote with them")]
+    pub input: PathBuf,
+
+    #[arg(short, long, help = "EPUB file to write (default: <name>_rebuilt.epub beside a <name>_markdown directory)")]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Args)]
+pub struct LintArgs {
+    #[arg(help = "A book's output directory, or a batch output directory of many books")]
+    pub input: PathBuf,
+
+    #[arg(long, value_enum, default_value_t = LintFormat::Text, help = "Print one line per issue, or a JSON report with totals")]
+    pub format: LintFormat,
+
+    #[arg(long, value_enum, default_value_t = SlugStyle::Github, env = "EPUB_TO_MD_SLUG_STYLE", help = "Heading anchor style the output was written with, which links to headings are checked against")]
+    pub slug_style: SlugStyle,
+
+    #[arg(long, value_name = "SEP", default_value = "-", help = "Separator between the words of --slug-style custom anchors")]
+    pub slug_separator: String,
+}
+
+#[derive(Args)]
+pub struct QuotesArgs {
+    #[arg(help = "Path to an EPUB file")]
+    pub input: PathBuf,
+
+    #[arg(short, long, help = "File to write the quotes to, or - for stdout (default: <name>_quotes.md, or .json with --format json)")]
+    pub output: Option<PathBuf>,
+
+    #[arg(long, value_enum, default_value_t = QuotesFormat::Markdown, help = "Write a Markdown collection grouped by chapter, or JSON")]
+    pub format: QuotesFormat,
+
+    #[arg(long, value_name = "N", default_value_t = 0, help = "Leave out quotes shorter than N words")]
+    pub min_words: usize,
+
+    #[arg(long, help = "Also collect the quoted speech in every paragraph")]
+    pub dialogue: bool,
+}
+
+#[derive(Args)]
+pub struct DaemonArgs {
+    #[arg(long, value_name = "PATH", help = "Unix socket to listen on; a stale one left by an earlier daemon is replaced")]
+    pub socket: PathBuf,
+
+    #[arg(long, value_name = "N", default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..), help = "Jobs run at once; later ones wait their turn (each job converts its books in parallel already)")]
+    pub jobs: u32,
+}
+
+#[derive(Args)]
+pub struct CompletionsArgs {
+    #[arg(value_enum, help = "Shell to generate completions for")]
+    pub shell: Shell,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn command_is_well_formed() {
+        Cli::command().debug_assert();
+    }
+}
@@ -0,0 +1,300 @@
+use crate::output::{AtomicFile, Newline};
+use crate::toc::{self, SlugRules, SlugSet};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Separator written after every chapter in the combined file
+const CHAPTER_SEPARATOR: &str = "\n\n---\n\n";
+
+/// A heading listed in the combined file's table of contents
+struct TocLine {
+    level: usize,
+    text: String,
+    slug: String,
+}
+
+/// Writer for `--single` and merged series output. Chapters stream into a hidden body
+/// file while their headings are collected; `commit` then writes the title block and an
+/// anchored table of contents, followed by the body, so memory stays bounded to one chapter.
+pub struct CombinedFile {
+    target: PathBuf,
+    /// Levels chapter headings are demoted by
+    depth: usize,
+    title: String,
+    header: String,
+    body_path: PathBuf,
+    body: Option<BufWriter<File>>,
+    slugs: SlugSet,
+    toc: Vec<TocLine>,
+    /// Levels of nesting kept in the table of contents; deeper entries are listed at the last
+    toc_depth: Option<usize>,
+    newline: Newline,
+    /// Bytes and words written to the body so far
+    body_bytes: u64,
+    body_words: usize,
+}
+
+impl CombinedFile {
+    pub fn create(path: &Path, title: &str, author: &str) -> io::Result<Self> {
+        Self::with_depth(path, title, author, 1)
+    }
+
+    /// A combined file whose chapters sit `depth` levels below the title, leaving room
+    /// for section headings (such as volumes) added with `add_heading`
+    pub fn with_depth(path: &Path, title: &str, author: &str, depth: usize) -> io::Result<Self> {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let body_path = path.with_file_name(format!(".{}.body-{}", name, std::process::id()));
+        let body = BufWriter::new(File::create(&body_path)?);
+
+        // Anchors are numbered in document order, so claim the title and contents headings first
+        let mut slugs = SlugSet::default();
+        slugs.claim(title);
+        slugs.claim("Contents");
+
+        Ok(Self {
+            target: path.to_path_buf(),
+            depth,
+            title: title.to_string(),
+            header: format!("# {}\n\n**Author:** {}\n\n---\n\n", title, author),
+            body_path,
+            body: Some(body),
+            slugs,
+            toc: Vec::new(),
+            toc_depth: None,
+            newline: Newline::Lf,
+            body_bytes: 0,
+            body_words: 0,
+        })
+    }
+
+    /// Text to put before the title block, such as a license header
+    pub fn set_preamble(&mut self, text: &str) {
+        self.header.insert_str(0, text);
+    }
+
+    /// Flatten the table of contents below `depth` levels, as `--toc-depth` asks
+    pub fn set_toc_depth(&mut self, depth: Option<usize>) {
+        self.toc_depth = depth;
+    }
+
+    /// Line endings to write the file with
+    pub fn set_newline(&mut self, newline: Newline) {
+        self.newline = newline;
+    }
+
+    /// How the contents' heading anchors are spelled; set before anything is added
+    pub fn set_slug_rules(&mut self, rules: &SlugRules) {
+        self.slugs = SlugSet::with_rules(rules.clone());
+        self.slugs.claim(&self.title);
+        self.slugs.claim("Contents");
+    }
+
+    /// Append a section heading at `level` and list it in the table of contents
+    pub fn add_heading(&mut self, level: usize, text: &str) -> io::Result<()> {
+        let slug = self.slugs.claim(text);
+        let slug = self.slugs.fragment(&slug);
+        self.toc.push(TocLine { level, text: text.to_string(), slug });
+        let line = format!("{} {}\n\n", "#".repeat(level), text);
+        self.body_words += count_words(text);
+        self.write_body(&line)
+    }
+
+    /// Append a chapter, demoting its headings beneath the title (and any section headings)
+    pub fn add_chapter(&mut self, markdown: &str) -> io::Result<()> {
+        let shifted = toc::shift_headings(markdown, self.depth);
+
+        let headings = toc::headings(&shifted);
+        let top = headings.iter().map(|h| h.0).min();
+        for (level, text) in headings {
+            let slug = self.slugs.claim(&text);
+            if Some(level) == top {
+                let slug = self.slugs.fragment(&slug);
+                self.toc.push(TocLine { level, text, slug });
+            }
+        }
+
+        self.body_words += count_words(markdown);
+        self.write_body(&shifted)?;
+        self.write_body(CHAPTER_SEPARATOR)
+    }
+
+    fn write_body(&mut self, text: &str) -> io::Result<()> {
+        let text = self.newline.apply(text);
+        self.body_bytes += text.len() as u64;
+        self.body.as_mut().expect("write after commit").write_all(text.as_bytes())
+    }
+
+    /// Size the file would have if committed now: the title block, the contents and the body
+    fn len(&self) -> u64 {
+        let head = self.header.len() + self.toc.iter().map(|line| line.level * 2 + line.text.len() + line.slug.len() + 8).sum::<usize>() + 16;
+        let endings = if self.newline == Newline::Crlf { self.header.lines().count() + self.toc.len() + 6 } else { 0 };
+        (head + endings) as u64 + self.body_bytes
+    }
+
+    fn is_empty(&self) -> bool {
+        self.body_bytes == 0
+    }
+
+    /// Assemble the final file and move it into place
+    pub fn commit(mut self) -> io::Result<()> {
+        if let Some(mut body) = self.body.take() {
+            body.flush()?;
+        }
+
+        let mut head = self.header.clone();
+        if !self.toc.is_empty() {
+            let base = self.toc.iter().map(|t| t.level).min().unwrap_or(2);
+            head.push_str("## Contents\n\n");
+            let max_indent = self.toc_depth.map_or(usize::MAX, |depth| depth.saturating_sub(1));
+            for line in &self.toc {
+                head.push_str(&format!("{}- [{}](#{})\n", "  ".repeat((line.level - base).min(max_indent)), line.text, line.slug));
+            }
+            head.push_str("\n---\n\n");
+        }
+        let mut out = AtomicFile::create(&self.target)?;
+        out.write_all(self.newline.apply(&head).as_bytes())?;
+        io::copy(&mut File::open(&self.body_path)?, &mut out)?;
+        out.commit()
+    }
+}
+
+impl Drop for CombinedFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.body_path);
+    }
+}
+
+/// Largest volume `--max-file-size` and `--max-words` allow for combined output
+#[derive(Debug, Clone, Copy, Default)]
+pub struct VolumeLimit {
+    pub max_bytes: Option<u64>,
+    pub max_words: Option<usize>,
+}
+
+impl VolumeLimit {
+    pub fn is_set(&self) -> bool {
+        self.max_bytes.is_some() || self.max_words.is_some()
+    }
+
+    /// Whether a chapter of `bytes` and `words` would take the volume past either limit
+    fn exceeded_by(&self, volume: &CombinedFile, bytes: u64, words: usize) -> bool {
+        self.max_bytes.is_some_and(|max| volume.len() + bytes > max) || self.max_words.is_some_and(|max| volume.body_words + words > max)
+    }
+}
+
+/// `--single` output, split at chapter boundaries into numbered volumes
+/// (`<title> - Volume 01.md`, each with its own title block and contents) when a
+/// `VolumeLimit` is set. A chapter too large for any volume gets one to itself.
+pub struct CombinedVolumes {
+    dir: PathBuf,
+    /// File name the output would have unsplit, that volumes are numbered from
+    file_name: String,
+    title: String,
+    author: String,
+    preamble: Option<String>,
+    toc_depth: Option<usize>,
+    newline: Newline,
+    slug_rules: SlugRules,
+    limit: VolumeLimit,
+    current: CombinedFile,
+    /// File name of the volume being written
+    current_name: String,
+    number: usize,
+    written: Vec<PathBuf>,
+}
+
+impl CombinedVolumes {
+    /// Combined output for a book in `dir`, written to `file_name` unless it is split
+    pub fn create(dir: &Path, file_name: &str, title: &str, author: &str, limit: VolumeLimit) -> io::Result<Self> {
+        let current_name = if limit.is_set() { volume_name(file_name, 1) } else { file_name.to_string() };
+        let current = CombinedFile::create(&dir.join(&current_name), &volume_title(title, 1, limit), author)?;
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            file_name: file_name.to_string(),
+            title: title.to_string(),
+            author: author.to_string(),
+            preamble: None,
+            toc_depth: None,
+            newline: Newline::Lf,
+            slug_rules: SlugRules::default(),
+            limit,
+            current,
+            current_name,
+            number: 1,
+            written: Vec::new(),
+        })
+    }
+
+    /// Text to put before each volume's title block, such as a license header
+    pub fn set_preamble(&mut self, text: &str) {
+        self.preamble = Some(text.to_string());
+        self.current.set_preamble(text);
+    }
+
+    /// Flatten each volume's table of contents below `depth` levels
+    pub fn set_toc_depth(&mut self, depth: Option<usize>) {
+        self.toc_depth = depth;
+        self.current.set_toc_depth(depth);
+    }
+
+    /// Line endings to write each volume with
+    pub fn set_newline(&mut self, newline: Newline) {
+        self.newline = newline;
+        self.current.set_newline(newline);
+    }
+
+    /// How each volume's heading anchors are spelled
+    pub fn set_slug_rules(&mut self, rules: &SlugRules) {
+        self.slug_rules = rules.clone();
+        self.current.set_slug_rules(rules);
+    }
+
+    /// Append a chapter, first starting a new volume if it would not fit in this one.
+    /// Returns the file name of the volume it went into.
+    pub fn add_chapter(&mut self, markdown: &str) -> io::Result<&str> {
+        let bytes = (self.newline.apply(markdown).len() + CHAPTER_SEPARATOR.len()) as u64;
+        if !self.current.is_empty() && self.limit.exceeded_by(&self.current, bytes, count_words(markdown)) {
+            self.number += 1;
+            let name = volume_name(&self.file_name, self.number);
+            let mut next = CombinedFile::create(&self.dir.join(&name), &volume_title(&self.title, self.number, self.limit), &self.author)?;
+            if let Some(preamble) = &self.preamble {
+                next.set_preamble(preamble);
+            }
+            next.set_toc_depth(self.toc_depth);
+            next.set_newline(self.newline);
+            next.set_slug_rules(&self.slug_rules);
+            let finished = std::mem::replace(&mut self.current, next);
+            self.written.push(finished.target.clone());
+            finished.commit()?;
+            self.current_name = name;
+        }
+        self.current.add_chapter(markdown)?;
+        Ok(&self.current_name)
+    }
+
+    /// Write the last volume and return the paths of all of them
+    pub fn commit(mut self) -> io::Result<Vec<PathBuf>> {
+        self.written.push(self.current.target.clone());
+        self.current.commit()?;
+        Ok(self.written)
+    }
+}
+
+/// `Title.md` as its `number`th volume, `Title - Volume 02.md`
+fn volume_name(file_name: &str, number: usize) -> String {
+    let (stem, extension) = file_name.rsplit_once('.').unwrap_or((file_name, "md"));
+    format!("{} - Volume {:02}.{}", stem.trim_end(), number, extension)
+}
+
+fn volume_title(title: &str, number: usize, limit: VolumeLimit) -> String {
+    if limit.is_set() {
+        format!("{} (Volume {})", title.trim_end(), number)
+    } else {
+        title.to_string()
+    }
+}
+
+fn count_words(markdown: &str) -> usize {
+    markdown.split_whitespace().filter(|w| w.chars().any(char::is_alphanumeric)).count()
+}
@@ -0,0 +1,448 @@
+use clap::{Arg, Command, ValueEnum, ValueHint};
+use std::fmt::Write;
+
+/// Shells we can generate completion scripts for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+    Powershell,
+}
+
+/// Completion script for `shell`, derived from the clap command definition
+pub fn generate(shell: Shell, cmd: &Command) -> String {
+    let name = cmd.get_name();
+    match shell {
+        Shell::Bash => bash(name, cmd),
+        Shell::Zsh => zsh(name, cmd),
+        Shell::Fish => fish(name, cmd),
+        Shell::Powershell => powershell(name, cmd),
+    }
+}
+
+/// Options of a (sub)command that users can type, skipping hidden and positional ones
+fn options(cmd: &Command) -> impl Iterator<Item = &Arg> {
+    cmd.get_arguments()
+        .filter(|a| !a.is_positional() && !a.is_hide_set())
+}
+
+fn subcommands(cmd: &Command) -> impl Iterator<Item = &Command> {
+    cmd.get_subcommands().filter(|c| !c.is_hide_set())
+}
+
+fn flags_of(arg: &Arg) -> Vec<String> {
+    let mut flags = Vec::new();
+    if let Some(short) = arg.get_short() {
+        flags.push(format!("-{}", short));
+    }
+    if let Some(long) = arg.get_long() {
+        flags.push(format!("--{}", long));
+    }
+    flags
+}
+
+fn takes_value(arg: &Arg) -> bool {
+    arg.get_action().takes_values()
+}
+
+fn possible_values(arg: &Arg) -> Vec<String> {
+    arg.get_possible_values()
+        .iter()
+        .filter(|v| !v.is_hide_set())
+        .map(|v| v.get_name().to_string())
+        .collect()
+}
+
+fn help_of(arg: &Arg) -> String {
+    arg.get_help().map(|h| h.to_string()).unwrap_or_default()
+}
+
+fn about_of(cmd: &Command) -> String {
+    cmd.get_about().map(|h| h.to_string()).unwrap_or_default()
+}
+
+fn function_name(name: &str) -> String {
+    name.replace('-', "_")
+}
+
+/// Subcommands with the pattern each is matched by in a shell `case`: its own name, and for
+/// `convert` the catch-all too, since a bare `epub-to-md <INPUT>` takes its options
+fn branches(cmd: &Command) -> Vec<(String, &Command)> {
+    let mut branches: Vec<(String, &Command)> = subcommands(cmd).map(|sub| (sub.get_name().to_string(), sub)).collect();
+    if let Some(convert) = cmd.find_subcommand("convert") {
+        branches.push(("*".to_string(), convert));
+    }
+    branches
+}
+
+/// The bash word list completing an option's value
+fn bash_values(arg: &Arg) -> String {
+    let values = possible_values(arg);
+    if !values.is_empty() {
+        format!("compgen -W \"{}\" -- \"$cur\"", values.join(" "))
+    } else if arg.get_value_hint() == ValueHint::DirPath {
+        "compgen -d -- \"$cur\"".to_string()
+    } else {
+        "compgen -f -- \"$cur\"".to_string()
+    }
+}
+
+fn bash(name: &str, cmd: &Command) -> String {
+    let func = function_name(name);
+    let names: Vec<&str> = subcommands(cmd).map(|c| c.get_name()).collect();
+    let mut out = String::new();
+
+    let _ = writeln!(out, "_{}() {{", func);
+    let _ = writeln!(out, "    local cur prev sub");
+    let _ = writeln!(out, "    cur=\"${{COMP_WORDS[COMP_CWORD]}}\"");
+    let _ = writeln!(out, "    prev=\"${{COMP_WORDS[COMP_CWORD-1]}}\"");
+    let _ = writeln!(out, "    sub=\"${{COMP_WORDS[1]}}\"");
+    let _ = writeln!(out);
+    let _ = writeln!(out, "    if [[ $COMP_CWORD -eq 1 && $cur != -* ]]; then");
+    let _ = writeln!(out, "        COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))", names.join(" "));
+    let _ = writeln!(out, "        [[ ${{#COMPREPLY[@]}} -eq 0 ]] && COMPREPLY=($(compgen -f -- \"$cur\"))");
+    let _ = writeln!(out, "        return 0");
+    let _ = writeln!(out, "    fi");
+    let _ = writeln!(out);
+    let _ = writeln!(out, "    local opts");
+    let _ = writeln!(out, "    case \"$sub\" in");
+    // The same flag may take different values in different subcommands, so values are
+    // completed within each subcommand's branch
+    for (pattern, sub) in branches(cmd) {
+        let opts: Vec<String> = options(sub).flat_map(flags_of).collect();
+        let _ = writeln!(out, "        {})", pattern);
+        let _ = writeln!(out, "            opts=\"{}\"", opts.join(" "));
+        let valued: Vec<&Arg> = options(sub).filter(|a| takes_value(a)).collect();
+        if !valued.is_empty() {
+            let _ = writeln!(out, "            case \"$prev\" in");
+            for arg in valued {
+                let _ = writeln!(out, "                {})", flags_of(arg).join("|"));
+                let _ = writeln!(out, "                    COMPREPLY=($({}))", bash_values(arg));
+                let _ = writeln!(out, "                    return 0");
+                let _ = writeln!(out, "                    ;;");
+            }
+            let _ = writeln!(out, "            esac");
+        }
+        let _ = writeln!(out, "            ;;");
+    }
+    let _ = writeln!(out, "    esac");
+    let _ = writeln!(out);
+    let _ = writeln!(out, "    if [[ $cur == -* ]]; then");
+    let _ = writeln!(out, "        COMPREPLY=($(compgen -W \"$opts\" -- \"$cur\"))");
+    let _ = writeln!(out, "    else");
+    let _ = writeln!(out, "        COMPREPLY=($(compgen -f -- \"$cur\"))");
+    let _ = writeln!(out, "    fi");
+    let _ = writeln!(out, "}}");
+    let _ = writeln!(out);
+    let _ = writeln!(out, "complete -o filenames -F _{} {}", func, name);
+    out
+}
+
+/// Escape text for use inside a zsh `[...]` description or `'...'` word
+fn zsh_escape(text: &str) -> String {
+    text.replace('\'', "'\\''")
+        .replace('[', "\\[")
+        .replace(']', "\\]")
+        .replace(':', "\\:")
+}
+
+fn zsh(name: &str, cmd: &Command) -> String {
+    let func = function_name(name);
+    let mut out = String::new();
+
+    let _ = writeln!(out, "#compdef {}", name);
+    let _ = writeln!(out);
+    let _ = writeln!(out, "_{}() {{", func);
+    let _ = writeln!(out, "    local -a commands");
+    let _ = writeln!(out, "    commands=(");
+    for sub in subcommands(cmd) {
+        let _ = writeln!(out, "        '{}:{}'", sub.get_name(), zsh_escape(&about_of(sub)));
+    }
+    let _ = writeln!(out, "    )");
+    let _ = writeln!(out);
+    let _ = writeln!(out, "    if (( CURRENT == 2 )); then");
+    let _ = writeln!(out, "        _describe -t commands 'command' commands");
+    let _ = writeln!(out, "        _files");
+    let _ = writeln!(out, "        return");
+    let _ = writeln!(out, "    fi");
+    let _ = writeln!(out);
+    let _ = writeln!(out, "    case ${{words[2]}} in");
+    for (pattern, sub) in branches(cmd) {
+        let _ = writeln!(out, "        {})", pattern);
+        let _ = writeln!(out, "            _arguments {}", zsh_specs(sub).join(" \\\n                "));
+        let _ = writeln!(out, "            ;;");
+    }
+    let _ = writeln!(out, "    esac");
+    let _ = writeln!(out, "}}");
+    let _ = writeln!(out);
+    let _ = writeln!(out, "_{} \"$@\"", func);
+    out
+}
+
+/// `_arguments` specs for a subcommand's options and positional arguments
+fn zsh_specs(sub: &Command) -> Vec<String> {
+    let mut specs = Vec::new();
+    for arg in options(sub) {
+        let help = zsh_escape(&help_of(arg));
+        let action = if !takes_value(arg) {
+            String::new()
+        } else {
+            let values = possible_values(arg);
+            let value_name = arg
+                .get_value_names()
+                .and_then(|v| v.first())
+                .map(|v| v.to_string())
+                .unwrap_or_else(|| arg.get_id().to_string());
+            if !values.is_empty() {
+                format!(":{}:({})", value_name.to_lowercase(), values.join(" "))
+            } else if arg.get_value_hint() == ValueHint::DirPath {
+                format!(":{}:_directories", value_name.to_lowercase())
+            } else {
+                format!(":{}:_files", value_name.to_lowercase())
+            }
+        };
+        for flag in flags_of(arg) {
+            specs.push(format!("'{}[{}]{}'", flag, help, action));
+        }
+    }
+    if sub.get_positionals().next().is_some() {
+        specs.push("'*:file:_files'".to_string());
+    }
+    specs
+}
+
+fn fish_escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('\'', "\\'")
+}
+
+fn fish(name: &str, cmd: &Command) -> String {
+    let mut out = String::new();
+    for sub in subcommands(cmd) {
+        let _ = writeln!(
+            out,
+            "complete -c {} -n '__fish_use_subcommand' -f -a {} -d '{}'",
+            name,
+            sub.get_name(),
+            fish_escape(&about_of(sub))
+        );
+    }
+    let names: Vec<&str> = subcommands(cmd).map(|c| c.get_name()).collect();
+    for (pattern, sub) in branches(cmd) {
+        let condition = if pattern == "*" {
+            format!("not __fish_seen_subcommand_from {}", names.join(" "))
+        } else {
+            format!("__fish_seen_subcommand_from {}", pattern)
+        };
+        for arg in options(sub) {
+            let mut line = format!("complete -c {} -n '{}'", name, condition);
+            if let Some(short) = arg.get_short() {
+                let _ = write!(line, " -s {}", short);
+            }
+            if let Some(long) = arg.get_long() {
+                let _ = write!(line, " -l {}", long);
+            }
+            if takes_value(arg) {
+                line.push_str(" -r");
+                let values = possible_values(arg);
+                if !values.is_empty() {
+                    let _ = write!(line, " -f -a '{}'", values.join(" "));
+                } else if arg.get_value_hint() == ValueHint::DirPath {
+                    line.push_str(" -f -a '(__fish_complete_directories)'");
+                }
+            }
+            let _ = write!(line, " -d '{}'", fish_escape(&help_of(arg)));
+            let _ = writeln!(out, "{}", line);
+        }
+    }
+    out
+}
+
+fn powershell(name: &str, cmd: &Command) -> String {
+    let quote = |s: &str| format!("'{}'", s.replace('\'', "''"));
+    let mut out = String::new();
+
+    let _ = writeln!(out, "Register-ArgumentCompleter -Native -CommandName {} -ScriptBlock {{", quote(name));
+    let _ = writeln!(out, "    param($wordToComplete, $commandAst, $cursorPosition)");
+    let _ = writeln!(out);
+    let _ = writeln!(out, "    $options = @{{");
+    for sub in subcommands(cmd) {
+        let flags: Vec<String> = options(sub).flat_map(flags_of).map(|f| quote(&f)).collect();
+        let _ = writeln!(out, "        {} = @({})", quote(sub.get_name()), flags.join(", "));
+    }
+    let _ = writeln!(out, "    }}");
+    // Values are keyed by subcommand and flag, since a flag's values differ between subcommands
+    let _ = writeln!(out, "    $values = @{{");
+    for sub in subcommands(cmd) {
+        for arg in options(sub).filter(|a| takes_value(a)) {
+            let values: Vec<String> = possible_values(arg).iter().map(|v| quote(v)).collect();
+            if values.is_empty() {
+                continue;
+            }
+            for flag in flags_of(arg) {
+                let _ = writeln!(out, "        {} = @({})", quote(&format!("{} {}", sub.get_name(), flag)), values.join(", "));
+            }
+        }
+    }
+    let _ = writeln!(out, "    }}");
+    let subs: Vec<String> = subcommands(cmd).map(|c| quote(c.get_name())).collect();
+    let _ = writeln!(out, "    $commands = @({})", subs.join(", "));
+    let _ = writeln!(out);
+    let _ = writeln!(out, "    $elements = @($commandAst.CommandElements | ForEach-Object {{ $_.ToString() }})");
+    let _ = writeln!(out, "    $prev = if ($wordToComplete) {{ $elements[-2] }} else {{ $elements[-1] }}");
+    let _ = writeln!(out, "    if ($elements.Count -le 1 -or ($elements.Count -eq 2 -and $wordToComplete)) {{");
+    let _ = writeln!(out, "        $candidates = $commands");
+    let _ = writeln!(out, "    }} else {{");
+    let _ = writeln!(out, "        $sub = if ($options.ContainsKey($elements[1])) {{ $elements[1] }} else {{ 'convert' }}");
+    let _ = writeln!(out, "        $candidates = if ($values.ContainsKey(\"$sub $prev\")) {{ $values[\"$sub $prev\"] }} else {{ $options[$sub] }}");
+    let _ = writeln!(out, "    }}");
+    let _ = writeln!(out);
+    let _ = writeln!(out, "    $candidates | Where-Object {{ $_ -like \"$wordToComplete*\" }} | ForEach-Object {{");
+    let _ = writeln!(out, "        [System.Management.Automation.CompletionResult]::new($_, $_, 'ParameterValue', $_)");
+    let _ = writeln!(out, "    }}");
+    let _ = writeln!(out, "}}");
+    out
+}
+
+/// Escape text for roff: backslashes, and leading dots/quotes that would read as requests
+fn roff_escape(text: &str) -> String {
+    let escaped = text.replace('\\', "\\e").replace('-', "\\-");
+    if escaped.starts_with('.') || escaped.starts_with('\'') {
+        format!("\\&{}", escaped)
+    } else {
+        escaped
+    }
+}
+
+fn roff_option(out: &mut String, arg: &Arg) {
+    let mut flags: Vec<String> = flags_of(arg).iter().map(|f| format!("\\fB{}\\fR", roff_escape(f))).collect();
+    if takes_value(arg) {
+        let value_name = arg
+            .get_value_names()
+            .and_then(|v| v.first())
+            .map(|v| v.to_string())
+            .unwrap_or_else(|| arg.get_id().to_string().to_uppercase());
+        let value = format!("\\fI{}\\fR", roff_escape(&value_name));
+        match flags.last_mut() {
+            Some(last) => {
+                let _ = write!(last, " {}", value);
+            }
+            // A positional argument
+            None => flags.push(value),
+        }
+    }
+    let _ = writeln!(out, ".TP");
+    let _ = writeln!(out, "{}", flags.join(", "));
+    let mut help = help_of(arg);
+    let values = possible_values(arg);
+    if !values.is_empty() {
+        let _ = write!(help, " [possible values: {}]", values.join(", "));
+    }
+    let _ = writeln!(out, "{}", roff_escape(&help));
+}
+
+/// A roff man page covering every subcommand and its options
+pub fn man_page(cmd: &Command) -> String {
+    let name = cmd.get_name();
+    let version = cmd.get_version().unwrap_or(env!("CARGO_PKG_VERSION"));
+    let mut out = String::new();
+
+    let _ = writeln!(out, ".TH {} 1 \"\" \"{} {}\" \"User Commands\"", name.to_uppercase(), name, version);
+    let _ = writeln!(out, ".SH NAME");
+    let _ = writeln!(out, "{} \\- {}", roff_escape(name), roff_escape(&about_of(cmd)));
+    let _ = writeln!(out, ".SH SYNOPSIS");
+    let _ = writeln!(out, "\\fB{}\\fR \\fICOMMAND\\fR [\\fIOPTIONS\\fR] \\fIINPUT\\fR", roff_escape(name));
+    let _ = writeln!(out, ".br");
+    let _ = writeln!(out, "\\fB{}\\fR \\fIINPUT\\fR [\\fIOPTIONS\\fR]", roff_escape(name));
+    if let Some(after) = cmd.get_after_help() {
+        let _ = writeln!(out, ".SH DESCRIPTION");
+        let _ = writeln!(out, "{}", roff_escape(&after.to_string().replace('`', "")));
+    }
+
+    let _ = writeln!(out, ".SH COMMANDS");
+    for sub in subcommands(cmd) {
+        let _ = writeln!(out, ".TP");
+        let _ = writeln!(out, "\\fB{}\\fR", roff_escape(sub.get_name()));
+        let _ = writeln!(out, "{}", roff_escape(&about_of(sub)));
+    }
+
+    // Subcommands sharing an option set (convert, batch, check) are documented once
+    let mut groups: Vec<(Vec<&str>, &Command)> = Vec::new();
+    for sub in subcommands(cmd) {
+        let ids: Vec<&str> = options(sub).map(|a| a.get_id().as_str()).collect();
+        let same = |c: &Command| options(c).map(|a| a.get_id().as_str()).eq(ids.iter().copied());
+        match groups.iter_mut().find(|(_, first)| same(first)) {
+            Some((names, _)) => names.push(sub.get_name()),
+            None => groups.push((vec![sub.get_name()], sub)),
+        }
+    }
+    for (names, sub) in groups {
+        if options(sub).all(|a| a.get_long() == Some("help")) {
+            continue;
+        }
+        let title = names.iter().map(|n| n.to_uppercase()).collect::<Vec<_>>().join(", ");
+        let _ = writeln!(out, ".SH {} OPTIONS", title);
+        for arg in sub.get_positionals().filter(|a| !a.is_hide_set()) {
+            roff_option(&mut out, arg);
+        }
+        for arg in options(sub) {
+            roff_option(&mut out, arg);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::Cli;
+    use clap::CommandFactory;
+
+    fn command() -> Command {
+        let mut cmd = Cli::command();
+        cmd.build();
+        cmd
+    }
+
+    #[test]
+    fn every_script_covers_every_subcommand_and_option() {
+        let cmd = command();
+        for &shell in Shell::value_variants() {
+            let script = generate(shell, &cmd);
+            for sub in subcommands(&cmd) {
+                assert!(script.contains(sub.get_name()), "{:?} script lacks {}", shell, sub.get_name());
+                for long in options(sub).filter_map(Arg::get_long) {
+                    assert!(script.contains(long), "{:?} script lacks {} --{}", shell, sub.get_name(), long);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn bash_completes_values_per_subcommand() {
+        let script = generate(Shell::Bash, &command());
+        let branch = |name: &str| {
+            let start = script.find(&format!("\n        {})\n", name)).unwrap();
+            let end = start + script[start..].find("\n            ;;\n").unwrap();
+            script[start..end].to_string()
+        };
+        // `--format` means different things to `convert` and `lint`
+        assert!(branch("lint").contains("compgen -W \"text json\""));
+        assert!(!branch("lint").contains("jex"));
+        assert!(branch("convert").contains("compgen -W \"markdown jex ssml\""));
+        // A bare `epub-to-md <INPUT>` completes like `convert`
+        assert_eq!(branch("*").replace("*)", ""), branch("convert").replace("convert)", ""));
+    }
+
+    #[test]
+    fn man_page_documents_every_option() {
+        let cmd = command();
+        let page = man_page(&cmd);
+        for sub in subcommands(&cmd) {
+            assert!(page.contains(&format!("\\fB{}\\fR", roff_escape(sub.get_name()))), "man page lacks {}", sub.get_name());
+            for long in options(sub).filter_map(Arg::get_long) {
+                assert!(page.contains(&roff_escape(&format!("--{}", long))), "man page lacks {} --{}", sub.get_name(), long);
+            }
+        }
+    }
+}
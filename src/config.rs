@@ -0,0 +1,450 @@
+use crate::cli::Cli;
+use anyhow::{Context, Result};
+use clap::CommandFactory;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Project config file, looked for in the working directory
+const PROJECT_FILE: &str = "epub-to-md.toml";
+
+/// Subcommands whose options a profile can set
+const PROFILE_COMMANDS: &[&str] = &["convert", "batch", "check", "diff"];
+
+/// A value in the config file
+#[derive(Debug, Clone)]
+pub enum Value {
+    String(String),
+    Integer(i64),
+    Float(f64),
+    Bool(bool),
+    Array(Vec<Value>),
+}
+
+/// A named table, `[<kind>.<name>]`: its keys (with `_` written as `-`), their values
+/// and the line each is on
+#[derive(Debug)]
+pub struct Table {
+    pub kind: String,
+    pub name: String,
+    pub entries: Vec<(String, Value, usize)>,
+}
+
+/// A named set of options, `[profile.<name>]`: long option names (without the dashes)
+/// and their values
+type Profile = Table;
+
+/// A parsed config file
+#[derive(Debug)]
+struct Config {
+    path: PathBuf,
+    profiles: Vec<Profile>,
+}
+
+/// `epub-to-md` directory under `$XDG_CONFIG_HOME` (or `~/.config`)
+pub fn config_dir() -> Option<PathBuf> {
+    if let Some(xdg) = std::env::var_os("XDG_CONFIG_HOME").filter(|v| !v.is_empty()) {
+        return Some(PathBuf::from(xdg).join("epub-to-md"));
+    }
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config").join("epub-to-md"))
+}
+
+/// Config files in the order profiles are looked up: `$EPUB_TO_MD_CONFIG` alone if set,
+/// otherwise `epub-to-md.toml` in the working directory, then
+/// `$XDG_CONFIG_HOME/epub-to-md/config.toml` (or `~/.config/epub-to-md/config.toml`)
+fn config_paths() -> Vec<PathBuf> {
+    if let Some(path) = std::env::var_os("EPUB_TO_MD_CONFIG").filter(|v| !v.is_empty()) {
+        return vec![PathBuf::from(path)];
+    }
+    let mut paths = vec![PathBuf::from(PROJECT_FILE)];
+    paths.extend(config_dir().map(|dir| dir.join("config.toml")));
+    paths
+}
+
+/// Replace `--profile NAME` in a command line with the options the profile sets, placed
+/// before the ones given on the command line so those win. Options set through their
+/// `EPUB_TO_MD_*` variables win too. `args` start with the program name and the subcommand.
+pub fn expand_profile(mut args: Vec<OsString>) -> Result<Vec<OsString>> {
+    let Some(command) = args.get(1).and_then(|a| a.to_str()).filter(|c| PROFILE_COMMANDS.contains(c)).map(str::to_string) else {
+        return Ok(args);
+    };
+    let Some(name) = find_profile_arg(&args) else {
+        return Ok(args);
+    };
+
+    let (path, profile) = find_profile(&name, &config_paths(), std::env::var_os("EPUB_TO_MD_CONFIG").is_some())?;
+    let profile_args = profile.to_args(&command, &path)?;
+    args.splice(2..2, profile_args);
+    Ok(args)
+}
+
+/// The first profile called `name` in the config files at `paths`, and the file it is in.
+/// A missing file is skipped, unless it was `required`.
+fn find_profile(name: &str, paths: &[PathBuf], required: bool) -> Result<(PathBuf, Profile)> {
+    let mut searched = Vec::new();
+    for path in paths {
+        if !path.is_file() {
+            if required {
+                anyhow::bail!("Config file {} does not exist", path.display());
+            }
+            continue;
+        }
+        let config = Config::load(path)?;
+        searched.push(path.display().to_string());
+        if let Some(profile) = config.profiles.into_iter().find(|p| p.name == name) {
+            return Ok((config.path, profile));
+        }
+    }
+    if searched.is_empty() {
+        anyhow::bail!("Unknown profile '{}': no config file found (looked for {})", name, display_paths(paths));
+    }
+    anyhow::bail!("Unknown profile '{}' (not in {})", name, searched.join(" or "))
+}
+
+/// Value of the `--profile` option, else of `$EPUB_TO_MD_PROFILE`; it is left in place
+/// for clap to see
+fn find_profile_arg(args: &[OsString]) -> Option<String> {
+    let from_env = || std::env::var("EPUB_TO_MD_PROFILE").ok().filter(|v| !v.is_empty());
+    for (i, arg) in args.iter().enumerate().skip(2) {
+        let arg = arg.to_str()?;
+        if arg == "--" {
+            break;
+        }
+        if arg == "--profile" {
+            return Some(args.get(i + 1)?.to_str()?.to_string());
+        }
+        if let Some(name) = arg.strip_prefix("--profile=") {
+            return Some(name.to_string());
+        }
+    }
+    from_env()
+}
+
+fn display_paths(paths: &[PathBuf]) -> String {
+    paths.iter().map(|p| p.display().to_string()).collect::<Vec<_>>().join(", ")
+}
+
+impl Profile {
+    /// The profile as command-line arguments, checked against the options `command` takes
+    fn to_args(&self, command: &str, path: &Path) -> Result<Vec<OsString>> {
+        let cli = Cli::command();
+        let subcommand = cli.find_subcommand(command).context("Unknown subcommand")?;
+        let mut args = Vec::new();
+        for (key, value, line) in &self.entries {
+            let location = || format!("{}:{}: profile '{}'", path.display(), line, self.name);
+            let Some(arg) = subcommand.get_arguments().find(|a| a.get_long() == Some(key.as_str())) else {
+                anyhow::bail!("{} sets '{}', which is not an option of {}", location(), key, command);
+            };
+            if key == "profile" {
+                anyhow::bail!("{} can't select another profile", location());
+            }
+            if arg.get_env().is_some_and(|var| std::env::var_os(var).is_some()) {
+                continue;
+            }
+            let takes_value = arg.get_action().takes_values();
+            let values = match value {
+                Value::Array(items) => items.clone(),
+                value => vec![value.clone()],
+            };
+            for value in values {
+                match (value, takes_value) {
+                    (Value::Bool(true), false) => args.push(OsString::from(format!("--{}", key))),
+                    (Value::Bool(false), false) => {}
+                    (_, false) => anyhow::bail!("{}: '{}' is a flag; set it to true or false", location(), key),
+                    (Value::Array(_), true) => anyhow::bail!("{}: '{}' can't hold nested arrays", location(), key),
+                    (value, true) => {
+                        let text = match value {
+                            Value::String(s) => s,
+                            Value::Integer(n) => n.to_string(),
+                            Value::Float(f) => f.to_string(),
+                            Value::Bool(b) => b.to_string(),
+                            Value::Array(_) => unreachable!(),
+                        };
+                        args.push(OsString::from(format!("--{}={}", key, text)));
+                    }
+                }
+            }
+        }
+        Ok(args)
+    }
+}
+
+impl Config {
+    fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path).with_context(|| format!("Failed to read config file {}", path.display()))?;
+        let profiles = parse_tables(&text, path)?.into_iter().filter(|t| t.kind == "profile").collect();
+        Ok(Self { path: path.to_path_buf(), profiles })
+    }
+}
+
+/// Parse the subset of TOML config and rules files need: `[<kind>.<name>]` tables of
+/// `key = value` lines, where values are strings, numbers, booleans or one-line arrays.
+/// Keys outside such a table are ignored, left for future settings.
+pub fn parse_tables(text: &str, path: &Path) -> Result<Vec<Table>> {
+    let mut tables: Vec<Table> = Vec::new();
+    let mut in_table = false;
+    for (n, raw) in text.lines().enumerate() {
+        let line_no = n + 1;
+        let error = |message: &str| anyhow::anyhow!("{}:{}: {}", path.display(), line_no, message);
+        let line = strip_comment(raw).trim();
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(header) = line.strip_prefix('[') {
+            let header = header.strip_suffix(']').ok_or_else(|| error("unclosed table header"))?.trim();
+            in_table = false;
+            let Some((kind, name)) = header.split_once('.') else {
+                continue;
+            };
+            let kind = kind.trim().to_string();
+            let name = unquote_key(name.trim()).ok_or_else(|| error(&format!("invalid {} name", kind)))?;
+            if tables.iter().any(|t| t.kind == kind && t.name == name) {
+                return Err(error(&format!("{} '{}' is defined twice", kind, name)));
+            }
+            tables.push(Table { kind, name, entries: Vec::new() });
+            in_table = true;
+            continue;
+        }
+        let (key, value) = line.split_once('=').ok_or_else(|| error("expected `key = value`"))?;
+        let key = unquote_key(key.trim()).ok_or_else(|| error("invalid key"))?.replace('_', "-");
+        let (value, rest) = parse_value(value.trim()).map_err(|e| error(&e))?;
+        if !rest.trim().is_empty() {
+            return Err(error("unexpected text after the value"));
+        }
+        if in_table {
+            let table = tables.last_mut().expect("in a table");
+            if table.entries.iter().any(|(k, _, _)| *k == key) {
+                return Err(error(&format!("'{}' is set twice", key)));
+            }
+            table.entries.push((key, value, line_no));
+        }
+    }
+    Ok(tables)
+}
+
+/// A line without its `#` comment, leaving `#` inside strings alone
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match quote {
+            Some('"') if escaped => escaped = false,
+            Some('"') if c == '\\' => escaped = true,
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None if c == '#' => return &line[..i],
+            None => {}
+        }
+    }
+    line
+}
+
+/// A bare or quoted key
+fn unquote_key(key: &str) -> Option<String> {
+    if key.starts_with('"') || key.starts_with('\'') {
+        return match parse_value(key) {
+            Ok((Value::String(s), rest)) if rest.trim().is_empty() => Some(s),
+            _ => None,
+        };
+    }
+    let bare = !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    bare.then(|| key.to_string())
+}
+
+/// Parse a value at the start of `text`, returning it and what follows
+fn parse_value(text: &str) -> Result<(Value, &str), String> {
+    if let Some(rest) = text.strip_prefix('"') {
+        let mut out = String::new();
+        let mut chars = rest.char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => return Ok((Value::String(out), &rest[i + 1..])),
+                '\\' => match chars.next().map(|(_, c)| c) {
+                    Some('n') => out.push('\n'),
+                    Some('t') => out.push('\t'),
+                    Some('"') => out.push('"'),
+                    Some('\\') => out.push('\\'),
+                    Some('u') => {
+                        let hex: String = chars.by_ref().take(4).map(|(_, c)| c).collect();
+                        let c = u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32).ok_or("invalid \\u escape")?;
+                        out.push(c);
+                    }
+                    _ => return Err("invalid escape in string".to_string()),
+                },
+                c => out.push(c),
+            }
+        }
+        return Err("unterminated string".to_string());
+    }
+    if let Some(rest) = text.strip_prefix('\'') {
+        let end = rest.find('\'').ok_or("unterminated string")?;
+        return Ok((Value::String(rest[..end].to_string()), &rest[end + 1..]));
+    }
+    if let Some(mut rest) = text.strip_prefix('[') {
+        let mut items = Vec::new();
+        loop {
+            rest = rest.trim_start();
+            if let Some(after) = rest.strip_prefix(']') {
+                return Ok((Value::Array(items), after));
+            }
+            if rest.is_empty() {
+                return Err("expected , or ] in array (arrays must fit on one line)".to_string());
+            }
+            let (item, after) = parse_value(rest)?;
+            items.push(item);
+            rest = after.trim_start();
+            if let Some(after) = rest.strip_prefix(',') {
+                rest = after;
+            } else if !rest.starts_with(']') {
+                return Err("expected , or ] in array (arrays must fit on one line)".to_string());
+            }
+        }
+    }
+
+    let end = text.find([',', ']']).unwrap_or(text.len());
+    let (word, rest) = (text[..end].trim(), &text[end..]);
+    let value = match word {
+        "true" => Value::Bool(true),
+        "false" => Value::Bool(false),
+        _ => {
+            let number = word.replace('_', "");
+            if let Ok(n) = number.parse::<i64>() {
+                Value::Integer(n)
+            } else if let Some(f) = number.parse::<f64>().ok().filter(|f| f.is_finite()) {
+                Value::Float(f)
+            } else if word.is_empty() {
+                return Err("missing value".to_string());
+            } else {
+                return Err(format!("invalid value '{}' (strings need quotes)", word));
+            }
+        }
+    };
+    Ok((value, rest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::Command;
+    use crate::render::Flavor;
+
+    fn parse(text: &str) -> Result<Vec<Table>> {
+        parse_tables(text, Path::new("config.toml"))
+    }
+
+    fn entry<'a>(table: &'a Table, key: &str) -> &'a Value {
+        &table.entries.iter().find(|(k, _, _)| k == key).unwrap().1
+    }
+
+    fn error(text: &str) -> String {
+        parse(text).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn strings_keep_their_escapes_and_comment_marks() {
+        let tables = parse(concat!(
+            "[profile.one]\n",
+            r#"basic = "say \"hi\"\\ \t\u00e9 # not a comment" # a comment"#,
+            "\n",
+            r#"literal = 'C:\books\#1'"#,
+            "\n",
+            r#""quoted key" = "x""#,
+        ))
+        .unwrap();
+        assert!(matches!(entry(&tables[0], "basic"), Value::String(s) if s == "say \"hi\"\\ \té # not a comment"));
+        assert!(matches!(entry(&tables[0], "literal"), Value::String(s) if s == r"C:\books\#1"));
+        assert!(matches!(entry(&tables[0], "quoted key"), Value::String(s) if s == "x"));
+    }
+
+    #[test]
+    fn numbers_booleans_and_arrays_are_read() {
+        let tables = parse("[profile.one]\ntoc_depth = 1_000\nratio = 2.5\njobs = -3\nsingle = true\ntag-map = [\"a=b\", 'c=', 7, false]\nempty = []\n").unwrap();
+        let table = &tables[0];
+        // Underscores in keys are written as dashes
+        assert!(matches!(entry(table, "toc-depth"), Value::Integer(1000)));
+        assert!(matches!(entry(table, "ratio"), Value::Float(f) if *f == 2.5));
+        assert!(matches!(entry(table, "jobs"), Value::Integer(-3)));
+        assert!(matches!(entry(table, "single"), Value::Bool(true)));
+        let Value::Array(items) = entry(table, "tag-map") else { panic!("not an array") };
+        assert!(matches!(items.as_slice(), [Value::String(a), Value::String(c), Value::Integer(7), Value::Bool(false)] if a == "a=b" && c == "c="));
+        assert!(matches!(entry(table, "empty"), Value::Array(items) if items.is_empty()));
+        assert_eq!(table.entries.iter().map(|(_, _, line)| *line).collect::<Vec<_>>(), [2, 3, 4, 5, 6, 7]);
+    }
+
+    #[test]
+    fn keys_outside_a_named_table_are_ignored() {
+        let tables = parse("version = 2\n[settings]\ncolor = \"auto\"\n[profile.one]\nsingle = true\n[rules.misc]\nx = 1\n").unwrap();
+        let names: Vec<(&str, &str)> = tables.iter().map(|t| (t.kind.as_str(), t.name.as_str())).collect();
+        assert_eq!(names, [("profile", "one"), ("rules", "misc")]);
+        assert_eq!(tables[0].entries.len(), 1);
+    }
+
+    #[test]
+    fn malformed_lines_are_reported_with_their_line() {
+        assert_eq!(error("[profile.one]\nflavor = gfm"), "config.toml:2: invalid value 'gfm' (strings need quotes)");
+        assert_eq!(error("[profile.one]\n\ntitle = \"open"), "config.toml:3: unterminated string");
+        assert_eq!(error("[profile.one]\ntitle = \"\\q\""), "config.toml:2: invalid escape in string");
+        assert_eq!(error("[profile.one]\ntags = [\"a\",\n\"b\"]"), "config.toml:2: expected , or ] in array (arrays must fit on one line)");
+        assert_eq!(error("[profile.one]\ntags = [\"a\""), "config.toml:2: expected , or ] in array (arrays must fit on one line)");
+        assert_eq!(error("[profile.one]\ntitle = \"a\" b"), "config.toml:2: unexpected text after the value");
+        assert_eq!(error("[profile.one]\nsingle = true\nsingle = false"), "config.toml:3: 'single' is set twice");
+        assert_eq!(error("[profile.one]\n[profile.one]"), "config.toml:2: profile 'one' is defined twice");
+        assert_eq!(error("[profile.one\n"), "config.toml:1: unclosed table header");
+        assert_eq!(error("[profile.one]\njust words"), "config.toml:2: expected `key = value`");
+    }
+
+    /// Write `text` to a config file in `scratch` and return its path
+    fn config_file(scratch: &crate::check::ScratchDir, name: &str, text: &str) -> PathBuf {
+        let path = scratch.path().join(name);
+        fs::write(&path, text).unwrap();
+        path
+    }
+
+    #[test]
+    fn profiles_are_looked_up_in_order_and_missing_ones_reported() {
+        let scratch = crate::check::ScratchDir::new("config").unwrap();
+        let project = config_file(&scratch, "epub-to-md.toml", "[profile.shared]\nsingle = true\n");
+        let user = config_file(&scratch, "config.toml", "[profile.shared]\nsingle = false\n[profile.user]\nsingle = true\n");
+        let absent = scratch.path().join("absent.toml");
+        let paths = [absent.clone(), project.clone(), user.clone()];
+
+        assert_eq!(find_profile("shared", &paths, false).unwrap().0, project);
+        assert_eq!(find_profile("user", &paths, false).unwrap().0, user);
+        let missing = find_profile("nope", &paths, false).unwrap_err().to_string();
+        assert_eq!(missing, format!("Unknown profile 'nope' (not in {} or {})", project.display(), user.display()));
+        let none = find_profile("nope", std::slice::from_ref(&absent), false).unwrap_err().to_string();
+        assert!(none.starts_with("Unknown profile 'nope': no config file found"), "{}", none);
+        let required = find_profile("shared", std::slice::from_ref(&absent), true).unwrap_err().to_string();
+        assert_eq!(required, format!("Config file {} does not exist", absent.display()));
+    }
+
+    #[test]
+    fn unknown_options_and_misused_flags_are_refused() {
+        let path = Path::new("config.toml");
+        let to_args = |text: &str| parse_tables(text, path).unwrap().remove(0).to_args("convert", path);
+        assert_eq!(to_args("[profile.p]\ncolour = 1").unwrap_err().to_string(), "config.toml:2: profile 'p' sets 'colour', which is not an option of convert");
+        assert_eq!(to_args("[profile.p]\nprofile = \"q\"").unwrap_err().to_string(), "config.toml:2: profile 'p' can't select another profile");
+        assert_eq!(to_args("[profile.p]\nsingle = \"yes\"").unwrap_err().to_string(), "config.toml:2: profile 'p': 'single' is a flag; set it to true or false");
+        let args = to_args("[profile.p]\nsingle = true\ndehyphenate = false\ntoc_depth = 2\ntag-map = [\"a=b\", \"c=d\"]").unwrap();
+        assert_eq!(args, ["--single", "--toc-depth=2", "--tag-map=a=b", "--tag-map=c=d"].map(OsString::from));
+    }
+
+    #[test]
+    fn options_on_the_command_line_override_the_profile() {
+        let path = Path::new("config.toml");
+        let profile = "[profile.p]\nflavor = \"pandoc\"\nno-tidy = true\ntoc-depth = 2\ninject-title = true\ntag-map = [\"a=b\"]";
+        let profile_args = parse_tables(profile, path).unwrap().remove(0).to_args("convert", path).unwrap();
+        let mut args: Vec<OsString> = ["epub-to-md", "convert", "book.epub", "--flavor", "obsidian", "--tidy", "--toc-depth", "3"].map(OsString::from).to_vec();
+        args.splice(2..2, profile_args);
+
+        let Command::Convert(convert) = Cli::try_parse_args(args).unwrap().command else { panic!("not convert") };
+        let output = &convert.output;
+        assert_eq!(output.flavor, Flavor::Obsidian);
+        assert!(output.tidy && !output.no_tidy);
+        assert_eq!(output.toc_depth, Some(3));
+        // What the command line doesn't mention still comes from the profile
+        assert!(output.inject_title);
+        assert_eq!(output.tag_map, [("a".to_string(), "b".to_string())]);
+    }
+}
@@ -0,0 +1,194 @@
+use crate::cli::{Cli, Command};
+use crate::progress::Sink;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::ffi::OsString;
+use std::fs;
+use std::io::{BufRead, BufReader};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::Instant;
+
+/// A job, one JSON object per line. `args` are what would follow the subcommand on the
+/// command line; relative paths resolve against the daemon's working directory.
+#[derive(Deserialize)]
+struct Request {
+    /// Echoed back in every response about the job
+    #[serde(default)]
+    id: Value,
+    /// `convert`, `batch` or `check`; or `ping`, or `shutdown` to stop the daemon once
+    /// the jobs already running finish
+    #[serde(default = "default_command")]
+    command: String,
+    #[serde(default)]
+    args: Vec<String>,
+}
+
+fn default_command() -> String {
+    "convert".to_string()
+}
+
+/// Lines sent back on the connection, besides the jobs' own progress events
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum Response<'a> {
+    /// The job waits for one of `--jobs` slots; `ahead` jobs are waiting before it
+    JobQueued { id: &'a Value, ahead: usize },
+    JobStarted { id: &'a Value },
+    JobFinished { id: &'a Value, seconds: f64 },
+    JobFailed { id: &'a Value, message: String },
+    Pong { id: &'a Value },
+    Stopping { id: &'a Value },
+}
+
+impl Response<'_> {
+    fn send(&self, sink: &Sink) {
+        if let Ok(line) = serde_json::to_string(self) {
+            sink.line(&line);
+        }
+    }
+}
+
+/// Jobs taking part in the queue: how many run and how many wait
+#[derive(Default)]
+struct Queue {
+    state: Mutex<(usize, usize)>,
+    turn: Condvar,
+}
+
+impl Queue {
+    /// Wait for a free slot, reporting the job as queued first if there isn't one
+    fn enter(&self, slots: usize, queued: impl FnOnce(usize)) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        if state.0 >= slots {
+            queued(state.1);
+            state.1 += 1;
+            while state.0 >= slots {
+                state = self.turn.wait(state).unwrap_or_else(|e| e.into_inner());
+            }
+            state.1 -= 1;
+        }
+        state.0 += 1;
+    }
+
+    fn leave(&self) {
+        self.state.lock().unwrap_or_else(|e| e.into_inner()).0 -= 1;
+        self.turn.notify_all();
+    }
+
+    /// Block until nothing is running or waiting
+    fn drain(&self) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        while *state != (0, 0) {
+            state = self.turn.wait(state).unwrap_or_else(|e| e.into_inner());
+        }
+    }
+}
+
+/// Listen on `socket` and run the conversion jobs clients send, at most `slots` at a
+/// time, until a client asks for a shutdown. Each connection's jobs run one after
+/// another; clients wanting several at once open several connections.
+pub fn serve(socket: &Path, slots: usize) -> Result<()> {
+    if socket.exists() {
+        if UnixStream::connect(socket).is_ok() {
+            anyhow::bail!("A daemon is already listening on {}", socket.display());
+        }
+        fs::remove_file(socket).with_context(|| format!("Failed to remove stale socket {}", socket.display()))?;
+    }
+    let listener = UnixListener::bind(socket).with_context(|| format!("Failed to listen on {}", socket.display()))?;
+    eprintln!("Listening on {}", socket.display());
+
+    let queue = Arc::new(Queue::default());
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                eprintln!("Failed to accept a connection: {}", e);
+                continue;
+            }
+        };
+        let queue = Arc::clone(&queue);
+        let socket = socket.to_path_buf();
+        thread::spawn(move || {
+            if let Err(e) = handle(stream, &queue, slots, &socket) {
+                eprintln!("Connection closed: {}", e);
+            }
+        });
+    }
+    Ok(())
+}
+
+fn handle(stream: UnixStream, queue: &Queue, slots: usize, socket: &Path) -> Result<()> {
+    let sink = Sink::new(stream.try_clone().context("Failed to set up the connection")?);
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let request: Request = match serde_json::from_str(&line) {
+            Ok(request) => request,
+            Err(e) => {
+                Response::JobFailed { id: &Value::Null, message: format!("Invalid request: {}", e) }.send(&sink);
+                continue;
+            }
+        };
+        let id = &request.id;
+        match request.command.as_str() {
+            "ping" => Response::Pong { id }.send(&sink),
+            "shutdown" => {
+                Response::Stopping { id }.send(&sink);
+                queue.drain();
+                let _ = fs::remove_file(socket);
+                eprintln!("Shutting down");
+                std::process::exit(0);
+            }
+            _ => run_job(&request, queue, slots, &sink),
+        }
+    }
+    Ok(())
+}
+
+fn run_job(request: &Request, queue: &Queue, slots: usize, sink: &Sink) {
+    let id = &request.id;
+    let argv = ["epub-to-md", request.command.as_str()].into_iter().map(String::from).chain(request.args.iter().cloned());
+    let argv = match crate::config::expand_profile(argv.map(OsString::from).collect()) {
+        Ok(argv) => argv,
+        Err(e) => {
+            Response::JobFailed { id, message: format!("{:#}", e) }.send(sink);
+            return;
+        }
+    };
+    let command = match Cli::try_parse_args(argv) {
+        Ok(cli) => cli.command,
+        Err(e) => {
+            Response::JobFailed { id, message: e.to_string().trim().to_string() }.send(sink);
+            return;
+        }
+    };
+    if !matches!(command, Command::Convert(_) | Command::Batch(_) | Command::Check(_)) {
+        let message = format!("The daemon runs convert, batch and check jobs, not {}", request.command);
+        Response::JobFailed { id, message }.send(sink);
+        return;
+    }
+
+    queue.enter(slots, |ahead| Response::JobQueued { id, ahead }.send(sink));
+    Response::JobStarted { id }.send(sink);
+    let start = Instant::now();
+    // A panic must still give the slot back, or every later job would wait forever
+    let result = panic::catch_unwind(AssertUnwindSafe(|| match command {
+        Command::Convert(args) => crate::run_conversion(&args, false, Some(sink.clone())),
+        Command::Batch(args) => crate::run_batch(&args, Some(sink.clone())),
+        Command::Check(args) => crate::run_conversion(&args, true, Some(sink.clone())),
+        _ => unreachable!("rejected above"),
+    }))
+    .unwrap_or_else(|_| Err(anyhow::anyhow!("The job panicked")));
+    queue.leave();
+    match result {
+        Ok(()) => Response::JobFinished { id, seconds: start.elapsed().as_secs_f64() }.send(sink),
+        Err(e) => Response::JobFailed { id, message: format!("{:#}", e) }.send(sink),
+    }
+}
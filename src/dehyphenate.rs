@@ -0,0 +1,304 @@
+use std::collections::HashSet;
+
+/// U+00AD, which print-to-EPUB tools sometimes leave where a line was broken
+const SOFT_HYPHEN: char = '\u{AD}';
+
+/// Emphasis markers, which close before a break and reopen after it when each line of an
+/// emphasized word was wrapped on its own (`*con-* *tinued*`)
+const EMPHASIS: [char; 2] = ['*', '_'];
+
+/// Words after which a trailing hyphen is suspended (`pre- and post-war`), not a line break
+const SUSPENDED_BEFORE: &[&str] = &["and", "or", "nor", "to", "und"];
+
+/// Repairs words and sentences broken by the line and page breaks of a print layout, as in
+/// EPUBs converted from PDFs: `con- tinued` becomes `continued`, while `well- known`
+/// keeps its hyphen. Whether the hyphen belongs is judged from the words the book itself
+/// uses; chapters are fixed in reading order, each with the words of the ones before it.
+#[derive(Debug, Default)]
+pub struct Dehyphenator {
+    /// Lowercased words and hyphenated compounds seen in the text so far
+    vocabulary: HashSet<String>,
+    /// Words joined, in the order they were
+    pub joined: Vec<String>,
+    /// Line-break hyphens kept because the word is a compound
+    pub kept: usize,
+    /// Paragraphs joined to the one before, having been split mid-sentence by a page break
+    pub paragraphs: usize,
+}
+
+impl Dehyphenator {
+    /// Repair one chapter's Markdown
+    pub fn fix(&mut self, markdown: &str) -> String {
+        let lines: Vec<String> = markdown.lines().map(remove_soft_hyphens).collect();
+        let prose = prose_lines(&lines);
+        let mut after_hyphen = false;
+        for (line, &is_prose) in lines.iter().zip(&prose) {
+            if is_prose {
+                self.learn(line, after_hyphen);
+                after_hyphen = hyphen_end(line).is_some();
+            } else if !line.trim().is_empty() {
+                after_hyphen = false;
+            }
+        }
+
+        let mut out: Vec<String> = Vec::with_capacity(lines.len());
+        // Index in `out` of the last prose line, while only blank lines follow it
+        let mut last_prose: Option<usize> = None;
+        for (line, &is_prose) in lines.iter().zip(&prose) {
+            if !is_prose {
+                if !line.trim().is_empty() {
+                    last_prose = None;
+                }
+                out.push(line.clone());
+                continue;
+            }
+            let line = self.join_within(line);
+            if let Some(index) = last_prose {
+                let paragraph_break = index + 1 < out.len();
+                let previous = out[index].trim_end();
+                let joined = if let Some((head, markers)) = hyphen_end(previous) {
+                    // The emphasis closed before the break reopens after it, and the pair goes
+                    line.strip_prefix(markers).filter(|rest| rest.starts_with(|c: char| c.is_lowercase())).map(|line| {
+                        let (tail, rest) = split_word(line);
+                        let separator = self.mend(head, tail);
+                        format!("{}{}{}{}", &previous[..previous.len() - 1 - markers.len()], separator, tail, rest)
+                    })
+                } else if paragraph_break && line.starts_with(|c: char| c.is_lowercase()) && continues_sentence(previous) {
+                    self.paragraphs += 1;
+                    Some(format!("{} {}", previous, line))
+                } else {
+                    None
+                };
+                if let Some(joined) = joined {
+                    out.truncate(index);
+                    out.push(joined);
+                    continue;
+                }
+            }
+            last_prose = Some(out.len());
+            out.push(line);
+        }
+
+        let mut fixed = out.join("\n");
+        if markdown.ends_with('\n') {
+            fixed.push('\n');
+        }
+        fixed
+    }
+
+    /// One-line summary of what was repaired
+    pub fn summary(&self) -> String {
+        let mut shown: Vec<&str> = Vec::new();
+        for word in &self.joined {
+            if shown.len() < 3 && !shown.contains(&word.as_str()) {
+                shown.push(word);
+            }
+        }
+        let mut line = format!("Joined {} hyphenated word(s)", self.joined.len());
+        if !shown.is_empty() {
+            let quoted: Vec<String> = shown.iter().map(|w| format!("\"{}\"", w)).collect();
+            line.push_str(&format!(" ({}{})", quoted.join(", "), if self.joined.len() > 3 { ", ..." } else { "" }));
+        }
+        line.push_str(&format!(", kept {} compound(s) and rejoined {} split paragraph(s)", self.kept, self.paragraphs));
+        line
+    }
+
+    /// Add a line's words to the vocabulary, leaving out the halves of broken words. With
+    /// `after_hyphen`, the line's first word continues one broken at the end of the last.
+    fn learn(&mut self, line: &str, after_hyphen: bool) {
+        let mut skip_next = after_hyphen;
+        for token in line.split_whitespace() {
+            if std::mem::take(&mut skip_next) {
+                continue;
+            }
+            if token.trim_end_matches(EMPHASIS).ends_with('-') {
+                skip_next = true;
+                continue;
+            }
+            let word = token.trim_matches(|c: char| !c.is_alphanumeric());
+            if word.chars().any(char::is_alphabetic) {
+                self.vocabulary.insert(word.to_lowercase());
+            }
+        }
+    }
+
+    /// Join the words broken by a hyphen and a space within a line, outside code spans
+    fn join_within(&mut self, line: &str) -> String {
+        if !line.contains("- ") && !line.contains("-* ") && !line.contains("-_ ") {
+            return line.to_string();
+        }
+        let mut out = String::with_capacity(line.len());
+        let mut rest = line;
+        while let Some(position) = rest.find('-') {
+            let (before, after) = (&rest[..position], &rest[position + 1..]);
+            let head = before.rsplit(|c: char| !c.is_alphabetic()).next().unwrap_or_default();
+            let markers = &after[..after.len() - after.trim_start_matches(EMPHASIS).len()];
+            let spaced = after[markers.len()..].trim_start_matches(' ');
+            let reopened = spaced.strip_prefix(markers).filter(|_| spaced.len() < after.len() - markers.len());
+            out.push_str(before);
+            let in_code = out.matches('`').count() % 2 == 1;
+            let Some(spaced) = reopened.filter(|t| !head.is_empty() && !in_code && t.starts_with(|c: char| c.is_lowercase())) else {
+                out.push('-');
+                rest = after;
+                continue;
+            };
+            let (tail, remainder) = split_word(spaced);
+            out.push_str(self.mend(head, tail));
+            out.push_str(tail);
+            rest = remainder;
+        }
+        out.push_str(rest);
+        out
+    }
+
+    /// What goes between the two halves of a word broken at a hyphen: nothing when it is
+    /// one word, the hyphen for a compound, or the hyphen and a space when it is suspended
+    /// (`pre- and post-war`)
+    fn mend(&mut self, head: &str, tail: &str) -> &'static str {
+        let lower_head = head.to_lowercase();
+        let lower_tail = tail.to_lowercase();
+        if SUSPENDED_BEFORE.contains(&lower_tail.as_str()) {
+            self.kept += 1;
+            return "- ";
+        }
+        let compound = !self.vocabulary.contains(&format!("{}{}", lower_head, lower_tail))
+            && (self.vocabulary.contains(&format!("{}-{}", lower_head, lower_tail))
+                || (head.chars().count() > 1 && self.vocabulary.contains(&lower_head) && self.vocabulary.contains(&lower_tail)));
+        if compound {
+            self.kept += 1;
+            "-"
+        } else {
+            self.joined.push(format!("{}{}", head, tail));
+            ""
+        }
+    }
+}
+
+/// A line with its soft hyphens, and any space a line break left after one, removed
+fn remove_soft_hyphens(line: &str) -> String {
+    if !line.contains(SOFT_HYPHEN) {
+        return line.to_string();
+    }
+    let mut out = String::with_capacity(line.len());
+    for (i, part) in line.split(SOFT_HYPHEN).enumerate() {
+        out.push_str(if i == 0 { part } else { part.trim_start_matches(' ') });
+    }
+    out
+}
+
+/// Which lines are plain prose paragraphs, outside code fences: not headings, lists,
+/// quotes, tables, images, HTML or indented code
+fn prose_lines(lines: &[String]) -> Vec<bool> {
+    let mut in_fence = false;
+    lines
+        .iter()
+        .map(|line| {
+            let trimmed = line.trim();
+            if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+                in_fence = !in_fence;
+                return false;
+            }
+            let numbered = trimmed.split_once(". ").is_some_and(|(n, _)| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()));
+            !in_fence
+                && !trimmed.is_empty()
+                && !line.starts_with("    ")
+                && !line.starts_with('\t')
+                && !trimmed.starts_with(['#', '|', '>', '!', '<', '=', '['])
+                && !["- ", "* ", "+ ", "---", "***"].iter().any(|m| trimmed.starts_with(m))
+                && !numbered
+        })
+        .collect()
+}
+
+/// The word before a hyphen ending the line, when it ends that way, and the emphasis
+/// markers closed after the hyphen
+fn hyphen_end(line: &str) -> Option<(&str, &str)> {
+    let trimmed = line.trim_end();
+    let unmarked = trimmed.trim_end_matches(EMPHASIS);
+    let stem = unmarked.strip_suffix('-')?;
+    let head = stem.rsplit(|c: char| !c.is_alphabetic()).next().unwrap_or_default();
+    (!head.is_empty()).then_some((head, &trimmed[unmarked.len()..]))
+}
+
+/// Whether a paragraph stops mid-sentence: on a letter or a comma
+fn continues_sentence(line: &str) -> bool {
+    line.trim_end().ends_with(|c: char| c.is_alphabetic() || c == ',')
+}
+
+/// The leading run of letters of a text, and what follows it
+fn split_word(text: &str) -> (&str, &str) {
+    let end = text.find(|c: char| !c.is_alphabetic()).unwrap_or(text.len());
+    text.split_at(end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fix(markdown: &str) -> String {
+        Dehyphenator::default().fix(markdown)
+    }
+
+    #[test]
+    fn broken_words_are_joined_within_and_across_lines() {
+        let mut dehyphenator = Dehyphenator::default();
+        assert_eq!(dehyphenator.fix("It con- tinued on.\nIt was re-\nmarkable."), "It continued on.\nIt was remarkable.");
+        assert_eq!(dehyphenator.joined, ["continued", "remarkable"]);
+        // A hyphen with no space after it, or a capital after the space, is left alone
+        assert_eq!(fix("A mother-in-law came. The Austro- Hungarian army."), "A mother-in-law came. The Austro- Hungarian army.");
+    }
+
+    #[test]
+    fn compounds_the_book_uses_keep_their_hyphen() {
+        let mut dehyphenator = Dehyphenator::default();
+        let fixed = dehyphenator.fix("A well-known fact.\n\nIt was well- known. A self-\nmade man, self-made and proud.");
+        assert_eq!(fixed, "A well-known fact.\n\nIt was well-known. A self-made man, self-made and proud.");
+        assert_eq!(dehyphenator.kept, 2);
+        assert!(dehyphenator.joined.is_empty());
+
+        // Both halves are words of the book, and their join isn't
+        assert_eq!(fix("The ice was cold. A cold- ice pack."), "The ice was cold. A cold-ice pack.");
+        // Unless the join is a word of the book too
+        assert_eq!(fix("Some other thing. Another. An- other day."), "Some other thing. Another. Another day.");
+    }
+
+    #[test]
+    fn suspended_hyphens_stay_suspended() {
+        assert_eq!(fix("Both pre- and post-war."), "Both pre- and post-war.");
+        assert_eq!(fix("The pre-\nand post-war years."), "The pre- and post-war years.");
+    }
+
+    #[test]
+    fn soft_hyphens_are_removed() {
+        assert_eq!(fix("con\u{AD}tinued, and con\u{AD} tinued."), "continued, and continued.");
+    }
+
+    #[test]
+    fn hyphens_break_words_across_emphasis() {
+        assert_eq!(fix("It *con- tinued* on."), "It *continued* on.");
+        assert_eq!(fix("It **con- tinued** on."), "It **continued** on.");
+        // Each half emphasized on its own line
+        assert_eq!(fix("It *con-* *tinued* on."), "It *continued* on.");
+        assert_eq!(fix("The *exam-*\n*ple* was."), "The *example* was.");
+        assert_eq!(fix("The _exam-\nple_ was."), "The _example_ was.");
+        // Emphasis that doesn't reopen after the break is left alone
+        assert_eq!(fix("The *exam-*\nple was."), "The *exam-*\nple was.");
+        assert_eq!(fix("Some *bold-* and plain."), "Some *bold-* and plain.");
+    }
+
+    #[test]
+    fn code_and_structure_are_left_alone() {
+        assert_eq!(fix("Run `con- tinued` now."), "Run `con- tinued` now.");
+        assert_eq!(fix("```\ncon-\ntinued\n```\n"), "```\ncon-\ntinued\n```\n");
+        assert_eq!(fix("# Head-\ning"), "# Head-\ning");
+        assert_eq!(fix("- item con-\n- tinued"), "- item con-\n- tinued");
+    }
+
+    #[test]
+    fn paragraphs_split_mid_sentence_are_rejoined() {
+        let mut dehyphenator = Dehyphenator::default();
+        assert_eq!(dehyphenator.fix("He walked into the\n\nroom and sat.\n\nThen he left."), "He walked into the room and sat.\n\nThen he left.");
+        assert_eq!(dehyphenator.paragraphs, 1);
+        assert_eq!(fix("He sat.\n\nthen he left."), "He sat.\n\nthen he left.");
+    }
+}
@@ -0,0 +1,145 @@
+use crate::digest::Sha256;
+use crate::license::FileSyntax;
+use crate::output::{self, ChapterFiles};
+use crate::toc::{self, SlugSet};
+use crate::BookMetadata;
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Top of the hierarchy every converted book goes under
+const ROOT: &str = "book";
+
+/// Writer for `--dendron`. The book becomes a subtree of hierarchical notes:
+/// `book.<title>` for the book, `book.<title>.ch001` for each chapter and
+/// `book.<title>.ch001.<section>` for each of the chapter's sections. Every note
+/// starts with the `id`/`title`/`created`/`updated` front matter Dendron indexes (and md-roam
+/// reads for org-roam). Ids are derived from the book and note names, so links into the
+/// vault survive reconverting the book.
+pub struct DendronWriter {
+    output_dir: PathBuf,
+    /// Hierarchy prefix of the book, `book.<title>`
+    book: String,
+    seed: String,
+    /// Milliseconds since the epoch, from the EPUB's modification time
+    time: u128,
+    chapters: Vec<(String, String)>,
+    files: ChapterFiles,
+}
+
+impl DendronWriter {
+    pub fn new(output_dir: &Path, title: &str, seed: &str, modified: SystemTime, files: ChapterFiles) -> Self {
+        Self {
+            output_dir: output_dir.to_path_buf(),
+            book: format!("{}.{}", ROOT, segment(title, "untitled")),
+            seed: seed.to_string(),
+            time: modified.duration_since(SystemTime::UNIX_EPOCH).map(|d| d.as_millis()).unwrap_or(0),
+            chapters: Vec::new(),
+            files,
+        }
+    }
+
+    /// Write a chapter note and a child note per section. Returns the files written,
+    /// relative to the output directory, the chapter note first.
+    pub fn write_chapter(&mut self, number: usize, width: usize, markdown: &str) -> Result<Vec<String>> {
+        let chapter = format!("{}.ch{:0width$}", self.book, number, width = width);
+        let markdown = toc::shift_headings(markdown, 0);
+        let headings = toc::heading_lines(&markdown);
+        let lines: Vec<&str> = markdown.lines().collect();
+        let title = headings.first().map(|(_, _, text)| text.clone()).unwrap_or_else(|| format!("Chapter {}", number));
+
+        // Split at each heading one level below the chapter's top heading; what comes
+        // before the first stays in the chapter note
+        let section_level = headings.iter().map(|(_, level, _)| level + 1).min();
+        let starts: Vec<(usize, String)> = headings
+            .iter()
+            .filter(|(_, level, _)| Some(*level) == section_level)
+            .map(|(line, _, text)| (*line, text.clone()))
+            .collect();
+        let intro_end = starts.first().map(|(line, _)| *line).unwrap_or(lines.len());
+
+        let mut slugs = SlugSet::default();
+        let mut sections = Vec::with_capacity(starts.len());
+        for (n, (start, text)) in starts.iter().enumerate() {
+            let end = starts.get(n + 1).map(|(line, _)| *line).unwrap_or(lines.len());
+            let name = format!("{}.{}", chapter, slugs.claim(&segment(text, &format!("section-{}", n + 1))));
+            sections.push((name, text.clone(), lines[*start..end].join("\n")));
+        }
+
+        let mut intro = lines[..intro_end].join("\n").trim_end().to_string();
+        if !sections.is_empty() {
+            intro.push_str("\n\n");
+            for (name, text, _) in &sections {
+                intro.push_str(&format!("- [[{}|{}]]\n", text, name));
+            }
+        }
+        let mut files = vec![self.write_chapter_note(&chapter, &title, &intro)?];
+        for (name, text, body) in &sections {
+            files.push(self.write_chapter_note(name, text, body)?);
+        }
+        self.chapters.push((chapter, title));
+        Ok(files)
+    }
+
+    /// Write the book's own note, linking to every chapter, and return its path
+    pub fn finish(self, metadata: &BookMetadata) -> Result<PathBuf> {
+        let title = metadata.title.clone().unwrap_or_else(|| "Unknown Title".to_string());
+        let mut body = String::new();
+        if !metadata.creators.is_empty() {
+            body.push_str(&format!("**Author:** {}\n\n", metadata.creators.join(", ")));
+        }
+        if let Some(description) = metadata.description.as_deref().filter(|d| !d.trim().is_empty()) {
+            body.push_str(&format!("{}\n\n", description.trim()));
+        }
+        for (name, title) in &self.chapters {
+            body.push_str(&format!("- [[{}|{}]]\n", title, name));
+        }
+        let text = self.note(&self.book, &title, &metadata.tags, &body);
+        let file = format!("{}.md", self.book);
+        let path = self.output_dir.join(&file);
+        output::write_atomic(&path, self.files.newline.apply(&text).as_bytes()).context(format!("Failed to write {}", file))?;
+        Ok(path)
+    }
+
+    /// Write a chapter or section note, returning its file name
+    fn write_chapter_note(&self, name: &str, title: &str, body: &str) -> Result<String> {
+        let file = format!("{}.md", name);
+        self.files
+            .write(&self.output_dir.join(&file), FileSyntax::FrontMatter, &self.note(name, title, &[], body))
+            .context(format!("Failed to write {}", file))?;
+        Ok(file)
+    }
+
+    fn note(&self, name: &str, title: &str, tags: &[String], body: &str) -> String {
+        // A JSON string is a valid double-quoted YAML scalar, and a JSON array a flow sequence
+        let quoted = serde_json::to_string(title).unwrap_or_default();
+        let tags = if tags.is_empty() { String::new() } else { format!("tags: {}\n", serde_json::to_string(tags).unwrap_or_default()) };
+        format!(
+            "---\nid: {}\ntitle: {}\ndesc: ''\nupdated: {}\ncreated: {}\n{}---\n\n{}\n",
+            note_id(&self.seed, name),
+            quoted,
+            self.time,
+            self.time,
+            tags,
+            body.trim()
+        )
+    }
+}
+
+/// One hierarchy level: a heading slug, with the dots that separate levels removed
+fn segment(text: &str, fallback: &str) -> String {
+    let slug = toc::github_slug(&text.replace('.', " "));
+    let slug = slug.trim_matches('-');
+    if slug.is_empty() { fallback.to_string() } else { slug.to_string() }
+}
+
+/// A 23-character lowercase alphanumeric id like the ones Dendron generates, stable for
+/// the same book and note
+fn note_id(seed: &str, name: &str) -> String {
+    const ALPHABET: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyz";
+    let mut hasher = Sha256::default();
+    hasher.update(seed.as_bytes());
+    hasher.update(&[0]);
+    hasher.update(name.as_bytes());
+    hasher.finalize().iter().take(23).map(|b| ALPHABET[*b as usize % ALPHABET.len()] as char).collect()
+}
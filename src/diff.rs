@@ -0,0 +1,324 @@
+use crate::check::ScratchDir;
+use crate::saved::SavedBook;
+use crate::toc;
+use crate::ConvertOptions;
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+
+/// How `diff` reports the changes between two editions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum DiffFormat {
+    /// Unified diff of the Markdown, one file section per chapter
+    Unified,
+    /// JSON summary of which chapters changed and by how many lines
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum ChapterStatus {
+    Unchanged,
+    Modified,
+    Added,
+    Removed,
+}
+
+/// One aligned pair of chapters in the JSON summary
+#[derive(Debug, Serialize)]
+struct ChapterChange {
+    status: ChapterStatus,
+    /// Chapter file in the old edition's output
+    #[serde(skip_serializing_if = "Option::is_none")]
+    old_file: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    new_file: Option<String>,
+    /// Path of the source XHTML document, from the new edition where there is one
+    source: String,
+    lines_added: usize,
+    lines_removed: usize,
+}
+
+#[derive(Debug, Default, Serialize)]
+struct Totals {
+    unchanged: usize,
+    modified: usize,
+    added: usize,
+    removed: usize,
+    lines_added: usize,
+    lines_removed: usize,
+}
+
+#[derive(Debug, Serialize)]
+struct DiffSummary {
+    old: String,
+    new: String,
+    chapters: Vec<ChapterChange>,
+    totals: Totals,
+}
+
+/// A converted chapter of one edition
+struct Chapter {
+    file: String,
+    source: String,
+    markdown: String,
+}
+
+impl Chapter {
+    fn heading(&self) -> Option<String> {
+        toc::headings(&self.markdown).into_iter().next().map(|(_, text)| text)
+    }
+}
+
+/// Convert both editions into scratch directories and print how their chapters differ.
+/// Chapters are aligned by source document, then by first heading, so a renamed file
+/// with the same chapter in it still diffs against its counterpart.
+pub fn diff_editions(old: &Path, new: &Path, options: &ConvertOptions, format: DiffFormat, context: usize) -> Result<()> {
+    let old_chapters = convert(old, options)?;
+    let new_chapters = convert(new, options)?;
+    let pairs = align(&old_chapters, &new_chapters);
+
+    let mut summary = DiffSummary {
+        old: old.display().to_string(),
+        new: new.display().to_string(),
+        chapters: Vec::with_capacity(pairs.len()),
+        totals: Totals::default(),
+    };
+    let mut unified = String::new();
+    for (old, new) in pairs {
+        // Lines keep their newline, so a missing one at the end of a file counts as a change
+        let before: Vec<&str> = old.map(|c| c.markdown.split_inclusive('\n').collect()).unwrap_or_default();
+        let after: Vec<&str> = new.map(|c| c.markdown.split_inclusive('\n').collect()).unwrap_or_default();
+        let ops = diff_lines(&before, &after);
+        let lines_added = ops.iter().filter(|op| matches!(op, Op::Insert(_))).count();
+        let lines_removed = ops.iter().filter(|op| matches!(op, Op::Delete(_))).count();
+        let status = match (old, new) {
+            (None, _) => ChapterStatus::Added,
+            (_, None) => ChapterStatus::Removed,
+            _ if lines_added + lines_removed == 0 => ChapterStatus::Unchanged,
+            _ => ChapterStatus::Modified,
+        };
+
+        if status != ChapterStatus::Unchanged {
+            let label = |chapter: Option<&Chapter>, side: &str| {
+                chapter.map_or("/dev/null".to_string(), |c| format!("{}/{}", side, c.file))
+            };
+            unified.push_str(&format!("--- {}\n+++ {}\n", label(old, "a"), label(new, "b")));
+            unified.push_str(&hunks(&ops, &before, &after, context));
+        }
+
+        let totals = &mut summary.totals;
+        match status {
+            ChapterStatus::Unchanged => totals.unchanged += 1,
+            ChapterStatus::Modified => totals.modified += 1,
+            ChapterStatus::Added => totals.added += 1,
+            ChapterStatus::Removed => totals.removed += 1,
+        }
+        totals.lines_added += lines_added;
+        totals.lines_removed += lines_removed;
+        summary.chapters.push(ChapterChange {
+            status,
+            old_file: old.map(|c| c.file.clone()),
+            new_file: new.map(|c| c.file.clone()),
+            source: new.or(old).map(|c| c.source.clone()).unwrap_or_default(),
+            lines_added,
+            lines_removed,
+        });
+    }
+
+    match format {
+        DiffFormat::Unified => print!("{}", unified),
+        DiffFormat::Json => {
+            let json = serde_json::to_string_pretty(&summary).context("Failed to serialize diff summary")?;
+            println!("{}", json);
+        }
+    }
+    Ok(())
+}
+
+fn convert(epub_path: &Path, options: &ConvertOptions) -> Result<Vec<Chapter>> {
+    let scratch = ScratchDir::new("diff")?;
+    crate::convert_epub_to_markdown(epub_path, scratch.path(), options)
+        .with_context(|| format!("Failed to convert {}", epub_path.display()))?;
+    let book = SavedBook::load(scratch.path())?;
+    book.chapters
+        .into_iter()
+        .map(|chapter| {
+            let path = scratch.path().join(&chapter.file);
+            let markdown = fs::read_to_string(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+            Ok(Chapter { file: chapter.file, source: chapter.source, markdown })
+        })
+        .collect()
+}
+
+/// Pair up the chapters of two editions in the new edition's reading order, with
+/// chapters only in the old edition placed where they used to be
+fn align<'a>(old: &'a [Chapter], new: &'a [Chapter]) -> Vec<(Option<&'a Chapter>, Option<&'a Chapter>)> {
+    let mut matched: Vec<Option<usize>> = vec![None; new.len()];
+    let mut taken = vec![false; old.len()];
+    for (i, chapter) in new.iter().enumerate() {
+        if let Some(j) = (0..old.len()).find(|&j| !taken[j] && old[j].source == chapter.source) {
+            matched[i] = Some(j);
+            taken[j] = true;
+        }
+    }
+    for (i, chapter) in new.iter().enumerate() {
+        let Some(heading) = chapter.heading().filter(|_| matched[i].is_none()) else { continue };
+        if let Some(j) = (0..old.len()).find(|&j| !taken[j] && old[j].heading().as_ref() == Some(&heading)) {
+            matched[i] = Some(j);
+            taken[j] = true;
+        }
+    }
+
+    // Chapters of the old edition in `range` that have no counterpart
+    let removed = |range: std::ops::Range<usize>| {
+        old[range.clone()]
+            .iter()
+            .zip(&taken[range])
+            .filter(|(_, &taken)| !taken)
+            .map(|(chapter, _)| (Some(chapter), None))
+    };
+    let mut pairs = Vec::with_capacity(new.len() + old.len());
+    let mut next_old = 0;
+    for (i, chapter) in new.iter().enumerate() {
+        if let Some(j) = matched[i] {
+            // Removed chapters that came before this one in the old edition
+            if j > next_old {
+                pairs.extend(removed(next_old..j));
+            }
+            next_old = next_old.max(j + 1);
+            pairs.push((Some(&old[j]), Some(chapter)));
+        } else {
+            pairs.push((None, Some(chapter)));
+        }
+    }
+    pairs.extend(removed(next_old..old.len()));
+    pairs
+}
+
+/// One step of an edit script. `Equal` and `Delete` index the old lines, `Insert` the new.
+#[derive(Debug, Clone, Copy)]
+enum Op {
+    Equal(usize),
+    Delete(usize),
+    Insert(usize),
+}
+
+/// Shortest edit script between two sequences of lines (Myers' algorithm). Only the
+/// diagonals reachable at each step are kept, so memory grows with the square of the
+/// number of differences rather than with the size of the chapters.
+fn diff_lines(a: &[&str], b: &[&str]) -> Vec<Op> {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let max = n + m;
+    let offset = max + 1;
+    let mut v = vec![0isize; 2 * offset as usize + 1];
+    let at = |k: isize| (k + offset) as usize;
+    // trace[d] holds the furthest x on diagonals -d..=d after d edits
+    let mut trace: Vec<Vec<isize>> = Vec::new();
+
+    'search: for d in 0..=max {
+        for k in (-d..=d).step_by(2) {
+            let mut x = if k == -d || (k != d && v[at(k - 1)] < v[at(k + 1)]) { v[at(k + 1)] } else { v[at(k - 1)] + 1 };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[at(k)] = x;
+            if x >= n && y >= m {
+                trace.push(v[at(-d)..=at(d)].to_vec());
+                break 'search;
+            }
+        }
+        trace.push(v[at(-d)..=at(d)].to_vec());
+    }
+
+    // Walk back from the end, one edit at a time
+    let mut ops = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for d in (1..trace.len() as isize).rev() {
+        let previous = &trace[d as usize - 1];
+        let before = |k: isize| previous[(k + d - 1) as usize];
+        let k = x - y;
+        let down = k == -d || (k != d && before(k - 1) < before(k + 1));
+        let prev_k = if down { k + 1 } else { k - 1 };
+        let prev_x = before(prev_k);
+        let prev_y = prev_x - prev_k;
+        while x > prev_x && y > prev_y {
+            x -= 1;
+            y -= 1;
+            ops.push(Op::Equal(x as usize));
+        }
+        if down {
+            ops.push(Op::Insert(prev_y as usize));
+        } else {
+            ops.push(Op::Delete(prev_x as usize));
+        }
+        x = prev_x;
+        y = prev_y;
+    }
+    while x > 0 && y > 0 {
+        x -= 1;
+        y -= 1;
+        ops.push(Op::Equal(x as usize));
+    }
+    ops.reverse();
+    ops
+}
+
+/// Unified diff hunks for an edit script, with `context` unchanged lines around each change
+fn hunks(ops: &[Op], a: &[&str], b: &[&str], context: usize) -> String {
+    let changes: Vec<usize> = (0..ops.len()).filter(|&i| !matches!(ops[i], Op::Equal(_))).collect();
+    let mut out = String::new();
+    let mut i = 0;
+    while i < changes.len() {
+        // Extend the hunk while the next change is close enough to share context
+        let start = changes[i].saturating_sub(context);
+        let mut end = changes[i];
+        while i + 1 < changes.len() && changes[i + 1] - end <= 2 * context + 1 {
+            i += 1;
+            end = changes[i];
+        }
+        let end = (end + context + 1).min(ops.len());
+        i += 1;
+
+        // Line numbers are where the hunk starts in each file, 1-based
+        let (mut old_start, mut new_start) = (0, 0);
+        for op in &ops[..start] {
+            match op {
+                Op::Equal(_) => {
+                    old_start += 1;
+                    new_start += 1;
+                }
+                Op::Delete(_) => old_start += 1,
+                Op::Insert(_) => new_start += 1,
+            }
+        }
+        let old_len = ops[start..end].iter().filter(|op| !matches!(op, Op::Insert(_))).count();
+        let new_len = ops[start..end].iter().filter(|op| !matches!(op, Op::Delete(_))).count();
+        // An empty range is numbered by the line before it
+        let number = |start: usize, len: usize| if len == 0 { start } else { start + 1 };
+        out.push_str(&format!(
+            "@@ -{},{} +{},{} @@\n",
+            number(old_start, old_len),
+            old_len,
+            number(new_start, new_len),
+            new_len
+        ));
+        for op in &ops[start..end] {
+            let (marker, line) = match *op {
+                Op::Equal(x) => (' ', a[x]),
+                Op::Delete(x) => ('-', a[x]),
+                Op::Insert(y) => ('+', b[y]),
+            };
+            out.push(marker);
+            out.push_str(line);
+            if !line.ends_with('\n') {
+                out.push_str("\n\\ No newline at end of file\n");
+            }
+        }
+    }
+    out
+}
@@ -0,0 +1,90 @@
+use serde::Serialize;
+use std::io;
+use thiserror::Error;
+
+/// Why a book failed to convert, for callers that act on the kind of failure rather than
+/// its message. Conversions still return `anyhow::Error`, with one of these somewhere in
+/// its chain when the cause is known; `ErrorReport::of` digs it out.
+#[derive(Debug, Error)]
+pub enum ConvertError {
+    #[error("The book is protected by DRM ({scheme}) and can't be converted")]
+    DrmProtected { scheme: String },
+    #[error("The EPUB archive is corrupt: {reason}")]
+    CorruptArchive { reason: String },
+    #[error("{href} is listed in the package but missing from the archive")]
+    MissingResource { href: String },
+    #[error("Failed to render chapter {chapter}: {reason}")]
+    RenderFailed { chapter: String, reason: String },
+    #[error("{output} is already being converted by {owner}")]
+    OutputLocked { output: String, owner: String },
+    #[error("Interrupted by {signal}")]
+    Interrupted { signal: &'static str },
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+impl From<zip::result::ZipError> for ConvertError {
+    fn from(error: zip::result::ZipError) -> Self {
+        match error {
+            zip::result::ZipError::Io(e) => ConvertError::Io(e),
+            e => ConvertError::CorruptArchive { reason: e.to_string() },
+        }
+    }
+}
+
+impl From<epub::doc::DocError> for ConvertError {
+    fn from(error: epub::doc::DocError) -> Self {
+        use epub::archive::ArchiveError;
+        use epub::doc::DocError;
+        match error {
+            DocError::IOError(e) | DocError::ArchiveError(ArchiveError::IO(e)) => ConvertError::Io(e),
+            DocError::ArchiveError(ArchiveError::Zip(e)) => e.into(),
+            e => ConvertError::CorruptArchive { reason: e.to_string() },
+        }
+    }
+}
+
+/// The kind of a failure, as named in `error` progress events
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    DrmProtected,
+    CorruptArchive,
+    MissingResource,
+    RenderFailed,
+    /// Another run is writing into the book's output directory
+    OutputLocked,
+    /// The run was stopped with Ctrl-C or SIGTERM
+    Interrupted,
+    Io,
+    /// Anything else: limits, timeouts, cancellation, bad options
+    Other,
+}
+
+/// What is known about a failure beyond its message
+#[derive(Debug)]
+pub struct ErrorReport<'a> {
+    pub kind: ErrorKind,
+    /// Archive path of the missing resource
+    pub href: Option<&'a str>,
+    /// Archive path of the chapter that failed to render
+    pub chapter: Option<&'a str>,
+}
+
+impl<'a> ErrorReport<'a> {
+    pub fn of(error: &'a anyhow::Error) -> Self {
+        let known = error.chain().find_map(|cause| cause.downcast_ref::<ConvertError>());
+        let (kind, href, chapter) = match known {
+            Some(ConvertError::DrmProtected { .. }) => (ErrorKind::DrmProtected, None, None),
+            Some(ConvertError::CorruptArchive { .. }) => (ErrorKind::CorruptArchive, None, None),
+            Some(ConvertError::MissingResource { href }) => (ErrorKind::MissingResource, Some(href.as_str()), None),
+            Some(ConvertError::RenderFailed { chapter, .. }) => (ErrorKind::RenderFailed, None, Some(chapter.as_str())),
+            Some(ConvertError::OutputLocked { .. }) => (ErrorKind::OutputLocked, None, None),
+            Some(ConvertError::Interrupted { .. }) => (ErrorKind::Interrupted, None, None),
+            Some(ConvertError::Io(_)) => (ErrorKind::Io, None, None),
+            None if error.chain().any(|cause| cause.is::<io::Error>()) => (ErrorKind::Io, None, None),
+            None => (ErrorKind::Other, None, None),
+        };
+        Self { kind, href, chapter }
+    }
+}
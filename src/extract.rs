@@ -0,0 +1,53 @@
+use crate::archive::{ResourceLimits, Resources};
+use crate::output;
+use anyhow::{Context, Result};
+use epub::doc::EpubDoc;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Directory under the book's output directory that resources are unpacked into
+pub const RESOURCES_DIR: &str = "resources";
+
+/// Unpack the EPUB's manifest resources into `resources/` beneath `output_dir`, keeping
+/// their paths inside the archive. Without `all`, the spine's XHTML documents are left
+/// out, since the Markdown already covers them; with it, the package document is
+/// included too. Unlike a plain unzip, only files the OPF declares are written, and
+/// paths that would escape the output directory are refused. What is decompressed is
+/// counted against `limits`, as in a conversion.
+pub fn extract_resources(epub_path: &Path, output_dir: &Path, all: bool, limits: ResourceLimits) -> Result<()> {
+    limits.check_archive(epub_path)?;
+    let doc = EpubDoc::new(epub_path).context("Failed to open EPUB file")?;
+    let mut resources = Resources::open(epub_path, limits)?;
+
+    let mut paths: Vec<PathBuf> = doc
+        .resources
+        .iter()
+        .filter(|(id, _)| all || !doc.spine.iter().any(|item| &item.idref == *id))
+        .map(|(_, r)| r.path.clone())
+        .collect();
+    if all {
+        paths.push(doc.root_file.clone());
+    }
+    paths.sort();
+    paths.dedup();
+
+    let root = output_dir.join(RESOURCES_DIR);
+    fs::create_dir_all(&root).context("Failed to create resources directory")?;
+    let mut written = 0;
+    for path in paths {
+        let href = path.to_string_lossy().replace('\\', "/");
+        let Some(content) = resources.read(&href)? else {
+            eprintln!("  Skipping {}: declared in the manifest but missing from the archive", href);
+            continue;
+        };
+        // Manifest paths come from the EPUB, so treat them as untrusted
+        let target = output::resource_path(&root, &href)
+            .with_context(|| format!("Refusing to extract {}", href))?;
+        output::write_atomic(&target, &content)
+            .with_context(|| format!("Failed to write {}", target.display()))?;
+        written += 1;
+    }
+
+    println!("Extracted {} resource(s) to {}", written, root.display());
+    Ok(())
+}
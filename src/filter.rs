@@ -0,0 +1,84 @@
+/// How a filter compares a metadata field against its value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    /// `field=value` or `field:value`: case-insensitive equality (whole words for authors)
+    Equals,
+    /// `field~=value`: case-insensitive substring
+    Contains,
+}
+
+/// A `field=value` / `field~=value` condition on book metadata
+#[derive(Debug, Clone)]
+pub struct MetadataFilter {
+    field: String,
+    op: Op,
+    value: String,
+}
+
+/// Fields a filter may name, with the aliases accepted for each
+const FIELDS: &[(&str, &[&str])] = &[
+    ("title", &["title"]),
+    ("author", &["author", "creator", "authors"]),
+    ("language", &["language", "lang"]),
+    ("subject", &["subject", "subjects", "tag", "category"]),
+    ("publisher", &["publisher"]),
+    ("identifier", &["identifier", "id", "isbn"]),
+];
+
+impl MetadataFilter {
+    /// Parse `field=value`, `field:value` or `field~=value`
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let (field, op, value) = if let Some((field, value)) = spec.split_once("~=") {
+            (field, Op::Contains, value)
+        } else if let Some(i) = spec.find(['=', ':']) {
+            (&spec[..i], Op::Equals, &spec[i + 1..])
+        } else {
+            return Err(format!("invalid filter '{}' (expected field=value or field~=value)", spec));
+        };
+
+        let field = field.trim().to_ascii_lowercase();
+        let canonical = FIELDS
+            .iter()
+            .find(|(_, aliases)| aliases.contains(&field.as_str()))
+            .map(|(name, _)| name.to_string())
+            .ok_or_else(|| {
+                let names: Vec<&str> = FIELDS.iter().map(|(name, _)| *name).collect();
+                format!("unknown filter field '{}' (use {})", field, names.join(", "))
+            })?;
+
+        Ok(Self { field: canonical, op, value: value.trim().to_lowercase() })
+    }
+
+    /// Whether any of the values `lookup` returns for this filter's field match
+    pub fn matches<'a>(&self, lookup: &dyn Fn(&str) -> Vec<&'a str>) -> bool {
+        lookup(&self.field).iter().any(|candidate| {
+            let candidate = candidate.trim().to_lowercase();
+            match self.op {
+                Op::Contains => candidate.contains(&self.value),
+                // `language=en` also matches regional tags like en-US
+                Op::Equals if self.field == "language" => {
+                    candidate == self.value || candidate.starts_with(&format!("{}-", self.value))
+                }
+                // `author=Tolstoy` matches "Leo Tolstoy" and "Tolstoy, Leo", but not "Tolstoyan"
+                Op::Equals if self.field == "author" => {
+                    candidate == self.value || contains_words(&candidate, &self.value)
+                }
+                Op::Equals => candidate == self.value,
+            }
+        })
+    }
+}
+
+/// Whether a book passes every filter
+pub fn matches_all<'a>(filters: &[MetadataFilter], lookup: &dyn Fn(&str) -> Vec<&'a str>) -> bool {
+    filters.iter().all(|f| f.matches(lookup))
+}
+
+/// Whether the words of `needle` appear consecutively among the words of `haystack`
+fn contains_words(haystack: &str, needle: &str) -> bool {
+    let words = |s: &str| -> Vec<String> {
+        s.split(|c: char| !c.is_alphanumeric()).filter(|w| !w.is_empty()).map(str::to_string).collect()
+    };
+    let (haystack, needle) = (words(haystack), words(needle));
+    !needle.is_empty() && haystack.windows(needle.len()).any(|w| w == needle.as_slice())
+}
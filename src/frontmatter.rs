@@ -0,0 +1,59 @@
+use clap::ValueEnum;
+
+/// Syntax of the front-matter blocks written at the top of output files
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum FrontMatterFormat {
+    /// Between `---` lines, as Jekyll, Obsidian and most tools read it
+    #[default]
+    Yaml,
+    /// Between `+++` lines, as Hugo and Zola read it
+    Toml,
+    /// A JSON object, as Hugo reads it
+    Json,
+}
+
+/// A front-matter block holding `fields` in order, followed by a blank line
+pub fn block(format: FrontMatterFormat, fields: &[(&str, &str)]) -> String {
+    match format {
+        FrontMatterFormat::Yaml => {
+            let mut out = String::from("---\n");
+            for (key, value) in fields {
+                out.push_str(&format!("{}: {}\n", key, yaml_scalar(value)));
+            }
+            out.push_str("---\n\n");
+            out
+        }
+        FrontMatterFormat::Toml => {
+            let mut out = String::from("+++\n");
+            for (key, value) in fields {
+                // A JSON string is a valid TOML basic string
+                out.push_str(&format!("{} = {}\n", key, quoted(value)));
+            }
+            out.push_str("+++\n\n");
+            out
+        }
+        FrontMatterFormat::Json => {
+            let entries: Vec<String> = fields.iter().map(|(key, value)| format!("  {}: {}", quoted(key), quoted(value))).collect();
+            format!("{{\n{}\n}}\n\n", entries.join(",\n"))
+        }
+    }
+}
+
+/// The value as a plain YAML scalar when it is a simple token such as an SPDX
+/// identifier, and double-quoted otherwise
+fn yaml_scalar(value: &str) -> String {
+    let plain = value.starts_with(|c: char| c.is_ascii_alphanumeric())
+        && value.chars().any(|c| c.is_ascii_alphabetic())
+        && value.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '.' | '+'))
+        && !matches!(value.to_ascii_lowercase().as_str(), "true" | "false" | "yes" | "no" | "on" | "off" | "null");
+    if plain {
+        value.to_string()
+    } else {
+        // A JSON string is a valid double-quoted YAML scalar
+        quoted(value)
+    }
+}
+
+fn quoted(value: &str) -> String {
+    serde_json::to_string(value).unwrap_or_default()
+}
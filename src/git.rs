@@ -0,0 +1,90 @@
+use crate::saved::SavedBook;
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+/// Identity used for commits when git has none configured
+const FALLBACK_NAME: &str = "epub-to-md";
+const FALLBACK_EMAIL: &str = "epub-to-md@localhost";
+
+/// `--git`: a repository at the output root that gets a commit for each converted book
+pub struct Library {
+    root: PathBuf,
+    /// `-c` settings giving commits an author when git has none configured
+    identity: Vec<String>,
+}
+
+impl Library {
+    /// Use the repository at `root`, creating it (and `root`) if there isn't one. A
+    /// repository further up doesn't count, so the library gets a history of its own.
+    pub fn open(root: &Path) -> Result<Self> {
+        std::fs::create_dir_all(root).with_context(|| format!("Failed to create {}", root.display()))?;
+        let root = root.canonicalize().with_context(|| format!("Failed to resolve {}", root.display()))?;
+        let mut library = Self { root, identity: Vec::new() };
+        if !library.root.join(".git").exists() {
+            library.git(&["init", "--quiet"])?;
+        }
+        let configured = |key: &str| library.run(&["config", key]).is_ok_and(|o| o.status.success() && !o.stdout.trim_ascii().is_empty());
+        if !configured("user.name") || !configured("user.email") {
+            library.identity = vec![format!("user.name={}", FALLBACK_NAME), format!("user.email={}", FALLBACK_EMAIL)];
+        }
+        Ok(library)
+    }
+
+    /// Commit everything under `paths` and return whether there was anything to commit;
+    /// re-converting a book to identical output adds nothing
+    pub fn commit(&self, paths: &[PathBuf], message: &str) -> Result<bool> {
+        let mut pathspecs = vec!["--".to_string()];
+        for path in paths {
+            let absolute = path.canonicalize().with_context(|| format!("Failed to resolve {}", path.display()))?;
+            let relative = absolute
+                .strip_prefix(&self.root)
+                .with_context(|| format!("{} is outside the repository at {}", path.display(), self.root.display()))?;
+            pathspecs.push(if relative.as_os_str().is_empty() { ".".to_string() } else { relative.to_string_lossy().into_owned() });
+        }
+        let pathspecs: Vec<&str> = pathspecs.iter().map(String::as_str).collect();
+
+        self.git(&[&["add", "--all"], pathspecs.as_slice()].concat())?;
+        let staged = self.run(&[&["diff", "--cached", "--quiet"], pathspecs.as_slice()].concat())?;
+        if staged.status.success() {
+            return Ok(false);
+        }
+        self.git(&[&["commit", "--quiet", "--no-verify", "--message", message], pathspecs.as_slice()].concat())?;
+        Ok(true)
+    }
+
+    fn run(&self, args: &[&str]) -> Result<Output> {
+        let mut command = Command::new("git");
+        command.arg("-C").arg(&self.root);
+        for setting in &self.identity {
+            command.args(["-c", setting]);
+        }
+        command.args(args).output().context("Failed to run git")
+    }
+
+    fn git(&self, args: &[&str]) -> Result<()> {
+        let output = self.run(args)?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+            anyhow::bail!("git {} failed in {}: {}", args[0], self.root.display(), stderr);
+        }
+        Ok(())
+    }
+}
+
+/// Commit message for a converted book: its title and authors, then its identifier and
+/// the version of the converter, so re-conversions can be told apart in the history. The
+/// metadata is the book's metadata.json, as `--set` and Calibre corrected it.
+pub fn book_message(epub_path: &Path, book: Option<&SavedBook>) -> String {
+    let fallback = crate::input::EpubExtensions::default().stem(epub_path);
+    let Some(book) = book else { return fallback };
+    let mut message = book.title.as_deref().map(str::trim).filter(|t| !t.is_empty()).map_or(fallback, str::to_string);
+    if !book.creators.is_empty() {
+        message.push_str(&format!(" by {}", book.creators.join(", ")));
+    }
+    if let Some(identifier) = &book.identifier {
+        message.push_str(&format!("\n\nIdentifier: {}", identifier));
+    }
+    message.push_str(&format!("\n\nConverted from {} by epub-to-md {}", epub_path.file_name().unwrap_or_default().to_string_lossy(), env!("CARGO_PKG_VERSION")));
+    message
+}
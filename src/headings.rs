@@ -0,0 +1,208 @@
+use crate::archive::Resources;
+use crate::html;
+use anyhow::Result;
+use epub::doc::EpubDoc;
+use markup5ever_rcdom::{Handle, NodeData};
+use std::collections::HashMap;
+use std::io::{Read, Seek};
+use std::rc::Rc;
+
+/// Font size, relative to the body text, from which a class is taken for a heading
+const MIN_HEADING_SIZE: f64 = 1.25;
+
+/// Longest paragraph text that is promoted; anything longer is body text set large
+const MAX_HEADING_CHARS: usize = 100;
+
+/// Elements older books set their headings in
+const CANDIDATE_TAGS: &[&str] = &["p", "div"];
+
+/// Class names conversion tools and publishers commonly give heading paragraphs, and the
+/// level each stands for, compared with `-` and `_` removed
+const HEADING_CLASSES: &[(&str, u8)] = &[
+    ("parttitle", 1),
+    ("parthead", 1),
+    ("chapterhead", 1),
+    ("chapterheading", 1),
+    ("chaptertitle", 1),
+    ("chaptitle", 1),
+    ("chaphead", 1),
+    ("ct", 1),
+    ("h1", 1),
+    ("head1", 1),
+    ("heading1", 1),
+    ("subhead", 2),
+    ("subheading", 2),
+    ("sectiontitle", 2),
+    ("sectionhead", 2),
+    ("h2", 2),
+    ("head2", 2),
+    ("heading2", 2),
+    ("h3", 3),
+    ("head3", 3),
+    ("heading3", 3),
+];
+
+/// The classes a book's stylesheets set large enough to be headings, and the level each
+/// one's size suggests
+#[derive(Debug, Default)]
+pub struct HeadingStyles {
+    classes: HashMap<String, u8>,
+}
+
+impl HeadingStyles {
+    /// Read the `font-size` of every class rule in the book's stylesheets
+    pub fn detect<R: Read + Seek>(doc: &EpubDoc<R>, resources: &mut Resources) -> Result<Self> {
+        let stylesheets: Vec<_> = doc.resources.values().filter(|r| r.mime == "text/css").map(|r| r.path.clone()).collect();
+        let mut styles = HeadingStyles::default();
+        for path in stylesheets {
+            let Some(css) = resources.read_string(&path.to_string_lossy())? else { continue };
+            styles.add_stylesheet(&css);
+        }
+        Ok(styles)
+    }
+
+    fn add_stylesheet(&mut self, css: &str) {
+        let css = strip_comments(css);
+        for rule in css.split('}') {
+            let Some((selectors, declarations)) = rule.rsplit_once('{') else { continue };
+            // Nested at-rules (`@media ... {`) leave their prelude before the selector
+            let selectors = selectors.rsplit(['{', ';']).next().unwrap_or_default();
+            let Some(size) = font_size(declarations) else { continue };
+            for selector in selectors.split(',') {
+                let Some(class) = subject_class(selector) else { continue };
+                if size >= MIN_HEADING_SIZE {
+                    self.classes.insert(class, size_level(size));
+                } else {
+                    // A later rule setting the class back to body size wins
+                    self.classes.remove(&class);
+                }
+            }
+        }
+    }
+
+    /// Level of an element's heading class: one the stylesheets set large, or else one
+    /// named like a heading
+    fn level(&self, classes: &str) -> Option<u8> {
+        let classes: Vec<&str> = classes.split_whitespace().collect();
+        classes.iter().find_map(|c| self.classes.get(&c.to_ascii_lowercase()).copied()).or_else(|| {
+            classes.iter().find_map(|c| {
+                let name: String = c.chars().filter(|c| !matches!(c, '-' | '_')).collect::<String>().to_ascii_lowercase();
+                HEADING_CLASSES.iter().find(|(class, _)| *class == name).map(|(_, level)| *level)
+            })
+        })
+    }
+}
+
+/// Chapter XHTML with its styled heading paragraphs turned into `<h1>`-`<h3>`, and how
+/// many were. Only chapters without real headings are touched, and only short paragraphs
+/// holding no other blocks; None when nothing was promoted.
+pub fn promote(content: &str, styles: &HeadingStyles) -> Option<(String, usize)> {
+    let dom = html::parse(content);
+    let mut has_headings = false;
+    let mut blocks = 0;
+    let mut class_counts: HashMap<String, usize> = HashMap::new();
+    let mut candidates: Vec<(Handle, String, u8)> = Vec::new();
+    html::walk(&dom.document, &mut |node| {
+        let Some(tag) = html::tag_name(node) else { return };
+        if matches!(tag.as_str(), "h1" | "h2" | "h3" | "h4" | "h5" | "h6") {
+            has_headings = true;
+        }
+        if !CANDIDATE_TAGS.contains(&tag.as_str()) {
+            return;
+        }
+        blocks += 1;
+        let Some(classes) = html::attr(node, "class") else { return };
+        *class_counts.entry(classes.clone()).or_default() += 1;
+        let Some(level) = styles.level(&classes) else { return };
+        let text = html::text_content(node);
+        let text = text.trim();
+        if text.is_empty() || text.chars().count() > MAX_HEADING_CHARS || has_block_children(node) {
+            return;
+        }
+        candidates.push((node.clone(), classes, level));
+    });
+    if has_headings {
+        return None;
+    }
+    // A class on most of the chapter's paragraphs is the body text's, however it's named
+    candidates.retain(|(_, classes, _)| {
+        let count = class_counts[classes];
+        count <= 2 || count * 2 <= blocks
+    });
+    if candidates.is_empty() {
+        return None;
+    }
+
+    for (node, _, level) in &candidates {
+        let Some(parent) = html::parent(node) else { continue };
+        let heading = html::retag(node, ["h1", "h2", "h3"][usize::from(*level) - 1]);
+        let mut children = parent.children.borrow_mut();
+        if let Some(position) = children.iter().position(|c| Rc::ptr_eq(c, node)) {
+            children[position] = heading;
+        }
+    }
+    Some((html::serialize(&dom), candidates.len()))
+}
+
+fn has_block_children(node: &Handle) -> bool {
+    node.children.borrow().iter().any(|child| {
+        matches!(child.data, NodeData::Element { .. })
+            && html::tag_name(child).is_some_and(|t| matches!(t.as_str(), "p" | "div" | "table" | "ul" | "ol" | "blockquote" | "figure"))
+    })
+}
+
+fn size_level(size: f64) -> u8 {
+    if size >= 1.75 {
+        1
+    } else if size >= 1.4 {
+        2
+    } else {
+        3
+    }
+}
+
+fn strip_comments(css: &str) -> String {
+    let mut out = String::with_capacity(css.len());
+    let mut rest = css;
+    while let Some(start) = rest.find("/*") {
+        out.push_str(&rest[..start]);
+        rest = rest[start + 2..].find("*/").map_or("", |end| &rest[start + 2 + end + 2..]);
+    }
+    out.push_str(rest);
+    out
+}
+
+/// The class a selector styles, when its subject is a lone class, optionally on a
+/// paragraph or div (`.chapterhead`, `p.ct`, `body .title`)
+fn subject_class(selector: &str) -> Option<String> {
+    let subject = selector.split([' ', '>', '+', '~']).rfind(|s| !s.is_empty())?.trim();
+    let (tag, class) = subject.split_once('.')?;
+    let valid = !class.is_empty() && class.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    (valid && (tag.is_empty() || CANDIDATE_TAGS.contains(&tag.to_ascii_lowercase().as_str()))).then(|| class.to_ascii_lowercase())
+}
+
+/// A `font-size` declaration, relative to the body text. Pixel and point sizes are taken
+/// against the usual 16px and 12pt defaults.
+fn font_size(declarations: &str) -> Option<f64> {
+    let value = declarations.split(';').rev().find_map(|declaration| {
+        let (property, value) = declaration.split_once(':')?;
+        (property.trim().eq_ignore_ascii_case("font-size")).then(|| value.trim().trim_end_matches("!important").trim().to_ascii_lowercase())
+    })?;
+    let keyword = match value.as_str() {
+        "medium" | "small" | "x-small" | "xx-small" | "smaller" => Some(1.0),
+        "large" | "larger" => Some(1.2),
+        "x-large" => Some(1.5),
+        "xx-large" => Some(2.0),
+        "xxx-large" => Some(3.0),
+        _ => None,
+    };
+    if keyword.is_some() {
+        return keyword;
+    }
+    let number = |unit: &str| value.strip_suffix(unit).and_then(|n| n.trim().parse::<f64>().ok());
+    number("rem")
+        .or_else(|| number("em"))
+        .or_else(|| number("%").map(|n| n / 100.0))
+        .or_else(|| number("pt").map(|n| n / 12.0))
+        .or_else(|| number("px").map(|n| n / 16.0))
+}
@@ -0,0 +1,131 @@
+use html5ever::driver::ParseOpts;
+use html5ever::parse_document;
+use html5ever::serialize::{SerializeOpts, TraversalScope};
+use html5ever::tendril::TendrilSink;
+use html5ever::{ns, namespace_url, Attribute, LocalName, QualName};
+use markup5ever_rcdom::{Handle, Node, NodeData, RcDom, SerializableHandle};
+use std::cell::RefCell;
+
+/// Parse an XHTML chapter into a DOM tree
+pub fn parse(html: &str) -> RcDom {
+    parse_document(RcDom::default(), ParseOpts::default()).one(html)
+}
+
+/// Local tag name of an element node, if it is one
+pub fn tag_name(node: &Handle) -> Option<String> {
+    match node.data {
+        NodeData::Element { ref name, .. } => Some(name.local.to_string()),
+        _ => None,
+    }
+}
+
+/// Value of an attribute on an element node, matched by local name
+pub fn attr(node: &Handle, name: &str) -> Option<String> {
+    match node.data {
+        NodeData::Element { ref attrs, .. } => attrs
+            .borrow()
+            .iter()
+            .find(|a| a.name.local.as_ref() == name)
+            .map(|a| a.value.to_string()),
+        _ => None,
+    }
+}
+
+/// Value of an element's `epub:type` attribute. The HTML parser keeps the prefix as
+/// part of the attribute name, while namespace-aware parsers split it off.
+pub fn epub_type(node: &Handle) -> Option<String> {
+    match node.data {
+        NodeData::Element { ref attrs, .. } => attrs
+            .borrow()
+            .iter()
+            .find(|a| {
+                a.name.local.as_ref() == "epub:type"
+                    || (a.name.local.as_ref() == "type" && a.name.prefix.as_ref().is_some_and(|p| p.as_ref() == "epub"))
+            })
+            .map(|a| a.value.to_string()),
+        _ => None,
+    }
+}
+
+/// Concatenated text of a node and its descendants, with whitespace collapsed
+pub fn text_content(node: &Handle) -> String {
+    fn collect(node: &Handle, out: &mut String) {
+        if let NodeData::Text { ref contents } = node.data {
+            out.push_str(&contents.borrow());
+        }
+        for child in node.children.borrow().iter() {
+            collect(child, out);
+        }
+    }
+
+    let mut raw = String::new();
+    collect(node, &mut raw);
+    raw.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Parent of a node, if it is still attached to one
+pub fn parent(node: &Handle) -> Option<Handle> {
+    let weak = node.parent.take()?;
+    let parent = weak.upgrade();
+    node.parent.set(Some(weak));
+    parent
+}
+
+/// Visit every node in document order
+pub fn walk(node: &Handle, visit: &mut dyn FnMut(&Handle)) {
+    visit(node);
+    for child in node.children.borrow().iter() {
+        walk(child, visit);
+    }
+}
+
+/// Serialize a parsed document back to HTML
+pub fn serialize(dom: &RcDom) -> String {
+    let mut out = Vec::new();
+    let handle: SerializableHandle = dom.document.clone().into();
+    let opts = SerializeOpts { traversal_scope: TraversalScope::ChildrenOnly(None), ..Default::default() };
+    // Writing to a Vec can't fail
+    let _ = html5ever::serialize::serialize(&mut out, &handle, opts);
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// A new HTML element with the given attributes and no children
+pub fn element(tag: &str, attrs: &[(&str, &str)]) -> Handle {
+    let attrs = attrs
+        .iter()
+        .map(|(name, value)| Attribute { name: QualName::new(None, ns!(), LocalName::from(*name)), value: (*value).into() })
+        .collect();
+    Node::new(NodeData::Element {
+        name: QualName::new(None, ns!(html), LocalName::from(tag)),
+        attrs: RefCell::new(attrs),
+        template_contents: RefCell::new(None),
+        mathml_annotation_xml_integration_point: false,
+    })
+}
+
+/// A copy of an element under another tag name, with its attributes and taking over its
+/// children; the caller puts it in the element's place
+pub fn retag(node: &Handle, tag: &str) -> Handle {
+    let attrs = match node.data {
+        NodeData::Element { ref attrs, .. } => attrs.borrow().clone(),
+        _ => Vec::new(),
+    };
+    let replacement = Node::new(NodeData::Element {
+        name: QualName::new(None, ns!(html), LocalName::from(tag)),
+        attrs: RefCell::new(attrs),
+        template_contents: RefCell::new(None),
+        mathml_annotation_xml_integration_point: false,
+    });
+    let children: Vec<Handle> = node.children.take();
+    for child in &children {
+        child.parent.set(Some(std::rc::Rc::downgrade(&replacement)));
+    }
+    *replacement.children.borrow_mut() = children;
+    replacement.parent.set(node.parent.take());
+    replacement
+}
+
+/// A new text node
+pub fn text(contents: &str) -> Handle {
+    Node::new(NodeData::Text { contents: RefCell::new(contents.into()) })
+}
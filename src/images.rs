@@ -0,0 +1,133 @@
+use crate::digest::Sha256;
+use crate::output;
+use crate::toc;
+use anyhow::{Context, Result};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Directory under the book's output directory that `--extract-images` writes to
+pub const IMAGES_DIR: &str = "images";
+
+/// Hex digits of the content hash kept in an image's file name
+const HASH_LEN: usize = 16;
+
+/// Writer for `--extract-images`: each image the chapters show is written once to
+/// `images/`, named by a hash of its bytes (`images/3fa9c2e07b41d5a8.png`), and the
+/// chapters' links are pointed at it. The same picture under several paths, in several
+/// chapters or in several books, gets the same name, and reconverting a book never
+/// renames its images.
+pub struct ImageStore {
+    root: PathBuf,
+    /// File name given to each archive path seen, or None for one that isn't an image in the archive
+    names: HashMap<String, Option<String>>,
+    /// Files written, relative to the output directory, by archive path: the manifest in metadata.json
+    pub manifest: BTreeMap<String, String>,
+    /// Files written this run
+    pub files: Vec<PathBuf>,
+}
+
+impl ImageStore {
+    pub fn new(output_dir: &Path) -> Self {
+        Self { root: output_dir.join(IMAGES_DIR), names: HashMap::new(), manifest: BTreeMap::new(), files: Vec::new() }
+    }
+
+    /// Point a chapter's `![alt](target)` images at their copies in `images/`, writing
+    /// each the first time it is seen. Targets are resolved against `source`, the
+    /// chapter's path inside the EPUB, and looked up with `resource`; `prefix` leads from
+    /// the chapter's file back to the output directory (`../` for a chapter in a part
+    /// directory). Images that aren't in the archive keep their links.
+    pub fn rewrite(
+        &mut self,
+        markdown: &str,
+        source: &str,
+        prefix: &str,
+        mut resource: impl FnMut(&str) -> Result<Option<(Vec<u8>, String)>>,
+    ) -> Result<String> {
+        let base = source.rsplit_once('/').map(|(dir, _)| dir).unwrap_or_default();
+        let mut out = String::with_capacity(markdown.len());
+        let mut rest = markdown;
+        while let Some((before, alt, target, after)) = next_image(rest) {
+            out.push_str(before);
+            let path = toc::target_path(Path::new(&format!("{}/{}", base, target)));
+            let name = match self.names.get(&path) {
+                Some(name) => name.clone(),
+                None => {
+                    let name = match resource(&path)? {
+                        Some((data, mime)) => Some(self.write(&path, &data, &mime)?),
+                        None => None,
+                    };
+                    self.names.insert(path, name.clone());
+                    name
+                }
+            };
+            match name {
+                Some(name) => out.push_str(&format!("![{}]({}{}/{})", alt, prefix, IMAGES_DIR, name)),
+                None => out.push_str(&format!("![{}]({})", alt, target)),
+            }
+            rest = after;
+        }
+        out.push_str(rest);
+        Ok(out)
+    }
+
+    fn write(&mut self, path: &str, data: &[u8], mime: &str) -> Result<String> {
+        let mut hasher = Sha256::default();
+        hasher.update(data);
+        let hash = hasher.finalize_hex();
+        let name = match extension(path, mime) {
+            Some(extension) => format!("{}.{}", &hash[..HASH_LEN], extension),
+            None => hash[..HASH_LEN].to_string(),
+        };
+        let target = self.root.join(&name);
+        if !self.files.contains(&target) {
+            // A file of this name already holds these bytes, from an earlier run
+            if !target.is_file() {
+                fs::create_dir_all(&self.root).context("Failed to create images directory")?;
+                output::write_atomic(&target, data).with_context(|| format!("Failed to write {}", target.display()))?;
+            }
+            self.files.push(target);
+        }
+        self.manifest.insert(path.to_string(), format!("{}/{}", IMAGES_DIR, name));
+        Ok(name)
+    }
+}
+
+/// An image's file extension: the one it has in the archive, else one for its media
+/// type. `.jpeg` is spelled `.jpg`, so the same bytes get the same name either way.
+fn extension(path: &str, mime: &str) -> Option<String> {
+    let name = path.rsplit('/').next().unwrap_or(path);
+    let extension = match name.rsplit_once('.').filter(|(_, e)| !e.is_empty() && e.len() <= 5 && e.chars().all(|c| c.is_ascii_alphanumeric())) {
+        Some((_, extension)) => extension.to_ascii_lowercase(),
+        None => match mime.strip_prefix("image/")? {
+            "svg+xml" => "svg".to_string(),
+            subtype => subtype.chars().take_while(char::is_ascii_alphanumeric).collect(),
+        },
+    };
+    match extension.as_str() {
+        "" => None,
+        "jpeg" => Some("jpg".to_string()),
+        _ => Some(extension),
+    }
+}
+
+/// The next `![alt](target)` in `text` with a relative target, as (text before, alt,
+/// target, text after)
+pub fn next_image(text: &str) -> Option<(&str, &str, &str, &str)> {
+    let mut from = 0;
+    loop {
+        let start = from + text[from..].find("![")?;
+        // The alt ends at the first `]` not escaped with a backslash
+        let alt = &text[start + 2..];
+        let alt_end = start + 2 + alt.match_indices(']').map(|(i, _)| i).find(|&i| alt[..i].bytes().rev().take_while(|&b| b == b'\\').count() % 2 == 0)?;
+        if let Some(after) = text[alt_end + 1..].strip_prefix('(') {
+            if let Some(len) = after.find([')', '\n']).filter(|&n| after.as_bytes()[n] == b')') {
+                let target = after[..len].split_whitespace().next().unwrap_or_default();
+                if !target.is_empty() && !target.contains("://") && !target.starts_with("data:") {
+                    return Some((&text[..start], &text[start + 2..alt_end], target, &after[len + 1..]));
+                }
+            }
+        }
+        from = start + 2;
+    }
+}
@@ -0,0 +1,93 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+/// Extensions taken for EPUBs when none are given
+pub const DEFAULT_EXTENSIONS: &[&str] = &["epub", "epub.zip"];
+
+/// What an OCF container's first entry, `mimetype`, holds
+const EPUB_MIMETYPE: &[u8] = b"application/epub+zip";
+
+/// File-name extensions that mark EPUBs, from `--extensions`. They are matched
+/// case-insensitively and may be double (`epub.zip`); the longest matching one is what
+/// a book's name is stripped of.
+#[derive(Debug, Clone)]
+pub struct EpubExtensions(Vec<String>);
+
+impl Default for EpubExtensions {
+    fn default() -> Self {
+        Self::new(DEFAULT_EXTENSIONS.iter().copied())
+    }
+}
+
+impl EpubExtensions {
+    pub fn new<'a>(extensions: impl IntoIterator<Item = &'a str>) -> Self {
+        let mut extensions: Vec<String> = extensions
+            .into_iter()
+            .map(|e| e.trim().trim_start_matches('.').to_lowercase())
+            .filter(|e| !e.is_empty())
+            .collect();
+        // Longest first, so `epub.zip` wins over `zip`
+        extensions.sort_by_key(|e| std::cmp::Reverse(e.len()));
+        extensions.dedup();
+        Self(extensions)
+    }
+
+    /// The extension the file name ends with, if it is one of these
+    fn matched(&self, path: &Path) -> Option<&str> {
+        let name = path.file_name()?.to_string_lossy().to_lowercase();
+        self.0
+            .iter()
+            .find(|e| name.len() > e.len() + 1 && name.ends_with(e.as_str()) && name[..name.len() - e.len()].ends_with('.'))
+            .map(String::as_str)
+    }
+
+    /// Whether the file name ends with one of these extensions
+    pub fn matches(&self, path: &Path) -> bool {
+        self.matched(path).is_some()
+    }
+
+    /// Whether the file is taken for an EPUB: by its extension, or else by the
+    /// `mimetype` entry an EPUB's zip container starts with
+    pub fn is_epub(&self, path: &Path) -> bool {
+        self.matches(path) || sniff(path)
+    }
+
+    /// The file name without its EPUB extension (`book` for `Book.EPUB` or
+    /// `book.epub.zip`), or without its last extension when it has none of these
+    pub fn stem(&self, path: &Path) -> String {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        match self.matched(path) {
+            Some(extension) => name[..name.len() - extension.len() - 1].to_string(),
+            None => path.file_stem().unwrap_or_default().to_string_lossy().into_owned(),
+        }
+    }
+}
+
+/// Whether the file is a zip whose first entry is an uncompressed `mimetype` holding
+/// `application/epub+zip`, as the OCF container format requires
+pub fn sniff(path: &Path) -> bool {
+    // Room for the local file header with a generous extra field
+    let mut header = [0u8; 512];
+    let Ok(mut file) = File::open(path) else { return false };
+    let mut read = 0;
+    while read < header.len() {
+        match file.read(&mut header[read..]) {
+            Ok(0) | Err(_) => break,
+            Ok(n) => read += n,
+        }
+    }
+    is_epub_header(&header[..read])
+}
+
+/// Whether the first bytes of a file are those of an EPUB's zip container
+pub fn is_epub_header(header: &[u8]) -> bool {
+    if header.len() < 30 || !header.starts_with(b"PK\x03\x04") {
+        return false;
+    }
+    let name_len = usize::from(u16::from_le_bytes([header[26], header[27]]));
+    let extra_len = usize::from(u16::from_le_bytes([header[28], header[29]]));
+    let content = 30 + name_len + extra_len;
+    header.get(30..30 + name_len) == Some(b"mimetype".as_slice())
+        && header.get(content..content + EPUB_MIMETYPE.len()) == Some(EPUB_MIMETYPE)
+}
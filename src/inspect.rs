@@ -0,0 +1,59 @@
+use crate::{extract_metadata, TocEntry};
+use anyhow::{Context, Result};
+use epub::doc::EpubDoc;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Print a human-readable overview of an EPUB: metadata, spine, TOC and resources
+pub fn print_summary(epub_path: &Path) -> Result<()> {
+    let mut doc = EpubDoc::new(epub_path)
+        .context("Failed to open EPUB file")?;
+    crate::toc::fill_from_nav(&mut doc);
+    let metadata = extract_metadata(&doc);
+
+    let unknown = || "-".to_string();
+    println!("File:         {}", epub_path.display());
+    println!("Title:        {}", metadata.title.clone().unwrap_or_else(unknown));
+    println!("Authors:      {}", if metadata.creators.is_empty() { unknown() } else { metadata.creators.join(", ") });
+    println!("Language:     {}", metadata.language.clone().unwrap_or_else(unknown));
+    println!("Publisher:    {}", metadata.publisher.clone().unwrap_or_else(unknown));
+    println!("Identifier:   {}", metadata.identifier.clone().unwrap_or_else(unknown));
+    println!("EPUB version: {}", metadata.epub_version);
+    println!("Root file:    {}", doc.root_file.display());
+
+    println!("\nSpine ({} items):", doc.spine.len());
+    for (i, item) in doc.spine.iter().enumerate() {
+        let (path, mime) = doc
+            .resources
+            .get(&item.idref)
+            .map(|r| (r.path.to_string_lossy().into_owned(), r.mime.as_str()))
+            .unwrap_or_else(|| (format!("<missing: {}>", item.idref), "?"));
+        let linear = if item.linear { "" } else { "  (non-linear)" };
+        println!("  {:>4}  {}  [{}]{}", i, path, mime, linear);
+    }
+
+    println!("\nTable of contents:");
+    if metadata.toc.is_empty() {
+        println!("  (empty)");
+    }
+    print_toc(&metadata.toc, 1);
+
+    // Resources grouped by media type, in a stable order
+    let mut by_mime: BTreeMap<&str, usize> = BTreeMap::new();
+    for resource in doc.resources.values() {
+        *by_mime.entry(resource.mime.as_str()).or_default() += 1;
+    }
+    println!("\nResources ({}):", doc.resources.len());
+    for (mime, count) in by_mime {
+        println!("  {:>4}  {}", count, mime);
+    }
+
+    Ok(())
+}
+
+fn print_toc(entries: &[TocEntry], depth: usize) {
+    for entry in entries {
+        println!("{}- {}", "  ".repeat(depth), entry.label);
+        print_toc(&entry.children, depth + 1);
+    }
+}
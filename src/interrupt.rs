@@ -0,0 +1,38 @@
+use std::sync::atomic::{AtomicI32, Ordering};
+
+/// The first SIGINT or SIGTERM received, or 0
+static SIGNAL: AtomicI32 = AtomicI32::new(0);
+
+const NOTICE: &[u8] = b"\nInterrupted: stopping after the books in progress reach a safe point (press Ctrl-C again to quit at once)\n";
+
+extern "C" fn on_signal(signal: libc::c_int) {
+    if SIGNAL.swap(signal, Ordering::SeqCst) != 0 {
+        // A second signal: the user doesn't want to wait
+        unsafe { libc::_exit(128 + signal) };
+    }
+    // Only async-signal-safe calls here, so no eprintln!
+    unsafe { libc::write(libc::STDERR_FILENO, NOTICE.as_ptr().cast(), NOTICE.len()) };
+}
+
+/// Catch SIGINT and SIGTERM so a run can stop cleanly: books not yet started are left
+/// alone and those in progress stop at their next batch of chapters
+pub fn install() {
+    let handler = on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    unsafe {
+        libc::signal(libc::SIGINT, handler);
+        libc::signal(libc::SIGTERM, handler);
+    }
+}
+
+/// Whether the run has been asked to stop
+pub fn requested() -> bool {
+    SIGNAL.load(Ordering::Relaxed) != 0
+}
+
+/// Name of the signal that stopped the run
+pub fn signal_name() -> &'static str {
+    match SIGNAL.load(Ordering::Relaxed) {
+        libc::SIGTERM => "SIGTERM",
+        _ => "SIGINT",
+    }
+}
@@ -0,0 +1,79 @@
+use anyhow::Result;
+use std::any::Any;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, TryRecvError};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Cooperative cancellation flag checked by the conversion between batches
+#[derive(Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+impl fmt::Debug for CancelToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("CancelToken")
+    }
+}
+
+/// Run `job`, turning a panic into an error and, with a timeout, giving up on it
+/// once the deadline passes. A timed-out job is cancelled via `cancel` and left to
+/// wind down in the background, since threads can't be killed.
+pub fn run_isolated<T, F>(timeout: Option<Duration>, cancel: &CancelToken, job: F) -> Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T> + Send + 'static,
+{
+    let Some(timeout) = timeout else {
+        return panic::catch_unwind(AssertUnwindSafe(job)).unwrap_or_else(|payload| Err(panic_error(payload)));
+    };
+
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let result = panic::catch_unwind(AssertUnwindSafe(job)).unwrap_or_else(|payload| Err(panic_error(payload)));
+        let _ = sender.send(result);
+    });
+
+    // Poll rather than block: when called from a rayon worker, yielding lets this
+    // thread run the job's own chapter tasks instead of starving the pool
+    let deadline = Instant::now() + timeout;
+    loop {
+        match receiver.try_recv() {
+            Ok(result) => return result,
+            Err(TryRecvError::Disconnected) => anyhow::bail!("Conversion thread exited unexpectedly"),
+            Err(TryRecvError::Empty) => {}
+        }
+        if Instant::now() >= deadline {
+            cancel.cancel();
+            anyhow::bail!("Timed out after {:?}", timeout);
+        }
+        if rayon::yield_now() != Some(rayon::Yield::Executed) {
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+}
+
+fn panic_error(payload: Box<dyn Any + Send>) -> anyhow::Error {
+    anyhow::anyhow!("Panicked while converting: {}", panic_message(payload))
+}
+
+/// The message a panic was raised with
+pub fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    payload
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown panic".to_string())
+}
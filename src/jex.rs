@@ -0,0 +1,290 @@
+use crate::digest::Sha256;
+use crate::images::next_image;
+use crate::output::AtomicFile;
+use crate::toc;
+use crate::units;
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::SystemTime;
+
+/// Joplin item types, as the `type_` property of each serialized item
+const NOTE: u8 = 1;
+const FOLDER: u8 = 2;
+const RESOURCE: u8 = 4;
+const TAG: u8 = 5;
+const NOTE_TAG: u8 = 6;
+
+/// Writer for `--format jex`: a Joplin export archive holding one notebook for the book,
+/// a note per chapter, its images as resources and the book's subjects as tags. Items
+/// stream into the tar archive as chapters are converted; `finish` adds the notebook and
+/// tags. Ids are derived from the book and item names, so reconverting a book gives the
+/// same archive.
+pub struct JexWriter {
+    tar: Tar<AtomicFile>,
+    seed: String,
+    folder_id: String,
+    /// Joplin timestamp used for every item, taken from the EPUB's modification time
+    time: String,
+    mtime: u64,
+    notes: Vec<String>,
+    /// Resource id for each archive path already added
+    resources: HashMap<String, String>,
+}
+
+impl JexWriter {
+    /// Start an archive at `path`. `seed` identifies the book (its identifier, else its title).
+    pub fn create(path: &Path, seed: &str, modified: SystemTime) -> io::Result<Self> {
+        let mtime = modified.duration_since(SystemTime::UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+        Ok(Self {
+            tar: Tar::new(AtomicFile::create(path)?),
+            seed: seed.to_string(),
+            folder_id: id(seed, "folder", ""),
+            time: units::utc_timestamp(modified).replace('Z', ".000Z"),
+            mtime,
+            notes: Vec::new(),
+            resources: HashMap::new(),
+        })
+    }
+
+    /// Add a chapter as a note. Images it embeds are looked up with `resource`, given
+    /// their archive path, and added as resources the note links to.
+    pub fn add_chapter(
+        &mut self,
+        title: &str,
+        markdown: &str,
+        source: &str,
+        mut resource: impl FnMut(&str) -> Result<Option<(Vec<u8>, String)>>,
+    ) -> Result<()> {
+        let base = source.rsplit_once('/').map(|(dir, _)| dir).unwrap_or_default();
+        let mut body = String::with_capacity(markdown.len());
+        let mut rest = markdown;
+        // Point ![alt](target) at the resource's `:/id`, adding each image once
+        while let Some((before, alt, target, after)) = next_image(rest) {
+            body.push_str(before);
+            let path = toc::target_path(Path::new(&format!("{}/{}", base, target)));
+            let linked = match self.resources.get(&path) {
+                Some(id) => Some(id.clone()),
+                None => match resource(&path)? {
+                    Some((data, mime)) => Some(self.add_resource(&path, &data, &mime)?),
+                    None => None,
+                },
+            };
+            match linked {
+                Some(id) => body.push_str(&format!("![{}](:/{})", alt, id)),
+                None => body.push_str(&format!("![{}]({})", alt, target)),
+            }
+            rest = after;
+        }
+        body.push_str(rest);
+
+        let note_id = id(&self.seed, "note", source);
+        let order = self.notes.len().to_string();
+        let item = serialize(
+            Some(&single_line(title)),
+            Some(body.trim()),
+            &[
+                ("id", &note_id),
+                ("parent_id", &self.folder_id),
+                ("created_time", &self.time),
+                ("updated_time", &self.time),
+                ("is_conflict", "0"),
+                ("latitude", "0.00000000"),
+                ("longitude", "0.00000000"),
+                ("altitude", "0.0000"),
+                ("author", ""),
+                ("source_url", ""),
+                ("is_todo", "0"),
+                ("todo_due", "0"),
+                ("todo_completed", "0"),
+                ("source", "epub-to-md"),
+                ("source_application", "epub-to-md"),
+                ("application_data", ""),
+                ("order", &order),
+                ("user_created_time", &self.time),
+                ("user_updated_time", &self.time),
+                ("encryption_cipher_text", ""),
+                ("encryption_applied", "0"),
+                ("markup_language", "1"),
+                ("is_shared", "0"),
+            ],
+            NOTE,
+        );
+        self.tar.append(&format!("{}.md", note_id), item.as_bytes(), self.mtime).context("Failed to write the Joplin archive")?;
+        self.notes.push(note_id);
+        Ok(())
+    }
+
+    fn add_resource(&mut self, path: &str, data: &[u8], mime: &str) -> Result<String> {
+        let resource_id = id(&self.seed, "resource", path);
+        let file_name = path.rsplit('/').next().unwrap_or(path);
+        let extension = file_name.rsplit_once('.').map(|(_, ext)| ext.to_ascii_lowercase()).unwrap_or_default();
+        let size = data.len().to_string();
+        let item = serialize(
+            Some(file_name),
+            None,
+            &[
+                ("id", &resource_id),
+                ("mime", mime),
+                ("filename", ""),
+                ("created_time", &self.time),
+                ("updated_time", &self.time),
+                ("user_created_time", &self.time),
+                ("user_updated_time", &self.time),
+                ("file_extension", &extension),
+                ("encryption_cipher_text", ""),
+                ("encryption_applied", "0"),
+                ("encryption_blob_encrypted", "0"),
+                ("size", &size),
+                ("is_shared", "0"),
+            ],
+            RESOURCE,
+        );
+        let blob = if extension.is_empty() { resource_id.clone() } else { format!("{}.{}", resource_id, extension) };
+        self.tar.append(&format!("resources/{}", blob), data, self.mtime).context("Failed to write the Joplin archive")?;
+        self.tar.append(&format!("{}.md", resource_id), item.as_bytes(), self.mtime).context("Failed to write the Joplin archive")?;
+        self.resources.insert(path.to_string(), resource_id.clone());
+        Ok(resource_id)
+    }
+
+    /// Add the book's notebook and each of the book's tags on every note, then move the
+    /// archive into place
+    pub fn finish(mut self, title: &str, tags: &[String]) -> Result<()> {
+        let folder = serialize(
+            Some(&single_line(title)),
+            None,
+            &[
+                ("id", &self.folder_id),
+                ("created_time", &self.time),
+                ("updated_time", &self.time),
+                ("user_created_time", &self.time),
+                ("user_updated_time", &self.time),
+                ("encryption_cipher_text", ""),
+                ("encryption_applied", "0"),
+                ("parent_id", ""),
+                ("is_shared", "0"),
+            ],
+            FOLDER,
+        );
+        let folder_file = format!("{}.md", self.folder_id);
+        self.tar.append(&folder_file, folder.as_bytes(), self.mtime).context("Failed to write the Joplin archive")?;
+
+        let mut tags = tags.to_vec();
+        tags.sort();
+        for tag in &tags {
+            // Joplin matches tags case-insensitively, so derive the id the same way
+            let tag_id = id("", "tag", &tag.to_lowercase());
+            let item = serialize(
+                Some(tag),
+                None,
+                &[
+                    ("id", &tag_id),
+                    ("created_time", &self.time),
+                    ("updated_time", &self.time),
+                    ("user_created_time", &self.time),
+                    ("user_updated_time", &self.time),
+                    ("encryption_cipher_text", ""),
+                    ("encryption_applied", "0"),
+                    ("is_shared", "0"),
+                    ("parent_id", ""),
+                ],
+                TAG,
+            );
+            self.tar.append(&format!("{}.md", tag_id), item.as_bytes(), self.mtime).context("Failed to write the Joplin archive")?;
+            for note_id in &self.notes {
+                let link_id = id(&self.seed, "note_tag", &format!("{}/{}", note_id, tag_id));
+                let item = serialize(
+                    None,
+                    None,
+                    &[
+                        ("id", &link_id),
+                        ("note_id", note_id),
+                        ("tag_id", &tag_id),
+                        ("created_time", &self.time),
+                        ("updated_time", &self.time),
+                        ("user_created_time", &self.time),
+                        ("user_updated_time", &self.time),
+                        ("encryption_cipher_text", ""),
+                        ("encryption_applied", "0"),
+                        ("is_shared", "0"),
+                    ],
+                    NOTE_TAG,
+                );
+                self.tar.append(&format!("{}.md", link_id), item.as_bytes(), self.mtime).context("Failed to write the Joplin archive")?;
+            }
+        }
+        self.tar.finish()?.commit().context("Failed to write the Joplin archive")
+    }
+}
+
+/// A 32-character hex id, stable for the same book and item
+fn id(seed: &str, kind: &str, name: &str) -> String {
+    let mut hasher = Sha256::default();
+    for part in [seed, kind, name] {
+        hasher.update(part.as_bytes());
+        hasher.update(&[0]);
+    }
+    hasher.finalize_hex()[..32].to_string()
+}
+
+fn single_line(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// An item in Joplin's raw format: title, body, then `key: value` properties
+fn serialize(title: Option<&str>, body: Option<&str>, properties: &[(&str, &str)], kind: u8) -> String {
+    let mut out = String::new();
+    if let Some(title) = title {
+        out.push_str(title);
+        out.push_str("\n\n");
+    }
+    if let Some(body) = body.filter(|b| !b.is_empty()) {
+        out.push_str(body);
+        out.push_str("\n\n");
+    }
+    for (key, value) in properties {
+        out.push_str(&format!("{}: {}\n", key, value));
+    }
+    out.push_str(&format!("type_: {}", kind));
+    out
+}
+
+/// Minimal writer for uncompressed ustar archives, which is what Joplin imports
+struct Tar<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> Tar<W> {
+    fn new(writer: W) -> Self {
+        Self { writer }
+    }
+
+    fn append(&mut self, name: &str, data: &[u8], mtime: u64) -> io::Result<()> {
+        let mut header = [0u8; 512];
+        let field = |header: &mut [u8; 512], at: usize, value: &[u8]| header[at..at + value.len()].copy_from_slice(value);
+        field(&mut header, 0, name.as_bytes());
+        field(&mut header, 100, b"0000644\0");
+        field(&mut header, 108, b"0000000\0");
+        field(&mut header, 116, b"0000000\0");
+        field(&mut header, 124, format!("{:011o}\0", data.len()).as_bytes());
+        field(&mut header, 136, format!("{:011o}\0", mtime).as_bytes());
+        field(&mut header, 148, b"        ");
+        header[156] = b'0';
+        field(&mut header, 257, b"ustar\0");
+        field(&mut header, 263, b"00");
+        let checksum: u32 = header.iter().map(|&b| u32::from(b)).sum();
+        field(&mut header, 148, format!("{:06o}\0 ", checksum).as_bytes());
+
+        self.writer.write_all(&header)?;
+        self.writer.write_all(data)?;
+        let padding = (512 - data.len() % 512) % 512;
+        self.writer.write_all(&[0u8; 512][..padding])
+    }
+
+    /// Write the end-of-archive marker and hand back the writer
+    fn finish(mut self) -> io::Result<W> {
+        self.writer.write_all(&[0u8; 1024])?;
+        Ok(self.writer)
+    }
+}
@@ -0,0 +1,66 @@
+use crate::output;
+use crate::summary::RunStatus;
+use crate::units;
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Record of an interrupted batch, written into its output root
+pub const JOURNAL_FILE: &str = "run-journal.json";
+
+/// The books an interrupted batch didn't finish, one path per line for `--files-from`
+pub const REMAINING_FILE: &str = "run-remaining.txt";
+
+#[derive(Serialize)]
+struct Journal {
+    interrupted_by: &'static str,
+    interrupted_at: String,
+    books: Vec<Entry>,
+}
+
+#[derive(Serialize)]
+struct Entry {
+    source: String,
+    output: String,
+    status: &'static str,
+}
+
+/// Write the journal of an interrupted batch: how each book fared, and the list of those
+/// left to convert. Returns the path of that list and how many books are on it.
+pub fn write(books: &[(&Path, &Path, RunStatus)], signal: &'static str, base: &Path) -> Result<(PathBuf, usize)> {
+    let journal = Journal {
+        interrupted_by: signal,
+        interrupted_at: units::utc_timestamp(SystemTime::now()),
+        books: books
+            .iter()
+            .map(|(epub_path, output_dir, status)| Entry {
+                source: epub_path.to_string_lossy().into_owned(),
+                output: output_dir.to_string_lossy().into_owned(),
+                status: status.label(),
+            })
+            .collect(),
+    };
+    let json = serde_json::to_string_pretty(&journal).context("Failed to serialize the run journal")?;
+    output::write_atomic(&base.join(JOURNAL_FILE), json + "\n").context("Failed to write the run journal")?;
+
+    // Absolute paths, so the list works from wherever the next run starts
+    let remaining: Vec<String> = books
+        .iter()
+        .filter(|(_, _, status)| matches!(status, RunStatus::Interrupted | RunStatus::NotStarted))
+        .map(|(epub_path, _, _)| epub_path.canonicalize().unwrap_or_else(|_| epub_path.to_path_buf()).to_string_lossy().into_owned())
+        .collect();
+    let path = base.join(REMAINING_FILE);
+    let mut list = remaining.join("\n");
+    list.push('\n');
+    output::write_atomic(&path, list).context("Failed to write the list of remaining books")?;
+    Ok((path, remaining.len()))
+}
+
+/// Remove the journal an earlier interrupted run left, once a batch runs to the end
+pub fn clear(base: &Path) {
+    for name in [JOURNAL_FILE, REMAINING_FILE] {
+        let _ = fs::remove_file(base.join(name));
+    }
+}
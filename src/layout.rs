@@ -0,0 +1,317 @@
+use crate::archive::Resources;
+use crate::html;
+use anyhow::Result;
+use clap::ValueEnum;
+use epub::doc::EpubDoc;
+use markup5ever_rcdom::Handle;
+use std::io::{Read, Seek};
+use std::rc::Rc;
+use xml::reader::{EventReader, XmlEvent};
+
+/// How right-to-left books are marked up so viewers render them correctly
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum RtlMarkup {
+    /// Leave the Markdown as is
+    #[default]
+    None,
+    /// Wrap each chapter in a `<div dir="rtl">` container
+    Html,
+    /// Start each line of text with a right-to-left mark (U+200F)
+    Marks,
+}
+
+/// Languages written right to left, by primary subtag
+const RTL_LANGUAGES: &[&str] = &["ar", "arc", "dv", "fa", "he", "iw", "ckb", "ps", "sd", "ug", "ur", "yi"];
+
+/// Page layout the book was designed for, as far as it affects the Markdown
+#[derive(Debug, Clone, Default)]
+pub struct BookLayout {
+    /// CSS writing mode when the book is typeset vertically (e.g. `vertical-rl`)
+    pub vertical: Option<String>,
+    /// Whether the text runs right to left
+    pub rtl: bool,
+    /// Whether every spine item is a fixed page, usually a full-page image
+    pub fixed: bool,
+}
+
+impl BookLayout {
+    /// Inspect the OPF, language and stylesheets for vertical or right-to-left text
+    pub fn detect<R: Read + Seek>(doc: &EpubDoc<R>, resources: &mut Resources) -> Result<Self> {
+        let mut layout = BookLayout::default();
+
+        // Right to left: declared page progression, or a right-to-left language.
+        // Vertical Japanese books also progress right to left, so that alone isn't enough
+        // when the language says otherwise.
+        let language = doc.mdata("language").map(|m| m.value.trim().to_ascii_lowercase()).unwrap_or_default();
+        let primary = language.split(['-', '_']).next().unwrap_or_default();
+        let rtl_language = RTL_LANGUAGES.contains(&primary) || language.contains("-arab") || language.contains("-hebr");
+        let opf = resources.read_string(&doc.root_file.to_string_lossy())?.unwrap_or_default();
+        let rtl_spine = opf.contains("page-progression-direction=\"rtl\"");
+        layout.rtl = rtl_language || (rtl_spine && language.is_empty());
+        layout.fixed = pre_paginated(&opf)
+            || doc.mdata("fixed-layout").is_some_and(|m| m.value.trim().eq_ignore_ascii_case("true"))
+            || resources.read_string(APPLE_DISPLAY_OPTIONS)?.is_some_and(|xml| apple_fixed_layout(&xml));
+
+        // Kindle-style `<meta name="primary-writing-mode" content="vertical-rl"/>`
+        if let Some(mode) = doc.mdata("primary-writing-mode").map(|m| m.value.trim().to_string()) {
+            if mode.starts_with("vertical") {
+                layout.vertical = Some(mode);
+            }
+        }
+
+        if layout.vertical.is_none() {
+            let stylesheets: Vec<_> = doc
+                .resources
+                .values()
+                .filter(|r| r.mime == "text/css")
+                .map(|r| r.path.clone())
+                .collect();
+            for path in stylesheets {
+                let Some(css) = resources.read_string(&path.to_string_lossy())? else { continue };
+                if let Some(mode) = vertical_writing_mode(&css) {
+                    layout.vertical = Some(mode);
+                    break;
+                }
+            }
+        }
+
+        Ok(layout)
+    }
+}
+
+/// Apple Books' display options, which say whether a book is fixed layout for that reader
+const APPLE_DISPLAY_OPTIONS: &str = "META-INF/com.apple.ibooks.display-options.xml";
+
+/// Whether the package's metadata gives `rendition:layout` as `pre-paginated`
+pub fn pre_paginated(opf: &str) -> bool {
+    let mut in_layout = false;
+    for event in EventReader::new(opf.as_bytes()).into_iter().flatten() {
+        match event {
+            XmlEvent::StartElement { name, attributes, .. } if name.local_name == "meta" => {
+                in_layout = attributes.iter().any(|a| a.name.local_name == "property" && a.value.trim() == "rendition:layout");
+            }
+            XmlEvent::Characters(text) if in_layout => return text.trim() == "pre-paginated",
+            XmlEvent::EndElement { .. } => in_layout = false,
+            _ => {}
+        }
+    }
+    false
+}
+
+/// Whether Apple's display options set `fixed-layout` to true
+fn apple_fixed_layout(xml: &str) -> bool {
+    let mut in_option = false;
+    for event in EventReader::new(xml.as_bytes()).into_iter().flatten() {
+        match event {
+            XmlEvent::StartElement { name, attributes, .. } if name.local_name == "option" => {
+                in_option = attributes.iter().any(|a| a.name.local_name == "name" && a.value.trim() == "fixed-layout");
+            }
+            XmlEvent::Characters(text) if in_option && text.trim() == "true" => return true,
+            XmlEvent::EndElement { .. } => in_option = false,
+            _ => {}
+        }
+    }
+    false
+}
+
+/// A fixed-layout page with the images its SVG wrappers draw (`<svg><image href>`)
+/// turned into plain `<img>` elements, so the page converts to Markdown images. Returns
+/// the page's XHTML and the number of images it shows.
+pub fn fixed_page(content: &str) -> (String, usize) {
+    let dom = html::parse(content);
+    let mut images = 0;
+    unwrap_svg_images(&dom.document, &mut images);
+    (html::serialize(&dom), images)
+}
+
+fn unwrap_svg_images(node: &Handle, images: &mut usize) {
+    let children: Vec<Handle> = node.children.take();
+    let mut kept = Vec::with_capacity(children.len());
+    for child in children {
+        match html::tag_name(&child).as_deref() {
+            Some("svg") => {
+                let title = svg_title(&child);
+                html::walk(&child, &mut |n| {
+                    if html::tag_name(n).as_deref() != Some("image") {
+                        return;
+                    }
+                    // SVG 2 `href`, or the older `xlink:href`, which the parser keeps by local name
+                    let Some(src) = html::attr(n, "href") else { return };
+                    let img = html::element("img", &[("src", src.trim()), ("alt", &title)]);
+                    img.parent.set(Some(Rc::downgrade(node)));
+                    kept.push(img);
+                    *images += 1;
+                });
+            }
+            Some("img") => {
+                *images += 1;
+                kept.push(child);
+            }
+            _ => {
+                unwrap_svg_images(&child, images);
+                kept.push(child);
+            }
+        }
+    }
+    *node.children.borrow_mut() = kept;
+}
+
+/// Text of an SVG's `<title>`, used as its images' alt text
+fn svg_title(svg: &Handle) -> String {
+    svg.children
+        .borrow()
+        .iter()
+        .find(|c| html::tag_name(c).as_deref() == Some("title"))
+        .map(html::text_content)
+        .unwrap_or_default()
+}
+
+/// The first vertical `writing-mode` declared in a stylesheet, including the
+/// `-epub-` and `-webkit-` prefixed forms
+fn vertical_writing_mode(css: &str) -> Option<String> {
+    let lower = css.to_ascii_lowercase();
+    let mut rest = lower.as_str();
+    while let Some(i) = rest.find("writing-mode") {
+        rest = &rest[i + "writing-mode".len()..];
+        let Some(value) = rest.trim_start().strip_prefix(':') else { continue };
+        let value = value.trim_start();
+        let end = value.find([';', '}', '!']).unwrap_or(value.len());
+        let value = value[..end].trim();
+        // `tb-rl` is the legacy IE/SVG spelling
+        match value {
+            "vertical-rl" | "vertical-lr" => return Some(value.to_string()),
+            "tb-rl" | "tb" => return Some("vertical-rl".to_string()),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Map vertical presentation forms (used by some vertically typeset books) back to the
+/// ordinary punctuation a horizontal reader expects
+fn horizontal_form(c: char) -> Option<char> {
+    Some(match c {
+        '\u{FE10}' => '，',
+        '\u{FE11}' => '、',
+        '\u{FE12}' => '。',
+        '\u{FE13}' => '：',
+        '\u{FE14}' => '；',
+        '\u{FE15}' => '！',
+        '\u{FE16}' => '？',
+        '\u{FE17}' => '〖',
+        '\u{FE18}' => '〗',
+        '\u{FE19}' => '…',
+        '\u{FE30}' => '‥',
+        '\u{FE31}' => '—',
+        '\u{FE32}' => '–',
+        '\u{FE33}' | '\u{FE34}' => '_',
+        '\u{FE35}' => '（',
+        '\u{FE36}' => '）',
+        '\u{FE37}' => '｛',
+        '\u{FE38}' => '｝',
+        '\u{FE39}' => '〔',
+        '\u{FE3A}' => '〕',
+        '\u{FE3B}' => '【',
+        '\u{FE3C}' => '】',
+        '\u{FE3D}' => '《',
+        '\u{FE3E}' => '》',
+        '\u{FE3F}' => '〈',
+        '\u{FE40}' => '〉',
+        '\u{FE41}' => '「',
+        '\u{FE42}' => '」',
+        '\u{FE43}' => '『',
+        '\u{FE44}' => '』',
+        '\u{FE47}' => '［',
+        '\u{FE48}' => '］',
+        _ => return None,
+    })
+}
+
+fn is_cjk(c: char) -> bool {
+    matches!(c as u32,
+        0x3000..=0x303F     // CJK punctuation
+        | 0x3040..=0x30FF   // kana
+        | 0x3400..=0x4DBF
+        | 0x4E00..=0x9FFF
+        | 0xF900..=0xFAFF
+        | 0xFF00..=0xFFEF)  // full-width forms
+}
+
+/// Clean up text from a vertically typeset book for horizontal Markdown: vertical
+/// punctuation forms become their horizontal equivalents, and the stray ASCII spaces
+/// left between CJK characters by line breaks in the source are removed
+pub fn normalize_vertical(markdown: &str) -> String {
+    let chars: Vec<char> = markdown.chars().map(|c| horizontal_form(c).unwrap_or(c)).collect();
+    let mut out = String::with_capacity(markdown.len());
+    for (i, &c) in chars.iter().enumerate() {
+        if c == ' ' {
+            let prev = out.chars().next_back();
+            let next = chars[i + 1..].iter().find(|&&n| n != ' ');
+            if prev.is_some_and(is_cjk) && next.is_some_and(|&n| is_cjk(n)) {
+                continue;
+            }
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// Add right-to-left markup to a chapter
+pub fn mark_rtl(markdown: &str, markup: RtlMarkup) -> String {
+    match markup {
+        RtlMarkup::None => markdown.to_string(),
+        RtlMarkup::Html => format!("<div dir=\"rtl\">\n\n{}\n\n</div>", markdown.trim_end()),
+        RtlMarkup::Marks => {
+            let mut out = String::with_capacity(markdown.len() + markdown.len() / 16);
+            let mut in_fence = false;
+            for line in markdown.lines() {
+                let trimmed = line.trim_start();
+                if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+                    in_fence = !in_fence;
+                }
+                let structural = trimmed.is_empty()
+                    || trimmed.starts_with('|')
+                    || trimmed.starts_with('<')
+                    || trimmed.chars().all(|c| matches!(c, '-' | '=' | '*' | '_' | ' '));
+                if in_fence || structural {
+                    out.push_str(line);
+                } else {
+                    // The mark goes after any heading, quote or list marker so they keep working
+                    let split = text_start(line);
+                    out.push_str(&line[..split]);
+                    out.push('\u{200F}');
+                    out.push_str(&line[split..]);
+                }
+                out.push('\n');
+            }
+            if !markdown.ends_with('\n') {
+                out.pop();
+            }
+            out
+        }
+    }
+}
+
+/// Byte offset where a line's text begins, after indentation and block markers
+fn text_start(line: &str) -> usize {
+    let bytes = line.as_bytes();
+    let mut i = 0;
+    loop {
+        while i < bytes.len() && bytes[i] == b' ' {
+            i += 1;
+        }
+        let rest = &line[i..];
+        let marker = if rest.starts_with('#') {
+            rest.find(' ').filter(|&n| rest[..n].chars().all(|c| c == '#'))
+        } else if rest == ">" || ["> ", "- ", "* ", "+ "].iter().any(|m| rest.starts_with(m)) {
+            Some(1)
+        } else {
+            let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+            (digits > 0 && (rest[digits..].starts_with(". ") || rest[digits..].starts_with(") "))).then_some(digits + 1)
+        };
+        match marker {
+            Some(n) => i += n,
+            None => return i,
+        }
+    }
+}
@@ -0,0 +1,271 @@
+use crate::frontmatter::{self, FrontMatterFormat};
+use clap::ValueEnum;
+
+/// Where the license header goes in each output file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum LicenseHeader {
+    /// An HTML comment, invisible when the Markdown is rendered
+    Comment,
+    /// `rights` and `license` fields in a front-matter block, YAML unless
+    /// `--frontmatter-format` says otherwise
+    FrontMatter,
+}
+
+/// SPDX identifier for the license named in rights statements or license metadata, if one
+/// can be recognized: an explicit `SPDX-License-Identifier`, a Creative Commons license
+/// (`CC BY-SA 4.0`, a creativecommons.org URL, or its full name), or a public domain
+/// statement (`LicenseRef-PublicDomain`, SPDX having no identifier for it)
+pub fn detect<'a>(texts: impl IntoIterator<Item = &'a str>) -> Option<String> {
+    let texts: Vec<&str> = texts.into_iter().collect();
+    texts
+        .iter()
+        .find_map(|t| explicit_spdx(t))
+        .or_else(|| texts.iter().find_map(|t| creative_commons(t)))
+        .or_else(|| {
+            texts
+                .iter()
+                .any(|t| t.to_lowercase().contains("public domain"))
+                .then(|| "LicenseRef-PublicDomain".to_string())
+        })
+}
+
+/// Syntax of the file a license header is placed in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileSyntax {
+    /// Plain Markdown: the header goes first
+    Markdown,
+    /// Markdown opening with the YAML front matter of a Dendron note, which the
+    /// `rights` and `license` fields join
+    FrontMatter,
+    /// Markdown opening with Logseq page properties, which `rights::` and `license::` join
+    Properties,
+    /// An XML document: the header is a comment after the declaration
+    Xml,
+}
+
+/// A book's rights statement and license, ready to head each of its output files
+#[derive(Debug, Clone)]
+pub struct Header {
+    style: LicenseHeader,
+    format: FrontMatterFormat,
+    fields: Vec<(&'static str, String)>,
+}
+
+impl Header {
+    /// The header for a book's files, or None when the book states neither rights nor license
+    pub fn new(style: LicenseHeader, format: FrontMatterFormat, rights: Option<&str>, license: Option<&str>) -> Option<Self> {
+        let fields: Vec<(&'static str, String)> = [("rights", rights), ("license", license)]
+            .into_iter()
+            .filter_map(|(key, value)| Some((key, value?.to_string())))
+            .collect();
+        (!fields.is_empty()).then_some(Self { style, format, fields })
+    }
+
+    /// The header as it starts a Markdown file
+    pub fn markdown(&self) -> String {
+        match self.style {
+            // A literal "-->" would end the comment early
+            LicenseHeader::Comment => format!("<!--\n{}-->\n\n", self.comment_lines(|value| value.replace("-->", "-- >"))),
+            LicenseHeader::FrontMatter => {
+                let fields: Vec<(&str, &str)> = self.fields.iter().map(|(key, value)| (*key, value.as_str())).collect();
+                frontmatter::block(self.format, &fields)
+            }
+        }
+    }
+
+    /// `text` with the header added where a file of this syntax has room for it
+    pub fn apply(&self, syntax: FileSyntax, text: &str) -> String {
+        match syntax {
+            FileSyntax::Markdown => format!("{}{}", self.markdown(), text),
+            FileSyntax::Xml => {
+                // The declaration has to stay first, and "--" can't appear inside an XML comment
+                let (declaration, rest) = match text.strip_prefix("<?xml").and_then(|_| text.split_once('\n')) {
+                    Some((declaration, rest)) => (format!("{}\n", declaration), rest),
+                    None => (String::new(), text),
+                };
+                let lines = self.comment_lines(|value| {
+                    let mut value = value.to_string();
+                    while value.contains("--") {
+                        value = value.replace("--", "- -");
+                    }
+                    value
+                });
+                format!("{}<!--\n{}-->\n{}", declaration, lines, rest)
+            }
+            FileSyntax::FrontMatter => {
+                let Some((block, body)) = text.strip_prefix("---\n").and_then(|rest| rest.split_once("\n---\n")) else {
+                    return self.apply(FileSyntax::Markdown, text);
+                };
+                match self.style {
+                    // Dendron only reads YAML, whatever `--frontmatter-format` says
+                    LicenseHeader::FrontMatter => {
+                        let fields: Vec<(&str, &str)> = self.fields.iter().map(|(key, value)| (*key, value.as_str())).collect();
+                        let yaml = frontmatter::block(FrontMatterFormat::Yaml, &fields);
+                        let lines = yaml.trim_end().trim_start_matches("---\n").trim_end_matches("---");
+                        format!("---\n{}\n{}---\n{}", block, lines, body)
+                    }
+                    LicenseHeader::Comment => {
+                        let body = body.trim_start_matches('\n');
+                        format!("---\n{}\n---\n\n{}{}", block, self.markdown(), body)
+                    }
+                }
+            }
+            FileSyntax::Properties => {
+                // Page properties run up to the first blank line
+                let (properties, body) = text.split_once("\n\n").unwrap_or((text.trim_end_matches('\n'), ""));
+                match self.style {
+                    LicenseHeader::FrontMatter => {
+                        let added: String = self
+                            .fields
+                            .iter()
+                            .map(|(key, value)| format!("{}:: {}\n", key, value.split_whitespace().collect::<Vec<_>>().join(" ")))
+                            .collect();
+                        format!("{}\n{}\n{}", properties, added, body)
+                    }
+                    LicenseHeader::Comment => format!("{}\n\n{}{}", properties, self.markdown(), body),
+                }
+            }
+        }
+    }
+
+    fn comment_lines(&self, escape: impl Fn(&str) -> String) -> String {
+        self.fields
+            .iter()
+            .map(|(key, value)| {
+                let label = if *key == "rights" { "Rights" } else { "License" };
+                format!("{}: {}\n", label, escape(value))
+            })
+            .collect()
+    }
+}
+
+fn explicit_spdx(text: &str) -> Option<String> {
+    let (_, rest) = text.split_once("SPDX-License-Identifier:")?;
+    let id: String = rest
+        .trim_start()
+        .chars()
+        .take_while(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '.' | '+'))
+        .collect();
+    let id = id.trim_end_matches('.');
+    (!id.is_empty()).then(|| id.to_string())
+}
+
+/// License elements of the Creative Commons licenses, in SPDX order
+const ELEMENTS: &[(&str, &str)] = &[("nc", "NC"), ("sa", "SA"), ("nd", "ND")];
+
+fn creative_commons(text: &str) -> Option<String> {
+    let lower = text.to_lowercase();
+
+    // creativecommons.org/licenses/by-nc-sa/4.0/ and creativecommons.org/publicdomain/zero/1.0/
+    if let Some((_, path)) = lower.split_once("creativecommons.org/") {
+        let parts: Vec<&str> = path.split(['/', ' ', '"', '>']).take(3).collect();
+        match parts.as_slice() {
+            ["licenses", kind, version, ..] if is_version(version) => {
+                let mut elements = kind.split('-');
+                if elements.next() == Some("by") {
+                    return Some(cc_id(&elements.collect::<Vec<_>>(), version));
+                }
+            }
+            ["publicdomain", "zero", version, ..] if is_version(version) => return Some(format!("CC0-{}", version)),
+            _ => {}
+        }
+    }
+
+    let tokens: Vec<&str> = lower
+        .split(|c: char| !(c.is_alphanumeric() || c == '.'))
+        .map(|t| t.trim_matches('.'))
+        .filter(|t| !t.is_empty())
+        .collect();
+    tokens.iter().enumerate().find_map(|(i, &token)| {
+        if token == "cc0" {
+            return Some("CC0-1.0".to_string());
+        }
+        // "CC BY-NC 4.0", or "Creative Commons Attribution-NonCommercial 4.0 International"
+        let rest = match (token, tokens.get(i + 1), tokens.get(i + 2)) {
+            ("cc", Some(&"by"), _) => &tokens[i + 2..],
+            ("creative", Some(&"commons"), Some(&"attribution")) => &tokens[i + 3..],
+            _ => return None,
+        };
+        let mut elements = Vec::new();
+        let mut rest = rest;
+        while let Some((element, used)) = element(rest) {
+            elements.push(element);
+            rest = &rest[used..];
+        }
+        let version = rest.first().filter(|v| is_version(v))?;
+        Some(cc_id(&elements, version))
+    })
+}
+
+/// A license element at the start of `tokens`, and how many tokens it took
+fn element(tokens: &[&str]) -> Option<(&'static str, usize)> {
+    let first = *tokens.first()?;
+    let second = tokens.get(1).copied().unwrap_or("");
+    match (first, second) {
+        ("nc" | "noncommercial", _) => Some(("nc", 1)),
+        ("non", "commercial") => Some(("nc", 2)),
+        ("sa" | "sharealike", _) => Some(("sa", 1)),
+        ("share", "alike") => Some(("sa", 2)),
+        ("nd" | "noderivatives" | "noderivs", _) => Some(("nd", 1)),
+        ("no", "derivatives" | "derivs") => Some(("nd", 2)),
+        _ => None,
+    }
+}
+
+fn cc_id(elements: &[&str], version: &str) -> String {
+    let mut id = "CC-BY".to_string();
+    for (element, code) in ELEMENTS {
+        if elements.contains(element) {
+            id.push('-');
+            id.push_str(code);
+        }
+    }
+    format!("{}-{}", id, version)
+}
+
+fn is_version(text: &str) -> bool {
+    matches!(text, "1.0" | "2.0" | "2.5" | "3.0" | "4.0")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(style: LicenseHeader) -> Header {
+        Header::new(style, FrontMatterFormat::Yaml, Some("Free -- to share"), Some("CC-BY-4.0")).unwrap()
+    }
+
+    #[test]
+    fn no_rights_means_no_header() {
+        assert!(Header::new(LicenseHeader::Comment, FrontMatterFormat::Yaml, None, None).is_none());
+    }
+
+    #[test]
+    fn xml_comment_follows_the_declaration() {
+        let text = "<?xml version=\"1.0\"?>\n<speak/>\n";
+        let out = header(LicenseHeader::FrontMatter).apply(FileSyntax::Xml, text);
+        assert_eq!(out, "<?xml version=\"1.0\"?>\n<!--\nRights: Free - - to share\nLicense: CC-BY-4.0\n-->\n<speak/>\n");
+    }
+
+    #[test]
+    fn front_matter_joins_the_note_block() {
+        let text = "---\nid: x\n---\n\nBody\n";
+        let out = header(LicenseHeader::FrontMatter).apply(FileSyntax::FrontMatter, text);
+        assert_eq!(out, "---\nid: x\nrights: \"Free -- to share\"\nlicense: CC-BY-4.0\n---\n\nBody\n");
+
+        let out = header(LicenseHeader::Comment).apply(FileSyntax::FrontMatter, text);
+        assert!(out.starts_with("---\nid: x\n---\n\n<!--\nRights: Free -- to share\n"), "{}", out);
+        assert!(out.ends_with("-->\n\nBody\n"), "{}", out);
+    }
+
+    #[test]
+    fn properties_join_the_page_properties() {
+        let text = "title:: Book/Chapter 1\n\n- Body\n";
+        let out = header(LicenseHeader::FrontMatter).apply(FileSyntax::Properties, text);
+        assert_eq!(out, "title:: Book/Chapter 1\nrights:: Free -- to share\nlicense:: CC-BY-4.0\n\n- Body\n");
+
+        let out = header(LicenseHeader::Comment).apply(FileSyntax::Properties, text);
+        assert!(out.starts_with("title:: Book/Chapter 1\n\n<!--\n"), "{}", out);
+        assert!(out.ends_with("-->\n\n- Body\n"), "{}", out);
+    }
+}
@@ -0,0 +1,126 @@
+use crate::html;
+use rayon::prelude::*;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::process::Command;
+
+/// An external hyperlink found in the book's content
+#[derive(Debug, Serialize)]
+pub struct ExternalLink {
+    /// Target URL as written in the EPUB
+    pub url: String,
+    /// Output chapter number the link appears in
+    pub chapter: usize,
+    /// Path of the source XHTML document inside the EPUB
+    pub source: String,
+    /// Anchor text of the link
+    pub text: String,
+    /// Result of `--check-links`, absent when links were not checked
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub check: Option<LinkCheck>,
+}
+
+/// Outcome of verifying a single URL
+#[derive(Debug, Clone, Serialize)]
+pub struct LinkCheck {
+    /// Whether the URL resolved to a non-error response
+    pub ok: bool,
+    /// Final HTTP status code, if a response was received
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<u16>,
+    /// Transport-level error, if the request failed outright
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Collect all external (http/https) links from a chapter's HTML.
+/// `chapter` is left at 0 for the caller to fill in.
+pub fn extract_external_links(content: &str, source: &str) -> Vec<ExternalLink> {
+    // Most chapters have no external links; skip the second HTML parse for them
+    if !content.contains("http") && !content.contains("HTTP") {
+        return Vec::new();
+    }
+
+    let dom = html::parse(content);
+    let mut links = Vec::new();
+
+    html::walk(&dom.document, &mut |node| {
+        if html::tag_name(node).as_deref() != Some("a") {
+            return;
+        }
+        if let Some(href) = html::attr(node, "href") {
+            let href = href.trim();
+            if is_external(href) {
+                links.push(ExternalLink {
+                    url: href.to_string(),
+                    chapter: 0,
+                    source: source.to_string(),
+                    text: html::text_content(node),
+                    check: None,
+                });
+            }
+        }
+    });
+
+    links
+}
+
+fn is_external(href: &str) -> bool {
+    let lower = href.to_ascii_lowercase();
+    lower.starts_with("http://") || lower.starts_with("https://")
+}
+
+/// Verify every link, checking each distinct URL only once.
+/// Returns the number of broken links.
+pub fn check_links(links: &mut [ExternalLink]) -> usize {
+    let mut urls: Vec<&str> = links.iter().map(|l| l.url.as_str()).collect();
+    urls.sort_unstable();
+    urls.dedup();
+
+    let results: HashMap<String, LinkCheck> = urls
+        .par_iter()
+        .map(|url| (url.to_string(), check_url(url)))
+        .collect();
+
+    let mut broken = 0;
+    for link in links.iter_mut() {
+        let check = results[&link.url].clone();
+        if !check.ok {
+            broken += 1;
+        }
+        link.check = Some(check);
+    }
+
+    broken
+}
+
+/// Issue a HEAD request via curl, falling back to GET for servers that reject HEAD
+fn check_url(url: &str) -> LinkCheck {
+    match curl_status(url, true) {
+        Ok(status) if status < 400 => LinkCheck { ok: true, status: Some(status), error: None },
+        Ok(_) | Err(_) => match curl_status(url, false) {
+            Ok(status) => LinkCheck { ok: status < 400, status: Some(status), error: None },
+            Err(e) => LinkCheck { ok: false, status: None, error: Some(e) },
+        },
+    }
+}
+
+fn curl_status(url: &str, head: bool) -> Result<u16, String> {
+    let mut cmd = Command::new("curl");
+    cmd.args(["--silent", "--show-error", "--location", "--max-time", "15"])
+        .args(["--output", "/dev/null", "--write-out", "%{http_code}"]);
+    if head {
+        cmd.arg("--head");
+    }
+    let output = cmd
+        .arg(url)
+        .output()
+        .map_err(|e| format!("failed to run curl: {}", e))?;
+
+    let code = String::from_utf8_lossy(&output.stdout).trim().parse::<u16>().unwrap_or(0);
+    if code == 0 {
+        let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        return Err(if stderr.is_empty() { "no response".to_string() } else { stderr });
+    }
+    Ok(code)
+}
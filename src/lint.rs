@@ -0,0 +1,472 @@
+use crate::toc::{self, SlugRules, SlugSet};
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use rayon::prelude::*;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+/// Tags the converter itself leaves in Markdown: inline styles without a Markdown form,
+/// page anchors and right-to-left wrappers. Anything else is a leftover from the XHTML.
+const EXPECTED_TAGS: &[&str] = &["sup", "sub", "u", "ins", "mark", "del", "a", "br", "div"];
+
+/// How `lint` reports what it found
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum LintFormat {
+    /// One `file:line: kind: message` line per issue
+    Text,
+    /// JSON report with the issues of every book and totals by kind
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum IssueKind {
+    BrokenLink,
+    MissingImage,
+    EmptyChapter,
+    HeadingJump,
+    StrayHtml,
+}
+
+impl IssueKind {
+    fn label(self) -> &'static str {
+        match self {
+            IssueKind::BrokenLink => "broken_link",
+            IssueKind::MissingImage => "missing_image",
+            IssueKind::EmptyChapter => "empty_chapter",
+            IssueKind::HeadingJump => "heading_jump",
+            IssueKind::StrayHtml => "stray_html",
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct Issue {
+    kind: IssueKind,
+    /// Markdown file, relative to the book's output directory
+    file: String,
+    /// 1-based line number, for issues on a particular line
+    #[serde(skip_serializing_if = "Option::is_none")]
+    line: Option<usize>,
+    message: String,
+}
+
+#[derive(Debug, Serialize)]
+struct BookReport {
+    dir: String,
+    files: usize,
+    issues: Vec<Issue>,
+}
+
+#[derive(Debug, Default, Serialize)]
+struct Totals {
+    books: usize,
+    books_with_issues: usize,
+    issues: usize,
+    broken_link: usize,
+    missing_image: usize,
+    empty_chapter: usize,
+    heading_jump: usize,
+    stray_html: usize,
+}
+
+#[derive(Debug, Serialize)]
+struct LintReport {
+    books: Vec<BookReport>,
+    totals: Totals,
+}
+
+/// Check converted output for common conversion defects: relative links and images that
+/// point at nothing, chapters with no text, skipped heading levels and leftover HTML.
+/// `dir` may be one book's output directory or a batch output tree, in which case every
+/// directory with a metadata.json below it is checked. Fails if any issue is found.
+pub fn lint(dir: &Path, format: LintFormat, slug_rules: &SlugRules) -> Result<()> {
+    if !dir.is_dir() {
+        anyhow::bail!("Not a directory: {}", dir.display());
+    }
+    let books = book_dirs(dir);
+    let reports: Vec<BookReport> = books.par_iter().map(|book| lint_book(book, slug_rules)).collect::<Result<_>>()?;
+
+    let mut totals = Totals { books: reports.len(), ..Totals::default() };
+    for report in &reports {
+        if !report.issues.is_empty() {
+            totals.books_with_issues += 1;
+        }
+        for issue in &report.issues {
+            totals.issues += 1;
+            match issue.kind {
+                IssueKind::BrokenLink => totals.broken_link += 1,
+                IssueKind::MissingImage => totals.missing_image += 1,
+                IssueKind::EmptyChapter => totals.empty_chapter += 1,
+                IssueKind::HeadingJump => totals.heading_jump += 1,
+                IssueKind::StrayHtml => totals.stray_html += 1,
+            }
+        }
+    }
+
+    match format {
+        LintFormat::Text => {
+            for report in &reports {
+                for issue in &report.issues {
+                    let location = match issue.line {
+                        Some(line) => format!("{}:{}", Path::new(&report.dir).join(&issue.file).display(), line),
+                        None => Path::new(&report.dir).join(&issue.file).display().to_string(),
+                    };
+                    println!("{}: {}: {}", location, issue.kind.label(), issue.message);
+                }
+            }
+        }
+        LintFormat::Json => {
+            let (issues, affected) = (totals.issues, totals.books_with_issues);
+            let report = LintReport { books: reports, totals };
+            let json = serde_json::to_string_pretty(&report).context("Failed to serialize lint report")?;
+            println!("{}", json);
+            if issues > 0 {
+                anyhow::bail!("{} issue(s) found in {} book(s)", issues, affected);
+            }
+            return Ok(());
+        }
+    }
+    if totals.issues > 0 {
+        anyhow::bail!("{} issue(s) found in {} book(s)", totals.issues, totals.books_with_issues);
+    }
+    println!("No issues found in {} book(s)", totals.books);
+    Ok(())
+}
+
+/// Book output directories at or below `dir`: those holding a metadata.json, or `dir`
+/// itself when there are none
+fn book_dirs(dir: &Path) -> Vec<PathBuf> {
+    let mut books: Vec<PathBuf> = WalkDir::new(dir)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file() && e.file_name() == "metadata.json")
+        .filter_map(|e| e.path().parent().map(Path::to_path_buf))
+        .collect();
+    if books.is_empty() {
+        books.push(dir.to_path_buf());
+    }
+    books
+}
+
+fn lint_book(book: &Path, slug_rules: &SlugRules) -> Result<BookReport> {
+    let mut files: Vec<PathBuf> = WalkDir::new(book)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file() && e.path().extension().is_some_and(|x| ["md", "markdown", "txt"].iter().any(|ext| x == *ext)))
+        // A nested book is linted on its own
+        .filter(|e| e.path().parent().is_some_and(|p| p == book || !p.join("metadata.json").exists()))
+        .map(|e| e.into_path())
+        .collect();
+    files.sort();
+
+    let mut texts = HashMap::new();
+    for path in &files {
+        let text = fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        texts.insert(path.clone(), text);
+    }
+    let anchors: HashMap<&PathBuf, HashSet<String>> = texts.iter().map(|(path, text)| (path, anchors(text, slug_rules))).collect();
+
+    let mut issues = Vec::new();
+    for path in &files {
+        let text = &texts[path];
+        let file = path.strip_prefix(book).unwrap_or(path).to_string_lossy().replace('\\', "/");
+        let mut issue = |kind, line: Option<usize>, message: String| {
+            issues.push(Issue { kind, file: file.clone(), line: line.map(|l| l + 1), message })
+        };
+
+        let headings = toc::heading_lines(text);
+        for pair in headings.windows(2) {
+            let ((_, from, _), (line, to, text)) = (&pair[0], &pair[1]);
+            if to > &(from + 1) {
+                issue(IssueKind::HeadingJump, Some(*line), format!("h{} \"{}\" follows h{}", to, text, from));
+            }
+        }
+        if is_empty(text, &headings) {
+            issue(IssueKind::EmptyChapter, None, "no text besides headings".to_string());
+        }
+
+        for (line, content) in prose_lines(text) {
+            for tag in tags(&content) {
+                if !EXPECTED_TAGS.contains(&tag.as_str()) {
+                    issue(IssueKind::StrayHtml, Some(line), format!("<{}> tag", tag));
+                }
+            }
+            for (image, destination) in links(&content) {
+                let Some(problem) = check_destination(book, path, &destination, &anchors) else { continue };
+                let kind = if image { IssueKind::MissingImage } else { IssueKind::BrokenLink };
+                issue(kind, Some(line), format!("{}: {}", destination, problem));
+            }
+        }
+    }
+
+    issues.sort_by(|a, b| (&a.file, a.line).cmp(&(&b.file, b.line)));
+    Ok(BookReport { dir: book.display().to_string(), files: files.len(), issues })
+}
+
+/// Why a link destination is broken, or None if it resolves
+fn check_destination(
+    book: &Path,
+    from: &Path,
+    destination: &str,
+    anchors: &HashMap<&PathBuf, HashSet<String>>,
+) -> Option<String> {
+    let scheme = destination.split_once(':').is_some_and(|(s, _)| !s.is_empty() && !s.contains(['/', '#', '?']));
+    if scheme || destination.starts_with("//") || destination.is_empty() {
+        return None;
+    }
+    let (path, fragment) = match destination.split_once('#') {
+        Some((path, fragment)) => (path, Some(fragment)),
+        None => (destination, None),
+    };
+    let path = path.split('?').next().unwrap_or_default();
+
+    let target = if path.is_empty() {
+        from.to_path_buf()
+    } else {
+        let decoded = percent_encoding::percent_decode_str(path).decode_utf8_lossy();
+        let relative = from.parent().unwrap_or(book).strip_prefix(book).unwrap_or(Path::new(""));
+        let mut parts: Vec<String> = relative.iter().map(|p| p.to_string_lossy().into_owned()).collect();
+        for segment in decoded.split('/') {
+            match segment {
+                "" | "." => {}
+                ".." => {
+                    if parts.pop().is_none() {
+                        return Some("points outside the output directory".to_string());
+                    }
+                }
+                s => parts.push(s.to_string()),
+            }
+        }
+        let target = parts.iter().fold(book.to_path_buf(), |p, part| p.join(part));
+        if !target.exists() {
+            return Some("no such file".to_string());
+        }
+        target
+    };
+
+    let fragment = fragment.filter(|f| !f.is_empty())?;
+    let known = anchors.get(&target)?;
+    let fragment = percent_encoding::percent_decode_str(fragment).decode_utf8_lossy();
+    (!known.contains(fragment.as_ref())).then(|| format!("no anchor #{}", fragment))
+}
+
+/// Anchors a Markdown file defines: heading slugs in the given style and explicit `id`
+/// attributes
+fn anchors(text: &str, slug_rules: &SlugRules) -> HashSet<String> {
+    let mut slugs = SlugSet::with_rules(slug_rules.clone());
+    let mut found: HashSet<String> = toc::heading_lines(text).iter().map(|(_, _, t)| slugs.claim(t)).collect();
+    let mut rest = text;
+    while let Some(at) = rest.find("id=\"") {
+        rest = &rest[at + 4..];
+        if let Some(end) = rest.find('"') {
+            found.insert(rest[..end].to_string());
+        }
+    }
+    found
+}
+
+/// A file with nothing but headings. Lines repeating a heading are ignored too, since
+/// html2md echoes the XHTML `<title>` above the first heading.
+fn is_empty(text: &str, headings: &[(usize, usize, String)]) -> bool {
+    let lines: Vec<&str> = text.lines().collect();
+    let mut skip: HashSet<usize> = HashSet::new();
+    for (line, _, _) in headings {
+        skip.insert(*line);
+        // A setext underline
+        if lines.get(line + 1).is_some_and(|next| next.trim().chars().all(|c| c == '=' || c == '-')) {
+            skip.insert(line + 1);
+        }
+    }
+    let titles: HashSet<&str> = headings.iter().map(|(_, _, t)| t.as_str()).collect();
+    // YAML, TOML or JSON front matter, ending at its closing line
+    let front_matter_end = match lines.first().map(|l| l.trim_end()) {
+        Some("---") => Some("---"),
+        Some("+++") => Some("+++"),
+        Some("{") => Some("}"),
+        _ => None,
+    };
+    let mut front_matter_done = front_matter_end.is_none();
+    let mut in_comment = false;
+    for (i, line) in lines.iter().enumerate() {
+        let trimmed = line.trim();
+        if !front_matter_done {
+            front_matter_done = i > 0 && Some(trimmed) == front_matter_end;
+            continue;
+        }
+        if in_comment || trimmed.starts_with("<!--") {
+            in_comment = !trimmed.contains("-->");
+            continue;
+        }
+        if skip.contains(&i) || trimmed.is_empty() || titles.contains(trimmed) {
+            continue;
+        }
+        // Page anchors and wrapper tags carry no text
+        if trimmed.starts_with('<') && trimmed.ends_with('>') && !tags(trimmed).is_empty() {
+            let bare: String = strip_tags(trimmed);
+            if bare.trim().is_empty() {
+                continue;
+            }
+        }
+        return false;
+    }
+    true
+}
+
+/// Lines outside fenced code as (line index, text with code spans blanked out)
+fn prose_lines(text: &str) -> Vec<(usize, String)> {
+    let mut lines = Vec::new();
+    let mut fence: Option<String> = None;
+    for (i, line) in text.lines().enumerate() {
+        let trimmed = line.trim_start();
+        let marker: String = trimmed.chars().take_while(|&c| c == '`' || c == '~').collect();
+        match &fence {
+            Some(open) if trimmed.starts_with(open.as_str()) => fence = None,
+            Some(_) => {}
+            None if marker.len() >= 3 => fence = Some(marker),
+            None => lines.push((i, blank_code_spans(line))),
+        }
+    }
+    lines
+}
+
+fn blank_code_spans(line: &str) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut rest = line;
+    while let Some(start) = rest.find('`') {
+        let run = rest[start..].len() - rest[start..].trim_start_matches('`').len();
+        let fence = &rest[start..start + run];
+        let after = &rest[start + run..];
+        match after.find(fence) {
+            Some(end) => {
+                out.push_str(&rest[..start]);
+                out.push_str(&" ".repeat(run + end + run));
+                rest = &after[end + run..];
+            }
+            None => {
+                out.push_str(&rest[..start + run]);
+                rest = after;
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Lower-cased names of the HTML tags on a line, skipping escaped `\<` and autolinks
+fn tags(line: &str) -> Vec<String> {
+    let mut found = Vec::new();
+    let bytes = line.as_bytes();
+    for (i, _) in line.match_indices('<') {
+        if i > 0 && bytes[i - 1] == b'\\' {
+            continue;
+        }
+        let rest = &line[i + 1..];
+        let rest = rest.strip_prefix('/').unwrap_or(rest);
+        let name: String = rest.chars().take_while(|c| c.is_ascii_alphanumeric()).collect();
+        let after = rest[name.len()..].chars().next();
+        let closed = rest.contains('>');
+        if name.starts_with(|c: char| c.is_ascii_alphabetic())
+            && closed
+            && matches!(after, Some(' ' | '/' | '>' | '\t') | None)
+        {
+            found.push(name.to_ascii_lowercase());
+        }
+    }
+    found
+}
+
+fn strip_tags(text: &str) -> String {
+    let mut out = String::new();
+    let mut in_tag = false;
+    for c in text.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' => in_tag = false,
+            c if !in_tag => out.push(c),
+            _ => {}
+        }
+    }
+    out
+}
+
+/// Link and image destinations on a line, as (is image, destination), including
+/// `src` and `href` attributes of inline HTML
+fn links(line: &str) -> Vec<(bool, String)> {
+    let mut found = Vec::new();
+    markdown_links(line, &mut found);
+    for (attribute, image) in [("src=\"", true), ("href=\"", false)] {
+        let mut rest = line;
+        while let Some(at) = rest.find(attribute) {
+            rest = &rest[at + attribute.len()..];
+            if let Some(end) = rest.find('"') {
+                found.push((image, rest[..end].to_string()));
+            }
+        }
+    }
+    found
+}
+
+fn markdown_links(text: &str, found: &mut Vec<(bool, String)>) {
+    let mut i = 0;
+    while let Some(open) = text[i..].find('[').map(|n| i + n) {
+        if open > 0 && text.as_bytes()[open - 1] == b'\\' {
+            i = open + 1;
+            continue;
+        }
+        // The label ends at the matching bracket
+        let mut depth = 0;
+        let mut close = None;
+        let mut escaped = false;
+        for (n, c) in text[open..].char_indices() {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '[' => depth += 1,
+                ']' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        close = Some(open + n);
+                        break;
+                    }
+                }
+                _ => {}
+            }
+        }
+        let Some(close) = close else { return };
+        let after = &text[close + 1..];
+        if !after.starts_with('(') {
+            i = open + 1;
+            continue;
+        }
+        let mut depth = 0;
+        let Some(end) = after.char_indices().find_map(|(n, c)| {
+            match c {
+                '(' => depth += 1,
+                ')' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Some(n);
+                    }
+                }
+                _ => {}
+            }
+            None
+        }) else {
+            return;
+        };
+        let inside = after[1..end].trim();
+        let destination = inside.split_whitespace().next().unwrap_or_default();
+        let destination = destination.strip_prefix('<').and_then(|d| d.strip_suffix('>')).unwrap_or(destination);
+        let image = open > 0 && text.as_bytes()[open - 1] == b'!';
+        // An image inside a link's label
+        markdown_links(&text[open + 1..close], found);
+        found.push((image, destination.to_string()));
+        i = close + 1 + end + 1;
+    }
+}
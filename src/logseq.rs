@@ -0,0 +1,191 @@
+use crate::license::FileSyntax;
+use crate::output::{self, ChapterFiles};
+use crate::toc;
+use crate::BookMetadata;
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Directory of a Logseq graph that holds its pages
+pub const PAGES_DIR: &str = "pages";
+
+/// Writer for `--logseq`. Each chapter becomes a page in the book's namespace
+/// (`Book/Chapter 001`) with its paragraphs as outline blocks nested under their headings;
+/// `finish` writes the book's own page with its metadata as page properties and links to
+/// every chapter.
+pub struct LogseqWriter {
+    pages_dir: PathBuf,
+    /// Namespace the chapter pages live under
+    book: String,
+    chapters: Vec<String>,
+    files: ChapterFiles,
+}
+
+impl LogseqWriter {
+    pub fn create(output_dir: &Path, title: &str, files: ChapterFiles) -> Result<Self> {
+        let pages_dir = output_dir.join(PAGES_DIR);
+        fs::create_dir_all(&pages_dir).context("Failed to create pages directory")?;
+        Ok(Self {
+            pages_dir,
+            // A slash would open another namespace level
+            book: single_line(title).replace('/', "-"),
+            chapters: Vec::new(),
+            files,
+        })
+    }
+
+    /// Write one chapter page, returning its path relative to the output directory
+    pub fn write_chapter(&mut self, number: usize, width: usize, markdown: &str) -> Result<String> {
+        let page = format!("{}/Chapter {:0width$}", self.book, number, width = width);
+        let mut text = property("title", &page);
+        text.push_str(&property("book", &format!("[[{}]]", self.book)));
+        text.push_str(&property("chapter", &number.to_string()));
+        text.push('\n');
+        text.push_str(&to_blocks(markdown));
+
+        let file = page_file(&page);
+        self.files.write(&self.pages_dir.join(&file), FileSyntax::Properties, &text).context(format!("Failed to write {}", file))?;
+        self.chapters.push(page);
+        Ok(format!("{}/{}", PAGES_DIR, file))
+    }
+
+    /// Write the book page and return its path
+    pub fn finish(self, metadata: &BookMetadata) -> Result<PathBuf> {
+        let links = |names: &[String]| names.iter().map(|n| format!("[[{}]]", single_line(n))).collect::<Vec<_>>().join(", ");
+
+        let mut text = property("title", &self.book);
+        text.push_str(&property("type", "book"));
+        let mut optional = |key: &str, value: Option<String>| {
+            if let Some(value) = value.filter(|v| !v.trim().is_empty()) {
+                text.push_str(&property(key, &value));
+            }
+        };
+        optional("author", Some(links(&metadata.creators)));
+        optional("contributors", Some(links(&metadata.contributors)));
+        optional("series", metadata.series.as_ref().map(|s| format!("[[{}]]", single_line(s))));
+        optional("series-index", metadata.series_index.map(|i| i.to_string()));
+        optional("publisher", metadata.publisher.clone());
+        optional("published", metadata.date.clone());
+        optional("language", metadata.language.clone());
+        optional("tags", Some(metadata.tags.join(", ")));
+        optional("identifier", metadata.identifier.clone());
+        optional("rights", metadata.rights.clone());
+        optional("license", metadata.license.clone());
+        optional("description", metadata.description.clone());
+        text.push('\n');
+        for page in &self.chapters {
+            text.push_str(&format!("- [[{}]]\n", page));
+        }
+
+        let path = self.pages_dir.join(page_file(&self.book));
+        output::write_atomic(&path, self.files.newline.apply(&text).as_bytes()).context("Failed to write the Logseq book page")?;
+        Ok(path)
+    }
+}
+
+/// A `key:: value` page property line
+fn property(key: &str, value: &str) -> String {
+    format!("{}:: {}\n", key, single_line(value))
+}
+
+fn single_line(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// File name Logseq gives a page: namespace separators become `___` and characters that
+/// aren't allowed in file names are percent-encoded
+fn page_file(page: &str) -> String {
+    let mut name = String::with_capacity(page.len() + 3);
+    for c in page.chars() {
+        match c {
+            '/' => name.push_str("___"),
+            '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' | '#' | '%' => name.push_str(&format!("%{:02X}", c as u32)),
+            c => name.push(c),
+        }
+    }
+    name.push_str(".md");
+    name
+}
+
+/// Rewrite a chapter as a Logseq outline: every paragraph, list item, table and code
+/// block becomes a `- ` block, nested beneath the heading block it follows
+pub fn to_blocks(markdown: &str) -> String {
+    // Setext headings become ATX, so every heading is a single line
+    let markdown = toc::shift_headings(markdown, 0);
+    let mut out = String::with_capacity(markdown.len() + markdown.len() / 8);
+    let mut headings: Vec<usize> = Vec::new();
+    let mut block: Vec<&str> = Vec::new();
+    let mut fence: Option<&str> = None;
+
+    for line in markdown.lines() {
+        let trimmed = line.trim_start();
+        if let Some(marker) = fence {
+            block.push(line);
+            if trimmed.starts_with(marker) && trimmed.trim_end() == marker {
+                fence = None;
+                push_block(&mut out, &mut block, headings.len());
+            }
+            continue;
+        }
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            push_block(&mut out, &mut block, headings.len());
+            fence = Some(&trimmed[..3]);
+            block.push(line);
+            continue;
+        }
+        if line.trim().is_empty() {
+            push_block(&mut out, &mut block, headings.len());
+            continue;
+        }
+        if let Some(level) = heading_level(line) {
+            push_block(&mut out, &mut block, headings.len());
+            while headings.last().is_some_and(|&open| open >= level) {
+                headings.pop();
+            }
+            block.push(line);
+            push_block(&mut out, &mut block, headings.len());
+            headings.push(level);
+            continue;
+        }
+        // Each top-level list item is a block of its own, the block's bullet standing in
+        // for the item's
+        if line == trimmed && is_list_item(line) {
+            push_block(&mut out, &mut block, headings.len());
+            let unordered = line.starts_with(['-', '*', '+']);
+            block.push(if unordered { &line[2..] } else { line });
+            continue;
+        }
+        block.push(line);
+    }
+    push_block(&mut out, &mut block, headings.len());
+    out
+}
+
+fn push_block(out: &mut String, block: &mut Vec<&str>, depth: usize) {
+    let indent = "\t".repeat(depth);
+    for (i, line) in block.iter().enumerate() {
+        out.push_str(&indent);
+        out.push_str(if i == 0 { "- " } else { "  " });
+        out.push_str(line);
+        out.push('\n');
+    }
+    block.clear();
+}
+
+fn heading_level(line: &str) -> Option<usize> {
+    let hashes = line.len() - line.trim_start_matches('#').len();
+    let rest = &line[hashes..];
+    ((1..=6).contains(&hashes) && (rest.is_empty() || rest.starts_with(' '))).then_some(hashes)
+}
+
+fn is_list_item(line: &str) -> bool {
+    let rule: String = line.chars().filter(|c| !c.is_whitespace()).collect();
+    if rule.len() >= 3 && (rule.chars().all(|c| c == '*') || rule.chars().all(|c| c == '-')) {
+        return false;
+    }
+    if line.starts_with("- ") || line.starts_with("* ") || line.starts_with("+ ") {
+        return true;
+    }
+    let digits = line.len() - line.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    digits > 0 && (line[digits..].starts_with(". ") || line[digits..].starts_with(") "))
+}
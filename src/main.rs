@@ -1,24 +1,410 @@
 use anyhow::{Context, Result};
-use clap::Parser;
+use cli::{Cli, Command, ConvertArgs, LimitArgs, OutputArgs, RunArgs};
 use epub::doc::EpubDoc;
+use error::ConvertError;
 use rayon::prelude::*;
+use progress::{Event, Progress, ProgressFormat};
 use serde::Serialize;
+use stats::BookStats;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
+use std::panic::{self, AssertUnwindSafe};
 use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
 use walkdir::WalkDir;
 
-#[derive(Parser)]
-#[command(name = "epub-to-md")]
-#[command(about = "Convert EPUB files to Markdown format", long_about = None)]
-struct Cli {
-    #[arg(help = "Path to an EPUB file or a directory containing EPUB files")]
-    input: PathBuf,
+mod alttext;
+mod analysis;
+mod anki;
+mod archive;
+mod artifacts;
+mod ascii;
+mod build;
+mod cache;
+mod callout;
+mod catalog;
+mod bundle;
+mod calibre;
+mod check;
+mod cli;
+mod combined;
+mod completions;
+mod config;
+mod daemon;
+mod dehyphenate;
+mod dendron;
+mod diff;
+mod digest;
+mod error;
+mod extract;
+mod frontmatter;
+mod filter;
+mod git;
+mod headings;
+mod html;
+mod images;
+mod input;
+mod inspect;
+mod interrupt;
+mod isolate;
+mod jex;
+mod journal;
+mod layout;
+mod license;
+mod links;
+mod lint;
+mod logseq;
+mod naming;
+mod normalize;
+mod notion;
+mod opds;
+mod organize;
+mod obfuscation;
+mod output;
+mod overrides;
+mod parts;
+mod overlay;
+mod picker;
+mod progress;
+mod provenance;
+mod quotes;
+mod references;
+mod remote;
+mod render;
+mod rendition;
+mod rules;
+mod sample;
+mod saved;
+mod sections;
+mod sqlite;
+mod sentences;
+mod series;
+mod spans;
+mod ssml;
+mod stats;
+mod styles;
+mod summary;
+mod tidy;
+mod tags;
+mod toc;
+mod units;
+mod upload;
+mod vocab;
+mod warnings;
 
-    #[arg(short, long, help = "Output directory for Markdown files")]
-    output: Option<PathBuf>,
+/// Options controlling how each EPUB is converted. Those that change the output make up
+/// the cache fingerprint; see `fingerprint`.
+#[derive(Debug, Clone)]
+struct ConvertOptions {
+    /// Merge all chapters into a single Markdown file
+    single_file: bool,
+    /// Line endings of the Markdown and SSML files written
+    newline: output::Newline,
+    /// Largest volume the single file may grow to before it is split
+    volume_limit: combined::VolumeLimit,
+    /// Levels of table-of-contents nesting kept; deeper entries are flattened to the last
+    toc_depth: Option<usize>,
+    /// Write chapters as Logseq pages with outline blocks and page properties
+    logseq: bool,
+    /// Write a hierarchy of Dendron notes, one per chapter and section
+    dendron: bool,
+    /// Keep to the Markdown subset Notion imports and write notion.csv
+    notion: bool,
+    /// Markdown files, or another format the chapters are packaged in
+    format: output::OutputFormat,
+    /// Verify external links before writing links.json
+    check_links: bool,
+    /// Write bibliography entries to references.json
+    references: bool,
+    /// Analyses written to analysis.json
+    analyze: Vec<analysis::Analysis>,
+    /// Write a frequency-ranked vocabulary list
+    vocab: Option<vocab::VocabFormat>,
+    /// File name of the flashcard deck made from the book's glossaries
+    anki: Option<String>,
+    /// Copy stylesheets and fonts into styles/
+    extract_styles: bool,
+    /// Copy the audio of media overlays into audio/
+    extract_audio: bool,
+    /// Write the images chapters show to images/, named by content hash
+    extract_images: bool,
+    /// Command describing images that have no alt text
+    alt_text_cmd: Option<alttext::AltTextCommand>,
+    /// Where the read-along timing of media overlays is written, if anywhere
+    read_along: Option<overlay::ReadAlong>,
+    /// Segment paragraphs into one sentence per line
+    sentence_per_line: bool,
+    /// Mark where each chapter's text came from with an HTML comment
+    source_comments: bool,
+    /// Remove running headers, footers and page numbers left over from scanned print books
+    clean_artifacts: bool,
+    /// Join words hyphenated across print line breaks, and paragraphs split by page breaks
+    dehyphenate: bool,
+    /// Prepend the TOC label as a heading to chapters that lack one
+    inject_title: bool,
+    /// How chapter XHTML is turned into Markdown
+    render: render::RenderOptions,
+    /// Collapse blank lines and spaces and drop empty inline markup after rendering
+    tidy: bool,
+    /// Markup added to chapters of right-to-left books
+    rtl_markup: layout::RtlMarkup,
+    /// How chapter files are numbered and padded
+    naming: naming::ChapterNaming,
+    /// Whether chapter files go in a directory per part
+    directory_layout: parts::DirectoryLayout,
+    /// Special sections left out of the output
+    skip: Vec<sections::Section>,
+    /// Convert table-of-contents pages rather than skipping them
+    keep_toc_page: bool,
+    /// Convert spine documents that render the same as an earlier one instead of skipping them
+    keep_duplicates: bool,
+    /// Promote paragraphs styled as headings in chapters that have none
+    detect_headings: bool,
+    /// Rendition of multi-rendition books to convert, instead of the first reflowable one
+    rendition: Option<String>,
+    /// Publisher cleanup rules, applied to the books they name
+    rules: rules::RulePacks,
+    /// Spine indices to convert, when only some chapters were picked
+    chapters: Option<Vec<usize>>,
+    /// Put the rights statement at the top of every output file
+    license_header: Option<license::LicenseHeader>,
+    /// Which spine media is converted, extracted or left out
+    mime_policy: archive::MimePolicy,
+    /// Renamed or dropped tags, by the tag their subject would make
+    tag_map: Vec<(String, String)>,
+    /// Syntax of the license front matter
+    frontmatter_format: frontmatter::FrontMatterFormat,
+    /// How heading anchors in combined files' contents are spelled
+    slug_rules: toc::SlugRules,
+    /// Rewrite text to ASCII, for tools that can't take curly quotes and dashes
+    ascii_only: Option<ascii::AsciiOnly>,
+    /// The book's record in the Calibre library it is converted from, whose metadata
+    /// takes the place of the EPUB's
+    calibre: Option<calibre::Book>,
+    /// `--set` corrections to the book's metadata, applied over the EPUB's and Calibre's
+    overrides: Vec<overrides::MetadataOverride>,
+    /// Unicode normalization applied to text and derived file names
+    normalize: normalize::Normalization,
+    /// Compare a fresh conversion against existing output instead of writing it
+    check: bool,
+    /// Print timing statistics after each book
+    stats: bool,
+    /// Conversion cache, when enabled
+    cache: Option<cache::ConversionCache>,
+    /// Where status lines and progress events go
+    progress: Progress,
+    /// Per-book time limit
+    timeout: Option<Duration>,
+    /// Set when this book's conversion should stop early
+    cancel: isolate::CancelToken,
+    /// Limits enforced while reading untrusted archives
+    limits: archive::ResourceLimits,
+    /// Option values recorded in metadata.json
+    settings: provenance::Settings,
+    /// Record the conversion time in the provenance block
+    record_time: bool,
+}
+
+impl ConvertOptions {
+    /// The options that change what a conversion writes, for the cache key. The fields are
+    /// listed exhaustively, so a new one has to be placed on one side or the other: how the
+    /// run reports and bounds its work (progress, timeout, limits, the cache itself),
+    /// whether it only checks, and the settings and time recorded for provenance (rewritten
+    /// when a conversion is restored) don't make a cached conversion stale.
+    fn fingerprint(&self) -> String {
+        let ConvertOptions {
+            single_file,
+            newline,
+            volume_limit,
+            toc_depth,
+            logseq,
+            dendron,
+            notion,
+            format,
+            check_links,
+            references,
+            analyze,
+            vocab,
+            anki,
+            extract_styles,
+            extract_audio,
+            extract_images,
+            alt_text_cmd,
+            read_along,
+            sentence_per_line,
+            source_comments,
+            clean_artifacts,
+            dehyphenate,
+            inject_title,
+            render,
+            tidy,
+            rtl_markup,
+            naming,
+            directory_layout,
+            skip,
+            keep_toc_page,
+            keep_duplicates,
+            detect_headings,
+            rendition,
+            rules,
+            chapters,
+            license_header,
+            mime_policy,
+            tag_map,
+            frontmatter_format,
+            slug_rules,
+            ascii_only,
+            calibre,
+            overrides,
+            normalize,
+            check: _,
+            stats,
+            cache: _,
+            progress: _,
+            timeout: _,
+            cancel: _,
+            limits: _,
+            settings: _,
+            record_time: _,
+        } = self;
+        let mut fingerprint = String::new();
+        let mut add = |name: &str, value: &dyn std::fmt::Debug| fingerprint.push_str(&format!("{}={:?}\n", name, value));
+        add("single_file", single_file);
+        add("newline", newline);
+        add("volume_limit", volume_limit);
+        add("toc_depth", toc_depth);
+        add("logseq", logseq);
+        add("dendron", dendron);
+        add("notion", notion);
+        add("format", format);
+        add("check_links", check_links);
+        add("references", references);
+        add("analyze", analyze);
+        add("vocab", vocab);
+        add("anki", anki);
+        add("extract_styles", extract_styles);
+        add("extract_audio", extract_audio);
+        add("extract_images", extract_images);
+        add("alt_text_cmd", alt_text_cmd);
+        add("read_along", read_along);
+        add("sentence_per_line", sentence_per_line);
+        add("source_comments", source_comments);
+        add("clean_artifacts", clean_artifacts);
+        add("dehyphenate", dehyphenate);
+        add("inject_title", inject_title);
+        add("render", render);
+        add("tidy", tidy);
+        add("rtl_markup", rtl_markup);
+        add("naming", naming);
+        add("directory_layout", directory_layout);
+        add("skip", skip);
+        add("keep_toc_page", keep_toc_page);
+        add("keep_duplicates", keep_duplicates);
+        add("detect_headings", detect_headings);
+        add("rendition", rendition);
+        add("rules", rules);
+        add("chapters", chapters);
+        add("license_header", license_header);
+        add("mime_policy", mime_policy);
+        add("tag_map", tag_map);
+        add("frontmatter_format", frontmatter_format);
+        add("slug_rules", slug_rules);
+        add("ascii_only", ascii_only);
+        add("calibre", calibre);
+        add("overrides", overrides);
+        add("normalize", normalize);
+        add("stats", stats);
+        fingerprint
+    }
+}
+
+/// Options that apply to a batch as a whole rather than to each book,
+/// kept apart from `ConvertOptions` so they don't affect the cache fingerprint
+#[derive(Debug, Clone, Default)]
+struct BatchOptions {
+    /// Write one combined Markdown file per detected series
+    merge_series: bool,
+    /// Write catalog.json and catalog.csv indexing every converted book
+    catalog: bool,
+    /// Write summary.csv with per-book timing and sizes
+    summary_csv: bool,
+    /// How many times a book is retried after a transient I/O error
+    retries: usize,
+    /// Wait before the first retry, doubled for each one after
+    retry_delay: Duration,
+    /// Only convert books whose metadata matches all of these
+    filters: Vec<filter::MetadataFilter>,
+    /// Name output directories from metadata instead of the EPUB file names
+    organize: Option<organize::Layout>,
+    /// Commit each converted book to a git repository at the output root
+    git: bool,
+    /// Leave out books that look like retailer samples
+    skip_samples: bool,
+    /// File-name extensions EPUBs are recognized by
+    extensions: input::EpubExtensions,
+    /// The zip or tar archive each EPUB unpacked from one came from; its output goes
+    /// next to the archive unless an output directory is given
+    unpacked_from: HashMap<PathBuf, bundle::Unpacked>,
+    /// Calibre's record of each book converted from a Calibre library, by EPUB path
+    calibre: HashMap<PathBuf, calibre::Book>,
+}
+
+impl BatchOptions {
+    /// How a batch book is named in the summary and catalog: its path, or the archive and
+    /// entry it was unpacked from
+    fn source_name(&self, epub_path: &Path) -> String {
+        match self.unpacked_from.get(epub_path) {
+            Some(unpacked) => unpacked.name(),
+            None => epub_path.to_string_lossy().into_owned(),
+        }
+    }
+}
+
+/// What a successful conversion produced
+#[derive(Debug, Default)]
+struct BookOutcome {
+    /// Every file written into the output directory
+    files: Vec<PathBuf>,
+    /// Whether the files were restored from the cache
+    cached: bool,
+}
+
+/// A spine document read from the EPUB, ready for rendering.
+/// Instances are reused across batches so their buffers keep their capacity.
+#[derive(Default)]
+struct SpineDocument {
+    /// Position in the spine
+    index: usize,
+    /// Path of the XHTML file inside the EPUB
+    source: String,
+    /// Raw XHTML content
+    content: String,
+    /// Whether the spine item is an image or extracted media, shown on a page of its own
+    image: bool,
+}
 
-    #[arg(short, long, help = "Create a single merged Markdown file instead of separate files")]
-    single: bool,
+/// A spine document converted to Markdown
+struct RenderedChapter {
+    markdown: String,
+    /// External links, with `chapter` filled in once the output number is known
+    links: Vec<links::ExternalLink>,
+    /// Bibliography entries, with `chapter` filled in once the output number is known
+    references: Vec<references::Reference>,
+    /// Glossary terms and definitions, with `--anki`
+    glossary: Vec<anki::Entry>,
+    /// Ids of the page-marker anchors in the Markdown
+    page_anchors: Vec<String>,
+    /// Special section this chapter is, if any
+    section: Option<sections::Section>,
+    /// SSML body of the chapter, with `--format ssml`
+    ssml: Option<String>,
+    /// Read-along timing of its paragraphs, with `--read-along`
+    read_along: Vec<overlay::Paragraph>,
+    /// Styled paragraphs promoted to headings
+    promoted_headings: usize,
 }
 
 /// Metadata extracted from an EPUB file
@@ -38,10 +424,15 @@ struct BookMetadata {
     date: Option<String>,
     /// Book subjects/categories
     subjects: Vec<String>,
+    /// The subjects as clean, slugged tags
+    tags: Vec<String>,
     /// Unique identifier (ISBN, UUID, etc.)
     identifier: Option<String>,
     /// Rights/copyright information
     rights: Option<String>,
+    /// SPDX identifier of the license, when one is recognized in the rights or license metadata
+    #[serde(skip_serializing_if = "Option::is_none")]
+    license: Option<String>,
     /// Contributors (editors, illustrators, etc.)
     contributors: Vec<String>,
     /// Source of the book
@@ -52,8 +443,148 @@ struct BookMetadata {
     release_identifier: Option<String>,
     /// Number of chapters in spine
     chapter_count: usize,
+    /// Series the book belongs to (EPUB3 collection or calibre series)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    series: Option<String>,
+    /// Position within the series
+    #[serde(skip_serializing_if = "Option::is_none")]
+    series_index: Option<f64>,
+    /// Calibre custom columns, by lookup name, for books from a Calibre library
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    custom_columns: BTreeMap<String, serde_json::Value>,
+    /// Original writing mode, when the book was typeset vertically
+    #[serde(skip_serializing_if = "Option::is_none")]
+    writing_mode: Option<String>,
+    /// Text direction, when it runs right to left
+    #[serde(skip_serializing_if = "Option::is_none")]
+    text_direction: Option<String>,
+    /// Rendition layout, when the book is fixed layout (`pre-paginated`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rendition_layout: Option<String>,
+    /// Words in the converted Markdown
+    #[serde(skip_serializing_if = "Option::is_none")]
+    word_count: Option<usize>,
     /// Table of contents entries
     toc: Vec<TocEntry>,
+    /// Where each converted spine item ended up, in reading order
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    chapters: Vec<ChapterEntry>,
+    /// Special sections found, and how each was handled
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    sections: Vec<sections::SectionReport>,
+    /// Stylesheets and fonts copied by `--extract-styles`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    styles: Vec<styles::StyleEntry>,
+    /// Audio copied by `--extract-audio`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    audio: Vec<overlay::AudioEntry>,
+    /// Where `--extract-images` wrote each image, by its path inside the EPUB
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    images: BTreeMap<String, String>,
+    /// What was lost or doubtful in the conversion
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    warnings: Vec<warnings::Warning>,
+    /// Publisher rule packs applied to the chapters
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    rules: Vec<String>,
+    /// Why the book looks like a retailer sample rather than the full text
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sample: Option<String>,
+    /// Package document converted, when the book has several renditions
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rendition: Option<String>,
+    /// Source EPUB, time and settings of the conversion
+    #[serde(skip_serializing_if = "Option::is_none")]
+    provenance: Option<provenance::Provenance>,
+}
+
+impl BookMetadata {
+    /// Apply `--set` corrections, in the order given
+    fn apply_overrides(&mut self, corrections: &[overrides::MetadataOverride]) {
+        use overrides::Field;
+        // List fields already replaced, which later `field=value` corrections add to
+        let mut replaced: Vec<Field> = Vec::new();
+        for correction in corrections {
+            let value = Some(correction.value.clone()).filter(|v| !v.is_empty());
+            let list = match correction.field {
+                Field::Creator => Some(&mut self.creators),
+                Field::Contributor => Some(&mut self.contributors),
+                Field::Subject => Some(&mut self.subjects),
+                _ => None,
+            };
+            if let Some(list) = list {
+                if !correction.append && !replaced.contains(&correction.field) {
+                    list.clear();
+                    replaced.push(correction.field);
+                }
+                list.extend(value);
+                continue;
+            }
+            match correction.field {
+                Field::Title => self.title = value,
+                Field::Language => self.language = value,
+                Field::Description => self.description = value,
+                Field::Publisher => self.publisher = value,
+                Field::Date => self.date = value,
+                Field::Identifier => self.identifier = value,
+                Field::Rights => {
+                    self.license = value.as_deref().and_then(|rights| license::detect(std::iter::once(rights)));
+                    self.rights = value;
+                }
+                Field::Source => self.source = value,
+                Field::Series => self.series = value,
+                Field::SeriesIndex => self.series_index = value.and_then(|v| v.parse().ok()),
+                Field::Creator | Field::Contributor | Field::Subject => {}
+            }
+        }
+    }
+
+    /// Normalize every text field, so metadata.json and names derived from it match the chapters
+    fn normalize(&mut self, form: normalize::Normalization) {
+        self.rewrite_text(&|text| form.apply_mut(text));
+    }
+
+    /// Rewrite every text field with `rewrite`
+    fn rewrite_text(&mut self, rewrite: &dyn Fn(&mut String)) {
+        fn toc(entries: &mut [TocEntry], rewrite: &dyn Fn(&mut String)) {
+            for entry in entries {
+                rewrite(&mut entry.label);
+                toc(&mut entry.children, rewrite);
+            }
+        }
+
+        let fields = [
+            &mut self.title,
+            &mut self.language,
+            &mut self.description,
+            &mut self.publisher,
+            &mut self.date,
+            &mut self.identifier,
+            &mut self.rights,
+            &mut self.source,
+            &mut self.series,
+        ];
+        for value in fields.into_iter().flatten() {
+            rewrite(value);
+        }
+        for value in self.creators.iter_mut().chain(&mut self.subjects).chain(&mut self.contributors) {
+            rewrite(value);
+        }
+        toc(&mut self.toc, rewrite);
+    }
+}
+
+/// Mapping from a spine item to the output it produced
+#[derive(Debug, Serialize)]
+struct ChapterEntry {
+    /// Chapter number used in file names and progress events
+    number: usize,
+    /// Zero-based position in the spine
+    spine_index: usize,
+    /// Path of the XHTML file inside the EPUB
+    source: String,
+    /// Output file holding the chapter
+    file: String,
 }
 
 /// Table of contents entry
@@ -64,108 +595,1061 @@ struct TocEntry {
     children: Vec<TocEntry>,
 }
 
+/// `entries` with everything nested more than `depth` levels deep lifted up to that
+/// level, keeping reading order
+fn flatten_toc(entries: Vec<TocEntry>, depth: usize) -> Vec<TocEntry> {
+    let mut flattened = Vec::with_capacity(entries.len());
+    for mut entry in entries {
+        let children = std::mem::take(&mut entry.children);
+        if depth <= 1 {
+            flattened.push(entry);
+            flattened.extend(flatten_toc(children, 1));
+        } else {
+            entry.children = flatten_toc(children, depth - 1);
+            flattened.push(entry);
+        }
+    }
+    flattened
+}
+
 fn main() -> Result<()> {
-    let cli = Cli::parse();
+    let cli = Cli::parse_with_default();
+    if matches!(cli.command, Command::Convert(_) | Command::Check(_) | Command::Batch(_)) {
+        interrupt::install();
+    }
+    match cli.command {
+        Command::Convert(args) => run_conversion(&args, false, None),
+        Command::Check(args) => run_conversion(&args, true, None),
+        Command::Batch(args) => run_batch(&args, None),
+        Command::Inspect(args) => inspect::print_summary(&args.input),
+        Command::Metadata(args) => {
+            let mut doc = EpubDoc::new(&args.input)
+                .context("Failed to open EPUB file")?;
+            toc::fill_from_nav(&mut doc);
+            let metadata_json = serde_json::to_string_pretty(&extract_metadata(&doc))
+                .context("Failed to serialize metadata")?;
+            println!("{}", metadata_json);
+            Ok(())
+        }
+        Command::Diff(args) => {
+            let mut options = build_options(&args.output, &RunArgs::default(), &args.limits, false)?;
+            // Chapters are compared file by file, and the conversions' own progress would mix with the diff
+            options.single_file = false;
+            options.format = output::OutputFormat::Markdown;
+            options.progress.quiet = true;
+            diff::diff_editions(&args.old, &args.new, &options, args.format, args.context)
+        }
+        Command::Extract(args) => {
+            let output_dir = args.output.clone().unwrap_or_else(|| {
+                PathBuf::from(format!("{}_markdown", input::EpubExtensions::default().stem(&args.input)))
+            });
+            extract::extract_resources(&args.input, &output_dir, args.all, resource_limits(&args.limits))
+        }
+        Command::Build(args) => {
+            let output_path = args.output.clone().unwrap_or_else(|| {
+                // book_markdown/ becomes book_rebuilt.epub beside it, leaving the original EPUB alone
+                let dir = args.input.canonicalize().unwrap_or_else(|_| args.input.clone());
+                let name = dir.file_name().unwrap_or_default().to_string_lossy().into_owned();
+                let stem = name.strip_suffix("_markdown").unwrap_or(&name);
+                dir.with_file_name(format!("{}_rebuilt.epub", stem))
+            });
+            build::build_epub(&args.input, &output_path)
+        }
+        Command::Lint(args) => {
+            let slug_rules = toc::SlugRules { style: args.slug_style, separator: args.slug_separator.clone() };
+            lint::lint(&args.input, args.format, &slug_rules)
+        }
+        Command::Quotes(args) => {
+            let output_path = args.output.clone().unwrap_or_else(|| {
+                let stem = input::EpubExtensions::default().stem(&args.input);
+                PathBuf::from(format!("{}_quotes.{}", stem, args.format.extension()))
+            });
+            quotes::extract_quotes(&args.input, &output_path, args.format, args.min_words, args.dialogue)
+        }
+        Command::Daemon(args) => daemon::serve(&args.socket, args.jobs as usize),
+        Command::Completions(args) => {
+            print!("{}", completions::generate(args.shell, &cli_command()));
+            Ok(())
+        }
+        Command::Man => {
+            print!("{}", completions::man_page(&cli_command()));
+            Ok(())
+        }
+    }
+}
+
+/// The fully built clap command, including the generated help flags and subcommand
+fn cli_command() -> clap::Command {
+    let mut cmd = <Cli as clap::CommandFactory>::command();
+    cmd.build();
+    cmd
+}
+
+/// Build conversion options from the shared command-line option groups
+fn build_options(output: &OutputArgs, run: &RunArgs, limits: &LimitArgs, check: bool) -> Result<ConvertOptions> {
+    let cache = if run.cache || run.cache_dir.is_some() {
+        Some(cache::ConversionCache::new(run.cache_dir.as_deref())?)
+    } else {
+        None
+    };
+
+    if output.format != output::OutputFormat::Markdown && (output.single || output.logseq || output.notion || output.dendron) {
+        anyhow::bail!("--single, --logseq, --notion and --dendron only apply to --format markdown");
+    }
+
+    if let Some(deck) = &output.anki {
+        let plain = Path::new(deck).file_name().is_some_and(|name| name == deck.as_str());
+        if !plain || anki::DeckFormat::from_name(deck).is_none() {
+            anyhow::bail!("--anki takes a file name ending in .apkg, .tsv or .txt, written into each book's output directory");
+        }
+    }
+
+    Ok(ConvertOptions {
+        single_file: output.single,
+        newline: output.newline,
+        volume_limit: combined::VolumeLimit { max_bytes: output.max_file_size, max_words: output.max_words },
+        toc_depth: output.toc_depth.map(|depth| depth as usize),
+        logseq: output.logseq,
+        format: output.format,
+        notion: output.notion,
+        dendron: output.dendron,
+        check_links: output.check_links,
+        references: output.references,
+        analyze: output.analyze.clone(),
+        vocab: output.vocab,
+        anki: output.anki.clone(),
+        extract_styles: output.extract_styles,
+        extract_audio: output.extract_audio,
+        extract_images: output.extract_images,
+        alt_text_cmd: output.alt_text_cmd.clone(),
+        read_along: output.read_along,
+        sentence_per_line: output.sentence_per_line,
+        source_comments: output.source_comments,
+        clean_artifacts: output.clean_artifacts,
+        dehyphenate: output.dehyphenate,
+        inject_title: output.inject_title,
+        render: render::RenderOptions {
+            flavor: output.flavor,
+            underline: output.underline,
+            highlight: output.highlight,
+            scene_break: output.scene_break.clone(),
+            callouts: output.callouts.or(output.notion.then_some(callout::CalloutStyle::Notion)),
+            callout_classes: output.callout_class.clone(),
+            page_markers: output.page_markers,
+            read_along: output.read_along == Some(overlay::ReadAlong::Comments),
+        },
+        tidy: !output.no_tidy,
+        rtl_markup: output.rtl_markup,
+        naming: naming::ChapterNaming {
+            numbering: output.numbering,
+            start: output.number_start,
+            width: output.number_width,
+            extension: output.extension,
+        },
+        directory_layout: output.layout,
+        skip: output.skip.clone(),
+        keep_toc_page: output.keep_toc_page,
+        keep_duplicates: output.keep_duplicates,
+        detect_headings: !output.no_heading_detection,
+        rendition: output.rendition.clone(),
+        rules: rules::RulePacks::load(&output.rules, output.no_default_rules)?,
+        chapters: None,
+        license_header: output.license_header,
+        frontmatter_format: output.frontmatter_format,
+        slug_rules: toc::SlugRules { style: output.slug_style, separator: output.slug_separator.clone() },
+        ascii_only: output.ascii_only.filter(|_| !output.keep_unicode),
+        tag_map: output.tag_map.clone(),
+        calibre: None,
+        overrides: output.set.clone(),
+        mime_policy: archive::MimePolicy {
+            allow: output.allow_mime.clone(),
+            deny: output.deny_mime.clone(),
+            extract: output.extract_mime.clone(),
+        },
+        normalize: output.normalize,
+        check: check || run.check,
+        stats: run.stats,
+        cache,
+        progress: Progress {
+            format: run.progress,
+            quiet: false,
+            paths: run.print_paths.then_some(if run.null { b'\0' } else { b'\n' }),
+            sink: None,
+            verbose: run.verbose,
+        },
+        timeout: run.timeout,
+        cancel: isolate::CancelToken::default(),
+        limits: resource_limits(limits),
+        settings: provenance::Settings::default(),
+        record_time: output.record_time,
+    })
+}
+
+fn resource_limits(limits: &LimitArgs) -> archive::ResourceLimits {
+    archive::ResourceLimits {
+        max_resource_bytes: limits.max_resource_size,
+        max_total_bytes: limits.max_total_size,
+        max_chapters: limits.max_chapters,
+        max_memory_bytes: limits.max_memory,
+    }
+}
+
+/// Convert every EPUB under a directory
+fn run_batch(args: &ConvertArgs, sink: Option<progress::Sink>) -> Result<()> {
+    if let Some(input) = args.input.as_ref().filter(|i| !i.is_dir()) {
+        anyhow::bail!("Batch input must be a directory: {}", input.display());
+    }
+    run_conversion(args, false, sink)
+}
+
+/// Convert (or check) a single EPUB or a directory of them. With a `sink`, progress
+/// goes there as JSON events whatever `--progress` says.
+fn run_conversion(args: &ConvertArgs, check: bool, sink: Option<progress::Sink>) -> Result<()> {
+    if let Some(jobs) = args.run.jobs {
+        // The pool can only be sized once per process; a daemon keeps its first job's size
+        let _ = rayon::ThreadPoolBuilder::new().num_threads(jobs as usize).build_global();
+    }
+    let mut options = build_options(&args.output, &args.run, &args.limits, check)?;
+    let ascii_only = options.ascii_only.and_then(|scope| clap::ValueEnum::to_possible_value(&scope)).map(|value| value.get_name().to_string());
+    options.settings = args.settings.resolved("tidy", "no-tidy", options.tidy).resolved("ascii-only", "keep-unicode", ascii_only);
+    if let Some(sink) = sink {
+        options.progress = Progress { format: ProgressFormat::Json, quiet: false, paths: None, sink: Some(sink), verbose: false };
+    }
+    let batch = BatchOptions {
+        merge_series: args.output.merge_series,
+        catalog: args.output.catalog,
+        summary_csv: args.output.summary_csv,
+        retries: args.run.retries,
+        retry_delay: args.run.retry_delay,
+        filters: args.filter.clone(),
+        organize: args.output.organize.clone(),
+        git: args.output.git,
+        skip_samples: args.skip_samples,
+        extensions: input::EpubExtensions::new(args.extensions.iter().map(String::as_str)),
+        unpacked_from: HashMap::new(),
+        calibre: HashMap::new(),
+    };
+    let output = args.output.output.as_deref();
+    let Some(url) = output.and_then(|o| o.to_str()).filter(|o| upload::is_object_url(o)) else {
+        return convert_input(args, output, options, batch);
+    };
+
+    // Convert into a scratch directory, then upload it as a whole
+    let destination = upload::Destination::parse(url)?;
+    if check {
+        anyhow::bail!("--check compares against local output and can't read back from {}", destination);
+    }
+    if batch.git {
+        anyhow::bail!("--git needs local output to keep a repository in, not {}", destination);
+    }
+    let uploader = upload::Uploader::new(&destination)?;
+    let scratch = check::ScratchDir::new("upload")?;
+    let progress = options.progress.clone();
+    convert_input(args, Some(scratch.path()), options, batch)?;
+    progress.text(format!("Uploading to {}...", destination));
+    let count = uploader.upload_dir(scratch.path())?;
+    progress.text(format!("Uploaded {} file(s) to {}", count, destination));
+    Ok(())
+}
+
+/// Convert the input named by `args` into `output`
+fn convert_input(args: &ConvertArgs, output: Option<&Path>, mut options: ConvertOptions, batch: BatchOptions) -> Result<()> {
+    let single_local_file = args.input.as_deref().is_some_and(|i| i.is_file() && !bundle::is_bundle(i, &batch.extensions));
+    if args.run.interactive && !single_local_file {
+        anyhow::bail!("--interactive applies to a single local EPUB file");
+    }
+
+    if let Some(list) = &args.from_url_list {
+        let urls = remote::read_url_list(list)?;
+        if urls.is_empty() {
+            anyhow::bail!("No URLs found in {}", list.display());
+        }
+        let downloads: Vec<(String, Option<String>)> = urls.into_iter().map(|url| (url, None)).collect();
+        return process_downloads(&downloads, output, &options, &batch);
+    }
+    if let Some(list) = &args.files_from {
+        let epub_files = read_file_list(list)?;
+        if epub_files.is_empty() {
+            anyhow::bail!("No EPUB paths found in {}", list.display());
+        }
+        options.progress.text(format!("Read {} EPUB path(s) from {}", epub_files.len(), list.display()));
+        return process_batch(&epub_files, output, output.unwrap_or(Path::new(".")), &options, &batch);
+    }
+    if let Some(dir) = &args.calibre_library {
+        let library = calibre::Library::open(dir)?;
+        if library.books.is_empty() {
+            anyhow::bail!("No books with an EPUB format found in Calibre library {}", dir.display());
+        }
+        options.progress.text(format!("Found {} book(s) with an EPUB format in Calibre library {}", library.books.len(), dir.display()));
+        if library.without_epub > 0 {
+            options.progress.text(format!("Left out {} book(s) with no EPUB file", library.without_epub));
+        }
+        let epub_files: Vec<PathBuf> = library.books.iter().map(|book| book.epub.clone()).collect();
+        let batch = BatchOptions { calibre: library.books.into_iter().map(|book| (book.epub.clone(), book)).collect(), ..batch };
+        let base = output.unwrap_or(Path::new("."));
+        return process_batch(&epub_files, Some(base), base, &options, &batch);
+    }
+    if let Some(root) = &args.opds {
+        let publications = opds::crawl(root, &args.filter, &options.progress)?;
+        if publications.is_empty() {
+            anyhow::bail!("No matching EPUBs found in OPDS catalog {}", root);
+        }
+        options.progress.text(format!("Found {} matching EPUB(s) in the catalog", publications.len()));
+        // The feed metadata was already filtered; don't filter again on the EPUBs' own
+        let batch = BatchOptions { filters: Vec::new(), ..batch };
+        let downloads: Vec<(String, Option<String>)> = publications
+            .into_iter()
+            .filter_map(|p| Some((p.epub_url?, Some(p.title).filter(|t| !t.is_empty()))))
+            .collect();
+        return process_downloads(&downloads, output, &options, &batch);
+    }
+    let input = args.input.as_deref().context("No input given")?;
+    if let Some(url) = input.to_str().filter(|s| remote::is_url(s)) {
+        let scratch = check::ScratchDir::new("download")?;
+        options.progress.text(format!("Downloading {}...", url));
+        let epub_path = remote::download(url, None, scratch.path())?;
+        return process_single_epub(&epub_path, output, &options, &batch);
+    }
 
     // Validate input exists
-    if !cli.input.exists() {
-        anyhow::bail!("Input path does not exist: {}", cli.input.display());
+    if !input.exists() {
+        anyhow::bail!("Input path does not exist: {}", input.display());
     }
 
-    // Check if input is a directory or a file
-    if cli.input.is_dir() {
-        process_directory(&cli.input, cli.output.as_deref(), cli.single)?;
+    // Check if input is a directory (or an archive of EPUBs) or a file
+    if input.is_dir() || bundle::is_bundle(input, &batch.extensions) {
+        process_directory(input, output, &options, &batch)?;
     } else {
         // Single file processing
-        if cli.input.extension().and_then(|s| s.to_str()) != Some("epub") {
-            anyhow::bail!("Input file must have .epub extension");
+        if !batch.extensions.is_epub(input) {
+            anyhow::bail!("Input file must have an .epub extension or be an EPUB archive");
+        }
+        if !batch.filters.is_empty() {
+            anyhow::bail!("--filter applies to directories, URL lists and OPDS catalogs");
         }
-        process_single_epub(&cli.input, cli.output.as_deref(), cli.single)?;
+        if args.run.interactive {
+            let Some(selection) = picker::pick(input, &options.render, options.single_file)? else {
+                options.progress.text("Nothing converted.");
+                return Ok(());
+            };
+            options.chapters = Some(selection.chapters);
+            options.single_file = selection.single_file;
+        }
+        process_single_epub(input, output, &options, &batch)?;
     }
 
     Ok(())
 }
 
-/// Recursively find all EPUB files in a directory
-fn find_epub_files(dir: &Path) -> Vec<PathBuf> {
-    WalkDir::new(dir)
-        .into_iter()
-        .filter_map(|entry| entry.ok())
-        .filter(|entry| {
-            entry.path().extension().and_then(|s| s.to_str()) == Some("epub")
-                && entry.file_type().is_file()
-        })
-        .map(|entry| entry.path().to_path_buf())
-        .collect()
+/// Download books (URL plus an optional file name) into a scratch directory and convert them
+/// as a batch. Output goes under `output_base` (default: the current directory), one folder per book.
+fn process_downloads(downloads: &[(String, Option<String>)], output_base: Option<&Path>, options: &ConvertOptions, batch: &BatchOptions) -> Result<()> {
+    let scratch = check::ScratchDir::new("download")?;
+    let mut failed = 0;
+    for (url, name) in downloads {
+        options.progress.text(format!("Downloading {}...", url));
+        if let Err(e) = remote::download(url, name.as_deref(), scratch.path()) {
+            eprintln!("{:#}", e);
+            options.progress.event(Event::error(url, &e));
+            failed += 1;
+        }
+    }
+    if failed == downloads.len() {
+        anyhow::bail!("All {} download(s) failed", failed);
+    }
+
+    let converted = process_directory(scratch.path(), Some(output_base.unwrap_or(Path::new("."))), options, batch);
+    if failed > 0 {
+        anyhow::bail!("{} of {} download(s) failed", failed, downloads.len());
+    }
+    converted
+}
+
+/// Recursively find all EPUB files in a directory, by extension or, failing that, by
+/// their zip container's mimetype entry, handing each to `on_epub` as it is found.
+/// Returns the zip and tar archives that may hold more.
+fn walk_epub_files(dir: &Path, extensions: &input::EpubExtensions, mut on_epub: impl FnMut(PathBuf)) -> Vec<PathBuf> {
+    let mut bundles = Vec::new();
+    for entry in WalkDir::new(dir).into_iter().filter_map(|entry| entry.ok()).filter(|entry| entry.file_type().is_file()) {
+        if extensions.is_epub(entry.path()) {
+            on_epub(entry.path().to_path_buf());
+        } else if bundle::is_bundle(entry.path(), extensions) {
+            bundles.push(entry.path().to_path_buf());
+        }
+    }
+    bundles
 }
 
-/// Process all EPUB files in a directory in parallel
-fn process_directory(dir: &Path, output_base: Option<&Path>, single_file: bool) -> Result<()> {
-    let epub_files = find_epub_files(dir);
+/// Read the EPUB paths listed in a file, or on stdin for `-`. Paths are separated by NUL
+/// bytes if there are any, else by newlines, so the output of both `find` and
+/// `find -print0` can be piped in.
+fn read_file_list(list: &Path) -> Result<Vec<PathBuf>> {
+    use std::io::Read;
+    use std::os::unix::ffi::OsStrExt;
+
+    let mut bytes = Vec::new();
+    if list == Path::new("-") {
+        std::io::stdin().lock().read_to_end(&mut bytes).context("Failed to read file list from stdin")?;
+    } else {
+        bytes = fs::read(list).with_context(|| format!("Failed to read file list {}", list.display()))?;
+    }
+    let separator = if bytes.contains(&0) { 0 } else { b'\n' };
+    Ok(bytes
+        .split(|&b| b == separator)
+        .map(|entry| entry.strip_suffix(b"\r").unwrap_or(entry))
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| PathBuf::from(std::ffi::OsStr::from_bytes(entry)))
+        .collect())
+}
+
+/// Process all EPUB files in a directory in parallel, including those inside zip and tar
+/// archives, which are unpacked to a scratch directory first. `dir` may also be a single
+/// archive.
+fn process_directory(dir: &Path, output_base: Option<&Path>, options: &ConvertOptions, batch: &BatchOptions) -> Result<()> {
+    if dir.is_dir() && batch.organize.is_none() {
+        return stream_directory(dir, output_base, options, batch);
+    }
+    let mut epub_files = Vec::new();
+    let bundles = if dir.is_dir() { walk_epub_files(dir, &batch.extensions, |epub_path| epub_files.push(epub_path)) } else { vec![dir.to_path_buf()] };
+    let (_scratch, unpacked_from) = unpack_bundles(&bundles, &mut epub_files, options, batch)?;
 
     if epub_files.is_empty() {
-        anyhow::bail!("No EPUB files found in directory: {}", dir.display());
+        anyhow::bail!("No EPUB files found in {}", dir.display());
     }
 
-    println!("Found {} EPUB file(s) in {}", epub_files.len(), dir.display());
-    println!("Processing in parallel...\n");
+    options.progress.text(format!("Found {} EPUB file(s) in {}", epub_files.len(), dir.display()));
+    let batch_base = if dir.is_dir() { dir } else { dir.parent().unwrap_or(Path::new(".")) };
+    let batch = BatchOptions { unpacked_from, ..batch.clone() };
+    process_batch(&epub_files, output_base, output_base.unwrap_or(batch_base), options, &batch)
+}
 
-    // Process all EPUB files in parallel
-    let results: Vec<Result<(), anyhow::Error>> = epub_files
-        .par_iter()
-        .map(|epub_path| {
-            let output_dir = if let Some(base) = output_base {
-                // Create output path that mirrors the input directory structure
-                let relative = epub_path.strip_prefix(dir).unwrap_or(epub_path);
-                let stem = relative.file_stem().unwrap_or_default();
-                base.join(format!("{}_markdown", stem.to_string_lossy()))
-            } else {
-                // Default: create output next to the epub file
-                let parent = epub_path.parent().unwrap_or_else(|| Path::new("."));
-                let stem = epub_path.file_stem().unwrap_or_default();
-                parent.join(format!("{}_markdown", stem.to_string_lossy()))
-            };
+/// Convert a directory's EPUBs while it is still being walked, as a pipeline: a walker
+/// thread hands each file to a screening thread as soon as it is found, which reads the
+/// metadata `--filter` and `--skip-samples` need and passes the book on to the worker
+/// pool. On slow network storage the first book starts converting without waiting for
+/// the whole tree to be listed, and later books' metadata is read while earlier ones
+/// convert. Archives are unpacked and converted once the walk is done. Results are put
+/// back in the order the files were found before the batch is reported.
+///
+/// `--organize` doesn't stream, since it hands out directories in input order.
+fn stream_directory(dir: &Path, output_base: Option<&Path>, options: &ConvertOptions, batch: &BatchOptions) -> Result<()> {
+    options.progress.text(format!("Converting EPUB files in {} as they are found...\n", dir.display()));
+    let (found_sender, walked) = mpsc::channel();
+    let (screened_sender, screened) = mpsc::channel();
+    let (mut found, bundles) = thread::scope(|scope| {
+        // The walk isn't cut short by an interrupt, so the run journal lists every book
+        let walker = scope.spawn(move || {
+            let mut count = 0;
+            let bundles = walk_epub_files(dir, &batch.extensions, |epub_path| {
+                let _ = found_sender.send((count, epub_path));
+                count += 1;
+            });
+            if count > 0 {
+                options.progress.text(format!("Found {} EPUB file(s) in {}", count, dir.display()));
+            }
+            bundles
+        });
+        // A thread of its own rather than the pool, whose workers may all be waiting on
+        // `screened`
+        scope.spawn(move || {
+            for (n, epub_path) in walked {
+                let skip = screen_for_batch(&epub_path, options, batch);
+                let _ = screened_sender.send((n, epub_path, skip));
+            }
+        });
+        let mut found: Vec<_> = screened
+            .into_iter()
+            .par_bridge()
+            .map(|(n, epub_path, skip)| {
+                let output_dir = output_dir_for(&epub_path, output_base, options, batch);
+                let converted = convert_screened(&epub_path, &output_dir, skip, options, batch);
+                (n, epub_path, output_dir, converted)
+            })
+            .collect();
+        found.sort_by_key(|(n, ..)| *n);
+        (found, walker.join().unwrap_or_else(|e| panic::resume_unwind(e)))
+    });
 
-            convert_epub_to_markdown(epub_path, &output_dir, single_file)
+    let mut epub_files = Vec::new();
+    let (_scratch, unpacked_from) = unpack_bundles(&bundles, &mut epub_files, options, batch)?;
+    let batch = BatchOptions { unpacked_from, ..batch.clone() };
+    let unpacked: Vec<_> = epub_files
+        .into_par_iter()
+        .map(|epub_path| {
+            let output_dir = output_dir_for(&epub_path, output_base, options, &batch);
+            let converted = convert_in_batch(&epub_path, &output_dir, options, &batch);
+            (0, epub_path, output_dir, converted)
         })
         .collect();
+    found.extend(unpacked);
+
+    if found.is_empty() {
+        anyhow::bail!("No EPUB files found in {}", dir.display());
+    }
+
+    let mut epub_files = Vec::with_capacity(found.len());
+    let mut output_dirs = Vec::with_capacity(found.len());
+    let mut results = Vec::with_capacity(found.len());
+    let mut runs = Vec::with_capacity(found.len());
+    for (_, epub_path, output_dir, (result, run)) in found {
+        epub_files.push(epub_path);
+        output_dirs.push(output_dir);
+        results.push(result);
+        runs.push(run);
+    }
+    report_batch(&epub_files, &output_dirs, &results, &runs, output_base.unwrap_or(dir), options, &batch)
+}
+
+/// Unpack the EPUBs in zip and tar archives to a scratch directory, adding them to
+/// `epub_files`. Returns the scratch directory, which must be kept until they are
+/// converted, and the archive each came from.
+fn unpack_bundles(
+    bundles: &[PathBuf],
+    epub_files: &mut Vec<PathBuf>,
+    options: &ConvertOptions,
+    batch: &BatchOptions,
+) -> Result<(Option<check::ScratchDir>, HashMap<PathBuf, bundle::Unpacked>)> {
+    let scratch = if bundles.is_empty() { None } else { Some(check::ScratchDir::new("unpack")?) };
+    let mut unpacked_from = HashMap::new();
+    for (n, bundle) in bundles.iter().enumerate() {
+        let Some(scratch) = &scratch else { break };
+        let dest = scratch.path().join(n.to_string());
+        match bundle::unpack(bundle, &batch.extensions, &dest) {
+            Ok(found) => {
+                if !found.is_empty() {
+                    options.progress.text(format!("Unpacked {} EPUB file(s) from {}", found.len(), bundle.display()));
+                }
+                for epub_path in found {
+                    let entry = epub_path.strip_prefix(&dest).unwrap_or(&epub_path).to_path_buf();
+                    unpacked_from.insert(epub_path.clone(), bundle::Unpacked { archive: bundle.clone(), entry });
+                    epub_files.push(epub_path);
+                }
+            }
+            Err(e) => {
+                eprintln!("Warning: {:#}", e);
+                options.progress.event(Event::error(&bundle.to_string_lossy(), &e));
+            }
+        }
+    }
+    Ok((scratch, unpacked_from))
+}
+
+/// How a batch book's run went besides its result: how long it took, how many attempts
+/// it had and, for one that wasn't converted, why
+type BookRun = (Duration, usize, Option<summary::RunStatus>);
+
+/// Convert a list of EPUB files in parallel. Batch-level outputs (series, catalog,
+/// summary) are written to `batch_base`.
+fn process_batch(epub_files: &[PathBuf], output_base: Option<&Path>, batch_base: &Path, options: &ConvertOptions, batch: &BatchOptions) -> Result<()> {
+    options.progress.text("Processing in parallel...\n");
+
+    let output_dirs: Vec<PathBuf> = if let Some(layout) = &batch.organize {
+        // Metadata is read in parallel, but directories are handed out in input order so
+        // the same batch always lands in the same places
+        let fields: Vec<Option<organize::BookFields>> = epub_files.par_iter().map(|p| organize::BookFields::read(p)).collect();
+        let base = output_base.unwrap_or(batch_base);
+        let mut organizer = organize::Organizer::new(layout, base, options.normalize);
+        epub_files
+            .iter()
+            .zip(&fields)
+            .map(|(epub_path, fields)| {
+                let stem = options.normalize.apply(&batch.extensions.stem(epub_path));
+                match fields {
+                    Some(fields) => organizer.dir_for(fields, &stem),
+                    // It will fail to convert; keep the usual name for the error message
+                    None => base.join(format!("{}_markdown", stem)),
+                }
+            })
+            .collect()
+    } else {
+        epub_files.iter().map(|epub_path| output_dir_for(epub_path, output_base, options, batch)).collect()
+    };
+
+    let (results, runs): (Vec<_>, Vec<_>) =
+        epub_files.par_iter().zip(&output_dirs).map(|(epub_path, output_dir)| convert_in_batch(epub_path, output_dir, options, batch)).unzip();
+    report_batch(epub_files, &output_dirs, &results, &runs, batch_base, options, batch)
+}
+
+/// Where a batch book's output goes, outside of `--organize`
+fn output_dir_for(epub_path: &Path, output_base: Option<&Path>, options: &ConvertOptions, batch: &BatchOptions) -> PathBuf {
+    let stem = options.normalize.apply(&batch.extensions.stem(epub_path));
+    if let (Some(base), Some(book)) = (output_base, batch.calibre.get(epub_path)) {
+        // Mirror the library's Author/Title (id) folders
+        base.join(&book.path)
+    } else if let Some(base) = output_base {
+        base.join(format!("{}_markdown", stem))
+    } else {
+        // Default: create output next to the epub file, or the archive it was in
+        let source = batch.unpacked_from.get(epub_path).map_or(epub_path, |unpacked| unpacked.archive.as_path());
+        let parent = source.parent().unwrap_or_else(|| Path::new("."));
+        parent.join(format!("{}_markdown", stem))
+    }
+}
+
+/// Convert one book of a batch, timing it and counting its attempts; a None result marks
+/// a book that wasn't converted, with the reason in its run
+fn convert_in_batch(epub_path: &Path, output_dir: &Path, options: &ConvertOptions, batch: &BatchOptions) -> (Option<Result<BookOutcome>>, BookRun) {
+    let skip = screen_for_batch(epub_path, options, batch);
+    convert_screened(epub_path, output_dir, skip, options, batch)
+}
+
+/// Why a batch book is left out before it is converted, if it is: an interrupt came
+/// first, it doesn't match the `--filter`s, or `--skip-samples` takes it for a sample
+fn screen_for_batch(epub_path: &Path, options: &ConvertOptions, batch: &BatchOptions) -> Option<summary::RunStatus> {
+    if interrupt::requested() {
+        return Some(summary::RunStatus::NotStarted);
+    }
+    if !passes_filters(epub_path, &batch.filters) {
+        return Some(summary::RunStatus::Skipped);
+    }
+    if batch.skip_samples {
+        if let Some(reason) = sample::detect_file(epub_path, options.limits) {
+            options.progress.text(format!("Skipping {}: looks like a retailer sample ({})", epub_path.display(), reason));
+            return Some(summary::RunStatus::Sample);
+        }
+    }
+    None
+}
+
+/// `convert_in_batch` for a book `screen_for_batch` has already looked at
+fn convert_screened(
+    epub_path: &Path,
+    output_dir: &Path,
+    skip: Option<summary::RunStatus>,
+    options: &ConvertOptions,
+    batch: &BatchOptions,
+) -> (Option<Result<BookOutcome>>, BookRun) {
+    // An interrupt may also have come while the book waited for a worker
+    let skip = skip.or_else(|| interrupt::requested().then_some(summary::RunStatus::NotStarted));
+    if let Some(status) = skip {
+        return (None, (Duration::ZERO, 0, Some(status)));
+    }
+    let start = Instant::now();
+    let book_options = batch.calibre.get(epub_path).map(|book| ConvertOptions { calibre: Some(book.clone()), ..options.clone() });
+    let (result, attempts) = process_book_with_retries(epub_path, output_dir, book_options.as_ref().unwrap_or(options), batch);
+    (Some(result), (start.elapsed(), attempts, None))
+}
+
+/// Report a converted batch and write its batch-level outputs to `batch_base`
+fn report_batch(
+    epub_files: &[PathBuf],
+    output_dirs: &[PathBuf],
+    results: &[Option<Result<BookOutcome>>],
+    runs: &[BookRun],
+    batch_base: &Path,
+    options: &ConvertOptions,
+    batch: &BatchOptions,
+) -> Result<()> {
+    let status_of = |result: &Option<Result<BookOutcome>>, skip: Option<summary::RunStatus>| match result {
+        Some(Ok(outcome)) if outcome.cached => summary::RunStatus::Cached,
+        Some(Ok(_)) => summary::RunStatus::Converted,
+        Some(Err(e)) if is_interrupted(e) => summary::RunStatus::Interrupted,
+        Some(Err(_)) => summary::RunStatus::Failed,
+        None => skip.unwrap_or(summary::RunStatus::Skipped),
+    };
 
     // Report results
     let mut success_count = 0;
     let mut error_count = 0;
+    let mut skipped_count = 0;
+    let mut sample_count = 0;
+    let mut flagged_samples = 0;
+    let mut retried_count = 0;
+    let mut interrupted_count = 0;
+    let mut not_started = 0;
 
-    for (path, result) in epub_files.iter().zip(results.iter()) {
+    for (((path, result), &(_, attempts, skip)), output_dir) in epub_files.iter().zip(results.iter()).zip(runs).zip(output_dirs) {
         match result {
-            Ok(()) => success_count += 1,
-            Err(e) => {
+            Some(Ok(_)) => {
+                success_count += 1;
+                if saved::SavedBook::load(output_dir).is_ok_and(|book| book.sample.is_some()) {
+                    flagged_samples += 1;
+                }
+            }
+            Some(Err(e)) if is_interrupted(e) => interrupted_count += 1,
+            Some(Err(e)) => {
                 error_count += 1;
-                eprintln!("Failed to process {}: {}", path.display(), e);
+                eprintln!("Failed to process {}: {}", batch.source_name(path), e);
+            }
+            None => match skip {
+                Some(summary::RunStatus::Sample) => sample_count += 1,
+                Some(summary::RunStatus::NotStarted) => not_started += 1,
+                _ => skipped_count += 1,
+            },
+        }
+        if attempts > 1 {
+            retried_count += 1;
+            let outcome = if matches!(result, Some(Ok(_))) { "succeeded" } else { "failed" };
+            options.progress.text(format!("Retried {}: {} after {} attempts", batch.source_name(path), outcome, attempts));
+        }
+    }
+
+    // Stop before the batch-level outputs, which would only cover part of the batch
+    if interrupt::requested() {
+        let signal = interrupt::signal_name();
+        options.progress.text("\n--- Interrupted ---");
+        options.progress.text(format!("Successfully processed: {}", success_count));
+        options.progress.text(format!("Stopped part-way: {}", interrupted_count));
+        options.progress.text(format!("Not started: {}", not_started));
+        if error_count > 0 {
+            options.progress.text(format!("Failed: {}", error_count));
+        }
+        if !options.check {
+            let books: Vec<(&Path, &Path, summary::RunStatus)> = epub_files
+                .iter()
+                .zip(output_dirs)
+                .zip(results.iter().zip(runs))
+                .map(|((epub_path, output_dir), (result, &(_, _, skip)))| (epub_path.as_path(), output_dir.as_path(), status_of(result, skip)))
+                .collect();
+            let (remaining_path, remaining) = journal::write(&books, signal, batch_base)?;
+            options.progress.text(format!("Run journal written to {}", batch_base.join(journal::JOURNAL_FILE).display()));
+            if remaining > 0 {
+                options.progress.text(format!("To resume, run the same command with the input replaced by --files-from {}", remaining_path.display()));
             }
         }
+        return Err(ConvertError::Interrupted { signal }.into());
+    }
+    if !options.check {
+        journal::clear(batch_base);
     }
 
-    println!("\n--- Summary ---");
-    println!("Successfully processed: {}", success_count);
+    // Batch-level outputs, built from the metadata.json of every book that converted
+    let converted: Vec<(PathBuf, PathBuf)> = epub_files
+        .iter()
+        .zip(output_dirs)
+        .zip(results)
+        .filter(|(_, result)| matches!(result, Some(Ok(_))))
+        .map(|((epub_path, output_dir), _)| (epub_path.clone(), output_dir.clone()))
+        .collect();
+    // Files written for the batch as a whole, committed together after the books with --git
+    let mut index_files = Vec::new();
+    if batch.merge_series && !options.check {
+        let output_dirs: Vec<PathBuf> = converted.iter().map(|(_, output_dir)| output_dir.clone()).collect();
+        for path in series::merge_series(&output_dirs, batch_base, options.toc_depth, options.naming.extension, options.newline, &options.slug_rules)? {
+            options.progress.text(format!("Merged series written to {}", path.display()));
+            options.progress.written(&path);
+            index_files.push(path);
+        }
+    }
+    if batch.catalog && !options.check {
+        let books: Vec<(String, PathBuf)> =
+            converted.iter().map(|(epub_path, output_dir)| (batch.source_name(epub_path), output_dir.clone())).collect();
+        let files = catalog::write_catalog(&books, batch_base)?;
+        let names: Vec<String> = files.iter().map(|f| f.display().to_string()).collect();
+        options.progress.text(format!("Catalog written to {}", names.join(" and ")));
+        files.iter().for_each(|f| options.progress.written(f));
+        index_files.extend(files);
+    }
+    if options.notion && !options.check {
+        let output_dirs: Vec<PathBuf> = converted.iter().map(|(_, output_dir)| output_dir.clone()).collect();
+        let path = notion::write_database(&output_dirs, batch_base)?;
+        options.progress.text(format!("Notion database written to {}", path.display()));
+        options.progress.written(&path);
+        index_files.push(path);
+    }
+    if batch.summary_csv && !options.check {
+        let runs: Vec<summary::BookRun> = epub_files
+            .iter()
+            .zip(output_dirs)
+            .zip(results.iter().zip(runs))
+            .map(|((epub_path, output_dir), (result, &(elapsed, attempts, skip)))| summary::BookRun {
+                source: batch.source_name(epub_path),
+                epub_path,
+                output_dir,
+                status: status_of(result, skip),
+                elapsed,
+                attempts,
+            })
+            .collect();
+        let path = summary::write_summary(&runs, batch_base)?;
+        options.progress.text(format!("Summary written to {}", path.display()));
+        options.progress.written(&path);
+        index_files.push(path);
+    }
+    if batch.git && !options.check {
+        // One commit per book, in input order, so the history reads like the batch
+        let library = git::Library::open(batch_base)?;
+        let mut commits = 0;
+        for (epub_path, output_dir) in &converted {
+            let message = git::book_message(epub_path, saved::SavedBook::load(output_dir).ok().as_ref());
+            match library.commit(std::slice::from_ref(output_dir), &message) {
+                Ok(committed) => commits += usize::from(committed),
+                Err(e) => eprintln!("Failed to commit {}: {}", output_dir.display(), e),
+            }
+        }
+        if !index_files.is_empty() && library.commit(&index_files, "Update library index")? {
+            commits += 1;
+        }
+        options.progress.text(format!("Made {} commit(s) in the git repository in {}", commits, batch_base.display()));
+    }
+
+    options.progress.text("\n--- Summary ---");
+    options.progress.text(format!("Successfully processed: {}", success_count));
+    if skipped_count > 0 {
+        options.progress.text(format!("Skipped by filters: {}", skipped_count));
+    }
+    if sample_count > 0 {
+        options.progress.text(format!("Skipped as samples: {}", sample_count));
+    }
+    if flagged_samples > 0 {
+        options.progress.text(format!("Converted but look like samples: {} (see `sample` in their metadata.json, or use --skip-samples)", flagged_samples));
+    }
+    if retried_count > 0 {
+        options.progress.text(format!("Retried: {}", retried_count));
+    }
     if error_count > 0 {
-        println!("Failed: {}", error_count);
+        options.progress.text(format!("Failed: {}", error_count));
         anyhow::bail!("{} EPUB file(s) failed to process", error_count);
     }
 
     Ok(())
 }
 
+/// Convert a book, retrying after transient I/O errors (such as a network share dropping
+/// out) up to `--retries` times, waiting `--retry-delay` and doubling it each time.
+/// Returns the last attempt's result and the number of attempts made.
+fn process_book_with_retries(epub_path: &Path, output_dir: &Path, options: &ConvertOptions, batch: &BatchOptions) -> (Result<BookOutcome>, usize) {
+    let mut delay = batch.retry_delay;
+    let mut attempts = 1;
+    loop {
+        let result = process_book(epub_path, output_dir, options);
+        match &result {
+            Err(e) if attempts <= batch.retries && is_transient(e) => {
+                let book = epub_path.to_string_lossy();
+                options.progress.text(format!("  [{}] {:#}; retrying in {:?} (retry {} of {})",
+                    epub_path.file_name().unwrap_or_default().to_string_lossy(),
+                    e,
+                    delay,
+                    attempts,
+                    batch.retries
+                ));
+                options.progress.event(Event::BookRetried { book: &book, attempt: attempts, message: format!("{:#}", e) });
+                std::thread::sleep(delay);
+                delay = delay.saturating_mul(2);
+                attempts += 1;
+            }
+            _ => return (result, attempts),
+        }
+    }
+}
+
+/// Whether a conversion stopped because the run was interrupted
+fn is_interrupted(error: &anyhow::Error) -> bool {
+    error::ErrorReport::of(error).kind == error::ErrorKind::Interrupted
+}
+
+/// Whether a failure came from I/O that may succeed if tried again: an interrupted or
+/// timed-out read, a busy file or a dropped connection. Anything else, such as a
+/// truncated archive (`UnexpectedEof`), a missing file, a panic or a timeout, fails the
+/// same way every time, so isn't retried.
+fn is_transient(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| {
+        let io = match cause.downcast_ref::<ConvertError>() {
+            Some(ConvertError::Io(e)) => Some(e),
+            _ => cause.downcast_ref::<std::io::Error>(),
+        };
+        io.is_some_and(|e| {
+            matches!(
+                e.kind(),
+                std::io::ErrorKind::Interrupted
+                    | std::io::ErrorKind::WouldBlock
+                    | std::io::ErrorKind::TimedOut
+                    | std::io::ErrorKind::ResourceBusy
+                    | std::io::ErrorKind::ConnectionReset
+            )
+        })
+    })
+}
+
+/// Whether a book's metadata passes every `--filter`. Books that can't be opened pass,
+/// so the conversion reports why they failed instead of them silently disappearing.
+fn passes_filters(epub_path: &Path, filters: &[filter::MetadataFilter]) -> bool {
+    if filters.is_empty() {
+        return true;
+    }
+    let path = epub_path.to_path_buf();
+    let metadata = isolate::run_isolated(None, &isolate::CancelToken::default(), move || {
+        let doc = EpubDoc::new(&path).context("Failed to open EPUB file")?;
+        Ok(extract_metadata(&doc))
+    });
+    let Ok(metadata) = metadata else {
+        return true;
+    };
+
+    let lookup = |field: &str| -> Vec<&str> {
+        match field {
+            "title" => metadata.title.as_deref().into_iter().collect(),
+            "author" => metadata.creators.iter().map(String::as_str).collect(),
+            "language" => metadata.language.as_deref().into_iter().collect(),
+            "subject" => metadata.subjects.iter().map(String::as_str).collect(),
+            "publisher" => metadata.publisher.as_deref().into_iter().collect(),
+            "identifier" => metadata.identifier.as_deref().into_iter().collect(),
+            _ => Vec::new(),
+        }
+    };
+    filter::matches_all(filters, &lookup)
+}
+
 /// Process a single EPUB file
-fn process_single_epub(epub_path: &Path, output_base: Option<&Path>, single_file: bool) -> Result<()> {
-    let output_dir = if let Some(base) = output_base {
+fn process_single_epub(epub_path: &Path, output_base: Option<&Path>, options: &ConvertOptions, batch: &BatchOptions) -> Result<()> {
+    let output_dir = if let Some(layout) = &batch.organize {
+        let fields = organize::BookFields::read(epub_path).context("Failed to open EPUB file")?;
+        let stem = options.normalize.apply(&batch.extensions.stem(epub_path));
+        organize::Organizer::new(layout, output_base.unwrap_or(Path::new(".")), options.normalize).dir_for(&fields, &stem)
+    } else if let Some(base) = output_base {
         base.to_path_buf()
     } else {
-        let stem = epub_path.file_stem().unwrap();
-        PathBuf::from(format!("{}_markdown", stem.to_string_lossy()))
+        PathBuf::from(format!("{}_markdown", options.normalize.apply(&batch.extensions.stem(epub_path))))
     };
 
-    println!("Converting {} to Markdown...", epub_path.display());
-    convert_epub_to_markdown(epub_path, &output_dir, single_file)?;
-    println!("Conversion complete! Output saved to: {}", output_dir.display());
+    if batch.skip_samples {
+        if let Some(reason) = sample::detect_file(epub_path, options.limits) {
+            options.progress.text(format!("Skipping {}: looks like a retailer sample ({})", epub_path.display(), reason));
+            return Ok(());
+        }
+    }
+
+    if options.check {
+        options.progress.text(format!("Checking {} against {}...", epub_path.display(), output_dir.display()));
+        process_book(epub_path, &output_dir, options)?;
+        options.progress.text("Output is up to date.");
+        return Ok(());
+    }
+
+    options.progress.text(format!("Converting {} to Markdown...", epub_path.display()));
+    process_book(epub_path, &output_dir, options)?;
+    if options.notion {
+        let path = notion::write_database(std::slice::from_ref(&output_dir), &output_dir)?;
+        options.progress.written(&path);
+    }
+    if batch.git {
+        let library = git::Library::open(&output_dir)?;
+        let message = git::book_message(epub_path, saved::SavedBook::load(&output_dir).ok().as_ref());
+        if library.commit(std::slice::from_ref(&output_dir), &message)? {
+            options.progress.text(format!("Committed to the git repository in {}", output_dir.display()));
+        }
+    }
+    options.progress.text(format!("Conversion complete! Output saved to: {}", output_dir.display()));
+
+    Ok(())
+}
+
+/// Convert (or, with `--check`, verify) one book, reporting start, finish and failure events
+fn process_book(epub_path: &Path, output_dir: &Path, options: &ConvertOptions) -> Result<BookOutcome> {
+    let book = epub_path.to_string_lossy();
+    let output = output_dir.to_string_lossy();
+    options.progress.event(Event::BookStarted { book: &book, output: &output });
+
+    // Isolate the book so a panic or hang in the parsing layers only fails this book.
+    // It gets its own cancellation token so a timeout stops nothing else.
+    let result = {
+        let mut book_options = options.clone();
+        book_options.cancel = isolate::CancelToken::default();
+        let cancel = book_options.cancel.clone();
+        let (epub_path, output_dir) = (epub_path.to_path_buf(), output_dir.to_path_buf());
+
+        isolate::run_isolated(options.timeout, &cancel, move || {
+            convert_book(&epub_path, &output_dir, &book_options)
+        })
+    };
+    match &result {
+        Ok(outcome) => {
+            for file in &outcome.files {
+                options.progress.written(file);
+            }
+            options.progress.event(Event::BookFinished {
+                book: &book,
+                output: &output,
+                files: outcome.files.len(),
+                cached: outcome.cached,
+            })
+        }
+        Err(e) => options.progress.event(Event::error(&book, e)),
+    }
+    result
+}
+
+/// Convert or verify one book, consulting the cache when enabled
+fn convert_book(epub_path: &Path, output_dir: &Path, options: &ConvertOptions) -> Result<BookOutcome> {
+    if options.check {
+        verify_epub_output(epub_path, output_dir, options)?;
+        return Ok(BookOutcome::default());
+    }
+
+    // Only one run at a time may write into a book's directory
+    let existed = output_dir.exists();
+    fs::create_dir_all(output_dir)
+        .context("Failed to create output directory")?;
+    let lock = output::OutputLock::acquire(output_dir)?;
+
+    let result = convert_or_restore(epub_path, output_dir, options);
+    if result.as_ref().is_err_and(is_interrupted) {
+        // Roll back what this run started; a directory that held earlier output keeps
+        // it, but without its completion marker
+        let name = epub_path.file_name().unwrap_or_default().to_string_lossy();
+        if existed {
+            options.progress.text(format!("  [{}] Interrupted; {} is incomplete (no {} marker)", name, output_dir.display(), output::COMPLETE_MARKER));
+        } else {
+            drop(lock);
+            let _ = fs::remove_dir_all(output_dir);
+            options.progress.text(format!("  [{}] Interrupted; removed its partial output", name));
+        }
+    }
+    result
+}
+
+/// Convert a book, or restore it from the cache when one is in use
+fn convert_or_restore(epub_path: &Path, output_dir: &Path, options: &ConvertOptions) -> Result<BookOutcome> {
+    // Link checks depend on the network, so they always run fresh
+    let cache = match &options.cache {
+        Some(cache) if !options.check_links => cache,
+        _ => return convert_epub_to_markdown(epub_path, output_dir, options),
+    };
+
+    let key = cache.key(epub_path, &options.fingerprint())?;
+    if let Some(files) = cache.restore(&key, output_dir)? {
+        restamp_provenance(epub_path, output_dir, options)?;
+        output::mark_complete(output_dir, &files)
+            .context("Failed to write completion marker")?;
+        options.progress.text(format!("  [{}] Unchanged, restored from cache",
+            epub_path.file_name().unwrap_or_default().to_string_lossy()
+        ));
+        return Ok(BookOutcome { files, cached: true });
+    }
+
+    let outcome = convert_epub_to_markdown(epub_path, output_dir, options)?;
+    cache.store(&key, output_dir, &outcome.files)?;
+    Ok(outcome)
+}
+
+/// Give restored output the provenance of this run: the cached metadata.json records the
+/// file name, options and time of the conversion that filled the cache
+fn restamp_provenance(epub_path: &Path, output_dir: &Path, options: &ConvertOptions) -> Result<()> {
+    let metadata_path = output_dir.join("metadata.json");
+    let Ok(metadata_json) = fs::read_to_string(&metadata_path) else {
+        return Ok(());
+    };
+    let provenance = provenance::Provenance::of(epub_path, &options.settings, options.record_time)
+        .with_context(|| format!("Failed to hash {}", epub_path.display()))?;
+    if let Some(restamped) = provenance::replace_in(&metadata_json, &provenance)? {
+        output::write_atomic(&metadata_path, restamped)
+            .with_context(|| format!("Failed to write {}", metadata_path.display()))?;
+    }
+    Ok(())
+}
+
+/// Reconvert an EPUB into a scratch directory and fail if it differs from the existing output
+fn verify_epub_output(epub_path: &Path, output_dir: &Path, options: &ConvertOptions) -> Result<()> {
+    let scratch = check::ScratchDir::new("check")?;
+    convert_epub_to_markdown(epub_path, scratch.path(), options)?;
+
+    let drift = check::diff_trees(output_dir, scratch.path())?;
+    if !drift.is_empty() {
+        for line in &drift {
+            eprintln!("  [{}] {}", epub_path.file_name().unwrap_or_default().to_string_lossy(), line);
+        }
+        anyhow::bail!("Output drift detected in {} ({} difference(s))", output_dir.display(), drift.len());
+    }
 
     Ok(())
 }
@@ -200,6 +1684,22 @@ fn extract_metadata<R: std::io::Read + std::io::Seek>(doc: &EpubDoc<R>) -> BookM
     // Get EPUB version as string
     let epub_version = format!("{:?}", doc.version);
 
+    // Series: the EPUB3 collection refined as a series, else calibre's meta tags
+    let collection = doc.metadata.iter().find(|m| {
+        m.property == "belongs-to-collection"
+            && m.refinement("collection-type").is_none_or(|t| t.value == "series")
+    });
+    let (series, series_index) = match collection {
+        Some(c) => (
+            Some(c.value.trim().to_string()),
+            c.refinement("group-position").and_then(|p| p.value.trim().parse().ok()),
+        ),
+        None => (
+            get_value("calibre:series").map(|s| s.trim().to_string()),
+            get_value("calibre:series_index").and_then(|i| i.trim().parse().ok()),
+        ),
+    };
+
     BookMetadata {
         title: get_value("title"),
         creators: get_all_values("creator"),
@@ -208,33 +1708,105 @@ fn extract_metadata<R: std::io::Read + std::io::Seek>(doc: &EpubDoc<R>) -> BookM
         publisher: get_value("publisher"),
         date: get_value("date"),
         subjects: get_all_values("subject"),
+        tags: tags::normalize(&get_all_values("subject"), &[]),
         identifier: get_value("identifier"),
+        license: license::detect(
+            doc.metadata
+                .iter()
+                .filter(|m| matches!(m.property.as_str(), "rights" | "license" | "dcterms:license"))
+                .map(|m| m.value.as_str()),
+        ),
         rights: get_value("rights"),
         contributors: get_all_values("contributor"),
         source: get_value("source"),
         epub_version,
         release_identifier: doc.get_release_identifier(),
         chapter_count: doc.spine.len(),
+        series: series.filter(|s| !s.is_empty()),
+        series_index,
+        custom_columns: BTreeMap::new(),
+        writing_mode: None,
+        text_direction: None,
+        rendition_layout: None,
+        word_count: None,
         toc: convert_toc(&doc.toc),
+        chapters: Vec::new(),
+        sections: Vec::new(),
+        styles: Vec::new(),
+        audio: Vec::new(),
+        images: BTreeMap::new(),
+        warnings: Vec::new(),
+        rules: Vec::new(),
+        sample: None,
+        rendition: None,
+        provenance: None,
     }
 }
 
-fn convert_epub_to_markdown(epub_path: &Path, output_dir: &Path, single_file: bool) -> Result<()> {
-    // Open the EPUB document
-    let mut doc = EpubDoc::new(epub_path)
+fn convert_epub_to_markdown(epub_path: &Path, output_dir: &Path, options: &ConvertOptions) -> Result<BookOutcome> {
+    let mut outcome = BookOutcome::default();
+    let mut stats = BookStats::default();
+    let open_start = Instant::now();
+
+    // Open the EPUB document, refusing oversized archives before anything is decompressed
+    options.limits.check_archive(epub_path)?;
+    // Everything the conversion decompresses is read through one reader, counted against the limits
+    let mut resources = archive::Resources::open(epub_path, options.limits)?;
+    let selected = rendition::select(epub_path, &mut resources, options.rendition.as_deref())?;
+    let source = selected.path(epub_path);
+    let mut doc = EpubDoc::new(source)
+        .map_err(ConvertError::from)
         .context("Failed to open EPUB file")?;
+    options.limits.check_chapter_count(doc.spine.len())?;
+    let mut reader = archive::SpineReader::open(resources, &doc, &options.mime_policy)?;
+    if doc.toc.is_empty() {
+        if let Some(nav) = toc::nav_document(&doc) {
+            if let Some(xhtml) = reader.resources.read_string(&nav.to_string_lossy())? {
+                doc.toc = toc::from_nav(&xhtml, &nav);
+            }
+        }
+    }
+    let mut layout = layout::BookLayout::detect(&doc, &mut reader.resources)?;
+    // The container can say a rendition is fixed layout where its package doesn't
+    layout.fixed |= selected.rendition.as_ref().is_some_and(|r| r.fixed);
+    let archive_names = reader.names();
+    let toc_pages = sections::toc_pages(&doc, &mut reader.resources)?;
+    let heading_styles =
+        if options.detect_headings { headings::HeadingStyles::detect(&doc, &mut reader.resources)? } else { headings::HeadingStyles::default() };
 
     // Create output directory
     fs::create_dir_all(output_dir)
         .context("Failed to create output directory")?;
+    output::mark_incomplete(output_dir)
+        .context("Failed to clear completion marker")?;
 
-    // Extract and save metadata
-    let metadata = extract_metadata(&doc);
-    let metadata_path = output_dir.join("metadata.json");
-    let metadata_json = serde_json::to_string_pretty(&metadata)
-        .context("Failed to serialize metadata")?;
-    fs::write(&metadata_path, &metadata_json)
-        .context("Failed to write metadata.json")?;
+    // Extract metadata; it is saved once the chapter mapping is known
+    let mut metadata = extract_metadata(&doc);
+    if let Some(book) = &options.calibre {
+        metadata.title = Some(book.title.clone()).filter(|t| !t.is_empty()).or(metadata.title);
+        if !book.authors.is_empty() {
+            metadata.creators = book.authors.clone();
+        }
+        metadata.subjects = book.tags.clone();
+        metadata.series = book.series.clone();
+        metadata.series_index = book.series_index;
+        metadata.custom_columns = book.custom_columns.clone();
+    }
+    metadata.apply_overrides(&options.overrides);
+    if let Some(depth) = options.toc_depth {
+        metadata.toc = flatten_toc(std::mem::take(&mut metadata.toc), depth);
+    }
+    metadata.normalize(options.normalize);
+    if let Some(ascii) = options.ascii_only {
+        metadata.rewrite_text(&|text| ascii.apply_mut(text));
+    }
+    metadata.tags = tags::normalize(&metadata.subjects, &options.tag_map);
+    metadata.writing_mode = layout.vertical.clone();
+    metadata.text_direction = layout.rtl.then(|| "rtl".to_string());
+    metadata.rendition_layout = layout.fixed.then(|| "pre-paginated".to_string());
+    metadata.sample = sample::detect(&doc, &mut reader.resources)?;
+    metadata.rendition = selected.rendition.as_ref().map(|r| r.path.clone());
+    stats.open = open_start.elapsed();
 
     // Get book metadata for display
     let title = metadata.title.clone().unwrap_or_else(|| "Unknown Title".to_string());
@@ -242,63 +1814,819 @@ fn convert_epub_to_markdown(epub_path: &Path, output_dir: &Path, single_file: bo
         .cloned()
         .unwrap_or_else(|| "Unknown Author".to_string());
 
-    println!("  [{}] Title: {}, Author: {}", 
+    options.progress.text(format!("  [{}] Title: {}, Author: {}", 
         epub_path.file_name().unwrap_or_default().to_string_lossy(),
         title, 
         author
-    );
+    ));
+    if let Some(chosen) = selected.rendition.as_ref().filter(|_| selected.count > 1) {
+        options.progress.text(format!("  [{}] {} renditions; converting {}",
+            epub_path.file_name().unwrap_or_default().to_string_lossy(),
+            selected.count,
+            chosen.describe()
+        ));
+    }
+    let rule_packs = options.rules.for_publisher(metadata.publisher.as_deref());
+    metadata.rules = rule_packs.iter().map(|pack| pack.name.clone()).collect();
+    if !rule_packs.is_empty() {
+        options.progress.text(format!("  [{}] Applying rules: {}",
+            epub_path.file_name().unwrap_or_default().to_string_lossy(),
+            metadata.rules.join(", ")
+        ));
+    }
+    if layout.fixed {
+        options.progress.text(format!("  [{}] Fixed layout: writing one file per page",
+            epub_path.file_name().unwrap_or_default().to_string_lossy()
+        ));
+    }
+    let spine_paths: Vec<String> = reader.entries.iter().map(|entry| entry.path.clone()).collect();
+    let part_directories = match options.directory_layout {
+        parts::DirectoryLayout::Parts => parts::PartDirectories::detect(&doc.toc, &spine_paths),
+        parts::DirectoryLayout::Flat => parts::PartDirectories::default(),
+    };
+    if options.directory_layout == parts::DirectoryLayout::Parts {
+        options.progress.text(match part_directories.names.len() {
+            0 => format!("  [{}] The table of contents has no parts, so chapters are written flat",
+                epub_path.file_name().unwrap_or_default().to_string_lossy()),
+            n => format!("  [{}] Writing chapters into {} part directories",
+                epub_path.file_name().unwrap_or_default().to_string_lossy(), n),
+        });
+    }
+    if let Some(reason) = &metadata.sample {
+        options.progress.text(format!("  [{}] Looks like a retailer sample: {}",
+            epub_path.file_name().unwrap_or_default().to_string_lossy(),
+            reason
+        ));
+    }
 
-    let mut all_content = String::new();
+    let overlays = match options.read_along {
+        Some(_) => overlay::Overlays::load(&doc, &mut reader.resources, options.extract_audio)?,
+        None => overlay::Overlays::default(),
+    };
+    if options.read_along.is_some() && overlays.is_empty() {
+        options.progress.text(format!("  [{}] No media overlays found, so there is no read-along timing",
+            epub_path.file_name().unwrap_or_default().to_string_lossy()
+        ));
+    }
+    let mut read_along = Vec::new();
+    let mut promoted_headings = 0;
 
-    // Add metadata to combined file
-    if single_file {
-        all_content.push_str(&format!("# {}\n\n", title));
-        all_content.push_str(&format!("**Author:** {}\n\n", author));
-        all_content.push_str("---\n\n");
+    let mut warnings = Vec::new();
+    if doc.toc.is_empty() {
+        warnings.push(warnings::Warning::new(warnings::WarningKind::EmptyToc, "The book has no table of contents", None));
     }
+    warnings.extend(warnings::check_reading_order(&doc.toc, &spine_paths));
+    let mut external_links = Vec::new();
+    let mut bibliography = Vec::new();
+    let mut glossary = Vec::new();
+    // Where each converted document went, so index entries can link to it
+    let mut link_targets: HashMap<String, sections::LinkTarget> = HashMap::new();
+    // Digest of each chapter's rendered Markdown, and the document it came from
+    let mut rendered_digests: HashMap<[u8; 32], String> = HashMap::new();
+    let mut cleaner = options.clean_artifacts.then(artifacts::ArtifactCleaner::default);
+    let mut dehyphenator = options.dehyphenate.then(dehyphenate::Dehyphenator::default);
+    let mut keywords = options.analyze.contains(&analysis::Analysis::Keywords).then(analysis::KeywordAnalyzer::default);
+    let mut vocabulary = options.vocab.map(|_| vocab::VocabularyCounter::default());
+    let mut readability: Vec<(usize, stats::TextCounts)> = Vec::new();
+    let toc_labels = if options.inject_title { toc::labels_by_path(&doc.toc) } else { Default::default() };
 
-    // Iterate through spine (reading order)
-    let mut chapter_num = 1;
+    let license_header = options.license_header.and_then(|style| {
+        let header = license::Header::new(style, options.frontmatter_format, metadata.rights.as_deref(), metadata.license.as_deref());
+        if header.is_none() {
+            options.progress.text(format!("  [{}] No rights statement or license found, so no license header is written",
+                epub_path.file_name().unwrap_or_default().to_string_lossy()
+            ));
+        }
+        header
+    });
+    // Every chapter file, whatever its format, is written through this
+    let chapter_files = output::ChapterFiles { newline: options.newline, license: license_header };
+
+    // Stream the combined file as chapters are converted rather than holding the whole book
+    let jex_output = options.format == output::OutputFormat::Jex;
+    let combined_name = format!("{}.{}", sanitize_filename(&title), if jex_output { "jex" } else { options.naming.extension.as_str() });
+    let mut combined = if options.single_file {
+        let mut writer = combined::CombinedVolumes::create(output_dir, &combined_name, &title, &author, options.volume_limit)
+            .context("Failed to create combined Markdown file")?;
+        if let Some(header) = &chapter_files.license {
+            writer.set_preamble(&header.markdown());
+        }
+        writer.set_toc_depth(options.toc_depth);
+        writer.set_newline(options.newline);
+        writer.set_slug_rules(&options.slug_rules);
+        Some(writer)
+    } else {
+        None
+    };
+    let mut jex = if jex_output {
+        let filepath = output_dir.join(&combined_name);
+        let modified = fs::metadata(epub_path).and_then(|m| m.modified()).unwrap_or(std::time::UNIX_EPOCH);
+        let seed = metadata.identifier.clone().unwrap_or_else(|| title.clone());
+        let writer = jex::JexWriter::create(&filepath, &seed, modified)
+            .context("Failed to create the Joplin archive")?;
+        outcome.files.push(filepath);
+        Some(writer)
+    } else {
+        None
+    };
+    // Joplin archives carry their images as resources instead
+    let mut images = (options.extract_images && !jex_output).then(|| images::ImageStore::new(output_dir));
+    let mut alt_text = options.alt_text_cmd.as_ref().map(alttext::AltTextWriter::new);
+    let mut dendron = options.dendron.then(|| {
+        let modified = fs::metadata(epub_path).and_then(|m| m.modified()).unwrap_or(std::time::UNIX_EPOCH);
+        let seed = metadata.identifier.clone().unwrap_or_else(|| title.clone());
+        dendron::DendronWriter::new(output_dir, &title, &seed, modified, chapter_files.clone())
+    });
+    let mut logseq = if options.logseq { Some(logseq::LogseqWriter::create(output_dir, &title, chapter_files.clone())?) } else { None };
+
+    // Work through the spine in batches: read each batch serially (EpubDoc can't be
+    // shared across threads), render it in parallel, then write it in reading order.
+    // This bounds memory to one batch of chapters regardless of book size.
     let spine_len = doc.spine.len();
+    let batch_size = rayon::current_num_threads() * 4;
+    let mut chapter_num = 1;
+    let mut pool: Vec<SpineDocument> = Vec::new();
 
-    for i in 0..spine_len {
-        doc.set_current_chapter(i);
+    let markup_budget = options.limits.max_markup_bytes();
+    let mut next = 0;
+    let mut mime_denied = 0;
 
-        if let Some((content, _mime)) = doc.get_current_str() {
-            // Convert HTML to Markdown
-            let markdown = html2md::parse_html(&content);
+    while next < spine_len {
+        if options.cancel.is_cancelled() {
+            anyhow::bail!("Conversion cancelled");
+        }
+        if interrupt::requested() {
+            return Err(ConvertError::Interrupted { signal: interrupt::signal_name() }.into());
+        }
+
+        // Fill pooled buffers straight from the archive. Under --max-memory a batch also
+        // stops before the markup in flight would outgrow the budget, but always takes one.
+        let mut filled = 0;
+        let mut in_flight = 0u64;
+        BookStats::time(&mut stats.read, || -> Result<()> {
+            while next < spine_len && filled < batch_size {
+                let i = next;
+                if options.chapters.as_ref().is_some_and(|picked| !picked.contains(&i)) {
+                    next += 1;
+                    continue;
+                }
+                if let Some(budget) = markup_budget {
+                    let size = reader.declared_size(i);
+                    if filled > 0 && in_flight.saturating_add(size) > budget {
+                        break;
+                    }
+                    in_flight = in_flight.saturating_add(size);
+                }
+                next += 1;
+                if filled == pool.len() {
+                    pool.push(SpineDocument::default());
+                }
+                let slot = &mut pool[filled];
+                let read = if reader.entries[i].content == archive::SpineContent::Extract {
+                    let part_dir = part_directories.get(&reader.entries[i].path);
+                    let (file, page) = extract_attachment(&mut reader, i, output_dir, part_dir)?;
+                    outcome.files.push(file);
+                    slot.content.clear();
+                    slot.content.push_str(&page);
+                    true
+                } else {
+                    reader.read_into(i, &mut slot.content)?
+                };
+                if read {
+                    slot.index = i;
+                    slot.source.clear();
+                    slot.source.push_str(&reader.entries[i].path);
+                    slot.image = matches!(reader.entries[i].content, archive::SpineContent::Image | archive::SpineContent::Extract);
+                    filled += 1;
+                } else if reader.entries[i].path.is_empty() {
+                    let message = format!("Spine item {} names no manifest item, so it was left out", doc.spine[i].idref);
+                    warnings.push(warnings::Warning::new(warnings::WarningKind::MissingManifestItem, message, None));
+                } else if reader.is_missing(i) {
+                    let href = reader.entries[i].path.clone();
+                    let message = ConvertError::MissingResource { href: href.clone() }.to_string() + ", so it was left out";
+                    warnings.push(warnings::Warning::new(warnings::WarningKind::MissingResource, message, Some(&href)));
+                } else if let archive::SpineContent::Denied(_) = &reader.entries[i].content {
+                    mime_denied += 1;
+                } else if let archive::SpineContent::Unsupported(mime) = &reader.entries[i].content {
+                    let source = Some(reader.entries[i].path.as_str());
+                    let message = format!("A {} spine item has no XHTML or image fallback, so it was left out", mime);
+                    warnings.push(warnings::Warning::new(warnings::WarningKind::UnsupportedMedia, message, source));
+                } else {
+                    let source = Some(reader.entries[i].path.as_str());
+                    let message = "Not valid UTF-8, so it was left out";
+                    warnings.push(warnings::Warning::new(warnings::WarningKind::InvalidEncoding, message, source));
+                }
+            }
+            Ok(())
+        })?;
+        let spine_docs = &pool[..filled];
+        stats.html_bytes += spine_docs.iter().map(|d| d.content.len()).sum::<usize>();
 
-            // Skip empty or minimal content
-            if markdown.trim().is_empty() || markdown.trim().len() < 50 {
+        // Convert chapters in parallel; collect() keeps spine order. A chapter that
+        // panics fails the book naming that chapter, rather than as an anonymous panic.
+        let rendered: Vec<(Option<RenderedChapter>, Vec<warnings::Warning>)> = BookStats::time(&mut stats.render, || {
+            spine_docs
+                .par_iter()
+                .map(|spine_doc| {
+                    panic::catch_unwind(AssertUnwindSafe(|| {
+                        let chapter_warnings = warnings::check_chapter(&spine_doc.content, &spine_doc.source, &archive_names);
+                        (render_chapter(spine_doc, options, &layout, &rule_packs, &heading_styles, &overlays), chapter_warnings)
+                    }))
+                    .map_err(|payload| {
+                        ConvertError::RenderFailed { chapter: spine_doc.source.clone(), reason: isolate::panic_message(payload) }
+                    })
+                })
+                .collect::<Result<_, _>>()
+        })?;
+
+        // Number only the chapters that produced content
+        let write_start = Instant::now();
+        for (spine_doc, (chapter, chapter_warnings)) in spine_docs.iter().zip(rendered) {
+            warnings.extend(chapter_warnings);
+            let Some(mut chapter) = chapter else {
                 continue;
+            };
+            if !options.keep_duplicates {
+                let mut digest = digest::Sha256::default();
+                digest.update(chapter.markdown.trim().as_bytes());
+                let digest = digest.finalize();
+                if let Some(original) = rendered_digests.get(&digest) {
+                    let message = format!("Same content as {}, so it was left out", original);
+                    warnings.push(warnings::Warning::new(warnings::WarningKind::DuplicateChapter, message, Some(&spine_doc.source)));
+                    // Links to the copy go to the chapter it repeats
+                    if let Some(target) = link_targets.get(original).cloned() {
+                        link_targets.insert(spine_doc.source.clone(), target);
+                    }
+                    continue;
+                }
+                rendered_digests.insert(digest, spine_doc.source.clone());
+            }
+            if toc_pages.contains(&spine_doc.source) {
+                chapter.section = Some(sections::Section::Toc);
+            }
+            if let Some(section) = chapter.section {
+                let skipped = options.skip.contains(&section) || (section == sections::Section::Toc && !options.keep_toc_page);
+                let mut report = sections::SectionReport {
+                    section,
+                    source: spine_doc.source.clone(),
+                    handling: if skipped { sections::Handling::Skipped } else { sections::Handling::Kept },
+                    links: None,
+                };
+                if !skipped && section == sections::Section::Index && options.render.page_markers {
+                    let (markdown, linked) = sections::link_index(&chapter.markdown, &spine_doc.source, &link_targets);
+                    chapter.markdown = markdown;
+                    report.handling = sections::Handling::Linked;
+                    report.links = Some(linked);
+                }
+                options.progress.text(format!("  [{}] {:?} ({}): {}",
+                    epub_path.file_name().unwrap_or_default().to_string_lossy(),
+                    section,
+                    spine_doc.source,
+                    match (report.handling, report.links) {
+                        (sections::Handling::Linked, Some(n)) => format!("{} link(s) pointed at the converted chapters", n),
+                        (sections::Handling::Skipped, _) => "skipped".to_string(),
+                        _ => "kept as is".to_string(),
+                    }
+                ));
+                metadata.sections.push(report);
+                if skipped {
+                    bibliography.extend(chapter.references);
+                    glossary.extend(chapter.glossary);
+                    continue;
+                }
+            }
+            let number = options.naming.number(chapter_num, spine_doc.index);
+            if let Some(cleaner) = cleaner.as_mut() {
+                chapter.markdown = cleaner.clean(&chapter.markdown, number);
+            }
+            if let Some(dehyphenator) = dehyphenator.as_mut() {
+                chapter.markdown = dehyphenator.fix(&chapter.markdown);
+            }
+            if let Some(label) = toc_labels.get(&toc::target_path(Path::new(&spine_doc.source))).filter(|l| !l.is_empty()) {
+                if !toc::starts_with_heading(&chapter.markdown) {
+                    let mut label = options.normalize.apply(label);
+                    if let Some(ascii) = options.ascii_only {
+                        ascii.apply_mut(&mut label);
+                    }
+                    chapter.markdown = format!("# {}\n\n{}", label, chapter.markdown);
+                }
+            }
+            if layout.rtl && options.rtl_markup != layout::RtlMarkup::None {
+                chapter.markdown = layout::mark_rtl(&chapter.markdown, options.rtl_markup);
+            }
+            if options.notion {
+                chapter.markdown = notion::constrain(&chapter.markdown);
+            }
+            stats.markdown_bytes += chapter.markdown.len();
+            stats.words += stats::count_words(&chapter.markdown);
+            if options.stats {
+                readability.push((number, stats::TextCounts::of(&chapter.markdown)));
+            }
+            if let Some(analyzer) = keywords.as_mut() {
+                analyzer.add_chapter(number, &chapter.markdown);
+            }
+            if let Some(counter) = vocabulary.as_mut() {
+                counter.add_chapter(number, &chapter.markdown);
+            }
+
+            external_links.extend(chapter.links.into_iter().map(|mut link| {
+                link.chapter = number;
+                link
+            }));
+            bibliography.extend(chapter.references.into_iter().map(|mut reference| {
+                reference.chapter = Some(number);
+                reference
+            }));
+            glossary.extend(chapter.glossary);
+
+            if let Some(writer) = alt_text.as_mut() {
+                chapter.markdown = writer.fill(&chapter.markdown, &spine_doc.source, |path| image_resource(&doc, &mut reader.resources, path))?;
+            }
+            if let Some(store) = images.as_mut() {
+                // Leads from the chapter's file back to the output directory
+                let depth = if combined.is_some() || dendron.is_some() {
+                    0
+                } else if logseq.is_some() {
+                    1
+                } else {
+                    part_directories.get(&spine_doc.source).map_or(0, |dir| dir.split('/').count())
+                };
+                chapter.markdown = store.rewrite(&chapter.markdown, &spine_doc.source, &"../".repeat(depth), |path| image_resource(&doc, &mut reader.resources, path))?;
+            }
+
+            // Joplin notes, SSML and Logseq outlines have no place for it
+            if options.source_comments && !jex_output && chapter.ssml.is_none() && logseq.is_none() {
+                chapter.markdown.insert_str(0, &source_comment(&spine_doc.source, spine_doc.index));
             }
 
-            if single_file {
+            let filename = if let Some(writer) = combined.as_mut() {
                 // Append to combined content
-                all_content.push_str(&markdown);
-                all_content.push_str("\n\n---\n\n");
+                let volume = writer.add_chapter(&chapter.markdown)
+                    .context("Failed to write combined Markdown file")?;
+                options.volume_limit.is_set().then(|| volume.to_string())
+            } else if let Some(writer) = jex.as_mut() {
+                let title = toc::heading_lines(&chapter.markdown)
+                    .into_iter()
+                    .next()
+                    .map(|(_, _, text)| text)
+                    .unwrap_or_else(|| format!("Chapter {}", number));
+                writer.add_chapter(&title, &chapter_files.finish(license::FileSyntax::Markdown, &chapter.markdown), &spine_doc.source, |path| image_resource(&doc, &mut reader.resources, path))?;
+                None
+            } else if let Some(body) = &chapter.ssml {
+                let filename = in_part(options.naming.filename_with(number, spine_len, "ssml"), part_directories.get(&spine_doc.source));
+                let filepath = output_dir.join(&filename);
+                create_part_dir(&filepath)?;
+                chapter_files.write(&filepath, license::FileSyntax::Xml, &ssml::document(body, metadata.language.as_deref()))
+                    .context(format!("Failed to write {}", filename))?;
+                outcome.files.push(filepath);
+                Some(filename)
+            } else if let Some(writer) = dendron.as_mut() {
+                let files = writer.write_chapter(number, options.naming.width(spine_len), &chapter.markdown)?;
+                outcome.files.extend(files.iter().map(|f| output_dir.join(f)));
+                files.into_iter().next()
+            } else if let Some(writer) = logseq.as_mut() {
+                let filename = writer.write_chapter(number, options.naming.width(spine_len), &chapter.markdown)?;
+                outcome.files.push(output_dir.join(&filename));
+                Some(filename)
             } else {
                 // Save as separate file
-                let filename = format!("chapter_{:03}.md", chapter_num);
+                let filename = in_part(options.naming.filename(number, spine_len), part_directories.get(&spine_doc.source));
                 let filepath = output_dir.join(&filename);
-
-                fs::write(&filepath, &markdown)
+                create_part_dir(&filepath)?;
+                chapter_files.write(&filepath, license::FileSyntax::Markdown, &chapter.markdown)
                     .context(format!("Failed to write {}", filename))?;
+                outcome.files.push(filepath);
+                Some(filename)
+            };
+
+            options.progress.event(Event::ChapterConverted {
+                book: &epub_path.to_string_lossy(),
+                chapter: number,
+                spine_index: spine_doc.index,
+                spine_total: spine_len,
+                source: &spine_doc.source,
+                file: filename.as_deref(),
+            });
+            let file = filename.unwrap_or_else(|| combined_name.clone());
+            link_targets.insert(spine_doc.source.clone(), sections::LinkTarget {
+                file: if options.single_file && !options.volume_limit.is_set() { String::new() } else { file.clone() },
+                anchors: chapter.page_anchors.into_iter().collect(),
+            });
+            promoted_headings += chapter.promoted_headings;
+            if options.read_along == Some(overlay::ReadAlong::Json) && !chapter.read_along.is_empty() {
+                read_along.push(overlay::ChapterTiming {
+                    chapter: number,
+                    file: file.clone(),
+                    source: spine_doc.source.clone(),
+                    paragraphs: chapter.read_along,
+                });
             }
+            metadata.chapters.push(ChapterEntry {
+                number,
+                spine_index: spine_doc.index,
+                source: spine_doc.source.clone(),
+                file,
+            });
 
             chapter_num += 1;
         }
-    }
+        stats.write += write_start.elapsed();
 
-    // Write single combined file if requested
-    if single_file {
-        let filename = format!("{}.md", sanitize_filename(&title));
-        let filepath = output_dir.join(&filename);
+        // Give back the capacity a large chapter left in the pool
+        if markup_budget.is_some() {
+            for slot in &mut pool {
+                slot.content.shrink_to(1 << 20);
+            }
+        }
+    }
 
-        fs::write(&filepath, all_content)
+    if let Some(writer) = combined {
+        let volumes = BookStats::time(&mut stats.write, || writer.commit())
             .context("Failed to write combined Markdown file")?;
+        if options.volume_limit.is_set() {
+            options.progress.text(format!("  [{}] Split the combined file into {} volume(s)",
+                epub_path.file_name().unwrap_or_default().to_string_lossy(),
+                volumes.len()
+            ));
+        }
+        outcome.files.extend(volumes);
+    }
+    stats.chapters = chapter_num - 1;
+    if mime_denied > 0 {
+        options.progress.text(format!("  [{}] Left out {} spine item(s) by media type",
+            epub_path.file_name().unwrap_or_default().to_string_lossy(),
+            mime_denied
+        ));
+    }
+    if promoted_headings > 0 {
+        options.progress.text(format!("  [{}] Promoted {} styled paragraph(s) to headings",
+            epub_path.file_name().unwrap_or_default().to_string_lossy(),
+            promoted_headings
+        ));
+    }
+    metadata.word_count = Some(stats.words);
+    if let Some(writer) = logseq {
+        outcome.files.push(writer.finish(&metadata)?);
+    }
+    if let Some(writer) = dendron {
+        outcome.files.push(writer.finish(&metadata)?);
+    }
+    if let Some(writer) = jex {
+        BookStats::time(&mut stats.write, || writer.finish(&title, &metadata.tags))?;
+    }
+
+    if options.extract_styles {
+        let (entries, files, unrestored) = styles::extract(&doc, &mut reader.resources, output_dir)?;
+        let fonts = entries.iter().filter(|e| e.kind == styles::StyleKind::Font).count();
+        options.progress.text(format!("  [{}] Extracted {} stylesheet(s) and {} font(s) to {}/",
+            epub_path.file_name().unwrap_or_default().to_string_lossy(),
+            entries.len() - fonts,
+            fonts,
+            styles::STYLES_DIR
+        ));
+        for font in unrestored {
+            options.progress.text(format!("  [{}] Left out {}: it is obfuscated with a key the book's identifiers don't give",
+                epub_path.file_name().unwrap_or_default().to_string_lossy(),
+                font
+            ));
+        }
+        metadata.styles = entries;
+        outcome.files.extend(files);
+    }
+    if let Some(writer) = alt_text {
+        if let Some((image, reason)) = writer.failures.first() {
+            options.progress.text(format!("  [{}] Couldn't describe {} image(s); {}: {}",
+                epub_path.file_name().unwrap_or_default().to_string_lossy(),
+                writer.failures.len(),
+                image,
+                reason
+            ));
+        }
+    }
+    if let Some(store) = images {
+        if !store.manifest.is_empty() {
+            options.progress.text(format!("  [{}] Extracted {} image(s) to {}/",
+                epub_path.file_name().unwrap_or_default().to_string_lossy(),
+                store.files.len(),
+                images::IMAGES_DIR
+            ));
+        }
+        metadata.images = store.manifest;
+        outcome.files.extend(store.files);
+    }
+    if options.extract_audio {
+        let (entries, files) = overlay::extract_audio(&doc, &mut reader.resources, output_dir)?;
+        options.progress.text(format!("  [{}] Extracted {} audio file(s) to {}/",
+            epub_path.file_name().unwrap_or_default().to_string_lossy(),
+            entries.len(),
+            overlay::AUDIO_DIR
+        ));
+        metadata.audio = entries;
+        outcome.files.extend(files);
+    }
+    if !read_along.is_empty() {
+        let overlays_path = output_dir.join(overlay::OVERLAYS_FILE);
+        let overlays_json = serde_json::to_string_pretty(&read_along)
+            .context("Failed to serialize read-along timing")?;
+        output::write_atomic(&overlays_path, overlays_json)
+            .with_context(|| format!("Failed to write {}", overlay::OVERLAYS_FILE))?;
+        outcome.files.push(overlays_path);
+    }
+
+    // Verify external links, so the broken ones are counted among the warnings
+    if options.check_links && !external_links.is_empty() {
+        let broken = links::check_links(&mut external_links);
+        if broken > 0 {
+            let message = format!("{} of {} external link(s) are broken", broken, external_links.len());
+            warnings.push(warnings::Warning::new(warnings::WarningKind::BrokenLinks, message, None));
+        }
+    }
+
+    report_warnings(epub_path, &warnings, &options.progress, options.check);
+    metadata.warnings = warnings;
+    metadata.provenance = Some(provenance::Provenance::of(epub_path, &options.settings, options.record_time)
+        .with_context(|| format!("Failed to hash {}", epub_path.display()))?);
+    let metadata_path = output_dir.join("metadata.json");
+    let metadata_json = serde_json::to_string_pretty(&metadata)
+        .context("Failed to serialize metadata")?;
+    output::write_atomic(&metadata_path, &metadata_json)
+        .context("Failed to write metadata.json")?;
+    outcome.files.push(metadata_path);
+
+    if let Some(cleaner) = cleaner {
+        options.progress.text(format!("  [{}] {}",
+            epub_path.file_name().unwrap_or_default().to_string_lossy(),
+            cleaner.summary()
+        ));
+        let artifacts_path = output_dir.join("artifacts.json");
+        let artifacts_json = serde_json::to_string_pretty(&cleaner.removed)
+            .context("Failed to serialize artifact report")?;
+        output::write_atomic(&artifacts_path, artifacts_json)
+            .context("Failed to write artifacts.json")?;
+        outcome.files.push(artifacts_path);
+    }
+
+    if let Some(dehyphenator) = dehyphenator {
+        options.progress.text(format!("  [{}] {}",
+            epub_path.file_name().unwrap_or_default().to_string_lossy(),
+            dehyphenator.summary()
+        ));
+    }
+
+    if options.references {
+        options.progress.text(format!("  [{}] {} reference(s) written to references.json",
+            epub_path.file_name().unwrap_or_default().to_string_lossy(),
+            bibliography.len()
+        ));
+        let references_path = output_dir.join("references.json");
+        let references_json = serde_json::to_string_pretty(&bibliography)
+            .context("Failed to serialize references")?;
+        output::write_atomic(&references_path, references_json)
+            .context("Failed to write references.json")?;
+        outcome.files.push(references_path);
+    }
+
+    if let Some(name) = &options.anki {
+        let file_name = epub_path.file_name().unwrap_or_default().to_string_lossy();
+        if glossary.is_empty() {
+            options.progress.text(format!("  [{}] No glossary found, so no flashcards were written", file_name));
+        } else {
+            let deck_path = output_dir.join(name);
+            let format = anki::DeckFormat::from_name(name).unwrap_or(anki::DeckFormat::Apkg);
+            let modified = fs::metadata(epub_path).and_then(|m| m.modified()).unwrap_or(std::time::UNIX_EPOCH);
+            let cards = anki::write_deck(&deck_path, format, &title, &title, &glossary, modified)?;
+            options.progress.text(format!("  [{}] {} flashcard(s) written to {}", file_name, cards, name));
+            outcome.files.push(deck_path);
+        }
+    }
+
+    if !options.analyze.is_empty() {
+        let report = analysis::BookAnalysis { keywords: keywords.map(analysis::KeywordAnalyzer::finish) };
+        let analysis_path = output_dir.join("analysis.json");
+        let analysis_json = serde_json::to_string_pretty(&report)
+            .context("Failed to serialize analysis")?;
+        output::write_atomic(&analysis_path, analysis_json)
+            .context("Failed to write analysis.json")?;
+        outcome.files.push(analysis_path);
+    }
+
+    if options.stats {
+        let report = stats::StatsReport::new(&stats, &readability);
+        let stats_path = output_dir.join("stats.json");
+        let stats_json = serde_json::to_string_pretty(&report)
+            .context("Failed to serialize statistics")?;
+        output::write_atomic(&stats_path, stats_json)
+            .context("Failed to write stats.json")?;
+        outcome.files.push(stats_path);
+    }
+
+    if let (Some(format), Some(counter)) = (options.vocab, vocabulary) {
+        let english = metadata.language.as_deref().is_none_or(|l| l.to_ascii_lowercase().starts_with("en"));
+        let list = counter.finish(english);
+        let contents = match format {
+            vocab::VocabFormat::Csv => list.to_csv(),
+            vocab::VocabFormat::Json => serde_json::to_string_pretty(&list).context("Failed to serialize vocabulary")?,
+        };
+        let vocab_path = output_dir.join(format.file_name());
+        output::write_atomic(&vocab_path, contents)
+            .with_context(|| format!("Failed to write {}", format.file_name()))?;
+        outcome.files.push(vocab_path);
+    }
+
+    // Save external links, verified before metadata.json is written
+    let links_json = serde_json::to_string_pretty(&external_links)
+        .context("Failed to serialize external links")?;
+    let links_path = output_dir.join("links.json");
+    output::write_atomic(&links_path, links_json)
+        .context("Failed to write links.json")?;
+    outcome.files.push(links_path);
+
+    // Only now is the book's output safe to treat as finished
+    if options.cancel.is_cancelled() {
+        anyhow::bail!("Conversion cancelled");
+    }
+    output::mark_complete(output_dir, &outcome.files)
+        .context("Failed to write completion marker")?;
+
+    if options.stats {
+        options.progress.text(format!("  [{}] {}",
+            epub_path.file_name().unwrap_or_default().to_string_lossy(),
+            stats.summary()
+        ));
+    }
+
+    Ok(outcome)
+}
+
+/// List a book's warnings with `-v`, or else say how many there were. Checking is
+/// where a confusing order is looked into, so `check` lists reading-order warnings
+/// in full either way and only counts the rest.
+fn report_warnings(epub_path: &Path, warnings: &[warnings::Warning], progress: &Progress, check: bool) {
+    let book = epub_path.to_string_lossy();
+    let name = epub_path.file_name().unwrap_or_default().to_string_lossy();
+    let mut unlisted = 0;
+    for warning in warnings {
+        progress.event(Event::Warning { book: &book, kind: warning.kind, message: &warning.message, source: warning.source.as_deref() });
+        let reading_order = matches!(warning.kind, warnings::WarningKind::TocOrder | warnings::WarningKind::TocOutsideSpine);
+        if !(progress.verbose || check && reading_order) {
+            unlisted += 1;
+            continue;
+        }
+        match &warning.source {
+            Some(source) => progress.text(format!("  [{}] warning: {} ({})", name, warning.message, source)),
+            None => progress.text(format!("  [{}] warning: {}", name, warning.message)),
+        }
+    }
+    if unlisted > 0 {
+        let other = if unlisted < warnings.len() { " other" } else { "" };
+        progress.text(format!("  [{}] {}{} warning(s); run with -v to list them, or see metadata.json", name, unlisted, other));
+    }
+}
+
+/// Tidies the rendered Markdown unless --no-tidy was given, then applies the
+/// Unicode normalization and ASCII folding chosen for the run.
+fn clean_rendered(mut markdown: String, options: &ConvertOptions) -> String {
+    if options.tidy {
+        markdown = tidy::tidy(&markdown);
+    }
+    options.normalize.apply_mut(&mut markdown);
+    if let Some(ascii) = options.ascii_only {
+        ascii.apply_mut(&mut markdown);
+    }
+    markdown
+}
+
+/// Convert one spine document to Markdown, or None if it has no meaningful content
+fn render_chapter(
+    spine_doc: &SpineDocument,
+    options: &ConvertOptions,
+    layout: &layout::BookLayout,
+    rule_packs: &[&rules::RulePack],
+    heading_styles: &headings::HeadingStyles,
+    overlays: &overlay::Overlays,
+) -> Option<RenderedChapter> {
+    // Convert HTML to Markdown, after the publisher's cleanup rules, promoting styled
+    // headings and merging drop caps and split spans back into their words. A
+    // fixed-layout page is kept for the images it shows, however little text it has.
+    let mut content = Cow::Borrowed(spine_doc.content.as_str());
+    if !rule_packs.is_empty() {
+        content = Cow::Owned(rules::apply(&content, rule_packs));
+    }
+    let mut promoted_headings = 0;
+    if options.detect_headings && !layout.fixed {
+        if let Some((promoted, count)) = headings::promote(&content, heading_styles) {
+            content = Cow::Owned(promoted);
+            promoted_headings = count;
+        }
+    }
+    if let Some(merged) = spans::merge(&content) {
+        content = Cow::Owned(merged);
+    }
+    let mut page_images = 0;
+    if layout.fixed {
+        let (page, images) = layout::fixed_page(&content);
+        content = Cow::Owned(page);
+        page_images = images;
+    }
+    let mut read_along = Vec::new();
+    if let Some(mode) = options.read_along {
+        let (marked, paragraphs) = overlays.paragraphs(&spine_doc.source, &content, mode == overlay::ReadAlong::Comments);
+        if let Some(marked) = marked {
+            content = Cow::Owned(marked);
+        }
+        read_along = paragraphs;
+    }
+    let rendered = render::to_markdown(&content, &options.render);
+    let mut markdown = clean_rendered(rendered.markdown, options);
+    if layout.vertical.is_some() {
+        markdown = layout::normalize_vertical(&markdown);
+    }
+
+    // Skip empty or minimal content; an image in the spine is the content
+    if markdown.trim().is_empty() || (markdown.trim().len() < 50 && !spine_doc.image && page_images == 0) {
+        return None;
+    }
+
+    if options.sentence_per_line {
+        markdown = sentences::sentence_per_line(&markdown);
+    }
+
+    let mut links = links::extract_external_links(&spine_doc.content, &spine_doc.source);
+    for link in &mut links {
+        options.normalize.apply_mut(&mut link.text);
+        if let Some(ascii) = options.ascii_only {
+            ascii.apply_mut(&mut link.text);
+        }
     }
 
+    let mut references = if options.references {
+        references::extract_references(&spine_doc.content, &spine_doc.source)
+    } else {
+        Vec::new()
+    };
+    for reference in &mut references {
+        options.normalize.apply_mut(&mut reference.text);
+        if let Some(ascii) = options.ascii_only {
+            ascii.apply_mut(&mut reference.text);
+        }
+    }
+
+    Some(RenderedChapter {
+        markdown,
+        links,
+        references,
+        glossary: if options.anki.is_some() { anki::extract_entries(&spine_doc.content) } else { Vec::new() },
+        page_anchors: rendered.page_anchors,
+        section: sections::detect(&spine_doc.content),
+        ssml: (options.format == output::OutputFormat::Ssml).then(|| ssml::from_xhtml(&spine_doc.content)),
+        read_along,
+        promoted_headings,
+    })
+}
+
+/// Copy an `--extract-mime` spine item into the attachments directory. Returns the file
+/// written and a page linking to it, for the chapter it stands in for, from a chapter
+/// file in `part_dir` when there is one.
+fn extract_attachment(reader: &mut archive::SpineReader, index: usize, output_dir: &Path, part_dir: Option<&str>) -> Result<(PathBuf, String)> {
+    let path = reader.entries[index].path.clone();
+    let bytes = reader.read_bytes(index)?;
+    let target = output::resource_path(&output_dir.join(archive::ATTACHMENTS_DIR), &path)
+        .with_context(|| format!("Refusing to extract {}", path))?;
+    create_part_dir(&target)?;
+    output::write_atomic(&target, bytes).with_context(|| format!("Failed to write {}", target.display()))?;
+
+    let up = if part_dir.is_some() { "../" } else { "" };
+    Ok((target, archive::attachment_page(&path, up)))
+}
+
+/// `<!-- source: OEBPS/ch12.xhtml spine:14 -->`, for `--source-comments`. A `--` would
+/// end the comment early, so it is percent-encoded.
+fn source_comment(source: &str, spine_index: usize) -> String {
+    format!("<!-- source: {} spine:{} -->\n\n", source.replace("--", "%2D%2D"), spine_index)
+}
+
+/// An image in the archive and its media type, given its path there. Manifest hrefs may
+/// be percent-encoded while `path` is not, so the type is looked up by either.
+fn image_resource<R: std::io::Read + std::io::Seek>(doc: &EpubDoc<R>, resources: &mut archive::Resources, path: &str) -> Result<Option<(Vec<u8>, String)>> {
+    let mime = doc.get_resource_mime_by_path(path).or_else(|| {
+        doc.resources.values().find(|r| toc::target_path(&r.path) == path).map(|r| r.mime.clone())
+    });
+    let Some(mime) = mime.filter(|mime| mime.starts_with("image/")) else { return Ok(None) };
+    Ok(resources.read(path)?.map(|data| (data, mime)))
+}
+
+/// A chapter's file name under its part directory, when it has one
+fn in_part(filename: String, directory: Option<&str>) -> String {
+    match directory {
+        Some(directory) => format!("{}/{}", directory, filename),
+        None => filename,
+    }
+}
+
+fn create_part_dir(filepath: &Path) -> Result<()> {
+    if let Some(parent) = filepath.parent() {
+        fs::create_dir_all(parent).context("Failed to create part directory")?;
+    }
     Ok(())
 }
 
@@ -310,3 +2638,28 @@ fn sanitize_filename(name: &str) -> String {
         })
         .collect()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::OsString;
+
+    fn options(flags: &[&str]) -> ConvertOptions {
+        let args = ["epub-to-md", "convert", "book.epub"].iter().chain(flags).map(OsString::from).collect();
+        let Command::Convert(convert) = Cli::try_parse_args(args).unwrap().command else { panic!("not convert") };
+        build_options(&convert.output, &convert.run, &convert.limits, false).unwrap()
+    }
+
+    #[test]
+    fn no_tidy_leaves_the_rendered_markdown_alone() {
+        assert!(options(&[]).tidy);
+        assert!(options(&["--no-tidy", "--tidy"]).tidy);
+        let untidied = options(&["--no-tidy"]);
+        assert!(!untidied.tidy);
+
+        let rendered = render::to_markdown("<p>One <b> </b>two.</p><p></p><p></p><ul><li></li><li>item</li></ul>", &untidied.render).markdown;
+        assert_ne!(tidy::tidy(&rendered), rendered, "the sample should give tidying something to do");
+        assert_eq!(clean_rendered(rendered.clone(), &untidied), rendered);
+        assert_eq!(clean_rendered(rendered.clone(), &options(&[])), tidy::tidy(&rendered));
+    }
+}
@@ -0,0 +1,62 @@
+use crate::output::Extension;
+use clap::ValueEnum;
+
+/// How per-chapter output files are numbered
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Numbering {
+    /// Consecutive numbers for the chapters that produced output
+    #[default]
+    Sequential,
+    /// The chapter's position in the spine, so skipped items leave gaps
+    Spine,
+}
+
+/// Narrowest zero padding used for chapter numbers
+const MIN_WIDTH: usize = 3;
+
+/// Numbering scheme for chapter files
+#[derive(Debug, Clone, Copy)]
+pub struct ChapterNaming {
+    pub numbering: Numbering,
+    /// Number given to the first chapter
+    pub start: usize,
+    /// Fixed zero padding; by default it grows to fit the book's last possible number
+    pub width: Option<usize>,
+    /// Extension of Markdown chapter files
+    pub extension: Extension,
+}
+
+impl Default for ChapterNaming {
+    fn default() -> Self {
+        Self { numbering: Numbering::Sequential, start: 1, width: None, extension: Extension::Md }
+    }
+}
+
+impl ChapterNaming {
+    /// Output number for the `sequential`-th converted chapter (1-based), found at `spine_index`
+    pub fn number(&self, sequential: usize, spine_index: usize) -> usize {
+        let offset = match self.numbering {
+            Numbering::Sequential => sequential - 1,
+            Numbering::Spine => spine_index,
+        };
+        self.start + offset
+    }
+
+    /// Padding for a book with `spine_len` items, wide enough that every file name sorts correctly
+    pub fn width(&self, spine_len: usize) -> usize {
+        self.width.unwrap_or_else(|| {
+            let last = self.start + spine_len.saturating_sub(1);
+            last.to_string().len().max(MIN_WIDTH)
+        })
+    }
+
+    /// File name for a chapter written as a separate file
+    pub fn filename(&self, number: usize, spine_len: usize) -> String {
+        self.filename_with(number, spine_len, self.extension.as_str())
+    }
+
+    /// File name for a chapter written in a format other than Markdown
+    pub fn filename_with(&self, number: usize, spine_len: usize, extension: &str) -> String {
+        format!("chapter_{:0width$}.{}", number, extension, width = self.width(spine_len))
+    }
+}
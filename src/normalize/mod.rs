@@ -0,0 +1,249 @@
+use clap::ValueEnum;
+
+mod tables;
+
+/// Unicode normalization form applied to output text and file names
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Normalization {
+    /// Keep text exactly as it appears in the EPUB
+    #[default]
+    None,
+    /// Canonical composition: é stays one character however the source spelled it
+    Nfc,
+    /// Compatibility composition: also folds ligatures, full-width and other variant forms
+    Nfkc,
+}
+
+impl Normalization {
+    /// Normalize `text` to this form
+    pub fn apply(self, text: &str) -> String {
+        match self {
+            Normalization::None => text.to_string(),
+            // ASCII is already in every normalization form
+            _ if text.is_ascii() => text.to_string(),
+            Normalization::Nfc => compose(decompose(text, false)),
+            Normalization::Nfkc => compose(decompose(text, true)),
+        }
+    }
+
+    /// Normalize a string in place
+    pub fn apply_mut(self, text: &mut String) {
+        if self != Normalization::None && !text.is_ascii() {
+            *text = self.apply(text);
+        }
+    }
+}
+
+/// A character in compatibility decomposition without its combining marks: `é` is `e`,
+/// `ﬁ` is `fi` and `…` is `...`
+pub fn fold(c: char) -> String {
+    decompose(c.encode_utf8(&mut [0; 4]), true).into_iter().filter(|&c| combining_class(c) == 0).collect()
+}
+
+// Hangul syllables are composed and decomposed arithmetically rather than from the tables
+const S_BASE: u32 = 0xAC00;
+const L_BASE: u32 = 0x1100;
+const V_BASE: u32 = 0x1161;
+const T_BASE: u32 = 0x11A7;
+const L_COUNT: u32 = 19;
+const V_COUNT: u32 = 21;
+const T_COUNT: u32 = 28;
+const N_COUNT: u32 = V_COUNT * T_COUNT;
+const S_COUNT: u32 = L_COUNT * N_COUNT;
+
+fn combining_class(c: char) -> u8 {
+    tables::COMBINING_CLASS
+        .binary_search_by(|&(lo, hi, _)| {
+            if hi < c {
+                std::cmp::Ordering::Less
+            } else if lo > c {
+                std::cmp::Ordering::Greater
+            } else {
+                std::cmp::Ordering::Equal
+            }
+        })
+        .map(|i| tables::COMBINING_CLASS[i].2)
+        .unwrap_or(0)
+}
+
+fn lookup(table: &'static [(char, &'static str)], c: char) -> Option<&'static str> {
+    table.binary_search_by_key(&c, |&(k, _)| k).ok().map(|i| table[i].1)
+}
+
+/// Fully decompose `text` and put combining marks in canonical order
+fn decompose(text: &str, compatibility: bool) -> Vec<char> {
+    let mut out: Vec<char> = Vec::with_capacity(text.len());
+    for c in text.chars() {
+        let s = c as u32;
+        if (S_BASE..S_BASE + S_COUNT).contains(&s) {
+            let index = s - S_BASE;
+            out.push(char::from_u32(L_BASE + index / N_COUNT).unwrap());
+            out.push(char::from_u32(V_BASE + (index % N_COUNT) / T_COUNT).unwrap());
+            if !index.is_multiple_of(T_COUNT) {
+                out.push(char::from_u32(T_BASE + index % T_COUNT).unwrap());
+            }
+            continue;
+        }
+        let mapped = compatibility
+            .then(|| lookup(tables::COMPATIBILITY, c))
+            .flatten()
+            .or_else(|| lookup(tables::CANONICAL, c));
+        match mapped {
+            Some(decomposed) => out.extend(decomposed.chars()),
+            None => out.push(c),
+        }
+    }
+
+    // Canonical ordering: stable-sort each run of combining marks by class
+    let mut start = 0;
+    while start < out.len() {
+        if combining_class(out[start]) == 0 {
+            start += 1;
+            continue;
+        }
+        let end = out[start..].iter().position(|&c| combining_class(c) == 0).map_or(out.len(), |n| start + n);
+        out[start..end].sort_by_key(|&c| combining_class(c));
+        start = end;
+    }
+    out
+}
+
+fn compose_pair(a: char, b: char) -> Option<char> {
+    let (sa, sb) = (a as u32, b as u32);
+    if (L_BASE..L_BASE + L_COUNT).contains(&sa) && (V_BASE..V_BASE + V_COUNT).contains(&sb) {
+        return char::from_u32(S_BASE + ((sa - L_BASE) * V_COUNT + (sb - V_BASE)) * T_COUNT);
+    }
+    if (S_BASE..S_BASE + S_COUNT).contains(&sa)
+        && (sa - S_BASE).is_multiple_of(T_COUNT)
+        && (T_BASE + 1..T_BASE + T_COUNT).contains(&sb)
+    {
+        return char::from_u32(sa + (sb - T_BASE));
+    }
+    tables::COMPOSITION
+        .binary_search_by_key(&(a, b), |&(x, y, _)| (x, y))
+        .ok()
+        .map(|i| tables::COMPOSITION[i].2)
+}
+
+/// Canonical composition of decomposed, canonically ordered text
+fn compose(chars: Vec<char>) -> String {
+    let mut out: Vec<char> = Vec::with_capacity(chars.len());
+    // Index in `out` of the last starter, and the class of the last character after it
+    let mut starter: Option<usize> = None;
+    let mut last_class = 0u8;
+    for c in chars {
+        let class = combining_class(c);
+        if let Some(s) = starter {
+            // A mark is blocked from the starter by an intervening character of equal or higher class
+            let adjacent = out.len() == s + 1;
+            if adjacent || (last_class != 0 && last_class < class) {
+                if let Some(composed) = compose_pair(out[s], c) {
+                    out[s] = composed;
+                    continue;
+                }
+            }
+        }
+        if class == 0 {
+            starter = Some(out.len());
+            last_class = 0;
+        } else {
+            last_class = class;
+        }
+        out.push(c);
+    }
+    out.into_iter().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn nfd(text: &str) -> String {
+        decompose(text, false).into_iter().collect()
+    }
+
+    fn nfkd(text: &str) -> String {
+        decompose(text, true).into_iter().collect()
+    }
+
+    #[test]
+    fn tables_are_sorted_for_binary_search() {
+        assert!(tables::CANONICAL.windows(2).all(|pair| pair[0].0 < pair[1].0));
+        assert!(tables::COMPATIBILITY.windows(2).all(|pair| pair[0].0 < pair[1].0));
+        assert!(tables::COMPOSITION.windows(2).all(|pair| (pair[0].0, pair[0].1) < (pair[1].0, pair[1].1)));
+        assert!(tables::COMBINING_CLASS.iter().all(|&(lo, hi, class)| lo <= hi && class > 0));
+        assert!(tables::COMBINING_CLASS.windows(2).all(|pair| pair[0].1 < pair[1].0));
+    }
+
+    #[test]
+    fn canonical_decompositions_round_trip() {
+        for &(c, decomposed) in tables::CANONICAL {
+            let original = c.to_string();
+            assert_eq!(nfd(&original), decomposed, "NFD of {:?}", c);
+            // Canonically equivalent spellings compose alike, and decomposing undoes it
+            let composed = Normalization::Nfc.apply(decomposed);
+            assert_eq!(Normalization::Nfc.apply(&original), composed, "NFC of {:?}", c);
+            assert_eq!(nfd(&composed), decomposed, "NFD of NFC of {:?}", c);
+        }
+        assert_eq!(Normalization::Nfc.apply("e\u{301}"), "é");
+        assert_eq!(nfd("é"), "e\u{301}");
+        // Singletons and composition exclusions don't come back
+        assert_eq!(Normalization::Nfc.apply("\u{212B}"), "\u{C5}");
+        assert_eq!(Normalization::Nfc.apply("\u{958}"), "\u{915}\u{93C}");
+    }
+
+    #[test]
+    fn every_composition_has_its_decomposition() {
+        for &(a, b, composed) in tables::COMPOSITION {
+            assert_eq!(compose_pair(a, b), Some(composed));
+            assert_eq!(nfd(&composed.to_string()), nfd(&format!("{}{}", a, b)), "{:?}", composed);
+        }
+    }
+
+    #[test]
+    fn combining_marks_are_put_in_canonical_order() {
+        assert_eq!(combining_class('\u{301}'), 230);
+        assert_eq!(combining_class('\u{323}'), 220);
+        assert_eq!(combining_class('a'), 0);
+        // Dot below (220) sorts before the circumflex (230) either way they are written
+        assert_eq!(nfd("a\u{302}\u{323}"), "a\u{323}\u{302}");
+        assert_eq!(Normalization::Nfc.apply("a\u{302}\u{323}"), "\u{1EAD}");
+        assert_eq!(Normalization::Nfc.apply("a\u{323}\u{302}"), "\u{1EAD}");
+    }
+
+    #[test]
+    fn hangul_syllables_compose_arithmetically() {
+        assert_eq!(nfd("한"), "\u{1112}\u{1161}\u{11AB}");
+        assert_eq!(Normalization::Nfc.apply("\u{1112}\u{1161}\u{11AB}"), "한");
+        assert_eq!(Normalization::Nfc.apply("\u{1100}\u{1161}"), "가");
+    }
+
+    #[test]
+    fn compatibility_forms_fold_and_stay_folded() {
+        for &(c, decomposed) in tables::COMPATIBILITY {
+            let once = Normalization::Nfkc.apply(&c.to_string());
+            assert_eq!(Normalization::Nfkc.apply(&once), once, "NFKC of {:?}", c);
+            assert_eq!(nfkd(&once), nfkd(decomposed), "NFKD of {:?}", c);
+        }
+        assert_eq!(Normalization::Nfkc.apply("ﬁne"), "fine");
+        assert_eq!(Normalization::Nfkc.apply("ＡＢＣ①"), "ABC1");
+        // NFC leaves compatibility forms alone
+        assert_eq!(Normalization::Nfc.apply("ﬁ①"), "ﬁ①");
+    }
+
+    #[test]
+    fn folding_drops_marks_and_spells_out_variants() {
+        assert_eq!(fold('é'), "e");
+        assert_eq!(fold('Ǻ'), "A");
+        assert_eq!(fold('ﬁ'), "fi");
+        assert_eq!(fold('…'), "...");
+        assert_eq!(fold('\u{301}'), "");
+        assert_eq!(fold('ж'), "ж");
+        // Every precomposed Latin letter folds to its ASCII base
+        for &(c, decomposed) in tables::CANONICAL.iter().filter(|(c, _)| ('\u{C0}'..='\u{17F}').contains(c)) {
+            let base = decomposed.chars().next().unwrap();
+            assert_eq!(fold(c), base.to_string(), "fold of {:?}", c);
+            assert!(base.is_ascii_alphabetic());
+        }
+    }
+}
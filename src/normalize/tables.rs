@@ -0,0 +1,7151 @@
+// Generated by scripts/unicode_tables.py from Unicode 14.0.0; do not edit.
+
+/// Full canonical decompositions, excluding Hangul syllables
+pub const CANONICAL: &[(char, &str)] = &[
+    ('\u{C0}', "\u{41}\u{300}"),
+    ('\u{C1}', "\u{41}\u{301}"),
+    ('\u{C2}', "\u{41}\u{302}"),
+    ('\u{C3}', "\u{41}\u{303}"),
+    ('\u{C4}', "\u{41}\u{308}"),
+    ('\u{C5}', "\u{41}\u{30A}"),
+    ('\u{C7}', "\u{43}\u{327}"),
+    ('\u{C8}', "\u{45}\u{300}"),
+    ('\u{C9}', "\u{45}\u{301}"),
+    ('\u{CA}', "\u{45}\u{302}"),
+    ('\u{CB}', "\u{45}\u{308}"),
+    ('\u{CC}', "\u{49}\u{300}"),
+    ('\u{CD}', "\u{49}\u{301}"),
+    ('\u{CE}', "\u{49}\u{302}"),
+    ('\u{CF}', "\u{49}\u{308}"),
+    ('\u{D1}', "\u{4E}\u{303}"),
+    ('\u{D2}', "\u{4F}\u{300}"),
+    ('\u{D3}', "\u{4F}\u{301}"),
+    ('\u{D4}', "\u{4F}\u{302}"),
+    ('\u{D5}', "\u{4F}\u{303}"),
+    ('\u{D6}', "\u{4F}\u{308}"),
+    ('\u{D9}', "\u{55}\u{300}"),
+    ('\u{DA}', "\u{55}\u{301}"),
+    ('\u{DB}', "\u{55}\u{302}"),
+    ('\u{DC}', "\u{55}\u{308}"),
+    ('\u{DD}', "\u{59}\u{301}"),
+    ('\u{E0}', "\u{61}\u{300}"),
+    ('\u{E1}', "\u{61}\u{301}"),
+    ('\u{E2}', "\u{61}\u{302}"),
+    ('\u{E3}', "\u{61}\u{303}"),
+    ('\u{E4}', "\u{61}\u{308}"),
+    ('\u{E5}', "\u{61}\u{30A}"),
+    ('\u{E7}', "\u{63}\u{327}"),
+    ('\u{E8}', "\u{65}\u{300}"),
+    ('\u{E9}', "\u{65}\u{301}"),
+    ('\u{EA}', "\u{65}\u{302}"),
+    ('\u{EB}', "\u{65}\u{308}"),
+    ('\u{EC}', "\u{69}\u{300}"),
+    ('\u{ED}', "\u{69}\u{301}"),
+    ('\u{EE}', "\u{69}\u{302}"),
+    ('\u{EF}', "\u{69}\u{308}"),
+    ('\u{F1}', "\u{6E}\u{303}"),
+    ('\u{F2}', "\u{6F}\u{300}"),
+    ('\u{F3}', "\u{6F}\u{301}"),
+    ('\u{F4}', "\u{6F}\u{302}"),
+    ('\u{F5}', "\u{6F}\u{303}"),
+    ('\u{F6}', "\u{6F}\u{308}"),
+    ('\u{F9}', "\u{75}\u{300}"),
+    ('\u{FA}', "\u{75}\u{301}"),
+    ('\u{FB}', "\u{75}\u{302}"),
+    ('\u{FC}', "\u{75}\u{308}"),
+    ('\u{FD}', "\u{79}\u{301}"),
+    ('\u{FF}', "\u{79}\u{308}"),
+    ('\u{100}', "\u{41}\u{304}"),
+    ('\u{101}', "\u{61}\u{304}"),
+    ('\u{102}', "\u{41}\u{306}"),
+    ('\u{103}', "\u{61}\u{306}"),
+    ('\u{104}', "\u{41}\u{328}"),
+    ('\u{105}', "\u{61}\u{328}"),
+    ('\u{106}', "\u{43}\u{301}"),
+    ('\u{107}', "\u{63}\u{301}"),
+    ('\u{108}', "\u{43}\u{302}"),
+    ('\u{109}', "\u{63}\u{302}"),
+    ('\u{10A}', "\u{43}\u{307}"),
+    ('\u{10B}', "\u{63}\u{307}"),
+    ('\u{10C}', "\u{43}\u{30C}"),
+    ('\u{10D}', "\u{63}\u{30C}"),
+    ('\u{10E}', "\u{44}\u{30C}"),
+    ('\u{10F}', "\u{64}\u{30C}"),
+    ('\u{112}', "\u{45}\u{304}"),
+    ('\u{113}', "\u{65}\u{304}"),
+    ('\u{114}', "\u{45}\u{306}"),
+    ('\u{115}', "\u{65}\u{306}"),
+    ('\u{116}', "\u{45}\u{307}"),
+    ('\u{117}', "\u{65}\u{307}"),
+    ('\u{118}', "\u{45}\u{328}"),
+    ('\u{119}', "\u{65}\u{328}"),
+    ('\u{11A}', "\u{45}\u{30C}"),
+    ('\u{11B}', "\u{65}\u{30C}"),
+    ('\u{11C}', "\u{47}\u{302}"),
+    ('\u{11D}', "\u{67}\u{302}"),
+    ('\u{11E}', "\u{47}\u{306}"),
+    ('\u{11F}', "\u{67}\u{306}"),
+    ('\u{120}', "\u{47}\u{307}"),
+    ('\u{121}', "\u{67}\u{307}"),
+    ('\u{122}', "\u{47}\u{327}"),
+    ('\u{123}', "\u{67}\u{327}"),
+    ('\u{124}', "\u{48}\u{302}"),
+    ('\u{125}', "\u{68}\u{302}"),
+    ('\u{128}', "\u{49}\u{303}"),
+    ('\u{129}', "\u{69}\u{303}"),
+    ('\u{12A}', "\u{49}\u{304}"),
+    ('\u{12B}', "\u{69}\u{304}"),
+    ('\u{12C}', "\u{49}\u{306}"),
+    ('\u{12D}', "\u{69}\u{306}"),
+    ('\u{12E}', "\u{49}\u{328}"),
+    ('\u{12F}', "\u{69}\u{328}"),
+    ('\u{130}', "\u{49}\u{307}"),
+    ('\u{134}', "\u{4A}\u{302}"),
+    ('\u{135}', "\u{6A}\u{302}"),
+    ('\u{136}', "\u{4B}\u{327}"),
+    ('\u{137}', "\u{6B}\u{327}"),
+    ('\u{139}', "\u{4C}\u{301}"),
+    ('\u{13A}', "\u{6C}\u{301}"),
+    ('\u{13B}', "\u{4C}\u{327}"),
+    ('\u{13C}', "\u{6C}\u{327}"),
+    ('\u{13D}', "\u{4C}\u{30C}"),
+    ('\u{13E}', "\u{6C}\u{30C}"),
+    ('\u{143}', "\u{4E}\u{301}"),
+    ('\u{144}', "\u{6E}\u{301}"),
+    ('\u{145}', "\u{4E}\u{327}"),
+    ('\u{146}', "\u{6E}\u{327}"),
+    ('\u{147}', "\u{4E}\u{30C}"),
+    ('\u{148}', "\u{6E}\u{30C}"),
+    ('\u{14C}', "\u{4F}\u{304}"),
+    ('\u{14D}', "\u{6F}\u{304}"),
+    ('\u{14E}', "\u{4F}\u{306}"),
+    ('\u{14F}', "\u{6F}\u{306}"),
+    ('\u{150}', "\u{4F}\u{30B}"),
+    ('\u{151}', "\u{6F}\u{30B}"),
+    ('\u{154}', "\u{52}\u{301}"),
+    ('\u{155}', "\u{72}\u{301}"),
+    ('\u{156}', "\u{52}\u{327}"),
+    ('\u{157}', "\u{72}\u{327}"),
+    ('\u{158}', "\u{52}\u{30C}"),
+    ('\u{159}', "\u{72}\u{30C}"),
+    ('\u{15A}', "\u{53}\u{301}"),
+    ('\u{15B}', "\u{73}\u{301}"),
+    ('\u{15C}', "\u{53}\u{302}"),
+    ('\u{15D}', "\u{73}\u{302}"),
+    ('\u{15E}', "\u{53}\u{327}"),
+    ('\u{15F}', "\u{73}\u{327}"),
+    ('\u{160}', "\u{53}\u{30C}"),
+    ('\u{161}', "\u{73}\u{30C}"),
+    ('\u{162}', "\u{54}\u{327}"),
+    ('\u{163}', "\u{74}\u{327}"),
+    ('\u{164}', "\u{54}\u{30C}"),
+    ('\u{165}', "\u{74}\u{30C}"),
+    ('\u{168}', "\u{55}\u{303}"),
+    ('\u{169}', "\u{75}\u{303}"),
+    ('\u{16A}', "\u{55}\u{304}"),
+    ('\u{16B}', "\u{75}\u{304}"),
+    ('\u{16C}', "\u{55}\u{306}"),
+    ('\u{16D}', "\u{75}\u{306}"),
+    ('\u{16E}', "\u{55}\u{30A}"),
+    ('\u{16F}', "\u{75}\u{30A}"),
+    ('\u{170}', "\u{55}\u{30B}"),
+    ('\u{171}', "\u{75}\u{30B}"),
+    ('\u{172}', "\u{55}\u{328}"),
+    ('\u{173}', "\u{75}\u{328}"),
+    ('\u{174}', "\u{57}\u{302}"),
+    ('\u{175}', "\u{77}\u{302}"),
+    ('\u{176}', "\u{59}\u{302}"),
+    ('\u{177}', "\u{79}\u{302}"),
+    ('\u{178}', "\u{59}\u{308}"),
+    ('\u{179}', "\u{5A}\u{301}"),
+    ('\u{17A}', "\u{7A}\u{301}"),
+    ('\u{17B}', "\u{5A}\u{307}"),
+    ('\u{17C}', "\u{7A}\u{307}"),
+    ('\u{17D}', "\u{5A}\u{30C}"),
+    ('\u{17E}', "\u{7A}\u{30C}"),
+    ('\u{1A0}', "\u{4F}\u{31B}"),
+    ('\u{1A1}', "\u{6F}\u{31B}"),
+    ('\u{1AF}', "\u{55}\u{31B}"),
+    ('\u{1B0}', "\u{75}\u{31B}"),
+    ('\u{1CD}', "\u{41}\u{30C}"),
+    ('\u{1CE}', "\u{61}\u{30C}"),
+    ('\u{1CF}', "\u{49}\u{30C}"),
+    ('\u{1D0}', "\u{69}\u{30C}"),
+    ('\u{1D1}', "\u{4F}\u{30C}"),
+    ('\u{1D2}', "\u{6F}\u{30C}"),
+    ('\u{1D3}', "\u{55}\u{30C}"),
+    ('\u{1D4}', "\u{75}\u{30C}"),
+    ('\u{1D5}', "\u{55}\u{308}\u{304}"),
+    ('\u{1D6}', "\u{75}\u{308}\u{304}"),
+    ('\u{1D7}', "\u{55}\u{308}\u{301}"),
+    ('\u{1D8}', "\u{75}\u{308}\u{301}"),
+    ('\u{1D9}', "\u{55}\u{308}\u{30C}"),
+    ('\u{1DA}', "\u{75}\u{308}\u{30C}"),
+    ('\u{1DB}', "\u{55}\u{308}\u{300}"),
+    ('\u{1DC}', "\u{75}\u{308}\u{300}"),
+    ('\u{1DE}', "\u{41}\u{308}\u{304}"),
+    ('\u{1DF}', "\u{61}\u{308}\u{304}"),
+    ('\u{1E0}', "\u{41}\u{307}\u{304}"),
+    ('\u{1E1}', "\u{61}\u{307}\u{304}"),
+    ('\u{1E2}', "\u{C6}\u{304}"),
+    ('\u{1E3}', "\u{E6}\u{304}"),
+    ('\u{1E6}', "\u{47}\u{30C}"),
+    ('\u{1E7}', "\u{67}\u{30C}"),
+    ('\u{1E8}', "\u{4B}\u{30C}"),
+    ('\u{1E9}', "\u{6B}\u{30C}"),
+    ('\u{1EA}', "\u{4F}\u{328}"),
+    ('\u{1EB}', "\u{6F}\u{328}"),
+    ('\u{1EC}', "\u{4F}\u{328}\u{304}"),
+    ('\u{1ED}', "\u{6F}\u{328}\u{304}"),
+    ('\u{1EE}', "\u{1B7}\u{30C}"),
+    ('\u{1EF}', "\u{292}\u{30C}"),
+    ('\u{1F0}', "\u{6A}\u{30C}"),
+    ('\u{1F4}', "\u{47}\u{301}"),
+    ('\u{1F5}', "\u{67}\u{301}"),
+    ('\u{1F8}', "\u{4E}\u{300}"),
+    ('\u{1F9}', "\u{6E}\u{300}"),
+    ('\u{1FA}', "\u{41}\u{30A}\u{301}"),
+    ('\u{1FB}', "\u{61}\u{30A}\u{301}"),
+    ('\u{1FC}', "\u{C6}\u{301}"),
+    ('\u{1FD}', "\u{E6}\u{301}"),
+    ('\u{1FE}', "\u{D8}\u{301}"),
+    ('\u{1FF}', "\u{F8}\u{301}"),
+    ('\u{200}', "\u{41}\u{30F}"),
+    ('\u{201}', "\u{61}\u{30F}"),
+    ('\u{202}', "\u{41}\u{311}"),
+    ('\u{203}', "\u{61}\u{311}"),
+    ('\u{204}', "\u{45}\u{30F}"),
+    ('\u{205}', "\u{65}\u{30F}"),
+    ('\u{206}', "\u{45}\u{311}"),
+    ('\u{207}', "\u{65}\u{311}"),
+    ('\u{208}', "\u{49}\u{30F}"),
+    ('\u{209}', "\u{69}\u{30F}"),
+    ('\u{20A}', "\u{49}\u{311}"),
+    ('\u{20B}', "\u{69}\u{311}"),
+    ('\u{20C}', "\u{4F}\u{30F}"),
+    ('\u{20D}', "\u{6F}\u{30F}"),
+    ('\u{20E}', "\u{4F}\u{311}"),
+    ('\u{20F}', "\u{6F}\u{311}"),
+    ('\u{210}', "\u{52}\u{30F}"),
+    ('\u{211}', "\u{72}\u{30F}"),
+    ('\u{212}', "\u{52}\u{311}"),
+    ('\u{213}', "\u{72}\u{311}"),
+    ('\u{214}', "\u{55}\u{30F}"),
+    ('\u{215}', "\u{75}\u{30F}"),
+    ('\u{216}', "\u{55}\u{311}"),
+    ('\u{217}', "\u{75}\u{311}"),
+    ('\u{218}', "\u{53}\u{326}"),
+    ('\u{219}', "\u{73}\u{326}"),
+    ('\u{21A}', "\u{54}\u{326}"),
+    ('\u{21B}', "\u{74}\u{326}"),
+    ('\u{21E}', "\u{48}\u{30C}"),
+    ('\u{21F}', "\u{68}\u{30C}"),
+    ('\u{226}', "\u{41}\u{307}"),
+    ('\u{227}', "\u{61}\u{307}"),
+    ('\u{228}', "\u{45}\u{327}"),
+    ('\u{229}', "\u{65}\u{327}"),
+    ('\u{22A}', "\u{4F}\u{308}\u{304}"),
+    ('\u{22B}', "\u{6F}\u{308}\u{304}"),
+    ('\u{22C}', "\u{4F}\u{303}\u{304}"),
+    ('\u{22D}', "\u{6F}\u{303}\u{304}"),
+    ('\u{22E}', "\u{4F}\u{307}"),
+    ('\u{22F}', "\u{6F}\u{307}"),
+    ('\u{230}', "\u{4F}\u{307}\u{304}"),
+    ('\u{231}', "\u{6F}\u{307}\u{304}"),
+    ('\u{232}', "\u{59}\u{304}"),
+    ('\u{233}', "\u{79}\u{304}"),
+    ('\u{340}', "\u{300}"),
+    ('\u{341}', "\u{301}"),
+    ('\u{343}', "\u{313}"),
+    ('\u{344}', "\u{308}\u{301}"),
+    ('\u{374}', "\u{2B9}"),
+    ('\u{37E}', "\u{3B}"),
+    ('\u{385}', "\u{A8}\u{301}"),
+    ('\u{386}', "\u{391}\u{301}"),
+    ('\u{387}', "\u{B7}"),
+    ('\u{388}', "\u{395}\u{301}"),
+    ('\u{389}', "\u{397}\u{301}"),
+    ('\u{38A}', "\u{399}\u{301}"),
+    ('\u{38C}', "\u{39F}\u{301}"),
+    ('\u{38E}', "\u{3A5}\u{301}"),
+    ('\u{38F}', "\u{3A9}\u{301}"),
+    ('\u{390}', "\u{3B9}\u{308}\u{301}"),
+    ('\u{3AA}', "\u{399}\u{308}"),
+    ('\u{3AB}', "\u{3A5}\u{308}"),
+    ('\u{3AC}', "\u{3B1}\u{301}"),
+    ('\u{3AD}', "\u{3B5}\u{301}"),
+    ('\u{3AE}', "\u{3B7}\u{301}"),
+    ('\u{3AF}', "\u{3B9}\u{301}"),
+    ('\u{3B0}', "\u{3C5}\u{308}\u{301}"),
+    ('\u{3CA}', "\u{3B9}\u{308}"),
+    ('\u{3CB}', "\u{3C5}\u{308}"),
+    ('\u{3CC}', "\u{3BF}\u{301}"),
+    ('\u{3CD}', "\u{3C5}\u{301}"),
+    ('\u{3CE}', "\u{3C9}\u{301}"),
+    ('\u{3D3}', "\u{3D2}\u{301}"),
+    ('\u{3D4}', "\u{3D2}\u{308}"),
+    ('\u{400}', "\u{415}\u{300}"),
+    ('\u{401}', "\u{415}\u{308}"),
+    ('\u{403}', "\u{413}\u{301}"),
+    ('\u{407}', "\u{406}\u{308}"),
+    ('\u{40C}', "\u{41A}\u{301}"),
+    ('\u{40D}', "\u{418}\u{300}"),
+    ('\u{40E}', "\u{423}\u{306}"),
+    ('\u{419}', "\u{418}\u{306}"),
+    ('\u{439}', "\u{438}\u{306}"),
+    ('\u{450}', "\u{435}\u{300}"),
+    ('\u{451}', "\u{435}\u{308}"),
+    ('\u{453}', "\u{433}\u{301}"),
+    ('\u{457}', "\u{456}\u{308}"),
+    ('\u{45C}', "\u{43A}\u{301}"),
+    ('\u{45D}', "\u{438}\u{300}"),
+    ('\u{45E}', "\u{443}\u{306}"),
+    ('\u{476}', "\u{474}\u{30F}"),
+    ('\u{477}', "\u{475}\u{30F}"),
+    ('\u{4C1}', "\u{416}\u{306}"),
+    ('\u{4C2}', "\u{436}\u{306}"),
+    ('\u{4D0}', "\u{410}\u{306}"),
+    ('\u{4D1}', "\u{430}\u{306}"),
+    ('\u{4D2}', "\u{410}\u{308}"),
+    ('\u{4D3}', "\u{430}\u{308}"),
+    ('\u{4D6}', "\u{415}\u{306}"),
+    ('\u{4D7}', "\u{435}\u{306}"),
+    ('\u{4DA}', "\u{4D8}\u{308}"),
+    ('\u{4DB}', "\u{4D9}\u{308}"),
+    ('\u{4DC}', "\u{416}\u{308}"),
+    ('\u{4DD}', "\u{436}\u{308}"),
+    ('\u{4DE}', "\u{417}\u{308}"),
+    ('\u{4DF}', "\u{437}\u{308}"),
+    ('\u{4E2}', "\u{418}\u{304}"),
+    ('\u{4E3}', "\u{438}\u{304}"),
+    ('\u{4E4}', "\u{418}\u{308}"),
+    ('\u{4E5}', "\u{438}\u{308}"),
+    ('\u{4E6}', "\u{41E}\u{308}"),
+    ('\u{4E7}', "\u{43E}\u{308}"),
+    ('\u{4EA}', "\u{4E8}\u{308}"),
+    ('\u{4EB}', "\u{4E9}\u{308}"),
+    ('\u{4EC}', "\u{42D}\u{308}"),
+    ('\u{4ED}', "\u{44D}\u{308}"),
+    ('\u{4EE}', "\u{423}\u{304}"),
+    ('\u{4EF}', "\u{443}\u{304}"),
+    ('\u{4F0}', "\u{423}\u{308}"),
+    ('\u{4F1}', "\u{443}\u{308}"),
+    ('\u{4F2}', "\u{423}\u{30B}"),
+    ('\u{4F3}', "\u{443}\u{30B}"),
+    ('\u{4F4}', "\u{427}\u{308}"),
+    ('\u{4F5}', "\u{447}\u{308}"),
+    ('\u{4F8}', "\u{42B}\u{308}"),
+    ('\u{4F9}', "\u{44B}\u{308}"),
+    ('\u{622}', "\u{627}\u{653}"),
+    ('\u{623}', "\u{627}\u{654}"),
+    ('\u{624}', "\u{648}\u{654}"),
+    ('\u{625}', "\u{627}\u{655}"),
+    ('\u{626}', "\u{64A}\u{654}"),
+    ('\u{6C0}', "\u{6D5}\u{654}"),
+    ('\u{6C2}', "\u{6C1}\u{654}"),
+    ('\u{6D3}', "\u{6D2}\u{654}"),
+    ('\u{929}', "\u{928}\u{93C}"),
+    ('\u{931}', "\u{930}\u{93C}"),
+    ('\u{934}', "\u{933}\u{93C}"),
+    ('\u{958}', "\u{915}\u{93C}"),
+    ('\u{959}', "\u{916}\u{93C}"),
+    ('\u{95A}', "\u{917}\u{93C}"),
+    ('\u{95B}', "\u{91C}\u{93C}"),
+    ('\u{95C}', "\u{921}\u{93C}"),
+    ('\u{95D}', "\u{922}\u{93C}"),
+    ('\u{95E}', "\u{92B}\u{93C}"),
+    ('\u{95F}', "\u{92F}\u{93C}"),
+    ('\u{9CB}', "\u{9C7}\u{9BE}"),
+    ('\u{9CC}', "\u{9C7}\u{9D7}"),
+    ('\u{9DC}', "\u{9A1}\u{9BC}"),
+    ('\u{9DD}', "\u{9A2}\u{9BC}"),
+    ('\u{9DF}', "\u{9AF}\u{9BC}"),
+    ('\u{A33}', "\u{A32}\u{A3C}"),
+    ('\u{A36}', "\u{A38}\u{A3C}"),
+    ('\u{A59}', "\u{A16}\u{A3C}"),
+    ('\u{A5A}', "\u{A17}\u{A3C}"),
+    ('\u{A5B}', "\u{A1C}\u{A3C}"),
+    ('\u{A5E}', "\u{A2B}\u{A3C}"),
+    ('\u{B48}', "\u{B47}\u{B56}"),
+    ('\u{B4B}', "\u{B47}\u{B3E}"),
+    ('\u{B4C}', "\u{B47}\u{B57}"),
+    ('\u{B5C}', "\u{B21}\u{B3C}"),
+    ('\u{B5D}', "\u{B22}\u{B3C}"),
+    ('\u{B94}', "\u{B92}\u{BD7}"),
+    ('\u{BCA}', "\u{BC6}\u{BBE}"),
+    ('\u{BCB}', "\u{BC7}\u{BBE}"),
+    ('\u{BCC}', "\u{BC6}\u{BD7}"),
+    ('\u{C48}', "\u{C46}\u{C56}"),
+    ('\u{CC0}', "\u{CBF}\u{CD5}"),
+    ('\u{CC7}', "\u{CC6}\u{CD5}"),
+    ('\u{CC8}', "\u{CC6}\u{CD6}"),
+    ('\u{CCA}', "\u{CC6}\u{CC2}"),
+    ('\u{CCB}', "\u{CC6}\u{CC2}\u{CD5}"),
+    ('\u{D4A}', "\u{D46}\u{D3E}"),
+    ('\u{D4B}', "\u{D47}\u{D3E}"),
+    ('\u{D4C}', "\u{D46}\u{D57}"),
+    ('\u{DDA}', "\u{DD9}\u{DCA}"),
+    ('\u{DDC}', "\u{DD9}\u{DCF}"),
+    ('\u{DDD}', "\u{DD9}\u{DCF}\u{DCA}"),
+    ('\u{DDE}', "\u{DD9}\u{DDF}"),
+    ('\u{F43}', "\u{F42}\u{FB7}"),
+    ('\u{F4D}', "\u{F4C}\u{FB7}"),
+    ('\u{F52}', "\u{F51}\u{FB7}"),
+    ('\u{F57}', "\u{F56}\u{FB7}"),
+    ('\u{F5C}', "\u{F5B}\u{FB7}"),
+    ('\u{F69}', "\u{F40}\u{FB5}"),
+    ('\u{F73}', "\u{F71}\u{F72}"),
+    ('\u{F75}', "\u{F71}\u{F74}"),
+    ('\u{F76}', "\u{FB2}\u{F80}"),
+    ('\u{F78}', "\u{FB3}\u{F80}"),
+    ('\u{F81}', "\u{F71}\u{F80}"),
+    ('\u{F93}', "\u{F92}\u{FB7}"),
+    ('\u{F9D}', "\u{F9C}\u{FB7}"),
+    ('\u{FA2}', "\u{FA1}\u{FB7}"),
+    ('\u{FA7}', "\u{FA6}\u{FB7}"),
+    ('\u{FAC}', "\u{FAB}\u{FB7}"),
+    ('\u{FB9}', "\u{F90}\u{FB5}"),
+    ('\u{1026}', "\u{1025}\u{102E}"),
+    ('\u{1B06}', "\u{1B05}\u{1B35}"),
+    ('\u{1B08}', "\u{1B07}\u{1B35}"),
+    ('\u{1B0A}', "\u{1B09}\u{1B35}"),
+    ('\u{1B0C}', "\u{1B0B}\u{1B35}"),
+    ('\u{1B0E}', "\u{1B0D}\u{1B35}"),
+    ('\u{1B12}', "\u{1B11}\u{1B35}"),
+    ('\u{1B3B}', "\u{1B3A}\u{1B35}"),
+    ('\u{1B3D}', "\u{1B3C}\u{1B35}"),
+    ('\u{1B40}', "\u{1B3E}\u{1B35}"),
+    ('\u{1B41}', "\u{1B3F}\u{1B35}"),
+    ('\u{1B43}', "\u{1B42}\u{1B35}"),
+    ('\u{1E00}', "\u{41}\u{325}"),
+    ('\u{1E01}', "\u{61}\u{325}"),
+    ('\u{1E02}', "\u{42}\u{307}"),
+    ('\u{1E03}', "\u{62}\u{307}"),
+    ('\u{1E04}', "\u{42}\u{323}"),
+    ('\u{1E05}', "\u{62}\u{323}"),
+    ('\u{1E06}', "\u{42}\u{331}"),
+    ('\u{1E07}', "\u{62}\u{331}"),
+    ('\u{1E08}', "\u{43}\u{327}\u{301}"),
+    ('\u{1E09}', "\u{63}\u{327}\u{301}"),
+    ('\u{1E0A}', "\u{44}\u{307}"),
+    ('\u{1E0B}', "\u{64}\u{307}"),
+    ('\u{1E0C}', "\u{44}\u{323}"),
+    ('\u{1E0D}', "\u{64}\u{323}"),
+    ('\u{1E0E}', "\u{44}\u{331}"),
+    ('\u{1E0F}', "\u{64}\u{331}"),
+    ('\u{1E10}', "\u{44}\u{327}"),
+    ('\u{1E11}', "\u{64}\u{327}"),
+    ('\u{1E12}', "\u{44}\u{32D}"),
+    ('\u{1E13}', "\u{64}\u{32D}"),
+    ('\u{1E14}', "\u{45}\u{304}\u{300}"),
+    ('\u{1E15}', "\u{65}\u{304}\u{300}"),
+    ('\u{1E16}', "\u{45}\u{304}\u{301}"),
+    ('\u{1E17}', "\u{65}\u{304}\u{301}"),
+    ('\u{1E18}', "\u{45}\u{32D}"),
+    ('\u{1E19}', "\u{65}\u{32D}"),
+    ('\u{1E1A}', "\u{45}\u{330}"),
+    ('\u{1E1B}', "\u{65}\u{330}"),
+    ('\u{1E1C}', "\u{45}\u{327}\u{306}"),
+    ('\u{1E1D}', "\u{65}\u{327}\u{306}"),
+    ('\u{1E1E}', "\u{46}\u{307}"),
+    ('\u{1E1F}', "\u{66}\u{307}"),
+    ('\u{1E20}', "\u{47}\u{304}"),
+    ('\u{1E21}', "\u{67}\u{304}"),
+    ('\u{1E22}', "\u{48}\u{307}"),
+    ('\u{1E23}', "\u{68}\u{307}"),
+    ('\u{1E24}', "\u{48}\u{323}"),
+    ('\u{1E25}', "\u{68}\u{323}"),
+    ('\u{1E26}', "\u{48}\u{308}"),
+    ('\u{1E27}', "\u{68}\u{308}"),
+    ('\u{1E28}', "\u{48}\u{327}"),
+    ('\u{1E29}', "\u{68}\u{327}"),
+    ('\u{1E2A}', "\u{48}\u{32E}"),
+    ('\u{1E2B}', "\u{68}\u{32E}"),
+    ('\u{1E2C}', "\u{49}\u{330}"),
+    ('\u{1E2D}', "\u{69}\u{330}"),
+    ('\u{1E2E}', "\u{49}\u{308}\u{301}"),
+    ('\u{1E2F}', "\u{69}\u{308}\u{301}"),
+    ('\u{1E30}', "\u{4B}\u{301}"),
+    ('\u{1E31}', "\u{6B}\u{301}"),
+    ('\u{1E32}', "\u{4B}\u{323}"),
+    ('\u{1E33}', "\u{6B}\u{323}"),
+    ('\u{1E34}', "\u{4B}\u{331}"),
+    ('\u{1E35}', "\u{6B}\u{331}"),
+    ('\u{1E36}', "\u{4C}\u{323}"),
+    ('\u{1E37}', "\u{6C}\u{323}"),
+    ('\u{1E38}', "\u{4C}\u{323}\u{304}"),
+    ('\u{1E39}', "\u{6C}\u{323}\u{304}"),
+    ('\u{1E3A}', "\u{4C}\u{331}"),
+    ('\u{1E3B}', "\u{6C}\u{331}"),
+    ('\u{1E3C}', "\u{4C}\u{32D}"),
+    ('\u{1E3D}', "\u{6C}\u{32D}"),
+    ('\u{1E3E}', "\u{4D}\u{301}"),
+    ('\u{1E3F}', "\u{6D}\u{301}"),
+    ('\u{1E40}', "\u{4D}\u{307}"),
+    ('\u{1E41}', "\u{6D}\u{307}"),
+    ('\u{1E42}', "\u{4D}\u{323}"),
+    ('\u{1E43}', "\u{6D}\u{323}"),
+    ('\u{1E44}', "\u{4E}\u{307}"),
+    ('\u{1E45}', "\u{6E}\u{307}"),
+    ('\u{1E46}', "\u{4E}\u{323}"),
+    ('\u{1E47}', "\u{6E}\u{323}"),
+    ('\u{1E48}', "\u{4E}\u{331}"),
+    ('\u{1E49}', "\u{6E}\u{331}"),
+    ('\u{1E4A}', "\u{4E}\u{32D}"),
+    ('\u{1E4B}', "\u{6E}\u{32D}"),
+    ('\u{1E4C}', "\u{4F}\u{303}\u{301}"),
+    ('\u{1E4D}', "\u{6F}\u{303}\u{301}"),
+    ('\u{1E4E}', "\u{4F}\u{303}\u{308}"),
+    ('\u{1E4F}', "\u{6F}\u{303}\u{308}"),
+    ('\u{1E50}', "\u{4F}\u{304}\u{300}"),
+    ('\u{1E51}', "\u{6F}\u{304}\u{300}"),
+    ('\u{1E52}', "\u{4F}\u{304}\u{301}"),
+    ('\u{1E53}', "\u{6F}\u{304}\u{301}"),
+    ('\u{1E54}', "\u{50}\u{301}"),
+    ('\u{1E55}', "\u{70}\u{301}"),
+    ('\u{1E56}', "\u{50}\u{307}"),
+    ('\u{1E57}', "\u{70}\u{307}"),
+    ('\u{1E58}', "\u{52}\u{307}"),
+    ('\u{1E59}', "\u{72}\u{307}"),
+    ('\u{1E5A}', "\u{52}\u{323}"),
+    ('\u{1E5B}', "\u{72}\u{323}"),
+    ('\u{1E5C}', "\u{52}\u{323}\u{304}"),
+    ('\u{1E5D}', "\u{72}\u{323}\u{304}"),
+    ('\u{1E5E}', "\u{52}\u{331}"),
+    ('\u{1E5F}', "\u{72}\u{331}"),
+    ('\u{1E60}', "\u{53}\u{307}"),
+    ('\u{1E61}', "\u{73}\u{307}"),
+    ('\u{1E62}', "\u{53}\u{323}"),
+    ('\u{1E63}', "\u{73}\u{323}"),
+    ('\u{1E64}', "\u{53}\u{301}\u{307}"),
+    ('\u{1E65}', "\u{73}\u{301}\u{307}"),
+    ('\u{1E66}', "\u{53}\u{30C}\u{307}"),
+    ('\u{1E67}', "\u{73}\u{30C}\u{307}"),
+    ('\u{1E68}', "\u{53}\u{323}\u{307}"),
+    ('\u{1E69}', "\u{73}\u{323}\u{307}"),
+    ('\u{1E6A}', "\u{54}\u{307}"),
+    ('\u{1E6B}', "\u{74}\u{307}"),
+    ('\u{1E6C}', "\u{54}\u{323}"),
+    ('\u{1E6D}', "\u{74}\u{323}"),
+    ('\u{1E6E}', "\u{54}\u{331}"),
+    ('\u{1E6F}', "\u{74}\u{331}"),
+    ('\u{1E70}', "\u{54}\u{32D}"),
+    ('\u{1E71}', "\u{74}\u{32D}"),
+    ('\u{1E72}', "\u{55}\u{324}"),
+    ('\u{1E73}', "\u{75}\u{324}"),
+    ('\u{1E74}', "\u{55}\u{330}"),
+    ('\u{1E75}', "\u{75}\u{330}"),
+    ('\u{1E76}', "\u{55}\u{32D}"),
+    ('\u{1E77}', "\u{75}\u{32D}"),
+    ('\u{1E78}', "\u{55}\u{303}\u{301}"),
+    ('\u{1E79}', "\u{75}\u{303}\u{301}"),
+    ('\u{1E7A}', "\u{55}\u{304}\u{308}"),
+    ('\u{1E7B}', "\u{75}\u{304}\u{308}"),
+    ('\u{1E7C}', "\u{56}\u{303}"),
+    ('\u{1E7D}', "\u{76}\u{303}"),
+    ('\u{1E7E}', "\u{56}\u{323}"),
+    ('\u{1E7F}', "\u{76}\u{323}"),
+    ('\u{1E80}', "\u{57}\u{300}"),
+    ('\u{1E81}', "\u{77}\u{300}"),
+    ('\u{1E82}', "\u{57}\u{301}"),
+    ('\u{1E83}', "\u{77}\u{301}"),
+    ('\u{1E84}', "\u{57}\u{308}"),
+    ('\u{1E85}', "\u{77}\u{308}"),
+    ('\u{1E86}', "\u{57}\u{307}"),
+    ('\u{1E87}', "\u{77}\u{307}"),
+    ('\u{1E88}', "\u{57}\u{323}"),
+    ('\u{1E89}', "\u{77}\u{323}"),
+    ('\u{1E8A}', "\u{58}\u{307}"),
+    ('\u{1E8B}', "\u{78}\u{307}"),
+    ('\u{1E8C}', "\u{58}\u{308}"),
+    ('\u{1E8D}', "\u{78}\u{308}"),
+    ('\u{1E8E}', "\u{59}\u{307}"),
+    ('\u{1E8F}', "\u{79}\u{307}"),
+    ('\u{1E90}', "\u{5A}\u{302}"),
+    ('\u{1E91}', "\u{7A}\u{302}"),
+    ('\u{1E92}', "\u{5A}\u{323}"),
+    ('\u{1E93}', "\u{7A}\u{323}"),
+    ('\u{1E94}', "\u{5A}\u{331}"),
+    ('\u{1E95}', "\u{7A}\u{331}"),
+    ('\u{1E96}', "\u{68}\u{331}"),
+    ('\u{1E97}', "\u{74}\u{308}"),
+    ('\u{1E98}', "\u{77}\u{30A}"),
+    ('\u{1E99}', "\u{79}\u{30A}"),
+    ('\u{1E9B}', "\u{17F}\u{307}"),
+    ('\u{1EA0}', "\u{41}\u{323}"),
+    ('\u{1EA1}', "\u{61}\u{323}"),
+    ('\u{1EA2}', "\u{41}\u{309}"),
+    ('\u{1EA3}', "\u{61}\u{309}"),
+    ('\u{1EA4}', "\u{41}\u{302}\u{301}"),
+    ('\u{1EA5}', "\u{61}\u{302}\u{301}"),
+    ('\u{1EA6}', "\u{41}\u{302}\u{300}"),
+    ('\u{1EA7}', "\u{61}\u{302}\u{300}"),
+    ('\u{1EA8}', "\u{41}\u{302}\u{309}"),
+    ('\u{1EA9}', "\u{61}\u{302}\u{309}"),
+    ('\u{1EAA}', "\u{41}\u{302}\u{303}"),
+    ('\u{1EAB}', "\u{61}\u{302}\u{303}"),
+    ('\u{1EAC}', "\u{41}\u{323}\u{302}"),
+    ('\u{1EAD}', "\u{61}\u{323}\u{302}"),
+    ('\u{1EAE}', "\u{41}\u{306}\u{301}"),
+    ('\u{1EAF}', "\u{61}\u{306}\u{301}"),
+    ('\u{1EB0}', "\u{41}\u{306}\u{300}"),
+    ('\u{1EB1}', "\u{61}\u{306}\u{300}"),
+    ('\u{1EB2}', "\u{41}\u{306}\u{309}"),
+    ('\u{1EB3}', "\u{61}\u{306}\u{309}"),
+    ('\u{1EB4}', "\u{41}\u{306}\u{303}"),
+    ('\u{1EB5}', "\u{61}\u{306}\u{303}"),
+    ('\u{1EB6}', "\u{41}\u{323}\u{306}"),
+    ('\u{1EB7}', "\u{61}\u{323}\u{306}"),
+    ('\u{1EB8}', "\u{45}\u{323}"),
+    ('\u{1EB9}', "\u{65}\u{323}"),
+    ('\u{1EBA}', "\u{45}\u{309}"),
+    ('\u{1EBB}', "\u{65}\u{309}"),
+    ('\u{1EBC}', "\u{45}\u{303}"),
+    ('\u{1EBD}', "\u{65}\u{303}"),
+    ('\u{1EBE}', "\u{45}\u{302}\u{301}"),
+    ('\u{1EBF}', "\u{65}\u{302}\u{301}"),
+    ('\u{1EC0}', "\u{45}\u{302}\u{300}"),
+    ('\u{1EC1}', "\u{65}\u{302}\u{300}"),
+    ('\u{1EC2}', "\u{45}\u{302}\u{309}"),
+    ('\u{1EC3}', "\u{65}\u{302}\u{309}"),
+    ('\u{1EC4}', "\u{45}\u{302}\u{303}"),
+    ('\u{1EC5}', "\u{65}\u{302}\u{303}"),
+    ('\u{1EC6}', "\u{45}\u{323}\u{302}"),
+    ('\u{1EC7}', "\u{65}\u{323}\u{302}"),
+    ('\u{1EC8}', "\u{49}\u{309}"),
+    ('\u{1EC9}', "\u{69}\u{309}"),
+    ('\u{1ECA}', "\u{49}\u{323}"),
+    ('\u{1ECB}', "\u{69}\u{323}"),
+    ('\u{1ECC}', "\u{4F}\u{323}"),
+    ('\u{1ECD}', "\u{6F}\u{323}"),
+    ('\u{1ECE}', "\u{4F}\u{309}"),
+    ('\u{1ECF}', "\u{6F}\u{309}"),
+    ('\u{1ED0}', "\u{4F}\u{302}\u{301}"),
+    ('\u{1ED1}', "\u{6F}\u{302}\u{301}"),
+    ('\u{1ED2}', "\u{4F}\u{302}\u{300}"),
+    ('\u{1ED3}', "\u{6F}\u{302}\u{300}"),
+    ('\u{1ED4}', "\u{4F}\u{302}\u{309}"),
+    ('\u{1ED5}', "\u{6F}\u{302}\u{309}"),
+    ('\u{1ED6}', "\u{4F}\u{302}\u{303}"),
+    ('\u{1ED7}', "\u{6F}\u{302}\u{303}"),
+    ('\u{1ED8}', "\u{4F}\u{323}\u{302}"),
+    ('\u{1ED9}', "\u{6F}\u{323}\u{302}"),
+    ('\u{1EDA}', "\u{4F}\u{31B}\u{301}"),
+    ('\u{1EDB}', "\u{6F}\u{31B}\u{301}"),
+    ('\u{1EDC}', "\u{4F}\u{31B}\u{300}"),
+    ('\u{1EDD}', "\u{6F}\u{31B}\u{300}"),
+    ('\u{1EDE}', "\u{4F}\u{31B}\u{309}"),
+    ('\u{1EDF}', "\u{6F}\u{31B}\u{309}"),
+    ('\u{1EE0}', "\u{4F}\u{31B}\u{303}"),
+    ('\u{1EE1}', "\u{6F}\u{31B}\u{303}"),
+    ('\u{1EE2}', "\u{4F}\u{31B}\u{323}"),
+    ('\u{1EE3}', "\u{6F}\u{31B}\u{323}"),
+    ('\u{1EE4}', "\u{55}\u{323}"),
+    ('\u{1EE5}', "\u{75}\u{323}"),
+    ('\u{1EE6}', "\u{55}\u{309}"),
+    ('\u{1EE7}', "\u{75}\u{309}"),
+    ('\u{1EE8}', "\u{55}\u{31B}\u{301}"),
+    ('\u{1EE9}', "\u{75}\u{31B}\u{301}"),
+    ('\u{1EEA}', "\u{55}\u{31B}\u{300}"),
+    ('\u{1EEB}', "\u{75}\u{31B}\u{300}"),
+    ('\u{1EEC}', "\u{55}\u{31B}\u{309}"),
+    ('\u{1EED}', "\u{75}\u{31B}\u{309}"),
+    ('\u{1EEE}', "\u{55}\u{31B}\u{303}"),
+    ('\u{1EEF}', "\u{75}\u{31B}\u{303}"),
+    ('\u{1EF0}', "\u{55}\u{31B}\u{323}"),
+    ('\u{1EF1}', "\u{75}\u{31B}\u{323}"),
+    ('\u{1EF2}', "\u{59}\u{300}"),
+    ('\u{1EF3}', "\u{79}\u{300}"),
+    ('\u{1EF4}', "\u{59}\u{323}"),
+    ('\u{1EF5}', "\u{79}\u{323}"),
+    ('\u{1EF6}', "\u{59}\u{309}"),
+    ('\u{1EF7}', "\u{79}\u{309}"),
+    ('\u{1EF8}', "\u{59}\u{303}"),
+    ('\u{1EF9}', "\u{79}\u{303}"),
+    ('\u{1F00}', "\u{3B1}\u{313}"),
+    ('\u{1F01}', "\u{3B1}\u{314}"),
+    ('\u{1F02}', "\u{3B1}\u{313}\u{300}"),
+    ('\u{1F03}', "\u{3B1}\u{314}\u{300}"),
+    ('\u{1F04}', "\u{3B1}\u{313}\u{301}"),
+    ('\u{1F05}', "\u{3B1}\u{314}\u{301}"),
+    ('\u{1F06}', "\u{3B1}\u{313}\u{342}"),
+    ('\u{1F07}', "\u{3B1}\u{314}\u{342}"),
+    ('\u{1F08}', "\u{391}\u{313}"),
+    ('\u{1F09}', "\u{391}\u{314}"),
+    ('\u{1F0A}', "\u{391}\u{313}\u{300}"),
+    ('\u{1F0B}', "\u{391}\u{314}\u{300}"),
+    ('\u{1F0C}', "\u{391}\u{313}\u{301}"),
+    ('\u{1F0D}', "\u{391}\u{314}\u{301}"),
+    ('\u{1F0E}', "\u{391}\u{313}\u{342}"),
+    ('\u{1F0F}', "\u{391}\u{314}\u{342}"),
+    ('\u{1F10}', "\u{3B5}\u{313}"),
+    ('\u{1F11}', "\u{3B5}\u{314}"),
+    ('\u{1F12}', "\u{3B5}\u{313}\u{300}"),
+    ('\u{1F13}', "\u{3B5}\u{314}\u{300}"),
+    ('\u{1F14}', "\u{3B5}\u{313}\u{301}"),
+    ('\u{1F15}', "\u{3B5}\u{314}\u{301}"),
+    ('\u{1F18}', "\u{395}\u{313}"),
+    ('\u{1F19}', "\u{395}\u{314}"),
+    ('\u{1F1A}', "\u{395}\u{313}\u{300}"),
+    ('\u{1F1B}', "\u{395}\u{314}\u{300}"),
+    ('\u{1F1C}', "\u{395}\u{313}\u{301}"),
+    ('\u{1F1D}', "\u{395}\u{314}\u{301}"),
+    ('\u{1F20}', "\u{3B7}\u{313}"),
+    ('\u{1F21}', "\u{3B7}\u{314}"),
+    ('\u{1F22}', "\u{3B7}\u{313}\u{300}"),
+    ('\u{1F23}', "\u{3B7}\u{314}\u{300}"),
+    ('\u{1F24}', "\u{3B7}\u{313}\u{301}"),
+    ('\u{1F25}', "\u{3B7}\u{314}\u{301}"),
+    ('\u{1F26}', "\u{3B7}\u{313}\u{342}"),
+    ('\u{1F27}', "\u{3B7}\u{314}\u{342}"),
+    ('\u{1F28}', "\u{397}\u{313}"),
+    ('\u{1F29}', "\u{397}\u{314}"),
+    ('\u{1F2A}', "\u{397}\u{313}\u{300}"),
+    ('\u{1F2B}', "\u{397}\u{314}\u{300}"),
+    ('\u{1F2C}', "\u{397}\u{313}\u{301}"),
+    ('\u{1F2D}', "\u{397}\u{314}\u{301}"),
+    ('\u{1F2E}', "\u{397}\u{313}\u{342}"),
+    ('\u{1F2F}', "\u{397}\u{314}\u{342}"),
+    ('\u{1F30}', "\u{3B9}\u{313}"),
+    ('\u{1F31}', "\u{3B9}\u{314}"),
+    ('\u{1F32}', "\u{3B9}\u{313}\u{300}"),
+    ('\u{1F33}', "\u{3B9}\u{314}\u{300}"),
+    ('\u{1F34}', "\u{3B9}\u{313}\u{301}"),
+    ('\u{1F35}', "\u{3B9}\u{314}\u{301}"),
+    ('\u{1F36}', "\u{3B9}\u{313}\u{342}"),
+    ('\u{1F37}', "\u{3B9}\u{314}\u{342}"),
+    ('\u{1F38}', "\u{399}\u{313}"),
+    ('\u{1F39}', "\u{399}\u{314}"),
+    ('\u{1F3A}', "\u{399}\u{313}\u{300}"),
+    ('\u{1F3B}', "\u{399}\u{314}\u{300}"),
+    ('\u{1F3C}', "\u{399}\u{313}\u{301}"),
+    ('\u{1F3D}', "\u{399}\u{314}\u{301}"),
+    ('\u{1F3E}', "\u{399}\u{313}\u{342}"),
+    ('\u{1F3F}', "\u{399}\u{314}\u{342}"),
+    ('\u{1F40}', "\u{3BF}\u{313}"),
+    ('\u{1F41}', "\u{3BF}\u{314}"),
+    ('\u{1F42}', "\u{3BF}\u{313}\u{300}"),
+    ('\u{1F43}', "\u{3BF}\u{314}\u{300}"),
+    ('\u{1F44}', "\u{3BF}\u{313}\u{301}"),
+    ('\u{1F45}', "\u{3BF}\u{314}\u{301}"),
+    ('\u{1F48}', "\u{39F}\u{313}"),
+    ('\u{1F49}', "\u{39F}\u{314}"),
+    ('\u{1F4A}', "\u{39F}\u{313}\u{300}"),
+    ('\u{1F4B}', "\u{39F}\u{314}\u{300}"),
+    ('\u{1F4C}', "\u{39F}\u{313}\u{301}"),
+    ('\u{1F4D}', "\u{39F}\u{314}\u{301}"),
+    ('\u{1F50}', "\u{3C5}\u{313}"),
+    ('\u{1F51}', "\u{3C5}\u{314}"),
+    ('\u{1F52}', "\u{3C5}\u{313}\u{300}"),
+    ('\u{1F53}', "\u{3C5}\u{314}\u{300}"),
+    ('\u{1F54}', "\u{3C5}\u{313}\u{301}"),
+    ('\u{1F55}', "\u{3C5}\u{314}\u{301}"),
+    ('\u{1F56}', "\u{3C5}\u{313}\u{342}"),
+    ('\u{1F57}', "\u{3C5}\u{314}\u{342}"),
+    ('\u{1F59}', "\u{3A5}\u{314}"),
+    ('\u{1F5B}', "\u{3A5}\u{314}\u{300}"),
+    ('\u{1F5D}', "\u{3A5}\u{314}\u{301}"),
+    ('\u{1F5F}', "\u{3A5}\u{314}\u{342}"),
+    ('\u{1F60}', "\u{3C9}\u{313}"),
+    ('\u{1F61}', "\u{3C9}\u{314}"),
+    ('\u{1F62}', "\u{3C9}\u{313}\u{300}"),
+    ('\u{1F63}', "\u{3C9}\u{314}\u{300}"),
+    ('\u{1F64}', "\u{3C9}\u{313}\u{301}"),
+    ('\u{1F65}', "\u{3C9}\u{314}\u{301}"),
+    ('\u{1F66}', "\u{3C9}\u{313}\u{342}"),
+    ('\u{1F67}', "\u{3C9}\u{314}\u{342}"),
+    ('\u{1F68}', "\u{3A9}\u{313}"),
+    ('\u{1F69}', "\u{3A9}\u{314}"),
+    ('\u{1F6A}', "\u{3A9}\u{313}\u{300}"),
+    ('\u{1F6B}', "\u{3A9}\u{314}\u{300}"),
+    ('\u{1F6C}', "\u{3A9}\u{313}\u{301}"),
+    ('\u{1F6D}', "\u{3A9}\u{314}\u{301}"),
+    ('\u{1F6E}', "\u{3A9}\u{313}\u{342}"),
+    ('\u{1F6F}', "\u{3A9}\u{314}\u{342}"),
+    ('\u{1F70}', "\u{3B1}\u{300}"),
+    ('\u{1F71}', "\u{3B1}\u{301}"),
+    ('\u{1F72}', "\u{3B5}\u{300}"),
+    ('\u{1F73}', "\u{3B5}\u{301}"),
+    ('\u{1F74}', "\u{3B7}\u{300}"),
+    ('\u{1F75}', "\u{3B7}\u{301}"),
+    ('\u{1F76}', "\u{3B9}\u{300}"),
+    ('\u{1F77}', "\u{3B9}\u{301}"),
+    ('\u{1F78}', "\u{3BF}\u{300}"),
+    ('\u{1F79}', "\u{3BF}\u{301}"),
+    ('\u{1F7A}', "\u{3C5}\u{300}"),
+    ('\u{1F7B}', "\u{3C5}\u{301}"),
+    ('\u{1F7C}', "\u{3C9}\u{300}"),
+    ('\u{1F7D}', "\u{3C9}\u{301}"),
+    ('\u{1F80}', "\u{3B1}\u{313}\u{345}"),
+    ('\u{1F81}', "\u{3B1}\u{314}\u{345}"),
+    ('\u{1F82}', "\u{3B1}\u{313}\u{300}\u{345}"),
+    ('\u{1F83}', "\u{3B1}\u{314}\u{300}\u{345}"),
+    ('\u{1F84}', "\u{3B1}\u{313}\u{301}\u{345}"),
+    ('\u{1F85}', "\u{3B1}\u{314}\u{301}\u{345}"),
+    ('\u{1F86}', "\u{3B1}\u{313}\u{342}\u{345}"),
+    ('\u{1F87}', "\u{3B1}\u{314}\u{342}\u{345}"),
+    ('\u{1F88}', "\u{391}\u{313}\u{345}"),
+    ('\u{1F89}', "\u{391}\u{314}\u{345}"),
+    ('\u{1F8A}', "\u{391}\u{313}\u{300}\u{345}"),
+    ('\u{1F8B}', "\u{391}\u{314}\u{300}\u{345}"),
+    ('\u{1F8C}', "\u{391}\u{313}\u{301}\u{345}"),
+    ('\u{1F8D}', "\u{391}\u{314}\u{301}\u{345}"),
+    ('\u{1F8E}', "\u{391}\u{313}\u{342}\u{345}"),
+    ('\u{1F8F}', "\u{391}\u{314}\u{342}\u{345}"),
+    ('\u{1F90}', "\u{3B7}\u{313}\u{345}"),
+    ('\u{1F91}', "\u{3B7}\u{314}\u{345}"),
+    ('\u{1F92}', "\u{3B7}\u{313}\u{300}\u{345}"),
+    ('\u{1F93}', "\u{3B7}\u{314}\u{300}\u{345}"),
+    ('\u{1F94}', "\u{3B7}\u{313}\u{301}\u{345}"),
+    ('\u{1F95}', "\u{3B7}\u{314}\u{301}\u{345}"),
+    ('\u{1F96}', "\u{3B7}\u{313}\u{342}\u{345}"),
+    ('\u{1F97}', "\u{3B7}\u{314}\u{342}\u{345}"),
+    ('\u{1F98}', "\u{397}\u{313}\u{345}"),
+    ('\u{1F99}', "\u{397}\u{314}\u{345}"),
+    ('\u{1F9A}', "\u{397}\u{313}\u{300}\u{345}"),
+    ('\u{1F9B}', "\u{397}\u{314}\u{300}\u{345}"),
+    ('\u{1F9C}', "\u{397}\u{313}\u{301}\u{345}"),
+    ('\u{1F9D}', "\u{397}\u{314}\u{301}\u{345}"),
+    ('\u{1F9E}', "\u{397}\u{313}\u{342}\u{345}"),
+    ('\u{1F9F}', "\u{397}\u{314}\u{342}\u{345}"),
+    ('\u{1FA0}', "\u{3C9}\u{313}\u{345}"),
+    ('\u{1FA1}', "\u{3C9}\u{314}\u{345}"),
+    ('\u{1FA2}', "\u{3C9}\u{313}\u{300}\u{345}"),
+    ('\u{1FA3}', "\u{3C9}\u{314}\u{300}\u{345}"),
+    ('\u{1FA4}', "\u{3C9}\u{313}\u{301}\u{345}"),
+    ('\u{1FA5}', "\u{3C9}\u{314}\u{301}\u{345}"),
+    ('\u{1FA6}', "\u{3C9}\u{313}\u{342}\u{345}"),
+    ('\u{1FA7}', "\u{3C9}\u{314}\u{342}\u{345}"),
+    ('\u{1FA8}', "\u{3A9}\u{313}\u{345}"),
+    ('\u{1FA9}', "\u{3A9}\u{314}\u{345}"),
+    ('\u{1FAA}', "\u{3A9}\u{313}\u{300}\u{345}"),
+    ('\u{1FAB}', "\u{3A9}\u{314}\u{300}\u{345}"),
+    ('\u{1FAC}', "\u{3A9}\u{313}\u{301}\u{345}"),
+    ('\u{1FAD}', "\u{3A9}\u{314}\u{301}\u{345}"),
+    ('\u{1FAE}', "\u{3A9}\u{313}\u{342}\u{345}"),
+    ('\u{1FAF}', "\u{3A9}\u{314}\u{342}\u{345}"),
+    ('\u{1FB0}', "\u{3B1}\u{306}"),
+    ('\u{1FB1}', "\u{3B1}\u{304}"),
+    ('\u{1FB2}', "\u{3B1}\u{300}\u{345}"),
+    ('\u{1FB3}', "\u{3B1}\u{345}"),
+    ('\u{1FB4}', "\u{3B1}\u{301}\u{345}"),
+    ('\u{1FB6}', "\u{3B1}\u{342}"),
+    ('\u{1FB7}', "\u{3B1}\u{342}\u{345}"),
+    ('\u{1FB8}', "\u{391}\u{306}"),
+    ('\u{1FB9}', "\u{391}\u{304}"),
+    ('\u{1FBA}', "\u{391}\u{300}"),
+    ('\u{1FBB}', "\u{391}\u{301}"),
+    ('\u{1FBC}', "\u{391}\u{345}"),
+    ('\u{1FBE}', "\u{3B9}"),
+    ('\u{1FC1}', "\u{A8}\u{342}"),
+    ('\u{1FC2}', "\u{3B7}\u{300}\u{345}"),
+    ('\u{1FC3}', "\u{3B7}\u{345}"),
+    ('\u{1FC4}', "\u{3B7}\u{301}\u{345}"),
+    ('\u{1FC6}', "\u{3B7}\u{342}"),
+    ('\u{1FC7}', "\u{3B7}\u{342}\u{345}"),
+    ('\u{1FC8}', "\u{395}\u{300}"),
+    ('\u{1FC9}', "\u{395}\u{301}"),
+    ('\u{1FCA}', "\u{397}\u{300}"),
+    ('\u{1FCB}', "\u{397}\u{301}"),
+    ('\u{1FCC}', "\u{397}\u{345}"),
+    ('\u{1FCD}', "\u{1FBF}\u{300}"),
+    ('\u{1FCE}', "\u{1FBF}\u{301}"),
+    ('\u{1FCF}', "\u{1FBF}\u{342}"),
+    ('\u{1FD0}', "\u{3B9}\u{306}"),
+    ('\u{1FD1}', "\u{3B9}\u{304}"),
+    ('\u{1FD2}', "\u{3B9}\u{308}\u{300}"),
+    ('\u{1FD3}', "\u{3B9}\u{308}\u{301}"),
+    ('\u{1FD6}', "\u{3B9}\u{342}"),
+    ('\u{1FD7}', "\u{3B9}\u{308}\u{342}"),
+    ('\u{1FD8}', "\u{399}\u{306}"),
+    ('\u{1FD9}', "\u{399}\u{304}"),
+    ('\u{1FDA}', "\u{399}\u{300}"),
+    ('\u{1FDB}', "\u{399}\u{301}"),
+    ('\u{1FDD}', "\u{1FFE}\u{300}"),
+    ('\u{1FDE}', "\u{1FFE}\u{301}"),
+    ('\u{1FDF}', "\u{1FFE}\u{342}"),
+    ('\u{1FE0}', "\u{3C5}\u{306}"),
+    ('\u{1FE1}', "\u{3C5}\u{304}"),
+    ('\u{1FE2}', "\u{3C5}\u{308}\u{300}"),
+    ('\u{1FE3}', "\u{3C5}\u{308}\u{301}"),
+    ('\u{1FE4}', "\u{3C1}\u{313}"),
+    ('\u{1FE5}', "\u{3C1}\u{314}"),
+    ('\u{1FE6}', "\u{3C5}\u{342}"),
+    ('\u{1FE7}', "\u{3C5}\u{308}\u{342}"),
+    ('\u{1FE8}', "\u{3A5}\u{306}"),
+    ('\u{1FE9}', "\u{3A5}\u{304}"),
+    ('\u{1FEA}', "\u{3A5}\u{300}"),
+    ('\u{1FEB}', "\u{3A5}\u{301}"),
+    ('\u{1FEC}', "\u{3A1}\u{314}"),
+    ('\u{1FED}', "\u{A8}\u{300}"),
+    ('\u{1FEE}', "\u{A8}\u{301}"),
+    ('\u{1FEF}', "\u{60}"),
+    ('\u{1FF2}', "\u{3C9}\u{300}\u{345}"),
+    ('\u{1FF3}', "\u{3C9}\u{345}"),
+    ('\u{1FF4}', "\u{3C9}\u{301}\u{345}"),
+    ('\u{1FF6}', "\u{3C9}\u{342}"),
+    ('\u{1FF7}', "\u{3C9}\u{342}\u{345}"),
+    ('\u{1FF8}', "\u{39F}\u{300}"),
+    ('\u{1FF9}', "\u{39F}\u{301}"),
+    ('\u{1FFA}', "\u{3A9}\u{300}"),
+    ('\u{1FFB}', "\u{3A9}\u{301}"),
+    ('\u{1FFC}', "\u{3A9}\u{345}"),
+    ('\u{1FFD}', "\u{B4}"),
+    ('\u{2000}', "\u{2002}"),
+    ('\u{2001}', "\u{2003}"),
+    ('\u{2126}', "\u{3A9}"),
+    ('\u{212A}', "\u{4B}"),
+    ('\u{212B}', "\u{41}\u{30A}"),
+    ('\u{219A}', "\u{2190}\u{338}"),
+    ('\u{219B}', "\u{2192}\u{338}"),
+    ('\u{21AE}', "\u{2194}\u{338}"),
+    ('\u{21CD}', "\u{21D0}\u{338}"),
+    ('\u{21CE}', "\u{21D4}\u{338}"),
+    ('\u{21CF}', "\u{21D2}\u{338}"),
+    ('\u{2204}', "\u{2203}\u{338}"),
+    ('\u{2209}', "\u{2208}\u{338}"),
+    ('\u{220C}', "\u{220B}\u{338}"),
+    ('\u{2224}', "\u{2223}\u{338}"),
+    ('\u{2226}', "\u{2225}\u{338}"),
+    ('\u{2241}', "\u{223C}\u{338}"),
+    ('\u{2244}', "\u{2243}\u{338}"),
+    ('\u{2247}', "\u{2245}\u{338}"),
+    ('\u{2249}', "\u{2248}\u{338}"),
+    ('\u{2260}', "\u{3D}\u{338}"),
+    ('\u{2262}', "\u{2261}\u{338}"),
+    ('\u{226D}', "\u{224D}\u{338}"),
+    ('\u{226E}', "\u{3C}\u{338}"),
+    ('\u{226F}', "\u{3E}\u{338}"),
+    ('\u{2270}', "\u{2264}\u{338}"),
+    ('\u{2271}', "\u{2265}\u{338}"),
+    ('\u{2274}', "\u{2272}\u{338}"),
+    ('\u{2275}', "\u{2273}\u{338}"),
+    ('\u{2278}', "\u{2276}\u{338}"),
+    ('\u{2279}', "\u{2277}\u{338}"),
+    ('\u{2280}', "\u{227A}\u{338}"),
+    ('\u{2281}', "\u{227B}\u{338}"),
+    ('\u{2284}', "\u{2282}\u{338}"),
+    ('\u{2285}', "\u{2283}\u{338}"),
+    ('\u{2288}', "\u{2286}\u{338}"),
+    ('\u{2289}', "\u{2287}\u{338}"),
+    ('\u{22AC}', "\u{22A2}\u{338}"),
+    ('\u{22AD}', "\u{22A8}\u{338}"),
+    ('\u{22AE}', "\u{22A9}\u{338}"),
+    ('\u{22AF}', "\u{22AB}\u{338}"),
+    ('\u{22E0}', "\u{227C}\u{338}"),
+    ('\u{22E1}', "\u{227D}\u{338}"),
+    ('\u{22E2}', "\u{2291}\u{338}"),
+    ('\u{22E3}', "\u{2292}\u{338}"),
+    ('\u{22EA}', "\u{22B2}\u{338}"),
+    ('\u{22EB}', "\u{22B3}\u{338}"),
+    ('\u{22EC}', "\u{22B4}\u{338}"),
+    ('\u{22ED}', "\u{22B5}\u{338}"),
+    ('\u{2329}', "\u{3008}"),
+    ('\u{232A}', "\u{3009}"),
+    ('\u{2ADC}', "\u{2ADD}\u{338}"),
+    ('\u{304C}', "\u{304B}\u{3099}"),
+    ('\u{304E}', "\u{304D}\u{3099}"),
+    ('\u{3050}', "\u{304F}\u{3099}"),
+    ('\u{3052}', "\u{3051}\u{3099}"),
+    ('\u{3054}', "\u{3053}\u{3099}"),
+    ('\u{3056}', "\u{3055}\u{3099}"),
+    ('\u{3058}', "\u{3057}\u{3099}"),
+    ('\u{305A}', "\u{3059}\u{3099}"),
+    ('\u{305C}', "\u{305B}\u{3099}"),
+    ('\u{305E}', "\u{305D}\u{3099}"),
+    ('\u{3060}', "\u{305F}\u{3099}"),
+    ('\u{3062}', "\u{3061}\u{3099}"),
+    ('\u{3065}', "\u{3064}\u{3099}"),
+    ('\u{3067}', "\u{3066}\u{3099}"),
+    ('\u{3069}', "\u{3068}\u{3099}"),
+    ('\u{3070}', "\u{306F}\u{3099}"),
+    ('\u{3071}', "\u{306F}\u{309A}"),
+    ('\u{3073}', "\u{3072}\u{3099}"),
+    ('\u{3074}', "\u{3072}\u{309A}"),
+    ('\u{3076}', "\u{3075}\u{3099}"),
+    ('\u{3077}', "\u{3075}\u{309A}"),
+    ('\u{3079}', "\u{3078}\u{3099}"),
+    ('\u{307A}', "\u{3078}\u{309A}"),
+    ('\u{307C}', "\u{307B}\u{3099}"),
+    ('\u{307D}', "\u{307B}\u{309A}"),
+    ('\u{3094}', "\u{3046}\u{3099}"),
+    ('\u{309E}', "\u{309D}\u{3099}"),
+    ('\u{30AC}', "\u{30AB}\u{3099}"),
+    ('\u{30AE}', "\u{30AD}\u{3099}"),
+    ('\u{30B0}', "\u{30AF}\u{3099}"),
+    ('\u{30B2}', "\u{30B1}\u{3099}"),
+    ('\u{30B4}', "\u{30B3}\u{3099}"),
+    ('\u{30B6}', "\u{30B5}\u{3099}"),
+    ('\u{30B8}', "\u{30B7}\u{3099}"),
+    ('\u{30BA}', "\u{30B9}\u{3099}"),
+    ('\u{30BC}', "\u{30BB}\u{3099}"),
+    ('\u{30BE}', "\u{30BD}\u{3099}"),
+    ('\u{30C0}', "\u{30BF}\u{3099}"),
+    ('\u{30C2}', "\u{30C1}\u{3099}"),
+    ('\u{30C5}', "\u{30C4}\u{3099}"),
+    ('\u{30C7}', "\u{30C6}\u{3099}"),
+    ('\u{30C9}', "\u{30C8}\u{3099}"),
+    ('\u{30D0}', "\u{30CF}\u{3099}"),
+    ('\u{30D1}', "\u{30CF}\u{309A}"),
+    ('\u{30D3}', "\u{30D2}\u{3099}"),
+    ('\u{30D4}', "\u{30D2}\u{309A}"),
+    ('\u{30D6}', "\u{30D5}\u{3099}"),
+    ('\u{30D7}', "\u{30D5}\u{309A}"),
+    ('\u{30D9}', "\u{30D8}\u{3099}"),
+    ('\u{30DA}', "\u{30D8}\u{309A}"),
+    ('\u{30DC}', "\u{30DB}\u{3099}"),
+    ('\u{30DD}', "\u{30DB}\u{309A}"),
+    ('\u{30F4}', "\u{30A6}\u{3099}"),
+    ('\u{30F7}', "\u{30EF}\u{3099}"),
+    ('\u{30F8}', "\u{30F0}\u{3099}"),
+    ('\u{30F9}', "\u{30F1}\u{3099}"),
+    ('\u{30FA}', "\u{30F2}\u{3099}"),
+    ('\u{30FE}', "\u{30FD}\u{3099}"),
+    ('\u{F900}', "\u{8C48}"),
+    ('\u{F901}', "\u{66F4}"),
+    ('\u{F902}', "\u{8ECA}"),
+    ('\u{F903}', "\u{8CC8}"),
+    ('\u{F904}', "\u{6ED1}"),
+    ('\u{F905}', "\u{4E32}"),
+    ('\u{F906}', "\u{53E5}"),
+    ('\u{F907}', "\u{9F9C}"),
+    ('\u{F908}', "\u{9F9C}"),
+    ('\u{F909}', "\u{5951}"),
+    ('\u{F90A}', "\u{91D1}"),
+    ('\u{F90B}', "\u{5587}"),
+    ('\u{F90C}', "\u{5948}"),
+    ('\u{F90D}', "\u{61F6}"),
+    ('\u{F90E}', "\u{7669}"),
+    ('\u{F90F}', "\u{7F85}"),
+    ('\u{F910}', "\u{863F}"),
+    ('\u{F911}', "\u{87BA}"),
+    ('\u{F912}', "\u{88F8}"),
+    ('\u{F913}', "\u{908F}"),
+    ('\u{F914}', "\u{6A02}"),
+    ('\u{F915}', "\u{6D1B}"),
+    ('\u{F916}', "\u{70D9}"),
+    ('\u{F917}', "\u{73DE}"),
+    ('\u{F918}', "\u{843D}"),
+    ('\u{F919}', "\u{916A}"),
+    ('\u{F91A}', "\u{99F1}"),
+    ('\u{F91B}', "\u{4E82}"),
+    ('\u{F91C}', "\u{5375}"),
+    ('\u{F91D}', "\u{6B04}"),
+    ('\u{F91E}', "\u{721B}"),
+    ('\u{F91F}', "\u{862D}"),
+    ('\u{F920}', "\u{9E1E}"),
+    ('\u{F921}', "\u{5D50}"),
+    ('\u{F922}', "\u{6FEB}"),
+    ('\u{F923}', "\u{85CD}"),
+    ('\u{F924}', "\u{8964}"),
+    ('\u{F925}', "\u{62C9}"),
+    ('\u{F926}', "\u{81D8}"),
+    ('\u{F927}', "\u{881F}"),
+    ('\u{F928}', "\u{5ECA}"),
+    ('\u{F929}', "\u{6717}"),
+    ('\u{F92A}', "\u{6D6A}"),
+    ('\u{F92B}', "\u{72FC}"),
+    ('\u{F92C}', "\u{90CE}"),
+    ('\u{F92D}', "\u{4F86}"),
+    ('\u{F92E}', "\u{51B7}"),
+    ('\u{F92F}', "\u{52DE}"),
+    ('\u{F930}', "\u{64C4}"),
+    ('\u{F931}', "\u{6AD3}"),
+    ('\u{F932}', "\u{7210}"),
+    ('\u{F933}', "\u{76E7}"),
+    ('\u{F934}', "\u{8001}"),
+    ('\u{F935}', "\u{8606}"),
+    ('\u{F936}', "\u{865C}"),
+    ('\u{F937}', "\u{8DEF}"),
+    ('\u{F938}', "\u{9732}"),
+    ('\u{F939}', "\u{9B6F}"),
+    ('\u{F93A}', "\u{9DFA}"),
+    ('\u{F93B}', "\u{788C}"),
+    ('\u{F93C}', "\u{797F}"),
+    ('\u{F93D}', "\u{7DA0}"),
+    ('\u{F93E}', "\u{83C9}"),
+    ('\u{F93F}', "\u{9304}"),
+    ('\u{F940}', "\u{9E7F}"),
+    ('\u{F941}', "\u{8AD6}"),
+    ('\u{F942}', "\u{58DF}"),
+    ('\u{F943}', "\u{5F04}"),
+    ('\u{F944}', "\u{7C60}"),
+    ('\u{F945}', "\u{807E}"),
+    ('\u{F946}', "\u{7262}"),
+    ('\u{F947}', "\u{78CA}"),
+    ('\u{F948}', "\u{8CC2}"),
+    ('\u{F949}', "\u{96F7}"),
+    ('\u{F94A}', "\u{58D8}"),
+    ('\u{F94B}', "\u{5C62}"),
+    ('\u{F94C}', "\u{6A13}"),
+    ('\u{F94D}', "\u{6DDA}"),
+    ('\u{F94E}', "\u{6F0F}"),
+    ('\u{F94F}', "\u{7D2F}"),
+    ('\u{F950}', "\u{7E37}"),
+    ('\u{F951}', "\u{964B}"),
+    ('\u{F952}', "\u{52D2}"),
+    ('\u{F953}', "\u{808B}"),
+    ('\u{F954}', "\u{51DC}"),
+    ('\u{F955}', "\u{51CC}"),
+    ('\u{F956}', "\u{7A1C}"),
+    ('\u{F957}', "\u{7DBE}"),
+    ('\u{F958}', "\u{83F1}"),
+    ('\u{F959}', "\u{9675}"),
+    ('\u{F95A}', "\u{8B80}"),
+    ('\u{F95B}', "\u{62CF}"),
+    ('\u{F95C}', "\u{6A02}"),
+    ('\u{F95D}', "\u{8AFE}"),
+    ('\u{F95E}', "\u{4E39}"),
+    ('\u{F95F}', "\u{5BE7}"),
+    ('\u{F960}', "\u{6012}"),
+    ('\u{F961}', "\u{7387}"),
+    ('\u{F962}', "\u{7570}"),
+    ('\u{F963}', "\u{5317}"),
+    ('\u{F964}', "\u{78FB}"),
+    ('\u{F965}', "\u{4FBF}"),
+    ('\u{F966}', "\u{5FA9}"),
+    ('\u{F967}', "\u{4E0D}"),
+    ('\u{F968}', "\u{6CCC}"),
+    ('\u{F969}', "\u{6578}"),
+    ('\u{F96A}', "\u{7D22}"),
+    ('\u{F96B}', "\u{53C3}"),
+    ('\u{F96C}', "\u{585E}"),
+    ('\u{F96D}', "\u{7701}"),
+    ('\u{F96E}', "\u{8449}"),
+    ('\u{F96F}', "\u{8AAA}"),
+    ('\u{F970}', "\u{6BBA}"),
+    ('\u{F971}', "\u{8FB0}"),
+    ('\u{F972}', "\u{6C88}"),
+    ('\u{F973}', "\u{62FE}"),
+    ('\u{F974}', "\u{82E5}"),
+    ('\u{F975}', "\u{63A0}"),
+    ('\u{F976}', "\u{7565}"),
+    ('\u{F977}', "\u{4EAE}"),
+    ('\u{F978}', "\u{5169}"),
+    ('\u{F979}', "\u{51C9}"),
+    ('\u{F97A}', "\u{6881}"),
+    ('\u{F97B}', "\u{7CE7}"),
+    ('\u{F97C}', "\u{826F}"),
+    ('\u{F97D}', "\u{8AD2}"),
+    ('\u{F97E}', "\u{91CF}"),
+    ('\u{F97F}', "\u{52F5}"),
+    ('\u{F980}', "\u{5442}"),
+    ('\u{F981}', "\u{5973}"),
+    ('\u{F982}', "\u{5EEC}"),
+    ('\u{F983}', "\u{65C5}"),
+    ('\u{F984}', "\u{6FFE}"),
+    ('\u{F985}', "\u{792A}"),
+    ('\u{F986}', "\u{95AD}"),
+    ('\u{F987}', "\u{9A6A}"),
+    ('\u{F988}', "\u{9E97}"),
+    ('\u{F989}', "\u{9ECE}"),
+    ('\u{F98A}', "\u{529B}"),
+    ('\u{F98B}', "\u{66C6}"),
+    ('\u{F98C}', "\u{6B77}"),
+    ('\u{F98D}', "\u{8F62}"),
+    ('\u{F98E}', "\u{5E74}"),
+    ('\u{F98F}', "\u{6190}"),
+    ('\u{F990}', "\u{6200}"),
+    ('\u{F991}', "\u{649A}"),
+    ('\u{F992}', "\u{6F23}"),
+    ('\u{F993}', "\u{7149}"),
+    ('\u{F994}', "\u{7489}"),
+    ('\u{F995}', "\u{79CA}"),
+    ('\u{F996}', "\u{7DF4}"),
+    ('\u{F997}', "\u{806F}"),
+    ('\u{F998}', "\u{8F26}"),
+    ('\u{F999}', "\u{84EE}"),
+    ('\u{F99A}', "\u{9023}"),
+    ('\u{F99B}', "\u{934A}"),
+    ('\u{F99C}', "\u{5217}"),
+    ('\u{F99D}', "\u{52A3}"),
+    ('\u{F99E}', "\u{54BD}"),
+    ('\u{F99F}', "\u{70C8}"),
+    ('\u{F9A0}', "\u{88C2}"),
+    ('\u{F9A1}', "\u{8AAA}"),
+    ('\u{F9A2}', "\u{5EC9}"),
+    ('\u{F9A3}', "\u{5FF5}"),
+    ('\u{F9A4}', "\u{637B}"),
+    ('\u{F9A5}', "\u{6BAE}"),
+    ('\u{F9A6}', "\u{7C3E}"),
+    ('\u{F9A7}', "\u{7375}"),
+    ('\u{F9A8}', "\u{4EE4}"),
+    ('\u{F9A9}', "\u{56F9}"),
+    ('\u{F9AA}', "\u{5BE7}"),
+    ('\u{F9AB}', "\u{5DBA}"),
+    ('\u{F9AC}', "\u{601C}"),
+    ('\u{F9AD}', "\u{73B2}"),
+    ('\u{F9AE}', "\u{7469}"),
+    ('\u{F9AF}', "\u{7F9A}"),
+    ('\u{F9B0}', "\u{8046}"),
+    ('\u{F9B1}', "\u{9234}"),
+    ('\u{F9B2}', "\u{96F6}"),
+    ('\u{F9B3}', "\u{9748}"),
+    ('\u{F9B4}', "\u{9818}"),
+    ('\u{F9B5}', "\u{4F8B}"),
+    ('\u{F9B6}', "\u{79AE}"),
+    ('\u{F9B7}', "\u{91B4}"),
+    ('\u{F9B8}', "\u{96B8}"),
+    ('\u{F9B9}', "\u{60E1}"),
+    ('\u{F9BA}', "\u{4E86}"),
+    ('\u{F9BB}', "\u{50DA}"),
+    ('\u{F9BC}', "\u{5BEE}"),
+    ('\u{F9BD}', "\u{5C3F}"),
+    ('\u{F9BE}', "\u{6599}"),
+    ('\u{F9BF}', "\u{6A02}"),
+    ('\u{F9C0}', "\u{71CE}"),
+    ('\u{F9C1}', "\u{7642}"),
+    ('\u{F9C2}', "\u{84FC}"),
+    ('\u{F9C3}', "\u{907C}"),
+    ('\u{F9C4}', "\u{9F8D}"),
+    ('\u{F9C5}', "\u{6688}"),
+    ('\u{F9C6}', "\u{962E}"),
+    ('\u{F9C7}', "\u{5289}"),
+    ('\u{F9C8}', "\u{677B}"),
+    ('\u{F9C9}', "\u{67F3}"),
+    ('\u{F9CA}', "\u{6D41}"),
+    ('\u{F9CB}', "\u{6E9C}"),
+    ('\u{F9CC}', "\u{7409}"),
+    ('\u{F9CD}', "\u{7559}"),
+    ('\u{F9CE}', "\u{786B}"),
+    ('\u{F9CF}', "\u{7D10}"),
+    ('\u{F9D0}', "\u{985E}"),
+    ('\u{F9D1}', "\u{516D}"),
+    ('\u{F9D2}', "\u{622E}"),
+    ('\u{F9D3}', "\u{9678}"),
+    ('\u{F9D4}', "\u{502B}"),
+    ('\u{F9D5}', "\u{5D19}"),
+    ('\u{F9D6}', "\u{6DEA}"),
+    ('\u{F9D7}', "\u{8F2A}"),
+    ('\u{F9D8}', "\u{5F8B}"),
+    ('\u{F9D9}', "\u{6144}"),
+    ('\u{F9DA}', "\u{6817}"),
+    ('\u{F9DB}', "\u{7387}"),
+    ('\u{F9DC}', "\u{9686}"),
+    ('\u{F9DD}', "\u{5229}"),
+    ('\u{F9DE}', "\u{540F}"),
+    ('\u{F9DF}', "\u{5C65}"),
+    ('\u{F9E0}', "\u{6613}"),
+    ('\u{F9E1}', "\u{674E}"),
+    ('\u{F9E2}', "\u{68A8}"),
+    ('\u{F9E3}', "\u{6CE5}"),
+    ('\u{F9E4}', "\u{7406}"),
+    ('\u{F9E5}', "\u{75E2}"),
+    ('\u{F9E6}', "\u{7F79}"),
+    ('\u{F9E7}', "\u{88CF}"),
+    ('\u{F9E8}', "\u{88E1}"),
+    ('\u{F9E9}', "\u{91CC}"),
+    ('\u{F9EA}', "\u{96E2}"),
+    ('\u{F9EB}', "\u{533F}"),
+    ('\u{F9EC}', "\u{6EBA}"),
+    ('\u{F9ED}', "\u{541D}"),
+    ('\u{F9EE}', "\u{71D0}"),
+    ('\u{F9EF}', "\u{7498}"),
+    ('\u{F9F0}', "\u{85FA}"),
+    ('\u{F9F1}', "\u{96A3}"),
+    ('\u{F9F2}', "\u{9C57}"),
+    ('\u{F9F3}', "\u{9E9F}"),
+    ('\u{F9F4}', "\u{6797}"),
+    ('\u{F9F5}', "\u{6DCB}"),
+    ('\u{F9F6}', "\u{81E8}"),
+    ('\u{F9F7}', "\u{7ACB}"),
+    ('\u{F9F8}', "\u{7B20}"),
+    ('\u{F9F9}', "\u{7C92}"),
+    ('\u{F9FA}', "\u{72C0}"),
+    ('\u{F9FB}', "\u{7099}"),
+    ('\u{F9FC}', "\u{8B58}"),
+    ('\u{F9FD}', "\u{4EC0}"),
+    ('\u{F9FE}', "\u{8336}"),
+    ('\u{F9FF}', "\u{523A}"),
+    ('\u{FA00}', "\u{5207}"),
+    ('\u{FA01}', "\u{5EA6}"),
+    ('\u{FA02}', "\u{62D3}"),
+    ('\u{FA03}', "\u{7CD6}"),
+    ('\u{FA04}', "\u{5B85}"),
+    ('\u{FA05}', "\u{6D1E}"),
+    ('\u{FA06}', "\u{66B4}"),
+    ('\u{FA07}', "\u{8F3B}"),
+    ('\u{FA08}', "\u{884C}"),
+    ('\u{FA09}', "\u{964D}"),
+    ('\u{FA0A}', "\u{898B}"),
+    ('\u{FA0B}', "\u{5ED3}"),
+    ('\u{FA0C}', "\u{5140}"),
+    ('\u{FA0D}', "\u{55C0}"),
+    ('\u{FA10}', "\u{585A}"),
+    ('\u{FA12}', "\u{6674}"),
+    ('\u{FA15}', "\u{51DE}"),
+    ('\u{FA16}', "\u{732A}"),
+    ('\u{FA17}', "\u{76CA}"),
+    ('\u{FA18}', "\u{793C}"),
+    ('\u{FA19}', "\u{795E}"),
+    ('\u{FA1A}', "\u{7965}"),
+    ('\u{FA1B}', "\u{798F}"),
+    ('\u{FA1C}', "\u{9756}"),
+    ('\u{FA1D}', "\u{7CBE}"),
+    ('\u{FA1E}', "\u{7FBD}"),
+    ('\u{FA20}', "\u{8612}"),
+    ('\u{FA22}', "\u{8AF8}"),
+    ('\u{FA25}', "\u{9038}"),
+    ('\u{FA26}', "\u{90FD}"),
+    ('\u{FA2A}', "\u{98EF}"),
+    ('\u{FA2B}', "\u{98FC}"),
+    ('\u{FA2C}', "\u{9928}"),
+    ('\u{FA2D}', "\u{9DB4}"),
+    ('\u{FA2E}', "\u{90DE}"),
+    ('\u{FA2F}', "\u{96B7}"),
+    ('\u{FA30}', "\u{4FAE}"),
+    ('\u{FA31}', "\u{50E7}"),
+    ('\u{FA32}', "\u{514D}"),
+    ('\u{FA33}', "\u{52C9}"),
+    ('\u{FA34}', "\u{52E4}"),
+    ('\u{FA35}', "\u{5351}"),
+    ('\u{FA36}', "\u{559D}"),
+    ('\u{FA37}', "\u{5606}"),
+    ('\u{FA38}', "\u{5668}"),
+    ('\u{FA39}', "\u{5840}"),
+    ('\u{FA3A}', "\u{58A8}"),
+    ('\u{FA3B}', "\u{5C64}"),
+    ('\u{FA3C}', "\u{5C6E}"),
+    ('\u{FA3D}', "\u{6094}"),
+    ('\u{FA3E}', "\u{6168}"),
+    ('\u{FA3F}', "\u{618E}"),
+    ('\u{FA40}', "\u{61F2}"),
+    ('\u{FA41}', "\u{654F}"),
+    ('\u{FA42}', "\u{65E2}"),
+    ('\u{FA43}', "\u{6691}"),
+    ('\u{FA44}', "\u{6885}"),
+    ('\u{FA45}', "\u{6D77}"),
+    ('\u{FA46}', "\u{6E1A}"),
+    ('\u{FA47}', "\u{6F22}"),
+    ('\u{FA48}', "\u{716E}"),
+    ('\u{FA49}', "\u{722B}"),
+    ('\u{FA4A}', "\u{7422}"),
+    ('\u{FA4B}', "\u{7891}"),
+    ('\u{FA4C}', "\u{793E}"),
+    ('\u{FA4D}', "\u{7949}"),
+    ('\u{FA4E}', "\u{7948}"),
+    ('\u{FA4F}', "\u{7950}"),
+    ('\u{FA50}', "\u{7956}"),
+    ('\u{FA51}', "\u{795D}"),
+    ('\u{FA52}', "\u{798D}"),
+    ('\u{FA53}', "\u{798E}"),
+    ('\u{FA54}', "\u{7A40}"),
+    ('\u{FA55}', "\u{7A81}"),
+    ('\u{FA56}', "\u{7BC0}"),
+    ('\u{FA57}', "\u{7DF4}"),
+    ('\u{FA58}', "\u{7E09}"),
+    ('\u{FA59}', "\u{7E41}"),
+    ('\u{FA5A}', "\u{7F72}"),
+    ('\u{FA5B}', "\u{8005}"),
+    ('\u{FA5C}', "\u{81ED}"),
+    ('\u{FA5D}', "\u{8279}"),
+    ('\u{FA5E}', "\u{8279}"),
+    ('\u{FA5F}', "\u{8457}"),
+    ('\u{FA60}', "\u{8910}"),
+    ('\u{FA61}', "\u{8996}"),
+    ('\u{FA62}', "\u{8B01}"),
+    ('\u{FA63}', "\u{8B39}"),
+    ('\u{FA64}', "\u{8CD3}"),
+    ('\u{FA65}', "\u{8D08}"),
+    ('\u{FA66}', "\u{8FB6}"),
+    ('\u{FA67}', "\u{9038}"),
+    ('\u{FA68}', "\u{96E3}"),
+    ('\u{FA69}', "\u{97FF}"),
+    ('\u{FA6A}', "\u{983B}"),
+    ('\u{FA6B}', "\u{6075}"),
+    ('\u{FA6C}', "\u{242EE}"),
+    ('\u{FA6D}', "\u{8218}"),
+    ('\u{FA70}', "\u{4E26}"),
+    ('\u{FA71}', "\u{51B5}"),
+    ('\u{FA72}', "\u{5168}"),
+    ('\u{FA73}', "\u{4F80}"),
+    ('\u{FA74}', "\u{5145}"),
+    ('\u{FA75}', "\u{5180}"),
+    ('\u{FA76}', "\u{52C7}"),
+    ('\u{FA77}', "\u{52FA}"),
+    ('\u{FA78}', "\u{559D}"),
+    ('\u{FA79}', "\u{5555}"),
+    ('\u{FA7A}', "\u{5599}"),
+    ('\u{FA7B}', "\u{55E2}"),
+    ('\u{FA7C}', "\u{585A}"),
+    ('\u{FA7D}', "\u{58B3}"),
+    ('\u{FA7E}', "\u{5944}"),
+    ('\u{FA7F}', "\u{5954}"),
+    ('\u{FA80}', "\u{5A62}"),
+    ('\u{FA81}', "\u{5B28}"),
+    ('\u{FA82}', "\u{5ED2}"),
+    ('\u{FA83}', "\u{5ED9}"),
+    ('\u{FA84}', "\u{5F69}"),
+    ('\u{FA85}', "\u{5FAD}"),
+    ('\u{FA86}', "\u{60D8}"),
+    ('\u{FA87}', "\u{614E}"),
+    ('\u{FA88}', "\u{6108}"),
+    ('\u{FA89}', "\u{618E}"),
+    ('\u{FA8A}', "\u{6160}"),
+    ('\u{FA8B}', "\u{61F2}"),
+    ('\u{FA8C}', "\u{6234}"),
+    ('\u{FA8D}', "\u{63C4}"),
+    ('\u{FA8E}', "\u{641C}"),
+    ('\u{FA8F}', "\u{6452}"),
+    ('\u{FA90}', "\u{6556}"),
+    ('\u{FA91}', "\u{6674}"),
+    ('\u{FA92}', "\u{6717}"),
+    ('\u{FA93}', "\u{671B}"),
+    ('\u{FA94}', "\u{6756}"),
+    ('\u{FA95}', "\u{6B79}"),
+    ('\u{FA96}', "\u{6BBA}"),
+    ('\u{FA97}', "\u{6D41}"),
+    ('\u{FA98}', "\u{6EDB}"),
+    ('\u{FA99}', "\u{6ECB}"),
+    ('\u{FA9A}', "\u{6F22}"),
+    ('\u{FA9B}', "\u{701E}"),
+    ('\u{FA9C}', "\u{716E}"),
+    ('\u{FA9D}', "\u{77A7}"),
+    ('\u{FA9E}', "\u{7235}"),
+    ('\u{FA9F}', "\u{72AF}"),
+    ('\u{FAA0}', "\u{732A}"),
+    ('\u{FAA1}', "\u{7471}"),
+    ('\u{FAA2}', "\u{7506}"),
+    ('\u{FAA3}', "\u{753B}"),
+    ('\u{FAA4}', "\u{761D}"),
+    ('\u{FAA5}', "\u{761F}"),
+    ('\u{FAA6}', "\u{76CA}"),
+    ('\u{FAA7}', "\u{76DB}"),
+    ('\u{FAA8}', "\u{76F4}"),
+    ('\u{FAA9}', "\u{774A}"),
+    ('\u{FAAA}', "\u{7740}"),
+    ('\u{FAAB}', "\u{78CC}"),
+    ('\u{FAAC}', "\u{7AB1}"),
+    ('\u{FAAD}', "\u{7BC0}"),
+    ('\u{FAAE}', "\u{7C7B}"),
+    ('\u{FAAF}', "\u{7D5B}"),
+    ('\u{FAB0}', "\u{7DF4}"),
+    ('\u{FAB1}', "\u{7F3E}"),
+    ('\u{FAB2}', "\u{8005}"),
+    ('\u{FAB3}', "\u{8352}"),
+    ('\u{FAB4}', "\u{83EF}"),
+    ('\u{FAB5}', "\u{8779}"),
+    ('\u{FAB6}', "\u{8941}"),
+    ('\u{FAB7}', "\u{8986}"),
+    ('\u{FAB8}', "\u{8996}"),
+    ('\u{FAB9}', "\u{8ABF}"),
+    ('\u{FABA}', "\u{8AF8}"),
+    ('\u{FABB}', "\u{8ACB}"),
+    ('\u{FABC}', "\u{8B01}"),
+    ('\u{FABD}', "\u{8AFE}"),
+    ('\u{FABE}', "\u{8AED}"),
+    ('\u{FABF}', "\u{8B39}"),
+    ('\u{FAC0}', "\u{8B8A}"),
+    ('\u{FAC1}', "\u{8D08}"),
+    ('\u{FAC2}', "\u{8F38}"),
+    ('\u{FAC3}', "\u{9072}"),
+    ('\u{FAC4}', "\u{9199}"),
+    ('\u{FAC5}', "\u{9276}"),
+    ('\u{FAC6}', "\u{967C}"),
+    ('\u{FAC7}', "\u{96E3}"),
+    ('\u{FAC8}', "\u{9756}"),
+    ('\u{FAC9}', "\u{97DB}"),
+    ('\u{FACA}', "\u{97FF}"),
+    ('\u{FACB}', "\u{980B}"),
+    ('\u{FACC}', "\u{983B}"),
+    ('\u{FACD}', "\u{9B12}"),
+    ('\u{FACE}', "\u{9F9C}"),
+    ('\u{FACF}', "\u{2284A}"),
+    ('\u{FAD0}', "\u{22844}"),
+    ('\u{FAD1}', "\u{233D5}"),
+    ('\u{FAD2}', "\u{3B9D}"),
+    ('\u{FAD3}', "\u{4018}"),
+    ('\u{FAD4}', "\u{4039}"),
+    ('\u{FAD5}', "\u{25249}"),
+    ('\u{FAD6}', "\u{25CD0}"),
+    ('\u{FAD7}', "\u{27ED3}"),
+    ('\u{FAD8}', "\u{9F43}"),
+    ('\u{FAD9}', "\u{9F8E}"),
+    ('\u{FB1D}', "\u{5D9}\u{5B4}"),
+    ('\u{FB1F}', "\u{5F2}\u{5B7}"),
+    ('\u{FB2A}', "\u{5E9}\u{5C1}"),
+    ('\u{FB2B}', "\u{5E9}\u{5C2}"),
+    ('\u{FB2C}', "\u{5E9}\u{5BC}\u{5C1}"),
+    ('\u{FB2D}', "\u{5E9}\u{5BC}\u{5C2}"),
+    ('\u{FB2E}', "\u{5D0}\u{5B7}"),
+    ('\u{FB2F}', "\u{5D0}\u{5B8}"),
+    ('\u{FB30}', "\u{5D0}\u{5BC}"),
+    ('\u{FB31}', "\u{5D1}\u{5BC}"),
+    ('\u{FB32}', "\u{5D2}\u{5BC}"),
+    ('\u{FB33}', "\u{5D3}\u{5BC}"),
+    ('\u{FB34}', "\u{5D4}\u{5BC}"),
+    ('\u{FB35}', "\u{5D5}\u{5BC}"),
+    ('\u{FB36}', "\u{5D6}\u{5BC}"),
+    ('\u{FB38}', "\u{5D8}\u{5BC}"),
+    ('\u{FB39}', "\u{5D9}\u{5BC}"),
+    ('\u{FB3A}', "\u{5DA}\u{5BC}"),
+    ('\u{FB3B}', "\u{5DB}\u{5BC}"),
+    ('\u{FB3C}', "\u{5DC}\u{5BC}"),
+    ('\u{FB3E}', "\u{5DE}\u{5BC}"),
+    ('\u{FB40}', "\u{5E0}\u{5BC}"),
+    ('\u{FB41}', "\u{5E1}\u{5BC}"),
+    ('\u{FB43}', "\u{5E3}\u{5BC}"),
+    ('\u{FB44}', "\u{5E4}\u{5BC}"),
+    ('\u{FB46}', "\u{5E6}\u{5BC}"),
+    ('\u{FB47}', "\u{5E7}\u{5BC}"),
+    ('\u{FB48}', "\u{5E8}\u{5BC}"),
+    ('\u{FB49}', "\u{5E9}\u{5BC}"),
+    ('\u{FB4A}', "\u{5EA}\u{5BC}"),
+    ('\u{FB4B}', "\u{5D5}\u{5B9}"),
+    ('\u{FB4C}', "\u{5D1}\u{5BF}"),
+    ('\u{FB4D}', "\u{5DB}\u{5BF}"),
+    ('\u{FB4E}', "\u{5E4}\u{5BF}"),
+    ('\u{1109A}', "\u{11099}\u{110BA}"),
+    ('\u{1109C}', "\u{1109B}\u{110BA}"),
+    ('\u{110AB}', "\u{110A5}\u{110BA}"),
+    ('\u{1112E}', "\u{11131}\u{11127}"),
+    ('\u{1112F}', "\u{11132}\u{11127}"),
+    ('\u{1134B}', "\u{11347}\u{1133E}"),
+    ('\u{1134C}', "\u{11347}\u{11357}"),
+    ('\u{114BB}', "\u{114B9}\u{114BA}"),
+    ('\u{114BC}', "\u{114B9}\u{114B0}"),
+    ('\u{114BE}', "\u{114B9}\u{114BD}"),
+    ('\u{115BA}', "\u{115B8}\u{115AF}"),
+    ('\u{115BB}', "\u{115B9}\u{115AF}"),
+    ('\u{11938}', "\u{11935}\u{11930}"),
+    ('\u{1D15E}', "\u{1D157}\u{1D165}"),
+    ('\u{1D15F}', "\u{1D158}\u{1D165}"),
+    ('\u{1D160}', "\u{1D158}\u{1D165}\u{1D16E}"),
+    ('\u{1D161}', "\u{1D158}\u{1D165}\u{1D16F}"),
+    ('\u{1D162}', "\u{1D158}\u{1D165}\u{1D170}"),
+    ('\u{1D163}', "\u{1D158}\u{1D165}\u{1D171}"),
+    ('\u{1D164}', "\u{1D158}\u{1D165}\u{1D172}"),
+    ('\u{1D1BB}', "\u{1D1B9}\u{1D165}"),
+    ('\u{1D1BC}', "\u{1D1BA}\u{1D165}"),
+    ('\u{1D1BD}', "\u{1D1B9}\u{1D165}\u{1D16E}"),
+    ('\u{1D1BE}', "\u{1D1BA}\u{1D165}\u{1D16E}"),
+    ('\u{1D1BF}', "\u{1D1B9}\u{1D165}\u{1D16F}"),
+    ('\u{1D1C0}', "\u{1D1BA}\u{1D165}\u{1D16F}"),
+    ('\u{2F800}', "\u{4E3D}"),
+    ('\u{2F801}', "\u{4E38}"),
+    ('\u{2F802}', "\u{4E41}"),
+    ('\u{2F803}', "\u{20122}"),
+    ('\u{2F804}', "\u{4F60}"),
+    ('\u{2F805}', "\u{4FAE}"),
+    ('\u{2F806}', "\u{4FBB}"),
+    ('\u{2F807}', "\u{5002}"),
+    ('\u{2F808}', "\u{507A}"),
+    ('\u{2F809}', "\u{5099}"),
+    ('\u{2F80A}', "\u{50E7}"),
+    ('\u{2F80B}', "\u{50CF}"),
+    ('\u{2F80C}', "\u{349E}"),
+    ('\u{2F80D}', "\u{2063A}"),
+    ('\u{2F80E}', "\u{514D}"),
+    ('\u{2F80F}', "\u{5154}"),
+    ('\u{2F810}', "\u{5164}"),
+    ('\u{2F811}', "\u{5177}"),
+    ('\u{2F812}', "\u{2051C}"),
+    ('\u{2F813}', "\u{34B9}"),
+    ('\u{2F814}', "\u{5167}"),
+    ('\u{2F815}', "\u{518D}"),
+    ('\u{2F816}', "\u{2054B}"),
+    ('\u{2F817}', "\u{5197}"),
+    ('\u{2F818}', "\u{51A4}"),
+    ('\u{2F819}', "\u{4ECC}"),
+    ('\u{2F81A}', "\u{51AC}"),
+    ('\u{2F81B}', "\u{51B5}"),
+    ('\u{2F81C}', "\u{291DF}"),
+    ('\u{2F81D}', "\u{51F5}"),
+    ('\u{2F81E}', "\u{5203}"),
+    ('\u{2F81F}', "\u{34DF}"),
+    ('\u{2F820}', "\u{523B}"),
+    ('\u{2F821}', "\u{5246}"),
+    ('\u{2F822}', "\u{5272}"),
+    ('\u{2F823}', "\u{5277}"),
+    ('\u{2F824}', "\u{3515}"),
+    ('\u{2F825}', "\u{52C7}"),
+    ('\u{2F826}', "\u{52C9}"),
+    ('\u{2F827}', "\u{52E4}"),
+    ('\u{2F828}', "\u{52FA}"),
+    ('\u{2F829}', "\u{5305}"),
+    ('\u{2F82A}', "\u{5306}"),
+    ('\u{2F82B}', "\u{5317}"),
+    ('\u{2F82C}', "\u{5349}"),
+    ('\u{2F82D}', "\u{5351}"),
+    ('\u{2F82E}', "\u{535A}"),
+    ('\u{2F82F}', "\u{5373}"),
+    ('\u{2F830}', "\u{537D}"),
+    ('\u{2F831}', "\u{537F}"),
+    ('\u{2F832}', "\u{537F}"),
+    ('\u{2F833}', "\u{537F}"),
+    ('\u{2F834}', "\u{20A2C}"),
+    ('\u{2F835}', "\u{7070}"),
+    ('\u{2F836}', "\u{53CA}"),
+    ('\u{2F837}', "\u{53DF}"),
+    ('\u{2F838}', "\u{20B63}"),
+    ('\u{2F839}', "\u{53EB}"),
+    ('\u{2F83A}', "\u{53F1}"),
+    ('\u{2F83B}', "\u{5406}"),
+    ('\u{2F83C}', "\u{549E}"),
+    ('\u{2F83D}', "\u{5438}"),
+    ('\u{2F83E}', "\u{5448}"),
+    ('\u{2F83F}', "\u{5468}"),
+    ('\u{2F840}', "\u{54A2}"),
+    ('\u{2F841}', "\u{54F6}"),
+    ('\u{2F842}', "\u{5510}"),
+    ('\u{2F843}', "\u{5553}"),
+    ('\u{2F844}', "\u{5563}"),
+    ('\u{2F845}', "\u{5584}"),
+    ('\u{2F846}', "\u{5584}"),
+    ('\u{2F847}', "\u{5599}"),
+    ('\u{2F848}', "\u{55AB}"),
+    ('\u{2F849}', "\u{55B3}"),
+    ('\u{2F84A}', "\u{55C2}"),
+    ('\u{2F84B}', "\u{5716}"),
+    ('\u{2F84C}', "\u{5606}"),
+    ('\u{2F84D}', "\u{5717}"),
+    ('\u{2F84E}', "\u{5651}"),
+    ('\u{2F84F}', "\u{5674}"),
+    ('\u{2F850}', "\u{5207}"),
+    ('\u{2F851}', "\u{58EE}"),
+    ('\u{2F852}', "\u{57CE}"),
+    ('\u{2F853}', "\u{57F4}"),
+    ('\u{2F854}', "\u{580D}"),
+    ('\u{2F855}', "\u{578B}"),
+    ('\u{2F856}', "\u{5832}"),
+    ('\u{2F857}', "\u{5831}"),
+    ('\u{2F858}', "\u{58AC}"),
+    ('\u{2F859}', "\u{214E4}"),
+    ('\u{2F85A}', "\u{58F2}"),
+    ('\u{2F85B}', "\u{58F7}"),
+    ('\u{2F85C}', "\u{5906}"),
+    ('\u{2F85D}', "\u{591A}"),
+    ('\u{2F85E}', "\u{5922}"),
+    ('\u{2F85F}', "\u{5962}"),
+    ('\u{2F860}', "\u{216A8}"),
+    ('\u{2F861}', "\u{216EA}"),
+    ('\u{2F862}', "\u{59EC}"),
+    ('\u{2F863}', "\u{5A1B}"),
+    ('\u{2F864}', "\u{5A27}"),
+    ('\u{2F865}', "\u{59D8}"),
+    ('\u{2F866}', "\u{5A66}"),
+    ('\u{2F867}', "\u{36EE}"),
+    ('\u{2F868}', "\u{36FC}"),
+    ('\u{2F869}', "\u{5B08}"),
+    ('\u{2F86A}', "\u{5B3E}"),
+    ('\u{2F86B}', "\u{5B3E}"),
+    ('\u{2F86C}', "\u{219C8}"),
+    ('\u{2F86D}', "\u{5BC3}"),
+    ('\u{2F86E}', "\u{5BD8}"),
+    ('\u{2F86F}', "\u{5BE7}"),
+    ('\u{2F870}', "\u{5BF3}"),
+    ('\u{2F871}', "\u{21B18}"),
+    ('\u{2F872}', "\u{5BFF}"),
+    ('\u{2F873}', "\u{5C06}"),
+    ('\u{2F874}', "\u{5F53}"),
+    ('\u{2F875}', "\u{5C22}"),
+    ('\u{2F876}', "\u{3781}"),
+    ('\u{2F877}', "\u{5C60}"),
+    ('\u{2F878}', "\u{5C6E}"),
+    ('\u{2F879}', "\u{5CC0}"),
+    ('\u{2F87A}', "\u{5C8D}"),
+    ('\u{2F87B}', "\u{21DE4}"),
+    ('\u{2F87C}', "\u{5D43}"),
+    ('\u{2F87D}', "\u{21DE6}"),
+    ('\u{2F87E}', "\u{5D6E}"),
+    ('\u{2F87F}', "\u{5D6B}"),
+    ('\u{2F880}', "\u{5D7C}"),
+    ('\u{2F881}', "\u{5DE1}"),
+    ('\u{2F882}', "\u{5DE2}"),
+    ('\u{2F883}', "\u{382F}"),
+    ('\u{2F884}', "\u{5DFD}"),
+    ('\u{2F885}', "\u{5E28}"),
+    ('\u{2F886}', "\u{5E3D}"),
+    ('\u{2F887}', "\u{5E69}"),
+    ('\u{2F888}', "\u{3862}"),
+    ('\u{2F889}', "\u{22183}"),
+    ('\u{2F88A}', "\u{387C}"),
+    ('\u{2F88B}', "\u{5EB0}"),
+    ('\u{2F88C}', "\u{5EB3}"),
+    ('\u{2F88D}', "\u{5EB6}"),
+    ('\u{2F88E}', "\u{5ECA}"),
+    ('\u{2F88F}', "\u{2A392}"),
+    ('\u{2F890}', "\u{5EFE}"),
+    ('\u{2F891}', "\u{22331}"),
+    ('\u{2F892}', "\u{22331}"),
+    ('\u{2F893}', "\u{8201}"),
+    ('\u{2F894}', "\u{5F22}"),
+    ('\u{2F895}', "\u{5F22}"),
+    ('\u{2F896}', "\u{38C7}"),
+    ('\u{2F897}', "\u{232B8}"),
+    ('\u{2F898}', "\u{261DA}"),
+    ('\u{2F899}', "\u{5F62}"),
+    ('\u{2F89A}', "\u{5F6B}"),
+    ('\u{2F89B}', "\u{38E3}"),
+    ('\u{2F89C}', "\u{5F9A}"),
+    ('\u{2F89D}', "\u{5FCD}"),
+    ('\u{2F89E}', "\u{5FD7}"),
+    ('\u{2F89F}', "\u{5FF9}"),
+    ('\u{2F8A0}', "\u{6081}"),
+    ('\u{2F8A1}', "\u{393A}"),
+    ('\u{2F8A2}', "\u{391C}"),
+    ('\u{2F8A3}', "\u{6094}"),
+    ('\u{2F8A4}', "\u{226D4}"),
+    ('\u{2F8A5}', "\u{60C7}"),
+    ('\u{2F8A6}', "\u{6148}"),
+    ('\u{2F8A7}', "\u{614C}"),
+    ('\u{2F8A8}', "\u{614E}"),
+    ('\u{2F8A9}', "\u{614C}"),
+    ('\u{2F8AA}', "\u{617A}"),
+    ('\u{2F8AB}', "\u{618E}"),
+    ('\u{2F8AC}', "\u{61B2}"),
+    ('\u{2F8AD}', "\u{61A4}"),
+    ('\u{2F8AE}', "\u{61AF}"),
+    ('\u{2F8AF}', "\u{61DE}"),
+    ('\u{2F8B0}', "\u{61F2}"),
+    ('\u{2F8B1}', "\u{61F6}"),
+    ('\u{2F8B2}', "\u{6210}"),
+    ('\u{2F8B3}', "\u{621B}"),
+    ('\u{2F8B4}', "\u{625D}"),
+    ('\u{2F8B5}', "\u{62B1}"),
+    ('\u{2F8B6}', "\u{62D4}"),
+    ('\u{2F8B7}', "\u{6350}"),
+    ('\u{2F8B8}', "\u{22B0C}"),
+    ('\u{2F8B9}', "\u{633D}"),
+    ('\u{2F8BA}', "\u{62FC}"),
+    ('\u{2F8BB}', "\u{6368}"),
+    ('\u{2F8BC}', "\u{6383}"),
+    ('\u{2F8BD}', "\u{63E4}"),
+    ('\u{2F8BE}', "\u{22BF1}"),
+    ('\u{2F8BF}', "\u{6422}"),
+    ('\u{2F8C0}', "\u{63C5}"),
+    ('\u{2F8C1}', "\u{63A9}"),
+    ('\u{2F8C2}', "\u{3A2E}"),
+    ('\u{2F8C3}', "\u{6469}"),
+    ('\u{2F8C4}', "\u{647E}"),
+    ('\u{2F8C5}', "\u{649D}"),
+    ('\u{2F8C6}', "\u{6477}"),
+    ('\u{2F8C7}', "\u{3A6C}"),
+    ('\u{2F8C8}', "\u{654F}"),
+    ('\u{2F8C9}', "\u{656C}"),
+    ('\u{2F8CA}', "\u{2300A}"),
+    ('\u{2F8CB}', "\u{65E3}"),
+    ('\u{2F8CC}', "\u{66F8}"),
+    ('\u{2F8CD}', "\u{6649}"),
+    ('\u{2F8CE}', "\u{3B19}"),
+    ('\u{2F8CF}', "\u{6691}"),
+    ('\u{2F8D0}', "\u{3B08}"),
+    ('\u{2F8D1}', "\u{3AE4}"),
+    ('\u{2F8D2}', "\u{5192}"),
+    ('\u{2F8D3}', "\u{5195}"),
+    ('\u{2F8D4}', "\u{6700}"),
+    ('\u{2F8D5}', "\u{669C}"),
+    ('\u{2F8D6}', "\u{80AD}"),
+    ('\u{2F8D7}', "\u{43D9}"),
+    ('\u{2F8D8}', "\u{6717}"),
+    ('\u{2F8D9}', "\u{671B}"),
+    ('\u{2F8DA}', "\u{6721}"),
+    ('\u{2F8DB}', "\u{675E}"),
+    ('\u{2F8DC}', "\u{6753}"),
+    ('\u{2F8DD}', "\u{233C3}"),
+    ('\u{2F8DE}', "\u{3B49}"),
+    ('\u{2F8DF}', "\u{67FA}"),
+    ('\u{2F8E0}', "\u{6785}"),
+    ('\u{2F8E1}', "\u{6852}"),
+    ('\u{2F8E2}', "\u{6885}"),
+    ('\u{2F8E3}', "\u{2346D}"),
+    ('\u{2F8E4}', "\u{688E}"),
+    ('\u{2F8E5}', "\u{681F}"),
+    ('\u{2F8E6}', "\u{6914}"),
+    ('\u{2F8E7}', "\u{3B9D}"),
+    ('\u{2F8E8}', "\u{6942}"),
+    ('\u{2F8E9}', "\u{69A3}"),
+    ('\u{2F8EA}', "\u{69EA}"),
+    ('\u{2F8EB}', "\u{6AA8}"),
+    ('\u{2F8EC}', "\u{236A3}"),
+    ('\u{2F8ED}', "\u{6ADB}"),
+    ('\u{2F8EE}', "\u{3C18}"),
+    ('\u{2F8EF}', "\u{6B21}"),
+    ('\u{2F8F0}', "\u{238A7}"),
+    ('\u{2F8F1}', "\u{6B54}"),
+    ('\u{2F8F2}', "\u{3C4E}"),
+    ('\u{2F8F3}', "\u{6B72}"),
+    ('\u{2F8F4}', "\u{6B9F}"),
+    ('\u{2F8F5}', "\u{6BBA}"),
+    ('\u{2F8F6}', "\u{6BBB}"),
+    ('\u{2F8F7}', "\u{23A8D}"),
+    ('\u{2F8F8}', "\u{21D0B}"),
+    ('\u{2F8F9}', "\u{23AFA}"),
+    ('\u{2F8FA}', "\u{6C4E}"),
+    ('\u{2F8FB}', "\u{23CBC}"),
+    ('\u{2F8FC}', "\u{6CBF}"),
+    ('\u{2F8FD}', "\u{6CCD}"),
+    ('\u{2F8FE}', "\u{6C67}"),
+    ('\u{2F8FF}', "\u{6D16}"),
+    ('\u{2F900}', "\u{6D3E}"),
+    ('\u{2F901}', "\u{6D77}"),
+    ('\u{2F902}', "\u{6D41}"),
+    ('\u{2F903}', "\u{6D69}"),
+    ('\u{2F904}', "\u{6D78}"),
+    ('\u{2F905}', "\u{6D85}"),
+    ('\u{2F906}', "\u{23D1E}"),
+    ('\u{2F907}', "\u{6D34}"),
+    ('\u{2F908}', "\u{6E2F}"),
+    ('\u{2F909}', "\u{6E6E}"),
+    ('\u{2F90A}', "\u{3D33}"),
+    ('\u{2F90B}', "\u{6ECB}"),
+    ('\u{2F90C}', "\u{6EC7}"),
+    ('\u{2F90D}', "\u{23ED1}"),
+    ('\u{2F90E}', "\u{6DF9}"),
+    ('\u{2F90F}', "\u{6F6E}"),
+    ('\u{2F910}', "\u{23F5E}"),
+    ('\u{2F911}', "\u{23F8E}"),
+    ('\u{2F912}', "\u{6FC6}"),
+    ('\u{2F913}', "\u{7039}"),
+    ('\u{2F914}', "\u{701E}"),
+    ('\u{2F915}', "\u{701B}"),
+    ('\u{2F916}', "\u{3D96}"),
+    ('\u{2F917}', "\u{704A}"),
+    ('\u{2F918}', "\u{707D}"),
+    ('\u{2F919}', "\u{7077}"),
+    ('\u{2F91A}', "\u{70AD}"),
+    ('\u{2F91B}', "\u{20525}"),
+    ('\u{2F91C}', "\u{7145}"),
+    ('\u{2F91D}', "\u{24263}"),
+    ('\u{2F91E}', "\u{719C}"),
+    ('\u{2F91F}', "\u{243AB}"),
+    ('\u{2F920}', "\u{7228}"),
+    ('\u{2F921}', "\u{7235}"),
+    ('\u{2F922}', "\u{7250}"),
+    ('\u{2F923}', "\u{24608}"),
+    ('\u{2F924}', "\u{7280}"),
+    ('\u{2F925}', "\u{7295}"),
+    ('\u{2F926}', "\u{24735}"),
+    ('\u{2F927}', "\u{24814}"),
+    ('\u{2F928}', "\u{737A}"),
+    ('\u{2F929}', "\u{738B}"),
+    ('\u{2F92A}', "\u{3EAC}"),
+    ('\u{2F92B}', "\u{73A5}"),
+    ('\u{2F92C}', "\u{3EB8}"),
+    ('\u{2F92D}', "\u{3EB8}"),
+    ('\u{2F92E}', "\u{7447}"),
+    ('\u{2F92F}', "\u{745C}"),
+    ('\u{2F930}', "\u{7471}"),
+    ('\u{2F931}', "\u{7485}"),
+    ('\u{2F932}', "\u{74CA}"),
+    ('\u{2F933}', "\u{3F1B}"),
+    ('\u{2F934}', "\u{7524}"),
+    ('\u{2F935}', "\u{24C36}"),
+    ('\u{2F936}', "\u{753E}"),
+    ('\u{2F937}', "\u{24C92}"),
+    ('\u{2F938}', "\u{7570}"),
+    ('\u{2F939}', "\u{2219F}"),
+    ('\u{2F93A}', "\u{7610}"),
+    ('\u{2F93B}', "\u{24FA1}"),
+    ('\u{2F93C}', "\u{24FB8}"),
+    ('\u{2F93D}', "\u{25044}"),
+    ('\u{2F93E}', "\u{3FFC}"),
+    ('\u{2F93F}', "\u{4008}"),
+    ('\u{2F940}', "\u{76F4}"),
+    ('\u{2F941}', "\u{250F3}"),
+    ('\u{2F942}', "\u{250F2}"),
+    ('\u{2F943}', "\u{25119}"),
+    ('\u{2F944}', "\u{25133}"),
+    ('\u{2F945}', "\u{771E}"),
+    ('\u{2F946}', "\u{771F}"),
+    ('\u{2F947}', "\u{771F}"),
+    ('\u{2F948}', "\u{774A}"),
+    ('\u{2F949}', "\u{4039}"),
+    ('\u{2F94A}', "\u{778B}"),
+    ('\u{2F94B}', "\u{4046}"),
+    ('\u{2F94C}', "\u{4096}"),
+    ('\u{2F94D}', "\u{2541D}"),
+    ('\u{2F94E}', "\u{784E}"),
+    ('\u{2F94F}', "\u{788C}"),
+    ('\u{2F950}', "\u{78CC}"),
+    ('\u{2F951}', "\u{40E3}"),
+    ('\u{2F952}', "\u{25626}"),
+    ('\u{2F953}', "\u{7956}"),
+    ('\u{2F954}', "\u{2569A}"),
+    ('\u{2F955}', "\u{256C5}"),
+    ('\u{2F956}', "\u{798F}"),
+    ('\u{2F957}', "\u{79EB}"),
+    ('\u{2F958}', "\u{412F}"),
+    ('\u{2F959}', "\u{7A40}"),
+    ('\u{2F95A}', "\u{7A4A}"),
+    ('\u{2F95B}', "\u{7A4F}"),
+    ('\u{2F95C}', "\u{2597C}"),
+    ('\u{2F95D}', "\u{25AA7}"),
+    ('\u{2F95E}', "\u{25AA7}"),
+    ('\u{2F95F}', "\u{7AEE}"),
+    ('\u{2F960}', "\u{4202}"),
+    ('\u{2F961}', "\u{25BAB}"),
+    ('\u{2F962}', "\u{7BC6}"),
+    ('\u{2F963}', "\u{7BC9}"),
+    ('\u{2F964}', "\u{4227}"),
+    ('\u{2F965}', "\u{25C80}"),
+    ('\u{2F966}', "\u{7CD2}"),
+    ('\u{2F967}', "\u{42A0}"),
+    ('\u{2F968}', "\u{7CE8}"),
+    ('\u{2F969}', "\u{7CE3}"),
+    ('\u{2F96A}', "\u{7D00}"),
+    ('\u{2F96B}', "\u{25F86}"),
+    ('\u{2F96C}', "\u{7D63}"),
+    ('\u{2F96D}', "\u{4301}"),
+    ('\u{2F96E}', "\u{7DC7}"),
+    ('\u{2F96F}', "\u{7E02}"),
+    ('\u{2F970}', "\u{7E45}"),
+    ('\u{2F971}', "\u{4334}"),
+    ('\u{2F972}', "\u{26228}"),
+    ('\u{2F973}', "\u{26247}"),
+    ('\u{2F974}', "\u{4359}"),
+    ('\u{2F975}', "\u{262D9}"),
+    ('\u{2F976}', "\u{7F7A}"),
+    ('\u{2F977}', "\u{2633E}"),
+    ('\u{2F978}', "\u{7F95}"),
+    ('\u{2F979}', "\u{7FFA}"),
+    ('\u{2F97A}', "\u{8005}"),
+    ('\u{2F97B}', "\u{264DA}"),
+    ('\u{2F97C}', "\u{26523}"),
+    ('\u{2F97D}', "\u{8060}"),
+    ('\u{2F97E}', "\u{265A8}"),
+    ('\u{2F97F}', "\u{8070}"),
+    ('\u{2F980}', "\u{2335F}"),
+    ('\u{2F981}', "\u{43D5}"),
+    ('\u{2F982}', "\u{80B2}"),
+    ('\u{2F983}', "\u{8103}"),
+    ('\u{2F984}', "\u{440B}"),
+    ('\u{2F985}', "\u{813E}"),
+    ('\u{2F986}', "\u{5AB5}"),
+    ('\u{2F987}', "\u{267A7}"),
+    ('\u{2F988}', "\u{267B5}"),
+    ('\u{2F989}', "\u{23393}"),
+    ('\u{2F98A}', "\u{2339C}"),
+    ('\u{2F98B}', "\u{8201}"),
+    ('\u{2F98C}', "\u{8204}"),
+    ('\u{2F98D}', "\u{8F9E}"),
+    ('\u{2F98E}', "\u{446B}"),
+    ('\u{2F98F}', "\u{8291}"),
+    ('\u{2F990}', "\u{828B}"),
+    ('\u{2F991}', "\u{829D}"),
+    ('\u{2F992}', "\u{52B3}"),
+    ('\u{2F993}', "\u{82B1}"),
+    ('\u{2F994}', "\u{82B3}"),
+    ('\u{2F995}', "\u{82BD}"),
+    ('\u{2F996}', "\u{82E6}"),
+    ('\u{2F997}', "\u{26B3C}"),
+    ('\u{2F998}', "\u{82E5}"),
+    ('\u{2F999}', "\u{831D}"),
+    ('\u{2F99A}', "\u{8363}"),
+    ('\u{2F99B}', "\u{83AD}"),
+    ('\u{2F99C}', "\u{8323}"),
+    ('\u{2F99D}', "\u{83BD}"),
+    ('\u{2F99E}', "\u{83E7}"),
+    ('\u{2F99F}', "\u{8457}"),
+    ('\u{2F9A0}', "\u{8353}"),
+    ('\u{2F9A1}', "\u{83CA}"),
+    ('\u{2F9A2}', "\u{83CC}"),
+    ('\u{2F9A3}', "\u{83DC}"),
+    ('\u{2F9A4}', "\u{26C36}"),
+    ('\u{2F9A5}', "\u{26D6B}"),
+    ('\u{2F9A6}', "\u{26CD5}"),
+    ('\u{2F9A7}', "\u{452B}"),
+    ('\u{2F9A8}', "\u{84F1}"),
+    ('\u{2F9A9}', "\u{84F3}"),
+    ('\u{2F9AA}', "\u{8516}"),
+    ('\u{2F9AB}', "\u{273CA}"),
+    ('\u{2F9AC}', "\u{8564}"),
+    ('\u{2F9AD}', "\u{26F2C}"),
+    ('\u{2F9AE}', "\u{455D}"),
+    ('\u{2F9AF}', "\u{4561}"),
+    ('\u{2F9B0}', "\u{26FB1}"),
+    ('\u{2F9B1}', "\u{270D2}"),
+    ('\u{2F9B2}', "\u{456B}"),
+    ('\u{2F9B3}', "\u{8650}"),
+    ('\u{2F9B4}', "\u{865C}"),
+    ('\u{2F9B5}', "\u{8667}"),
+    ('\u{2F9B6}', "\u{8669}"),
+    ('\u{2F9B7}', "\u{86A9}"),
+    ('\u{2F9B8}', "\u{8688}"),
+    ('\u{2F9B9}', "\u{870E}"),
+    ('\u{2F9BA}', "\u{86E2}"),
+    ('\u{2F9BB}', "\u{8779}"),
+    ('\u{2F9BC}', "\u{8728}"),
+    ('\u{2F9BD}', "\u{876B}"),
+    ('\u{2F9BE}', "\u{8786}"),
+    ('\u{2F9BF}', "\u{45D7}"),
+    ('\u{2F9C0}', "\u{87E1}"),
+    ('\u{2F9C1}', "\u{8801}"),
+    ('\u{2F9C2}', "\u{45F9}"),
+    ('\u{2F9C3}', "\u{8860}"),
+    ('\u{2F9C4}', "\u{8863}"),
+    ('\u{2F9C5}', "\u{27667}"),
+    ('\u{2F9C6}', "\u{88D7}"),
+    ('\u{2F9C7}', "\u{88DE}"),
+    ('\u{2F9C8}', "\u{4635}"),
+    ('\u{2F9C9}', "\u{88FA}"),
+    ('\u{2F9CA}', "\u{34BB}"),
+    ('\u{2F9CB}', "\u{278AE}"),
+    ('\u{2F9CC}', "\u{27966}"),
+    ('\u{2F9CD}', "\u{46BE}"),
+    ('\u{2F9CE}', "\u{46C7}"),
+    ('\u{2F9CF}', "\u{8AA0}"),
+    ('\u{2F9D0}', "\u{8AED}"),
+    ('\u{2F9D1}', "\u{8B8A}"),
+    ('\u{2F9D2}', "\u{8C55}"),
+    ('\u{2F9D3}', "\u{27CA8}"),
+    ('\u{2F9D4}', "\u{8CAB}"),
+    ('\u{2F9D5}', "\u{8CC1}"),
+    ('\u{2F9D6}', "\u{8D1B}"),
+    ('\u{2F9D7}', "\u{8D77}"),
+    ('\u{2F9D8}', "\u{27F2F}"),
+    ('\u{2F9D9}', "\u{20804}"),
+    ('\u{2F9DA}', "\u{8DCB}"),
+    ('\u{2F9DB}', "\u{8DBC}"),
+    ('\u{2F9DC}', "\u{8DF0}"),
+    ('\u{2F9DD}', "\u{208DE}"),
+    ('\u{2F9DE}', "\u{8ED4}"),
+    ('\u{2F9DF}', "\u{8F38}"),
+    ('\u{2F9E0}', "\u{285D2}"),
+    ('\u{2F9E1}', "\u{285ED}"),
+    ('\u{2F9E2}', "\u{9094}"),
+    ('\u{2F9E3}', "\u{90F1}"),
+    ('\u{2F9E4}', "\u{9111}"),
+    ('\u{2F9E5}', "\u{2872E}"),
+    ('\u{2F9E6}', "\u{911B}"),
+    ('\u{2F9E7}', "\u{9238}"),
+    ('\u{2F9E8}', "\u{92D7}"),
+    ('\u{2F9E9}', "\u{92D8}"),
+    ('\u{2F9EA}', "\u{927C}"),
+    ('\u{2F9EB}', "\u{93F9}"),
+    ('\u{2F9EC}', "\u{9415}"),
+    ('\u{2F9ED}', "\u{28BFA}"),
+    ('\u{2F9EE}', "\u{958B}"),
+    ('\u{2F9EF}', "\u{4995}"),
+    ('\u{2F9F0}', "\u{95B7}"),
+    ('\u{2F9F1}', "\u{28D77}"),
+    ('\u{2F9F2}', "\u{49E6}"),
+    ('\u{2F9F3}', "\u{96C3}"),
+    ('\u{2F9F4}', "\u{5DB2}"),
+    ('\u{2F9F5}', "\u{9723}"),
+    ('\u{2F9F6}', "\u{29145}"),
+    ('\u{2F9F7}', "\u{2921A}"),
+    ('\u{2F9F8}', "\u{4A6E}"),
+    ('\u{2F9F9}', "\u{4A76}"),
+    ('\u{2F9FA}', "\u{97E0}"),
+    ('\u{2F9FB}', "\u{2940A}"),
+    ('\u{2F9FC}', "\u{4AB2}"),
+    ('\u{2F9FD}', "\u{29496}"),
+    ('\u{2F9FE}', "\u{980B}"),
+    ('\u{2F9FF}', "\u{980B}"),
+    ('\u{2FA00}', "\u{9829}"),
+    ('\u{2FA01}', "\u{295B6}"),
+    ('\u{2FA02}', "\u{98E2}"),
+    ('\u{2FA03}', "\u{4B33}"),
+    ('\u{2FA04}', "\u{9929}"),
+    ('\u{2FA05}', "\u{99A7}"),
+    ('\u{2FA06}', "\u{99C2}"),
+    ('\u{2FA07}', "\u{99FE}"),
+    ('\u{2FA08}', "\u{4BCE}"),
+    ('\u{2FA09}', "\u{29B30}"),
+    ('\u{2FA0A}', "\u{9B12}"),
+    ('\u{2FA0B}', "\u{9C40}"),
+    ('\u{2FA0C}', "\u{9CFD}"),
+    ('\u{2FA0D}', "\u{4CCE}"),
+    ('\u{2FA0E}', "\u{4CED}"),
+    ('\u{2FA0F}', "\u{9D67}"),
+    ('\u{2FA10}', "\u{2A0CE}"),
+    ('\u{2FA11}', "\u{4CF8}"),
+    ('\u{2FA12}', "\u{2A105}"),
+    ('\u{2FA13}', "\u{2A20E}"),
+    ('\u{2FA14}', "\u{2A291}"),
+    ('\u{2FA15}', "\u{9EBB}"),
+    ('\u{2FA16}', "\u{4D56}"),
+    ('\u{2FA17}', "\u{9EF9}"),
+    ('\u{2FA18}', "\u{9EFE}"),
+    ('\u{2FA19}', "\u{9F05}"),
+    ('\u{2FA1A}', "\u{9F0F}"),
+    ('\u{2FA1B}', "\u{9F16}"),
+    ('\u{2FA1C}', "\u{9F3B}"),
+    ('\u{2FA1D}', "\u{2A600}"),
+];
+
+/// Full compatibility decompositions that differ from the canonical one
+pub const COMPATIBILITY: &[(char, &str)] = &[
+    ('\u{A0}', "\u{20}"),
+    ('\u{A8}', "\u{20}\u{308}"),
+    ('\u{AA}', "\u{61}"),
+    ('\u{AF}', "\u{20}\u{304}"),
+    ('\u{B2}', "\u{32}"),
+    ('\u{B3}', "\u{33}"),
+    ('\u{B4}', "\u{20}\u{301}"),
+    ('\u{B5}', "\u{3BC}"),
+    ('\u{B8}', "\u{20}\u{327}"),
+    ('\u{B9}', "\u{31}"),
+    ('\u{BA}', "\u{6F}"),
+    ('\u{BC}', "\u{31}\u{2044}\u{34}"),
+    ('\u{BD}', "\u{31}\u{2044}\u{32}"),
+    ('\u{BE}', "\u{33}\u{2044}\u{34}"),
+    ('\u{132}', "\u{49}\u{4A}"),
+    ('\u{133}', "\u{69}\u{6A}"),
+    ('\u{13F}', "\u{4C}\u{B7}"),
+    ('\u{140}', "\u{6C}\u{B7}"),
+    ('\u{149}', "\u{2BC}\u{6E}"),
+    ('\u{17F}', "\u{73}"),
+    ('\u{1C4}', "\u{44}\u{5A}\u{30C}"),
+    ('\u{1C5}', "\u{44}\u{7A}\u{30C}"),
+    ('\u{1C6}', "\u{64}\u{7A}\u{30C}"),
+    ('\u{1C7}', "\u{4C}\u{4A}"),
+    ('\u{1C8}', "\u{4C}\u{6A}"),
+    ('\u{1C9}', "\u{6C}\u{6A}"),
+    ('\u{1CA}', "\u{4E}\u{4A}"),
+    ('\u{1CB}', "\u{4E}\u{6A}"),
+    ('\u{1CC}', "\u{6E}\u{6A}"),
+    ('\u{1F1}', "\u{44}\u{5A}"),
+    ('\u{1F2}', "\u{44}\u{7A}"),
+    ('\u{1F3}', "\u{64}\u{7A}"),
+    ('\u{2B0}', "\u{68}"),
+    ('\u{2B1}', "\u{266}"),
+    ('\u{2B2}', "\u{6A}"),
+    ('\u{2B3}', "\u{72}"),
+    ('\u{2B4}', "\u{279}"),
+    ('\u{2B5}', "\u{27B}"),
+    ('\u{2B6}', "\u{281}"),
+    ('\u{2B7}', "\u{77}"),
+    ('\u{2B8}', "\u{79}"),
+    ('\u{2D8}', "\u{20}\u{306}"),
+    ('\u{2D9}', "\u{20}\u{307}"),
+    ('\u{2DA}', "\u{20}\u{30A}"),
+    ('\u{2DB}', "\u{20}\u{328}"),
+    ('\u{2DC}', "\u{20}\u{303}"),
+    ('\u{2DD}', "\u{20}\u{30B}"),
+    ('\u{2E0}', "\u{263}"),
+    ('\u{2E1}', "\u{6C}"),
+    ('\u{2E2}', "\u{73}"),
+    ('\u{2E3}', "\u{78}"),
+    ('\u{2E4}', "\u{295}"),
+    ('\u{37A}', "\u{20}\u{345}"),
+    ('\u{384}', "\u{20}\u{301}"),
+    ('\u{385}', "\u{20}\u{308}\u{301}"),
+    ('\u{3D0}', "\u{3B2}"),
+    ('\u{3D1}', "\u{3B8}"),
+    ('\u{3D2}', "\u{3A5}"),
+    ('\u{3D3}', "\u{3A5}\u{301}"),
+    ('\u{3D4}', "\u{3A5}\u{308}"),
+    ('\u{3D5}', "\u{3C6}"),
+    ('\u{3D6}', "\u{3C0}"),
+    ('\u{3F0}', "\u{3BA}"),
+    ('\u{3F1}', "\u{3C1}"),
+    ('\u{3F2}', "\u{3C2}"),
+    ('\u{3F4}', "\u{398}"),
+    ('\u{3F5}', "\u{3B5}"),
+    ('\u{3F9}', "\u{3A3}"),
+    ('\u{587}', "\u{565}\u{582}"),
+    ('\u{675}', "\u{627}\u{674}"),
+    ('\u{676}', "\u{648}\u{674}"),
+    ('\u{677}', "\u{6C7}\u{674}"),
+    ('\u{678}', "\u{64A}\u{674}"),
+    ('\u{E33}', "\u{E4D}\u{E32}"),
+    ('\u{EB3}', "\u{ECD}\u{EB2}"),
+    ('\u{EDC}', "\u{EAB}\u{E99}"),
+    ('\u{EDD}', "\u{EAB}\u{EA1}"),
+    ('\u{F0C}', "\u{F0B}"),
+    ('\u{F77}', "\u{FB2}\u{F71}\u{F80}"),
+    ('\u{F79}', "\u{FB3}\u{F71}\u{F80}"),
+    ('\u{10FC}', "\u{10DC}"),
+    ('\u{1D2C}', "\u{41}"),
+    ('\u{1D2D}', "\u{C6}"),
+    ('\u{1D2E}', "\u{42}"),
+    ('\u{1D30}', "\u{44}"),
+    ('\u{1D31}', "\u{45}"),
+    ('\u{1D32}', "\u{18E}"),
+    ('\u{1D33}', "\u{47}"),
+    ('\u{1D34}', "\u{48}"),
+    ('\u{1D35}', "\u{49}"),
+    ('\u{1D36}', "\u{4A}"),
+    ('\u{1D37}', "\u{4B}"),
+    ('\u{1D38}', "\u{4C}"),
+    ('\u{1D39}', "\u{4D}"),
+    ('\u{1D3A}', "\u{4E}"),
+    ('\u{1D3C}', "\u{4F}"),
+    ('\u{1D3D}', "\u{222}"),
+    ('\u{1D3E}', "\u{50}"),
+    ('\u{1D3F}', "\u{52}"),
+    ('\u{1D40}', "\u{54}"),
+    ('\u{1D41}', "\u{55}"),
+    ('\u{1D42}', "\u{57}"),
+    ('\u{1D43}', "\u{61}"),
+    ('\u{1D44}', "\u{250}"),
+    ('\u{1D45}', "\u{251}"),
+    ('\u{1D46}', "\u{1D02}"),
+    ('\u{1D47}', "\u{62}"),
+    ('\u{1D48}', "\u{64}"),
+    ('\u{1D49}', "\u{65}"),
+    ('\u{1D4A}', "\u{259}"),
+    ('\u{1D4B}', "\u{25B}"),
+    ('\u{1D4C}', "\u{25C}"),
+    ('\u{1D4D}', "\u{67}"),
+    ('\u{1D4F}', "\u{6B}"),
+    ('\u{1D50}', "\u{6D}"),
+    ('\u{1D51}', "\u{14B}"),
+    ('\u{1D52}', "\u{6F}"),
+    ('\u{1D53}', "\u{254}"),
+    ('\u{1D54}', "\u{1D16}"),
+    ('\u{1D55}', "\u{1D17}"),
+    ('\u{1D56}', "\u{70}"),
+    ('\u{1D57}', "\u{74}"),
+    ('\u{1D58}', "\u{75}"),
+    ('\u{1D59}', "\u{1D1D}"),
+    ('\u{1D5A}', "\u{26F}"),
+    ('\u{1D5B}', "\u{76}"),
+    ('\u{1D5C}', "\u{1D25}"),
+    ('\u{1D5D}', "\u{3B2}"),
+    ('\u{1D5E}', "\u{3B3}"),
+    ('\u{1D5F}', "\u{3B4}"),
+    ('\u{1D60}', "\u{3C6}"),
+    ('\u{1D61}', "\u{3C7}"),
+    ('\u{1D62}', "\u{69}"),
+    ('\u{1D63}', "\u{72}"),
+    ('\u{1D64}', "\u{75}"),
+    ('\u{1D65}', "\u{76}"),
+    ('\u{1D66}', "\u{3B2}"),
+    ('\u{1D67}', "\u{3B3}"),
+    ('\u{1D68}', "\u{3C1}"),
+    ('\u{1D69}', "\u{3C6}"),
+    ('\u{1D6A}', "\u{3C7}"),
+    ('\u{1D78}', "\u{43D}"),
+    ('\u{1D9B}', "\u{252}"),
+    ('\u{1D9C}', "\u{63}"),
+    ('\u{1D9D}', "\u{255}"),
+    ('\u{1D9E}', "\u{F0}"),
+    ('\u{1D9F}', "\u{25C}"),
+    ('\u{1DA0}', "\u{66}"),
+    ('\u{1DA1}', "\u{25F}"),
+    ('\u{1DA2}', "\u{261}"),
+    ('\u{1DA3}', "\u{265}"),
+    ('\u{1DA4}', "\u{268}"),
+    ('\u{1DA5}', "\u{269}"),
+    ('\u{1DA6}', "\u{26A}"),
+    ('\u{1DA7}', "\u{1D7B}"),
+    ('\u{1DA8}', "\u{29D}"),
+    ('\u{1DA9}', "\u{26D}"),
+    ('\u{1DAA}', "\u{1D85}"),
+    ('\u{1DAB}', "\u{29F}"),
+    ('\u{1DAC}', "\u{271}"),
+    ('\u{1DAD}', "\u{270}"),
+    ('\u{1DAE}', "\u{272}"),
+    ('\u{1DAF}', "\u{273}"),
+    ('\u{1DB0}', "\u{274}"),
+    ('\u{1DB1}', "\u{275}"),
+    ('\u{1DB2}', "\u{278}"),
+    ('\u{1DB3}', "\u{282}"),
+    ('\u{1DB4}', "\u{283}"),
+    ('\u{1DB5}', "\u{1AB}"),
+    ('\u{1DB6}', "\u{289}"),
+    ('\u{1DB7}', "\u{28A}"),
+    ('\u{1DB8}', "\u{1D1C}"),
+    ('\u{1DB9}', "\u{28B}"),
+    ('\u{1DBA}', "\u{28C}"),
+    ('\u{1DBB}', "\u{7A}"),
+    ('\u{1DBC}', "\u{290}"),
+    ('\u{1DBD}', "\u{291}"),
+    ('\u{1DBE}', "\u{292}"),
+    ('\u{1DBF}', "\u{3B8}"),
+    ('\u{1E9A}', "\u{61}\u{2BE}"),
+    ('\u{1E9B}', "\u{73}\u{307}"),
+    ('\u{1FBD}', "\u{20}\u{313}"),
+    ('\u{1FBF}', "\u{20}\u{313}"),
+    ('\u{1FC0}', "\u{20}\u{342}"),
+    ('\u{1FC1}', "\u{20}\u{308}\u{342}"),
+    ('\u{1FCD}', "\u{20}\u{313}\u{300}"),
+    ('\u{1FCE}', "\u{20}\u{313}\u{301}"),
+    ('\u{1FCF}', "\u{20}\u{313}\u{342}"),
+    ('\u{1FDD}', "\u{20}\u{314}\u{300}"),
+    ('\u{1FDE}', "\u{20}\u{314}\u{301}"),
+    ('\u{1FDF}', "\u{20}\u{314}\u{342}"),
+    ('\u{1FED}', "\u{20}\u{308}\u{300}"),
+    ('\u{1FEE}', "\u{20}\u{308}\u{301}"),
+    ('\u{1FFD}', "\u{20}\u{301}"),
+    ('\u{1FFE}', "\u{20}\u{314}"),
+    ('\u{2000}', "\u{20}"),
+    ('\u{2001}', "\u{20}"),
+    ('\u{2002}', "\u{20}"),
+    ('\u{2003}', "\u{20}"),
+    ('\u{2004}', "\u{20}"),
+    ('\u{2005}', "\u{20}"),
+    ('\u{2006}', "\u{20}"),
+    ('\u{2007}', "\u{20}"),
+    ('\u{2008}', "\u{20}"),
+    ('\u{2009}', "\u{20}"),
+    ('\u{200A}', "\u{20}"),
+    ('\u{2011}', "\u{2010}"),
+    ('\u{2017}', "\u{20}\u{333}"),
+    ('\u{2024}', "\u{2E}"),
+    ('\u{2025}', "\u{2E}\u{2E}"),
+    ('\u{2026}', "\u{2E}\u{2E}\u{2E}"),
+    ('\u{202F}', "\u{20}"),
+    ('\u{2033}', "\u{2032}\u{2032}"),
+    ('\u{2034}', "\u{2032}\u{2032}\u{2032}"),
+    ('\u{2036}', "\u{2035}\u{2035}"),
+    ('\u{2037}', "\u{2035}\u{2035}\u{2035}"),
+    ('\u{203C}', "\u{21}\u{21}"),
+    ('\u{203E}', "\u{20}\u{305}"),
+    ('\u{2047}', "\u{3F}\u{3F}"),
+    ('\u{2048}', "\u{3F}\u{21}"),
+    ('\u{2049}', "\u{21}\u{3F}"),
+    ('\u{2057}', "\u{2032}\u{2032}\u{2032}\u{2032}"),
+    ('\u{205F}', "\u{20}"),
+    ('\u{2070}', "\u{30}"),
+    ('\u{2071}', "\u{69}"),
+    ('\u{2074}', "\u{34}"),
+    ('\u{2075}', "\u{35}"),
+    ('\u{2076}', "\u{36}"),
+    ('\u{2077}', "\u{37}"),
+    ('\u{2078}', "\u{38}"),
+    ('\u{2079}', "\u{39}"),
+    ('\u{207A}', "\u{2B}"),
+    ('\u{207B}', "\u{2212}"),
+    ('\u{207C}', "\u{3D}"),
+    ('\u{207D}', "\u{28}"),
+    ('\u{207E}', "\u{29}"),
+    ('\u{207F}', "\u{6E}"),
+    ('\u{2080}', "\u{30}"),
+    ('\u{2081}', "\u{31}"),
+    ('\u{2082}', "\u{32}"),
+    ('\u{2083}', "\u{33}"),
+    ('\u{2084}', "\u{34}"),
+    ('\u{2085}', "\u{35}"),
+    ('\u{2086}', "\u{36}"),
+    ('\u{2087}', "\u{37}"),
+    ('\u{2088}', "\u{38}"),
+    ('\u{2089}', "\u{39}"),
+    ('\u{208A}', "\u{2B}"),
+    ('\u{208B}', "\u{2212}"),
+    ('\u{208C}', "\u{3D}"),
+    ('\u{208D}', "\u{28}"),
+    ('\u{208E}', "\u{29}"),
+    ('\u{2090}', "\u{61}"),
+    ('\u{2091}', "\u{65}"),
+    ('\u{2092}', "\u{6F}"),
+    ('\u{2093}', "\u{78}"),
+    ('\u{2094}', "\u{259}"),
+    ('\u{2095}', "\u{68}"),
+    ('\u{2096}', "\u{6B}"),
+    ('\u{2097}', "\u{6C}"),
+    ('\u{2098}', "\u{6D}"),
+    ('\u{2099}', "\u{6E}"),
+    ('\u{209A}', "\u{70}"),
+    ('\u{209B}', "\u{73}"),
+    ('\u{209C}', "\u{74}"),
+    ('\u{20A8}', "\u{52}\u{73}"),
+    ('\u{2100}', "\u{61}\u{2F}\u{63}"),
+    ('\u{2101}', "\u{61}\u{2F}\u{73}"),
+    ('\u{2102}', "\u{43}"),
+    ('\u{2103}', "\u{B0}\u{43}"),
+    ('\u{2105}', "\u{63}\u{2F}\u{6F}"),
+    ('\u{2106}', "\u{63}\u{2F}\u{75}"),
+    ('\u{2107}', "\u{190}"),
+    ('\u{2109}', "\u{B0}\u{46}"),
+    ('\u{210A}', "\u{67}"),
+    ('\u{210B}', "\u{48}"),
+    ('\u{210C}', "\u{48}"),
+    ('\u{210D}', "\u{48}"),
+    ('\u{210E}', "\u{68}"),
+    ('\u{210F}', "\u{127}"),
+    ('\u{2110}', "\u{49}"),
+    ('\u{2111}', "\u{49}"),
+    ('\u{2112}', "\u{4C}"),
+    ('\u{2113}', "\u{6C}"),
+    ('\u{2115}', "\u{4E}"),
+    ('\u{2116}', "\u{4E}\u{6F}"),
+    ('\u{2119}', "\u{50}"),
+    ('\u{211A}', "\u{51}"),
+    ('\u{211B}', "\u{52}"),
+    ('\u{211C}', "\u{52}"),
+    ('\u{211D}', "\u{52}"),
+    ('\u{2120}', "\u{53}\u{4D}"),
+    ('\u{2121}', "\u{54}\u{45}\u{4C}"),
+    ('\u{2122}', "\u{54}\u{4D}"),
+    ('\u{2124}', "\u{5A}"),
+    ('\u{2128}', "\u{5A}"),
+    ('\u{212C}', "\u{42}"),
+    ('\u{212D}', "\u{43}"),
+    ('\u{212F}', "\u{65}"),
+    ('\u{2130}', "\u{45}"),
+    ('\u{2131}', "\u{46}"),
+    ('\u{2133}', "\u{4D}"),
+    ('\u{2134}', "\u{6F}"),
+    ('\u{2135}', "\u{5D0}"),
+    ('\u{2136}', "\u{5D1}"),
+    ('\u{2137}', "\u{5D2}"),
+    ('\u{2138}', "\u{5D3}"),
+    ('\u{2139}', "\u{69}"),
+    ('\u{213B}', "\u{46}\u{41}\u{58}"),
+    ('\u{213C}', "\u{3C0}"),
+    ('\u{213D}', "\u{3B3}"),
+    ('\u{213E}', "\u{393}"),
+    ('\u{213F}', "\u{3A0}"),
+    ('\u{2140}', "\u{2211}"),
+    ('\u{2145}', "\u{44}"),
+    ('\u{2146}', "\u{64}"),
+    ('\u{2147}', "\u{65}"),
+    ('\u{2148}', "\u{69}"),
+    ('\u{2149}', "\u{6A}"),
+    ('\u{2150}', "\u{31}\u{2044}\u{37}"),
+    ('\u{2151}', "\u{31}\u{2044}\u{39}"),
+    ('\u{2152}', "\u{31}\u{2044}\u{31}\u{30}"),
+    ('\u{2153}', "\u{31}\u{2044}\u{33}"),
+    ('\u{2154}', "\u{32}\u{2044}\u{33}"),
+    ('\u{2155}', "\u{31}\u{2044}\u{35}"),
+    ('\u{2156}', "\u{32}\u{2044}\u{35}"),
+    ('\u{2157}', "\u{33}\u{2044}\u{35}"),
+    ('\u{2158}', "\u{34}\u{2044}\u{35}"),
+    ('\u{2159}', "\u{31}\u{2044}\u{36}"),
+    ('\u{215A}', "\u{35}\u{2044}\u{36}"),
+    ('\u{215B}', "\u{31}\u{2044}\u{38}"),
+    ('\u{215C}', "\u{33}\u{2044}\u{38}"),
+    ('\u{215D}', "\u{35}\u{2044}\u{38}"),
+    ('\u{215E}', "\u{37}\u{2044}\u{38}"),
+    ('\u{215F}', "\u{31}\u{2044}"),
+    ('\u{2160}', "\u{49}"),
+    ('\u{2161}', "\u{49}\u{49}"),
+    ('\u{2162}', "\u{49}\u{49}\u{49}"),
+    ('\u{2163}', "\u{49}\u{56}"),
+    ('\u{2164}', "\u{56}"),
+    ('\u{2165}', "\u{56}\u{49}"),
+    ('\u{2166}', "\u{56}\u{49}\u{49}"),
+    ('\u{2167}', "\u{56}\u{49}\u{49}\u{49}"),
+    ('\u{2168}', "\u{49}\u{58}"),
+    ('\u{2169}', "\u{58}"),
+    ('\u{216A}', "\u{58}\u{49}"),
+    ('\u{216B}', "\u{58}\u{49}\u{49}"),
+    ('\u{216C}', "\u{4C}"),
+    ('\u{216D}', "\u{43}"),
+    ('\u{216E}', "\u{44}"),
+    ('\u{216F}', "\u{4D}"),
+    ('\u{2170}', "\u{69}"),
+    ('\u{2171}', "\u{69}\u{69}"),
+    ('\u{2172}', "\u{69}\u{69}\u{69}"),
+    ('\u{2173}', "\u{69}\u{76}"),
+    ('\u{2174}', "\u{76}"),
+    ('\u{2175}', "\u{76}\u{69}"),
+    ('\u{2176}', "\u{76}\u{69}\u{69}"),
+    ('\u{2177}', "\u{76}\u{69}\u{69}\u{69}"),
+    ('\u{2178}', "\u{69}\u{78}"),
+    ('\u{2179}', "\u{78}"),
+    ('\u{217A}', "\u{78}\u{69}"),
+    ('\u{217B}', "\u{78}\u{69}\u{69}"),
+    ('\u{217C}', "\u{6C}"),
+    ('\u{217D}', "\u{63}"),
+    ('\u{217E}', "\u{64}"),
+    ('\u{217F}', "\u{6D}"),
+    ('\u{2189}', "\u{30}\u{2044}\u{33}"),
+    ('\u{222C}', "\u{222B}\u{222B}"),
+    ('\u{222D}', "\u{222B}\u{222B}\u{222B}"),
+    ('\u{222F}', "\u{222E}\u{222E}"),
+    ('\u{2230}', "\u{222E}\u{222E}\u{222E}"),
+    ('\u{2460}', "\u{31}"),
+    ('\u{2461}', "\u{32}"),
+    ('\u{2462}', "\u{33}"),
+    ('\u{2463}', "\u{34}"),
+    ('\u{2464}', "\u{35}"),
+    ('\u{2465}', "\u{36}"),
+    ('\u{2466}', "\u{37}"),
+    ('\u{2467}', "\u{38}"),
+    ('\u{2468}', "\u{39}"),
+    ('\u{2469}', "\u{31}\u{30}"),
+    ('\u{246A}', "\u{31}\u{31}"),
+    ('\u{246B}', "\u{31}\u{32}"),
+    ('\u{246C}', "\u{31}\u{33}"),
+    ('\u{246D}', "\u{31}\u{34}"),
+    ('\u{246E}', "\u{31}\u{35}"),
+    ('\u{246F}', "\u{31}\u{36}"),
+    ('\u{2470}', "\u{31}\u{37}"),
+    ('\u{2471}', "\u{31}\u{38}"),
+    ('\u{2472}', "\u{31}\u{39}"),
+    ('\u{2473}', "\u{32}\u{30}"),
+    ('\u{2474}', "\u{28}\u{31}\u{29}"),
+    ('\u{2475}', "\u{28}\u{32}\u{29}"),
+    ('\u{2476}', "\u{28}\u{33}\u{29}"),
+    ('\u{2477}', "\u{28}\u{34}\u{29}"),
+    ('\u{2478}', "\u{28}\u{35}\u{29}"),
+    ('\u{2479}', "\u{28}\u{36}\u{29}"),
+    ('\u{247A}', "\u{28}\u{37}\u{29}"),
+    ('\u{247B}', "\u{28}\u{38}\u{29}"),
+    ('\u{247C}', "\u{28}\u{39}\u{29}"),
+    ('\u{247D}', "\u{28}\u{31}\u{30}\u{29}"),
+    ('\u{247E}', "\u{28}\u{31}\u{31}\u{29}"),
+    ('\u{247F}', "\u{28}\u{31}\u{32}\u{29}"),
+    ('\u{2480}', "\u{28}\u{31}\u{33}\u{29}"),
+    ('\u{2481}', "\u{28}\u{31}\u{34}\u{29}"),
+    ('\u{2482}', "\u{28}\u{31}\u{35}\u{29}"),
+    ('\u{2483}', "\u{28}\u{31}\u{36}\u{29}"),
+    ('\u{2484}', "\u{28}\u{31}\u{37}\u{29}"),
+    ('\u{2485}', "\u{28}\u{31}\u{38}\u{29}"),
+    ('\u{2486}', "\u{28}\u{31}\u{39}\u{29}"),
+    ('\u{2487}', "\u{28}\u{32}\u{30}\u{29}"),
+    ('\u{2488}', "\u{31}\u{2E}"),
+    ('\u{2489}', "\u{32}\u{2E}"),
+    ('\u{248A}', "\u{33}\u{2E}"),
+    ('\u{248B}', "\u{34}\u{2E}"),
+    ('\u{248C}', "\u{35}\u{2E}"),
+    ('\u{248D}', "\u{36}\u{2E}"),
+    ('\u{248E}', "\u{37}\u{2E}"),
+    ('\u{248F}', "\u{38}\u{2E}"),
+    ('\u{2490}', "\u{39}\u{2E}"),
+    ('\u{2491}', "\u{31}\u{30}\u{2E}"),
+    ('\u{2492}', "\u{31}\u{31}\u{2E}"),
+    ('\u{2493}', "\u{31}\u{32}\u{2E}"),
+    ('\u{2494}', "\u{31}\u{33}\u{2E}"),
+    ('\u{2495}', "\u{31}\u{34}\u{2E}"),
+    ('\u{2496}', "\u{31}\u{35}\u{2E}"),
+    ('\u{2497}', "\u{31}\u{36}\u{2E}"),
+    ('\u{2498}', "\u{31}\u{37}\u{2E}"),
+    ('\u{2499}', "\u{31}\u{38}\u{2E}"),
+    ('\u{249A}', "\u{31}\u{39}\u{2E}"),
+    ('\u{249B}', "\u{32}\u{30}\u{2E}"),
+    ('\u{249C}', "\u{28}\u{61}\u{29}"),
+    ('\u{249D}', "\u{28}\u{62}\u{29}"),
+    ('\u{249E}', "\u{28}\u{63}\u{29}"),
+    ('\u{249F}', "\u{28}\u{64}\u{29}"),
+    ('\u{24A0}', "\u{28}\u{65}\u{29}"),
+    ('\u{24A1}', "\u{28}\u{66}\u{29}"),
+    ('\u{24A2}', "\u{28}\u{67}\u{29}"),
+    ('\u{24A3}', "\u{28}\u{68}\u{29}"),
+    ('\u{24A4}', "\u{28}\u{69}\u{29}"),
+    ('\u{24A5}', "\u{28}\u{6A}\u{29}"),
+    ('\u{24A6}', "\u{28}\u{6B}\u{29}"),
+    ('\u{24A7}', "\u{28}\u{6C}\u{29}"),
+    ('\u{24A8}', "\u{28}\u{6D}\u{29}"),
+    ('\u{24A9}', "\u{28}\u{6E}\u{29}"),
+    ('\u{24AA}', "\u{28}\u{6F}\u{29}"),
+    ('\u{24AB}', "\u{28}\u{70}\u{29}"),
+    ('\u{24AC}', "\u{28}\u{71}\u{29}"),
+    ('\u{24AD}', "\u{28}\u{72}\u{29}"),
+    ('\u{24AE}', "\u{28}\u{73}\u{29}"),
+    ('\u{24AF}', "\u{28}\u{74}\u{29}"),
+    ('\u{24B0}', "\u{28}\u{75}\u{29}"),
+    ('\u{24B1}', "\u{28}\u{76}\u{29}"),
+    ('\u{24B2}', "\u{28}\u{77}\u{29}"),
+    ('\u{24B3}', "\u{28}\u{78}\u{29}"),
+    ('\u{24B4}', "\u{28}\u{79}\u{29}"),
+    ('\u{24B5}', "\u{28}\u{7A}\u{29}"),
+    ('\u{24B6}', "\u{41}"),
+    ('\u{24B7}', "\u{42}"),
+    ('\u{24B8}', "\u{43}"),
+    ('\u{24B9}', "\u{44}"),
+    ('\u{24BA}', "\u{45}"),
+    ('\u{24BB}', "\u{46}"),
+    ('\u{24BC}', "\u{47}"),
+    ('\u{24BD}', "\u{48}"),
+    ('\u{24BE}', "\u{49}"),
+    ('\u{24BF}', "\u{4A}"),
+    ('\u{24C0}', "\u{4B}"),
+    ('\u{24C1}', "\u{4C}"),
+    ('\u{24C2}', "\u{4D}"),
+    ('\u{24C3}', "\u{4E}"),
+    ('\u{24C4}', "\u{4F}"),
+    ('\u{24C5}', "\u{50}"),
+    ('\u{24C6}', "\u{51}"),
+    ('\u{24C7}', "\u{52}"),
+    ('\u{24C8}', "\u{53}"),
+    ('\u{24C9}', "\u{54}"),
+    ('\u{24CA}', "\u{55}"),
+    ('\u{24CB}', "\u{56}"),
+    ('\u{24CC}', "\u{57}"),
+    ('\u{24CD}', "\u{58}"),
+    ('\u{24CE}', "\u{59}"),
+    ('\u{24CF}', "\u{5A}"),
+    ('\u{24D0}', "\u{61}"),
+    ('\u{24D1}', "\u{62}"),
+    ('\u{24D2}', "\u{63}"),
+    ('\u{24D3}', "\u{64}"),
+    ('\u{24D4}', "\u{65}"),
+    ('\u{24D5}', "\u{66}"),
+    ('\u{24D6}', "\u{67}"),
+    ('\u{24D7}', "\u{68}"),
+    ('\u{24D8}', "\u{69}"),
+    ('\u{24D9}', "\u{6A}"),
+    ('\u{24DA}', "\u{6B}"),
+    ('\u{24DB}', "\u{6C}"),
+    ('\u{24DC}', "\u{6D}"),
+    ('\u{24DD}', "\u{6E}"),
+    ('\u{24DE}', "\u{6F}"),
+    ('\u{24DF}', "\u{70}"),
+    ('\u{24E0}', "\u{71}"),
+    ('\u{24E1}', "\u{72}"),
+    ('\u{24E2}', "\u{73}"),
+    ('\u{24E3}', "\u{74}"),
+    ('\u{24E4}', "\u{75}"),
+    ('\u{24E5}', "\u{76}"),
+    ('\u{24E6}', "\u{77}"),
+    ('\u{24E7}', "\u{78}"),
+    ('\u{24E8}', "\u{79}"),
+    ('\u{24E9}', "\u{7A}"),
+    ('\u{24EA}', "\u{30}"),
+    ('\u{2A0C}', "\u{222B}\u{222B}\u{222B}\u{222B}"),
+    ('\u{2A74}', "\u{3A}\u{3A}\u{3D}"),
+    ('\u{2A75}', "\u{3D}\u{3D}"),
+    ('\u{2A76}', "\u{3D}\u{3D}\u{3D}"),
+    ('\u{2C7C}', "\u{6A}"),
+    ('\u{2C7D}', "\u{56}"),
+    ('\u{2D6F}', "\u{2D61}"),
+    ('\u{2E9F}', "\u{6BCD}"),
+    ('\u{2EF3}', "\u{9F9F}"),
+    ('\u{2F00}', "\u{4E00}"),
+    ('\u{2F01}', "\u{4E28}"),
+    ('\u{2F02}', "\u{4E36}"),
+    ('\u{2F03}', "\u{4E3F}"),
+    ('\u{2F04}', "\u{4E59}"),
+    ('\u{2F05}', "\u{4E85}"),
+    ('\u{2F06}', "\u{4E8C}"),
+    ('\u{2F07}', "\u{4EA0}"),
+    ('\u{2F08}', "\u{4EBA}"),
+    ('\u{2F09}', "\u{513F}"),
+    ('\u{2F0A}', "\u{5165}"),
+    ('\u{2F0B}', "\u{516B}"),
+    ('\u{2F0C}', "\u{5182}"),
+    ('\u{2F0D}', "\u{5196}"),
+    ('\u{2F0E}', "\u{51AB}"),
+    ('\u{2F0F}', "\u{51E0}"),
+    ('\u{2F10}', "\u{51F5}"),
+    ('\u{2F11}', "\u{5200}"),
+    ('\u{2F12}', "\u{529B}"),
+    ('\u{2F13}', "\u{52F9}"),
+    ('\u{2F14}', "\u{5315}"),
+    ('\u{2F15}', "\u{531A}"),
+    ('\u{2F16}', "\u{5338}"),
+    ('\u{2F17}', "\u{5341}"),
+    ('\u{2F18}', "\u{535C}"),
+    ('\u{2F19}', "\u{5369}"),
+    ('\u{2F1A}', "\u{5382}"),
+    ('\u{2F1B}', "\u{53B6}"),
+    ('\u{2F1C}', "\u{53C8}"),
+    ('\u{2F1D}', "\u{53E3}"),
+    ('\u{2F1E}', "\u{56D7}"),
+    ('\u{2F1F}', "\u{571F}"),
+    ('\u{2F20}', "\u{58EB}"),
+    ('\u{2F21}', "\u{5902}"),
+    ('\u{2F22}', "\u{590A}"),
+    ('\u{2F23}', "\u{5915}"),
+    ('\u{2F24}', "\u{5927}"),
+    ('\u{2F25}', "\u{5973}"),
+    ('\u{2F26}', "\u{5B50}"),
+    ('\u{2F27}', "\u{5B80}"),
+    ('\u{2F28}', "\u{5BF8}"),
+    ('\u{2F29}', "\u{5C0F}"),
+    ('\u{2F2A}', "\u{5C22}"),
+    ('\u{2F2B}', "\u{5C38}"),
+    ('\u{2F2C}', "\u{5C6E}"),
+    ('\u{2F2D}', "\u{5C71}"),
+    ('\u{2F2E}', "\u{5DDB}"),
+    ('\u{2F2F}', "\u{5DE5}"),
+    ('\u{2F30}', "\u{5DF1}"),
+    ('\u{2F31}', "\u{5DFE}"),
+    ('\u{2F32}', "\u{5E72}"),
+    ('\u{2F33}', "\u{5E7A}"),
+    ('\u{2F34}', "\u{5E7F}"),
+    ('\u{2F35}', "\u{5EF4}"),
+    ('\u{2F36}', "\u{5EFE}"),
+    ('\u{2F37}', "\u{5F0B}"),
+    ('\u{2F38}', "\u{5F13}"),
+    ('\u{2F39}', "\u{5F50}"),
+    ('\u{2F3A}', "\u{5F61}"),
+    ('\u{2F3B}', "\u{5F73}"),
+    ('\u{2F3C}', "\u{5FC3}"),
+    ('\u{2F3D}', "\u{6208}"),
+    ('\u{2F3E}', "\u{6236}"),
+    ('\u{2F3F}', "\u{624B}"),
+    ('\u{2F40}', "\u{652F}"),
+    ('\u{2F41}', "\u{6534}"),
+    ('\u{2F42}', "\u{6587}"),
+    ('\u{2F43}', "\u{6597}"),
+    ('\u{2F44}', "\u{65A4}"),
+    ('\u{2F45}', "\u{65B9}"),
+    ('\u{2F46}', "\u{65E0}"),
+    ('\u{2F47}', "\u{65E5}"),
+    ('\u{2F48}', "\u{66F0}"),
+    ('\u{2F49}', "\u{6708}"),
+    ('\u{2F4A}', "\u{6728}"),
+    ('\u{2F4B}', "\u{6B20}"),
+    ('\u{2F4C}', "\u{6B62}"),
+    ('\u{2F4D}', "\u{6B79}"),
+    ('\u{2F4E}', "\u{6BB3}"),
+    ('\u{2F4F}', "\u{6BCB}"),
+    ('\u{2F50}', "\u{6BD4}"),
+    ('\u{2F51}', "\u{6BDB}"),
+    ('\u{2F52}', "\u{6C0F}"),
+    ('\u{2F53}', "\u{6C14}"),
+    ('\u{2F54}', "\u{6C34}"),
+    ('\u{2F55}', "\u{706B}"),
+    ('\u{2F56}', "\u{722A}"),
+    ('\u{2F57}', "\u{7236}"),
+    ('\u{2F58}', "\u{723B}"),
+    ('\u{2F59}', "\u{723F}"),
+    ('\u{2F5A}', "\u{7247}"),
+    ('\u{2F5B}', "\u{7259}"),
+    ('\u{2F5C}', "\u{725B}"),
+    ('\u{2F5D}', "\u{72AC}"),
+    ('\u{2F5E}', "\u{7384}"),
+    ('\u{2F5F}', "\u{7389}"),
+    ('\u{2F60}', "\u{74DC}"),
+    ('\u{2F61}', "\u{74E6}"),
+    ('\u{2F62}', "\u{7518}"),
+    ('\u{2F63}', "\u{751F}"),
+    ('\u{2F64}', "\u{7528}"),
+    ('\u{2F65}', "\u{7530}"),
+    ('\u{2F66}', "\u{758B}"),
+    ('\u{2F67}', "\u{7592}"),
+    ('\u{2F68}', "\u{7676}"),
+    ('\u{2F69}', "\u{767D}"),
+    ('\u{2F6A}', "\u{76AE}"),
+    ('\u{2F6B}', "\u{76BF}"),
+    ('\u{2F6C}', "\u{76EE}"),
+    ('\u{2F6D}', "\u{77DB}"),
+    ('\u{2F6E}', "\u{77E2}"),
+    ('\u{2F6F}', "\u{77F3}"),
+    ('\u{2F70}', "\u{793A}"),
+    ('\u{2F71}', "\u{79B8}"),
+    ('\u{2F72}', "\u{79BE}"),
+    ('\u{2F73}', "\u{7A74}"),
+    ('\u{2F74}', "\u{7ACB}"),
+    ('\u{2F75}', "\u{7AF9}"),
+    ('\u{2F76}', "\u{7C73}"),
+    ('\u{2F77}', "\u{7CF8}"),
+    ('\u{2F78}', "\u{7F36}"),
+    ('\u{2F79}', "\u{7F51}"),
+    ('\u{2F7A}', "\u{7F8A}"),
+    ('\u{2F7B}', "\u{7FBD}"),
+    ('\u{2F7C}', "\u{8001}"),
+    ('\u{2F7D}', "\u{800C}"),
+    ('\u{2F7E}', "\u{8012}"),
+    ('\u{2F7F}', "\u{8033}"),
+    ('\u{2F80}', "\u{807F}"),
+    ('\u{2F81}', "\u{8089}"),
+    ('\u{2F82}', "\u{81E3}"),
+    ('\u{2F83}', "\u{81EA}"),
+    ('\u{2F84}', "\u{81F3}"),
+    ('\u{2F85}', "\u{81FC}"),
+    ('\u{2F86}', "\u{820C}"),
+    ('\u{2F87}', "\u{821B}"),
+    ('\u{2F88}', "\u{821F}"),
+    ('\u{2F89}', "\u{826E}"),
+    ('\u{2F8A}', "\u{8272}"),
+    ('\u{2F8B}', "\u{8278}"),
+    ('\u{2F8C}', "\u{864D}"),
+    ('\u{2F8D}', "\u{866B}"),
+    ('\u{2F8E}', "\u{8840}"),
+    ('\u{2F8F}', "\u{884C}"),
+    ('\u{2F90}', "\u{8863}"),
+    ('\u{2F91}', "\u{897E}"),
+    ('\u{2F92}', "\u{898B}"),
+    ('\u{2F93}', "\u{89D2}"),
+    ('\u{2F94}', "\u{8A00}"),
+    ('\u{2F95}', "\u{8C37}"),
+    ('\u{2F96}', "\u{8C46}"),
+    ('\u{2F97}', "\u{8C55}"),
+    ('\u{2F98}', "\u{8C78}"),
+    ('\u{2F99}', "\u{8C9D}"),
+    ('\u{2F9A}', "\u{8D64}"),
+    ('\u{2F9B}', "\u{8D70}"),
+    ('\u{2F9C}', "\u{8DB3}"),
+    ('\u{2F9D}', "\u{8EAB}"),
+    ('\u{2F9E}', "\u{8ECA}"),
+    ('\u{2F9F}', "\u{8F9B}"),
+    ('\u{2FA0}', "\u{8FB0}"),
+    ('\u{2FA1}', "\u{8FB5}"),
+    ('\u{2FA2}', "\u{9091}"),
+    ('\u{2FA3}', "\u{9149}"),
+    ('\u{2FA4}', "\u{91C6}"),
+    ('\u{2FA5}', "\u{91CC}"),
+    ('\u{2FA6}', "\u{91D1}"),
+    ('\u{2FA7}', "\u{9577}"),
+    ('\u{2FA8}', "\u{9580}"),
+    ('\u{2FA9}', "\u{961C}"),
+    ('\u{2FAA}', "\u{96B6}"),
+    ('\u{2FAB}', "\u{96B9}"),
+    ('\u{2FAC}', "\u{96E8}"),
+    ('\u{2FAD}', "\u{9751}"),
+    ('\u{2FAE}', "\u{975E}"),
+    ('\u{2FAF}', "\u{9762}"),
+    ('\u{2FB0}', "\u{9769}"),
+    ('\u{2FB1}', "\u{97CB}"),
+    ('\u{2FB2}', "\u{97ED}"),
+    ('\u{2FB3}', "\u{97F3}"),
+    ('\u{2FB4}', "\u{9801}"),
+    ('\u{2FB5}', "\u{98A8}"),
+    ('\u{2FB6}', "\u{98DB}"),
+    ('\u{2FB7}', "\u{98DF}"),
+    ('\u{2FB8}', "\u{9996}"),
+    ('\u{2FB9}', "\u{9999}"),
+    ('\u{2FBA}', "\u{99AC}"),
+    ('\u{2FBB}', "\u{9AA8}"),
+    ('\u{2FBC}', "\u{9AD8}"),
+    ('\u{2FBD}', "\u{9ADF}"),
+    ('\u{2FBE}', "\u{9B25}"),
+    ('\u{2FBF}', "\u{9B2F}"),
+    ('\u{2FC0}', "\u{9B32}"),
+    ('\u{2FC1}', "\u{9B3C}"),
+    ('\u{2FC2}', "\u{9B5A}"),
+    ('\u{2FC3}', "\u{9CE5}"),
+    ('\u{2FC4}', "\u{9E75}"),
+    ('\u{2FC5}', "\u{9E7F}"),
+    ('\u{2FC6}', "\u{9EA5}"),
+    ('\u{2FC7}', "\u{9EBB}"),
+    ('\u{2FC8}', "\u{9EC3}"),
+    ('\u{2FC9}', "\u{9ECD}"),
+    ('\u{2FCA}', "\u{9ED1}"),
+    ('\u{2FCB}', "\u{9EF9}"),
+    ('\u{2FCC}', "\u{9EFD}"),
+    ('\u{2FCD}', "\u{9F0E}"),
+    ('\u{2FCE}', "\u{9F13}"),
+    ('\u{2FCF}', "\u{9F20}"),
+    ('\u{2FD0}', "\u{9F3B}"),
+    ('\u{2FD1}', "\u{9F4A}"),
+    ('\u{2FD2}', "\u{9F52}"),
+    ('\u{2FD3}', "\u{9F8D}"),
+    ('\u{2FD4}', "\u{9F9C}"),
+    ('\u{2FD5}', "\u{9FA0}"),
+    ('\u{3000}', "\u{20}"),
+    ('\u{3036}', "\u{3012}"),
+    ('\u{3038}', "\u{5341}"),
+    ('\u{3039}', "\u{5344}"),
+    ('\u{303A}', "\u{5345}"),
+    ('\u{309B}', "\u{20}\u{3099}"),
+    ('\u{309C}', "\u{20}\u{309A}"),
+    ('\u{309F}', "\u{3088}\u{308A}"),
+    ('\u{30FF}', "\u{30B3}\u{30C8}"),
+    ('\u{3131}', "\u{1100}"),
+    ('\u{3132}', "\u{1101}"),
+    ('\u{3133}', "\u{11AA}"),
+    ('\u{3134}', "\u{1102}"),
+    ('\u{3135}', "\u{11AC}"),
+    ('\u{3136}', "\u{11AD}"),
+    ('\u{3137}', "\u{1103}"),
+    ('\u{3138}', "\u{1104}"),
+    ('\u{3139}', "\u{1105}"),
+    ('\u{313A}', "\u{11B0}"),
+    ('\u{313B}', "\u{11B1}"),
+    ('\u{313C}', "\u{11B2}"),
+    ('\u{313D}', "\u{11B3}"),
+    ('\u{313E}', "\u{11B4}"),
+    ('\u{313F}', "\u{11B5}"),
+    ('\u{3140}', "\u{111A}"),
+    ('\u{3141}', "\u{1106}"),
+    ('\u{3142}', "\u{1107}"),
+    ('\u{3143}', "\u{1108}"),
+    ('\u{3144}', "\u{1121}"),
+    ('\u{3145}', "\u{1109}"),
+    ('\u{3146}', "\u{110A}"),
+    ('\u{3147}', "\u{110B}"),
+    ('\u{3148}', "\u{110C}"),
+    ('\u{3149}', "\u{110D}"),
+    ('\u{314A}', "\u{110E}"),
+    ('\u{314B}', "\u{110F}"),
+    ('\u{314C}', "\u{1110}"),
+    ('\u{314D}', "\u{1111}"),
+    ('\u{314E}', "\u{1112}"),
+    ('\u{314F}', "\u{1161}"),
+    ('\u{3150}', "\u{1162}"),
+    ('\u{3151}', "\u{1163}"),
+    ('\u{3152}', "\u{1164}"),
+    ('\u{3153}', "\u{1165}"),
+    ('\u{3154}', "\u{1166}"),
+    ('\u{3155}', "\u{1167}"),
+    ('\u{3156}', "\u{1168}"),
+    ('\u{3157}', "\u{1169}"),
+    ('\u{3158}', "\u{116A}"),
+    ('\u{3159}', "\u{116B}"),
+    ('\u{315A}', "\u{116C}"),
+    ('\u{315B}', "\u{116D}"),
+    ('\u{315C}', "\u{116E}"),
+    ('\u{315D}', "\u{116F}"),
+    ('\u{315E}', "\u{1170}"),
+    ('\u{315F}', "\u{1171}"),
+    ('\u{3160}', "\u{1172}"),
+    ('\u{3161}', "\u{1173}"),
+    ('\u{3162}', "\u{1174}"),
+    ('\u{3163}', "\u{1175}"),
+    ('\u{3164}', "\u{1160}"),
+    ('\u{3165}', "\u{1114}"),
+    ('\u{3166}', "\u{1115}"),
+    ('\u{3167}', "\u{11C7}"),
+    ('\u{3168}', "\u{11C8}"),
+    ('\u{3169}', "\u{11CC}"),
+    ('\u{316A}', "\u{11CE}"),
+    ('\u{316B}', "\u{11D3}"),
+    ('\u{316C}', "\u{11D7}"),
+    ('\u{316D}', "\u{11D9}"),
+    ('\u{316E}', "\u{111C}"),
+    ('\u{316F}', "\u{11DD}"),
+    ('\u{3170}', "\u{11DF}"),
+    ('\u{3171}', "\u{111D}"),
+    ('\u{3172}', "\u{111E}"),
+    ('\u{3173}', "\u{1120}"),
+    ('\u{3174}', "\u{1122}"),
+    ('\u{3175}', "\u{1123}"),
+    ('\u{3176}', "\u{1127}"),
+    ('\u{3177}', "\u{1129}"),
+    ('\u{3178}', "\u{112B}"),
+    ('\u{3179}', "\u{112C}"),
+    ('\u{317A}', "\u{112D}"),
+    ('\u{317B}', "\u{112E}"),
+    ('\u{317C}', "\u{112F}"),
+    ('\u{317D}', "\u{1132}"),
+    ('\u{317E}', "\u{1136}"),
+    ('\u{317F}', "\u{1140}"),
+    ('\u{3180}', "\u{1147}"),
+    ('\u{3181}', "\u{114C}"),
+    ('\u{3182}', "\u{11F1}"),
+    ('\u{3183}', "\u{11F2}"),
+    ('\u{3184}', "\u{1157}"),
+    ('\u{3185}', "\u{1158}"),
+    ('\u{3186}', "\u{1159}"),
+    ('\u{3187}', "\u{1184}"),
+    ('\u{3188}', "\u{1185}"),
+    ('\u{3189}', "\u{1188}"),
+    ('\u{318A}', "\u{1191}"),
+    ('\u{318B}', "\u{1192}"),
+    ('\u{318C}', "\u{1194}"),
+    ('\u{318D}', "\u{119E}"),
+    ('\u{318E}', "\u{11A1}"),
+    ('\u{3192}', "\u{4E00}"),
+    ('\u{3193}', "\u{4E8C}"),
+    ('\u{3194}', "\u{4E09}"),
+    ('\u{3195}', "\u{56DB}"),
+    ('\u{3196}', "\u{4E0A}"),
+    ('\u{3197}', "\u{4E2D}"),
+    ('\u{3198}', "\u{4E0B}"),
+    ('\u{3199}', "\u{7532}"),
+    ('\u{319A}', "\u{4E59}"),
+    ('\u{319B}', "\u{4E19}"),
+    ('\u{319C}', "\u{4E01}"),
+    ('\u{319D}', "\u{5929}"),
+    ('\u{319E}', "\u{5730}"),
+    ('\u{319F}', "\u{4EBA}"),
+    ('\u{3200}', "\u{28}\u{1100}\u{29}"),
+    ('\u{3201}', "\u{28}\u{1102}\u{29}"),
+    ('\u{3202}', "\u{28}\u{1103}\u{29}"),
+    ('\u{3203}', "\u{28}\u{1105}\u{29}"),
+    ('\u{3204}', "\u{28}\u{1106}\u{29}"),
+    ('\u{3205}', "\u{28}\u{1107}\u{29}"),
+    ('\u{3206}', "\u{28}\u{1109}\u{29}"),
+    ('\u{3207}', "\u{28}\u{110B}\u{29}"),
+    ('\u{3208}', "\u{28}\u{110C}\u{29}"),
+    ('\u{3209}', "\u{28}\u{110E}\u{29}"),
+    ('\u{320A}', "\u{28}\u{110F}\u{29}"),
+    ('\u{320B}', "\u{28}\u{1110}\u{29}"),
+    ('\u{320C}', "\u{28}\u{1111}\u{29}"),
+    ('\u{320D}', "\u{28}\u{1112}\u{29}"),
+    ('\u{320E}', "\u{28}\u{1100}\u{1161}\u{29}"),
+    ('\u{320F}', "\u{28}\u{1102}\u{1161}\u{29}"),
+    ('\u{3210}', "\u{28}\u{1103}\u{1161}\u{29}"),
+    ('\u{3211}', "\u{28}\u{1105}\u{1161}\u{29}"),
+    ('\u{3212}', "\u{28}\u{1106}\u{1161}\u{29}"),
+    ('\u{3213}', "\u{28}\u{1107}\u{1161}\u{29}"),
+    ('\u{3214}', "\u{28}\u{1109}\u{1161}\u{29}"),
+    ('\u{3215}', "\u{28}\u{110B}\u{1161}\u{29}"),
+    ('\u{3216}', "\u{28}\u{110C}\u{1161}\u{29}"),
+    ('\u{3217}', "\u{28}\u{110E}\u{1161}\u{29}"),
+    ('\u{3218}', "\u{28}\u{110F}\u{1161}\u{29}"),
+    ('\u{3219}', "\u{28}\u{1110}\u{1161}\u{29}"),
+    ('\u{321A}', "\u{28}\u{1111}\u{1161}\u{29}"),
+    ('\u{321B}', "\u{28}\u{1112}\u{1161}\u{29}"),
+    ('\u{321C}', "\u{28}\u{110C}\u{116E}\u{29}"),
+    ('\u{321D}', "\u{28}\u{110B}\u{1169}\u{110C}\u{1165}\u{11AB}\u{29}"),
+    ('\u{321E}', "\u{28}\u{110B}\u{1169}\u{1112}\u{116E}\u{29}"),
+    ('\u{3220}', "\u{28}\u{4E00}\u{29}"),
+    ('\u{3221}', "\u{28}\u{4E8C}\u{29}"),
+    ('\u{3222}', "\u{28}\u{4E09}\u{29}"),
+    ('\u{3223}', "\u{28}\u{56DB}\u{29}"),
+    ('\u{3224}', "\u{28}\u{4E94}\u{29}"),
+    ('\u{3225}', "\u{28}\u{516D}\u{29}"),
+    ('\u{3226}', "\u{28}\u{4E03}\u{29}"),
+    ('\u{3227}', "\u{28}\u{516B}\u{29}"),
+    ('\u{3228}', "\u{28}\u{4E5D}\u{29}"),
+    ('\u{3229}', "\u{28}\u{5341}\u{29}"),
+    ('\u{322A}', "\u{28}\u{6708}\u{29}"),
+    ('\u{322B}', "\u{28}\u{706B}\u{29}"),
+    ('\u{322C}', "\u{28}\u{6C34}\u{29}"),
+    ('\u{322D}', "\u{28}\u{6728}\u{29}"),
+    ('\u{322E}', "\u{28}\u{91D1}\u{29}"),
+    ('\u{322F}', "\u{28}\u{571F}\u{29}"),
+    ('\u{3230}', "\u{28}\u{65E5}\u{29}"),
+    ('\u{3231}', "\u{28}\u{682A}\u{29}"),
+    ('\u{3232}', "\u{28}\u{6709}\u{29}"),
+    ('\u{3233}', "\u{28}\u{793E}\u{29}"),
+    ('\u{3234}', "\u{28}\u{540D}\u{29}"),
+    ('\u{3235}', "\u{28}\u{7279}\u{29}"),
+    ('\u{3236}', "\u{28}\u{8CA1}\u{29}"),
+    ('\u{3237}', "\u{28}\u{795D}\u{29}"),
+    ('\u{3238}', "\u{28}\u{52B4}\u{29}"),
+    ('\u{3239}', "\u{28}\u{4EE3}\u{29}"),
+    ('\u{323A}', "\u{28}\u{547C}\u{29}"),
+    ('\u{323B}', "\u{28}\u{5B66}\u{29}"),
+    ('\u{323C}', "\u{28}\u{76E3}\u{29}"),
+    ('\u{323D}', "\u{28}\u{4F01}\u{29}"),
+    ('\u{323E}', "\u{28}\u{8CC7}\u{29}"),
+    ('\u{323F}', "\u{28}\u{5354}\u{29}"),
+    ('\u{3240}', "\u{28}\u{796D}\u{29}"),
+    ('\u{3241}', "\u{28}\u{4F11}\u{29}"),
+    ('\u{3242}', "\u{28}\u{81EA}\u{29}"),
+    ('\u{3243}', "\u{28}\u{81F3}\u{29}"),
+    ('\u{3244}', "\u{554F}"),
+    ('\u{3245}', "\u{5E7C}"),
+    ('\u{3246}', "\u{6587}"),
+    ('\u{3247}', "\u{7B8F}"),
+    ('\u{3250}', "\u{50}\u{54}\u{45}"),
+    ('\u{3251}', "\u{32}\u{31}"),
+    ('\u{3252}', "\u{32}\u{32}"),
+    ('\u{3253}', "\u{32}\u{33}"),
+    ('\u{3254}', "\u{32}\u{34}"),
+    ('\u{3255}', "\u{32}\u{35}"),
+    ('\u{3256}', "\u{32}\u{36}"),
+    ('\u{3257}', "\u{32}\u{37}"),
+    ('\u{3258}', "\u{32}\u{38}"),
+    ('\u{3259}', "\u{32}\u{39}"),
+    ('\u{325A}', "\u{33}\u{30}"),
+    ('\u{325B}', "\u{33}\u{31}"),
+    ('\u{325C}', "\u{33}\u{32}"),
+    ('\u{325D}', "\u{33}\u{33}"),
+    ('\u{325E}', "\u{33}\u{34}"),
+    ('\u{325F}', "\u{33}\u{35}"),
+    ('\u{3260}', "\u{1100}"),
+    ('\u{3261}', "\u{1102}"),
+    ('\u{3262}', "\u{1103}"),
+    ('\u{3263}', "\u{1105}"),
+    ('\u{3264}', "\u{1106}"),
+    ('\u{3265}', "\u{1107}"),
+    ('\u{3266}', "\u{1109}"),
+    ('\u{3267}', "\u{110B}"),
+    ('\u{3268}', "\u{110C}"),
+    ('\u{3269}', "\u{110E}"),
+    ('\u{326A}', "\u{110F}"),
+    ('\u{326B}', "\u{1110}"),
+    ('\u{326C}', "\u{1111}"),
+    ('\u{326D}', "\u{1112}"),
+    ('\u{326E}', "\u{1100}\u{1161}"),
+    ('\u{326F}', "\u{1102}\u{1161}"),
+    ('\u{3270}', "\u{1103}\u{1161}"),
+    ('\u{3271}', "\u{1105}\u{1161}"),
+    ('\u{3272}', "\u{1106}\u{1161}"),
+    ('\u{3273}', "\u{1107}\u{1161}"),
+    ('\u{3274}', "\u{1109}\u{1161}"),
+    ('\u{3275}', "\u{110B}\u{1161}"),
+    ('\u{3276}', "\u{110C}\u{1161}"),
+    ('\u{3277}', "\u{110E}\u{1161}"),
+    ('\u{3278}', "\u{110F}\u{1161}"),
+    ('\u{3279}', "\u{1110}\u{1161}"),
+    ('\u{327A}', "\u{1111}\u{1161}"),
+    ('\u{327B}', "\u{1112}\u{1161}"),
+    ('\u{327C}', "\u{110E}\u{1161}\u{11B7}\u{1100}\u{1169}"),
+    ('\u{327D}', "\u{110C}\u{116E}\u{110B}\u{1174}"),
+    ('\u{327E}', "\u{110B}\u{116E}"),
+    ('\u{3280}', "\u{4E00}"),
+    ('\u{3281}', "\u{4E8C}"),
+    ('\u{3282}', "\u{4E09}"),
+    ('\u{3283}', "\u{56DB}"),
+    ('\u{3284}', "\u{4E94}"),
+    ('\u{3285}', "\u{516D}"),
+    ('\u{3286}', "\u{4E03}"),
+    ('\u{3287}', "\u{516B}"),
+    ('\u{3288}', "\u{4E5D}"),
+    ('\u{3289}', "\u{5341}"),
+    ('\u{328A}', "\u{6708}"),
+    ('\u{328B}', "\u{706B}"),
+    ('\u{328C}', "\u{6C34}"),
+    ('\u{328D}', "\u{6728}"),
+    ('\u{328E}', "\u{91D1}"),
+    ('\u{328F}', "\u{571F}"),
+    ('\u{3290}', "\u{65E5}"),
+    ('\u{3291}', "\u{682A}"),
+    ('\u{3292}', "\u{6709}"),
+    ('\u{3293}', "\u{793E}"),
+    ('\u{3294}', "\u{540D}"),
+    ('\u{3295}', "\u{7279}"),
+    ('\u{3296}', "\u{8CA1}"),
+    ('\u{3297}', "\u{795D}"),
+    ('\u{3298}', "\u{52B4}"),
+    ('\u{3299}', "\u{79D8}"),
+    ('\u{329A}', "\u{7537}"),
+    ('\u{329B}', "\u{5973}"),
+    ('\u{329C}', "\u{9069}"),
+    ('\u{329D}', "\u{512A}"),
+    ('\u{329E}', "\u{5370}"),
+    ('\u{329F}', "\u{6CE8}"),
+    ('\u{32A0}', "\u{9805}"),
+    ('\u{32A1}', "\u{4F11}"),
+    ('\u{32A2}', "\u{5199}"),
+    ('\u{32A3}', "\u{6B63}"),
+    ('\u{32A4}', "\u{4E0A}"),
+    ('\u{32A5}', "\u{4E2D}"),
+    ('\u{32A6}', "\u{4E0B}"),
+    ('\u{32A7}', "\u{5DE6}"),
+    ('\u{32A8}', "\u{53F3}"),
+    ('\u{32A9}', "\u{533B}"),
+    ('\u{32AA}', "\u{5B97}"),
+    ('\u{32AB}', "\u{5B66}"),
+    ('\u{32AC}', "\u{76E3}"),
+    ('\u{32AD}', "\u{4F01}"),
+    ('\u{32AE}', "\u{8CC7}"),
+    ('\u{32AF}', "\u{5354}"),
+    ('\u{32B0}', "\u{591C}"),
+    ('\u{32B1}', "\u{33}\u{36}"),
+    ('\u{32B2}', "\u{33}\u{37}"),
+    ('\u{32B3}', "\u{33}\u{38}"),
+    ('\u{32B4}', "\u{33}\u{39}"),
+    ('\u{32B5}', "\u{34}\u{30}"),
+    ('\u{32B6}', "\u{34}\u{31}"),
+    ('\u{32B7}', "\u{34}\u{32}"),
+    ('\u{32B8}', "\u{34}\u{33}"),
+    ('\u{32B9}', "\u{34}\u{34}"),
+    ('\u{32BA}', "\u{34}\u{35}"),
+    ('\u{32BB}', "\u{34}\u{36}"),
+    ('\u{32BC}', "\u{34}\u{37}"),
+    ('\u{32BD}', "\u{34}\u{38}"),
+    ('\u{32BE}', "\u{34}\u{39}"),
+    ('\u{32BF}', "\u{35}\u{30}"),
+    ('\u{32C0}', "\u{31}\u{6708}"),
+    ('\u{32C1}', "\u{32}\u{6708}"),
+    ('\u{32C2}', "\u{33}\u{6708}"),
+    ('\u{32C3}', "\u{34}\u{6708}"),
+    ('\u{32C4}', "\u{35}\u{6708}"),
+    ('\u{32C5}', "\u{36}\u{6708}"),
+    ('\u{32C6}', "\u{37}\u{6708}"),
+    ('\u{32C7}', "\u{38}\u{6708}"),
+    ('\u{32C8}', "\u{39}\u{6708}"),
+    ('\u{32C9}', "\u{31}\u{30}\u{6708}"),
+    ('\u{32CA}', "\u{31}\u{31}\u{6708}"),
+    ('\u{32CB}', "\u{31}\u{32}\u{6708}"),
+    ('\u{32CC}', "\u{48}\u{67}"),
+    ('\u{32CD}', "\u{65}\u{72}\u{67}"),
+    ('\u{32CE}', "\u{65}\u{56}"),
+    ('\u{32CF}', "\u{4C}\u{54}\u{44}"),
+    ('\u{32D0}', "\u{30A2}"),
+    ('\u{32D1}', "\u{30A4}"),
+    ('\u{32D2}', "\u{30A6}"),
+    ('\u{32D3}', "\u{30A8}"),
+    ('\u{32D4}', "\u{30AA}"),
+    ('\u{32D5}', "\u{30AB}"),
+    ('\u{32D6}', "\u{30AD}"),
+    ('\u{32D7}', "\u{30AF}"),
+    ('\u{32D8}', "\u{30B1}"),
+    ('\u{32D9}', "\u{30B3}"),
+    ('\u{32DA}', "\u{30B5}"),
+    ('\u{32DB}', "\u{30B7}"),
+    ('\u{32DC}', "\u{30B9}"),
+    ('\u{32DD}', "\u{30BB}"),
+    ('\u{32DE}', "\u{30BD}"),
+    ('\u{32DF}', "\u{30BF}"),
+    ('\u{32E0}', "\u{30C1}"),
+    ('\u{32E1}', "\u{30C4}"),
+    ('\u{32E2}', "\u{30C6}"),
+    ('\u{32E3}', "\u{30C8}"),
+    ('\u{32E4}', "\u{30CA}"),
+    ('\u{32E5}', "\u{30CB}"),
+    ('\u{32E6}', "\u{30CC}"),
+    ('\u{32E7}', "\u{30CD}"),
+    ('\u{32E8}', "\u{30CE}"),
+    ('\u{32E9}', "\u{30CF}"),
+    ('\u{32EA}', "\u{30D2}"),
+    ('\u{32EB}', "\u{30D5}"),
+    ('\u{32EC}', "\u{30D8}"),
+    ('\u{32ED}', "\u{30DB}"),
+    ('\u{32EE}', "\u{30DE}"),
+    ('\u{32EF}', "\u{30DF}"),
+    ('\u{32F0}', "\u{30E0}"),
+    ('\u{32F1}', "\u{30E1}"),
+    ('\u{32F2}', "\u{30E2}"),
+    ('\u{32F3}', "\u{30E4}"),
+    ('\u{32F4}', "\u{30E6}"),
+    ('\u{32F5}', "\u{30E8}"),
+    ('\u{32F6}', "\u{30E9}"),
+    ('\u{32F7}', "\u{30EA}"),
+    ('\u{32F8}', "\u{30EB}"),
+    ('\u{32F9}', "\u{30EC}"),
+    ('\u{32FA}', "\u{30ED}"),
+    ('\u{32FB}', "\u{30EF}"),
+    ('\u{32FC}', "\u{30F0}"),
+    ('\u{32FD}', "\u{30F1}"),
+    ('\u{32FE}', "\u{30F2}"),
+    ('\u{32FF}', "\u{4EE4}\u{548C}"),
+    ('\u{3300}', "\u{30A2}\u{30CF}\u{309A}\u{30FC}\u{30C8}"),
+    ('\u{3301}', "\u{30A2}\u{30EB}\u{30D5}\u{30A1}"),
+    ('\u{3302}', "\u{30A2}\u{30F3}\u{30D8}\u{309A}\u{30A2}"),
+    ('\u{3303}', "\u{30A2}\u{30FC}\u{30EB}"),
+    ('\u{3304}', "\u{30A4}\u{30CB}\u{30F3}\u{30AF}\u{3099}"),
+    ('\u{3305}', "\u{30A4}\u{30F3}\u{30C1}"),
+    ('\u{3306}', "\u{30A6}\u{30A9}\u{30F3}"),
+    ('\u{3307}', "\u{30A8}\u{30B9}\u{30AF}\u{30FC}\u{30C8}\u{3099}"),
+    ('\u{3308}', "\u{30A8}\u{30FC}\u{30AB}\u{30FC}"),
+    ('\u{3309}', "\u{30AA}\u{30F3}\u{30B9}"),
+    ('\u{330A}', "\u{30AA}\u{30FC}\u{30E0}"),
+    ('\u{330B}', "\u{30AB}\u{30A4}\u{30EA}"),
+    ('\u{330C}', "\u{30AB}\u{30E9}\u{30C3}\u{30C8}"),
+    ('\u{330D}', "\u{30AB}\u{30ED}\u{30EA}\u{30FC}"),
+    ('\u{330E}', "\u{30AB}\u{3099}\u{30ED}\u{30F3}"),
+    ('\u{330F}', "\u{30AB}\u{3099}\u{30F3}\u{30DE}"),
+    ('\u{3310}', "\u{30AD}\u{3099}\u{30AB}\u{3099}"),
+    ('\u{3311}', "\u{30AD}\u{3099}\u{30CB}\u{30FC}"),
+    ('\u{3312}', "\u{30AD}\u{30E5}\u{30EA}\u{30FC}"),
+    ('\u{3313}', "\u{30AD}\u{3099}\u{30EB}\u{30BF}\u{3099}\u{30FC}"),
+    ('\u{3314}', "\u{30AD}\u{30ED}"),
+    ('\u{3315}', "\u{30AD}\u{30ED}\u{30AF}\u{3099}\u{30E9}\u{30E0}"),
+    ('\u{3316}', "\u{30AD}\u{30ED}\u{30E1}\u{30FC}\u{30C8}\u{30EB}"),
+    ('\u{3317}', "\u{30AD}\u{30ED}\u{30EF}\u{30C3}\u{30C8}"),
+    ('\u{3318}', "\u{30AF}\u{3099}\u{30E9}\u{30E0}"),
+    ('\u{3319}', "\u{30AF}\u{3099}\u{30E9}\u{30E0}\u{30C8}\u{30F3}"),
+    ('\u{331A}', "\u{30AF}\u{30EB}\u{30BB}\u{3099}\u{30A4}\u{30ED}"),
+    ('\u{331B}', "\u{30AF}\u{30ED}\u{30FC}\u{30CD}"),
+    ('\u{331C}', "\u{30B1}\u{30FC}\u{30B9}"),
+    ('\u{331D}', "\u{30B3}\u{30EB}\u{30CA}"),
+    ('\u{331E}', "\u{30B3}\u{30FC}\u{30DB}\u{309A}"),
+    ('\u{331F}', "\u{30B5}\u{30A4}\u{30AF}\u{30EB}"),
+    ('\u{3320}', "\u{30B5}\u{30F3}\u{30C1}\u{30FC}\u{30E0}"),
+    ('\u{3321}', "\u{30B7}\u{30EA}\u{30F3}\u{30AF}\u{3099}"),
+    ('\u{3322}', "\u{30BB}\u{30F3}\u{30C1}"),
+    ('\u{3323}', "\u{30BB}\u{30F3}\u{30C8}"),
+    ('\u{3324}', "\u{30BF}\u{3099}\u{30FC}\u{30B9}"),
+    ('\u{3325}', "\u{30C6}\u{3099}\u{30B7}"),
+    ('\u{3326}', "\u{30C8}\u{3099}\u{30EB}"),
+    ('\u{3327}', "\u{30C8}\u{30F3}"),
+    ('\u{3328}', "\u{30CA}\u{30CE}"),
+    ('\u{3329}', "\u{30CE}\u{30C3}\u{30C8}"),
+    ('\u{332A}', "\u{30CF}\u{30A4}\u{30C4}"),
+    ('\u{332B}', "\u{30CF}\u{309A}\u{30FC}\u{30BB}\u{30F3}\u{30C8}"),
+    ('\u{332C}', "\u{30CF}\u{309A}\u{30FC}\u{30C4}"),
+    ('\u{332D}', "\u{30CF}\u{3099}\u{30FC}\u{30EC}\u{30EB}"),
+    ('\u{332E}', "\u{30D2}\u{309A}\u{30A2}\u{30B9}\u{30C8}\u{30EB}"),
+    ('\u{332F}', "\u{30D2}\u{309A}\u{30AF}\u{30EB}"),
+    ('\u{3330}', "\u{30D2}\u{309A}\u{30B3}"),
+    ('\u{3331}', "\u{30D2}\u{3099}\u{30EB}"),
+    ('\u{3332}', "\u{30D5}\u{30A1}\u{30E9}\u{30C3}\u{30C8}\u{3099}"),
+    ('\u{3333}', "\u{30D5}\u{30A3}\u{30FC}\u{30C8}"),
+    ('\u{3334}', "\u{30D5}\u{3099}\u{30C3}\u{30B7}\u{30A7}\u{30EB}"),
+    ('\u{3335}', "\u{30D5}\u{30E9}\u{30F3}"),
+    ('\u{3336}', "\u{30D8}\u{30AF}\u{30BF}\u{30FC}\u{30EB}"),
+    ('\u{3337}', "\u{30D8}\u{309A}\u{30BD}"),
+    ('\u{3338}', "\u{30D8}\u{309A}\u{30CB}\u{30D2}"),
+    ('\u{3339}', "\u{30D8}\u{30EB}\u{30C4}"),
+    ('\u{333A}', "\u{30D8}\u{309A}\u{30F3}\u{30B9}"),
+    ('\u{333B}', "\u{30D8}\u{309A}\u{30FC}\u{30B7}\u{3099}"),
+    ('\u{333C}', "\u{30D8}\u{3099}\u{30FC}\u{30BF}"),
+    ('\u{333D}', "\u{30DB}\u{309A}\u{30A4}\u{30F3}\u{30C8}"),
+    ('\u{333E}', "\u{30DB}\u{3099}\u{30EB}\u{30C8}"),
+    ('\u{333F}', "\u{30DB}\u{30F3}"),
+    ('\u{3340}', "\u{30DB}\u{309A}\u{30F3}\u{30C8}\u{3099}"),
+    ('\u{3341}', "\u{30DB}\u{30FC}\u{30EB}"),
+    ('\u{3342}', "\u{30DB}\u{30FC}\u{30F3}"),
+    ('\u{3343}', "\u{30DE}\u{30A4}\u{30AF}\u{30ED}"),
+    ('\u{3344}', "\u{30DE}\u{30A4}\u{30EB}"),
+    ('\u{3345}', "\u{30DE}\u{30C3}\u{30CF}"),
+    ('\u{3346}', "\u{30DE}\u{30EB}\u{30AF}"),
+    ('\u{3347}', "\u{30DE}\u{30F3}\u{30B7}\u{30E7}\u{30F3}"),
+    ('\u{3348}', "\u{30DF}\u{30AF}\u{30ED}\u{30F3}"),
+    ('\u{3349}', "\u{30DF}\u{30EA}"),
+    ('\u{334A}', "\u{30DF}\u{30EA}\u{30CF}\u{3099}\u{30FC}\u{30EB}"),
+    ('\u{334B}', "\u{30E1}\u{30AB}\u{3099}"),
+    ('\u{334C}', "\u{30E1}\u{30AB}\u{3099}\u{30C8}\u{30F3}"),
+    ('\u{334D}', "\u{30E1}\u{30FC}\u{30C8}\u{30EB}"),
+    ('\u{334E}', "\u{30E4}\u{30FC}\u{30C8}\u{3099}"),
+    ('\u{334F}', "\u{30E4}\u{30FC}\u{30EB}"),
+    ('\u{3350}', "\u{30E6}\u{30A2}\u{30F3}"),
+    ('\u{3351}', "\u{30EA}\u{30C3}\u{30C8}\u{30EB}"),
+    ('\u{3352}', "\u{30EA}\u{30E9}"),
+    ('\u{3353}', "\u{30EB}\u{30D2}\u{309A}\u{30FC}"),
+    ('\u{3354}', "\u{30EB}\u{30FC}\u{30D5}\u{3099}\u{30EB}"),
+    ('\u{3355}', "\u{30EC}\u{30E0}"),
+    ('\u{3356}', "\u{30EC}\u{30F3}\u{30C8}\u{30B1}\u{3099}\u{30F3}"),
+    ('\u{3357}', "\u{30EF}\u{30C3}\u{30C8}"),
+    ('\u{3358}', "\u{30}\u{70B9}"),
+    ('\u{3359}', "\u{31}\u{70B9}"),
+    ('\u{335A}', "\u{32}\u{70B9}"),
+    ('\u{335B}', "\u{33}\u{70B9}"),
+    ('\u{335C}', "\u{34}\u{70B9}"),
+    ('\u{335D}', "\u{35}\u{70B9}"),
+    ('\u{335E}', "\u{36}\u{70B9}"),
+    ('\u{335F}', "\u{37}\u{70B9}"),
+    ('\u{3360}', "\u{38}\u{70B9}"),
+    ('\u{3361}', "\u{39}\u{70B9}"),
+    ('\u{3362}', "\u{31}\u{30}\u{70B9}"),
+    ('\u{3363}', "\u{31}\u{31}\u{70B9}"),
+    ('\u{3364}', "\u{31}\u{32}\u{70B9}"),
+    ('\u{3365}', "\u{31}\u{33}\u{70B9}"),
+    ('\u{3366}', "\u{31}\u{34}\u{70B9}"),
+    ('\u{3367}', "\u{31}\u{35}\u{70B9}"),
+    ('\u{3368}', "\u{31}\u{36}\u{70B9}"),
+    ('\u{3369}', "\u{31}\u{37}\u{70B9}"),
+    ('\u{336A}', "\u{31}\u{38}\u{70B9}"),
+    ('\u{336B}', "\u{31}\u{39}\u{70B9}"),
+    ('\u{336C}', "\u{32}\u{30}\u{70B9}"),
+    ('\u{336D}', "\u{32}\u{31}\u{70B9}"),
+    ('\u{336E}', "\u{32}\u{32}\u{70B9}"),
+    ('\u{336F}', "\u{32}\u{33}\u{70B9}"),
+    ('\u{3370}', "\u{32}\u{34}\u{70B9}"),
+    ('\u{3371}', "\u{68}\u{50}\u{61}"),
+    ('\u{3372}', "\u{64}\u{61}"),
+    ('\u{3373}', "\u{41}\u{55}"),
+    ('\u{3374}', "\u{62}\u{61}\u{72}"),
+    ('\u{3375}', "\u{6F}\u{56}"),
+    ('\u{3376}', "\u{70}\u{63}"),
+    ('\u{3377}', "\u{64}\u{6D}"),
+    ('\u{3378}', "\u{64}\u{6D}\u{32}"),
+    ('\u{3379}', "\u{64}\u{6D}\u{33}"),
+    ('\u{337A}', "\u{49}\u{55}"),
+    ('\u{337B}', "\u{5E73}\u{6210}"),
+    ('\u{337C}', "\u{662D}\u{548C}"),
+    ('\u{337D}', "\u{5927}\u{6B63}"),
+    ('\u{337E}', "\u{660E}\u{6CBB}"),
+    ('\u{337F}', "\u{682A}\u{5F0F}\u{4F1A}\u{793E}"),
+    ('\u{3380}', "\u{70}\u{41}"),
+    ('\u{3381}', "\u{6E}\u{41}"),
+    ('\u{3382}', "\u{3BC}\u{41}"),
+    ('\u{3383}', "\u{6D}\u{41}"),
+    ('\u{3384}', "\u{6B}\u{41}"),
+    ('\u{3385}', "\u{4B}\u{42}"),
+    ('\u{3386}', "\u{4D}\u{42}"),
+    ('\u{3387}', "\u{47}\u{42}"),
+    ('\u{3388}', "\u{63}\u{61}\u{6C}"),
+    ('\u{3389}', "\u{6B}\u{63}\u{61}\u{6C}"),
+    ('\u{338A}', "\u{70}\u{46}"),
+    ('\u{338B}', "\u{6E}\u{46}"),
+    ('\u{338C}', "\u{3BC}\u{46}"),
+    ('\u{338D}', "\u{3BC}\u{67}"),
+    ('\u{338E}', "\u{6D}\u{67}"),
+    ('\u{338F}', "\u{6B}\u{67}"),
+    ('\u{3390}', "\u{48}\u{7A}"),
+    ('\u{3391}', "\u{6B}\u{48}\u{7A}"),
+    ('\u{3392}', "\u{4D}\u{48}\u{7A}"),
+    ('\u{3393}', "\u{47}\u{48}\u{7A}"),
+    ('\u{3394}', "\u{54}\u{48}\u{7A}"),
+    ('\u{3395}', "\u{3BC}\u{6C}"),
+    ('\u{3396}', "\u{6D}\u{6C}"),
+    ('\u{3397}', "\u{64}\u{6C}"),
+    ('\u{3398}', "\u{6B}\u{6C}"),
+    ('\u{3399}', "\u{66}\u{6D}"),
+    ('\u{339A}', "\u{6E}\u{6D}"),
+    ('\u{339B}', "\u{3BC}\u{6D}"),
+    ('\u{339C}', "\u{6D}\u{6D}"),
+    ('\u{339D}', "\u{63}\u{6D}"),
+    ('\u{339E}', "\u{6B}\u{6D}"),
+    ('\u{339F}', "\u{6D}\u{6D}\u{32}"),
+    ('\u{33A0}', "\u{63}\u{6D}\u{32}"),
+    ('\u{33A1}', "\u{6D}\u{32}"),
+    ('\u{33A2}', "\u{6B}\u{6D}\u{32}"),
+    ('\u{33A3}', "\u{6D}\u{6D}\u{33}"),
+    ('\u{33A4}', "\u{63}\u{6D}\u{33}"),
+    ('\u{33A5}', "\u{6D}\u{33}"),
+    ('\u{33A6}', "\u{6B}\u{6D}\u{33}"),
+    ('\u{33A7}', "\u{6D}\u{2215}\u{73}"),
+    ('\u{33A8}', "\u{6D}\u{2215}\u{73}\u{32}"),
+    ('\u{33A9}', "\u{50}\u{61}"),
+    ('\u{33AA}', "\u{6B}\u{50}\u{61}"),
+    ('\u{33AB}', "\u{4D}\u{50}\u{61}"),
+    ('\u{33AC}', "\u{47}\u{50}\u{61}"),
+    ('\u{33AD}', "\u{72}\u{61}\u{64}"),
+    ('\u{33AE}', "\u{72}\u{61}\u{64}\u{2215}\u{73}"),
+    ('\u{33AF}', "\u{72}\u{61}\u{64}\u{2215}\u{73}\u{32}"),
+    ('\u{33B0}', "\u{70}\u{73}"),
+    ('\u{33B1}', "\u{6E}\u{73}"),
+    ('\u{33B2}', "\u{3BC}\u{73}"),
+    ('\u{33B3}', "\u{6D}\u{73}"),
+    ('\u{33B4}', "\u{70}\u{56}"),
+    ('\u{33B5}', "\u{6E}\u{56}"),
+    ('\u{33B6}', "\u{3BC}\u{56}"),
+    ('\u{33B7}', "\u{6D}\u{56}"),
+    ('\u{33B8}', "\u{6B}\u{56}"),
+    ('\u{33B9}', "\u{4D}\u{56}"),
+    ('\u{33BA}', "\u{70}\u{57}"),
+    ('\u{33BB}', "\u{6E}\u{57}"),
+    ('\u{33BC}', "\u{3BC}\u{57}"),
+    ('\u{33BD}', "\u{6D}\u{57}"),
+    ('\u{33BE}', "\u{6B}\u{57}"),
+    ('\u{33BF}', "\u{4D}\u{57}"),
+    ('\u{33C0}', "\u{6B}\u{3A9}"),
+    ('\u{33C1}', "\u{4D}\u{3A9}"),
+    ('\u{33C2}', "\u{61}\u{2E}\u{6D}\u{2E}"),
+    ('\u{33C3}', "\u{42}\u{71}"),
+    ('\u{33C4}', "\u{63}\u{63}"),
+    ('\u{33C5}', "\u{63}\u{64}"),
+    ('\u{33C6}', "\u{43}\u{2215}\u{6B}\u{67}"),
+    ('\u{33C7}', "\u{43}\u{6F}\u{2E}"),
+    ('\u{33C8}', "\u{64}\u{42}"),
+    ('\u{33C9}', "\u{47}\u{79}"),
+    ('\u{33CA}', "\u{68}\u{61}"),
+    ('\u{33CB}', "\u{48}\u{50}"),
+    ('\u{33CC}', "\u{69}\u{6E}"),
+    ('\u{33CD}', "\u{4B}\u{4B}"),
+    ('\u{33CE}', "\u{4B}\u{4D}"),
+    ('\u{33CF}', "\u{6B}\u{74}"),
+    ('\u{33D0}', "\u{6C}\u{6D}"),
+    ('\u{33D1}', "\u{6C}\u{6E}"),
+    ('\u{33D2}', "\u{6C}\u{6F}\u{67}"),
+    ('\u{33D3}', "\u{6C}\u{78}"),
+    ('\u{33D4}', "\u{6D}\u{62}"),
+    ('\u{33D5}', "\u{6D}\u{69}\u{6C}"),
+    ('\u{33D6}', "\u{6D}\u{6F}\u{6C}"),
+    ('\u{33D7}', "\u{50}\u{48}"),
+    ('\u{33D8}', "\u{70}\u{2E}\u{6D}\u{2E}"),
+    ('\u{33D9}', "\u{50}\u{50}\u{4D}"),
+    ('\u{33DA}', "\u{50}\u{52}"),
+    ('\u{33DB}', "\u{73}\u{72}"),
+    ('\u{33DC}', "\u{53}\u{76}"),
+    ('\u{33DD}', "\u{57}\u{62}"),
+    ('\u{33DE}', "\u{56}\u{2215}\u{6D}"),
+    ('\u{33DF}', "\u{41}\u{2215}\u{6D}"),
+    ('\u{33E0}', "\u{31}\u{65E5}"),
+    ('\u{33E1}', "\u{32}\u{65E5}"),
+    ('\u{33E2}', "\u{33}\u{65E5}"),
+    ('\u{33E3}', "\u{34}\u{65E5}"),
+    ('\u{33E4}', "\u{35}\u{65E5}"),
+    ('\u{33E5}', "\u{36}\u{65E5}"),
+    ('\u{33E6}', "\u{37}\u{65E5}"),
+    ('\u{33E7}', "\u{38}\u{65E5}"),
+    ('\u{33E8}', "\u{39}\u{65E5}"),
+    ('\u{33E9}', "\u{31}\u{30}\u{65E5}"),
+    ('\u{33EA}', "\u{31}\u{31}\u{65E5}"),
+    ('\u{33EB}', "\u{31}\u{32}\u{65E5}"),
+    ('\u{33EC}', "\u{31}\u{33}\u{65E5}"),
+    ('\u{33ED}', "\u{31}\u{34}\u{65E5}"),
+    ('\u{33EE}', "\u{31}\u{35}\u{65E5}"),
+    ('\u{33EF}', "\u{31}\u{36}\u{65E5}"),
+    ('\u{33F0}', "\u{31}\u{37}\u{65E5}"),
+    ('\u{33F1}', "\u{31}\u{38}\u{65E5}"),
+    ('\u{33F2}', "\u{31}\u{39}\u{65E5}"),
+    ('\u{33F3}', "\u{32}\u{30}\u{65E5}"),
+    ('\u{33F4}', "\u{32}\u{31}\u{65E5}"),
+    ('\u{33F5}', "\u{32}\u{32}\u{65E5}"),
+    ('\u{33F6}', "\u{32}\u{33}\u{65E5}"),
+    ('\u{33F7}', "\u{32}\u{34}\u{65E5}"),
+    ('\u{33F8}', "\u{32}\u{35}\u{65E5}"),
+    ('\u{33F9}', "\u{32}\u{36}\u{65E5}"),
+    ('\u{33FA}', "\u{32}\u{37}\u{65E5}"),
+    ('\u{33FB}', "\u{32}\u{38}\u{65E5}"),
+    ('\u{33FC}', "\u{32}\u{39}\u{65E5}"),
+    ('\u{33FD}', "\u{33}\u{30}\u{65E5}"),
+    ('\u{33FE}', "\u{33}\u{31}\u{65E5}"),
+    ('\u{33FF}', "\u{67}\u{61}\u{6C}"),
+    ('\u{A69C}', "\u{44A}"),
+    ('\u{A69D}', "\u{44C}"),
+    ('\u{A770}', "\u{A76F}"),
+    ('\u{A7F2}', "\u{43}"),
+    ('\u{A7F3}', "\u{46}"),
+    ('\u{A7F4}', "\u{51}"),
+    ('\u{A7F8}', "\u{126}"),
+    ('\u{A7F9}', "\u{153}"),
+    ('\u{AB5C}', "\u{A727}"),
+    ('\u{AB5D}', "\u{AB37}"),
+    ('\u{AB5E}', "\u{26B}"),
+    ('\u{AB5F}', "\u{AB52}"),
+    ('\u{AB69}', "\u{28D}"),
+    ('\u{FB00}', "\u{66}\u{66}"),
+    ('\u{FB01}', "\u{66}\u{69}"),
+    ('\u{FB02}', "\u{66}\u{6C}"),
+    ('\u{FB03}', "\u{66}\u{66}\u{69}"),
+    ('\u{FB04}', "\u{66}\u{66}\u{6C}"),
+    ('\u{FB05}', "\u{73}\u{74}"),
+    ('\u{FB06}', "\u{73}\u{74}"),
+    ('\u{FB13}', "\u{574}\u{576}"),
+    ('\u{FB14}', "\u{574}\u{565}"),
+    ('\u{FB15}', "\u{574}\u{56B}"),
+    ('\u{FB16}', "\u{57E}\u{576}"),
+    ('\u{FB17}', "\u{574}\u{56D}"),
+    ('\u{FB20}', "\u{5E2}"),
+    ('\u{FB21}', "\u{5D0}"),
+    ('\u{FB22}', "\u{5D3}"),
+    ('\u{FB23}', "\u{5D4}"),
+    ('\u{FB24}', "\u{5DB}"),
+    ('\u{FB25}', "\u{5DC}"),
+    ('\u{FB26}', "\u{5DD}"),
+    ('\u{FB27}', "\u{5E8}"),
+    ('\u{FB28}', "\u{5EA}"),
+    ('\u{FB29}', "\u{2B}"),
+    ('\u{FB4F}', "\u{5D0}\u{5DC}"),
+    ('\u{FB50}', "\u{671}"),
+    ('\u{FB51}', "\u{671}"),
+    ('\u{FB52}', "\u{67B}"),
+    ('\u{FB53}', "\u{67B}"),
+    ('\u{FB54}', "\u{67B}"),
+    ('\u{FB55}', "\u{67B}"),
+    ('\u{FB56}', "\u{67E}"),
+    ('\u{FB57}', "\u{67E}"),
+    ('\u{FB58}', "\u{67E}"),
+    ('\u{FB59}', "\u{67E}"),
+    ('\u{FB5A}', "\u{680}"),
+    ('\u{FB5B}', "\u{680}"),
+    ('\u{FB5C}', "\u{680}"),
+    ('\u{FB5D}', "\u{680}"),
+    ('\u{FB5E}', "\u{67A}"),
+    ('\u{FB5F}', "\u{67A}"),
+    ('\u{FB60}', "\u{67A}"),
+    ('\u{FB61}', "\u{67A}"),
+    ('\u{FB62}', "\u{67F}"),
+    ('\u{FB63}', "\u{67F}"),
+    ('\u{FB64}', "\u{67F}"),
+    ('\u{FB65}', "\u{67F}"),
+    ('\u{FB66}', "\u{679}"),
+    ('\u{FB67}', "\u{679}"),
+    ('\u{FB68}', "\u{679}"),
+    ('\u{FB69}', "\u{679}"),
+    ('\u{FB6A}', "\u{6A4}"),
+    ('\u{FB6B}', "\u{6A4}"),
+    ('\u{FB6C}', "\u{6A4}"),
+    ('\u{FB6D}', "\u{6A4}"),
+    ('\u{FB6E}', "\u{6A6}"),
+    ('\u{FB6F}', "\u{6A6}"),
+    ('\u{FB70}', "\u{6A6}"),
+    ('\u{FB71}', "\u{6A6}"),
+    ('\u{FB72}', "\u{684}"),
+    ('\u{FB73}', "\u{684}"),
+    ('\u{FB74}', "\u{684}"),
+    ('\u{FB75}', "\u{684}"),
+    ('\u{FB76}', "\u{683}"),
+    ('\u{FB77}', "\u{683}"),
+    ('\u{FB78}', "\u{683}"),
+    ('\u{FB79}', "\u{683}"),
+    ('\u{FB7A}', "\u{686}"),
+    ('\u{FB7B}', "\u{686}"),
+    ('\u{FB7C}', "\u{686}"),
+    ('\u{FB7D}', "\u{686}"),
+    ('\u{FB7E}', "\u{687}"),
+    ('\u{FB7F}', "\u{687}"),
+    ('\u{FB80}', "\u{687}"),
+    ('\u{FB81}', "\u{687}"),
+    ('\u{FB82}', "\u{68D}"),
+    ('\u{FB83}', "\u{68D}"),
+    ('\u{FB84}', "\u{68C}"),
+    ('\u{FB85}', "\u{68C}"),
+    ('\u{FB86}', "\u{68E}"),
+    ('\u{FB87}', "\u{68E}"),
+    ('\u{FB88}', "\u{688}"),
+    ('\u{FB89}', "\u{688}"),
+    ('\u{FB8A}', "\u{698}"),
+    ('\u{FB8B}', "\u{698}"),
+    ('\u{FB8C}', "\u{691}"),
+    ('\u{FB8D}', "\u{691}"),
+    ('\u{FB8E}', "\u{6A9}"),
+    ('\u{FB8F}', "\u{6A9}"),
+    ('\u{FB90}', "\u{6A9}"),
+    ('\u{FB91}', "\u{6A9}"),
+    ('\u{FB92}', "\u{6AF}"),
+    ('\u{FB93}', "\u{6AF}"),
+    ('\u{FB94}', "\u{6AF}"),
+    ('\u{FB95}', "\u{6AF}"),
+    ('\u{FB96}', "\u{6B3}"),
+    ('\u{FB97}', "\u{6B3}"),
+    ('\u{FB98}', "\u{6B3}"),
+    ('\u{FB99}', "\u{6B3}"),
+    ('\u{FB9A}', "\u{6B1}"),
+    ('\u{FB9B}', "\u{6B1}"),
+    ('\u{FB9C}', "\u{6B1}"),
+    ('\u{FB9D}', "\u{6B1}"),
+    ('\u{FB9E}', "\u{6BA}"),
+    ('\u{FB9F}', "\u{6BA}"),
+    ('\u{FBA0}', "\u{6BB}"),
+    ('\u{FBA1}', "\u{6BB}"),
+    ('\u{FBA2}', "\u{6BB}"),
+    ('\u{FBA3}', "\u{6BB}"),
+    ('\u{FBA4}', "\u{6D5}\u{654}"),
+    ('\u{FBA5}', "\u{6D5}\u{654}"),
+    ('\u{FBA6}', "\u{6C1}"),
+    ('\u{FBA7}', "\u{6C1}"),
+    ('\u{FBA8}', "\u{6C1}"),
+    ('\u{FBA9}', "\u{6C1}"),
+    ('\u{FBAA}', "\u{6BE}"),
+    ('\u{FBAB}', "\u{6BE}"),
+    ('\u{FBAC}', "\u{6BE}"),
+    ('\u{FBAD}', "\u{6BE}"),
+    ('\u{FBAE}', "\u{6D2}"),
+    ('\u{FBAF}', "\u{6D2}"),
+    ('\u{FBB0}', "\u{6D2}\u{654}"),
+    ('\u{FBB1}', "\u{6D2}\u{654}"),
+    ('\u{FBD3}', "\u{6AD}"),
+    ('\u{FBD4}', "\u{6AD}"),
+    ('\u{FBD5}', "\u{6AD}"),
+    ('\u{FBD6}', "\u{6AD}"),
+    ('\u{FBD7}', "\u{6C7}"),
+    ('\u{FBD8}', "\u{6C7}"),
+    ('\u{FBD9}', "\u{6C6}"),
+    ('\u{FBDA}', "\u{6C6}"),
+    ('\u{FBDB}', "\u{6C8}"),
+    ('\u{FBDC}', "\u{6C8}"),
+    ('\u{FBDD}', "\u{6C7}\u{674}"),
+    ('\u{FBDE}', "\u{6CB}"),
+    ('\u{FBDF}', "\u{6CB}"),
+    ('\u{FBE0}', "\u{6C5}"),
+    ('\u{FBE1}', "\u{6C5}"),
+    ('\u{FBE2}', "\u{6C9}"),
+    ('\u{FBE3}', "\u{6C9}"),
+    ('\u{FBE4}', "\u{6D0}"),
+    ('\u{FBE5}', "\u{6D0}"),
+    ('\u{FBE6}', "\u{6D0}"),
+    ('\u{FBE7}', "\u{6D0}"),
+    ('\u{FBE8}', "\u{649}"),
+    ('\u{FBE9}', "\u{649}"),
+    ('\u{FBEA}', "\u{64A}\u{654}\u{627}"),
+    ('\u{FBEB}', "\u{64A}\u{654}\u{627}"),
+    ('\u{FBEC}', "\u{64A}\u{654}\u{6D5}"),
+    ('\u{FBED}', "\u{64A}\u{654}\u{6D5}"),
+    ('\u{FBEE}', "\u{64A}\u{654}\u{648}"),
+    ('\u{FBEF}', "\u{64A}\u{654}\u{648}"),
+    ('\u{FBF0}', "\u{64A}\u{654}\u{6C7}"),
+    ('\u{FBF1}', "\u{64A}\u{654}\u{6C7}"),
+    ('\u{FBF2}', "\u{64A}\u{654}\u{6C6}"),
+    ('\u{FBF3}', "\u{64A}\u{654}\u{6C6}"),
+    ('\u{FBF4}', "\u{64A}\u{654}\u{6C8}"),
+    ('\u{FBF5}', "\u{64A}\u{654}\u{6C8}"),
+    ('\u{FBF6}', "\u{64A}\u{654}\u{6D0}"),
+    ('\u{FBF7}', "\u{64A}\u{654}\u{6D0}"),
+    ('\u{FBF8}', "\u{64A}\u{654}\u{6D0}"),
+    ('\u{FBF9}', "\u{64A}\u{654}\u{649}"),
+    ('\u{FBFA}', "\u{64A}\u{654}\u{649}"),
+    ('\u{FBFB}', "\u{64A}\u{654}\u{649}"),
+    ('\u{FBFC}', "\u{6CC}"),
+    ('\u{FBFD}', "\u{6CC}"),
+    ('\u{FBFE}', "\u{6CC}"),
+    ('\u{FBFF}', "\u{6CC}"),
+    ('\u{FC00}', "\u{64A}\u{654}\u{62C}"),
+    ('\u{FC01}', "\u{64A}\u{654}\u{62D}"),
+    ('\u{FC02}', "\u{64A}\u{654}\u{645}"),
+    ('\u{FC03}', "\u{64A}\u{654}\u{649}"),
+    ('\u{FC04}', "\u{64A}\u{654}\u{64A}"),
+    ('\u{FC05}', "\u{628}\u{62C}"),
+    ('\u{FC06}', "\u{628}\u{62D}"),
+    ('\u{FC07}', "\u{628}\u{62E}"),
+    ('\u{FC08}', "\u{628}\u{645}"),
+    ('\u{FC09}', "\u{628}\u{649}"),
+    ('\u{FC0A}', "\u{628}\u{64A}"),
+    ('\u{FC0B}', "\u{62A}\u{62C}"),
+    ('\u{FC0C}', "\u{62A}\u{62D}"),
+    ('\u{FC0D}', "\u{62A}\u{62E}"),
+    ('\u{FC0E}', "\u{62A}\u{645}"),
+    ('\u{FC0F}', "\u{62A}\u{649}"),
+    ('\u{FC10}', "\u{62A}\u{64A}"),
+    ('\u{FC11}', "\u{62B}\u{62C}"),
+    ('\u{FC12}', "\u{62B}\u{645}"),
+    ('\u{FC13}', "\u{62B}\u{649}"),
+    ('\u{FC14}', "\u{62B}\u{64A}"),
+    ('\u{FC15}', "\u{62C}\u{62D}"),
+    ('\u{FC16}', "\u{62C}\u{645}"),
+    ('\u{FC17}', "\u{62D}\u{62C}"),
+    ('\u{FC18}', "\u{62D}\u{645}"),
+    ('\u{FC19}', "\u{62E}\u{62C}"),
+    ('\u{FC1A}', "\u{62E}\u{62D}"),
+    ('\u{FC1B}', "\u{62E}\u{645}"),
+    ('\u{FC1C}', "\u{633}\u{62C}"),
+    ('\u{FC1D}', "\u{633}\u{62D}"),
+    ('\u{FC1E}', "\u{633}\u{62E}"),
+    ('\u{FC1F}', "\u{633}\u{645}"),
+    ('\u{FC20}', "\u{635}\u{62D}"),
+    ('\u{FC21}', "\u{635}\u{645}"),
+    ('\u{FC22}', "\u{636}\u{62C}"),
+    ('\u{FC23}', "\u{636}\u{62D}"),
+    ('\u{FC24}', "\u{636}\u{62E}"),
+    ('\u{FC25}', "\u{636}\u{645}"),
+    ('\u{FC26}', "\u{637}\u{62D}"),
+    ('\u{FC27}', "\u{637}\u{645}"),
+    ('\u{FC28}', "\u{638}\u{645}"),
+    ('\u{FC29}', "\u{639}\u{62C}"),
+    ('\u{FC2A}', "\u{639}\u{645}"),
+    ('\u{FC2B}', "\u{63A}\u{62C}"),
+    ('\u{FC2C}', "\u{63A}\u{645}"),
+    ('\u{FC2D}', "\u{641}\u{62C}"),
+    ('\u{FC2E}', "\u{641}\u{62D}"),
+    ('\u{FC2F}', "\u{641}\u{62E}"),
+    ('\u{FC30}', "\u{641}\u{645}"),
+    ('\u{FC31}', "\u{641}\u{649}"),
+    ('\u{FC32}', "\u{641}\u{64A}"),
+    ('\u{FC33}', "\u{642}\u{62D}"),
+    ('\u{FC34}', "\u{642}\u{645}"),
+    ('\u{FC35}', "\u{642}\u{649}"),
+    ('\u{FC36}', "\u{642}\u{64A}"),
+    ('\u{FC37}', "\u{643}\u{627}"),
+    ('\u{FC38}', "\u{643}\u{62C}"),
+    ('\u{FC39}', "\u{643}\u{62D}"),
+    ('\u{FC3A}', "\u{643}\u{62E}"),
+    ('\u{FC3B}', "\u{643}\u{644}"),
+    ('\u{FC3C}', "\u{643}\u{645}"),
+    ('\u{FC3D}', "\u{643}\u{649}"),
+    ('\u{FC3E}', "\u{643}\u{64A}"),
+    ('\u{FC3F}', "\u{644}\u{62C}"),
+    ('\u{FC40}', "\u{644}\u{62D}"),
+    ('\u{FC41}', "\u{644}\u{62E}"),
+    ('\u{FC42}', "\u{644}\u{645}"),
+    ('\u{FC43}', "\u{644}\u{649}"),
+    ('\u{FC44}', "\u{644}\u{64A}"),
+    ('\u{FC45}', "\u{645}\u{62C}"),
+    ('\u{FC46}', "\u{645}\u{62D}"),
+    ('\u{FC47}', "\u{645}\u{62E}"),
+    ('\u{FC48}', "\u{645}\u{645}"),
+    ('\u{FC49}', "\u{645}\u{649}"),
+    ('\u{FC4A}', "\u{645}\u{64A}"),
+    ('\u{FC4B}', "\u{646}\u{62C}"),
+    ('\u{FC4C}', "\u{646}\u{62D}"),
+    ('\u{FC4D}', "\u{646}\u{62E}"),
+    ('\u{FC4E}', "\u{646}\u{645}"),
+    ('\u{FC4F}', "\u{646}\u{649}"),
+    ('\u{FC50}', "\u{646}\u{64A}"),
+    ('\u{FC51}', "\u{647}\u{62C}"),
+    ('\u{FC52}', "\u{647}\u{645}"),
+    ('\u{FC53}', "\u{647}\u{649}"),
+    ('\u{FC54}', "\u{647}\u{64A}"),
+    ('\u{FC55}', "\u{64A}\u{62C}"),
+    ('\u{FC56}', "\u{64A}\u{62D}"),
+    ('\u{FC57}', "\u{64A}\u{62E}"),
+    ('\u{FC58}', "\u{64A}\u{645}"),
+    ('\u{FC59}', "\u{64A}\u{649}"),
+    ('\u{FC5A}', "\u{64A}\u{64A}"),
+    ('\u{FC5B}', "\u{630}\u{670}"),
+    ('\u{FC5C}', "\u{631}\u{670}"),
+    ('\u{FC5D}', "\u{649}\u{670}"),
+    ('\u{FC5E}', "\u{20}\u{64C}\u{651}"),
+    ('\u{FC5F}', "\u{20}\u{64D}\u{651}"),
+    ('\u{FC60}', "\u{20}\u{64E}\u{651}"),
+    ('\u{FC61}', "\u{20}\u{64F}\u{651}"),
+    ('\u{FC62}', "\u{20}\u{650}\u{651}"),
+    ('\u{FC63}', "\u{20}\u{651}\u{670}"),
+    ('\u{FC64}', "\u{64A}\u{654}\u{631}"),
+    ('\u{FC65}', "\u{64A}\u{654}\u{632}"),
+    ('\u{FC66}', "\u{64A}\u{654}\u{645}"),
+    ('\u{FC67}', "\u{64A}\u{654}\u{646}"),
+    ('\u{FC68}', "\u{64A}\u{654}\u{649}"),
+    ('\u{FC69}', "\u{64A}\u{654}\u{64A}"),
+    ('\u{FC6A}', "\u{628}\u{631}"),
+    ('\u{FC6B}', "\u{628}\u{632}"),
+    ('\u{FC6C}', "\u{628}\u{645}"),
+    ('\u{FC6D}', "\u{628}\u{646}"),
+    ('\u{FC6E}', "\u{628}\u{649}"),
+    ('\u{FC6F}', "\u{628}\u{64A}"),
+    ('\u{FC70}', "\u{62A}\u{631}"),
+    ('\u{FC71}', "\u{62A}\u{632}"),
+    ('\u{FC72}', "\u{62A}\u{645}"),
+    ('\u{FC73}', "\u{62A}\u{646}"),
+    ('\u{FC74}', "\u{62A}\u{649}"),
+    ('\u{FC75}', "\u{62A}\u{64A}"),
+    ('\u{FC76}', "\u{62B}\u{631}"),
+    ('\u{FC77}', "\u{62B}\u{632}"),
+    ('\u{FC78}', "\u{62B}\u{645}"),
+    ('\u{FC79}', "\u{62B}\u{646}"),
+    ('\u{FC7A}', "\u{62B}\u{649}"),
+    ('\u{FC7B}', "\u{62B}\u{64A}"),
+    ('\u{FC7C}', "\u{641}\u{649}"),
+    ('\u{FC7D}', "\u{641}\u{64A}"),
+    ('\u{FC7E}', "\u{642}\u{649}"),
+    ('\u{FC7F}', "\u{642}\u{64A}"),
+    ('\u{FC80}', "\u{643}\u{627}"),
+    ('\u{FC81}', "\u{643}\u{644}"),
+    ('\u{FC82}', "\u{643}\u{645}"),
+    ('\u{FC83}', "\u{643}\u{649}"),
+    ('\u{FC84}', "\u{643}\u{64A}"),
+    ('\u{FC85}', "\u{644}\u{645}"),
+    ('\u{FC86}', "\u{644}\u{649}"),
+    ('\u{FC87}', "\u{644}\u{64A}"),
+    ('\u{FC88}', "\u{645}\u{627}"),
+    ('\u{FC89}', "\u{645}\u{645}"),
+    ('\u{FC8A}', "\u{646}\u{631}"),
+    ('\u{FC8B}', "\u{646}\u{632}"),
+    ('\u{FC8C}', "\u{646}\u{645}"),
+    ('\u{FC8D}', "\u{646}\u{646}"),
+    ('\u{FC8E}', "\u{646}\u{649}"),
+    ('\u{FC8F}', "\u{646}\u{64A}"),
+    ('\u{FC90}', "\u{649}\u{670}"),
+    ('\u{FC91}', "\u{64A}\u{631}"),
+    ('\u{FC92}', "\u{64A}\u{632}"),
+    ('\u{FC93}', "\u{64A}\u{645}"),
+    ('\u{FC94}', "\u{64A}\u{646}"),
+    ('\u{FC95}', "\u{64A}\u{649}"),
+    ('\u{FC96}', "\u{64A}\u{64A}"),
+    ('\u{FC97}', "\u{64A}\u{654}\u{62C}"),
+    ('\u{FC98}', "\u{64A}\u{654}\u{62D}"),
+    ('\u{FC99}', "\u{64A}\u{654}\u{62E}"),
+    ('\u{FC9A}', "\u{64A}\u{654}\u{645}"),
+    ('\u{FC9B}', "\u{64A}\u{654}\u{647}"),
+    ('\u{FC9C}', "\u{628}\u{62C}"),
+    ('\u{FC9D}', "\u{628}\u{62D}"),
+    ('\u{FC9E}', "\u{628}\u{62E}"),
+    ('\u{FC9F}', "\u{628}\u{645}"),
+    ('\u{FCA0}', "\u{628}\u{647}"),
+    ('\u{FCA1}', "\u{62A}\u{62C}"),
+    ('\u{FCA2}', "\u{62A}\u{62D}"),
+    ('\u{FCA3}', "\u{62A}\u{62E}"),
+    ('\u{FCA4}', "\u{62A}\u{645}"),
+    ('\u{FCA5}', "\u{62A}\u{647}"),
+    ('\u{FCA6}', "\u{62B}\u{645}"),
+    ('\u{FCA7}', "\u{62C}\u{62D}"),
+    ('\u{FCA8}', "\u{62C}\u{645}"),
+    ('\u{FCA9}', "\u{62D}\u{62C}"),
+    ('\u{FCAA}', "\u{62D}\u{645}"),
+    ('\u{FCAB}', "\u{62E}\u{62C}"),
+    ('\u{FCAC}', "\u{62E}\u{645}"),
+    ('\u{FCAD}', "\u{633}\u{62C}"),
+    ('\u{FCAE}', "\u{633}\u{62D}"),
+    ('\u{FCAF}', "\u{633}\u{62E}"),
+    ('\u{FCB0}', "\u{633}\u{645}"),
+    ('\u{FCB1}', "\u{635}\u{62D}"),
+    ('\u{FCB2}', "\u{635}\u{62E}"),
+    ('\u{FCB3}', "\u{635}\u{645}"),
+    ('\u{FCB4}', "\u{636}\u{62C}"),
+    ('\u{FCB5}', "\u{636}\u{62D}"),
+    ('\u{FCB6}', "\u{636}\u{62E}"),
+    ('\u{FCB7}', "\u{636}\u{645}"),
+    ('\u{FCB8}', "\u{637}\u{62D}"),
+    ('\u{FCB9}', "\u{638}\u{645}"),
+    ('\u{FCBA}', "\u{639}\u{62C}"),
+    ('\u{FCBB}', "\u{639}\u{645}"),
+    ('\u{FCBC}', "\u{63A}\u{62C}"),
+    ('\u{FCBD}', "\u{63A}\u{645}"),
+    ('\u{FCBE}', "\u{641}\u{62C}"),
+    ('\u{FCBF}', "\u{641}\u{62D}"),
+    ('\u{FCC0}', "\u{641}\u{62E}"),
+    ('\u{FCC1}', "\u{641}\u{645}"),
+    ('\u{FCC2}', "\u{642}\u{62D}"),
+    ('\u{FCC3}', "\u{642}\u{645}"),
+    ('\u{FCC4}', "\u{643}\u{62C}"),
+    ('\u{FCC5}', "\u{643}\u{62D}"),
+    ('\u{FCC6}', "\u{643}\u{62E}"),
+    ('\u{FCC7}', "\u{643}\u{644}"),
+    ('\u{FCC8}', "\u{643}\u{645}"),
+    ('\u{FCC9}', "\u{644}\u{62C}"),
+    ('\u{FCCA}', "\u{644}\u{62D}"),
+    ('\u{FCCB}', "\u{644}\u{62E}"),
+    ('\u{FCCC}', "\u{644}\u{645}"),
+    ('\u{FCCD}', "\u{644}\u{647}"),
+    ('\u{FCCE}', "\u{645}\u{62C}"),
+    ('\u{FCCF}', "\u{645}\u{62D}"),
+    ('\u{FCD0}', "\u{645}\u{62E}"),
+    ('\u{FCD1}', "\u{645}\u{645}"),
+    ('\u{FCD2}', "\u{646}\u{62C}"),
+    ('\u{FCD3}', "\u{646}\u{62D}"),
+    ('\u{FCD4}', "\u{646}\u{62E}"),
+    ('\u{FCD5}', "\u{646}\u{645}"),
+    ('\u{FCD6}', "\u{646}\u{647}"),
+    ('\u{FCD7}', "\u{647}\u{62C}"),
+    ('\u{FCD8}', "\u{647}\u{645}"),
+    ('\u{FCD9}', "\u{647}\u{670}"),
+    ('\u{FCDA}', "\u{64A}\u{62C}"),
+    ('\u{FCDB}', "\u{64A}\u{62D}"),
+    ('\u{FCDC}', "\u{64A}\u{62E}"),
+    ('\u{FCDD}', "\u{64A}\u{645}"),
+    ('\u{FCDE}', "\u{64A}\u{647}"),
+    ('\u{FCDF}', "\u{64A}\u{654}\u{645}"),
+    ('\u{FCE0}', "\u{64A}\u{654}\u{647}"),
+    ('\u{FCE1}', "\u{628}\u{645}"),
+    ('\u{FCE2}', "\u{628}\u{647}"),
+    ('\u{FCE3}', "\u{62A}\u{645}"),
+    ('\u{FCE4}', "\u{62A}\u{647}"),
+    ('\u{FCE5}', "\u{62B}\u{645}"),
+    ('\u{FCE6}', "\u{62B}\u{647}"),
+    ('\u{FCE7}', "\u{633}\u{645}"),
+    ('\u{FCE8}', "\u{633}\u{647}"),
+    ('\u{FCE9}', "\u{634}\u{645}"),
+    ('\u{FCEA}', "\u{634}\u{647}"),
+    ('\u{FCEB}', "\u{643}\u{644}"),
+    ('\u{FCEC}', "\u{643}\u{645}"),
+    ('\u{FCED}', "\u{644}\u{645}"),
+    ('\u{FCEE}', "\u{646}\u{645}"),
+    ('\u{FCEF}', "\u{646}\u{647}"),
+    ('\u{FCF0}', "\u{64A}\u{645}"),
+    ('\u{FCF1}', "\u{64A}\u{647}"),
+    ('\u{FCF2}', "\u{640}\u{64E}\u{651}"),
+    ('\u{FCF3}', "\u{640}\u{64F}\u{651}"),
+    ('\u{FCF4}', "\u{640}\u{650}\u{651}"),
+    ('\u{FCF5}', "\u{637}\u{649}"),
+    ('\u{FCF6}', "\u{637}\u{64A}"),
+    ('\u{FCF7}', "\u{639}\u{649}"),
+    ('\u{FCF8}', "\u{639}\u{64A}"),
+    ('\u{FCF9}', "\u{63A}\u{649}"),
+    ('\u{FCFA}', "\u{63A}\u{64A}"),
+    ('\u{FCFB}', "\u{633}\u{649}"),
+    ('\u{FCFC}', "\u{633}\u{64A}"),
+    ('\u{FCFD}', "\u{634}\u{649}"),
+    ('\u{FCFE}', "\u{634}\u{64A}"),
+    ('\u{FCFF}', "\u{62D}\u{649}"),
+    ('\u{FD00}', "\u{62D}\u{64A}"),
+    ('\u{FD01}', "\u{62C}\u{649}"),
+    ('\u{FD02}', "\u{62C}\u{64A}"),
+    ('\u{FD03}', "\u{62E}\u{649}"),
+    ('\u{FD04}', "\u{62E}\u{64A}"),
+    ('\u{FD05}', "\u{635}\u{649}"),
+    ('\u{FD06}', "\u{635}\u{64A}"),
+    ('\u{FD07}', "\u{636}\u{649}"),
+    ('\u{FD08}', "\u{636}\u{64A}"),
+    ('\u{FD09}', "\u{634}\u{62C}"),
+    ('\u{FD0A}', "\u{634}\u{62D}"),
+    ('\u{FD0B}', "\u{634}\u{62E}"),
+    ('\u{FD0C}', "\u{634}\u{645}"),
+    ('\u{FD0D}', "\u{634}\u{631}"),
+    ('\u{FD0E}', "\u{633}\u{631}"),
+    ('\u{FD0F}', "\u{635}\u{631}"),
+    ('\u{FD10}', "\u{636}\u{631}"),
+    ('\u{FD11}', "\u{637}\u{649}"),
+    ('\u{FD12}', "\u{637}\u{64A}"),
+    ('\u{FD13}', "\u{639}\u{649}"),
+    ('\u{FD14}', "\u{639}\u{64A}"),
+    ('\u{FD15}', "\u{63A}\u{649}"),
+    ('\u{FD16}', "\u{63A}\u{64A}"),
+    ('\u{FD17}', "\u{633}\u{649}"),
+    ('\u{FD18}', "\u{633}\u{64A}"),
+    ('\u{FD19}', "\u{634}\u{649}"),
+    ('\u{FD1A}', "\u{634}\u{64A}"),
+    ('\u{FD1B}', "\u{62D}\u{649}"),
+    ('\u{FD1C}', "\u{62D}\u{64A}"),
+    ('\u{FD1D}', "\u{62C}\u{649}"),
+    ('\u{FD1E}', "\u{62C}\u{64A}"),
+    ('\u{FD1F}', "\u{62E}\u{649}"),
+    ('\u{FD20}', "\u{62E}\u{64A}"),
+    ('\u{FD21}', "\u{635}\u{649}"),
+    ('\u{FD22}', "\u{635}\u{64A}"),
+    ('\u{FD23}', "\u{636}\u{649}"),
+    ('\u{FD24}', "\u{636}\u{64A}"),
+    ('\u{FD25}', "\u{634}\u{62C}"),
+    ('\u{FD26}', "\u{634}\u{62D}"),
+    ('\u{FD27}', "\u{634}\u{62E}"),
+    ('\u{FD28}', "\u{634}\u{645}"),
+    ('\u{FD29}', "\u{634}\u{631}"),
+    ('\u{FD2A}', "\u{633}\u{631}"),
+    ('\u{FD2B}', "\u{635}\u{631}"),
+    ('\u{FD2C}', "\u{636}\u{631}"),
+    ('\u{FD2D}', "\u{634}\u{62C}"),
+    ('\u{FD2E}', "\u{634}\u{62D}"),
+    ('\u{FD2F}', "\u{634}\u{62E}"),
+    ('\u{FD30}', "\u{634}\u{645}"),
+    ('\u{FD31}', "\u{633}\u{647}"),
+    ('\u{FD32}', "\u{634}\u{647}"),
+    ('\u{FD33}', "\u{637}\u{645}"),
+    ('\u{FD34}', "\u{633}\u{62C}"),
+    ('\u{FD35}', "\u{633}\u{62D}"),
+    ('\u{FD36}', "\u{633}\u{62E}"),
+    ('\u{FD37}', "\u{634}\u{62C}"),
+    ('\u{FD38}', "\u{634}\u{62D}"),
+    ('\u{FD39}', "\u{634}\u{62E}"),
+    ('\u{FD3A}', "\u{637}\u{645}"),
+    ('\u{FD3B}', "\u{638}\u{645}"),
+    ('\u{FD3C}', "\u{627}\u{64B}"),
+    ('\u{FD3D}', "\u{627}\u{64B}"),
+    ('\u{FD50}', "\u{62A}\u{62C}\u{645}"),
+    ('\u{FD51}', "\u{62A}\u{62D}\u{62C}"),
+    ('\u{FD52}', "\u{62A}\u{62D}\u{62C}"),
+    ('\u{FD53}', "\u{62A}\u{62D}\u{645}"),
+    ('\u{FD54}', "\u{62A}\u{62E}\u{645}"),
+    ('\u{FD55}', "\u{62A}\u{645}\u{62C}"),
+    ('\u{FD56}', "\u{62A}\u{645}\u{62D}"),
+    ('\u{FD57}', "\u{62A}\u{645}\u{62E}"),
+    ('\u{FD58}', "\u{62C}\u{645}\u{62D}"),
+    ('\u{FD59}', "\u{62C}\u{645}\u{62D}"),
+    ('\u{FD5A}', "\u{62D}\u{645}\u{64A}"),
+    ('\u{FD5B}', "\u{62D}\u{645}\u{649}"),
+    ('\u{FD5C}', "\u{633}\u{62D}\u{62C}"),
+    ('\u{FD5D}', "\u{633}\u{62C}\u{62D}"),
+    ('\u{FD5E}', "\u{633}\u{62C}\u{649}"),
+    ('\u{FD5F}', "\u{633}\u{645}\u{62D}"),
+    ('\u{FD60}', "\u{633}\u{645}\u{62D}"),
+    ('\u{FD61}', "\u{633}\u{645}\u{62C}"),
+    ('\u{FD62}', "\u{633}\u{645}\u{645}"),
+    ('\u{FD63}', "\u{633}\u{645}\u{645}"),
+    ('\u{FD64}', "\u{635}\u{62D}\u{62D}"),
+    ('\u{FD65}', "\u{635}\u{62D}\u{62D}"),
+    ('\u{FD66}', "\u{635}\u{645}\u{645}"),
+    ('\u{FD67}', "\u{634}\u{62D}\u{645}"),
+    ('\u{FD68}', "\u{634}\u{62D}\u{645}"),
+    ('\u{FD69}', "\u{634}\u{62C}\u{64A}"),
+    ('\u{FD6A}', "\u{634}\u{645}\u{62E}"),
+    ('\u{FD6B}', "\u{634}\u{645}\u{62E}"),
+    ('\u{FD6C}', "\u{634}\u{645}\u{645}"),
+    ('\u{FD6D}', "\u{634}\u{645}\u{645}"),
+    ('\u{FD6E}', "\u{636}\u{62D}\u{649}"),
+    ('\u{FD6F}', "\u{636}\u{62E}\u{645}"),
+    ('\u{FD70}', "\u{636}\u{62E}\u{645}"),
+    ('\u{FD71}', "\u{637}\u{645}\u{62D}"),
+    ('\u{FD72}', "\u{637}\u{645}\u{62D}"),
+    ('\u{FD73}', "\u{637}\u{645}\u{645}"),
+    ('\u{FD74}', "\u{637}\u{645}\u{64A}"),
+    ('\u{FD75}', "\u{639}\u{62C}\u{645}"),
+    ('\u{FD76}', "\u{639}\u{645}\u{645}"),
+    ('\u{FD77}', "\u{639}\u{645}\u{645}"),
+    ('\u{FD78}', "\u{639}\u{645}\u{649}"),
+    ('\u{FD79}', "\u{63A}\u{645}\u{645}"),
+    ('\u{FD7A}', "\u{63A}\u{645}\u{64A}"),
+    ('\u{FD7B}', "\u{63A}\u{645}\u{649}"),
+    ('\u{FD7C}', "\u{641}\u{62E}\u{645}"),
+    ('\u{FD7D}', "\u{641}\u{62E}\u{645}"),
+    ('\u{FD7E}', "\u{642}\u{645}\u{62D}"),
+    ('\u{FD7F}', "\u{642}\u{645}\u{645}"),
+    ('\u{FD80}', "\u{644}\u{62D}\u{645}"),
+    ('\u{FD81}', "\u{644}\u{62D}\u{64A}"),
+    ('\u{FD82}', "\u{644}\u{62D}\u{649}"),
+    ('\u{FD83}', "\u{644}\u{62C}\u{62C}"),
+    ('\u{FD84}', "\u{644}\u{62C}\u{62C}"),
+    ('\u{FD85}', "\u{644}\u{62E}\u{645}"),
+    ('\u{FD86}', "\u{644}\u{62E}\u{645}"),
+    ('\u{FD87}', "\u{644}\u{645}\u{62D}"),
+    ('\u{FD88}', "\u{644}\u{645}\u{62D}"),
+    ('\u{FD89}', "\u{645}\u{62D}\u{62C}"),
+    ('\u{FD8A}', "\u{645}\u{62D}\u{645}"),
+    ('\u{FD8B}', "\u{645}\u{62D}\u{64A}"),
+    ('\u{FD8C}', "\u{645}\u{62C}\u{62D}"),
+    ('\u{FD8D}', "\u{645}\u{62C}\u{645}"),
+    ('\u{FD8E}', "\u{645}\u{62E}\u{62C}"),
+    ('\u{FD8F}', "\u{645}\u{62E}\u{645}"),
+    ('\u{FD92}', "\u{645}\u{62C}\u{62E}"),
+    ('\u{FD93}', "\u{647}\u{645}\u{62C}"),
+    ('\u{FD94}', "\u{647}\u{645}\u{645}"),
+    ('\u{FD95}', "\u{646}\u{62D}\u{645}"),
+    ('\u{FD96}', "\u{646}\u{62D}\u{649}"),
+    ('\u{FD97}', "\u{646}\u{62C}\u{645}"),
+    ('\u{FD98}', "\u{646}\u{62C}\u{645}"),
+    ('\u{FD99}', "\u{646}\u{62C}\u{649}"),
+    ('\u{FD9A}', "\u{646}\u{645}\u{64A}"),
+    ('\u{FD9B}', "\u{646}\u{645}\u{649}"),
+    ('\u{FD9C}', "\u{64A}\u{645}\u{645}"),
+    ('\u{FD9D}', "\u{64A}\u{645}\u{645}"),
+    ('\u{FD9E}', "\u{628}\u{62E}\u{64A}"),
+    ('\u{FD9F}', "\u{62A}\u{62C}\u{64A}"),
+    ('\u{FDA0}', "\u{62A}\u{62C}\u{649}"),
+    ('\u{FDA1}', "\u{62A}\u{62E}\u{64A}"),
+    ('\u{FDA2}', "\u{62A}\u{62E}\u{649}"),
+    ('\u{FDA3}', "\u{62A}\u{645}\u{64A}"),
+    ('\u{FDA4}', "\u{62A}\u{645}\u{649}"),
+    ('\u{FDA5}', "\u{62C}\u{645}\u{64A}"),
+    ('\u{FDA6}', "\u{62C}\u{62D}\u{649}"),
+    ('\u{FDA7}', "\u{62C}\u{645}\u{649}"),
+    ('\u{FDA8}', "\u{633}\u{62E}\u{649}"),
+    ('\u{FDA9}', "\u{635}\u{62D}\u{64A}"),
+    ('\u{FDAA}', "\u{634}\u{62D}\u{64A}"),
+    ('\u{FDAB}', "\u{636}\u{62D}\u{64A}"),
+    ('\u{FDAC}', "\u{644}\u{62C}\u{64A}"),
+    ('\u{FDAD}', "\u{644}\u{645}\u{64A}"),
+    ('\u{FDAE}', "\u{64A}\u{62D}\u{64A}"),
+    ('\u{FDAF}', "\u{64A}\u{62C}\u{64A}"),
+    ('\u{FDB0}', "\u{64A}\u{645}\u{64A}"),
+    ('\u{FDB1}', "\u{645}\u{645}\u{64A}"),
+    ('\u{FDB2}', "\u{642}\u{645}\u{64A}"),
+    ('\u{FDB3}', "\u{646}\u{62D}\u{64A}"),
+    ('\u{FDB4}', "\u{642}\u{645}\u{62D}"),
+    ('\u{FDB5}', "\u{644}\u{62D}\u{645}"),
+    ('\u{FDB6}', "\u{639}\u{645}\u{64A}"),
+    ('\u{FDB7}', "\u{643}\u{645}\u{64A}"),
+    ('\u{FDB8}', "\u{646}\u{62C}\u{62D}"),
+    ('\u{FDB9}', "\u{645}\u{62E}\u{64A}"),
+    ('\u{FDBA}', "\u{644}\u{62C}\u{645}"),
+    ('\u{FDBB}', "\u{643}\u{645}\u{645}"),
+    ('\u{FDBC}', "\u{644}\u{62C}\u{645}"),
+    ('\u{FDBD}', "\u{646}\u{62C}\u{62D}"),
+    ('\u{FDBE}', "\u{62C}\u{62D}\u{64A}"),
+    ('\u{FDBF}', "\u{62D}\u{62C}\u{64A}"),
+    ('\u{FDC0}', "\u{645}\u{62C}\u{64A}"),
+    ('\u{FDC1}', "\u{641}\u{645}\u{64A}"),
+    ('\u{FDC2}', "\u{628}\u{62D}\u{64A}"),
+    ('\u{FDC3}', "\u{643}\u{645}\u{645}"),
+    ('\u{FDC4}', "\u{639}\u{62C}\u{645}"),
+    ('\u{FDC5}', "\u{635}\u{645}\u{645}"),
+    ('\u{FDC6}', "\u{633}\u{62E}\u{64A}"),
+    ('\u{FDC7}', "\u{646}\u{62C}\u{64A}"),
+    ('\u{FDF0}', "\u{635}\u{644}\u{6D2}"),
+    ('\u{FDF1}', "\u{642}\u{644}\u{6D2}"),
+    ('\u{FDF2}', "\u{627}\u{644}\u{644}\u{647}"),
+    ('\u{FDF3}', "\u{627}\u{643}\u{628}\u{631}"),
+    ('\u{FDF4}', "\u{645}\u{62D}\u{645}\u{62F}"),
+    ('\u{FDF5}', "\u{635}\u{644}\u{639}\u{645}"),
+    ('\u{FDF6}', "\u{631}\u{633}\u{648}\u{644}"),
+    ('\u{FDF7}', "\u{639}\u{644}\u{64A}\u{647}"),
+    ('\u{FDF8}', "\u{648}\u{633}\u{644}\u{645}"),
+    ('\u{FDF9}', "\u{635}\u{644}\u{649}"),
+    ('\u{FDFA}', "\u{635}\u{644}\u{649}\u{20}\u{627}\u{644}\u{644}\u{647}\u{20}\u{639}\u{644}\u{64A}\u{647}\u{20}\u{648}\u{633}\u{644}\u{645}"),
+    ('\u{FDFB}', "\u{62C}\u{644}\u{20}\u{62C}\u{644}\u{627}\u{644}\u{647}"),
+    ('\u{FDFC}', "\u{631}\u{6CC}\u{627}\u{644}"),
+    ('\u{FE10}', "\u{2C}"),
+    ('\u{FE11}', "\u{3001}"),
+    ('\u{FE12}', "\u{3002}"),
+    ('\u{FE13}', "\u{3A}"),
+    ('\u{FE14}', "\u{3B}"),
+    ('\u{FE15}', "\u{21}"),
+    ('\u{FE16}', "\u{3F}"),
+    ('\u{FE17}', "\u{3016}"),
+    ('\u{FE18}', "\u{3017}"),
+    ('\u{FE19}', "\u{2E}\u{2E}\u{2E}"),
+    ('\u{FE30}', "\u{2E}\u{2E}"),
+    ('\u{FE31}', "\u{2014}"),
+    ('\u{FE32}', "\u{2013}"),
+    ('\u{FE33}', "\u{5F}"),
+    ('\u{FE34}', "\u{5F}"),
+    ('\u{FE35}', "\u{28}"),
+    ('\u{FE36}', "\u{29}"),
+    ('\u{FE37}', "\u{7B}"),
+    ('\u{FE38}', "\u{7D}"),
+    ('\u{FE39}', "\u{3014}"),
+    ('\u{FE3A}', "\u{3015}"),
+    ('\u{FE3B}', "\u{3010}"),
+    ('\u{FE3C}', "\u{3011}"),
+    ('\u{FE3D}', "\u{300A}"),
+    ('\u{FE3E}', "\u{300B}"),
+    ('\u{FE3F}', "\u{3008}"),
+    ('\u{FE40}', "\u{3009}"),
+    ('\u{FE41}', "\u{300C}"),
+    ('\u{FE42}', "\u{300D}"),
+    ('\u{FE43}', "\u{300E}"),
+    ('\u{FE44}', "\u{300F}"),
+    ('\u{FE47}', "\u{5B}"),
+    ('\u{FE48}', "\u{5D}"),
+    ('\u{FE49}', "\u{20}\u{305}"),
+    ('\u{FE4A}', "\u{20}\u{305}"),
+    ('\u{FE4B}', "\u{20}\u{305}"),
+    ('\u{FE4C}', "\u{20}\u{305}"),
+    ('\u{FE4D}', "\u{5F}"),
+    ('\u{FE4E}', "\u{5F}"),
+    ('\u{FE4F}', "\u{5F}"),
+    ('\u{FE50}', "\u{2C}"),
+    ('\u{FE51}', "\u{3001}"),
+    ('\u{FE52}', "\u{2E}"),
+    ('\u{FE54}', "\u{3B}"),
+    ('\u{FE55}', "\u{3A}"),
+    ('\u{FE56}', "\u{3F}"),
+    ('\u{FE57}', "\u{21}"),
+    ('\u{FE58}', "\u{2014}"),
+    ('\u{FE59}', "\u{28}"),
+    ('\u{FE5A}', "\u{29}"),
+    ('\u{FE5B}', "\u{7B}"),
+    ('\u{FE5C}', "\u{7D}"),
+    ('\u{FE5D}', "\u{3014}"),
+    ('\u{FE5E}', "\u{3015}"),
+    ('\u{FE5F}', "\u{23}"),
+    ('\u{FE60}', "\u{26}"),
+    ('\u{FE61}', "\u{2A}"),
+    ('\u{FE62}', "\u{2B}"),
+    ('\u{FE63}', "\u{2D}"),
+    ('\u{FE64}', "\u{3C}"),
+    ('\u{FE65}', "\u{3E}"),
+    ('\u{FE66}', "\u{3D}"),
+    ('\u{FE68}', "\u{5C}"),
+    ('\u{FE69}', "\u{24}"),
+    ('\u{FE6A}', "\u{25}"),
+    ('\u{FE6B}', "\u{40}"),
+    ('\u{FE70}', "\u{20}\u{64B}"),
+    ('\u{FE71}', "\u{640}\u{64B}"),
+    ('\u{FE72}', "\u{20}\u{64C}"),
+    ('\u{FE74}', "\u{20}\u{64D}"),
+    ('\u{FE76}', "\u{20}\u{64E}"),
+    ('\u{FE77}', "\u{640}\u{64E}"),
+    ('\u{FE78}', "\u{20}\u{64F}"),
+    ('\u{FE79}', "\u{640}\u{64F}"),
+    ('\u{FE7A}', "\u{20}\u{650}"),
+    ('\u{FE7B}', "\u{640}\u{650}"),
+    ('\u{FE7C}', "\u{20}\u{651}"),
+    ('\u{FE7D}', "\u{640}\u{651}"),
+    ('\u{FE7E}', "\u{20}\u{652}"),
+    ('\u{FE7F}', "\u{640}\u{652}"),
+    ('\u{FE80}', "\u{621}"),
+    ('\u{FE81}', "\u{627}\u{653}"),
+    ('\u{FE82}', "\u{627}\u{653}"),
+    ('\u{FE83}', "\u{627}\u{654}"),
+    ('\u{FE84}', "\u{627}\u{654}"),
+    ('\u{FE85}', "\u{648}\u{654}"),
+    ('\u{FE86}', "\u{648}\u{654}"),
+    ('\u{FE87}', "\u{627}\u{655}"),
+    ('\u{FE88}', "\u{627}\u{655}"),
+    ('\u{FE89}', "\u{64A}\u{654}"),
+    ('\u{FE8A}', "\u{64A}\u{654}"),
+    ('\u{FE8B}', "\u{64A}\u{654}"),
+    ('\u{FE8C}', "\u{64A}\u{654}"),
+    ('\u{FE8D}', "\u{627}"),
+    ('\u{FE8E}', "\u{627}"),
+    ('\u{FE8F}', "\u{628}"),
+    ('\u{FE90}', "\u{628}"),
+    ('\u{FE91}', "\u{628}"),
+    ('\u{FE92}', "\u{628}"),
+    ('\u{FE93}', "\u{629}"),
+    ('\u{FE94}', "\u{629}"),
+    ('\u{FE95}', "\u{62A}"),
+    ('\u{FE96}', "\u{62A}"),
+    ('\u{FE97}', "\u{62A}"),
+    ('\u{FE98}', "\u{62A}"),
+    ('\u{FE99}', "\u{62B}"),
+    ('\u{FE9A}', "\u{62B}"),
+    ('\u{FE9B}', "\u{62B}"),
+    ('\u{FE9C}', "\u{62B}"),
+    ('\u{FE9D}', "\u{62C}"),
+    ('\u{FE9E}', "\u{62C}"),
+    ('\u{FE9F}', "\u{62C}"),
+    ('\u{FEA0}', "\u{62C}"),
+    ('\u{FEA1}', "\u{62D}"),
+    ('\u{FEA2}', "\u{62D}"),
+    ('\u{FEA3}', "\u{62D}"),
+    ('\u{FEA4}', "\u{62D}"),
+    ('\u{FEA5}', "\u{62E}"),
+    ('\u{FEA6}', "\u{62E}"),
+    ('\u{FEA7}', "\u{62E}"),
+    ('\u{FEA8}', "\u{62E}"),
+    ('\u{FEA9}', "\u{62F}"),
+    ('\u{FEAA}', "\u{62F}"),
+    ('\u{FEAB}', "\u{630}"),
+    ('\u{FEAC}', "\u{630}"),
+    ('\u{FEAD}', "\u{631}"),
+    ('\u{FEAE}', "\u{631}"),
+    ('\u{FEAF}', "\u{632}"),
+    ('\u{FEB0}', "\u{632}"),
+    ('\u{FEB1}', "\u{633}"),
+    ('\u{FEB2}', "\u{633}"),
+    ('\u{FEB3}', "\u{633}"),
+    ('\u{FEB4}', "\u{633}"),
+    ('\u{FEB5}', "\u{634}"),
+    ('\u{FEB6}', "\u{634}"),
+    ('\u{FEB7}', "\u{634}"),
+    ('\u{FEB8}', "\u{634}"),
+    ('\u{FEB9}', "\u{635}"),
+    ('\u{FEBA}', "\u{635}"),
+    ('\u{FEBB}', "\u{635}"),
+    ('\u{FEBC}', "\u{635}"),
+    ('\u{FEBD}', "\u{636}"),
+    ('\u{FEBE}', "\u{636}"),
+    ('\u{FEBF}', "\u{636}"),
+    ('\u{FEC0}', "\u{636}"),
+    ('\u{FEC1}', "\u{637}"),
+    ('\u{FEC2}', "\u{637}"),
+    ('\u{FEC3}', "\u{637}"),
+    ('\u{FEC4}', "\u{637}"),
+    ('\u{FEC5}', "\u{638}"),
+    ('\u{FEC6}', "\u{638}"),
+    ('\u{FEC7}', "\u{638}"),
+    ('\u{FEC8}', "\u{638}"),
+    ('\u{FEC9}', "\u{639}"),
+    ('\u{FECA}', "\u{639}"),
+    ('\u{FECB}', "\u{639}"),
+    ('\u{FECC}', "\u{639}"),
+    ('\u{FECD}', "\u{63A}"),
+    ('\u{FECE}', "\u{63A}"),
+    ('\u{FECF}', "\u{63A}"),
+    ('\u{FED0}', "\u{63A}"),
+    ('\u{FED1}', "\u{641}"),
+    ('\u{FED2}', "\u{641}"),
+    ('\u{FED3}', "\u{641}"),
+    ('\u{FED4}', "\u{641}"),
+    ('\u{FED5}', "\u{642}"),
+    ('\u{FED6}', "\u{642}"),
+    ('\u{FED7}', "\u{642}"),
+    ('\u{FED8}', "\u{642}"),
+    ('\u{FED9}', "\u{643}"),
+    ('\u{FEDA}', "\u{643}"),
+    ('\u{FEDB}', "\u{643}"),
+    ('\u{FEDC}', "\u{643}"),
+    ('\u{FEDD}', "\u{644}"),
+    ('\u{FEDE}', "\u{644}"),
+    ('\u{FEDF}', "\u{644}"),
+    ('\u{FEE0}', "\u{644}"),
+    ('\u{FEE1}', "\u{645}"),
+    ('\u{FEE2}', "\u{645}"),
+    ('\u{FEE3}', "\u{645}"),
+    ('\u{FEE4}', "\u{645}"),
+    ('\u{FEE5}', "\u{646}"),
+    ('\u{FEE6}', "\u{646}"),
+    ('\u{FEE7}', "\u{646}"),
+    ('\u{FEE8}', "\u{646}"),
+    ('\u{FEE9}', "\u{647}"),
+    ('\u{FEEA}', "\u{647}"),
+    ('\u{FEEB}', "\u{647}"),
+    ('\u{FEEC}', "\u{647}"),
+    ('\u{FEED}', "\u{648}"),
+    ('\u{FEEE}', "\u{648}"),
+    ('\u{FEEF}', "\u{649}"),
+    ('\u{FEF0}', "\u{649}"),
+    ('\u{FEF1}', "\u{64A}"),
+    ('\u{FEF2}', "\u{64A}"),
+    ('\u{FEF3}', "\u{64A}"),
+    ('\u{FEF4}', "\u{64A}"),
+    ('\u{FEF5}', "\u{644}\u{627}\u{653}"),
+    ('\u{FEF6}', "\u{644}\u{627}\u{653}"),
+    ('\u{FEF7}', "\u{644}\u{627}\u{654}"),
+    ('\u{FEF8}', "\u{644}\u{627}\u{654}"),
+    ('\u{FEF9}', "\u{644}\u{627}\u{655}"),
+    ('\u{FEFA}', "\u{644}\u{627}\u{655}"),
+    ('\u{FEFB}', "\u{644}\u{627}"),
+    ('\u{FEFC}', "\u{644}\u{627}"),
+    ('\u{FF01}', "\u{21}"),
+    ('\u{FF02}', "\u{22}"),
+    ('\u{FF03}', "\u{23}"),
+    ('\u{FF04}', "\u{24}"),
+    ('\u{FF05}', "\u{25}"),
+    ('\u{FF06}', "\u{26}"),
+    ('\u{FF07}', "\u{27}"),
+    ('\u{FF08}', "\u{28}"),
+    ('\u{FF09}', "\u{29}"),
+    ('\u{FF0A}', "\u{2A}"),
+    ('\u{FF0B}', "\u{2B}"),
+    ('\u{FF0C}', "\u{2C}"),
+    ('\u{FF0D}', "\u{2D}"),
+    ('\u{FF0E}', "\u{2E}"),
+    ('\u{FF0F}', "\u{2F}"),
+    ('\u{FF10}', "\u{30}"),
+    ('\u{FF11}', "\u{31}"),
+    ('\u{FF12}', "\u{32}"),
+    ('\u{FF13}', "\u{33}"),
+    ('\u{FF14}', "\u{34}"),
+    ('\u{FF15}', "\u{35}"),
+    ('\u{FF16}', "\u{36}"),
+    ('\u{FF17}', "\u{37}"),
+    ('\u{FF18}', "\u{38}"),
+    ('\u{FF19}', "\u{39}"),
+    ('\u{FF1A}', "\u{3A}"),
+    ('\u{FF1B}', "\u{3B}"),
+    ('\u{FF1C}', "\u{3C}"),
+    ('\u{FF1D}', "\u{3D}"),
+    ('\u{FF1E}', "\u{3E}"),
+    ('\u{FF1F}', "\u{3F}"),
+    ('\u{FF20}', "\u{40}"),
+    ('\u{FF21}', "\u{41}"),
+    ('\u{FF22}', "\u{42}"),
+    ('\u{FF23}', "\u{43}"),
+    ('\u{FF24}', "\u{44}"),
+    ('\u{FF25}', "\u{45}"),
+    ('\u{FF26}', "\u{46}"),
+    ('\u{FF27}', "\u{47}"),
+    ('\u{FF28}', "\u{48}"),
+    ('\u{FF29}', "\u{49}"),
+    ('\u{FF2A}', "\u{4A}"),
+    ('\u{FF2B}', "\u{4B}"),
+    ('\u{FF2C}', "\u{4C}"),
+    ('\u{FF2D}', "\u{4D}"),
+    ('\u{FF2E}', "\u{4E}"),
+    ('\u{FF2F}', "\u{4F}"),
+    ('\u{FF30}', "\u{50}"),
+    ('\u{FF31}', "\u{51}"),
+    ('\u{FF32}', "\u{52}"),
+    ('\u{FF33}', "\u{53}"),
+    ('\u{FF34}', "\u{54}"),
+    ('\u{FF35}', "\u{55}"),
+    ('\u{FF36}', "\u{56}"),
+    ('\u{FF37}', "\u{57}"),
+    ('\u{FF38}', "\u{58}"),
+    ('\u{FF39}', "\u{59}"),
+    ('\u{FF3A}', "\u{5A}"),
+    ('\u{FF3B}', "\u{5B}"),
+    ('\u{FF3C}', "\u{5C}"),
+    ('\u{FF3D}', "\u{5D}"),
+    ('\u{FF3E}', "\u{5E}"),
+    ('\u{FF3F}', "\u{5F}"),
+    ('\u{FF40}', "\u{60}"),
+    ('\u{FF41}', "\u{61}"),
+    ('\u{FF42}', "\u{62}"),
+    ('\u{FF43}', "\u{63}"),
+    ('\u{FF44}', "\u{64}"),
+    ('\u{FF45}', "\u{65}"),
+    ('\u{FF46}', "\u{66}"),
+    ('\u{FF47}', "\u{67}"),
+    ('\u{FF48}', "\u{68}"),
+    ('\u{FF49}', "\u{69}"),
+    ('\u{FF4A}', "\u{6A}"),
+    ('\u{FF4B}', "\u{6B}"),
+    ('\u{FF4C}', "\u{6C}"),
+    ('\u{FF4D}', "\u{6D}"),
+    ('\u{FF4E}', "\u{6E}"),
+    ('\u{FF4F}', "\u{6F}"),
+    ('\u{FF50}', "\u{70}"),
+    ('\u{FF51}', "\u{71}"),
+    ('\u{FF52}', "\u{72}"),
+    ('\u{FF53}', "\u{73}"),
+    ('\u{FF54}', "\u{74}"),
+    ('\u{FF55}', "\u{75}"),
+    ('\u{FF56}', "\u{76}"),
+    ('\u{FF57}', "\u{77}"),
+    ('\u{FF58}', "\u{78}"),
+    ('\u{FF59}', "\u{79}"),
+    ('\u{FF5A}', "\u{7A}"),
+    ('\u{FF5B}', "\u{7B}"),
+    ('\u{FF5C}', "\u{7C}"),
+    ('\u{FF5D}', "\u{7D}"),
+    ('\u{FF5E}', "\u{7E}"),
+    ('\u{FF5F}', "\u{2985}"),
+    ('\u{FF60}', "\u{2986}"),
+    ('\u{FF61}', "\u{3002}"),
+    ('\u{FF62}', "\u{300C}"),
+    ('\u{FF63}', "\u{300D}"),
+    ('\u{FF64}', "\u{3001}"),
+    ('\u{FF65}', "\u{30FB}"),
+    ('\u{FF66}', "\u{30F2}"),
+    ('\u{FF67}', "\u{30A1}"),
+    ('\u{FF68}', "\u{30A3}"),
+    ('\u{FF69}', "\u{30A5}"),
+    ('\u{FF6A}', "\u{30A7}"),
+    ('\u{FF6B}', "\u{30A9}"),
+    ('\u{FF6C}', "\u{30E3}"),
+    ('\u{FF6D}', "\u{30E5}"),
+    ('\u{FF6E}', "\u{30E7}"),
+    ('\u{FF6F}', "\u{30C3}"),
+    ('\u{FF70}', "\u{30FC}"),
+    ('\u{FF71}', "\u{30A2}"),
+    ('\u{FF72}', "\u{30A4}"),
+    ('\u{FF73}', "\u{30A6}"),
+    ('\u{FF74}', "\u{30A8}"),
+    ('\u{FF75}', "\u{30AA}"),
+    ('\u{FF76}', "\u{30AB}"),
+    ('\u{FF77}', "\u{30AD}"),
+    ('\u{FF78}', "\u{30AF}"),
+    ('\u{FF79}', "\u{30B1}"),
+    ('\u{FF7A}', "\u{30B3}"),
+    ('\u{FF7B}', "\u{30B5}"),
+    ('\u{FF7C}', "\u{30B7}"),
+    ('\u{FF7D}', "\u{30B9}"),
+    ('\u{FF7E}', "\u{30BB}"),
+    ('\u{FF7F}', "\u{30BD}"),
+    ('\u{FF80}', "\u{30BF}"),
+    ('\u{FF81}', "\u{30C1}"),
+    ('\u{FF82}', "\u{30C4}"),
+    ('\u{FF83}', "\u{30C6}"),
+    ('\u{FF84}', "\u{30C8}"),
+    ('\u{FF85}', "\u{30CA}"),
+    ('\u{FF86}', "\u{30CB}"),
+    ('\u{FF87}', "\u{30CC}"),
+    ('\u{FF88}', "\u{30CD}"),
+    ('\u{FF89}', "\u{30CE}"),
+    ('\u{FF8A}', "\u{30CF}"),
+    ('\u{FF8B}', "\u{30D2}"),
+    ('\u{FF8C}', "\u{30D5}"),
+    ('\u{FF8D}', "\u{30D8}"),
+    ('\u{FF8E}', "\u{30DB}"),
+    ('\u{FF8F}', "\u{30DE}"),
+    ('\u{FF90}', "\u{30DF}"),
+    ('\u{FF91}', "\u{30E0}"),
+    ('\u{FF92}', "\u{30E1}"),
+    ('\u{FF93}', "\u{30E2}"),
+    ('\u{FF94}', "\u{30E4}"),
+    ('\u{FF95}', "\u{30E6}"),
+    ('\u{FF96}', "\u{30E8}"),
+    ('\u{FF97}', "\u{30E9}"),
+    ('\u{FF98}', "\u{30EA}"),
+    ('\u{FF99}', "\u{30EB}"),
+    ('\u{FF9A}', "\u{30EC}"),
+    ('\u{FF9B}', "\u{30ED}"),
+    ('\u{FF9C}', "\u{30EF}"),
+    ('\u{FF9D}', "\u{30F3}"),
+    ('\u{FF9E}', "\u{3099}"),
+    ('\u{FF9F}', "\u{309A}"),
+    ('\u{FFA0}', "\u{1160}"),
+    ('\u{FFA1}', "\u{1100}"),
+    ('\u{FFA2}', "\u{1101}"),
+    ('\u{FFA3}', "\u{11AA}"),
+    ('\u{FFA4}', "\u{1102}"),
+    ('\u{FFA5}', "\u{11AC}"),
+    ('\u{FFA6}', "\u{11AD}"),
+    ('\u{FFA7}', "\u{1103}"),
+    ('\u{FFA8}', "\u{1104}"),
+    ('\u{FFA9}', "\u{1105}"),
+    ('\u{FFAA}', "\u{11B0}"),
+    ('\u{FFAB}', "\u{11B1}"),
+    ('\u{FFAC}', "\u{11B2}"),
+    ('\u{FFAD}', "\u{11B3}"),
+    ('\u{FFAE}', "\u{11B4}"),
+    ('\u{FFAF}', "\u{11B5}"),
+    ('\u{FFB0}', "\u{111A}"),
+    ('\u{FFB1}', "\u{1106}"),
+    ('\u{FFB2}', "\u{1107}"),
+    ('\u{FFB3}', "\u{1108}"),
+    ('\u{FFB4}', "\u{1121}"),
+    ('\u{FFB5}', "\u{1109}"),
+    ('\u{FFB6}', "\u{110A}"),
+    ('\u{FFB7}', "\u{110B}"),
+    ('\u{FFB8}', "\u{110C}"),
+    ('\u{FFB9}', "\u{110D}"),
+    ('\u{FFBA}', "\u{110E}"),
+    ('\u{FFBB}', "\u{110F}"),
+    ('\u{FFBC}', "\u{1110}"),
+    ('\u{FFBD}', "\u{1111}"),
+    ('\u{FFBE}', "\u{1112}"),
+    ('\u{FFC2}', "\u{1161}"),
+    ('\u{FFC3}', "\u{1162}"),
+    ('\u{FFC4}', "\u{1163}"),
+    ('\u{FFC5}', "\u{1164}"),
+    ('\u{FFC6}', "\u{1165}"),
+    ('\u{FFC7}', "\u{1166}"),
+    ('\u{FFCA}', "\u{1167}"),
+    ('\u{FFCB}', "\u{1168}"),
+    ('\u{FFCC}', "\u{1169}"),
+    ('\u{FFCD}', "\u{116A}"),
+    ('\u{FFCE}', "\u{116B}"),
+    ('\u{FFCF}', "\u{116C}"),
+    ('\u{FFD2}', "\u{116D}"),
+    ('\u{FFD3}', "\u{116E}"),
+    ('\u{FFD4}', "\u{116F}"),
+    ('\u{FFD5}', "\u{1170}"),
+    ('\u{FFD6}', "\u{1171}"),
+    ('\u{FFD7}', "\u{1172}"),
+    ('\u{FFDA}', "\u{1173}"),
+    ('\u{FFDB}', "\u{1174}"),
+    ('\u{FFDC}', "\u{1175}"),
+    ('\u{FFE0}', "\u{A2}"),
+    ('\u{FFE1}', "\u{A3}"),
+    ('\u{FFE2}', "\u{AC}"),
+    ('\u{FFE3}', "\u{20}\u{304}"),
+    ('\u{FFE4}', "\u{A6}"),
+    ('\u{FFE5}', "\u{A5}"),
+    ('\u{FFE6}', "\u{20A9}"),
+    ('\u{FFE8}', "\u{2502}"),
+    ('\u{FFE9}', "\u{2190}"),
+    ('\u{FFEA}', "\u{2191}"),
+    ('\u{FFEB}', "\u{2192}"),
+    ('\u{FFEC}', "\u{2193}"),
+    ('\u{FFED}', "\u{25A0}"),
+    ('\u{FFEE}', "\u{25CB}"),
+    ('\u{10781}', "\u{2D0}"),
+    ('\u{10782}', "\u{2D1}"),
+    ('\u{10783}', "\u{E6}"),
+    ('\u{10784}', "\u{299}"),
+    ('\u{10785}', "\u{253}"),
+    ('\u{10787}', "\u{2A3}"),
+    ('\u{10788}', "\u{AB66}"),
+    ('\u{10789}', "\u{2A5}"),
+    ('\u{1078A}', "\u{2A4}"),
+    ('\u{1078B}', "\u{256}"),
+    ('\u{1078C}', "\u{257}"),
+    ('\u{1078D}', "\u{1D91}"),
+    ('\u{1078E}', "\u{258}"),
+    ('\u{1078F}', "\u{25E}"),
+    ('\u{10790}', "\u{2A9}"),
+    ('\u{10791}', "\u{264}"),
+    ('\u{10792}', "\u{262}"),
+    ('\u{10793}', "\u{260}"),
+    ('\u{10794}', "\u{29B}"),
+    ('\u{10795}', "\u{127}"),
+    ('\u{10796}', "\u{29C}"),
+    ('\u{10797}', "\u{267}"),
+    ('\u{10798}', "\u{284}"),
+    ('\u{10799}', "\u{2AA}"),
+    ('\u{1079A}', "\u{2AB}"),
+    ('\u{1079B}', "\u{26C}"),
+    ('\u{1079C}', "\u{1DF04}"),
+    ('\u{1079D}', "\u{A78E}"),
+    ('\u{1079E}', "\u{26E}"),
+    ('\u{1079F}', "\u{1DF05}"),
+    ('\u{107A0}', "\u{28E}"),
+    ('\u{107A1}', "\u{1DF06}"),
+    ('\u{107A2}', "\u{F8}"),
+    ('\u{107A3}', "\u{276}"),
+    ('\u{107A4}', "\u{277}"),
+    ('\u{107A5}', "\u{71}"),
+    ('\u{107A6}', "\u{27A}"),
+    ('\u{107A7}', "\u{1DF08}"),
+    ('\u{107A8}', "\u{27D}"),
+    ('\u{107A9}', "\u{27E}"),
+    ('\u{107AA}', "\u{280}"),
+    ('\u{107AB}', "\u{2A8}"),
+    ('\u{107AC}', "\u{2A6}"),
+    ('\u{107AD}', "\u{AB67}"),
+    ('\u{107AE}', "\u{2A7}"),
+    ('\u{107AF}', "\u{288}"),
+    ('\u{107B0}', "\u{2C71}"),
+    ('\u{107B2}', "\u{28F}"),
+    ('\u{107B3}', "\u{2A1}"),
+    ('\u{107B4}', "\u{2A2}"),
+    ('\u{107B5}', "\u{298}"),
+    ('\u{107B6}', "\u{1C0}"),
+    ('\u{107B7}', "\u{1C1}"),
+    ('\u{107B8}', "\u{1C2}"),
+    ('\u{107B9}', "\u{1DF0A}"),
+    ('\u{107BA}', "\u{1DF1E}"),
+    ('\u{1D400}', "\u{41}"),
+    ('\u{1D401}', "\u{42}"),
+    ('\u{1D402}', "\u{43}"),
+    ('\u{1D403}', "\u{44}"),
+    ('\u{1D404}', "\u{45}"),
+    ('\u{1D405}', "\u{46}"),
+    ('\u{1D406}', "\u{47}"),
+    ('\u{1D407}', "\u{48}"),
+    ('\u{1D408}', "\u{49}"),
+    ('\u{1D409}', "\u{4A}"),
+    ('\u{1D40A}', "\u{4B}"),
+    ('\u{1D40B}', "\u{4C}"),
+    ('\u{1D40C}', "\u{4D}"),
+    ('\u{1D40D}', "\u{4E}"),
+    ('\u{1D40E}', "\u{4F}"),
+    ('\u{1D40F}', "\u{50}"),
+    ('\u{1D410}', "\u{51}"),
+    ('\u{1D411}', "\u{52}"),
+    ('\u{1D412}', "\u{53}"),
+    ('\u{1D413}', "\u{54}"),
+    ('\u{1D414}', "\u{55}"),
+    ('\u{1D415}', "\u{56}"),
+    ('\u{1D416}', "\u{57}"),
+    ('\u{1D417}', "\u{58}"),
+    ('\u{1D418}', "\u{59}"),
+    ('\u{1D419}', "\u{5A}"),
+    ('\u{1D41A}', "\u{61}"),
+    ('\u{1D41B}', "\u{62}"),
+    ('\u{1D41C}', "\u{63}"),
+    ('\u{1D41D}', "\u{64}"),
+    ('\u{1D41E}', "\u{65}"),
+    ('\u{1D41F}', "\u{66}"),
+    ('\u{1D420}', "\u{67}"),
+    ('\u{1D421}', "\u{68}"),
+    ('\u{1D422}', "\u{69}"),
+    ('\u{1D423}', "\u{6A}"),
+    ('\u{1D424}', "\u{6B}"),
+    ('\u{1D425}', "\u{6C}"),
+    ('\u{1D426}', "\u{6D}"),
+    ('\u{1D427}', "\u{6E}"),
+    ('\u{1D428}', "\u{6F}"),
+    ('\u{1D429}', "\u{70}"),
+    ('\u{1D42A}', "\u{71}"),
+    ('\u{1D42B}', "\u{72}"),
+    ('\u{1D42C}', "\u{73}"),
+    ('\u{1D42D}', "\u{74}"),
+    ('\u{1D42E}', "\u{75}"),
+    ('\u{1D42F}', "\u{76}"),
+    ('\u{1D430}', "\u{77}"),
+    ('\u{1D431}', "\u{78}"),
+    ('\u{1D432}', "\u{79}"),
+    ('\u{1D433}', "\u{7A}"),
+    ('\u{1D434}', "\u{41}"),
+    ('\u{1D435}', "\u{42}"),
+    ('\u{1D436}', "\u{43}"),
+    ('\u{1D437}', "\u{44}"),
+    ('\u{1D438}', "\u{45}"),
+    ('\u{1D439}', "\u{46}"),
+    ('\u{1D43A}', "\u{47}"),
+    ('\u{1D43B}', "\u{48}"),
+    ('\u{1D43C}', "\u{49}"),
+    ('\u{1D43D}', "\u{4A}"),
+    ('\u{1D43E}', "\u{4B}"),
+    ('\u{1D43F}', "\u{4C}"),
+    ('\u{1D440}', "\u{4D}"),
+    ('\u{1D441}', "\u{4E}"),
+    ('\u{1D442}', "\u{4F}"),
+    ('\u{1D443}', "\u{50}"),
+    ('\u{1D444}', "\u{51}"),
+    ('\u{1D445}', "\u{52}"),
+    ('\u{1D446}', "\u{53}"),
+    ('\u{1D447}', "\u{54}"),
+    ('\u{1D448}', "\u{55}"),
+    ('\u{1D449}', "\u{56}"),
+    ('\u{1D44A}', "\u{57}"),
+    ('\u{1D44B}', "\u{58}"),
+    ('\u{1D44C}', "\u{59}"),
+    ('\u{1D44D}', "\u{5A}"),
+    ('\u{1D44E}', "\u{61}"),
+    ('\u{1D44F}', "\u{62}"),
+    ('\u{1D450}', "\u{63}"),
+    ('\u{1D451}', "\u{64}"),
+    ('\u{1D452}', "\u{65}"),
+    ('\u{1D453}', "\u{66}"),
+    ('\u{1D454}', "\u{67}"),
+    ('\u{1D456}', "\u{69}"),
+    ('\u{1D457}', "\u{6A}"),
+    ('\u{1D458}', "\u{6B}"),
+    ('\u{1D459}', "\u{6C}"),
+    ('\u{1D45A}', "\u{6D}"),
+    ('\u{1D45B}', "\u{6E}"),
+    ('\u{1D45C}', "\u{6F}"),
+    ('\u{1D45D}', "\u{70}"),
+    ('\u{1D45E}', "\u{71}"),
+    ('\u{1D45F}', "\u{72}"),
+    ('\u{1D460}', "\u{73}"),
+    ('\u{1D461}', "\u{74}"),
+    ('\u{1D462}', "\u{75}"),
+    ('\u{1D463}', "\u{76}"),
+    ('\u{1D464}', "\u{77}"),
+    ('\u{1D465}', "\u{78}"),
+    ('\u{1D466}', "\u{79}"),
+    ('\u{1D467}', "\u{7A}"),
+    ('\u{1D468}', "\u{41}"),
+    ('\u{1D469}', "\u{42}"),
+    ('\u{1D46A}', "\u{43}"),
+    ('\u{1D46B}', "\u{44}"),
+    ('\u{1D46C}', "\u{45}"),
+    ('\u{1D46D}', "\u{46}"),
+    ('\u{1D46E}', "\u{47}"),
+    ('\u{1D46F}', "\u{48}"),
+    ('\u{1D470}', "\u{49}"),
+    ('\u{1D471}', "\u{4A}"),
+    ('\u{1D472}', "\u{4B}"),
+    ('\u{1D473}', "\u{4C}"),
+    ('\u{1D474}', "\u{4D}"),
+    ('\u{1D475}', "\u{4E}"),
+    ('\u{1D476}', "\u{4F}"),
+    ('\u{1D477}', "\u{50}"),
+    ('\u{1D478}', "\u{51}"),
+    ('\u{1D479}', "\u{52}"),
+    ('\u{1D47A}', "\u{53}"),
+    ('\u{1D47B}', "\u{54}"),
+    ('\u{1D47C}', "\u{55}"),
+    ('\u{1D47D}', "\u{56}"),
+    ('\u{1D47E}', "\u{57}"),
+    ('\u{1D47F}', "\u{58}"),
+    ('\u{1D480}', "\u{59}"),
+    ('\u{1D481}', "\u{5A}"),
+    ('\u{1D482}', "\u{61}"),
+    ('\u{1D483}', "\u{62}"),
+    ('\u{1D484}', "\u{63}"),
+    ('\u{1D485}', "\u{64}"),
+    ('\u{1D486}', "\u{65}"),
+    ('\u{1D487}', "\u{66}"),
+    ('\u{1D488}', "\u{67}"),
+    ('\u{1D489}', "\u{68}"),
+    ('\u{1D48A}', "\u{69}"),
+    ('\u{1D48B}', "\u{6A}"),
+    ('\u{1D48C}', "\u{6B}"),
+    ('\u{1D48D}', "\u{6C}"),
+    ('\u{1D48E}', "\u{6D}"),
+    ('\u{1D48F}', "\u{6E}"),
+    ('\u{1D490}', "\u{6F}"),
+    ('\u{1D491}', "\u{70}"),
+    ('\u{1D492}', "\u{71}"),
+    ('\u{1D493}', "\u{72}"),
+    ('\u{1D494}', "\u{73}"),
+    ('\u{1D495}', "\u{74}"),
+    ('\u{1D496}', "\u{75}"),
+    ('\u{1D497}', "\u{76}"),
+    ('\u{1D498}', "\u{77}"),
+    ('\u{1D499}', "\u{78}"),
+    ('\u{1D49A}', "\u{79}"),
+    ('\u{1D49B}', "\u{7A}"),
+    ('\u{1D49C}', "\u{41}"),
+    ('\u{1D49E}', "\u{43}"),
+    ('\u{1D49F}', "\u{44}"),
+    ('\u{1D4A2}', "\u{47}"),
+    ('\u{1D4A5}', "\u{4A}"),
+    ('\u{1D4A6}', "\u{4B}"),
+    ('\u{1D4A9}', "\u{4E}"),
+    ('\u{1D4AA}', "\u{4F}"),
+    ('\u{1D4AB}', "\u{50}"),
+    ('\u{1D4AC}', "\u{51}"),
+    ('\u{1D4AE}', "\u{53}"),
+    ('\u{1D4AF}', "\u{54}"),
+    ('\u{1D4B0}', "\u{55}"),
+    ('\u{1D4B1}', "\u{56}"),
+    ('\u{1D4B2}', "\u{57}"),
+    ('\u{1D4B3}', "\u{58}"),
+    ('\u{1D4B4}', "\u{59}"),
+    ('\u{1D4B5}', "\u{5A}"),
+    ('\u{1D4B6}', "\u{61}"),
+    ('\u{1D4B7}', "\u{62}"),
+    ('\u{1D4B8}', "\u{63}"),
+    ('\u{1D4B9}', "\u{64}"),
+    ('\u{1D4BB}', "\u{66}"),
+    ('\u{1D4BD}', "\u{68}"),
+    ('\u{1D4BE}', "\u{69}"),
+    ('\u{1D4BF}', "\u{6A}"),
+    ('\u{1D4C0}', "\u{6B}"),
+    ('\u{1D4C1}', "\u{6C}"),
+    ('\u{1D4C2}', "\u{6D}"),
+    ('\u{1D4C3}', "\u{6E}"),
+    ('\u{1D4C5}', "\u{70}"),
+    ('\u{1D4C6}', "\u{71}"),
+    ('\u{1D4C7}', "\u{72}"),
+    ('\u{1D4C8}', "\u{73}"),
+    ('\u{1D4C9}', "\u{74}"),
+    ('\u{1D4CA}', "\u{75}"),
+    ('\u{1D4CB}', "\u{76}"),
+    ('\u{1D4CC}', "\u{77}"),
+    ('\u{1D4CD}', "\u{78}"),
+    ('\u{1D4CE}', "\u{79}"),
+    ('\u{1D4CF}', "\u{7A}"),
+    ('\u{1D4D0}', "\u{41}"),
+    ('\u{1D4D1}', "\u{42}"),
+    ('\u{1D4D2}', "\u{43}"),
+    ('\u{1D4D3}', "\u{44}"),
+    ('\u{1D4D4}', "\u{45}"),
+    ('\u{1D4D5}', "\u{46}"),
+    ('\u{1D4D6}', "\u{47}"),
+    ('\u{1D4D7}', "\u{48}"),
+    ('\u{1D4D8}', "\u{49}"),
+    ('\u{1D4D9}', "\u{4A}"),
+    ('\u{1D4DA}', "\u{4B}"),
+    ('\u{1D4DB}', "\u{4C}"),
+    ('\u{1D4DC}', "\u{4D}"),
+    ('\u{1D4DD}', "\u{4E}"),
+    ('\u{1D4DE}', "\u{4F}"),
+    ('\u{1D4DF}', "\u{50}"),
+    ('\u{1D4E0}', "\u{51}"),
+    ('\u{1D4E1}', "\u{52}"),
+    ('\u{1D4E2}', "\u{53}"),
+    ('\u{1D4E3}', "\u{54}"),
+    ('\u{1D4E4}', "\u{55}"),
+    ('\u{1D4E5}', "\u{56}"),
+    ('\u{1D4E6}', "\u{57}"),
+    ('\u{1D4E7}', "\u{58}"),
+    ('\u{1D4E8}', "\u{59}"),
+    ('\u{1D4E9}', "\u{5A}"),
+    ('\u{1D4EA}', "\u{61}"),
+    ('\u{1D4EB}', "\u{62}"),
+    ('\u{1D4EC}', "\u{63}"),
+    ('\u{1D4ED}', "\u{64}"),
+    ('\u{1D4EE}', "\u{65}"),
+    ('\u{1D4EF}', "\u{66}"),
+    ('\u{1D4F0}', "\u{67}"),
+    ('\u{1D4F1}', "\u{68}"),
+    ('\u{1D4F2}', "\u{69}"),
+    ('\u{1D4F3}', "\u{6A}"),
+    ('\u{1D4F4}', "\u{6B}"),
+    ('\u{1D4F5}', "\u{6C}"),
+    ('\u{1D4F6}', "\u{6D}"),
+    ('\u{1D4F7}', "\u{6E}"),
+    ('\u{1D4F8}', "\u{6F}"),
+    ('\u{1D4F9}', "\u{70}"),
+    ('\u{1D4FA}', "\u{71}"),
+    ('\u{1D4FB}', "\u{72}"),
+    ('\u{1D4FC}', "\u{73}"),
+    ('\u{1D4FD}', "\u{74}"),
+    ('\u{1D4FE}', "\u{75}"),
+    ('\u{1D4FF}', "\u{76}"),
+    ('\u{1D500}', "\u{77}"),
+    ('\u{1D501}', "\u{78}"),
+    ('\u{1D502}', "\u{79}"),
+    ('\u{1D503}', "\u{7A}"),
+    ('\u{1D504}', "\u{41}"),
+    ('\u{1D505}', "\u{42}"),
+    ('\u{1D507}', "\u{44}"),
+    ('\u{1D508}', "\u{45}"),
+    ('\u{1D509}', "\u{46}"),
+    ('\u{1D50A}', "\u{47}"),
+    ('\u{1D50D}', "\u{4A}"),
+    ('\u{1D50E}', "\u{4B}"),
+    ('\u{1D50F}', "\u{4C}"),
+    ('\u{1D510}', "\u{4D}"),
+    ('\u{1D511}', "\u{4E}"),
+    ('\u{1D512}', "\u{4F}"),
+    ('\u{1D513}', "\u{50}"),
+    ('\u{1D514}', "\u{51}"),
+    ('\u{1D516}', "\u{53}"),
+    ('\u{1D517}', "\u{54}"),
+    ('\u{1D518}', "\u{55}"),
+    ('\u{1D519}', "\u{56}"),
+    ('\u{1D51A}', "\u{57}"),
+    ('\u{1D51B}', "\u{58}"),
+    ('\u{1D51C}', "\u{59}"),
+    ('\u{1D51E}', "\u{61}"),
+    ('\u{1D51F}', "\u{62}"),
+    ('\u{1D520}', "\u{63}"),
+    ('\u{1D521}', "\u{64}"),
+    ('\u{1D522}', "\u{65}"),
+    ('\u{1D523}', "\u{66}"),
+    ('\u{1D524}', "\u{67}"),
+    ('\u{1D525}', "\u{68}"),
+    ('\u{1D526}', "\u{69}"),
+    ('\u{1D527}', "\u{6A}"),
+    ('\u{1D528}', "\u{6B}"),
+    ('\u{1D529}', "\u{6C}"),
+    ('\u{1D52A}', "\u{6D}"),
+    ('\u{1D52B}', "\u{6E}"),
+    ('\u{1D52C}', "\u{6F}"),
+    ('\u{1D52D}', "\u{70}"),
+    ('\u{1D52E}', "\u{71}"),
+    ('\u{1D52F}', "\u{72}"),
+    ('\u{1D530}', "\u{73}"),
+    ('\u{1D531}', "\u{74}"),
+    ('\u{1D532}', "\u{75}"),
+    ('\u{1D533}', "\u{76}"),
+    ('\u{1D534}', "\u{77}"),
+    ('\u{1D535}', "\u{78}"),
+    ('\u{1D536}', "\u{79}"),
+    ('\u{1D537}', "\u{7A}"),
+    ('\u{1D538}', "\u{41}"),
+    ('\u{1D539}', "\u{42}"),
+    ('\u{1D53B}', "\u{44}"),
+    ('\u{1D53C}', "\u{45}"),
+    ('\u{1D53D}', "\u{46}"),
+    ('\u{1D53E}', "\u{47}"),
+    ('\u{1D540}', "\u{49}"),
+    ('\u{1D541}', "\u{4A}"),
+    ('\u{1D542}', "\u{4B}"),
+    ('\u{1D543}', "\u{4C}"),
+    ('\u{1D544}', "\u{4D}"),
+    ('\u{1D546}', "\u{4F}"),
+    ('\u{1D54A}', "\u{53}"),
+    ('\u{1D54B}', "\u{54}"),
+    ('\u{1D54C}', "\u{55}"),
+    ('\u{1D54D}', "\u{56}"),
+    ('\u{1D54E}', "\u{57}"),
+    ('\u{1D54F}', "\u{58}"),
+    ('\u{1D550}', "\u{59}"),
+    ('\u{1D552}', "\u{61}"),
+    ('\u{1D553}', "\u{62}"),
+    ('\u{1D554}', "\u{63}"),
+    ('\u{1D555}', "\u{64}"),
+    ('\u{1D556}', "\u{65}"),
+    ('\u{1D557}', "\u{66}"),
+    ('\u{1D558}', "\u{67}"),
+    ('\u{1D559}', "\u{68}"),
+    ('\u{1D55A}', "\u{69}"),
+    ('\u{1D55B}', "\u{6A}"),
+    ('\u{1D55C}', "\u{6B}"),
+    ('\u{1D55D}', "\u{6C}"),
+    ('\u{1D55E}', "\u{6D}"),
+    ('\u{1D55F}', "\u{6E}"),
+    ('\u{1D560}', "\u{6F}"),
+    ('\u{1D561}', "\u{70}"),
+    ('\u{1D562}', "\u{71}"),
+    ('\u{1D563}', "\u{72}"),
+    ('\u{1D564}', "\u{73}"),
+    ('\u{1D565}', "\u{74}"),
+    ('\u{1D566}', "\u{75}"),
+    ('\u{1D567}', "\u{76}"),
+    ('\u{1D568}', "\u{77}"),
+    ('\u{1D569}', "\u{78}"),
+    ('\u{1D56A}', "\u{79}"),
+    ('\u{1D56B}', "\u{7A}"),
+    ('\u{1D56C}', "\u{41}"),
+    ('\u{1D56D}', "\u{42}"),
+    ('\u{1D56E}', "\u{43}"),
+    ('\u{1D56F}', "\u{44}"),
+    ('\u{1D570}', "\u{45}"),
+    ('\u{1D571}', "\u{46}"),
+    ('\u{1D572}', "\u{47}"),
+    ('\u{1D573}', "\u{48}"),
+    ('\u{1D574}', "\u{49}"),
+    ('\u{1D575}', "\u{4A}"),
+    ('\u{1D576}', "\u{4B}"),
+    ('\u{1D577}', "\u{4C}"),
+    ('\u{1D578}', "\u{4D}"),
+    ('\u{1D579}', "\u{4E}"),
+    ('\u{1D57A}', "\u{4F}"),
+    ('\u{1D57B}', "\u{50}"),
+    ('\u{1D57C}', "\u{51}"),
+    ('\u{1D57D}', "\u{52}"),
+    ('\u{1D57E}', "\u{53}"),
+    ('\u{1D57F}', "\u{54}"),
+    ('\u{1D580}', "\u{55}"),
+    ('\u{1D581}', "\u{56}"),
+    ('\u{1D582}', "\u{57}"),
+    ('\u{1D583}', "\u{58}"),
+    ('\u{1D584}', "\u{59}"),
+    ('\u{1D585}', "\u{5A}"),
+    ('\u{1D586}', "\u{61}"),
+    ('\u{1D587}', "\u{62}"),
+    ('\u{1D588}', "\u{63}"),
+    ('\u{1D589}', "\u{64}"),
+    ('\u{1D58A}', "\u{65}"),
+    ('\u{1D58B}', "\u{66}"),
+    ('\u{1D58C}', "\u{67}"),
+    ('\u{1D58D}', "\u{68}"),
+    ('\u{1D58E}', "\u{69}"),
+    ('\u{1D58F}', "\u{6A}"),
+    ('\u{1D590}', "\u{6B}"),
+    ('\u{1D591}', "\u{6C}"),
+    ('\u{1D592}', "\u{6D}"),
+    ('\u{1D593}', "\u{6E}"),
+    ('\u{1D594}', "\u{6F}"),
+    ('\u{1D595}', "\u{70}"),
+    ('\u{1D596}', "\u{71}"),
+    ('\u{1D597}', "\u{72}"),
+    ('\u{1D598}', "\u{73}"),
+    ('\u{1D599}', "\u{74}"),
+    ('\u{1D59A}', "\u{75}"),
+    ('\u{1D59B}', "\u{76}"),
+    ('\u{1D59C}', "\u{77}"),
+    ('\u{1D59D}', "\u{78}"),
+    ('\u{1D59E}', "\u{79}"),
+    ('\u{1D59F}', "\u{7A}"),
+    ('\u{1D5A0}', "\u{41}"),
+    ('\u{1D5A1}', "\u{42}"),
+    ('\u{1D5A2}', "\u{43}"),
+    ('\u{1D5A3}', "\u{44}"),
+    ('\u{1D5A4}', "\u{45}"),
+    ('\u{1D5A5}', "\u{46}"),
+    ('\u{1D5A6}', "\u{47}"),
+    ('\u{1D5A7}', "\u{48}"),
+    ('\u{1D5A8}', "\u{49}"),
+    ('\u{1D5A9}', "\u{4A}"),
+    ('\u{1D5AA}', "\u{4B}"),
+    ('\u{1D5AB}', "\u{4C}"),
+    ('\u{1D5AC}', "\u{4D}"),
+    ('\u{1D5AD}', "\u{4E}"),
+    ('\u{1D5AE}', "\u{4F}"),
+    ('\u{1D5AF}', "\u{50}"),
+    ('\u{1D5B0}', "\u{51}"),
+    ('\u{1D5B1}', "\u{52}"),
+    ('\u{1D5B2}', "\u{53}"),
+    ('\u{1D5B3}', "\u{54}"),
+    ('\u{1D5B4}', "\u{55}"),
+    ('\u{1D5B5}', "\u{56}"),
+    ('\u{1D5B6}', "\u{57}"),
+    ('\u{1D5B7}', "\u{58}"),
+    ('\u{1D5B8}', "\u{59}"),
+    ('\u{1D5B9}', "\u{5A}"),
+    ('\u{1D5BA}', "\u{61}"),
+    ('\u{1D5BB}', "\u{62}"),
+    ('\u{1D5BC}', "\u{63}"),
+    ('\u{1D5BD}', "\u{64}"),
+    ('\u{1D5BE}', "\u{65}"),
+    ('\u{1D5BF}', "\u{66}"),
+    ('\u{1D5C0}', "\u{67}"),
+    ('\u{1D5C1}', "\u{68}"),
+    ('\u{1D5C2}', "\u{69}"),
+    ('\u{1D5C3}', "\u{6A}"),
+    ('\u{1D5C4}', "\u{6B}"),
+    ('\u{1D5C5}', "\u{6C}"),
+    ('\u{1D5C6}', "\u{6D}"),
+    ('\u{1D5C7}', "\u{6E}"),
+    ('\u{1D5C8}', "\u{6F}"),
+    ('\u{1D5C9}', "\u{70}"),
+    ('\u{1D5CA}', "\u{71}"),
+    ('\u{1D5CB}', "\u{72}"),
+    ('\u{1D5CC}', "\u{73}"),
+    ('\u{1D5CD}', "\u{74}"),
+    ('\u{1D5CE}', "\u{75}"),
+    ('\u{1D5CF}', "\u{76}"),
+    ('\u{1D5D0}', "\u{77}"),
+    ('\u{1D5D1}', "\u{78}"),
+    ('\u{1D5D2}', "\u{79}"),
+    ('\u{1D5D3}', "\u{7A}"),
+    ('\u{1D5D4}', "\u{41}"),
+    ('\u{1D5D5}', "\u{42}"),
+    ('\u{1D5D6}', "\u{43}"),
+    ('\u{1D5D7}', "\u{44}"),
+    ('\u{1D5D8}', "\u{45}"),
+    ('\u{1D5D9}', "\u{46}"),
+    ('\u{1D5DA}', "\u{47}"),
+    ('\u{1D5DB}', "\u{48}"),
+    ('\u{1D5DC}', "\u{49}"),
+    ('\u{1D5DD}', "\u{4A}"),
+    ('\u{1D5DE}', "\u{4B}"),
+    ('\u{1D5DF}', "\u{4C}"),
+    ('\u{1D5E0}', "\u{4D}"),
+    ('\u{1D5E1}', "\u{4E}"),
+    ('\u{1D5E2}', "\u{4F}"),
+    ('\u{1D5E3}', "\u{50}"),
+    ('\u{1D5E4}', "\u{51}"),
+    ('\u{1D5E5}', "\u{52}"),
+    ('\u{1D5E6}', "\u{53}"),
+    ('\u{1D5E7}', "\u{54}"),
+    ('\u{1D5E8}', "\u{55}"),
+    ('\u{1D5E9}', "\u{56}"),
+    ('\u{1D5EA}', "\u{57}"),
+    ('\u{1D5EB}', "\u{58}"),
+    ('\u{1D5EC}', "\u{59}"),
+    ('\u{1D5ED}', "\u{5A}"),
+    ('\u{1D5EE}', "\u{61}"),
+    ('\u{1D5EF}', "\u{62}"),
+    ('\u{1D5F0}', "\u{63}"),
+    ('\u{1D5F1}', "\u{64}"),
+    ('\u{1D5F2}', "\u{65}"),
+    ('\u{1D5F3}', "\u{66}"),
+    ('\u{1D5F4}', "\u{67}"),
+    ('\u{1D5F5}', "\u{68}"),
+    ('\u{1D5F6}', "\u{69}"),
+    ('\u{1D5F7}', "\u{6A}"),
+    ('\u{1D5F8}', "\u{6B}"),
+    ('\u{1D5F9}', "\u{6C}"),
+    ('\u{1D5FA}', "\u{6D}"),
+    ('\u{1D5FB}', "\u{6E}"),
+    ('\u{1D5FC}', "\u{6F}"),
+    ('\u{1D5FD}', "\u{70}"),
+    ('\u{1D5FE}', "\u{71}"),
+    ('\u{1D5FF}', "\u{72}"),
+    ('\u{1D600}', "\u{73}"),
+    ('\u{1D601}', "\u{74}"),
+    ('\u{1D602}', "\u{75}"),
+    ('\u{1D603}', "\u{76}"),
+    ('\u{1D604}', "\u{77}"),
+    ('\u{1D605}', "\u{78}"),
+    ('\u{1D606}', "\u{79}"),
+    ('\u{1D607}', "\u{7A}"),
+    ('\u{1D608}', "\u{41}"),
+    ('\u{1D609}', "\u{42}"),
+    ('\u{1D60A}', "\u{43}"),
+    ('\u{1D60B}', "\u{44}"),
+    ('\u{1D60C}', "\u{45}"),
+    ('\u{1D60D}', "\u{46}"),
+    ('\u{1D60E}', "\u{47}"),
+    ('\u{1D60F}', "\u{48}"),
+    ('\u{1D610}', "\u{49}"),
+    ('\u{1D611}', "\u{4A}"),
+    ('\u{1D612}', "\u{4B}"),
+    ('\u{1D613}', "\u{4C}"),
+    ('\u{1D614}', "\u{4D}"),
+    ('\u{1D615}', "\u{4E}"),
+    ('\u{1D616}', "\u{4F}"),
+    ('\u{1D617}', "\u{50}"),
+    ('\u{1D618}', "\u{51}"),
+    ('\u{1D619}', "\u{52}"),
+    ('\u{1D61A}', "\u{53}"),
+    ('\u{1D61B}', "\u{54}"),
+    ('\u{1D61C}', "\u{55}"),
+    ('\u{1D61D}', "\u{56}"),
+    ('\u{1D61E}', "\u{57}"),
+    ('\u{1D61F}', "\u{58}"),
+    ('\u{1D620}', "\u{59}"),
+    ('\u{1D621}', "\u{5A}"),
+    ('\u{1D622}', "\u{61}"),
+    ('\u{1D623}', "\u{62}"),
+    ('\u{1D624}', "\u{63}"),
+    ('\u{1D625}', "\u{64}"),
+    ('\u{1D626}', "\u{65}"),
+    ('\u{1D627}', "\u{66}"),
+    ('\u{1D628}', "\u{67}"),
+    ('\u{1D629}', "\u{68}"),
+    ('\u{1D62A}', "\u{69}"),
+    ('\u{1D62B}', "\u{6A}"),
+    ('\u{1D62C}', "\u{6B}"),
+    ('\u{1D62D}', "\u{6C}"),
+    ('\u{1D62E}', "\u{6D}"),
+    ('\u{1D62F}', "\u{6E}"),
+    ('\u{1D630}', "\u{6F}"),
+    ('\u{1D631}', "\u{70}"),
+    ('\u{1D632}', "\u{71}"),
+    ('\u{1D633}', "\u{72}"),
+    ('\u{1D634}', "\u{73}"),
+    ('\u{1D635}', "\u{74}"),
+    ('\u{1D636}', "\u{75}"),
+    ('\u{1D637}', "\u{76}"),
+    ('\u{1D638}', "\u{77}"),
+    ('\u{1D639}', "\u{78}"),
+    ('\u{1D63A}', "\u{79}"),
+    ('\u{1D63B}', "\u{7A}"),
+    ('\u{1D63C}', "\u{41}"),
+    ('\u{1D63D}', "\u{42}"),
+    ('\u{1D63E}', "\u{43}"),
+    ('\u{1D63F}', "\u{44}"),
+    ('\u{1D640}', "\u{45}"),
+    ('\u{1D641}', "\u{46}"),
+    ('\u{1D642}', "\u{47}"),
+    ('\u{1D643}', "\u{48}"),
+    ('\u{1D644}', "\u{49}"),
+    ('\u{1D645}', "\u{4A}"),
+    ('\u{1D646}', "\u{4B}"),
+    ('\u{1D647}', "\u{4C}"),
+    ('\u{1D648}', "\u{4D}"),
+    ('\u{1D649}', "\u{4E}"),
+    ('\u{1D64A}', "\u{4F}"),
+    ('\u{1D64B}', "\u{50}"),
+    ('\u{1D64C}', "\u{51}"),
+    ('\u{1D64D}', "\u{52}"),
+    ('\u{1D64E}', "\u{53}"),
+    ('\u{1D64F}', "\u{54}"),
+    ('\u{1D650}', "\u{55}"),
+    ('\u{1D651}', "\u{56}"),
+    ('\u{1D652}', "\u{57}"),
+    ('\u{1D653}', "\u{58}"),
+    ('\u{1D654}', "\u{59}"),
+    ('\u{1D655}', "\u{5A}"),
+    ('\u{1D656}', "\u{61}"),
+    ('\u{1D657}', "\u{62}"),
+    ('\u{1D658}', "\u{63}"),
+    ('\u{1D659}', "\u{64}"),
+    ('\u{1D65A}', "\u{65}"),
+    ('\u{1D65B}', "\u{66}"),
+    ('\u{1D65C}', "\u{67}"),
+    ('\u{1D65D}', "\u{68}"),
+    ('\u{1D65E}', "\u{69}"),
+    ('\u{1D65F}', "\u{6A}"),
+    ('\u{1D660}', "\u{6B}"),
+    ('\u{1D661}', "\u{6C}"),
+    ('\u{1D662}', "\u{6D}"),
+    ('\u{1D663}', "\u{6E}"),
+    ('\u{1D664}', "\u{6F}"),
+    ('\u{1D665}', "\u{70}"),
+    ('\u{1D666}', "\u{71}"),
+    ('\u{1D667}', "\u{72}"),
+    ('\u{1D668}', "\u{73}"),
+    ('\u{1D669}', "\u{74}"),
+    ('\u{1D66A}', "\u{75}"),
+    ('\u{1D66B}', "\u{76}"),
+    ('\u{1D66C}', "\u{77}"),
+    ('\u{1D66D}', "\u{78}"),
+    ('\u{1D66E}', "\u{79}"),
+    ('\u{1D66F}', "\u{7A}"),
+    ('\u{1D670}', "\u{41}"),
+    ('\u{1D671}', "\u{42}"),
+    ('\u{1D672}', "\u{43}"),
+    ('\u{1D673}', "\u{44}"),
+    ('\u{1D674}', "\u{45}"),
+    ('\u{1D675}', "\u{46}"),
+    ('\u{1D676}', "\u{47}"),
+    ('\u{1D677}', "\u{48}"),
+    ('\u{1D678}', "\u{49}"),
+    ('\u{1D679}', "\u{4A}"),
+    ('\u{1D67A}', "\u{4B}"),
+    ('\u{1D67B}', "\u{4C}"),
+    ('\u{1D67C}', "\u{4D}"),
+    ('\u{1D67D}', "\u{4E}"),
+    ('\u{1D67E}', "\u{4F}"),
+    ('\u{1D67F}', "\u{50}"),
+    ('\u{1D680}', "\u{51}"),
+    ('\u{1D681}', "\u{52}"),
+    ('\u{1D682}', "\u{53}"),
+    ('\u{1D683}', "\u{54}"),
+    ('\u{1D684}', "\u{55}"),
+    ('\u{1D685}', "\u{56}"),
+    ('\u{1D686}', "\u{57}"),
+    ('\u{1D687}', "\u{58}"),
+    ('\u{1D688}', "\u{59}"),
+    ('\u{1D689}', "\u{5A}"),
+    ('\u{1D68A}', "\u{61}"),
+    ('\u{1D68B}', "\u{62}"),
+    ('\u{1D68C}', "\u{63}"),
+    ('\u{1D68D}', "\u{64}"),
+    ('\u{1D68E}', "\u{65}"),
+    ('\u{1D68F}', "\u{66}"),
+    ('\u{1D690}', "\u{67}"),
+    ('\u{1D691}', "\u{68}"),
+    ('\u{1D692}', "\u{69}"),
+    ('\u{1D693}', "\u{6A}"),
+    ('\u{1D694}', "\u{6B}"),
+    ('\u{1D695}', "\u{6C}"),
+    ('\u{1D696}', "\u{6D}"),
+    ('\u{1D697}', "\u{6E}"),
+    ('\u{1D698}', "\u{6F}"),
+    ('\u{1D699}', "\u{70}"),
+    ('\u{1D69A}', "\u{71}"),
+    ('\u{1D69B}', "\u{72}"),
+    ('\u{1D69C}', "\u{73}"),
+    ('\u{1D69D}', "\u{74}"),
+    ('\u{1D69E}', "\u{75}"),
+    ('\u{1D69F}', "\u{76}"),
+    ('\u{1D6A0}', "\u{77}"),
+    ('\u{1D6A1}', "\u{78}"),
+    ('\u{1D6A2}', "\u{79}"),
+    ('\u{1D6A3}', "\u{7A}"),
+    ('\u{1D6A4}', "\u{131}"),
+    ('\u{1D6A5}', "\u{237}"),
+    ('\u{1D6A8}', "\u{391}"),
+    ('\u{1D6A9}', "\u{392}"),
+    ('\u{1D6AA}', "\u{393}"),
+    ('\u{1D6AB}', "\u{394}"),
+    ('\u{1D6AC}', "\u{395}"),
+    ('\u{1D6AD}', "\u{396}"),
+    ('\u{1D6AE}', "\u{397}"),
+    ('\u{1D6AF}', "\u{398}"),
+    ('\u{1D6B0}', "\u{399}"),
+    ('\u{1D6B1}', "\u{39A}"),
+    ('\u{1D6B2}', "\u{39B}"),
+    ('\u{1D6B3}', "\u{39C}"),
+    ('\u{1D6B4}', "\u{39D}"),
+    ('\u{1D6B5}', "\u{39E}"),
+    ('\u{1D6B6}', "\u{39F}"),
+    ('\u{1D6B7}', "\u{3A0}"),
+    ('\u{1D6B8}', "\u{3A1}"),
+    ('\u{1D6B9}', "\u{398}"),
+    ('\u{1D6BA}', "\u{3A3}"),
+    ('\u{1D6BB}', "\u{3A4}"),
+    ('\u{1D6BC}', "\u{3A5}"),
+    ('\u{1D6BD}', "\u{3A6}"),
+    ('\u{1D6BE}', "\u{3A7}"),
+    ('\u{1D6BF}', "\u{3A8}"),
+    ('\u{1D6C0}', "\u{3A9}"),
+    ('\u{1D6C1}', "\u{2207}"),
+    ('\u{1D6C2}', "\u{3B1}"),
+    ('\u{1D6C3}', "\u{3B2}"),
+    ('\u{1D6C4}', "\u{3B3}"),
+    ('\u{1D6C5}', "\u{3B4}"),
+    ('\u{1D6C6}', "\u{3B5}"),
+    ('\u{1D6C7}', "\u{3B6}"),
+    ('\u{1D6C8}', "\u{3B7}"),
+    ('\u{1D6C9}', "\u{3B8}"),
+    ('\u{1D6CA}', "\u{3B9}"),
+    ('\u{1D6CB}', "\u{3BA}"),
+    ('\u{1D6CC}', "\u{3BB}"),
+    ('\u{1D6CD}', "\u{3BC}"),
+    ('\u{1D6CE}', "\u{3BD}"),
+    ('\u{1D6CF}', "\u{3BE}"),
+    ('\u{1D6D0}', "\u{3BF}"),
+    ('\u{1D6D1}', "\u{3C0}"),
+    ('\u{1D6D2}', "\u{3C1}"),
+    ('\u{1D6D3}', "\u{3C2}"),
+    ('\u{1D6D4}', "\u{3C3}"),
+    ('\u{1D6D5}', "\u{3C4}"),
+    ('\u{1D6D6}', "\u{3C5}"),
+    ('\u{1D6D7}', "\u{3C6}"),
+    ('\u{1D6D8}', "\u{3C7}"),
+    ('\u{1D6D9}', "\u{3C8}"),
+    ('\u{1D6DA}', "\u{3C9}"),
+    ('\u{1D6DB}', "\u{2202}"),
+    ('\u{1D6DC}', "\u{3B5}"),
+    ('\u{1D6DD}', "\u{3B8}"),
+    ('\u{1D6DE}', "\u{3BA}"),
+    ('\u{1D6DF}', "\u{3C6}"),
+    ('\u{1D6E0}', "\u{3C1}"),
+    ('\u{1D6E1}', "\u{3C0}"),
+    ('\u{1D6E2}', "\u{391}"),
+    ('\u{1D6E3}', "\u{392}"),
+    ('\u{1D6E4}', "\u{393}"),
+    ('\u{1D6E5}', "\u{394}"),
+    ('\u{1D6E6}', "\u{395}"),
+    ('\u{1D6E7}', "\u{396}"),
+    ('\u{1D6E8}', "\u{397}"),
+    ('\u{1D6E9}', "\u{398}"),
+    ('\u{1D6EA}', "\u{399}"),
+    ('\u{1D6EB}', "\u{39A}"),
+    ('\u{1D6EC}', "\u{39B}"),
+    ('\u{1D6ED}', "\u{39C}"),
+    ('\u{1D6EE}', "\u{39D}"),
+    ('\u{1D6EF}', "\u{39E}"),
+    ('\u{1D6F0}', "\u{39F}"),
+    ('\u{1D6F1}', "\u{3A0}"),
+    ('\u{1D6F2}', "\u{3A1}"),
+    ('\u{1D6F3}', "\u{398}"),
+    ('\u{1D6F4}', "\u{3A3}"),
+    ('\u{1D6F5}', "\u{3A4}"),
+    ('\u{1D6F6}', "\u{3A5}"),
+    ('\u{1D6F7}', "\u{3A6}"),
+    ('\u{1D6F8}', "\u{3A7}"),
+    ('\u{1D6F9}', "\u{3A8}"),
+    ('\u{1D6FA}', "\u{3A9}"),
+    ('\u{1D6FB}', "\u{2207}"),
+    ('\u{1D6FC}', "\u{3B1}"),
+    ('\u{1D6FD}', "\u{3B2}"),
+    ('\u{1D6FE}', "\u{3B3}"),
+    ('\u{1D6FF}', "\u{3B4}"),
+    ('\u{1D700}', "\u{3B5}"),
+    ('\u{1D701}', "\u{3B6}"),
+    ('\u{1D702}', "\u{3B7}"),
+    ('\u{1D703}', "\u{3B8}"),
+    ('\u{1D704}', "\u{3B9}"),
+    ('\u{1D705}', "\u{3BA}"),
+    ('\u{1D706}', "\u{3BB}"),
+    ('\u{1D707}', "\u{3BC}"),
+    ('\u{1D708}', "\u{3BD}"),
+    ('\u{1D709}', "\u{3BE}"),
+    ('\u{1D70A}', "\u{3BF}"),
+    ('\u{1D70B}', "\u{3C0}"),
+    ('\u{1D70C}', "\u{3C1}"),
+    ('\u{1D70D}', "\u{3C2}"),
+    ('\u{1D70E}', "\u{3C3}"),
+    ('\u{1D70F}', "\u{3C4}"),
+    ('\u{1D710}', "\u{3C5}"),
+    ('\u{1D711}', "\u{3C6}"),
+    ('\u{1D712}', "\u{3C7}"),
+    ('\u{1D713}', "\u{3C8}"),
+    ('\u{1D714}', "\u{3C9}"),
+    ('\u{1D715}', "\u{2202}"),
+    ('\u{1D716}', "\u{3B5}"),
+    ('\u{1D717}', "\u{3B8}"),
+    ('\u{1D718}', "\u{3BA}"),
+    ('\u{1D719}', "\u{3C6}"),
+    ('\u{1D71A}', "\u{3C1}"),
+    ('\u{1D71B}', "\u{3C0}"),
+    ('\u{1D71C}', "\u{391}"),
+    ('\u{1D71D}', "\u{392}"),
+    ('\u{1D71E}', "\u{393}"),
+    ('\u{1D71F}', "\u{394}"),
+    ('\u{1D720}', "\u{395}"),
+    ('\u{1D721}', "\u{396}"),
+    ('\u{1D722}', "\u{397}"),
+    ('\u{1D723}', "\u{398}"),
+    ('\u{1D724}', "\u{399}"),
+    ('\u{1D725}', "\u{39A}"),
+    ('\u{1D726}', "\u{39B}"),
+    ('\u{1D727}', "\u{39C}"),
+    ('\u{1D728}', "\u{39D}"),
+    ('\u{1D729}', "\u{39E}"),
+    ('\u{1D72A}', "\u{39F}"),
+    ('\u{1D72B}', "\u{3A0}"),
+    ('\u{1D72C}', "\u{3A1}"),
+    ('\u{1D72D}', "\u{398}"),
+    ('\u{1D72E}', "\u{3A3}"),
+    ('\u{1D72F}', "\u{3A4}"),
+    ('\u{1D730}', "\u{3A5}"),
+    ('\u{1D731}', "\u{3A6}"),
+    ('\u{1D732}', "\u{3A7}"),
+    ('\u{1D733}', "\u{3A8}"),
+    ('\u{1D734}', "\u{3A9}"),
+    ('\u{1D735}', "\u{2207}"),
+    ('\u{1D736}', "\u{3B1}"),
+    ('\u{1D737}', "\u{3B2}"),
+    ('\u{1D738}', "\u{3B3}"),
+    ('\u{1D739}', "\u{3B4}"),
+    ('\u{1D73A}', "\u{3B5}"),
+    ('\u{1D73B}', "\u{3B6}"),
+    ('\u{1D73C}', "\u{3B7}"),
+    ('\u{1D73D}', "\u{3B8}"),
+    ('\u{1D73E}', "\u{3B9}"),
+    ('\u{1D73F}', "\u{3BA}"),
+    ('\u{1D740}', "\u{3BB}"),
+    ('\u{1D741}', "\u{3BC}"),
+    ('\u{1D742}', "\u{3BD}"),
+    ('\u{1D743}', "\u{3BE}"),
+    ('\u{1D744}', "\u{3BF}"),
+    ('\u{1D745}', "\u{3C0}"),
+    ('\u{1D746}', "\u{3C1}"),
+    ('\u{1D747}', "\u{3C2}"),
+    ('\u{1D748}', "\u{3C3}"),
+    ('\u{1D749}', "\u{3C4}"),
+    ('\u{1D74A}', "\u{3C5}"),
+    ('\u{1D74B}', "\u{3C6}"),
+    ('\u{1D74C}', "\u{3C7}"),
+    ('\u{1D74D}', "\u{3C8}"),
+    ('\u{1D74E}', "\u{3C9}"),
+    ('\u{1D74F}', "\u{2202}"),
+    ('\u{1D750}', "\u{3B5}"),
+    ('\u{1D751}', "\u{3B8}"),
+    ('\u{1D752}', "\u{3BA}"),
+    ('\u{1D753}', "\u{3C6}"),
+    ('\u{1D754}', "\u{3C1}"),
+    ('\u{1D755}', "\u{3C0}"),
+    ('\u{1D756}', "\u{391}"),
+    ('\u{1D757}', "\u{392}"),
+    ('\u{1D758}', "\u{393}"),
+    ('\u{1D759}', "\u{394}"),
+    ('\u{1D75A}', "\u{395}"),
+    ('\u{1D75B}', "\u{396}"),
+    ('\u{1D75C}', "\u{397}"),
+    ('\u{1D75D}', "\u{398}"),
+    ('\u{1D75E}', "\u{399}"),
+    ('\u{1D75F}', "\u{39A}"),
+    ('\u{1D760}', "\u{39B}"),
+    ('\u{1D761}', "\u{39C}"),
+    ('\u{1D762}', "\u{39D}"),
+    ('\u{1D763}', "\u{39E}"),
+    ('\u{1D764}', "\u{39F}"),
+    ('\u{1D765}', "\u{3A0}"),
+    ('\u{1D766}', "\u{3A1}"),
+    ('\u{1D767}', "\u{398}"),
+    ('\u{1D768}', "\u{3A3}"),
+    ('\u{1D769}', "\u{3A4}"),
+    ('\u{1D76A}', "\u{3A5}"),
+    ('\u{1D76B}', "\u{3A6}"),
+    ('\u{1D76C}', "\u{3A7}"),
+    ('\u{1D76D}', "\u{3A8}"),
+    ('\u{1D76E}', "\u{3A9}"),
+    ('\u{1D76F}', "\u{2207}"),
+    ('\u{1D770}', "\u{3B1}"),
+    ('\u{1D771}', "\u{3B2}"),
+    ('\u{1D772}', "\u{3B3}"),
+    ('\u{1D773}', "\u{3B4}"),
+    ('\u{1D774}', "\u{3B5}"),
+    ('\u{1D775}', "\u{3B6}"),
+    ('\u{1D776}', "\u{3B7}"),
+    ('\u{1D777}', "\u{3B8}"),
+    ('\u{1D778}', "\u{3B9}"),
+    ('\u{1D779}', "\u{3BA}"),
+    ('\u{1D77A}', "\u{3BB}"),
+    ('\u{1D77B}', "\u{3BC}"),
+    ('\u{1D77C}', "\u{3BD}"),
+    ('\u{1D77D}', "\u{3BE}"),
+    ('\u{1D77E}', "\u{3BF}"),
+    ('\u{1D77F}', "\u{3C0}"),
+    ('\u{1D780}', "\u{3C1}"),
+    ('\u{1D781}', "\u{3C2}"),
+    ('\u{1D782}', "\u{3C3}"),
+    ('\u{1D783}', "\u{3C4}"),
+    ('\u{1D784}', "\u{3C5}"),
+    ('\u{1D785}', "\u{3C6}"),
+    ('\u{1D786}', "\u{3C7}"),
+    ('\u{1D787}', "\u{3C8}"),
+    ('\u{1D788}', "\u{3C9}"),
+    ('\u{1D789}', "\u{2202}"),
+    ('\u{1D78A}', "\u{3B5}"),
+    ('\u{1D78B}', "\u{3B8}"),
+    ('\u{1D78C}', "\u{3BA}"),
+    ('\u{1D78D}', "\u{3C6}"),
+    ('\u{1D78E}', "\u{3C1}"),
+    ('\u{1D78F}', "\u{3C0}"),
+    ('\u{1D790}', "\u{391}"),
+    ('\u{1D791}', "\u{392}"),
+    ('\u{1D792}', "\u{393}"),
+    ('\u{1D793}', "\u{394}"),
+    ('\u{1D794}', "\u{395}"),
+    ('\u{1D795}', "\u{396}"),
+    ('\u{1D796}', "\u{397}"),
+    ('\u{1D797}', "\u{398}"),
+    ('\u{1D798}', "\u{399}"),
+    ('\u{1D799}', "\u{39A}"),
+    ('\u{1D79A}', "\u{39B}"),
+    ('\u{1D79B}', "\u{39C}"),
+    ('\u{1D79C}', "\u{39D}"),
+    ('\u{1D79D}', "\u{39E}"),
+    ('\u{1D79E}', "\u{39F}"),
+    ('\u{1D79F}', "\u{3A0}"),
+    ('\u{1D7A0}', "\u{3A1}"),
+    ('\u{1D7A1}', "\u{398}"),
+    ('\u{1D7A2}', "\u{3A3}"),
+    ('\u{1D7A3}', "\u{3A4}"),
+    ('\u{1D7A4}', "\u{3A5}"),
+    ('\u{1D7A5}', "\u{3A6}"),
+    ('\u{1D7A6}', "\u{3A7}"),
+    ('\u{1D7A7}', "\u{3A8}"),
+    ('\u{1D7A8}', "\u{3A9}"),
+    ('\u{1D7A9}', "\u{2207}"),
+    ('\u{1D7AA}', "\u{3B1}"),
+    ('\u{1D7AB}', "\u{3B2}"),
+    ('\u{1D7AC}', "\u{3B3}"),
+    ('\u{1D7AD}', "\u{3B4}"),
+    ('\u{1D7AE}', "\u{3B5}"),
+    ('\u{1D7AF}', "\u{3B6}"),
+    ('\u{1D7B0}', "\u{3B7}"),
+    ('\u{1D7B1}', "\u{3B8}"),
+    ('\u{1D7B2}', "\u{3B9}"),
+    ('\u{1D7B3}', "\u{3BA}"),
+    ('\u{1D7B4}', "\u{3BB}"),
+    ('\u{1D7B5}', "\u{3BC}"),
+    ('\u{1D7B6}', "\u{3BD}"),
+    ('\u{1D7B7}', "\u{3BE}"),
+    ('\u{1D7B8}', "\u{3BF}"),
+    ('\u{1D7B9}', "\u{3C0}"),
+    ('\u{1D7BA}', "\u{3C1}"),
+    ('\u{1D7BB}', "\u{3C2}"),
+    ('\u{1D7BC}', "\u{3C3}"),
+    ('\u{1D7BD}', "\u{3C4}"),
+    ('\u{1D7BE}', "\u{3C5}"),
+    ('\u{1D7BF}', "\u{3C6}"),
+    ('\u{1D7C0}', "\u{3C7}"),
+    ('\u{1D7C1}', "\u{3C8}"),
+    ('\u{1D7C2}', "\u{3C9}"),
+    ('\u{1D7C3}', "\u{2202}"),
+    ('\u{1D7C4}', "\u{3B5}"),
+    ('\u{1D7C5}', "\u{3B8}"),
+    ('\u{1D7C6}', "\u{3BA}"),
+    ('\u{1D7C7}', "\u{3C6}"),
+    ('\u{1D7C8}', "\u{3C1}"),
+    ('\u{1D7C9}', "\u{3C0}"),
+    ('\u{1D7CA}', "\u{3DC}"),
+    ('\u{1D7CB}', "\u{3DD}"),
+    ('\u{1D7CE}', "\u{30}"),
+    ('\u{1D7CF}', "\u{31}"),
+    ('\u{1D7D0}', "\u{32}"),
+    ('\u{1D7D1}', "\u{33}"),
+    ('\u{1D7D2}', "\u{34}"),
+    ('\u{1D7D3}', "\u{35}"),
+    ('\u{1D7D4}', "\u{36}"),
+    ('\u{1D7D5}', "\u{37}"),
+    ('\u{1D7D6}', "\u{38}"),
+    ('\u{1D7D7}', "\u{39}"),
+    ('\u{1D7D8}', "\u{30}"),
+    ('\u{1D7D9}', "\u{31}"),
+    ('\u{1D7DA}', "\u{32}"),
+    ('\u{1D7DB}', "\u{33}"),
+    ('\u{1D7DC}', "\u{34}"),
+    ('\u{1D7DD}', "\u{35}"),
+    ('\u{1D7DE}', "\u{36}"),
+    ('\u{1D7DF}', "\u{37}"),
+    ('\u{1D7E0}', "\u{38}"),
+    ('\u{1D7E1}', "\u{39}"),
+    ('\u{1D7E2}', "\u{30}"),
+    ('\u{1D7E3}', "\u{31}"),
+    ('\u{1D7E4}', "\u{32}"),
+    ('\u{1D7E5}', "\u{33}"),
+    ('\u{1D7E6}', "\u{34}"),
+    ('\u{1D7E7}', "\u{35}"),
+    ('\u{1D7E8}', "\u{36}"),
+    ('\u{1D7E9}', "\u{37}"),
+    ('\u{1D7EA}', "\u{38}"),
+    ('\u{1D7EB}', "\u{39}"),
+    ('\u{1D7EC}', "\u{30}"),
+    ('\u{1D7ED}', "\u{31}"),
+    ('\u{1D7EE}', "\u{32}"),
+    ('\u{1D7EF}', "\u{33}"),
+    ('\u{1D7F0}', "\u{34}"),
+    ('\u{1D7F1}', "\u{35}"),
+    ('\u{1D7F2}', "\u{36}"),
+    ('\u{1D7F3}', "\u{37}"),
+    ('\u{1D7F4}', "\u{38}"),
+    ('\u{1D7F5}', "\u{39}"),
+    ('\u{1D7F6}', "\u{30}"),
+    ('\u{1D7F7}', "\u{31}"),
+    ('\u{1D7F8}', "\u{32}"),
+    ('\u{1D7F9}', "\u{33}"),
+    ('\u{1D7FA}', "\u{34}"),
+    ('\u{1D7FB}', "\u{35}"),
+    ('\u{1D7FC}', "\u{36}"),
+    ('\u{1D7FD}', "\u{37}"),
+    ('\u{1D7FE}', "\u{38}"),
+    ('\u{1D7FF}', "\u{39}"),
+    ('\u{1EE00}', "\u{627}"),
+    ('\u{1EE01}', "\u{628}"),
+    ('\u{1EE02}', "\u{62C}"),
+    ('\u{1EE03}', "\u{62F}"),
+    ('\u{1EE05}', "\u{648}"),
+    ('\u{1EE06}', "\u{632}"),
+    ('\u{1EE07}', "\u{62D}"),
+    ('\u{1EE08}', "\u{637}"),
+    ('\u{1EE09}', "\u{64A}"),
+    ('\u{1EE0A}', "\u{643}"),
+    ('\u{1EE0B}', "\u{644}"),
+    ('\u{1EE0C}', "\u{645}"),
+    ('\u{1EE0D}', "\u{646}"),
+    ('\u{1EE0E}', "\u{633}"),
+    ('\u{1EE0F}', "\u{639}"),
+    ('\u{1EE10}', "\u{641}"),
+    ('\u{1EE11}', "\u{635}"),
+    ('\u{1EE12}', "\u{642}"),
+    ('\u{1EE13}', "\u{631}"),
+    ('\u{1EE14}', "\u{634}"),
+    ('\u{1EE15}', "\u{62A}"),
+    ('\u{1EE16}', "\u{62B}"),
+    ('\u{1EE17}', "\u{62E}"),
+    ('\u{1EE18}', "\u{630}"),
+    ('\u{1EE19}', "\u{636}"),
+    ('\u{1EE1A}', "\u{638}"),
+    ('\u{1EE1B}', "\u{63A}"),
+    ('\u{1EE1C}', "\u{66E}"),
+    ('\u{1EE1D}', "\u{6BA}"),
+    ('\u{1EE1E}', "\u{6A1}"),
+    ('\u{1EE1F}', "\u{66F}"),
+    ('\u{1EE21}', "\u{628}"),
+    ('\u{1EE22}', "\u{62C}"),
+    ('\u{1EE24}', "\u{647}"),
+    ('\u{1EE27}', "\u{62D}"),
+    ('\u{1EE29}', "\u{64A}"),
+    ('\u{1EE2A}', "\u{643}"),
+    ('\u{1EE2B}', "\u{644}"),
+    ('\u{1EE2C}', "\u{645}"),
+    ('\u{1EE2D}', "\u{646}"),
+    ('\u{1EE2E}', "\u{633}"),
+    ('\u{1EE2F}', "\u{639}"),
+    ('\u{1EE30}', "\u{641}"),
+    ('\u{1EE31}', "\u{635}"),
+    ('\u{1EE32}', "\u{642}"),
+    ('\u{1EE34}', "\u{634}"),
+    ('\u{1EE35}', "\u{62A}"),
+    ('\u{1EE36}', "\u{62B}"),
+    ('\u{1EE37}', "\u{62E}"),
+    ('\u{1EE39}', "\u{636}"),
+    ('\u{1EE3B}', "\u{63A}"),
+    ('\u{1EE42}', "\u{62C}"),
+    ('\u{1EE47}', "\u{62D}"),
+    ('\u{1EE49}', "\u{64A}"),
+    ('\u{1EE4B}', "\u{644}"),
+    ('\u{1EE4D}', "\u{646}"),
+    ('\u{1EE4E}', "\u{633}"),
+    ('\u{1EE4F}', "\u{639}"),
+    ('\u{1EE51}', "\u{635}"),
+    ('\u{1EE52}', "\u{642}"),
+    ('\u{1EE54}', "\u{634}"),
+    ('\u{1EE57}', "\u{62E}"),
+    ('\u{1EE59}', "\u{636}"),
+    ('\u{1EE5B}', "\u{63A}"),
+    ('\u{1EE5D}', "\u{6BA}"),
+    ('\u{1EE5F}', "\u{66F}"),
+    ('\u{1EE61}', "\u{628}"),
+    ('\u{1EE62}', "\u{62C}"),
+    ('\u{1EE64}', "\u{647}"),
+    ('\u{1EE67}', "\u{62D}"),
+    ('\u{1EE68}', "\u{637}"),
+    ('\u{1EE69}', "\u{64A}"),
+    ('\u{1EE6A}', "\u{643}"),
+    ('\u{1EE6C}', "\u{645}"),
+    ('\u{1EE6D}', "\u{646}"),
+    ('\u{1EE6E}', "\u{633}"),
+    ('\u{1EE6F}', "\u{639}"),
+    ('\u{1EE70}', "\u{641}"),
+    ('\u{1EE71}', "\u{635}"),
+    ('\u{1EE72}', "\u{642}"),
+    ('\u{1EE74}', "\u{634}"),
+    ('\u{1EE75}', "\u{62A}"),
+    ('\u{1EE76}', "\u{62B}"),
+    ('\u{1EE77}', "\u{62E}"),
+    ('\u{1EE79}', "\u{636}"),
+    ('\u{1EE7A}', "\u{638}"),
+    ('\u{1EE7B}', "\u{63A}"),
+    ('\u{1EE7C}', "\u{66E}"),
+    ('\u{1EE7E}', "\u{6A1}"),
+    ('\u{1EE80}', "\u{627}"),
+    ('\u{1EE81}', "\u{628}"),
+    ('\u{1EE82}', "\u{62C}"),
+    ('\u{1EE83}', "\u{62F}"),
+    ('\u{1EE84}', "\u{647}"),
+    ('\u{1EE85}', "\u{648}"),
+    ('\u{1EE86}', "\u{632}"),
+    ('\u{1EE87}', "\u{62D}"),
+    ('\u{1EE88}', "\u{637}"),
+    ('\u{1EE89}', "\u{64A}"),
+    ('\u{1EE8B}', "\u{644}"),
+    ('\u{1EE8C}', "\u{645}"),
+    ('\u{1EE8D}', "\u{646}"),
+    ('\u{1EE8E}', "\u{633}"),
+    ('\u{1EE8F}', "\u{639}"),
+    ('\u{1EE90}', "\u{641}"),
+    ('\u{1EE91}', "\u{635}"),
+    ('\u{1EE92}', "\u{642}"),
+    ('\u{1EE93}', "\u{631}"),
+    ('\u{1EE94}', "\u{634}"),
+    ('\u{1EE95}', "\u{62A}"),
+    ('\u{1EE96}', "\u{62B}"),
+    ('\u{1EE97}', "\u{62E}"),
+    ('\u{1EE98}', "\u{630}"),
+    ('\u{1EE99}', "\u{636}"),
+    ('\u{1EE9A}', "\u{638}"),
+    ('\u{1EE9B}', "\u{63A}"),
+    ('\u{1EEA1}', "\u{628}"),
+    ('\u{1EEA2}', "\u{62C}"),
+    ('\u{1EEA3}', "\u{62F}"),
+    ('\u{1EEA5}', "\u{648}"),
+    ('\u{1EEA6}', "\u{632}"),
+    ('\u{1EEA7}', "\u{62D}"),
+    ('\u{1EEA8}', "\u{637}"),
+    ('\u{1EEA9}', "\u{64A}"),
+    ('\u{1EEAB}', "\u{644}"),
+    ('\u{1EEAC}', "\u{645}"),
+    ('\u{1EEAD}', "\u{646}"),
+    ('\u{1EEAE}', "\u{633}"),
+    ('\u{1EEAF}', "\u{639}"),
+    ('\u{1EEB0}', "\u{641}"),
+    ('\u{1EEB1}', "\u{635}"),
+    ('\u{1EEB2}', "\u{642}"),
+    ('\u{1EEB3}', "\u{631}"),
+    ('\u{1EEB4}', "\u{634}"),
+    ('\u{1EEB5}', "\u{62A}"),
+    ('\u{1EEB6}', "\u{62B}"),
+    ('\u{1EEB7}', "\u{62E}"),
+    ('\u{1EEB8}', "\u{630}"),
+    ('\u{1EEB9}', "\u{636}"),
+    ('\u{1EEBA}', "\u{638}"),
+    ('\u{1EEBB}', "\u{63A}"),
+    ('\u{1F100}', "\u{30}\u{2E}"),
+    ('\u{1F101}', "\u{30}\u{2C}"),
+    ('\u{1F102}', "\u{31}\u{2C}"),
+    ('\u{1F103}', "\u{32}\u{2C}"),
+    ('\u{1F104}', "\u{33}\u{2C}"),
+    ('\u{1F105}', "\u{34}\u{2C}"),
+    ('\u{1F106}', "\u{35}\u{2C}"),
+    ('\u{1F107}', "\u{36}\u{2C}"),
+    ('\u{1F108}', "\u{37}\u{2C}"),
+    ('\u{1F109}', "\u{38}\u{2C}"),
+    ('\u{1F10A}', "\u{39}\u{2C}"),
+    ('\u{1F110}', "\u{28}\u{41}\u{29}"),
+    ('\u{1F111}', "\u{28}\u{42}\u{29}"),
+    ('\u{1F112}', "\u{28}\u{43}\u{29}"),
+    ('\u{1F113}', "\u{28}\u{44}\u{29}"),
+    ('\u{1F114}', "\u{28}\u{45}\u{29}"),
+    ('\u{1F115}', "\u{28}\u{46}\u{29}"),
+    ('\u{1F116}', "\u{28}\u{47}\u{29}"),
+    ('\u{1F117}', "\u{28}\u{48}\u{29}"),
+    ('\u{1F118}', "\u{28}\u{49}\u{29}"),
+    ('\u{1F119}', "\u{28}\u{4A}\u{29}"),
+    ('\u{1F11A}', "\u{28}\u{4B}\u{29}"),
+    ('\u{1F11B}', "\u{28}\u{4C}\u{29}"),
+    ('\u{1F11C}', "\u{28}\u{4D}\u{29}"),
+    ('\u{1F11D}', "\u{28}\u{4E}\u{29}"),
+    ('\u{1F11E}', "\u{28}\u{4F}\u{29}"),
+    ('\u{1F11F}', "\u{28}\u{50}\u{29}"),
+    ('\u{1F120}', "\u{28}\u{51}\u{29}"),
+    ('\u{1F121}', "\u{28}\u{52}\u{29}"),
+    ('\u{1F122}', "\u{28}\u{53}\u{29}"),
+    ('\u{1F123}', "\u{28}\u{54}\u{29}"),
+    ('\u{1F124}', "\u{28}\u{55}\u{29}"),
+    ('\u{1F125}', "\u{28}\u{56}\u{29}"),
+    ('\u{1F126}', "\u{28}\u{57}\u{29}"),
+    ('\u{1F127}', "\u{28}\u{58}\u{29}"),
+    ('\u{1F128}', "\u{28}\u{59}\u{29}"),
+    ('\u{1F129}', "\u{28}\u{5A}\u{29}"),
+    ('\u{1F12A}', "\u{3014}\u{53}\u{3015}"),
+    ('\u{1F12B}', "\u{43}"),
+    ('\u{1F12C}', "\u{52}"),
+    ('\u{1F12D}', "\u{43}\u{44}"),
+    ('\u{1F12E}', "\u{57}\u{5A}"),
+    ('\u{1F130}', "\u{41}"),
+    ('\u{1F131}', "\u{42}"),
+    ('\u{1F132}', "\u{43}"),
+    ('\u{1F133}', "\u{44}"),
+    ('\u{1F134}', "\u{45}"),
+    ('\u{1F135}', "\u{46}"),
+    ('\u{1F136}', "\u{47}"),
+    ('\u{1F137}', "\u{48}"),
+    ('\u{1F138}', "\u{49}"),
+    ('\u{1F139}', "\u{4A}"),
+    ('\u{1F13A}', "\u{4B}"),
+    ('\u{1F13B}', "\u{4C}"),
+    ('\u{1F13C}', "\u{4D}"),
+    ('\u{1F13D}', "\u{4E}"),
+    ('\u{1F13E}', "\u{4F}"),
+    ('\u{1F13F}', "\u{50}"),
+    ('\u{1F140}', "\u{51}"),
+    ('\u{1F141}', "\u{52}"),
+    ('\u{1F142}', "\u{53}"),
+    ('\u{1F143}', "\u{54}"),
+    ('\u{1F144}', "\u{55}"),
+    ('\u{1F145}', "\u{56}"),
+    ('\u{1F146}', "\u{57}"),
+    ('\u{1F147}', "\u{58}"),
+    ('\u{1F148}', "\u{59}"),
+    ('\u{1F149}', "\u{5A}"),
+    ('\u{1F14A}', "\u{48}\u{56}"),
+    ('\u{1F14B}', "\u{4D}\u{56}"),
+    ('\u{1F14C}', "\u{53}\u{44}"),
+    ('\u{1F14D}', "\u{53}\u{53}"),
+    ('\u{1F14E}', "\u{50}\u{50}\u{56}"),
+    ('\u{1F14F}', "\u{57}\u{43}"),
+    ('\u{1F16A}', "\u{4D}\u{43}"),
+    ('\u{1F16B}', "\u{4D}\u{44}"),
+    ('\u{1F16C}', "\u{4D}\u{52}"),
+    ('\u{1F190}', "\u{44}\u{4A}"),
+    ('\u{1F200}', "\u{307B}\u{304B}"),
+    ('\u{1F201}', "\u{30B3}\u{30B3}"),
+    ('\u{1F202}', "\u{30B5}"),
+    ('\u{1F210}', "\u{624B}"),
+    ('\u{1F211}', "\u{5B57}"),
+    ('\u{1F212}', "\u{53CC}"),
+    ('\u{1F213}', "\u{30C6}\u{3099}"),
+    ('\u{1F214}', "\u{4E8C}"),
+    ('\u{1F215}', "\u{591A}"),
+    ('\u{1F216}', "\u{89E3}"),
+    ('\u{1F217}', "\u{5929}"),
+    ('\u{1F218}', "\u{4EA4}"),
+    ('\u{1F219}', "\u{6620}"),
+    ('\u{1F21A}', "\u{7121}"),
+    ('\u{1F21B}', "\u{6599}"),
+    ('\u{1F21C}', "\u{524D}"),
+    ('\u{1F21D}', "\u{5F8C}"),
+    ('\u{1F21E}', "\u{518D}"),
+    ('\u{1F21F}', "\u{65B0}"),
+    ('\u{1F220}', "\u{521D}"),
+    ('\u{1F221}', "\u{7D42}"),
+    ('\u{1F222}', "\u{751F}"),
+    ('\u{1F223}', "\u{8CA9}"),
+    ('\u{1F224}', "\u{58F0}"),
+    ('\u{1F225}', "\u{5439}"),
+    ('\u{1F226}', "\u{6F14}"),
+    ('\u{1F227}', "\u{6295}"),
+    ('\u{1F228}', "\u{6355}"),
+    ('\u{1F229}', "\u{4E00}"),
+    ('\u{1F22A}', "\u{4E09}"),
+    ('\u{1F22B}', "\u{904A}"),
+    ('\u{1F22C}', "\u{5DE6}"),
+    ('\u{1F22D}', "\u{4E2D}"),
+    ('\u{1F22E}', "\u{53F3}"),
+    ('\u{1F22F}', "\u{6307}"),
+    ('\u{1F230}', "\u{8D70}"),
+    ('\u{1F231}', "\u{6253}"),
+    ('\u{1F232}', "\u{7981}"),
+    ('\u{1F233}', "\u{7A7A}"),
+    ('\u{1F234}', "\u{5408}"),
+    ('\u{1F235}', "\u{6E80}"),
+    ('\u{1F236}', "\u{6709}"),
+    ('\u{1F237}', "\u{6708}"),
+    ('\u{1F238}', "\u{7533}"),
+    ('\u{1F239}', "\u{5272}"),
+    ('\u{1F23A}', "\u{55B6}"),
+    ('\u{1F23B}', "\u{914D}"),
+    ('\u{1F240}', "\u{3014}\u{672C}\u{3015}"),
+    ('\u{1F241}', "\u{3014}\u{4E09}\u{3015}"),
+    ('\u{1F242}', "\u{3014}\u{4E8C}\u{3015}"),
+    ('\u{1F243}', "\u{3014}\u{5B89}\u{3015}"),
+    ('\u{1F244}', "\u{3014}\u{70B9}\u{3015}"),
+    ('\u{1F245}', "\u{3014}\u{6253}\u{3015}"),
+    ('\u{1F246}', "\u{3014}\u{76D7}\u{3015}"),
+    ('\u{1F247}', "\u{3014}\u{52DD}\u{3015}"),
+    ('\u{1F248}', "\u{3014}\u{6557}\u{3015}"),
+    ('\u{1F250}', "\u{5F97}"),
+    ('\u{1F251}', "\u{53EF}"),
+    ('\u{1FBF0}', "\u{30}"),
+    ('\u{1FBF1}', "\u{31}"),
+    ('\u{1FBF2}', "\u{32}"),
+    ('\u{1FBF3}', "\u{33}"),
+    ('\u{1FBF4}', "\u{34}"),
+    ('\u{1FBF5}', "\u{35}"),
+    ('\u{1FBF6}', "\u{36}"),
+    ('\u{1FBF7}', "\u{37}"),
+    ('\u{1FBF8}', "\u{38}"),
+    ('\u{1FBF9}', "\u{39}"),
+];
+
+/// Ranges of characters with a non-zero canonical combining class
+pub const COMBINING_CLASS: &[(char, char, u8)] = &[
+    ('\u{300}', '\u{314}', 230),
+    ('\u{315}', '\u{315}', 232),
+    ('\u{316}', '\u{319}', 220),
+    ('\u{31A}', '\u{31A}', 232),
+    ('\u{31B}', '\u{31B}', 216),
+    ('\u{31C}', '\u{320}', 220),
+    ('\u{321}', '\u{322}', 202),
+    ('\u{323}', '\u{326}', 220),
+    ('\u{327}', '\u{328}', 202),
+    ('\u{329}', '\u{333}', 220),
+    ('\u{334}', '\u{338}', 1),
+    ('\u{339}', '\u{33C}', 220),
+    ('\u{33D}', '\u{344}', 230),
+    ('\u{345}', '\u{345}', 240),
+    ('\u{346}', '\u{346}', 230),
+    ('\u{347}', '\u{349}', 220),
+    ('\u{34A}', '\u{34C}', 230),
+    ('\u{34D}', '\u{34E}', 220),
+    ('\u{350}', '\u{352}', 230),
+    ('\u{353}', '\u{356}', 220),
+    ('\u{357}', '\u{357}', 230),
+    ('\u{358}', '\u{358}', 232),
+    ('\u{359}', '\u{35A}', 220),
+    ('\u{35B}', '\u{35B}', 230),
+    ('\u{35C}', '\u{35C}', 233),
+    ('\u{35D}', '\u{35E}', 234),
+    ('\u{35F}', '\u{35F}', 233),
+    ('\u{360}', '\u{361}', 234),
+    ('\u{362}', '\u{362}', 233),
+    ('\u{363}', '\u{36F}', 230),
+    ('\u{483}', '\u{487}', 230),
+    ('\u{591}', '\u{591}', 220),
+    ('\u{592}', '\u{595}', 230),
+    ('\u{596}', '\u{596}', 220),
+    ('\u{597}', '\u{599}', 230),
+    ('\u{59A}', '\u{59A}', 222),
+    ('\u{59B}', '\u{59B}', 220),
+    ('\u{59C}', '\u{5A1}', 230),
+    ('\u{5A2}', '\u{5A7}', 220),
+    ('\u{5A8}', '\u{5A9}', 230),
+    ('\u{5AA}', '\u{5AA}', 220),
+    ('\u{5AB}', '\u{5AC}', 230),
+    ('\u{5AD}', '\u{5AD}', 222),
+    ('\u{5AE}', '\u{5AE}', 228),
+    ('\u{5AF}', '\u{5AF}', 230),
+    ('\u{5B0}', '\u{5B0}', 10),
+    ('\u{5B1}', '\u{5B1}', 11),
+    ('\u{5B2}', '\u{5B2}', 12),
+    ('\u{5B3}', '\u{5B3}', 13),
+    ('\u{5B4}', '\u{5B4}', 14),
+    ('\u{5B5}', '\u{5B5}', 15),
+    ('\u{5B6}', '\u{5B6}', 16),
+    ('\u{5B7}', '\u{5B7}', 17),
+    ('\u{5B8}', '\u{5B8}', 18),
+    ('\u{5B9}', '\u{5BA}', 19),
+    ('\u{5BB}', '\u{5BB}', 20),
+    ('\u{5BC}', '\u{5BC}', 21),
+    ('\u{5BD}', '\u{5BD}', 22),
+    ('\u{5BF}', '\u{5BF}', 23),
+    ('\u{5C1}', '\u{5C1}', 24),
+    ('\u{5C2}', '\u{5C2}', 25),
+    ('\u{5C4}', '\u{5C4}', 230),
+    ('\u{5C5}', '\u{5C5}', 220),
+    ('\u{5C7}', '\u{5C7}', 18),
+    ('\u{610}', '\u{617}', 230),
+    ('\u{618}', '\u{618}', 30),
+    ('\u{619}', '\u{619}', 31),
+    ('\u{61A}', '\u{61A}', 32),
+    ('\u{64B}', '\u{64B}', 27),
+    ('\u{64C}', '\u{64C}', 28),
+    ('\u{64D}', '\u{64D}', 29),
+    ('\u{64E}', '\u{64E}', 30),
+    ('\u{64F}', '\u{64F}', 31),
+    ('\u{650}', '\u{650}', 32),
+    ('\u{651}', '\u{651}', 33),
+    ('\u{652}', '\u{652}', 34),
+    ('\u{653}', '\u{654}', 230),
+    ('\u{655}', '\u{656}', 220),
+    ('\u{657}', '\u{65B}', 230),
+    ('\u{65C}', '\u{65C}', 220),
+    ('\u{65D}', '\u{65E}', 230),
+    ('\u{65F}', '\u{65F}', 220),
+    ('\u{670}', '\u{670}', 35),
+    ('\u{6D6}', '\u{6DC}', 230),
+    ('\u{6DF}', '\u{6E2}', 230),
+    ('\u{6E3}', '\u{6E3}', 220),
+    ('\u{6E4}', '\u{6E4}', 230),
+    ('\u{6E7}', '\u{6E8}', 230),
+    ('\u{6EA}', '\u{6EA}', 220),
+    ('\u{6EB}', '\u{6EC}', 230),
+    ('\u{6ED}', '\u{6ED}', 220),
+    ('\u{711}', '\u{711}', 36),
+    ('\u{730}', '\u{730}', 230),
+    ('\u{731}', '\u{731}', 220),
+    ('\u{732}', '\u{733}', 230),
+    ('\u{734}', '\u{734}', 220),
+    ('\u{735}', '\u{736}', 230),
+    ('\u{737}', '\u{739}', 220),
+    ('\u{73A}', '\u{73A}', 230),
+    ('\u{73B}', '\u{73C}', 220),
+    ('\u{73D}', '\u{73D}', 230),
+    ('\u{73E}', '\u{73E}', 220),
+    ('\u{73F}', '\u{741}', 230),
+    ('\u{742}', '\u{742}', 220),
+    ('\u{743}', '\u{743}', 230),
+    ('\u{744}', '\u{744}', 220),
+    ('\u{745}', '\u{745}', 230),
+    ('\u{746}', '\u{746}', 220),
+    ('\u{747}', '\u{747}', 230),
+    ('\u{748}', '\u{748}', 220),
+    ('\u{749}', '\u{74A}', 230),
+    ('\u{7EB}', '\u{7F1}', 230),
+    ('\u{7F2}', '\u{7F2}', 220),
+    ('\u{7F3}', '\u{7F3}', 230),
+    ('\u{7FD}', '\u{7FD}', 220),
+    ('\u{816}', '\u{819}', 230),
+    ('\u{81B}', '\u{823}', 230),
+    ('\u{825}', '\u{827}', 230),
+    ('\u{829}', '\u{82D}', 230),
+    ('\u{859}', '\u{85B}', 220),
+    ('\u{898}', '\u{898}', 230),
+    ('\u{899}', '\u{89B}', 220),
+    ('\u{89C}', '\u{89F}', 230),
+    ('\u{8CA}', '\u{8CE}', 230),
+    ('\u{8CF}', '\u{8D3}', 220),
+    ('\u{8D4}', '\u{8E1}', 230),
+    ('\u{8E3}', '\u{8E3}', 220),
+    ('\u{8E4}', '\u{8E5}', 230),
+    ('\u{8E6}', '\u{8E6}', 220),
+    ('\u{8E7}', '\u{8E8}', 230),
+    ('\u{8E9}', '\u{8E9}', 220),
+    ('\u{8EA}', '\u{8EC}', 230),
+    ('\u{8ED}', '\u{8EF}', 220),
+    ('\u{8F0}', '\u{8F0}', 27),
+    ('\u{8F1}', '\u{8F1}', 28),
+    ('\u{8F2}', '\u{8F2}', 29),
+    ('\u{8F3}', '\u{8F5}', 230),
+    ('\u{8F6}', '\u{8F6}', 220),
+    ('\u{8F7}', '\u{8F8}', 230),
+    ('\u{8F9}', '\u{8FA}', 220),
+    ('\u{8FB}', '\u{8FF}', 230),
+    ('\u{93C}', '\u{93C}', 7),
+    ('\u{94D}', '\u{94D}', 9),
+    ('\u{951}', '\u{951}', 230),
+    ('\u{952}', '\u{952}', 220),
+    ('\u{953}', '\u{954}', 230),
+    ('\u{9BC}', '\u{9BC}', 7),
+    ('\u{9CD}', '\u{9CD}', 9),
+    ('\u{9FE}', '\u{9FE}', 230),
+    ('\u{A3C}', '\u{A3C}', 7),
+    ('\u{A4D}', '\u{A4D}', 9),
+    ('\u{ABC}', '\u{ABC}', 7),
+    ('\u{ACD}', '\u{ACD}', 9),
+    ('\u{B3C}', '\u{B3C}', 7),
+    ('\u{B4D}', '\u{B4D}', 9),
+    ('\u{BCD}', '\u{BCD}', 9),
+    ('\u{C3C}', '\u{C3C}', 7),
+    ('\u{C4D}', '\u{C4D}', 9),
+    ('\u{C55}', '\u{C55}', 84),
+    ('\u{C56}', '\u{C56}', 91),
+    ('\u{CBC}', '\u{CBC}', 7),
+    ('\u{CCD}', '\u{CCD}', 9),
+    ('\u{D3B}', '\u{D3C}', 9),
+    ('\u{D4D}', '\u{D4D}', 9),
+    ('\u{DCA}', '\u{DCA}', 9),
+    ('\u{E38}', '\u{E39}', 103),
+    ('\u{E3A}', '\u{E3A}', 9),
+    ('\u{E48}', '\u{E4B}', 107),
+    ('\u{EB8}', '\u{EB9}', 118),
+    ('\u{EBA}', '\u{EBA}', 9),
+    ('\u{EC8}', '\u{ECB}', 122),
+    ('\u{F18}', '\u{F19}', 220),
+    ('\u{F35}', '\u{F35}', 220),
+    ('\u{F37}', '\u{F37}', 220),
+    ('\u{F39}', '\u{F39}', 216),
+    ('\u{F71}', '\u{F71}', 129),
+    ('\u{F72}', '\u{F72}', 130),
+    ('\u{F74}', '\u{F74}', 132),
+    ('\u{F7A}', '\u{F7D}', 130),
+    ('\u{F80}', '\u{F80}', 130),
+    ('\u{F82}', '\u{F83}', 230),
+    ('\u{F84}', '\u{F84}', 9),
+    ('\u{F86}', '\u{F87}', 230),
+    ('\u{FC6}', '\u{FC6}', 220),
+    ('\u{1037}', '\u{1037}', 7),
+    ('\u{1039}', '\u{103A}', 9),
+    ('\u{108D}', '\u{108D}', 220),
+    ('\u{135D}', '\u{135F}', 230),
+    ('\u{1714}', '\u{1715}', 9),
+    ('\u{1734}', '\u{1734}', 9),
+    ('\u{17D2}', '\u{17D2}', 9),
+    ('\u{17DD}', '\u{17DD}', 230),
+    ('\u{18A9}', '\u{18A9}', 228),
+    ('\u{1939}', '\u{1939}', 222),
+    ('\u{193A}', '\u{193A}', 230),
+    ('\u{193B}', '\u{193B}', 220),
+    ('\u{1A17}', '\u{1A17}', 230),
+    ('\u{1A18}', '\u{1A18}', 220),
+    ('\u{1A60}', '\u{1A60}', 9),
+    ('\u{1A75}', '\u{1A7C}', 230),
+    ('\u{1A7F}', '\u{1A7F}', 220),
+    ('\u{1AB0}', '\u{1AB4}', 230),
+    ('\u{1AB5}', '\u{1ABA}', 220),
+    ('\u{1ABB}', '\u{1ABC}', 230),
+    ('\u{1ABD}', '\u{1ABD}', 220),
+    ('\u{1ABF}', '\u{1AC0}', 220),
+    ('\u{1AC1}', '\u{1AC2}', 230),
+    ('\u{1AC3}', '\u{1AC4}', 220),
+    ('\u{1AC5}', '\u{1AC9}', 230),
+    ('\u{1ACA}', '\u{1ACA}', 220),
+    ('\u{1ACB}', '\u{1ACE}', 230),
+    ('\u{1B34}', '\u{1B34}', 7),
+    ('\u{1B44}', '\u{1B44}', 9),
+    ('\u{1B6B}', '\u{1B6B}', 230),
+    ('\u{1B6C}', '\u{1B6C}', 220),
+    ('\u{1B6D}', '\u{1B73}', 230),
+    ('\u{1BAA}', '\u{1BAB}', 9),
+    ('\u{1BE6}', '\u{1BE6}', 7),
+    ('\u{1BF2}', '\u{1BF3}', 9),
+    ('\u{1C37}', '\u{1C37}', 7),
+    ('\u{1CD0}', '\u{1CD2}', 230),
+    ('\u{1CD4}', '\u{1CD4}', 1),
+    ('\u{1CD5}', '\u{1CD9}', 220),
+    ('\u{1CDA}', '\u{1CDB}', 230),
+    ('\u{1CDC}', '\u{1CDF}', 220),
+    ('\u{1CE0}', '\u{1CE0}', 230),
+    ('\u{1CE2}', '\u{1CE8}', 1),
+    ('\u{1CED}', '\u{1CED}', 220),
+    ('\u{1CF4}', '\u{1CF4}', 230),
+    ('\u{1CF8}', '\u{1CF9}', 230),
+    ('\u{1DC0}', '\u{1DC1}', 230),
+    ('\u{1DC2}', '\u{1DC2}', 220),
+    ('\u{1DC3}', '\u{1DC9}', 230),
+    ('\u{1DCA}', '\u{1DCA}', 220),
+    ('\u{1DCB}', '\u{1DCC}', 230),
+    ('\u{1DCD}', '\u{1DCD}', 234),
+    ('\u{1DCE}', '\u{1DCE}', 214),
+    ('\u{1DCF}', '\u{1DCF}', 220),
+    ('\u{1DD0}', '\u{1DD0}', 202),
+    ('\u{1DD1}', '\u{1DF5}', 230),
+    ('\u{1DF6}', '\u{1DF6}', 232),
+    ('\u{1DF7}', '\u{1DF8}', 228),
+    ('\u{1DF9}', '\u{1DF9}', 220),
+    ('\u{1DFA}', '\u{1DFA}', 218),
+    ('\u{1DFB}', '\u{1DFB}', 230),
+    ('\u{1DFC}', '\u{1DFC}', 233),
+    ('\u{1DFD}', '\u{1DFD}', 220),
+    ('\u{1DFE}', '\u{1DFE}', 230),
+    ('\u{1DFF}', '\u{1DFF}', 220),
+    ('\u{20D0}', '\u{20D1}', 230),
+    ('\u{20D2}', '\u{20D3}', 1),
+    ('\u{20D4}', '\u{20D7}', 230),
+    ('\u{20D8}', '\u{20DA}', 1),
+    ('\u{20DB}', '\u{20DC}', 230),
+    ('\u{20E1}', '\u{20E1}', 230),
+    ('\u{20E5}', '\u{20E6}', 1),
+    ('\u{20E7}', '\u{20E7}', 230),
+    ('\u{20E8}', '\u{20E8}', 220),
+    ('\u{20E9}', '\u{20E9}', 230),
+    ('\u{20EA}', '\u{20EB}', 1),
+    ('\u{20EC}', '\u{20EF}', 220),
+    ('\u{20F0}', '\u{20F0}', 230),
+    ('\u{2CEF}', '\u{2CF1}', 230),
+    ('\u{2D7F}', '\u{2D7F}', 9),
+    ('\u{2DE0}', '\u{2DFF}', 230),
+    ('\u{302A}', '\u{302A}', 218),
+    ('\u{302B}', '\u{302B}', 228),
+    ('\u{302C}', '\u{302C}', 232),
+    ('\u{302D}', '\u{302D}', 222),
+    ('\u{302E}', '\u{302F}', 224),
+    ('\u{3099}', '\u{309A}', 8),
+    ('\u{A66F}', '\u{A66F}', 230),
+    ('\u{A674}', '\u{A67D}', 230),
+    ('\u{A69E}', '\u{A69F}', 230),
+    ('\u{A6F0}', '\u{A6F1}', 230),
+    ('\u{A806}', '\u{A806}', 9),
+    ('\u{A82C}', '\u{A82C}', 9),
+    ('\u{A8C4}', '\u{A8C4}', 9),
+    ('\u{A8E0}', '\u{A8F1}', 230),
+    ('\u{A92B}', '\u{A92D}', 220),
+    ('\u{A953}', '\u{A953}', 9),
+    ('\u{A9B3}', '\u{A9B3}', 7),
+    ('\u{A9C0}', '\u{A9C0}', 9),
+    ('\u{AAB0}', '\u{AAB0}', 230),
+    ('\u{AAB2}', '\u{AAB3}', 230),
+    ('\u{AAB4}', '\u{AAB4}', 220),
+    ('\u{AAB7}', '\u{AAB8}', 230),
+    ('\u{AABE}', '\u{AABF}', 230),
+    ('\u{AAC1}', '\u{AAC1}', 230),
+    ('\u{AAF6}', '\u{AAF6}', 9),
+    ('\u{ABED}', '\u{ABED}', 9),
+    ('\u{FB1E}', '\u{FB1E}', 26),
+    ('\u{FE20}', '\u{FE26}', 230),
+    ('\u{FE27}', '\u{FE2D}', 220),
+    ('\u{FE2E}', '\u{FE2F}', 230),
+    ('\u{101FD}', '\u{101FD}', 220),
+    ('\u{102E0}', '\u{102E0}', 220),
+    ('\u{10376}', '\u{1037A}', 230),
+    ('\u{10A0D}', '\u{10A0D}', 220),
+    ('\u{10A0F}', '\u{10A0F}', 230),
+    ('\u{10A38}', '\u{10A38}', 230),
+    ('\u{10A39}', '\u{10A39}', 1),
+    ('\u{10A3A}', '\u{10A3A}', 220),
+    ('\u{10A3F}', '\u{10A3F}', 9),
+    ('\u{10AE5}', '\u{10AE5}', 230),
+    ('\u{10AE6}', '\u{10AE6}', 220),
+    ('\u{10D24}', '\u{10D27}', 230),
+    ('\u{10EAB}', '\u{10EAC}', 230),
+    ('\u{10F46}', '\u{10F47}', 220),
+    ('\u{10F48}', '\u{10F4A}', 230),
+    ('\u{10F4B}', '\u{10F4B}', 220),
+    ('\u{10F4C}', '\u{10F4C}', 230),
+    ('\u{10F4D}', '\u{10F50}', 220),
+    ('\u{10F82}', '\u{10F82}', 230),
+    ('\u{10F83}', '\u{10F83}', 220),
+    ('\u{10F84}', '\u{10F84}', 230),
+    ('\u{10F85}', '\u{10F85}', 220),
+    ('\u{11046}', '\u{11046}', 9),
+    ('\u{11070}', '\u{11070}', 9),
+    ('\u{1107F}', '\u{1107F}', 9),
+    ('\u{110B9}', '\u{110B9}', 9),
+    ('\u{110BA}', '\u{110BA}', 7),
+    ('\u{11100}', '\u{11102}', 230),
+    ('\u{11133}', '\u{11134}', 9),
+    ('\u{11173}', '\u{11173}', 7),
+    ('\u{111C0}', '\u{111C0}', 9),
+    ('\u{111CA}', '\u{111CA}', 7),
+    ('\u{11235}', '\u{11235}', 9),
+    ('\u{11236}', '\u{11236}', 7),
+    ('\u{112E9}', '\u{112E9}', 7),
+    ('\u{112EA}', '\u{112EA}', 9),
+    ('\u{1133B}', '\u{1133C}', 7),
+    ('\u{1134D}', '\u{1134D}', 9),
+    ('\u{11366}', '\u{1136C}', 230),
+    ('\u{11370}', '\u{11374}', 230),
+    ('\u{11442}', '\u{11442}', 9),
+    ('\u{11446}', '\u{11446}', 7),
+    ('\u{1145E}', '\u{1145E}', 230),
+    ('\u{114C2}', '\u{114C2}', 9),
+    ('\u{114C3}', '\u{114C3}', 7),
+    ('\u{115BF}', '\u{115BF}', 9),
+    ('\u{115C0}', '\u{115C0}', 7),
+    ('\u{1163F}', '\u{1163F}', 9),
+    ('\u{116B6}', '\u{116B6}', 9),
+    ('\u{116B7}', '\u{116B7}', 7),
+    ('\u{1172B}', '\u{1172B}', 9),
+    ('\u{11839}', '\u{11839}', 9),
+    ('\u{1183A}', '\u{1183A}', 7),
+    ('\u{1193D}', '\u{1193E}', 9),
+    ('\u{11943}', '\u{11943}', 7),
+    ('\u{119E0}', '\u{119E0}', 9),
+    ('\u{11A34}', '\u{11A34}', 9),
+    ('\u{11A47}', '\u{11A47}', 9),
+    ('\u{11A99}', '\u{11A99}', 9),
+    ('\u{11C3F}', '\u{11C3F}', 9),
+    ('\u{11D42}', '\u{11D42}', 7),
+    ('\u{11D44}', '\u{11D45}', 9),
+    ('\u{11D97}', '\u{11D97}', 9),
+    ('\u{16AF0}', '\u{16AF4}', 1),
+    ('\u{16B30}', '\u{16B36}', 230),
+    ('\u{16FF0}', '\u{16FF1}', 6),
+    ('\u{1BC9E}', '\u{1BC9E}', 1),
+    ('\u{1D165}', '\u{1D166}', 216),
+    ('\u{1D167}', '\u{1D169}', 1),
+    ('\u{1D16D}', '\u{1D16D}', 226),
+    ('\u{1D16E}', '\u{1D172}', 216),
+    ('\u{1D17B}', '\u{1D182}', 220),
+    ('\u{1D185}', '\u{1D189}', 230),
+    ('\u{1D18A}', '\u{1D18B}', 220),
+    ('\u{1D1AA}', '\u{1D1AD}', 230),
+    ('\u{1D242}', '\u{1D244}', 230),
+    ('\u{1E000}', '\u{1E006}', 230),
+    ('\u{1E008}', '\u{1E018}', 230),
+    ('\u{1E01B}', '\u{1E021}', 230),
+    ('\u{1E023}', '\u{1E024}', 230),
+    ('\u{1E026}', '\u{1E02A}', 230),
+    ('\u{1E130}', '\u{1E136}', 230),
+    ('\u{1E2AE}', '\u{1E2AE}', 230),
+    ('\u{1E2EC}', '\u{1E2EF}', 230),
+    ('\u{1E8D0}', '\u{1E8D6}', 220),
+    ('\u{1E944}', '\u{1E949}', 230),
+    ('\u{1E94A}', '\u{1E94A}', 7),
+];
+
+/// Primary composites: (starter, combining character, composed), sorted by the pair
+pub const COMPOSITION: &[(char, char, char)] = &[
+    ('\u{3C}', '\u{338}', '\u{226E}'),
+    ('\u{3D}', '\u{338}', '\u{2260}'),
+    ('\u{3E}', '\u{338}', '\u{226F}'),
+    ('\u{41}', '\u{300}', '\u{C0}'),
+    ('\u{41}', '\u{301}', '\u{C1}'),
+    ('\u{41}', '\u{302}', '\u{C2}'),
+    ('\u{41}', '\u{303}', '\u{C3}'),
+    ('\u{41}', '\u{304}', '\u{100}'),
+    ('\u{41}', '\u{306}', '\u{102}'),
+    ('\u{41}', '\u{307}', '\u{226}'),
+    ('\u{41}', '\u{308}', '\u{C4}'),
+    ('\u{41}', '\u{309}', '\u{1EA2}'),
+    ('\u{41}', '\u{30A}', '\u{C5}'),
+    ('\u{41}', '\u{30C}', '\u{1CD}'),
+    ('\u{41}', '\u{30F}', '\u{200}'),
+    ('\u{41}', '\u{311}', '\u{202}'),
+    ('\u{41}', '\u{323}', '\u{1EA0}'),
+    ('\u{41}', '\u{325}', '\u{1E00}'),
+    ('\u{41}', '\u{328}', '\u{104}'),
+    ('\u{42}', '\u{307}', '\u{1E02}'),
+    ('\u{42}', '\u{323}', '\u{1E04}'),
+    ('\u{42}', '\u{331}', '\u{1E06}'),
+    ('\u{43}', '\u{301}', '\u{106}'),
+    ('\u{43}', '\u{302}', '\u{108}'),
+    ('\u{43}', '\u{307}', '\u{10A}'),
+    ('\u{43}', '\u{30C}', '\u{10C}'),
+    ('\u{43}', '\u{327}', '\u{C7}'),
+    ('\u{44}', '\u{307}', '\u{1E0A}'),
+    ('\u{44}', '\u{30C}', '\u{10E}'),
+    ('\u{44}', '\u{323}', '\u{1E0C}'),
+    ('\u{44}', '\u{327}', '\u{1E10}'),
+    ('\u{44}', '\u{32D}', '\u{1E12}'),
+    ('\u{44}', '\u{331}', '\u{1E0E}'),
+    ('\u{45}', '\u{300}', '\u{C8}'),
+    ('\u{45}', '\u{301}', '\u{C9}'),
+    ('\u{45}', '\u{302}', '\u{CA}'),
+    ('\u{45}', '\u{303}', '\u{1EBC}'),
+    ('\u{45}', '\u{304}', '\u{112}'),
+    ('\u{45}', '\u{306}', '\u{114}'),
+    ('\u{45}', '\u{307}', '\u{116}'),
+    ('\u{45}', '\u{308}', '\u{CB}'),
+    ('\u{45}', '\u{309}', '\u{1EBA}'),
+    ('\u{45}', '\u{30C}', '\u{11A}'),
+    ('\u{45}', '\u{30F}', '\u{204}'),
+    ('\u{45}', '\u{311}', '\u{206}'),
+    ('\u{45}', '\u{323}', '\u{1EB8}'),
+    ('\u{45}', '\u{327}', '\u{228}'),
+    ('\u{45}', '\u{328}', '\u{118}'),
+    ('\u{45}', '\u{32D}', '\u{1E18}'),
+    ('\u{45}', '\u{330}', '\u{1E1A}'),
+    ('\u{46}', '\u{307}', '\u{1E1E}'),
+    ('\u{47}', '\u{301}', '\u{1F4}'),
+    ('\u{47}', '\u{302}', '\u{11C}'),
+    ('\u{47}', '\u{304}', '\u{1E20}'),
+    ('\u{47}', '\u{306}', '\u{11E}'),
+    ('\u{47}', '\u{307}', '\u{120}'),
+    ('\u{47}', '\u{30C}', '\u{1E6}'),
+    ('\u{47}', '\u{327}', '\u{122}'),
+    ('\u{48}', '\u{302}', '\u{124}'),
+    ('\u{48}', '\u{307}', '\u{1E22}'),
+    ('\u{48}', '\u{308}', '\u{1E26}'),
+    ('\u{48}', '\u{30C}', '\u{21E}'),
+    ('\u{48}', '\u{323}', '\u{1E24}'),
+    ('\u{48}', '\u{327}', '\u{1E28}'),
+    ('\u{48}', '\u{32E}', '\u{1E2A}'),
+    ('\u{49}', '\u{300}', '\u{CC}'),
+    ('\u{49}', '\u{301}', '\u{CD}'),
+    ('\u{49}', '\u{302}', '\u{CE}'),
+    ('\u{49}', '\u{303}', '\u{128}'),
+    ('\u{49}', '\u{304}', '\u{12A}'),
+    ('\u{49}', '\u{306}', '\u{12C}'),
+    ('\u{49}', '\u{307}', '\u{130}'),
+    ('\u{49}', '\u{308}', '\u{CF}'),
+    ('\u{49}', '\u{309}', '\u{1EC8}'),
+    ('\u{49}', '\u{30C}', '\u{1CF}'),
+    ('\u{49}', '\u{30F}', '\u{208}'),
+    ('\u{49}', '\u{311}', '\u{20A}'),
+    ('\u{49}', '\u{323}', '\u{1ECA}'),
+    ('\u{49}', '\u{328}', '\u{12E}'),
+    ('\u{49}', '\u{330}', '\u{1E2C}'),
+    ('\u{4A}', '\u{302}', '\u{134}'),
+    ('\u{4B}', '\u{301}', '\u{1E30}'),
+    ('\u{4B}', '\u{30C}', '\u{1E8}'),
+    ('\u{4B}', '\u{323}', '\u{1E32}'),
+    ('\u{4B}', '\u{327}', '\u{136}'),
+    ('\u{4B}', '\u{331}', '\u{1E34}'),
+    ('\u{4C}', '\u{301}', '\u{139}'),
+    ('\u{4C}', '\u{30C}', '\u{13D}'),
+    ('\u{4C}', '\u{323}', '\u{1E36}'),
+    ('\u{4C}', '\u{327}', '\u{13B}'),
+    ('\u{4C}', '\u{32D}', '\u{1E3C}'),
+    ('\u{4C}', '\u{331}', '\u{1E3A}'),
+    ('\u{4D}', '\u{301}', '\u{1E3E}'),
+    ('\u{4D}', '\u{307}', '\u{1E40}'),
+    ('\u{4D}', '\u{323}', '\u{1E42}'),
+    ('\u{4E}', '\u{300}', '\u{1F8}'),
+    ('\u{4E}', '\u{301}', '\u{143}'),
+    ('\u{4E}', '\u{303}', '\u{D1}'),
+    ('\u{4E}', '\u{307}', '\u{1E44}'),
+    ('\u{4E}', '\u{30C}', '\u{147}'),
+    ('\u{4E}', '\u{323}', '\u{1E46}'),
+    ('\u{4E}', '\u{327}', '\u{145}'),
+    ('\u{4E}', '\u{32D}', '\u{1E4A}'),
+    ('\u{4E}', '\u{331}', '\u{1E48}'),
+    ('\u{4F}', '\u{300}', '\u{D2}'),
+    ('\u{4F}', '\u{301}', '\u{D3}'),
+    ('\u{4F}', '\u{302}', '\u{D4}'),
+    ('\u{4F}', '\u{303}', '\u{D5}'),
+    ('\u{4F}', '\u{304}', '\u{14C}'),
+    ('\u{4F}', '\u{306}', '\u{14E}'),
+    ('\u{4F}', '\u{307}', '\u{22E}'),
+    ('\u{4F}', '\u{308}', '\u{D6}'),
+    ('\u{4F}', '\u{309}', '\u{1ECE}'),
+    ('\u{4F}', '\u{30B}', '\u{150}'),
+    ('\u{4F}', '\u{30C}', '\u{1D1}'),
+    ('\u{4F}', '\u{30F}', '\u{20C}'),
+    ('\u{4F}', '\u{311}', '\u{20E}'),
+    ('\u{4F}', '\u{31B}', '\u{1A0}'),
+    ('\u{4F}', '\u{323}', '\u{1ECC}'),
+    ('\u{4F}', '\u{328}', '\u{1EA}'),
+    ('\u{50}', '\u{301}', '\u{1E54}'),
+    ('\u{50}', '\u{307}', '\u{1E56}'),
+    ('\u{52}', '\u{301}', '\u{154}'),
+    ('\u{52}', '\u{307}', '\u{1E58}'),
+    ('\u{52}', '\u{30C}', '\u{158}'),
+    ('\u{52}', '\u{30F}', '\u{210}'),
+    ('\u{52}', '\u{311}', '\u{212}'),
+    ('\u{52}', '\u{323}', '\u{1E5A}'),
+    ('\u{52}', '\u{327}', '\u{156}'),
+    ('\u{52}', '\u{331}', '\u{1E5E}'),
+    ('\u{53}', '\u{301}', '\u{15A}'),
+    ('\u{53}', '\u{302}', '\u{15C}'),
+    ('\u{53}', '\u{307}', '\u{1E60}'),
+    ('\u{53}', '\u{30C}', '\u{160}'),
+    ('\u{53}', '\u{323}', '\u{1E62}'),
+    ('\u{53}', '\u{326}', '\u{218}'),
+    ('\u{53}', '\u{327}', '\u{15E}'),
+    ('\u{54}', '\u{307}', '\u{1E6A}'),
+    ('\u{54}', '\u{30C}', '\u{164}'),
+    ('\u{54}', '\u{323}', '\u{1E6C}'),
+    ('\u{54}', '\u{326}', '\u{21A}'),
+    ('\u{54}', '\u{327}', '\u{162}'),
+    ('\u{54}', '\u{32D}', '\u{1E70}'),
+    ('\u{54}', '\u{331}', '\u{1E6E}'),
+    ('\u{55}', '\u{300}', '\u{D9}'),
+    ('\u{55}', '\u{301}', '\u{DA}'),
+    ('\u{55}', '\u{302}', '\u{DB}'),
+    ('\u{55}', '\u{303}', '\u{168}'),
+    ('\u{55}', '\u{304}', '\u{16A}'),
+    ('\u{55}', '\u{306}', '\u{16C}'),
+    ('\u{55}', '\u{308}', '\u{DC}'),
+    ('\u{55}', '\u{309}', '\u{1EE6}'),
+    ('\u{55}', '\u{30A}', '\u{16E}'),
+    ('\u{55}', '\u{30B}', '\u{170}'),
+    ('\u{55}', '\u{30C}', '\u{1D3}'),
+    ('\u{55}', '\u{30F}', '\u{214}'),
+    ('\u{55}', '\u{311}', '\u{216}'),
+    ('\u{55}', '\u{31B}', '\u{1AF}'),
+    ('\u{55}', '\u{323}', '\u{1EE4}'),
+    ('\u{55}', '\u{324}', '\u{1E72}'),
+    ('\u{55}', '\u{328}', '\u{172}'),
+    ('\u{55}', '\u{32D}', '\u{1E76}'),
+    ('\u{55}', '\u{330}', '\u{1E74}'),
+    ('\u{56}', '\u{303}', '\u{1E7C}'),
+    ('\u{56}', '\u{323}', '\u{1E7E}'),
+    ('\u{57}', '\u{300}', '\u{1E80}'),
+    ('\u{57}', '\u{301}', '\u{1E82}'),
+    ('\u{57}', '\u{302}', '\u{174}'),
+    ('\u{57}', '\u{307}', '\u{1E86}'),
+    ('\u{57}', '\u{308}', '\u{1E84}'),
+    ('\u{57}', '\u{323}', '\u{1E88}'),
+    ('\u{58}', '\u{307}', '\u{1E8A}'),
+    ('\u{58}', '\u{308}', '\u{1E8C}'),
+    ('\u{59}', '\u{300}', '\u{1EF2}'),
+    ('\u{59}', '\u{301}', '\u{DD}'),
+    ('\u{59}', '\u{302}', '\u{176}'),
+    ('\u{59}', '\u{303}', '\u{1EF8}'),
+    ('\u{59}', '\u{304}', '\u{232}'),
+    ('\u{59}', '\u{307}', '\u{1E8E}'),
+    ('\u{59}', '\u{308}', '\u{178}'),
+    ('\u{59}', '\u{309}', '\u{1EF6}'),
+    ('\u{59}', '\u{323}', '\u{1EF4}'),
+    ('\u{5A}', '\u{301}', '\u{179}'),
+    ('\u{5A}', '\u{302}', '\u{1E90}'),
+    ('\u{5A}', '\u{307}', '\u{17B}'),
+    ('\u{5A}', '\u{30C}', '\u{17D}'),
+    ('\u{5A}', '\u{323}', '\u{1E92}'),
+    ('\u{5A}', '\u{331}', '\u{1E94}'),
+    ('\u{61}', '\u{300}', '\u{E0}'),
+    ('\u{61}', '\u{301}', '\u{E1}'),
+    ('\u{61}', '\u{302}', '\u{E2}'),
+    ('\u{61}', '\u{303}', '\u{E3}'),
+    ('\u{61}', '\u{304}', '\u{101}'),
+    ('\u{61}', '\u{306}', '\u{103}'),
+    ('\u{61}', '\u{307}', '\u{227}'),
+    ('\u{61}', '\u{308}', '\u{E4}'),
+    ('\u{61}', '\u{309}', '\u{1EA3}'),
+    ('\u{61}', '\u{30A}', '\u{E5}'),
+    ('\u{61}', '\u{30C}', '\u{1CE}'),
+    ('\u{61}', '\u{30F}', '\u{201}'),
+    ('\u{61}', '\u{311}', '\u{203}'),
+    ('\u{61}', '\u{323}', '\u{1EA1}'),
+    ('\u{61}', '\u{325}', '\u{1E01}'),
+    ('\u{61}', '\u{328}', '\u{105}'),
+    ('\u{62}', '\u{307}', '\u{1E03}'),
+    ('\u{62}', '\u{323}', '\u{1E05}'),
+    ('\u{62}', '\u{331}', '\u{1E07}'),
+    ('\u{63}', '\u{301}', '\u{107}'),
+    ('\u{63}', '\u{302}', '\u{109}'),
+    ('\u{63}', '\u{307}', '\u{10B}'),
+    ('\u{63}', '\u{30C}', '\u{10D}'),
+    ('\u{63}', '\u{327}', '\u{E7}'),
+    ('\u{64}', '\u{307}', '\u{1E0B}'),
+    ('\u{64}', '\u{30C}', '\u{10F}'),
+    ('\u{64}', '\u{323}', '\u{1E0D}'),
+    ('\u{64}', '\u{327}', '\u{1E11}'),
+    ('\u{64}', '\u{32D}', '\u{1E13}'),
+    ('\u{64}', '\u{331}', '\u{1E0F}'),
+    ('\u{65}', '\u{300}', '\u{E8}'),
+    ('\u{65}', '\u{301}', '\u{E9}'),
+    ('\u{65}', '\u{302}', '\u{EA}'),
+    ('\u{65}', '\u{303}', '\u{1EBD}'),
+    ('\u{65}', '\u{304}', '\u{113}'),
+    ('\u{65}', '\u{306}', '\u{115}'),
+    ('\u{65}', '\u{307}', '\u{117}'),
+    ('\u{65}', '\u{308}', '\u{EB}'),
+    ('\u{65}', '\u{309}', '\u{1EBB}'),
+    ('\u{65}', '\u{30C}', '\u{11B}'),
+    ('\u{65}', '\u{30F}', '\u{205}'),
+    ('\u{65}', '\u{311}', '\u{207}'),
+    ('\u{65}', '\u{323}', '\u{1EB9}'),
+    ('\u{65}', '\u{327}', '\u{229}'),
+    ('\u{65}', '\u{328}', '\u{119}'),
+    ('\u{65}', '\u{32D}', '\u{1E19}'),
+    ('\u{65}', '\u{330}', '\u{1E1B}'),
+    ('\u{66}', '\u{307}', '\u{1E1F}'),
+    ('\u{67}', '\u{301}', '\u{1F5}'),
+    ('\u{67}', '\u{302}', '\u{11D}'),
+    ('\u{67}', '\u{304}', '\u{1E21}'),
+    ('\u{67}', '\u{306}', '\u{11F}'),
+    ('\u{67}', '\u{307}', '\u{121}'),
+    ('\u{67}', '\u{30C}', '\u{1E7}'),
+    ('\u{67}', '\u{327}', '\u{123}'),
+    ('\u{68}', '\u{302}', '\u{125}'),
+    ('\u{68}', '\u{307}', '\u{1E23}'),
+    ('\u{68}', '\u{308}', '\u{1E27}'),
+    ('\u{68}', '\u{30C}', '\u{21F}'),
+    ('\u{68}', '\u{323}', '\u{1E25}'),
+    ('\u{68}', '\u{327}', '\u{1E29}'),
+    ('\u{68}', '\u{32E}', '\u{1E2B}'),
+    ('\u{68}', '\u{331}', '\u{1E96}'),
+    ('\u{69}', '\u{300}', '\u{EC}'),
+    ('\u{69}', '\u{301}', '\u{ED}'),
+    ('\u{69}', '\u{302}', '\u{EE}'),
+    ('\u{69}', '\u{303}', '\u{129}'),
+    ('\u{69}', '\u{304}', '\u{12B}'),
+    ('\u{69}', '\u{306}', '\u{12D}'),
+    ('\u{69}', '\u{308}', '\u{EF}'),
+    ('\u{69}', '\u{309}', '\u{1EC9}'),
+    ('\u{69}', '\u{30C}', '\u{1D0}'),
+    ('\u{69}', '\u{30F}', '\u{209}'),
+    ('\u{69}', '\u{311}', '\u{20B}'),
+    ('\u{69}', '\u{323}', '\u{1ECB}'),
+    ('\u{69}', '\u{328}', '\u{12F}'),
+    ('\u{69}', '\u{330}', '\u{1E2D}'),
+    ('\u{6A}', '\u{302}', '\u{135}'),
+    ('\u{6A}', '\u{30C}', '\u{1F0}'),
+    ('\u{6B}', '\u{301}', '\u{1E31}'),
+    ('\u{6B}', '\u{30C}', '\u{1E9}'),
+    ('\u{6B}', '\u{323}', '\u{1E33}'),
+    ('\u{6B}', '\u{327}', '\u{137}'),
+    ('\u{6B}', '\u{331}', '\u{1E35}'),
+    ('\u{6C}', '\u{301}', '\u{13A}'),
+    ('\u{6C}', '\u{30C}', '\u{13E}'),
+    ('\u{6C}', '\u{323}', '\u{1E37}'),
+    ('\u{6C}', '\u{327}', '\u{13C}'),
+    ('\u{6C}', '\u{32D}', '\u{1E3D}'),
+    ('\u{6C}', '\u{331}', '\u{1E3B}'),
+    ('\u{6D}', '\u{301}', '\u{1E3F}'),
+    ('\u{6D}', '\u{307}', '\u{1E41}'),
+    ('\u{6D}', '\u{323}', '\u{1E43}'),
+    ('\u{6E}', '\u{300}', '\u{1F9}'),
+    ('\u{6E}', '\u{301}', '\u{144}'),
+    ('\u{6E}', '\u{303}', '\u{F1}'),
+    ('\u{6E}', '\u{307}', '\u{1E45}'),
+    ('\u{6E}', '\u{30C}', '\u{148}'),
+    ('\u{6E}', '\u{323}', '\u{1E47}'),
+    ('\u{6E}', '\u{327}', '\u{146}'),
+    ('\u{6E}', '\u{32D}', '\u{1E4B}'),
+    ('\u{6E}', '\u{331}', '\u{1E49}'),
+    ('\u{6F}', '\u{300}', '\u{F2}'),
+    ('\u{6F}', '\u{301}', '\u{F3}'),
+    ('\u{6F}', '\u{302}', '\u{F4}'),
+    ('\u{6F}', '\u{303}', '\u{F5}'),
+    ('\u{6F}', '\u{304}', '\u{14D}'),
+    ('\u{6F}', '\u{306}', '\u{14F}'),
+    ('\u{6F}', '\u{307}', '\u{22F}'),
+    ('\u{6F}', '\u{308}', '\u{F6}'),
+    ('\u{6F}', '\u{309}', '\u{1ECF}'),
+    ('\u{6F}', '\u{30B}', '\u{151}'),
+    ('\u{6F}', '\u{30C}', '\u{1D2}'),
+    ('\u{6F}', '\u{30F}', '\u{20D}'),
+    ('\u{6F}', '\u{311}', '\u{20F}'),
+    ('\u{6F}', '\u{31B}', '\u{1A1}'),
+    ('\u{6F}', '\u{323}', '\u{1ECD}'),
+    ('\u{6F}', '\u{328}', '\u{1EB}'),
+    ('\u{70}', '\u{301}', '\u{1E55}'),
+    ('\u{70}', '\u{307}', '\u{1E57}'),
+    ('\u{72}', '\u{301}', '\u{155}'),
+    ('\u{72}', '\u{307}', '\u{1E59}'),
+    ('\u{72}', '\u{30C}', '\u{159}'),
+    ('\u{72}', '\u{30F}', '\u{211}'),
+    ('\u{72}', '\u{311}', '\u{213}'),
+    ('\u{72}', '\u{323}', '\u{1E5B}'),
+    ('\u{72}', '\u{327}', '\u{157}'),
+    ('\u{72}', '\u{331}', '\u{1E5F}'),
+    ('\u{73}', '\u{301}', '\u{15B}'),
+    ('\u{73}', '\u{302}', '\u{15D}'),
+    ('\u{73}', '\u{307}', '\u{1E61}'),
+    ('\u{73}', '\u{30C}', '\u{161}'),
+    ('\u{73}', '\u{323}', '\u{1E63}'),
+    ('\u{73}', '\u{326}', '\u{219}'),
+    ('\u{73}', '\u{327}', '\u{15F}'),
+    ('\u{74}', '\u{307}', '\u{1E6B}'),
+    ('\u{74}', '\u{308}', '\u{1E97}'),
+    ('\u{74}', '\u{30C}', '\u{165}'),
+    ('\u{74}', '\u{323}', '\u{1E6D}'),
+    ('\u{74}', '\u{326}', '\u{21B}'),
+    ('\u{74}', '\u{327}', '\u{163}'),
+    ('\u{74}', '\u{32D}', '\u{1E71}'),
+    ('\u{74}', '\u{331}', '\u{1E6F}'),
+    ('\u{75}', '\u{300}', '\u{F9}'),
+    ('\u{75}', '\u{301}', '\u{FA}'),
+    ('\u{75}', '\u{302}', '\u{FB}'),
+    ('\u{75}', '\u{303}', '\u{169}'),
+    ('\u{75}', '\u{304}', '\u{16B}'),
+    ('\u{75}', '\u{306}', '\u{16D}'),
+    ('\u{75}', '\u{308}', '\u{FC}'),
+    ('\u{75}', '\u{309}', '\u{1EE7}'),
+    ('\u{75}', '\u{30A}', '\u{16F}'),
+    ('\u{75}', '\u{30B}', '\u{171}'),
+    ('\u{75}', '\u{30C}', '\u{1D4}'),
+    ('\u{75}', '\u{30F}', '\u{215}'),
+    ('\u{75}', '\u{311}', '\u{217}'),
+    ('\u{75}', '\u{31B}', '\u{1B0}'),
+    ('\u{75}', '\u{323}', '\u{1EE5}'),
+    ('\u{75}', '\u{324}', '\u{1E73}'),
+    ('\u{75}', '\u{328}', '\u{173}'),
+    ('\u{75}', '\u{32D}', '\u{1E77}'),
+    ('\u{75}', '\u{330}', '\u{1E75}'),
+    ('\u{76}', '\u{303}', '\u{1E7D}'),
+    ('\u{76}', '\u{323}', '\u{1E7F}'),
+    ('\u{77}', '\u{300}', '\u{1E81}'),
+    ('\u{77}', '\u{301}', '\u{1E83}'),
+    ('\u{77}', '\u{302}', '\u{175}'),
+    ('\u{77}', '\u{307}', '\u{1E87}'),
+    ('\u{77}', '\u{308}', '\u{1E85}'),
+    ('\u{77}', '\u{30A}', '\u{1E98}'),
+    ('\u{77}', '\u{323}', '\u{1E89}'),
+    ('\u{78}', '\u{307}', '\u{1E8B}'),
+    ('\u{78}', '\u{308}', '\u{1E8D}'),
+    ('\u{79}', '\u{300}', '\u{1EF3}'),
+    ('\u{79}', '\u{301}', '\u{FD}'),
+    ('\u{79}', '\u{302}', '\u{177}'),
+    ('\u{79}', '\u{303}', '\u{1EF9}'),
+    ('\u{79}', '\u{304}', '\u{233}'),
+    ('\u{79}', '\u{307}', '\u{1E8F}'),
+    ('\u{79}', '\u{308}', '\u{FF}'),
+    ('\u{79}', '\u{309}', '\u{1EF7}'),
+    ('\u{79}', '\u{30A}', '\u{1E99}'),
+    ('\u{79}', '\u{323}', '\u{1EF5}'),
+    ('\u{7A}', '\u{301}', '\u{17A}'),
+    ('\u{7A}', '\u{302}', '\u{1E91}'),
+    ('\u{7A}', '\u{307}', '\u{17C}'),
+    ('\u{7A}', '\u{30C}', '\u{17E}'),
+    ('\u{7A}', '\u{323}', '\u{1E93}'),
+    ('\u{7A}', '\u{331}', '\u{1E95}'),
+    ('\u{A8}', '\u{300}', '\u{1FED}'),
+    ('\u{A8}', '\u{301}', '\u{385}'),
+    ('\u{A8}', '\u{342}', '\u{1FC1}'),
+    ('\u{C2}', '\u{300}', '\u{1EA6}'),
+    ('\u{C2}', '\u{301}', '\u{1EA4}'),
+    ('\u{C2}', '\u{303}', '\u{1EAA}'),
+    ('\u{C2}', '\u{309}', '\u{1EA8}'),
+    ('\u{C4}', '\u{304}', '\u{1DE}'),
+    ('\u{C5}', '\u{301}', '\u{1FA}'),
+    ('\u{C6}', '\u{301}', '\u{1FC}'),
+    ('\u{C6}', '\u{304}', '\u{1E2}'),
+    ('\u{C7}', '\u{301}', '\u{1E08}'),
+    ('\u{CA}', '\u{300}', '\u{1EC0}'),
+    ('\u{CA}', '\u{301}', '\u{1EBE}'),
+    ('\u{CA}', '\u{303}', '\u{1EC4}'),
+    ('\u{CA}', '\u{309}', '\u{1EC2}'),
+    ('\u{CF}', '\u{301}', '\u{1E2E}'),
+    ('\u{D4}', '\u{300}', '\u{1ED2}'),
+    ('\u{D4}', '\u{301}', '\u{1ED0}'),
+    ('\u{D4}', '\u{303}', '\u{1ED6}'),
+    ('\u{D4}', '\u{309}', '\u{1ED4}'),
+    ('\u{D5}', '\u{301}', '\u{1E4C}'),
+    ('\u{D5}', '\u{304}', '\u{22C}'),
+    ('\u{D5}', '\u{308}', '\u{1E4E}'),
+    ('\u{D6}', '\u{304}', '\u{22A}'),
+    ('\u{D8}', '\u{301}', '\u{1FE}'),
+    ('\u{DC}', '\u{300}', '\u{1DB}'),
+    ('\u{DC}', '\u{301}', '\u{1D7}'),
+    ('\u{DC}', '\u{304}', '\u{1D5}'),
+    ('\u{DC}', '\u{30C}', '\u{1D9}'),
+    ('\u{E2}', '\u{300}', '\u{1EA7}'),
+    ('\u{E2}', '\u{301}', '\u{1EA5}'),
+    ('\u{E2}', '\u{303}', '\u{1EAB}'),
+    ('\u{E2}', '\u{309}', '\u{1EA9}'),
+    ('\u{E4}', '\u{304}', '\u{1DF}'),
+    ('\u{E5}', '\u{301}', '\u{1FB}'),
+    ('\u{E6}', '\u{301}', '\u{1FD}'),
+    ('\u{E6}', '\u{304}', '\u{1E3}'),
+    ('\u{E7}', '\u{301}', '\u{1E09}'),
+    ('\u{EA}', '\u{300}', '\u{1EC1}'),
+    ('\u{EA}', '\u{301}', '\u{1EBF}'),
+    ('\u{EA}', '\u{303}', '\u{1EC5}'),
+    ('\u{EA}', '\u{309}', '\u{1EC3}'),
+    ('\u{EF}', '\u{301}', '\u{1E2F}'),
+    ('\u{F4}', '\u{300}', '\u{1ED3}'),
+    ('\u{F4}', '\u{301}', '\u{1ED1}'),
+    ('\u{F4}', '\u{303}', '\u{1ED7}'),
+    ('\u{F4}', '\u{309}', '\u{1ED5}'),
+    ('\u{F5}', '\u{301}', '\u{1E4D}'),
+    ('\u{F5}', '\u{304}', '\u{22D}'),
+    ('\u{F5}', '\u{308}', '\u{1E4F}'),
+    ('\u{F6}', '\u{304}', '\u{22B}'),
+    ('\u{F8}', '\u{301}', '\u{1FF}'),
+    ('\u{FC}', '\u{300}', '\u{1DC}'),
+    ('\u{FC}', '\u{301}', '\u{1D8}'),
+    ('\u{FC}', '\u{304}', '\u{1D6}'),
+    ('\u{FC}', '\u{30C}', '\u{1DA}'),
+    ('\u{102}', '\u{300}', '\u{1EB0}'),
+    ('\u{102}', '\u{301}', '\u{1EAE}'),
+    ('\u{102}', '\u{303}', '\u{1EB4}'),
+    ('\u{102}', '\u{309}', '\u{1EB2}'),
+    ('\u{103}', '\u{300}', '\u{1EB1}'),
+    ('\u{103}', '\u{301}', '\u{1EAF}'),
+    ('\u{103}', '\u{303}', '\u{1EB5}'),
+    ('\u{103}', '\u{309}', '\u{1EB3}'),
+    ('\u{112}', '\u{300}', '\u{1E14}'),
+    ('\u{112}', '\u{301}', '\u{1E16}'),
+    ('\u{113}', '\u{300}', '\u{1E15}'),
+    ('\u{113}', '\u{301}', '\u{1E17}'),
+    ('\u{14C}', '\u{300}', '\u{1E50}'),
+    ('\u{14C}', '\u{301}', '\u{1E52}'),
+    ('\u{14D}', '\u{300}', '\u{1E51}'),
+    ('\u{14D}', '\u{301}', '\u{1E53}'),
+    ('\u{15A}', '\u{307}', '\u{1E64}'),
+    ('\u{15B}', '\u{307}', '\u{1E65}'),
+    ('\u{160}', '\u{307}', '\u{1E66}'),
+    ('\u{161}', '\u{307}', '\u{1E67}'),
+    ('\u{168}', '\u{301}', '\u{1E78}'),
+    ('\u{169}', '\u{301}', '\u{1E79}'),
+    ('\u{16A}', '\u{308}', '\u{1E7A}'),
+    ('\u{16B}', '\u{308}', '\u{1E7B}'),
+    ('\u{17F}', '\u{307}', '\u{1E9B}'),
+    ('\u{1A0}', '\u{300}', '\u{1EDC}'),
+    ('\u{1A0}', '\u{301}', '\u{1EDA}'),
+    ('\u{1A0}', '\u{303}', '\u{1EE0}'),
+    ('\u{1A0}', '\u{309}', '\u{1EDE}'),
+    ('\u{1A0}', '\u{323}', '\u{1EE2}'),
+    ('\u{1A1}', '\u{300}', '\u{1EDD}'),
+    ('\u{1A1}', '\u{301}', '\u{1EDB}'),
+    ('\u{1A1}', '\u{303}', '\u{1EE1}'),
+    ('\u{1A1}', '\u{309}', '\u{1EDF}'),
+    ('\u{1A1}', '\u{323}', '\u{1EE3}'),
+    ('\u{1AF}', '\u{300}', '\u{1EEA}'),
+    ('\u{1AF}', '\u{301}', '\u{1EE8}'),
+    ('\u{1AF}', '\u{303}', '\u{1EEE}'),
+    ('\u{1AF}', '\u{309}', '\u{1EEC}'),
+    ('\u{1AF}', '\u{323}', '\u{1EF0}'),
+    ('\u{1B0}', '\u{300}', '\u{1EEB}'),
+    ('\u{1B0}', '\u{301}', '\u{1EE9}'),
+    ('\u{1B0}', '\u{303}', '\u{1EEF}'),
+    ('\u{1B0}', '\u{309}', '\u{1EED}'),
+    ('\u{1B0}', '\u{323}', '\u{1EF1}'),
+    ('\u{1B7}', '\u{30C}', '\u{1EE}'),
+    ('\u{1EA}', '\u{304}', '\u{1EC}'),
+    ('\u{1EB}', '\u{304}', '\u{1ED}'),
+    ('\u{226}', '\u{304}', '\u{1E0}'),
+    ('\u{227}', '\u{304}', '\u{1E1}'),
+    ('\u{228}', '\u{306}', '\u{1E1C}'),
+    ('\u{229}', '\u{306}', '\u{1E1D}'),
+    ('\u{22E}', '\u{304}', '\u{230}'),
+    ('\u{22F}', '\u{304}', '\u{231}'),
+    ('\u{292}', '\u{30C}', '\u{1EF}'),
+    ('\u{391}', '\u{300}', '\u{1FBA}'),
+    ('\u{391}', '\u{301}', '\u{386}'),
+    ('\u{391}', '\u{304}', '\u{1FB9}'),
+    ('\u{391}', '\u{306}', '\u{1FB8}'),
+    ('\u{391}', '\u{313}', '\u{1F08}'),
+    ('\u{391}', '\u{314}', '\u{1F09}'),
+    ('\u{391}', '\u{345}', '\u{1FBC}'),
+    ('\u{395}', '\u{300}', '\u{1FC8}'),
+    ('\u{395}', '\u{301}', '\u{388}'),
+    ('\u{395}', '\u{313}', '\u{1F18}'),
+    ('\u{395}', '\u{314}', '\u{1F19}'),
+    ('\u{397}', '\u{300}', '\u{1FCA}'),
+    ('\u{397}', '\u{301}', '\u{389}'),
+    ('\u{397}', '\u{313}', '\u{1F28}'),
+    ('\u{397}', '\u{314}', '\u{1F29}'),
+    ('\u{397}', '\u{345}', '\u{1FCC}'),
+    ('\u{399}', '\u{300}', '\u{1FDA}'),
+    ('\u{399}', '\u{301}', '\u{38A}'),
+    ('\u{399}', '\u{304}', '\u{1FD9}'),
+    ('\u{399}', '\u{306}', '\u{1FD8}'),
+    ('\u{399}', '\u{308}', '\u{3AA}'),
+    ('\u{399}', '\u{313}', '\u{1F38}'),
+    ('\u{399}', '\u{314}', '\u{1F39}'),
+    ('\u{39F}', '\u{300}', '\u{1FF8}'),
+    ('\u{39F}', '\u{301}', '\u{38C}'),
+    ('\u{39F}', '\u{313}', '\u{1F48}'),
+    ('\u{39F}', '\u{314}', '\u{1F49}'),
+    ('\u{3A1}', '\u{314}', '\u{1FEC}'),
+    ('\u{3A5}', '\u{300}', '\u{1FEA}'),
+    ('\u{3A5}', '\u{301}', '\u{38E}'),
+    ('\u{3A5}', '\u{304}', '\u{1FE9}'),
+    ('\u{3A5}', '\u{306}', '\u{1FE8}'),
+    ('\u{3A5}', '\u{308}', '\u{3AB}'),
+    ('\u{3A5}', '\u{314}', '\u{1F59}'),
+    ('\u{3A9}', '\u{300}', '\u{1FFA}'),
+    ('\u{3A9}', '\u{301}', '\u{38F}'),
+    ('\u{3A9}', '\u{313}', '\u{1F68}'),
+    ('\u{3A9}', '\u{314}', '\u{1F69}'),
+    ('\u{3A9}', '\u{345}', '\u{1FFC}'),
+    ('\u{3AC}', '\u{345}', '\u{1FB4}'),
+    ('\u{3AE}', '\u{345}', '\u{1FC4}'),
+    ('\u{3B1}', '\u{300}', '\u{1F70}'),
+    ('\u{3B1}', '\u{301}', '\u{3AC}'),
+    ('\u{3B1}', '\u{304}', '\u{1FB1}'),
+    ('\u{3B1}', '\u{306}', '\u{1FB0}'),
+    ('\u{3B1}', '\u{313}', '\u{1F00}'),
+    ('\u{3B1}', '\u{314}', '\u{1F01}'),
+    ('\u{3B1}', '\u{342}', '\u{1FB6}'),
+    ('\u{3B1}', '\u{345}', '\u{1FB3}'),
+    ('\u{3B5}', '\u{300}', '\u{1F72}'),
+    ('\u{3B5}', '\u{301}', '\u{3AD}'),
+    ('\u{3B5}', '\u{313}', '\u{1F10}'),
+    ('\u{3B5}', '\u{314}', '\u{1F11}'),
+    ('\u{3B7}', '\u{300}', '\u{1F74}'),
+    ('\u{3B7}', '\u{301}', '\u{3AE}'),
+    ('\u{3B7}', '\u{313}', '\u{1F20}'),
+    ('\u{3B7}', '\u{314}', '\u{1F21}'),
+    ('\u{3B7}', '\u{342}', '\u{1FC6}'),
+    ('\u{3B7}', '\u{345}', '\u{1FC3}'),
+    ('\u{3B9}', '\u{300}', '\u{1F76}'),
+    ('\u{3B9}', '\u{301}', '\u{3AF}'),
+    ('\u{3B9}', '\u{304}', '\u{1FD1}'),
+    ('\u{3B9}', '\u{306}', '\u{1FD0}'),
+    ('\u{3B9}', '\u{308}', '\u{3CA}'),
+    ('\u{3B9}', '\u{313}', '\u{1F30}'),
+    ('\u{3B9}', '\u{314}', '\u{1F31}'),
+    ('\u{3B9}', '\u{342}', '\u{1FD6}'),
+    ('\u{3BF}', '\u{300}', '\u{1F78}'),
+    ('\u{3BF}', '\u{301}', '\u{3CC}'),
+    ('\u{3BF}', '\u{313}', '\u{1F40}'),
+    ('\u{3BF}', '\u{314}', '\u{1F41}'),
+    ('\u{3C1}', '\u{313}', '\u{1FE4}'),
+    ('\u{3C1}', '\u{314}', '\u{1FE5}'),
+    ('\u{3C5}', '\u{300}', '\u{1F7A}'),
+    ('\u{3C5}', '\u{301}', '\u{3CD}'),
+    ('\u{3C5}', '\u{304}', '\u{1FE1}'),
+    ('\u{3C5}', '\u{306}', '\u{1FE0}'),
+    ('\u{3C5}', '\u{308}', '\u{3CB}'),
+    ('\u{3C5}', '\u{313}', '\u{1F50}'),
+    ('\u{3C5}', '\u{314}', '\u{1F51}'),
+    ('\u{3C5}', '\u{342}', '\u{1FE6}'),
+    ('\u{3C9}', '\u{300}', '\u{1F7C}'),
+    ('\u{3C9}', '\u{301}', '\u{3CE}'),
+    ('\u{3C9}', '\u{313}', '\u{1F60}'),
+    ('\u{3C9}', '\u{314}', '\u{1F61}'),
+    ('\u{3C9}', '\u{342}', '\u{1FF6}'),
+    ('\u{3C9}', '\u{345}', '\u{1FF3}'),
+    ('\u{3CA}', '\u{300}', '\u{1FD2}'),
+    ('\u{3CA}', '\u{301}', '\u{390}'),
+    ('\u{3CA}', '\u{342}', '\u{1FD7}'),
+    ('\u{3CB}', '\u{300}', '\u{1FE2}'),
+    ('\u{3CB}', '\u{301}', '\u{3B0}'),
+    ('\u{3CB}', '\u{342}', '\u{1FE7}'),
+    ('\u{3CE}', '\u{345}', '\u{1FF4}'),
+    ('\u{3D2}', '\u{301}', '\u{3D3}'),
+    ('\u{3D2}', '\u{308}', '\u{3D4}'),
+    ('\u{406}', '\u{308}', '\u{407}'),
+    ('\u{410}', '\u{306}', '\u{4D0}'),
+    ('\u{410}', '\u{308}', '\u{4D2}'),
+    ('\u{413}', '\u{301}', '\u{403}'),
+    ('\u{415}', '\u{300}', '\u{400}'),
+    ('\u{415}', '\u{306}', '\u{4D6}'),
+    ('\u{415}', '\u{308}', '\u{401}'),
+    ('\u{416}', '\u{306}', '\u{4C1}'),
+    ('\u{416}', '\u{308}', '\u{4DC}'),
+    ('\u{417}', '\u{308}', '\u{4DE}'),
+    ('\u{418}', '\u{300}', '\u{40D}'),
+    ('\u{418}', '\u{304}', '\u{4E2}'),
+    ('\u{418}', '\u{306}', '\u{419}'),
+    ('\u{418}', '\u{308}', '\u{4E4}'),
+    ('\u{41A}', '\u{301}', '\u{40C}'),
+    ('\u{41E}', '\u{308}', '\u{4E6}'),
+    ('\u{423}', '\u{304}', '\u{4EE}'),
+    ('\u{423}', '\u{306}', '\u{40E}'),
+    ('\u{423}', '\u{308}', '\u{4F0}'),
+    ('\u{423}', '\u{30B}', '\u{4F2}'),
+    ('\u{427}', '\u{308}', '\u{4F4}'),
+    ('\u{42B}', '\u{308}', '\u{4F8}'),
+    ('\u{42D}', '\u{308}', '\u{4EC}'),
+    ('\u{430}', '\u{306}', '\u{4D1}'),
+    ('\u{430}', '\u{308}', '\u{4D3}'),
+    ('\u{433}', '\u{301}', '\u{453}'),
+    ('\u{435}', '\u{300}', '\u{450}'),
+    ('\u{435}', '\u{306}', '\u{4D7}'),
+    ('\u{435}', '\u{308}', '\u{451}'),
+    ('\u{436}', '\u{306}', '\u{4C2}'),
+    ('\u{436}', '\u{308}', '\u{4DD}'),
+    ('\u{437}', '\u{308}', '\u{4DF}'),
+    ('\u{438}', '\u{300}', '\u{45D}'),
+    ('\u{438}', '\u{304}', '\u{4E3}'),
+    ('\u{438}', '\u{306}', '\u{439}'),
+    ('\u{438}', '\u{308}', '\u{4E5}'),
+    ('\u{43A}', '\u{301}', '\u{45C}'),
+    ('\u{43E}', '\u{308}', '\u{4E7}'),
+    ('\u{443}', '\u{304}', '\u{4EF}'),
+    ('\u{443}', '\u{306}', '\u{45E}'),
+    ('\u{443}', '\u{308}', '\u{4F1}'),
+    ('\u{443}', '\u{30B}', '\u{4F3}'),
+    ('\u{447}', '\u{308}', '\u{4F5}'),
+    ('\u{44B}', '\u{308}', '\u{4F9}'),
+    ('\u{44D}', '\u{308}', '\u{4ED}'),
+    ('\u{456}', '\u{308}', '\u{457}'),
+    ('\u{474}', '\u{30F}', '\u{476}'),
+    ('\u{475}', '\u{30F}', '\u{477}'),
+    ('\u{4D8}', '\u{308}', '\u{4DA}'),
+    ('\u{4D9}', '\u{308}', '\u{4DB}'),
+    ('\u{4E8}', '\u{308}', '\u{4EA}'),
+    ('\u{4E9}', '\u{308}', '\u{4EB}'),
+    ('\u{627}', '\u{653}', '\u{622}'),
+    ('\u{627}', '\u{654}', '\u{623}'),
+    ('\u{627}', '\u{655}', '\u{625}'),
+    ('\u{648}', '\u{654}', '\u{624}'),
+    ('\u{64A}', '\u{654}', '\u{626}'),
+    ('\u{6C1}', '\u{654}', '\u{6C2}'),
+    ('\u{6D2}', '\u{654}', '\u{6D3}'),
+    ('\u{6D5}', '\u{654}', '\u{6C0}'),
+    ('\u{928}', '\u{93C}', '\u{929}'),
+    ('\u{930}', '\u{93C}', '\u{931}'),
+    ('\u{933}', '\u{93C}', '\u{934}'),
+    ('\u{9C7}', '\u{9BE}', '\u{9CB}'),
+    ('\u{9C7}', '\u{9D7}', '\u{9CC}'),
+    ('\u{B47}', '\u{B3E}', '\u{B4B}'),
+    ('\u{B47}', '\u{B56}', '\u{B48}'),
+    ('\u{B47}', '\u{B57}', '\u{B4C}'),
+    ('\u{B92}', '\u{BD7}', '\u{B94}'),
+    ('\u{BC6}', '\u{BBE}', '\u{BCA}'),
+    ('\u{BC6}', '\u{BD7}', '\u{BCC}'),
+    ('\u{BC7}', '\u{BBE}', '\u{BCB}'),
+    ('\u{C46}', '\u{C56}', '\u{C48}'),
+    ('\u{CBF}', '\u{CD5}', '\u{CC0}'),
+    ('\u{CC6}', '\u{CC2}', '\u{CCA}'),
+    ('\u{CC6}', '\u{CD5}', '\u{CC7}'),
+    ('\u{CC6}', '\u{CD6}', '\u{CC8}'),
+    ('\u{CCA}', '\u{CD5}', '\u{CCB}'),
+    ('\u{D46}', '\u{D3E}', '\u{D4A}'),
+    ('\u{D46}', '\u{D57}', '\u{D4C}'),
+    ('\u{D47}', '\u{D3E}', '\u{D4B}'),
+    ('\u{DD9}', '\u{DCA}', '\u{DDA}'),
+    ('\u{DD9}', '\u{DCF}', '\u{DDC}'),
+    ('\u{DD9}', '\u{DDF}', '\u{DDE}'),
+    ('\u{DDC}', '\u{DCA}', '\u{DDD}'),
+    ('\u{1025}', '\u{102E}', '\u{1026}'),
+    ('\u{1B05}', '\u{1B35}', '\u{1B06}'),
+    ('\u{1B07}', '\u{1B35}', '\u{1B08}'),
+    ('\u{1B09}', '\u{1B35}', '\u{1B0A}'),
+    ('\u{1B0B}', '\u{1B35}', '\u{1B0C}'),
+    ('\u{1B0D}', '\u{1B35}', '\u{1B0E}'),
+    ('\u{1B11}', '\u{1B35}', '\u{1B12}'),
+    ('\u{1B3A}', '\u{1B35}', '\u{1B3B}'),
+    ('\u{1B3C}', '\u{1B35}', '\u{1B3D}'),
+    ('\u{1B3E}', '\u{1B35}', '\u{1B40}'),
+    ('\u{1B3F}', '\u{1B35}', '\u{1B41}'),
+    ('\u{1B42}', '\u{1B35}', '\u{1B43}'),
+    ('\u{1E36}', '\u{304}', '\u{1E38}'),
+    ('\u{1E37}', '\u{304}', '\u{1E39}'),
+    ('\u{1E5A}', '\u{304}', '\u{1E5C}'),
+    ('\u{1E5B}', '\u{304}', '\u{1E5D}'),
+    ('\u{1E62}', '\u{307}', '\u{1E68}'),
+    ('\u{1E63}', '\u{307}', '\u{1E69}'),
+    ('\u{1EA0}', '\u{302}', '\u{1EAC}'),
+    ('\u{1EA0}', '\u{306}', '\u{1EB6}'),
+    ('\u{1EA1}', '\u{302}', '\u{1EAD}'),
+    ('\u{1EA1}', '\u{306}', '\u{1EB7}'),
+    ('\u{1EB8}', '\u{302}', '\u{1EC6}'),
+    ('\u{1EB9}', '\u{302}', '\u{1EC7}'),
+    ('\u{1ECC}', '\u{302}', '\u{1ED8}'),
+    ('\u{1ECD}', '\u{302}', '\u{1ED9}'),
+    ('\u{1F00}', '\u{300}', '\u{1F02}'),
+    ('\u{1F00}', '\u{301}', '\u{1F04}'),
+    ('\u{1F00}', '\u{342}', '\u{1F06}'),
+    ('\u{1F00}', '\u{345}', '\u{1F80}'),
+    ('\u{1F01}', '\u{300}', '\u{1F03}'),
+    ('\u{1F01}', '\u{301}', '\u{1F05}'),
+    ('\u{1F01}', '\u{342}', '\u{1F07}'),
+    ('\u{1F01}', '\u{345}', '\u{1F81}'),
+    ('\u{1F02}', '\u{345}', '\u{1F82}'),
+    ('\u{1F03}', '\u{345}', '\u{1F83}'),
+    ('\u{1F04}', '\u{345}', '\u{1F84}'),
+    ('\u{1F05}', '\u{345}', '\u{1F85}'),
+    ('\u{1F06}', '\u{345}', '\u{1F86}'),
+    ('\u{1F07}', '\u{345}', '\u{1F87}'),
+    ('\u{1F08}', '\u{300}', '\u{1F0A}'),
+    ('\u{1F08}', '\u{301}', '\u{1F0C}'),
+    ('\u{1F08}', '\u{342}', '\u{1F0E}'),
+    ('\u{1F08}', '\u{345}', '\u{1F88}'),
+    ('\u{1F09}', '\u{300}', '\u{1F0B}'),
+    ('\u{1F09}', '\u{301}', '\u{1F0D}'),
+    ('\u{1F09}', '\u{342}', '\u{1F0F}'),
+    ('\u{1F09}', '\u{345}', '\u{1F89}'),
+    ('\u{1F0A}', '\u{345}', '\u{1F8A}'),
+    ('\u{1F0B}', '\u{345}', '\u{1F8B}'),
+    ('\u{1F0C}', '\u{345}', '\u{1F8C}'),
+    ('\u{1F0D}', '\u{345}', '\u{1F8D}'),
+    ('\u{1F0E}', '\u{345}', '\u{1F8E}'),
+    ('\u{1F0F}', '\u{345}', '\u{1F8F}'),
+    ('\u{1F10}', '\u{300}', '\u{1F12}'),
+    ('\u{1F10}', '\u{301}', '\u{1F14}'),
+    ('\u{1F11}', '\u{300}', '\u{1F13}'),
+    ('\u{1F11}', '\u{301}', '\u{1F15}'),
+    ('\u{1F18}', '\u{300}', '\u{1F1A}'),
+    ('\u{1F18}', '\u{301}', '\u{1F1C}'),
+    ('\u{1F19}', '\u{300}', '\u{1F1B}'),
+    ('\u{1F19}', '\u{301}', '\u{1F1D}'),
+    ('\u{1F20}', '\u{300}', '\u{1F22}'),
+    ('\u{1F20}', '\u{301}', '\u{1F24}'),
+    ('\u{1F20}', '\u{342}', '\u{1F26}'),
+    ('\u{1F20}', '\u{345}', '\u{1F90}'),
+    ('\u{1F21}', '\u{300}', '\u{1F23}'),
+    ('\u{1F21}', '\u{301}', '\u{1F25}'),
+    ('\u{1F21}', '\u{342}', '\u{1F27}'),
+    ('\u{1F21}', '\u{345}', '\u{1F91}'),
+    ('\u{1F22}', '\u{345}', '\u{1F92}'),
+    ('\u{1F23}', '\u{345}', '\u{1F93}'),
+    ('\u{1F24}', '\u{345}', '\u{1F94}'),
+    ('\u{1F25}', '\u{345}', '\u{1F95}'),
+    ('\u{1F26}', '\u{345}', '\u{1F96}'),
+    ('\u{1F27}', '\u{345}', '\u{1F97}'),
+    ('\u{1F28}', '\u{300}', '\u{1F2A}'),
+    ('\u{1F28}', '\u{301}', '\u{1F2C}'),
+    ('\u{1F28}', '\u{342}', '\u{1F2E}'),
+    ('\u{1F28}', '\u{345}', '\u{1F98}'),
+    ('\u{1F29}', '\u{300}', '\u{1F2B}'),
+    ('\u{1F29}', '\u{301}', '\u{1F2D}'),
+    ('\u{1F29}', '\u{342}', '\u{1F2F}'),
+    ('\u{1F29}', '\u{345}', '\u{1F99}'),
+    ('\u{1F2A}', '\u{345}', '\u{1F9A}'),
+    ('\u{1F2B}', '\u{345}', '\u{1F9B}'),
+    ('\u{1F2C}', '\u{345}', '\u{1F9C}'),
+    ('\u{1F2D}', '\u{345}', '\u{1F9D}'),
+    ('\u{1F2E}', '\u{345}', '\u{1F9E}'),
+    ('\u{1F2F}', '\u{345}', '\u{1F9F}'),
+    ('\u{1F30}', '\u{300}', '\u{1F32}'),
+    ('\u{1F30}', '\u{301}', '\u{1F34}'),
+    ('\u{1F30}', '\u{342}', '\u{1F36}'),
+    ('\u{1F31}', '\u{300}', '\u{1F33}'),
+    ('\u{1F31}', '\u{301}', '\u{1F35}'),
+    ('\u{1F31}', '\u{342}', '\u{1F37}'),
+    ('\u{1F38}', '\u{300}', '\u{1F3A}'),
+    ('\u{1F38}', '\u{301}', '\u{1F3C}'),
+    ('\u{1F38}', '\u{342}', '\u{1F3E}'),
+    ('\u{1F39}', '\u{300}', '\u{1F3B}'),
+    ('\u{1F39}', '\u{301}', '\u{1F3D}'),
+    ('\u{1F39}', '\u{342}', '\u{1F3F}'),
+    ('\u{1F40}', '\u{300}', '\u{1F42}'),
+    ('\u{1F40}', '\u{301}', '\u{1F44}'),
+    ('\u{1F41}', '\u{300}', '\u{1F43}'),
+    ('\u{1F41}', '\u{301}', '\u{1F45}'),
+    ('\u{1F48}', '\u{300}', '\u{1F4A}'),
+    ('\u{1F48}', '\u{301}', '\u{1F4C}'),
+    ('\u{1F49}', '\u{300}', '\u{1F4B}'),
+    ('\u{1F49}', '\u{301}', '\u{1F4D}'),
+    ('\u{1F50}', '\u{300}', '\u{1F52}'),
+    ('\u{1F50}', '\u{301}', '\u{1F54}'),
+    ('\u{1F50}', '\u{342}', '\u{1F56}'),
+    ('\u{1F51}', '\u{300}', '\u{1F53}'),
+    ('\u{1F51}', '\u{301}', '\u{1F55}'),
+    ('\u{1F51}', '\u{342}', '\u{1F57}'),
+    ('\u{1F59}', '\u{300}', '\u{1F5B}'),
+    ('\u{1F59}', '\u{301}', '\u{1F5D}'),
+    ('\u{1F59}', '\u{342}', '\u{1F5F}'),
+    ('\u{1F60}', '\u{300}', '\u{1F62}'),
+    ('\u{1F60}', '\u{301}', '\u{1F64}'),
+    ('\u{1F60}', '\u{342}', '\u{1F66}'),
+    ('\u{1F60}', '\u{345}', '\u{1FA0}'),
+    ('\u{1F61}', '\u{300}', '\u{1F63}'),
+    ('\u{1F61}', '\u{301}', '\u{1F65}'),
+    ('\u{1F61}', '\u{342}', '\u{1F67}'),
+    ('\u{1F61}', '\u{345}', '\u{1FA1}'),
+    ('\u{1F62}', '\u{345}', '\u{1FA2}'),
+    ('\u{1F63}', '\u{345}', '\u{1FA3}'),
+    ('\u{1F64}', '\u{345}', '\u{1FA4}'),
+    ('\u{1F65}', '\u{345}', '\u{1FA5}'),
+    ('\u{1F66}', '\u{345}', '\u{1FA6}'),
+    ('\u{1F67}', '\u{345}', '\u{1FA7}'),
+    ('\u{1F68}', '\u{300}', '\u{1F6A}'),
+    ('\u{1F68}', '\u{301}', '\u{1F6C}'),
+    ('\u{1F68}', '\u{342}', '\u{1F6E}'),
+    ('\u{1F68}', '\u{345}', '\u{1FA8}'),
+    ('\u{1F69}', '\u{300}', '\u{1F6B}'),
+    ('\u{1F69}', '\u{301}', '\u{1F6D}'),
+    ('\u{1F69}', '\u{342}', '\u{1F6F}'),
+    ('\u{1F69}', '\u{345}', '\u{1FA9}'),
+    ('\u{1F6A}', '\u{345}', '\u{1FAA}'),
+    ('\u{1F6B}', '\u{345}', '\u{1FAB}'),
+    ('\u{1F6C}', '\u{345}', '\u{1FAC}'),
+    ('\u{1F6D}', '\u{345}', '\u{1FAD}'),
+    ('\u{1F6E}', '\u{345}', '\u{1FAE}'),
+    ('\u{1F6F}', '\u{345}', '\u{1FAF}'),
+    ('\u{1F70}', '\u{345}', '\u{1FB2}'),
+    ('\u{1F74}', '\u{345}', '\u{1FC2}'),
+    ('\u{1F7C}', '\u{345}', '\u{1FF2}'),
+    ('\u{1FB6}', '\u{345}', '\u{1FB7}'),
+    ('\u{1FBF}', '\u{300}', '\u{1FCD}'),
+    ('\u{1FBF}', '\u{301}', '\u{1FCE}'),
+    ('\u{1FBF}', '\u{342}', '\u{1FCF}'),
+    ('\u{1FC6}', '\u{345}', '\u{1FC7}'),
+    ('\u{1FF6}', '\u{345}', '\u{1FF7}'),
+    ('\u{1FFE}', '\u{300}', '\u{1FDD}'),
+    ('\u{1FFE}', '\u{301}', '\u{1FDE}'),
+    ('\u{1FFE}', '\u{342}', '\u{1FDF}'),
+    ('\u{2190}', '\u{338}', '\u{219A}'),
+    ('\u{2192}', '\u{338}', '\u{219B}'),
+    ('\u{2194}', '\u{338}', '\u{21AE}'),
+    ('\u{21D0}', '\u{338}', '\u{21CD}'),
+    ('\u{21D2}', '\u{338}', '\u{21CF}'),
+    ('\u{21D4}', '\u{338}', '\u{21CE}'),
+    ('\u{2203}', '\u{338}', '\u{2204}'),
+    ('\u{2208}', '\u{338}', '\u{2209}'),
+    ('\u{220B}', '\u{338}', '\u{220C}'),
+    ('\u{2223}', '\u{338}', '\u{2224}'),
+    ('\u{2225}', '\u{338}', '\u{2226}'),
+    ('\u{223C}', '\u{338}', '\u{2241}'),
+    ('\u{2243}', '\u{338}', '\u{2244}'),
+    ('\u{2245}', '\u{338}', '\u{2247}'),
+    ('\u{2248}', '\u{338}', '\u{2249}'),
+    ('\u{224D}', '\u{338}', '\u{226D}'),
+    ('\u{2261}', '\u{338}', '\u{2262}'),
+    ('\u{2264}', '\u{338}', '\u{2270}'),
+    ('\u{2265}', '\u{338}', '\u{2271}'),
+    ('\u{2272}', '\u{338}', '\u{2274}'),
+    ('\u{2273}', '\u{338}', '\u{2275}'),
+    ('\u{2276}', '\u{338}', '\u{2278}'),
+    ('\u{2277}', '\u{338}', '\u{2279}'),
+    ('\u{227A}', '\u{338}', '\u{2280}'),
+    ('\u{227B}', '\u{338}', '\u{2281}'),
+    ('\u{227C}', '\u{338}', '\u{22E0}'),
+    ('\u{227D}', '\u{338}', '\u{22E1}'),
+    ('\u{2282}', '\u{338}', '\u{2284}'),
+    ('\u{2283}', '\u{338}', '\u{2285}'),
+    ('\u{2286}', '\u{338}', '\u{2288}'),
+    ('\u{2287}', '\u{338}', '\u{2289}'),
+    ('\u{2291}', '\u{338}', '\u{22E2}'),
+    ('\u{2292}', '\u{338}', '\u{22E3}'),
+    ('\u{22A2}', '\u{338}', '\u{22AC}'),
+    ('\u{22A8}', '\u{338}', '\u{22AD}'),
+    ('\u{22A9}', '\u{338}', '\u{22AE}'),
+    ('\u{22AB}', '\u{338}', '\u{22AF}'),
+    ('\u{22B2}', '\u{338}', '\u{22EA}'),
+    ('\u{22B3}', '\u{338}', '\u{22EB}'),
+    ('\u{22B4}', '\u{338}', '\u{22EC}'),
+    ('\u{22B5}', '\u{338}', '\u{22ED}'),
+    ('\u{3046}', '\u{3099}', '\u{3094}'),
+    ('\u{304B}', '\u{3099}', '\u{304C}'),
+    ('\u{304D}', '\u{3099}', '\u{304E}'),
+    ('\u{304F}', '\u{3099}', '\u{3050}'),
+    ('\u{3051}', '\u{3099}', '\u{3052}'),
+    ('\u{3053}', '\u{3099}', '\u{3054}'),
+    ('\u{3055}', '\u{3099}', '\u{3056}'),
+    ('\u{3057}', '\u{3099}', '\u{3058}'),
+    ('\u{3059}', '\u{3099}', '\u{305A}'),
+    ('\u{305B}', '\u{3099}', '\u{305C}'),
+    ('\u{305D}', '\u{3099}', '\u{305E}'),
+    ('\u{305F}', '\u{3099}', '\u{3060}'),
+    ('\u{3061}', '\u{3099}', '\u{3062}'),
+    ('\u{3064}', '\u{3099}', '\u{3065}'),
+    ('\u{3066}', '\u{3099}', '\u{3067}'),
+    ('\u{3068}', '\u{3099}', '\u{3069}'),
+    ('\u{306F}', '\u{3099}', '\u{3070}'),
+    ('\u{306F}', '\u{309A}', '\u{3071}'),
+    ('\u{3072}', '\u{3099}', '\u{3073}'),
+    ('\u{3072}', '\u{309A}', '\u{3074}'),
+    ('\u{3075}', '\u{3099}', '\u{3076}'),
+    ('\u{3075}', '\u{309A}', '\u{3077}'),
+    ('\u{3078}', '\u{3099}', '\u{3079}'),
+    ('\u{3078}', '\u{309A}', '\u{307A}'),
+    ('\u{307B}', '\u{3099}', '\u{307C}'),
+    ('\u{307B}', '\u{309A}', '\u{307D}'),
+    ('\u{309D}', '\u{3099}', '\u{309E}'),
+    ('\u{30A6}', '\u{3099}', '\u{30F4}'),
+    ('\u{30AB}', '\u{3099}', '\u{30AC}'),
+    ('\u{30AD}', '\u{3099}', '\u{30AE}'),
+    ('\u{30AF}', '\u{3099}', '\u{30B0}'),
+    ('\u{30B1}', '\u{3099}', '\u{30B2}'),
+    ('\u{30B3}', '\u{3099}', '\u{30B4}'),
+    ('\u{30B5}', '\u{3099}', '\u{30B6}'),
+    ('\u{30B7}', '\u{3099}', '\u{30B8}'),
+    ('\u{30B9}', '\u{3099}', '\u{30BA}'),
+    ('\u{30BB}', '\u{3099}', '\u{30BC}'),
+    ('\u{30BD}', '\u{3099}', '\u{30BE}'),
+    ('\u{30BF}', '\u{3099}', '\u{30C0}'),
+    ('\u{30C1}', '\u{3099}', '\u{30C2}'),
+    ('\u{30C4}', '\u{3099}', '\u{30C5}'),
+    ('\u{30C6}', '\u{3099}', '\u{30C7}'),
+    ('\u{30C8}', '\u{3099}', '\u{30C9}'),
+    ('\u{30CF}', '\u{3099}', '\u{30D0}'),
+    ('\u{30CF}', '\u{309A}', '\u{30D1}'),
+    ('\u{30D2}', '\u{3099}', '\u{30D3}'),
+    ('\u{30D2}', '\u{309A}', '\u{30D4}'),
+    ('\u{30D5}', '\u{3099}', '\u{30D6}'),
+    ('\u{30D5}', '\u{309A}', '\u{30D7}'),
+    ('\u{30D8}', '\u{3099}', '\u{30D9}'),
+    ('\u{30D8}', '\u{309A}', '\u{30DA}'),
+    ('\u{30DB}', '\u{3099}', '\u{30DC}'),
+    ('\u{30DB}', '\u{309A}', '\u{30DD}'),
+    ('\u{30EF}', '\u{3099}', '\u{30F7}'),
+    ('\u{30F0}', '\u{3099}', '\u{30F8}'),
+    ('\u{30F1}', '\u{3099}', '\u{30F9}'),
+    ('\u{30F2}', '\u{3099}', '\u{30FA}'),
+    ('\u{30FD}', '\u{3099}', '\u{30FE}'),
+    ('\u{11099}', '\u{110BA}', '\u{1109A}'),
+    ('\u{1109B}', '\u{110BA}', '\u{1109C}'),
+    ('\u{110A5}', '\u{110BA}', '\u{110AB}'),
+    ('\u{11131}', '\u{11127}', '\u{1112E}'),
+    ('\u{11132}', '\u{11127}', '\u{1112F}'),
+    ('\u{11347}', '\u{1133E}', '\u{1134B}'),
+    ('\u{11347}', '\u{11357}', '\u{1134C}'),
+    ('\u{114B9}', '\u{114B0}', '\u{114BC}'),
+    ('\u{114B9}', '\u{114BA}', '\u{114BB}'),
+    ('\u{114B9}', '\u{114BD}', '\u{114BE}'),
+    ('\u{115B8}', '\u{115AF}', '\u{115BA}'),
+    ('\u{115B9}', '\u{115AF}', '\u{115BB}'),
+    ('\u{11935}', '\u{11930}', '\u{11938}'),
+];
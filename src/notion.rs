@@ -0,0 +1,131 @@
+use crate::catalog::csv_field;
+use crate::output;
+use crate::saved::SavedBook;
+use crate::toc;
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+
+/// Inline HTML Notion's importer shows as literal text; the tags are dropped and their
+/// text kept
+const STRIPPED_TAGS: &[&str] = &[
+    "sup", "sub", "u", "ins", "mark", "span", "small", "cite", "abbr", "q", "div", "p", "bdi", "bdo", "font",
+];
+
+/// Deepest heading level Notion has
+const MAX_HEADING: usize = 3;
+
+/// Rewrite a chapter into the Markdown Notion's importer handles: headings below level 3
+/// become bold paragraphs, nested quotes are flattened, and inline HTML other than the
+/// `<aside>` callouts is reduced to its text. Fenced code is left alone.
+pub fn constrain(markdown: &str) -> String {
+    // Setext headings become ATX, so every heading is a single line
+    let markdown = toc::shift_headings(markdown, 0);
+    let mut out = String::with_capacity(markdown.len());
+    let mut in_fence = false;
+
+    for line in markdown.lines() {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_fence = !in_fence;
+        }
+        if in_fence || trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            out.push_str(line);
+            out.push('\n');
+            continue;
+        }
+
+        let hashes = line.len() - line.trim_start_matches('#').len();
+        let line = if hashes > MAX_HEADING && hashes <= 6 && line[hashes..].starts_with(' ') {
+            format!("**{}**", line[hashes..].trim())
+        } else if line.starts_with('>') {
+            // Notion quotes don't nest
+            let text = line.trim_start_matches(['>', ' ']);
+            if text.is_empty() { ">".to_string() } else { format!("> {}", text) }
+        } else {
+            line.to_string()
+        };
+        let line = strip_tags(&line);
+        // A line left empty by removed wrapper tags would split its paragraph
+        if line.trim().is_empty() && !trimmed.is_empty() {
+            continue;
+        }
+        out.push_str(&line);
+        out.push('\n');
+    }
+    if !markdown.ends_with('\n') {
+        out.pop();
+    }
+    out
+}
+
+/// Drop the tags in `STRIPPED_TAGS`, and `<br>` for a space, outside code spans
+fn strip_tags(line: &str) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut in_code = false;
+    let mut rest = line;
+    while let Some(c) = rest.chars().next() {
+        if c == '`' {
+            in_code = !in_code;
+        }
+        if c == '<' && !in_code {
+            if let Some(end) = rest.find('>') {
+                let tag = &rest[1..end];
+                let name = tag.trim_start_matches('/').split([' ', '/']).next().unwrap_or_default().to_ascii_lowercase();
+                if name == "br" {
+                    out.push(' ');
+                    rest = &rest[end + 1..];
+                    continue;
+                }
+                if STRIPPED_TAGS.contains(&name.as_str()) {
+                    rest = &rest[end + 1..];
+                    continue;
+                }
+            }
+        }
+        out.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+    out
+}
+
+const CSV_COLUMNS: &[&str] = &[
+    "Name", "Authors", "Series", "Series Index", "Published", "Publisher", "Language", "Tags", "Identifier", "Words", "Folder",
+];
+
+/// Write `notion.csv` into `base`: one row per converted book, to import into Notion as a
+/// database. Multi-value columns are comma-separated, as Notion's multi-select expects.
+pub fn write_database(books: &[PathBuf], base: &Path) -> Result<PathBuf> {
+    let mut rows = Vec::with_capacity(books.len());
+    for output_dir in books {
+        let book = SavedBook::load(output_dir)?;
+        let list = |values: &[String]| values.iter().map(|v| v.replace(',', " ").trim().to_string()).collect::<Vec<_>>().join(", ");
+        let opt = |v: Option<String>| v.unwrap_or_default();
+        let folder = output_dir.strip_prefix(base).unwrap_or(output_dir).to_string_lossy().into_owned();
+        rows.push(vec![
+            opt(book.title),
+            list(&book.creators),
+            opt(book.series),
+            book.series_index.map(|i| i.to_string()).unwrap_or_default(),
+            // Notion only recognizes full dates
+            book.date.map(|d| d.chars().take(10).collect()).unwrap_or_default(),
+            opt(book.publisher),
+            opt(book.language),
+            list(&book.subjects),
+            opt(book.identifier),
+            book.word_count.map(|w| w.to_string()).unwrap_or_default(),
+            folder,
+        ]);
+    }
+    rows.sort_by(|a, b| a[10].cmp(&b[10]));
+
+    let mut csv = CSV_COLUMNS.join(",");
+    csv.push('\n');
+    for fields in &rows {
+        let row: Vec<String> = fields.iter().map(|f| csv_field(f)).collect();
+        csv.push_str(&row.join(","));
+        csv.push('\n');
+    }
+    let path = base.join("notion.csv");
+    output::write_atomic(&path, csv).context("Failed to write notion.csv")?;
+    Ok(path)
+}
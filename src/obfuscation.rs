@@ -0,0 +1,102 @@
+use crate::digest;
+use serde::Serialize;
+use std::collections::HashMap;
+use xml::reader::{EventReader, XmlEvent};
+
+/// Where the archive lists its encrypted and obfuscated resources
+pub const ENCRYPTION_FILE: &str = "META-INF/encryption.xml";
+
+const IDPF: &str = "http://www.idpf.org/2008/embedding";
+const ADOBE: &str = "http://ns.adobe.com/pdf/enc#RC";
+
+/// Encryption algorithms that only obfuscate embedded fonts, leaving the text readable
+pub const FONT_ALGORITHMS: &[&str] = &[IDPF, ADOBE];
+
+/// How an embedded font was obfuscated: its first bytes XORed with a key derived from the
+/// book's identifier, so the font can't simply be copied out of the archive
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Obfuscation {
+    /// IDPF font obfuscation: the first 1040 bytes, with the SHA-1 of the unique identifier
+    Idpf,
+    /// Adobe's: the first 1024 bytes, with the 16 bytes of the book's UUID
+    Adobe,
+}
+
+impl Obfuscation {
+    /// Bytes at the start of the font that are obfuscated
+    fn header_len(self) -> usize {
+        match self {
+            Obfuscation::Idpf => 1040,
+            Obfuscation::Adobe => 1024,
+        }
+    }
+
+    /// The key, from the package's unique identifier and its other identifiers; None when
+    /// none of them gives one (Adobe's needs a UUID)
+    fn key(self, unique_identifier: Option<&str>, identifiers: &[String]) -> Option<Vec<u8>> {
+        match self {
+            Obfuscation::Idpf => {
+                // Only these four whitespace characters are removed, as the specification says
+                let id: String = unique_identifier?.chars().filter(|c| !matches!(c, ' ' | '\t' | '\r' | '\n')).collect();
+                Some(digest::sha1(id.as_bytes()).to_vec())
+            }
+            Obfuscation::Adobe => unique_identifier.into_iter().chain(identifiers.iter().map(String::as_str)).find_map(uuid_bytes),
+        }
+    }
+}
+
+/// The 16 bytes of a UUID identifier (`urn:uuid:...`, or just the UUID)
+fn uuid_bytes(identifier: &str) -> Option<Vec<u8>> {
+    let identifier = identifier.trim();
+    let uuid = identifier.strip_prefix("urn:uuid:").unwrap_or(identifier);
+    let hex: String = uuid.chars().filter(|&c| c != '-').collect();
+    if hex.len() != 32 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    (0..16).map(|i| u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).ok()).collect()
+}
+
+/// The resources `encryption.xml` lists as obfuscated fonts, by archive path
+pub fn obfuscated_resources(encryption_xml: &str) -> HashMap<String, Obfuscation> {
+    let mut resources = HashMap::new();
+    let mut method = None;
+    for event in EventReader::new(encryption_xml.as_bytes()).into_iter().flatten() {
+        match event {
+            XmlEvent::StartElement { name, attributes, .. } => {
+                let attr = |key: &str| attributes.iter().find(|a| a.name.local_name == key).map(|a| a.value.trim().to_string());
+                match name.local_name.as_str() {
+                    "EncryptedData" => method = None,
+                    "EncryptionMethod" => {
+                        method = match attr("Algorithm").as_deref() {
+                            Some(IDPF) => Some(Obfuscation::Idpf),
+                            Some(ADOBE) => Some(Obfuscation::Adobe),
+                            _ => None,
+                        }
+                    }
+                    "CipherReference" => {
+                        if let (Some(method), Some(uri)) = (method, attr("URI")) {
+                            let path = percent_encoding::percent_decode_str(&uri).decode_utf8_lossy().into_owned();
+                            resources.insert(path, method);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            XmlEvent::EndElement { name } if name.local_name == "EncryptedData" => method = None,
+            _ => {}
+        }
+    }
+    resources
+}
+
+/// Restore an obfuscated font in place. Returns false, leaving it untouched, when the
+/// book's identifiers don't give the key.
+pub fn deobfuscate(data: &mut [u8], method: Obfuscation, unique_identifier: Option<&str>, identifiers: &[String]) -> bool {
+    let Some(key) = method.key(unique_identifier, identifiers) else { return false };
+    let len = data.len().min(method.header_len());
+    for (byte, k) in data[..len].iter_mut().zip(key.iter().cycle()) {
+        *byte ^= k;
+    }
+    true
+}
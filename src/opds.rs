@@ -0,0 +1,186 @@
+use crate::filter::{self, MetadataFilter};
+use crate::remote;
+use anyhow::{Context, Result};
+use std::collections::HashSet;
+use xml::reader::{EventReader, XmlEvent};
+
+/// Upper bound on feed pages fetched in one crawl, in case a catalog links to itself endlessly
+const MAX_PAGES: usize = 1000;
+
+/// A publication listed in an OPDS feed
+#[derive(Debug, Default)]
+pub struct Publication {
+    pub title: String,
+    pub authors: Vec<String>,
+    pub language: Option<String>,
+    pub subjects: Vec<String>,
+    pub identifier: Option<String>,
+    pub publisher: Option<String>,
+    /// Absolute URL of the EPUB acquisition link
+    pub epub_url: Option<String>,
+}
+
+impl Publication {
+    fn field(&self, name: &str) -> Vec<&str> {
+        match name {
+            "title" => vec![self.title.as_str()],
+            "author" => self.authors.iter().map(String::as_str).collect(),
+            "language" => self.language.iter().map(String::as_str).collect(),
+            "subject" => self.subjects.iter().map(String::as_str).collect(),
+            "publisher" => self.publisher.iter().map(String::as_str).collect(),
+            "identifier" => self.identifier.iter().map(String::as_str).collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// One parsed feed page: its publications and the feeds it links to
+#[derive(Default)]
+struct FeedPage {
+    publications: Vec<Publication>,
+    /// Pagination and navigation links to other catalog feeds
+    feeds: Vec<String>,
+}
+
+/// Walk an OPDS catalog from `root`, following pagination and navigation links,
+/// and return every EPUB publication that passes `filters`, in discovery order
+pub fn crawl(root: &str, filters: &[MetadataFilter], progress: &crate::progress::Progress) -> Result<Vec<Publication>> {
+    let mut queue = vec![root.to_string()];
+    let mut visited = HashSet::new();
+    let mut seen_books = HashSet::new();
+    let mut found = Vec::new();
+
+    while let Some(url) = queue.pop() {
+        if !visited.insert(url.clone()) {
+            continue;
+        }
+        if visited.len() > MAX_PAGES {
+            anyhow::bail!("OPDS catalog has more than {} feed pages; giving up", MAX_PAGES);
+        }
+
+        progress.text(format!("Reading feed {}", url));
+        let body = remote::fetch(&url)?;
+        let page = parse_feed(&body, &url).with_context(|| format!("Failed to parse OPDS feed {}", url))?;
+
+        for publication in page.publications {
+            let Some(epub_url) = &publication.epub_url else { continue };
+            if !filter::matches_all(filters, &|name| publication.field(name)) {
+                continue;
+            }
+            if seen_books.insert(epub_url.clone()) {
+                found.push(publication);
+            }
+        }
+        // Depth-first in document order
+        queue.extend(page.feeds.into_iter().rev().filter(|f| !visited.contains(f)));
+    }
+
+    Ok(found)
+}
+
+fn attr<'a>(attributes: &'a [xml::attribute::OwnedAttribute], name: &str) -> Option<&'a str> {
+    attributes
+        .iter()
+        .find(|a| a.name.local_name == name && a.name.prefix.is_none())
+        .map(|a| a.value.as_str())
+}
+
+const OPEN_ACCESS: &str = "http://opds-spec.org/acquisition/open-access";
+
+fn is_epub_acquisition(rel: &str, mime: &str) -> bool {
+    rel.starts_with("http://opds-spec.org/acquisition") && mime.starts_with("application/epub+zip")
+}
+
+/// Whether a link points at another catalog feed worth crawling
+fn is_feed_link(rel: &str, mime: &str) -> bool {
+    let catalog = mime.starts_with("application/atom+xml") && !mime.contains("type=entry");
+    let skip = ["self", "start", "up", "search", "alternate", "related"];
+    catalog && !skip.contains(&rel) && !rel.ends_with("/facet")
+}
+
+fn parse_feed(body: &str, base: &str) -> Result<FeedPage> {
+    let mut page = FeedPage::default();
+    let mut current: Option<Publication> = None;
+    let mut path: Vec<String> = Vec::new();
+    let mut text = String::new();
+
+    for event in EventReader::new(body.as_bytes()) {
+        match event.context("Malformed XML")? {
+            XmlEvent::StartElement { name, attributes, .. } => {
+                let local = name.local_name.as_str();
+                text.clear();
+                match (local, current.as_mut()) {
+                    ("entry", _) => current = Some(Publication::default()),
+                    ("link", entry) => {
+                        let rel = attr(&attributes, "rel").unwrap_or("");
+                        let mime = attr(&attributes, "type").unwrap_or("");
+                        let href = attr(&attributes, "href").map(|h| resolve_url(base, h));
+                        match (entry, href) {
+                            // Prefer the open-access acquisition over buy/borrow variants
+                            (Some(p), Some(href))
+                                if is_epub_acquisition(rel, mime)
+                                    && (p.epub_url.is_none() || rel == OPEN_ACCESS) =>
+                            {
+                                p.epub_url = Some(href);
+                            }
+                            (_, Some(href)) if is_feed_link(rel, mime) => page.feeds.push(href),
+                            _ => {}
+                        }
+                    }
+                    ("category", Some(p)) => {
+                        if let Some(label) = attr(&attributes, "label").or_else(|| attr(&attributes, "term")) {
+                            p.subjects.push(label.to_string());
+                        }
+                    }
+                    _ => {}
+                }
+                path.push(name.local_name);
+            }
+            XmlEvent::Characters(chunk) | XmlEvent::CData(chunk) => text.push_str(&chunk),
+            XmlEvent::EndElement { name } => {
+                path.pop();
+                let value = text.trim().to_string();
+                let parent = path.last().map(String::as_str);
+                if name.local_name == "entry" {
+                    page.publications.extend(current.take());
+                } else if let Some(p) = current.as_mut() {
+                    match (name.local_name.as_str(), parent) {
+                        ("title", Some("entry")) => p.title = value,
+                        ("name", Some("author")) => p.authors.push(value),
+                        ("language", Some("entry")) => p.language = Some(value),
+                        ("publisher", Some("entry")) => p.publisher = Some(value),
+                        ("identifier", Some("entry")) => p.identifier = Some(value),
+                        ("id", Some("entry")) if p.identifier.is_none() => p.identifier = Some(value),
+                        _ => {}
+                    }
+                }
+                text.clear();
+            }
+            _ => {}
+        }
+    }
+
+    Ok(page)
+}
+
+/// Resolve `href` against the URL of the document it appeared in
+fn resolve_url(base: &str, href: &str) -> String {
+    if remote::is_url(href) {
+        return href.to_string();
+    }
+    let scheme_end = base.find("://").map(|i| i + 3).unwrap_or(0);
+    if let Some(rest) = href.strip_prefix("//") {
+        return format!("{}{}", &base[..scheme_end], rest);
+    }
+    let origin_end = base[scheme_end..].find('/').map(|i| i + scheme_end).unwrap_or(base.len());
+    if href.starts_with('/') {
+        return format!("{}{}", &base[..origin_end], href);
+    }
+
+    let base_path = base.split(['?', '#']).next().unwrap_or(base);
+    let dir_end = base_path[origin_end..].rfind('/').map(|i| i + origin_end + 1);
+    match dir_end {
+        Some(end) => format!("{}{}", &base_path[..end], href),
+        None => format!("{}/{}", base_path, href),
+    }
+}
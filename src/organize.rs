@@ -0,0 +1,213 @@
+use crate::normalize::Normalization;
+use crate::sanitize_filename;
+use epub::doc::EpubDoc;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Longest directory name written, in characters
+const MAX_NAME_LEN: usize = 100;
+
+/// A level of the directory layout
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Component {
+    /// The first author's name as printed, "Jane Doe"
+    Author,
+    /// The first author's sort name, "Doe, Jane"
+    AuthorSort,
+    Title,
+    /// Left out for books not in a series
+    Series,
+    Language,
+    Publisher,
+    /// Year of publication
+    Year,
+}
+
+const COMPONENTS: &[(&str, Component)] = &[
+    ("author", Component::Author),
+    ("author-sort", Component::AuthorSort),
+    ("title", Component::Title),
+    ("series", Component::Series),
+    ("language", Component::Language),
+    ("publisher", Component::Publisher),
+    ("year", Component::Year),
+];
+
+/// `--organize`: the metadata fields that name each level of a book's output directory,
+/// such as `author/title`
+#[derive(Debug, Clone)]
+pub struct Layout(Vec<Component>);
+
+impl Layout {
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let components = spec
+            .split('/')
+            .filter(|part| !part.trim().is_empty())
+            .map(|part| {
+                let name = part.trim().to_ascii_lowercase().replace('_', "-");
+                COMPONENTS.iter().find(|(n, _)| *n == name).map(|(_, c)| *c).ok_or_else(|| {
+                    let names: Vec<&str> = COMPONENTS.iter().map(|(n, _)| *n).collect();
+                    format!("unknown field '{}' in layout (use {}, separated by /)", part.trim(), names.join(", "))
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if components.is_empty() {
+            return Err("empty layout (expected fields such as author/title)".to_string());
+        }
+        if !components.contains(&Component::Title) {
+            return Err("the layout must include title, so each book gets its own directory".to_string());
+        }
+        Ok(Self(components))
+    }
+}
+
+/// What a book's directory is named after
+#[derive(Debug, Default)]
+pub struct BookFields {
+    title: Option<String>,
+    author: Option<String>,
+    author_sort: Option<String>,
+    series: Option<String>,
+    language: Option<String>,
+    publisher: Option<String>,
+    year: Option<String>,
+}
+
+impl BookFields {
+    /// Read the fields from an EPUB's package metadata, or None if it can't be opened
+    pub fn read(epub_path: &Path) -> Option<Self> {
+        let doc = EpubDoc::new(epub_path).ok()?;
+        let value = |property: &str| doc.mdata(property).map(|m| m.value.trim().to_string()).filter(|v| !v.is_empty());
+        let creator = doc.metadata.iter().find(|m| m.property == "creator" && !m.value.trim().is_empty());
+        let series = doc
+            .metadata
+            .iter()
+            .find(|m| m.property == "belongs-to-collection" && m.refinement("collection-type").is_none_or(|t| t.value == "series"))
+            .map(|m| m.value.trim().to_string())
+            .or_else(|| value("calibre:series"));
+        Some(Self {
+            title: value("title"),
+            author: creator.map(|c| c.value.trim().to_string()),
+            // EPUB3 refines the creator with file-as; EPUB2 has an opf:file-as attribute
+            author_sort: creator
+                .and_then(|c| c.refinement("file-as"))
+                .map(|r| r.value.trim().to_string())
+                .filter(|v| !v.is_empty()),
+            series,
+            language: value("language"),
+            publisher: value("publisher"),
+            year: value("date").and_then(|d| {
+                let year: String = d.chars().take_while(char::is_ascii_digit).collect();
+                (year.len() == 4).then_some(year)
+            }),
+        })
+    }
+}
+
+/// Hands out organized output directories beneath `base`. Authors are matched by sort
+/// name without regard to case, so "Jane Doe", "jane doe" and "Doe, Jane" share the
+/// directory of whichever spelling came first, including directories already in `base`
+/// from earlier runs. Two books of this run that would share a directory get numbered.
+pub struct Organizer {
+    layout: Layout,
+    base: PathBuf,
+    normalize: Normalization,
+    /// Name chosen for each author key, per parent directory
+    authors: HashMap<(PathBuf, String), String>,
+    /// Parent directories whose existing entries have been read
+    scanned: HashSet<PathBuf>,
+    claimed: HashSet<PathBuf>,
+}
+
+impl Organizer {
+    pub fn new(layout: &Layout, base: &Path, normalize: Normalization) -> Self {
+        Self {
+            layout: layout.clone(),
+            base: base.to_path_buf(),
+            normalize,
+            authors: HashMap::new(),
+            scanned: HashSet::new(),
+            claimed: HashSet::new(),
+        }
+    }
+
+    /// Output directory of a book; `fallback` names it when it has no title
+    pub fn dir_for(&mut self, fields: &BookFields, fallback: &str) -> PathBuf {
+        let mut dir = self.base.clone();
+        for component in self.layout.0.clone() {
+            let name = match component {
+                Component::Author | Component::AuthorSort => {
+                    let display = fields.author.clone().unwrap_or_else(|| "Unknown Author".to_string());
+                    let sort = fields.author_sort.clone().unwrap_or_else(|| sort_name(&display));
+                    let name = if component == Component::Author { display } else { sort.clone() };
+                    self.author_dir(&dir, &sort, &name)
+                }
+                Component::Title => directory_name(fields.title.as_deref().unwrap_or(fallback), self.normalize),
+                Component::Series => match &fields.series {
+                    Some(series) => directory_name(series, self.normalize),
+                    None => continue,
+                },
+                Component::Language => directory_name(fields.language.as_deref().unwrap_or("Unknown Language"), self.normalize),
+                Component::Publisher => directory_name(fields.publisher.as_deref().unwrap_or("Unknown Publisher"), self.normalize),
+                Component::Year => directory_name(fields.year.as_deref().unwrap_or("Unknown Year"), self.normalize),
+            };
+            dir.push(name);
+        }
+
+        let title = dir.file_name().unwrap_or_default().to_string_lossy().into_owned();
+        let mut unique = dir.clone();
+        let mut n = 1;
+        while !self.claimed.insert(unique.clone()) {
+            n += 1;
+            unique = dir.with_file_name(format!("{} ({})", title, n));
+        }
+        unique
+    }
+
+    /// Directory name for an author under `parent`, reusing the name already given to the
+    /// same sort key there
+    fn author_dir(&mut self, parent: &Path, sort: &str, name: &str) -> String {
+        if self.scanned.insert(parent.to_path_buf()) {
+            if let Ok(entries) = fs::read_dir(parent) {
+                for entry in entries.flatten().filter(|e| e.path().is_dir()) {
+                    let existing = entry.file_name().to_string_lossy().into_owned();
+                    self.authors.entry((parent.to_path_buf(), author_key(&sort_name(&existing)))).or_insert(existing);
+                }
+            }
+        }
+        self.authors
+            .entry((parent.to_path_buf(), author_key(sort)))
+            .or_insert_with(|| directory_name(name, self.normalize))
+            .clone()
+    }
+}
+
+/// "Jane Doe" as "Doe, Jane"; names already in sort order, and single names, are kept
+fn sort_name(name: &str) -> String {
+    let name = name.split_whitespace().collect::<Vec<_>>().join(" ");
+    if name.contains(',') {
+        return name;
+    }
+    match name.rsplit_once(' ') {
+        Some((given, family)) => format!("{}, {}", family, given),
+        None => name,
+    }
+}
+
+/// Key an author is matched by: the sort name, lower-cased, with punctuation and
+/// spacing differences ignored
+fn author_key(sort: &str) -> String {
+    let (family, given) = sort.split_once(',').unwrap_or((sort, ""));
+    let words = |s: &str| s.split(|c: char| !c.is_alphanumeric()).filter(|w| !w.is_empty()).map(str::to_lowercase).collect::<Vec<_>>().join(" ");
+    format!("{}|{}", words(family), words(given))
+}
+
+/// A metadata value made safe as a single directory name on any platform
+fn directory_name(value: &str, normalize: Normalization) -> String {
+    let name = sanitize_filename(&normalize.apply(value));
+    let name: String = name.split_whitespace().collect::<Vec<_>>().join(" ").chars().take(MAX_NAME_LEN).collect();
+    // Windows drops trailing dots and spaces, and a leading dot would hide the directory
+    let name = name.trim_matches(|c: char| c == '.' || c.is_whitespace()).to_string();
+    if name.is_empty() { "_".to_string() } else { name }
+}
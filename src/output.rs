@@ -0,0 +1,372 @@
+use crate::error::ConvertError;
+use crate::license::{self, FileSyntax};
+use clap::ValueEnum;
+use std::borrow::Cow;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Component, Path, PathBuf};
+
+/// What each book is written as
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Markdown files, one per chapter or combined with `--single`
+    #[default]
+    Markdown,
+    /// A Joplin export archive: one notebook per book, one note per chapter
+    Jex,
+    /// An SSML document per chapter, for speech synthesis
+    Ssml,
+}
+
+/// Extension given to Markdown files
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Extension {
+    #[default]
+    Md,
+    Markdown,
+    Txt,
+}
+
+impl Extension {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Extension::Md => "md",
+            Extension::Markdown => "markdown",
+            Extension::Txt => "txt",
+        }
+    }
+}
+
+/// Line endings of the Markdown and SSML files written
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Newline {
+    #[default]
+    Lf,
+    Crlf,
+}
+
+impl Newline {
+    /// `text`, which has Unix line endings, with this style's
+    pub fn apply(self, text: &str) -> Cow<'_, str> {
+        match self {
+            Newline::Lf => Cow::Borrowed(text),
+            Newline::Crlf => Cow::Owned(text.replace("\r\n", "\n").replace('\n', "\r\n")),
+        }
+    }
+}
+
+/// Marker written into a book's output directory once every file is in place
+pub const COMPLETE_MARKER: &str = ".complete";
+
+/// Lock file held in a book's output directory while it is being written
+pub const LOCK_FILE: &str = ".lock";
+
+/// Temp file path next to `path`, so the final rename never crosses filesystems
+fn temp_path(path: &Path) -> PathBuf {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!(".{}.tmp-{}", name, std::process::id()))
+}
+
+/// Write `contents` to `path` via a temp file, renaming into place on success
+pub fn write_atomic(path: &Path, contents: impl AsRef<[u8]>) -> io::Result<()> {
+    let temp = temp_path(path);
+    if let Err(e) = fs::write(&temp, contents) {
+        let _ = fs::remove_file(&temp);
+        return Err(e);
+    }
+    fs::rename(&temp, path)
+}
+
+/// How a book's chapter files are finished on their way to disk: each one gets the
+/// license header, placed the way its syntax allows, and the configured line endings
+#[derive(Debug, Clone, Default)]
+pub struct ChapterFiles {
+    pub newline: Newline,
+    pub license: Option<license::Header>,
+}
+
+impl ChapterFiles {
+    /// `text` with the license header added, if there is one
+    pub fn finish<'a>(&self, syntax: FileSyntax, text: &'a str) -> Cow<'a, str> {
+        match &self.license {
+            Some(header) => Cow::Owned(header.apply(syntax, text)),
+            None => Cow::Borrowed(text),
+        }
+    }
+
+    /// Finish `text` and write it to `path` atomically
+    pub fn write(&self, path: &Path, syntax: FileSyntax, text: &str) -> io::Result<()> {
+        write_atomic(path, self.newline.apply(&self.finish(syntax, text)).as_bytes())
+    }
+}
+
+/// Copy `from` to `to` via a temp file, renaming into place on success
+pub fn copy_atomic(from: &Path, to: &Path) -> io::Result<()> {
+    let temp = temp_path(to);
+    if let Err(e) = fs::copy(from, &temp) {
+        let _ = fs::remove_file(&temp);
+        return Err(e);
+    }
+    fs::rename(&temp, to)
+}
+
+/// A buffered file that only appears at its final path once committed.
+/// Dropping it without committing discards the partial data.
+pub struct AtomicFile {
+    temp: PathBuf,
+    target: PathBuf,
+    writer: Option<BufWriter<File>>,
+}
+
+impl AtomicFile {
+    pub fn create(path: &Path) -> io::Result<Self> {
+        let temp = temp_path(path);
+        let file = File::create(&temp)?;
+        Ok(Self {
+            temp,
+            target: path.to_path_buf(),
+            writer: Some(BufWriter::new(file)),
+        })
+    }
+
+    /// Flush buffered data and move the file into place
+    pub fn commit(mut self) -> io::Result<()> {
+        if let Some(mut writer) = self.writer.take() {
+            writer.flush()?;
+        }
+        fs::rename(&self.temp, &self.target)
+    }
+}
+
+impl Write for AtomicFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writer.as_mut().expect("write after commit").write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.as_mut().expect("flush after commit").flush()
+    }
+}
+
+impl Drop for AtomicFile {
+    fn drop(&mut self) {
+        if self.writer.take().is_some() {
+            let _ = fs::remove_file(&self.temp);
+        }
+    }
+}
+
+/// Remove the completion marker before a book's output is rewritten
+pub fn mark_incomplete(output_dir: &Path) -> io::Result<()> {
+    match fs::remove_file(output_dir.join(COMPLETE_MARKER)) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// An advisory lock on a book's output directory, so two runs writing into the same
+/// tree don't interleave their files. The lock file holds the owner's PID; it is removed
+/// when the lock is dropped, and the operating system releases the lock if the process
+/// dies, so a file left behind by a crash doesn't block anyone.
+pub struct OutputLock {
+    path: PathBuf,
+    _file: File,
+}
+
+impl OutputLock {
+    /// Take the lock on `output_dir`, failing at once if another process holds it
+    pub fn acquire(output_dir: &Path) -> Result<Self, ConvertError> {
+        use std::os::unix::fs::MetadataExt;
+        use std::os::unix::io::AsRawFd;
+
+        let path = output_dir.join(LOCK_FILE);
+        loop {
+            let mut file = fs::OpenOptions::new().read(true).write(true).create(true).truncate(false).open(&path)?;
+            if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } != 0 {
+                let error = io::Error::last_os_error();
+                if error.kind() != io::ErrorKind::WouldBlock {
+                    return Err(error.into());
+                }
+                let pid = fs::read_to_string(&path).ok().and_then(|pid| pid.trim().parse::<u32>().ok());
+                return Err(ConvertError::OutputLocked {
+                    output: output_dir.display().to_string(),
+                    owner: pid.map(|pid| format!("PID {}", pid)).unwrap_or_else(|| "another process".to_string()),
+                });
+            }
+            // The previous holder may have removed the file between our open and lock;
+            // then the lock is on an orphaned inode and we must try again
+            let same = fs::metadata(&path).is_ok_and(|m| m.ino() == file.metadata().map(|f| f.ino()).unwrap_or(0));
+            if !same {
+                continue;
+            }
+            file.set_len(0)?;
+            write!(file, "{}", std::process::id())?;
+            return Ok(Self { path, _file: file });
+        }
+    }
+}
+
+impl Drop for OutputLock {
+    fn drop(&mut self) {
+        // Removed while still locked, so nobody can take a lock on the doomed file
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Record that a book's output is complete, listing the files it consists of
+pub fn mark_complete(output_dir: &Path, files: &[PathBuf]) -> io::Result<()> {
+    let mut listing = String::new();
+    for file in files {
+        let relative = file.strip_prefix(output_dir).unwrap_or(file);
+        listing.push_str(&relative.to_string_lossy());
+        listing.push('\n');
+    }
+    write_atomic(&output_dir.join(COMPLETE_MARKER), listing)
+}
+
+/// Resolve an archive-relative href (e.g. a manifest entry) beneath `root`,
+/// rejecting anything that could land outside it: `..` segments that climb past
+/// the root, absolute paths, drive prefixes and NUL bytes. Percent-encoding and
+/// Windows separators are decoded first so `%2e%2e/` and `..\` are caught too.
+pub fn safe_join(root: &Path, href: &str) -> io::Result<PathBuf> {
+    let decoded = percent_encoding::percent_decode_str(href).decode_utf8_lossy();
+    let reject = || io::Error::new(io::ErrorKind::InvalidInput, format!("unsafe resource path: {}", href));
+
+    if decoded.contains('\0') {
+        return Err(reject());
+    }
+
+    let mut parts: Vec<&str> = Vec::new();
+    for segment in decoded.split(['/', '\\']) {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                parts.pop().ok_or_else(reject)?;
+            }
+            // Drive letters and alternate data streams on Windows
+            s if s.contains(':') => return Err(reject()),
+            s => parts.push(s),
+        }
+    }
+    if decoded.starts_with(['/', '\\']) || parts.is_empty() {
+        return Err(reject());
+    }
+
+    let mut path = root.to_path_buf();
+    for part in parts {
+        path.push(part);
+    }
+    // Belt and braces: the result must consist only of normal components below root
+    let relative = path.strip_prefix(root).map_err(|_| reject())?;
+    if relative.components().any(|c| !matches!(c, Component::Normal(_))) {
+        return Err(reject());
+    }
+    Ok(path)
+}
+
+/// Like `safe_join`, but also creates the parent directories and confirms, after
+/// resolving symlinks, that they still live inside `root`. Each directory is checked
+/// before the next is created in it, so a symlink can't have one made outside the root.
+pub fn resource_path(root: &Path, href: &str) -> io::Result<PathBuf> {
+    let path = safe_join(root, href)?;
+    fs::create_dir_all(root)?;
+    let canonical_root = root.canonicalize()?;
+
+    let parent = path.parent().unwrap_or(root);
+    let mut dir = root.to_path_buf();
+    for component in parent.strip_prefix(root).unwrap_or(Path::new("")).components() {
+        dir.push(component);
+        match fs::create_dir(&dir) {
+            Err(e) if e.kind() != io::ErrorKind::AlreadyExists => return Err(e),
+            _ => {}
+        }
+        if !dir.canonicalize()?.starts_with(&canonical_root) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("resource path escapes the output directory via a symlink: {}", href),
+            ));
+        }
+    }
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::check::ScratchDir;
+
+    fn rejected(href: &str) -> bool {
+        safe_join(Path::new("/out/resources"), href).is_err()
+    }
+
+    #[test]
+    fn joins_paths_inside_the_root() {
+        let root = Path::new("/out/resources");
+        assert_eq!(safe_join(root, "OEBPS/images/cover.jpg").unwrap(), root.join("OEBPS/images/cover.jpg"));
+        assert_eq!(safe_join(root, "OEBPS/text/../images/a%20b.png").unwrap(), root.join("OEBPS/images/a b.png"));
+        assert_eq!(safe_join(root, "./OEBPS\\style.css").unwrap(), root.join("OEBPS/style.css"));
+    }
+
+    #[test]
+    fn rejects_parent_directories() {
+        assert!(rejected("../../evil.sh"));
+        assert!(rejected("OEBPS/../../evil.sh"));
+        assert!(rejected("..\\evil.sh"));
+    }
+
+    #[test]
+    fn rejects_percent_encoded_parent_directories() {
+        assert!(rejected("%2e%2e/evil.sh"));
+        assert!(rejected("%2E%2E%2Fevil.sh"));
+        assert!(rejected("OEBPS/%2e%2e%5c%2e%2e%5cevil.sh"));
+    }
+
+    #[test]
+    fn rejects_absolute_paths() {
+        assert!(rejected("/etc/passwd"));
+        assert!(rejected("\\Windows\\evil.dll"));
+        assert!(rejected("%2Fetc%2Fpasswd"));
+    }
+
+    #[test]
+    fn rejects_drive_prefixes() {
+        assert!(rejected("C:foo"));
+        assert!(rejected("C:\\Windows\\evil.dll"));
+        assert!(rejected("OEBPS/font.otf:stream"));
+    }
+
+    #[test]
+    fn rejects_embedded_nul() {
+        assert!(rejected("OEBPS/a\0.png"));
+        assert!(rejected("OEBPS/a%00.png"));
+    }
+
+    #[test]
+    fn rejects_empty_paths() {
+        assert!(rejected(""));
+        assert!(rejected("./"));
+        assert!(rejected("OEBPS/.."));
+    }
+
+    #[test]
+    fn refuses_a_symlinked_parent_directory() {
+        let scratch = ScratchDir::new("output-test").unwrap();
+        let root = scratch.path().join("resources");
+        let outside = scratch.path().join("outside");
+        fs::create_dir_all(&root).unwrap();
+        fs::create_dir_all(&outside).unwrap();
+        std::os::unix::fs::symlink(&outside, root.join("OEBPS")).unwrap();
+
+        assert!(resource_path(&root, "OEBPS/evil.sh").is_err());
+        assert!(resource_path(&root, "OEBPS/nested/evil.sh").is_err());
+        assert!(!outside.join("nested").exists());
+    }
+
+    #[test]
+    fn creates_parent_directories_inside_the_root() {
+        let scratch = ScratchDir::new("output-test").unwrap();
+        let path = resource_path(scratch.path(), "OEBPS/images/cover.jpg").unwrap();
+        assert_eq!(path, scratch.path().join("OEBPS/images/cover.jpg"));
+        assert!(scratch.path().join("OEBPS/images").is_dir());
+    }
+}
@@ -0,0 +1,307 @@
+use crate::archive::Resources;
+use crate::html;
+use crate::output;
+use crate::toc;
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use epub::doc::EpubDoc;
+use markup5ever_rcdom::Handle;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Read, Seek};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use xml::reader::{EventReader, XmlEvent};
+
+/// Directory under the book's output directory that `--extract-audio` writes to
+pub const AUDIO_DIR: &str = "audio";
+
+/// Sidecar written with `--read-along json`
+pub const OVERLAYS_FILE: &str = "overlays.json";
+
+/// Element put in front of each timed paragraph before rendering, written out as an HTML comment
+pub const MARKER_TAG: &str = "read-along";
+
+/// Media type of EPUB3 media overlay documents
+const SMIL_TYPE: &str = "application/smil+xml";
+
+/// Elements a run of timed fragments is grouped into
+const BLOCK_TAGS: &[&str] = &["p", "h1", "h2", "h3", "h4", "h5", "h6", "li", "blockquote", "dt", "dd", "td", "th", "figcaption", "pre"];
+
+/// Blocks whose marker goes inside them, as their first child, since a sibling there would
+/// break the list or table around them
+const INNER_MARKER_TAGS: &[&str] = &["li", "dt", "dd", "td", "th"];
+
+/// Where the read-along timing of each paragraph is written
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ReadAlong {
+    /// An HTML comment before each paragraph in the chapter files
+    Comments,
+    /// overlays.json in the book's output directory
+    Json,
+}
+
+/// One timed text fragment of a media overlay (a SMIL `<par>`)
+#[derive(Debug, Clone)]
+struct Clip {
+    /// Id of the element the fragment points at
+    fragment: String,
+    /// The audio, as referenced from the output
+    audio: String,
+    begin: f64,
+    end: Option<f64>,
+}
+
+/// The media overlays of a book: for each spine document, its timed fragments in order
+#[derive(Debug, Default)]
+pub struct Overlays {
+    documents: HashMap<String, Vec<Clip>>,
+}
+
+impl Overlays {
+    /// Read every SMIL document in the manifest. Audio is referenced by its path inside
+    /// the EPUB, or under `audio/` once `extracted`.
+    pub fn load<R: Read + Seek>(doc: &EpubDoc<R>, resources: &mut Resources, extracted: bool) -> Result<Self> {
+        let smil: Vec<PathBuf> = doc.resources.values().filter(|r| r.mime == SMIL_TYPE).map(|r| r.path.clone()).collect();
+        let root_base = doc.root_base.clone();
+        let mut overlays = Overlays::default();
+        for path in smil {
+            let Some(text) = resources.read_string(&path.to_string_lossy())? else { continue };
+            let base = path.parent().unwrap_or(Path::new(""));
+            for par in parse_smil(&text) {
+                let (Some(text_src), Some(audio_src)) = (&par.text, &par.audio) else { continue };
+                let (document, fragment) = text_src.split_once('#').unwrap_or((text_src, ""));
+                if fragment.is_empty() {
+                    continue;
+                }
+                let audio = toc::target_path(&base.join(audio_src));
+                let audio = if extracted { audio_reference(&audio, &root_base) } else { audio };
+                overlays.documents.entry(toc::target_path(&base.join(document))).or_default().push(Clip {
+                    fragment: fragment.to_string(),
+                    audio,
+                    begin: par.begin,
+                    end: par.end,
+                });
+            }
+        }
+        Ok(overlays)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.documents.is_empty()
+    }
+
+    /// Group a spine document's timed fragments into the paragraphs holding them. With
+    /// `mark`, also returns the document with a `<read-along>` marker before each of them.
+    pub fn paragraphs(&self, source: &str, content: &str, mark: bool) -> (Option<String>, Vec<Paragraph>) {
+        let Some(clips) = self.documents.get(source) else { return (None, Vec::new()) };
+        let by_id: HashMap<&str, &Clip> = clips.iter().map(|c| (c.fragment.as_str(), c)).collect();
+
+        let dom = html::parse(content);
+        let mut blocks: Vec<(Handle, Paragraph)> = Vec::new();
+        html::walk(&dom.document, &mut |node| {
+            let Some(clip) = html::attr(node, "id").and_then(|id| by_id.get(id.as_str()).copied()) else { return };
+            let block = enclosing_block(node).unwrap_or_else(|| node.clone());
+            match blocks.last_mut() {
+                Some((last, paragraph)) if Rc::ptr_eq(last, &block) => {
+                    paragraph.fragments += 1;
+                    if clip.audio == paragraph.audio {
+                        paragraph.end = clip.end.or(paragraph.end);
+                    }
+                }
+                _ => {
+                    let paragraph = Paragraph {
+                        id: html::attr(&block, "id").unwrap_or_else(|| clip.fragment.clone()),
+                        audio: clip.audio.clone(),
+                        begin: clip.begin,
+                        end: clip.end,
+                        fragments: 1,
+                    };
+                    blocks.push((block, paragraph));
+                }
+            }
+        });
+
+        let marked = mark.then(|| {
+            for (block, paragraph) in &blocks {
+                insert_marker(block, paragraph);
+            }
+            html::serialize(&dom)
+        });
+        (marked, blocks.into_iter().map(|(_, paragraph)| paragraph).collect())
+    }
+}
+
+/// The read-along timing of one paragraph
+#[derive(Debug, Clone, Serialize)]
+pub struct Paragraph {
+    /// Id of the paragraph's element, or of its first timed fragment when it has none
+    pub id: String,
+    /// Audio file the paragraph is read in
+    pub audio: String,
+    /// Where the reading starts in the audio, in seconds
+    pub begin: f64,
+    /// Where it ends, in seconds, when the overlay says
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end: Option<f64>,
+    /// Timed fragments (usually sentences) in the paragraph
+    pub fragments: usize,
+}
+
+impl Paragraph {
+    /// Text of the HTML comment written before the paragraph
+    pub fn comment(&self) -> String {
+        match self.end {
+            Some(end) => format!("read-along: {} {}-{}", self.audio, clock(self.begin), clock(end)),
+            None => format!("read-along: {} {}", self.audio, clock(self.begin)),
+        }
+    }
+}
+
+/// The timed paragraphs of one converted chapter, as listed in overlays.json
+#[derive(Debug, Serialize)]
+pub struct ChapterTiming {
+    pub chapter: usize,
+    pub file: String,
+    /// Path of the spine document inside the EPUB
+    pub source: String,
+    pub paragraphs: Vec<Paragraph>,
+}
+
+fn enclosing_block(node: &Handle) -> Option<Handle> {
+    let mut current = Some(node.clone());
+    while let Some(n) = current {
+        if html::tag_name(&n).is_some_and(|t| BLOCK_TAGS.contains(&t.as_str())) {
+            return Some(n);
+        }
+        current = html::parent(&n);
+    }
+    None
+}
+
+fn insert_marker(block: &Handle, paragraph: &Paragraph) {
+    let comment = paragraph.comment();
+    let inner = html::tag_name(block).is_some_and(|t| INNER_MARKER_TAGS.contains(&t.as_str()));
+    let marker = html::element(MARKER_TAG, &[("data-comment", &comment), ("data-inline", if inner { "true" } else { "false" })]);
+    if inner {
+        marker.parent.set(Some(Rc::downgrade(block)));
+        block.children.borrow_mut().insert(0, marker);
+        return;
+    }
+    let Some(parent) = html::parent(block) else { return };
+    let mut children = parent.children.borrow_mut();
+    let Some(position) = children.iter().position(|c| Rc::ptr_eq(c, block)) else { return };
+    marker.parent.set(Some(Rc::downgrade(&parent)));
+    children.insert(position, marker);
+}
+
+/// A SMIL `<par>`: a text fragment and the clip of audio reading it
+#[derive(Default)]
+struct Par {
+    text: Option<String>,
+    audio: Option<String>,
+    begin: f64,
+    end: Option<f64>,
+}
+
+/// The `<par>` elements of a SMIL document that have both text and audio
+fn parse_smil(text: &str) -> Vec<Par> {
+    let mut pars = Vec::new();
+    let mut current: Option<Par> = None;
+    for event in EventReader::new(text.as_bytes()).into_iter().flatten() {
+        match event {
+            XmlEvent::StartElement { name, attributes, .. } => {
+                let attr = |key: &str| attributes.iter().find(|a| a.name.local_name == key).map(|a| a.value.trim().to_string());
+                match (name.local_name.as_str(), current.as_mut()) {
+                    ("par", _) => current = Some(Par::default()),
+                    ("text", Some(par)) => par.text = attr("src"),
+                    ("audio", Some(par)) => {
+                        par.audio = attr("src");
+                        par.begin = attr("clipBegin").and_then(|v| parse_clock(&v)).unwrap_or(0.0);
+                        par.end = attr("clipEnd").and_then(|v| parse_clock(&v));
+                    }
+                    _ => {}
+                }
+            }
+            XmlEvent::EndElement { name } if name.local_name == "par" => {
+                pars.extend(current.take().filter(|par| par.text.is_some() && par.audio.is_some()));
+            }
+            _ => {}
+        }
+    }
+    pars
+}
+
+/// A SMIL clock value in seconds: `h:mm:ss.fff`, `mm:ss.fff`, or a count with an
+/// optional `h`, `min`, `s` or `ms` unit
+fn parse_clock(value: &str) -> Option<f64> {
+    let value = value.trim();
+    if value.contains(':') {
+        return value.split(':').try_fold(0.0, |total, part| Some(total * 60.0 + part.trim().parse::<f64>().ok()?));
+    }
+    let (number, scale) = [("ms", 0.001), ("min", 60.0), ("h", 3600.0), ("s", 1.0)]
+        .iter()
+        .find_map(|(unit, scale)| value.strip_suffix(unit).map(|n| (n, *scale)))
+        .unwrap_or((value, 1.0));
+    number.trim().parse::<f64>().ok().map(|n| n * scale)
+}
+
+/// Seconds as `h:mm:ss.fff`
+fn clock(seconds: f64) -> String {
+    let millis = (seconds * 1000.0).round() as u64;
+    format!("{}:{:02}:{:02}.{:03}", millis / 3_600_000, millis / 60_000 % 60, millis / 1000 % 60, millis % 1000)
+}
+
+/// Where an audio file from the EPUB is written under `audio/`, kept relative to the
+/// package document like `--extract-styles` does
+fn audio_reference(path: &str, root_base: &Path) -> String {
+    let relative = Path::new(path).strip_prefix(root_base).unwrap_or(Path::new(path));
+    format!("{}/{}", AUDIO_DIR, relative.to_string_lossy().replace('\\', "/"))
+}
+
+/// An audio file copied out of the EPUB, as recorded in metadata.json
+#[derive(Debug, Serialize)]
+pub struct AudioEntry {
+    /// Manifest item id
+    pub id: String,
+    /// Path of the file inside the EPUB
+    pub source: String,
+    /// Media type declared in the manifest
+    pub media_type: String,
+    /// Where it was written, relative to the output directory
+    pub file: String,
+    pub bytes: usize,
+}
+
+/// Copy the manifest's audio into `audio/`. Returns the entries, sorted by source path,
+/// and the files written.
+pub fn extract_audio<R: Read + Seek>(doc: &EpubDoc<R>, resources: &mut Resources, output_dir: &Path) -> Result<(Vec<AudioEntry>, Vec<PathBuf>)> {
+    let mut items: Vec<(String, PathBuf, String)> = doc
+        .resources
+        .iter()
+        .filter(|(_, r)| r.mime.starts_with("audio/"))
+        .map(|(id, r)| (id.clone(), r.path.clone(), r.mime.clone()))
+        .collect();
+    items.sort_by(|a, b| a.1.cmp(&b.1));
+
+    let root = output_dir.join(AUDIO_DIR);
+    if !items.is_empty() {
+        fs::create_dir_all(&root).context("Failed to create audio directory")?;
+    }
+    let root_base = doc.root_base.clone();
+    let mut entries = Vec::with_capacity(items.len());
+    let mut files = Vec::with_capacity(items.len());
+    for (id, path, media_type) in items {
+        let Some(content) = resources.read(&path.to_string_lossy())? else {
+            continue;
+        };
+        let file = audio_reference(&path.to_string_lossy(), &root_base);
+        // Manifest paths come from the EPUB, so treat them as untrusted
+        let target = output::resource_path(&root, &file[AUDIO_DIR.len() + 1..]).context("Refusing to extract audio")?;
+        output::write_atomic(&target, &content).with_context(|| format!("Failed to write {}", target.display()))?;
+        entries.push(AudioEntry { id, source: path.to_string_lossy().into_owned(), media_type, file, bytes: content.len() });
+        files.push(target);
+    }
+    Ok((entries, files))
+}
@@ -0,0 +1,79 @@
+/// A metadata field `--set` can correct
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    Title,
+    Creator,
+    Contributor,
+    Subject,
+    Language,
+    Description,
+    Publisher,
+    Date,
+    Identifier,
+    Rights,
+    Source,
+    Series,
+    SeriesIndex,
+}
+
+/// Fields `--set` may name, with the aliases accepted for each
+const FIELDS: &[(Field, &[&str])] = &[
+    (Field::Title, &["title"]),
+    (Field::Creator, &["creator", "author", "authors"]),
+    (Field::Contributor, &["contributor"]),
+    (Field::Subject, &["subject", "subjects", "tag"]),
+    (Field::Language, &["language", "lang"]),
+    (Field::Description, &["description"]),
+    (Field::Publisher, &["publisher"]),
+    (Field::Date, &["date"]),
+    (Field::Identifier, &["identifier", "id", "isbn"]),
+    (Field::Rights, &["rights"]),
+    (Field::Source, &["source"]),
+    (Field::Series, &["series"]),
+    (Field::SeriesIndex, &["series-index", "series_index"]),
+];
+
+impl Field {
+    /// Whether the field holds a list of values rather than one
+    pub fn is_list(self) -> bool {
+        matches!(self, Field::Creator | Field::Contributor | Field::Subject)
+    }
+}
+
+/// A `--set field=value` correction to a book's metadata. For the list fields (creator,
+/// contributor, subject), `=` replaces the book's values, and several `--set` of the same
+/// field give the list; `field+=value` adds to what the book has instead. An empty value
+/// clears the field.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetadataOverride {
+    pub field: Field,
+    pub value: String,
+    /// `field+=value`: keep the book's values and add this one
+    pub append: bool,
+}
+
+impl MetadataOverride {
+    /// Parse `field=value` or `field+=value`
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let (field, value) = spec
+            .split_once('=')
+            .ok_or_else(|| format!("invalid override '{}' (expected field=value or field+=value)", spec))?;
+        let (field, append) = match field.strip_suffix('+') {
+            Some(field) => (field, true),
+            None => (field, false),
+        };
+        let name = field.trim().to_ascii_lowercase();
+        let field = FIELDS.iter().find(|(_, aliases)| aliases.contains(&name.as_str())).map(|(field, _)| *field).ok_or_else(|| {
+            let names: Vec<&str> = FIELDS.iter().map(|(_, aliases)| aliases[0]).collect();
+            format!("unknown metadata field '{}' (use {})", name, names.join(", "))
+        })?;
+        if append && !field.is_list() {
+            return Err(format!("'{}' holds a single value; use {}=VALUE to replace it", name, name));
+        }
+        let value = value.trim().to_string();
+        if field == Field::SeriesIndex && !value.is_empty() && value.parse::<f64>().is_err() {
+            return Err(format!("invalid series index '{}' (expected a number such as 2 or 1.5)", value));
+        }
+        Ok(Self { field, value, append })
+    }
+}
@@ -0,0 +1,77 @@
+use crate::toc;
+use clap::ValueEnum;
+use epub::doc::NavPoint;
+use std::collections::HashMap;
+
+/// How chapter files are arranged in a book's output directory
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum DirectoryLayout {
+    /// Every chapter file directly in the output directory
+    #[default]
+    Flat,
+    /// A subdirectory per part (each top-level TOC entry with entries of its own), holding
+    /// that part's chapters
+    Parts,
+}
+
+/// The part directory of each spine document that falls inside a part
+#[derive(Debug, Default)]
+pub struct PartDirectories {
+    by_source: HashMap<String, String>,
+    /// Directory names, in reading order
+    pub names: Vec<String>,
+}
+
+impl PartDirectories {
+    /// Work out the parts of a book from its table of contents. A part runs from the
+    /// first spine document any of its entries points at up to the next top-level entry,
+    /// so front and back matter listed at the top level stays outside every part.
+    pub fn detect(toc: &[NavPoint], spine: &[String]) -> Self {
+        let mut positions: HashMap<&str, usize> = HashMap::new();
+        for (i, source) in spine.iter().enumerate() {
+            positions.entry(source.as_str()).or_insert(i);
+        }
+
+        // Where each top-level entry starts in the spine, and whether it is a part
+        let mut starts: Vec<(usize, Option<&str>)> = toc
+            .iter()
+            .filter_map(|point| {
+                let start = first_position(point, &positions)?;
+                Some((start, (!point.children.is_empty()).then_some(point.label.trim())))
+            })
+            .collect();
+        starts.sort_by_key(|(start, _)| *start);
+
+        let parts = starts.iter().filter(|(_, part)| part.is_some()).count();
+        let width = parts.to_string().len().max(2);
+        let mut directories = PartDirectories::default();
+        let mut current: Option<String> = None;
+        let mut next = starts.iter().peekable();
+        for (i, source) in spine.iter().enumerate() {
+            while let Some((_, part)) = next.next_if(|(start, _)| *start <= i) {
+                current = part.map(|label| {
+                    let slug = toc::github_slug(label);
+                    let n = directories.names.len() + 1;
+                    let name = if slug.is_empty() { format!("{:0width$}-part", n, width = width) } else { format!("{:0width$}-{}", n, slug, width = width) };
+                    directories.names.push(name.clone());
+                    name
+                });
+            }
+            if let Some(directory) = &current {
+                directories.by_source.entry(source.clone()).or_insert_with(|| directory.clone());
+            }
+        }
+        directories
+    }
+
+    /// Directory a spine document's chapter is written in, if it is inside a part
+    pub fn get(&self, source: &str) -> Option<&str> {
+        self.by_source.get(source).map(String::as_str)
+    }
+}
+
+/// Earliest spine position any entry in a TOC subtree points at
+fn first_position(point: &NavPoint, positions: &HashMap<&str, usize>) -> Option<usize> {
+    let own = positions.get(toc::target_path(&point.content).as_str()).copied();
+    point.children.iter().filter_map(|child| first_position(child, positions)).chain(own).min()
+}
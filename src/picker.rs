@@ -0,0 +1,451 @@
+use crate::render::{self, RenderOptions};
+use crate::toc;
+use anyhow::{Context, Result};
+use epub::doc::EpubDoc;
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+/// What the user picked before converting
+pub struct Selection {
+    /// Spine indices to convert, in reading order
+    pub chapters: Vec<usize>,
+    pub single_file: bool,
+}
+
+/// One spine item in the list
+struct Item {
+    label: String,
+    source: String,
+    selected: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Key {
+    Up,
+    Down,
+    PageUp,
+    PageDown,
+    Home,
+    End,
+    Left,
+    Right,
+    Enter,
+    Escape,
+    Char(char),
+}
+
+impl Key {
+    /// The key one read from the terminal stands for, if any
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        let key = match bytes {
+            [] => return None,
+            b"\x1b[A" | b"\x1bOA" => Key::Up,
+            b"\x1b[B" | b"\x1bOB" => Key::Down,
+            b"\x1b[C" | b"\x1bOC" => Key::Right,
+            b"\x1b[D" | b"\x1bOD" => Key::Left,
+            b"\x1b[5~" => Key::PageUp,
+            b"\x1b[6~" => Key::PageDown,
+            b"\x1b[H" | b"\x1b[1~" | b"\x1bOH" => Key::Home,
+            b"\x1b[F" | b"\x1b[4~" | b"\x1bOF" => Key::End,
+            b"\x1b" | [3] => Key::Escape,
+            [b'\r'] | [b'\n'] => Key::Enter,
+            bytes => Key::Char(std::str::from_utf8(bytes).ok()?.chars().next()?),
+        };
+        Some(key)
+    }
+}
+
+/// What a key press asks of the list
+#[derive(Debug, PartialEq, Eq)]
+enum Action {
+    /// Redraw and wait for the next key
+    Stay,
+    /// Show the chapter at this position
+    Preview(usize),
+    Convert(Vec<usize>),
+    Quit,
+}
+
+/// The chapter list, apart from the terminal it is drawn on
+struct Picker {
+    items: Vec<Item>,
+    single_file: bool,
+    cursor: usize,
+    /// First item on screen
+    top: usize,
+}
+
+impl Picker {
+    /// Act on a key, with `rows` items fitting on screen
+    fn handle(&mut self, key: Key, rows: usize) -> Action {
+        let last = self.items.len() - 1;
+        match key {
+            Key::Up | Key::Char('k') => self.cursor = self.cursor.saturating_sub(1),
+            Key::Down | Key::Char('j') => self.cursor = (self.cursor + 1).min(last),
+            Key::PageUp => self.cursor = self.cursor.saturating_sub(rows),
+            Key::PageDown => self.cursor = (self.cursor + rows).min(last),
+            Key::Home | Key::Char('g') => self.cursor = 0,
+            Key::End | Key::Char('G') => self.cursor = last,
+            Key::Char(' ') => self.items[self.cursor].selected = !self.items[self.cursor].selected,
+            Key::Char('a') => {
+                let all = self.items.iter().all(|i| i.selected);
+                self.items.iter_mut().for_each(|i| i.selected = !all);
+            }
+            Key::Char('s') => self.single_file = !self.single_file,
+            Key::Char('p') | Key::Right => return Action::Preview(self.cursor),
+            Key::Enter => {
+                let chapters: Vec<usize> = (0..self.items.len()).filter(|&n| self.items[n].selected).collect();
+                // Converting nothing isn't a choice; quit for that
+                if !chapters.is_empty() {
+                    return Action::Convert(chapters);
+                }
+            }
+            Key::Char('q') | Key::Escape => return Action::Quit,
+            _ => {}
+        }
+        Action::Stay
+    }
+
+    /// Scroll so the cursor is among the `rows` items on screen
+    fn scroll(&mut self, rows: usize) {
+        if self.cursor < self.top {
+            self.top = self.cursor;
+        } else if self.cursor >= self.top + rows {
+            self.top = self.cursor + 1 - rows;
+        }
+    }
+
+    fn screen(&self, title: &str, width: usize, rows: usize) -> String {
+        let selected = self.items.iter().filter(|i| i.selected).count();
+        let mut screen = String::new();
+        screen.push_str(&line(
+            &format!(
+                "{} — {} of {} selected — single file: {}",
+                title,
+                selected,
+                self.items.len(),
+                if self.single_file { "on" } else { "off" }
+            ),
+            width,
+            true,
+        ));
+        for (n, item) in self.items.iter().enumerate().skip(self.top).take(rows) {
+            let name = if item.label.is_empty() { &item.source } else { &item.label };
+            let text = format!("{} [{}] {:>3}  {}", if n == self.cursor { '>' } else { ' ' }, if item.selected { 'x' } else { ' ' }, n, name);
+            screen.push_str(&line(&text, width, n == self.cursor));
+        }
+        for _ in self.items.len().saturating_sub(self.top).min(rows)..rows {
+            screen.push_str("\r\n");
+        }
+        screen.push_str(&line(
+            "↑↓ move  space select  a all/none  s single file  p preview  enter convert  q quit",
+            width,
+            false,
+        ));
+        screen
+    }
+}
+
+/// Show the book's spine in a terminal UI and let the user choose which chapters to
+/// convert, toggle single-file output and preview chapters. Returns None if the user
+/// backs out.
+pub fn pick(epub_path: &Path, render: &RenderOptions, single_file: bool) -> Result<Option<Selection>> {
+    // SAFETY: isatty only inspects the descriptors
+    if unsafe { libc::isatty(libc::STDIN_FILENO) == 0 || libc::isatty(libc::STDOUT_FILENO) == 0 } {
+        anyhow::bail!("--interactive needs a terminal");
+    }
+    let mut doc = EpubDoc::new(epub_path).context("Failed to open EPUB file")?;
+    toc::fill_from_nav(&mut doc);
+    let title = doc.mdata("title").map(|m| m.value.clone()).unwrap_or_else(|| "Unknown Title".to_string());
+    let labels = toc::labels_by_path(&doc.toc);
+    let items: Vec<Item> = doc
+        .spine
+        .iter()
+        .map(|spine| {
+            let source = doc
+                .resources
+                .get(&spine.idref)
+                .map(|r| r.path.to_string_lossy().replace('\\', "/"))
+                .unwrap_or_else(|| spine.idref.clone());
+            let label = labels.get(&toc::target_path(Path::new(&source))).cloned().unwrap_or_default();
+            Item { label, source, selected: true }
+        })
+        .collect();
+    if items.is_empty() {
+        anyhow::bail!("The EPUB has no chapters to pick from");
+    }
+
+    let terminal = Terminal::enter().context("Failed to set up the terminal")?;
+    let mut picker = Picker { items, single_file, cursor: 0, top: 0 };
+    loop {
+        let (width, height) = terminal.size();
+        let rows = height.saturating_sub(3).max(1);
+        picker.scroll(rows);
+        terminal.draw(&picker.screen(&title, width, rows))?;
+
+        match picker.handle(terminal.key()?, rows) {
+            Action::Stay => {}
+            Action::Preview(n) => {
+                let source = &picker.items[n].source;
+                let content = doc.get_resource_str_by_path(source).unwrap_or_default();
+                let markdown = render::to_markdown(&content, render).markdown;
+                preview(&terminal, source, &markdown)?;
+            }
+            Action::Convert(chapters) => return Ok(Some(Selection { chapters, single_file: picker.single_file })),
+            Action::Quit => return Ok(None),
+        }
+    }
+}
+
+/// Scroll position in a chapter preview
+struct Pager {
+    top: usize,
+    /// Lowest `top` that still fills the screen
+    last: usize,
+}
+
+impl Pager {
+    /// Act on a key, with `rows` lines on screen; false means back to the list
+    fn handle(&mut self, key: Key, rows: usize) -> bool {
+        match key {
+            Key::Up | Key::Char('k') => self.top = self.top.saturating_sub(1),
+            Key::Down | Key::Char('j') => self.top = (self.top + 1).min(self.last),
+            Key::PageUp | Key::Char('b') => self.top = self.top.saturating_sub(rows),
+            Key::PageDown | Key::Char(' ') => self.top = (self.top + rows).min(self.last),
+            Key::Home | Key::Char('g') => self.top = 0,
+            Key::End | Key::Char('G') => self.top = self.last,
+            Key::Char('q') | Key::Char('p') | Key::Escape | Key::Left => return false,
+            _ => {}
+        }
+        true
+    }
+}
+
+/// Page through one chapter's Markdown until the user goes back to the list
+fn preview(terminal: &Terminal, source: &str, markdown: &str) -> Result<()> {
+    let (width, height) = terminal.size();
+    let wrapped = wrap(markdown, width.max(1));
+    let rows = height.saturating_sub(2).max(1);
+    let mut pager = Pager { top: 0, last: wrapped.len().saturating_sub(rows) };
+    loop {
+        let top = pager.top;
+        let mut screen = line(&format!("{} — lines {}-{} of {}", source, top + 1, (top + rows).min(wrapped.len()), wrapped.len()), width, true);
+        for row in wrapped.iter().skip(top).take(rows) {
+            screen.push_str(&line(row, width, false));
+        }
+        for _ in wrapped.len().saturating_sub(top).min(rows)..rows {
+            screen.push_str("\r\n");
+        }
+        screen.push_str(&line("↑↓ scroll  space/b page  q back", width, false));
+        terminal.draw(&screen)?;
+
+        if !pager.handle(terminal.key()?, rows) {
+            return Ok(());
+        }
+    }
+}
+
+/// One screen row, cut to the terminal width, optionally in reverse video
+fn line(text: &str, width: usize, highlight: bool) -> String {
+    let cut: String = text.chars().filter(|c| !c.is_control()).take(width).collect();
+    if highlight {
+        format!("\x1b[7m{}\x1b[0m\x1b[K\r\n", cut)
+    } else {
+        format!("{}\x1b[K\r\n", cut)
+    }
+}
+
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let mut rows = Vec::new();
+    for line in text.lines() {
+        let chars: Vec<char> = line.chars().collect();
+        if chars.is_empty() {
+            rows.push(String::new());
+        }
+        for chunk in chars.chunks(width) {
+            rows.push(chunk.iter().collect());
+        }
+    }
+    rows
+}
+
+/// The terminal in raw mode on the alternate screen, restored when dropped
+struct Terminal {
+    original: libc::termios,
+}
+
+impl Terminal {
+    fn enter() -> io::Result<Self> {
+        // SAFETY: termios is plain data, filled in by tcgetattr before use
+        let mut original: libc::termios = unsafe { std::mem::zeroed() };
+        if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut original) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut raw = original;
+        // No echo, line buffering or signal keys: Ctrl-C arrives as a byte and quits cleanly
+        raw.c_lflag &= !(libc::ECHO | libc::ICANON | libc::ISIG | libc::IEXTEN);
+        raw.c_iflag &= !(libc::IXON | libc::ICRNL);
+        raw.c_cc[libc::VMIN] = 1;
+        raw.c_cc[libc::VTIME] = 0;
+        if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSAFLUSH, &raw) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let terminal = Self { original };
+        // Alternate screen, hidden cursor
+        terminal.draw("\x1b[?1049h\x1b[?25l")?;
+        Ok(terminal)
+    }
+
+    /// Columns and rows, falling back to 80x24 if the size can't be read
+    fn size(&self) -> (usize, usize) {
+        // SAFETY: TIOCGWINSZ fills in the winsize struct
+        let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+        let ok = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) } == 0;
+        if ok && size.ws_col > 0 && size.ws_row > 0 {
+            (size.ws_col as usize, size.ws_row as usize)
+        } else {
+            (80, 24)
+        }
+    }
+
+    /// Redraw the whole screen
+    fn draw(&self, screen: &str) -> io::Result<()> {
+        let mut out = io::stdout().lock();
+        write!(out, "\x1b[H{}\x1b[J", screen.trim_end_matches("\r\n"))?;
+        out.flush()
+    }
+
+    fn key(&self) -> io::Result<Key> {
+        let mut buf = [0u8; 8];
+        loop {
+            let n = io::stdin().lock().read(&mut buf)?;
+            if let Some(key) = Key::decode(&buf[..n]) {
+                return Ok(key);
+            }
+        }
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        let _ = self.draw("\x1b[?25h\x1b[?1049l");
+        // SAFETY: restores the settings read in `enter`
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSAFLUSH, &self.original);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn picker(chapters: usize) -> Picker {
+        let items = (0..chapters).map(|n| Item { label: String::new(), source: format!("ch{}.xhtml", n), selected: true }).collect();
+        Picker { items, single_file: false, cursor: 0, top: 0 }
+    }
+
+    fn selected(picker: &Picker) -> Vec<bool> {
+        picker.items.iter().map(|i| i.selected).collect()
+    }
+
+    #[test]
+    fn decodes_terminal_input() {
+        assert_eq!(Key::decode(b"\x1b[A"), Some(Key::Up));
+        assert_eq!(Key::decode(b"\x1bOB"), Some(Key::Down));
+        assert_eq!(Key::decode(b"\x1b[6~"), Some(Key::PageDown));
+        assert_eq!(Key::decode(b"\x1b[1~"), Some(Key::Home));
+        assert_eq!(Key::decode(b"\x1b"), Some(Key::Escape));
+        // Ctrl-C, which raw mode delivers as a byte
+        assert_eq!(Key::decode(&[3]), Some(Key::Escape));
+        assert_eq!(Key::decode(b"\r"), Some(Key::Enter));
+        assert_eq!(Key::decode("é".as_bytes()), Some(Key::Char('é')));
+        assert_eq!(Key::decode(b""), None);
+        assert_eq!(Key::decode(&[0xff]), None);
+    }
+
+    #[test]
+    fn cursor_stays_on_the_list() {
+        let mut picker = picker(5);
+        assert_eq!(picker.handle(Key::Up, 2), Action::Stay);
+        assert_eq!(picker.cursor, 0);
+        picker.handle(Key::PageDown, 2);
+        assert_eq!(picker.cursor, 2);
+        picker.handle(Key::PageDown, 10);
+        assert_eq!(picker.cursor, 4);
+        picker.handle(Key::Char('j'), 2);
+        assert_eq!(picker.cursor, 4);
+        picker.handle(Key::Home, 2);
+        assert_eq!(picker.cursor, 0);
+        picker.handle(Key::Char('G'), 2);
+        assert_eq!(picker.cursor, 4);
+        picker.handle(Key::Char('k'), 2);
+        assert_eq!(picker.cursor, 3);
+    }
+
+    #[test]
+    fn scrolling_keeps_the_cursor_on_screen() {
+        let mut picker = picker(10);
+        picker.handle(Key::End, 3);
+        picker.scroll(3);
+        assert_eq!(picker.top, 7);
+        picker.handle(Key::PageUp, 3);
+        picker.handle(Key::Up, 3);
+        picker.scroll(3);
+        assert_eq!((picker.cursor, picker.top), (5, 5));
+    }
+
+    #[test]
+    fn toggles_selection_and_single_file() {
+        let mut picker = picker(3);
+        picker.handle(Key::Down, 3);
+        picker.handle(Key::Char(' '), 3);
+        assert_eq!(selected(&picker), [true, false, true]);
+        // Not all selected, so `a` selects all; then it clears them
+        picker.handle(Key::Char('a'), 3);
+        assert_eq!(selected(&picker), [true, true, true]);
+        picker.handle(Key::Char('a'), 3);
+        assert_eq!(selected(&picker), [false, false, false]);
+        picker.handle(Key::Char('s'), 3);
+        assert!(picker.single_file);
+    }
+
+    #[test]
+    fn enter_converts_the_selection_in_order() {
+        let mut picker = picker(4);
+        picker.handle(Key::Down, 4);
+        picker.handle(Key::Char(' '), 4);
+        assert_eq!(picker.handle(Key::Enter, 4), Action::Convert(vec![0, 2, 3]));
+
+        picker.handle(Key::Char('a'), 4);
+        picker.handle(Key::Char('a'), 4);
+        assert_eq!(picker.handle(Key::Enter, 4), Action::Stay, "nothing selected");
+    }
+
+    #[test]
+    fn preview_and_quit() {
+        let mut picker = picker(3);
+        picker.handle(Key::End, 3);
+        assert_eq!(picker.handle(Key::Char('p'), 3), Action::Preview(2));
+        assert_eq!(picker.handle(Key::Right, 3), Action::Preview(2));
+        assert_eq!(picker.handle(Key::Char('q'), 3), Action::Quit);
+        assert_eq!(picker.handle(Key::Escape, 3), Action::Quit);
+        assert_eq!(picker.handle(Key::Char('x'), 3), Action::Stay);
+    }
+
+    #[test]
+    fn pager_scrolls_within_the_chapter() {
+        let mut pager = Pager { top: 0, last: 5 };
+        assert!(pager.handle(Key::Char(' '), 4));
+        assert_eq!(pager.top, 4);
+        pager.handle(Key::PageDown, 4);
+        assert_eq!(pager.top, 5);
+        pager.handle(Key::Char('b'), 4);
+        assert_eq!(pager.top, 1);
+        pager.handle(Key::Up, 4);
+        pager.handle(Key::Up, 4);
+        assert_eq!(pager.top, 0);
+        assert!(!pager.handle(Key::Left, 4));
+        assert!(!pager.handle(Key::Char('p'), 4));
+    }
+}
@@ -0,0 +1,221 @@
+use crate::error::{ErrorKind, ErrorReport};
+use crate::warnings::WarningKind;
+use clap::ValueEnum;
+use serde::Serialize;
+use std::fmt::{self, Display};
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Set once stdout's reader has gone away, as when the output is piped into `head`;
+/// nothing more is printed after that
+static STDOUT_CLOSED: AtomicBool = AtomicBool::new(false);
+
+/// How progress is reported
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum ProgressFormat {
+    /// Human-readable lines on stdout
+    #[default]
+    Text,
+    /// Newline-delimited JSON events on stdout, one object per line
+    Json,
+}
+
+/// Machine-readable progress event. The `event` field names the variant;
+/// fields are only ever added, never renamed or removed.
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event<'a> {
+    BookStarted {
+        book: &'a str,
+        output: &'a str,
+    },
+    ChapterConverted {
+        book: &'a str,
+        chapter: usize,
+        spine_index: usize,
+        spine_total: usize,
+        source: &'a str,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        file: Option<&'a str>,
+    },
+    BookFinished {
+        book: &'a str,
+        output: &'a str,
+        files: usize,
+        cached: bool,
+    },
+    Error {
+        book: &'a str,
+        message: String,
+        kind: ErrorKind,
+        /// The missing resource, for `missing_resource`
+        #[serde(skip_serializing_if = "Option::is_none")]
+        href: Option<&'a str>,
+        /// The chapter's archive path, for `render_failed`
+        #[serde(skip_serializing_if = "Option::is_none")]
+        chapter: Option<&'a str>,
+    },
+    /// Something lost or doubtful in a book that still converted
+    Warning {
+        book: &'a str,
+        kind: WarningKind,
+        message: &'a str,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        source: Option<&'a str>,
+    },
+    /// A transient failure, after which the book is converted again
+    BookRetried {
+        book: &'a str,
+        attempt: usize,
+        message: String,
+    },
+}
+
+impl<'a> Event<'a> {
+    /// The `error` event for a book that failed, classified by `ErrorReport`
+    pub fn error(book: &'a str, error: &'a anyhow::Error) -> Self {
+        let report = ErrorReport::of(error);
+        Event::Error { book, message: format!("{:#}", error), kind: report.kind, href: report.href, chapter: report.chapter }
+    }
+}
+
+/// Where JSON events go instead of stdout, such as a daemon client's connection
+#[derive(Clone)]
+pub struct Sink(Arc<Mutex<dyn Write + Send>>);
+
+impl Sink {
+    pub fn new(writer: impl Write + Send + 'static) -> Self {
+        Self(Arc::new(Mutex::new(writer)))
+    }
+
+    /// Write one line; a writer that has gone away is ignored
+    pub fn line(&self, line: &str) {
+        if let Ok(mut writer) = self.0.lock() {
+            let _ = writer.write_all(format!("{}\n", line).as_bytes()).and_then(|_| writer.flush());
+        }
+    }
+}
+
+impl fmt::Debug for Sink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Sink")
+    }
+}
+
+/// Progress sink shared by every conversion in a run
+#[derive(Debug, Clone, Default)]
+pub struct Progress {
+    pub format: ProgressFormat,
+    /// Report nothing, for conversions whose output is used internally
+    pub quiet: bool,
+    /// Print only the paths of files written, each followed by this byte (`--print-paths`)
+    pub paths: Option<u8>,
+    /// Send JSON events here rather than printing them
+    pub sink: Option<Sink>,
+    /// List each book's warnings rather than only counting them (`-v`)
+    pub verbose: bool,
+}
+
+impl Progress {
+    /// Print a human-readable status line (suppressed in JSON mode)
+    pub fn text(&self, line: impl Display) {
+        if self.format == ProgressFormat::Text && !self.quiet && self.paths.is_none() {
+            print_line(format!("{}\n", line).as_bytes());
+        }
+    }
+
+    /// Emit a structured event (only in JSON mode)
+    pub fn event(&self, event: Event) {
+        if self.format == ProgressFormat::Json && !self.quiet && self.paths.is_none() {
+            if let Ok(line) = serde_json::to_string(&event) {
+                match &self.sink {
+                    Some(sink) => sink.line(&line),
+                    None => print_line(format!("{}\n", line).as_bytes()),
+                }
+            }
+        }
+    }
+
+    /// List a file that was written (only with `--print-paths`)
+    pub fn written(&self, path: &Path) {
+        let Some(delimiter) = self.paths.filter(|_| !self.quiet) else {
+            return;
+        };
+        let mut line = path.as_os_str().as_encoded_bytes().to_vec();
+        line.push(delimiter);
+        print_line(&line);
+    }
+}
+
+/// Print a line to stdout
+fn print_line(line: &[u8]) {
+    write_line(&mut io::stdout().lock(), &STDOUT_CLOSED, line);
+}
+
+/// Write a line in one piece, so lines from books converting in parallel don't
+/// interleave. A broken pipe sets `closed` and stops the writes that follow, where
+/// `println!` would panic; other errors only lose the line.
+fn write_line(out: &mut impl Write, closed: &AtomicBool, line: &[u8]) {
+    if closed.load(Ordering::Relaxed) {
+        return;
+    }
+    if let Err(e) = out.write_all(line).and_then(|_| out.flush()) {
+        if e.kind() == io::ErrorKind::BrokenPipe {
+            closed.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A pipe whose reader goes away after `open` writes
+    struct Pipe {
+        open: usize,
+        writes: usize,
+    }
+
+    impl Write for Pipe {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.writes += 1;
+            if self.writes > self.open {
+                return Err(io::ErrorKind::BrokenPipe.into());
+            }
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn a_broken_pipe_stops_the_output() {
+        let closed = AtomicBool::new(false);
+        let mut pipe = Pipe { open: 2, writes: 0 };
+        for _ in 0..5 {
+            write_line(&mut pipe, &closed, b"{\"event\":\"book_started\"}\n");
+        }
+        assert!(closed.load(Ordering::Relaxed));
+        assert_eq!(pipe.writes, 3);
+    }
+
+    #[test]
+    fn other_errors_only_lose_the_line() {
+        struct Full;
+        impl Write for Full {
+            fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+                Err(io::Error::other("disk full"))
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+        let closed = AtomicBool::new(false);
+        write_line(&mut Full, &closed, b"line\n");
+        assert!(!closed.load(Ordering::Relaxed));
+    }
+}
@@ -0,0 +1,180 @@
+use crate::digest;
+use crate::units;
+use clap::builder::ArgAction;
+use clap::ArgMatches;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+/// Every option of a conversion run by its long name, with the value it had: given on
+/// the command line, from a profile or environment variable, or the default
+#[derive(Clone, Default)]
+pub struct Settings(Arc<BTreeMap<String, Value>>);
+
+impl fmt::Debug for Settings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Settings")
+    }
+}
+
+impl Settings {
+    /// The settings of a parsed subcommand
+    pub fn from_matches(command: &clap::Command, matches: &ArgMatches) -> Self {
+        let mut settings = BTreeMap::new();
+        for arg in command.get_arguments() {
+            let id = arg.get_id().as_str();
+            if matches!(arg.get_action(), ArgAction::Help | ArgAction::Version) {
+                continue;
+            }
+            let name = arg.get_long().unwrap_or(id).to_string();
+            let value = match arg.get_action() {
+                ArgAction::SetTrue | ArgAction::SetFalse => Value::Bool(matches.get_flag(id)),
+                ArgAction::Count => Value::from(matches.get_count(id)),
+                action => {
+                    let values: Vec<Value> = matches
+                        .get_raw(id)
+                        .into_iter()
+                        .flatten()
+                        .map(|v| Value::String(v.to_string_lossy().into_owned()))
+                        .collect();
+                    match (action, values.len()) {
+                        (ArgAction::Append, _) => Value::Array(values),
+                        (_, 0) => Value::Null,
+                        (_, 1) => values.into_iter().next().expect("one value"),
+                        _ => Value::Array(values),
+                    }
+                }
+            };
+            settings.insert(name, value);
+        }
+        Self(Arc::new(settings))
+    }
+
+    /// These settings with a pair of opposing flags, such as `--tidy` and `--no-tidy`,
+    /// recorded as the one value they came to under `name`
+    pub fn resolved(&self, name: &str, opposite: &str, value: impl Into<Value>) -> Self {
+        if !self.0.contains_key(name) {
+            return self.clone();
+        }
+        let mut settings = (*self.0).clone();
+        settings.remove(opposite);
+        settings.insert(name.to_string(), value.into());
+        Self(Arc::new(settings))
+    }
+}
+
+impl Serialize for Settings {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0.serialize(serializer)
+    }
+}
+
+/// Where a book's output came from, so converted books can be traced back to their exact
+/// source and settings
+#[derive(Debug, Serialize)]
+pub struct Provenance {
+    /// File name of the source EPUB
+    pub source_file: String,
+    /// SHA-256 of the source EPUB, as lowercase hex
+    pub sha256: String,
+    /// Size of the source EPUB in bytes
+    pub size: u64,
+    /// When the conversion ran (UTC), with `--record-time`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub converted_at: Option<String>,
+    /// Version of epub-to-md that converted it
+    pub tool_version: String,
+    /// Every option of the run and its value
+    pub options: Settings,
+}
+
+impl Provenance {
+    pub fn of(epub_path: &Path, settings: &Settings, record_time: bool) -> io::Result<Self> {
+        Ok(Self {
+            source_file: epub_path.file_name().unwrap_or_default().to_string_lossy().into_owned(),
+            sha256: digest::sha256_file(epub_path)?,
+            size: epub_path.metadata()?.len(),
+            converted_at: record_time.then(|| units::utc_timestamp(SystemTime::now())),
+            tool_version: env!("CARGO_PKG_VERSION").to_string(),
+            options: settings.clone(),
+        })
+    }
+}
+
+/// `metadata_json`, as a conversion wrote it, with its provenance block replaced. Output
+/// restored from the cache gets the provenance of the run that restored it this way, since
+/// the source file, its name or the options may have changed. None if there was no block.
+pub fn replace_in(metadata_json: &str, provenance: &Provenance) -> serde_json::Result<Option<String>> {
+    // The block is the last field, so it runs to the end of the file
+    let Some(start) = metadata_json.rfind("\n  \"provenance\": ") else {
+        return Ok(None);
+    };
+    #[derive(Serialize)]
+    struct Tail<'a> {
+        provenance: &'a Provenance,
+    }
+    // Pretty-printed the same way, indented as a field of the metadata object
+    let tail = serde_json::to_string_pretty(&Tail { provenance })?;
+    Ok(Some(format!("{}{}", &metadata_json[..start], &tail[1..])))
+}
+
+/// Fields of the provenance block that describe the run rather than the output, left out
+/// when `check` compares metadata.json
+pub const RUN_FIELDS: &[&str] = &["converted_at", "tool_version", "options"];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn provenance(source_file: &str) -> Provenance {
+        Provenance {
+            source_file: source_file.to_string(),
+            sha256: "00".to_string(),
+            size: 1,
+            converted_at: None,
+            tool_version: "0.1.0".to_string(),
+            options: Settings::default(),
+        }
+    }
+
+    #[derive(Serialize)]
+    struct Metadata {
+        title: &'static str,
+        nested: BTreeMap<&'static str, Vec<u8>>,
+        provenance: Provenance,
+    }
+
+    #[test]
+    fn opposing_flags_are_recorded_as_the_value_they_come_to() {
+        let settings = Settings(Arc::new(BTreeMap::from([
+            ("tidy".to_string(), Value::Bool(false)),
+            ("no-tidy".to_string(), Value::Bool(false)),
+        ])));
+        let resolved = serde_json::to_string(&settings.resolved("tidy", "no-tidy", true)).unwrap();
+        assert_eq!(resolved, r#"{"tidy":true}"#);
+        // Settings that weren't parsed from a command line stay empty
+        let empty = Settings::default().resolved("tidy", "no-tidy", true);
+        assert_eq!(serde_json::to_string(&empty).unwrap(), "{}");
+    }
+
+    #[test]
+    fn replacing_the_block_matches_a_fresh_write() {
+        let metadata = |source_file| Metadata {
+            title: "Book",
+            nested: BTreeMap::from([("provenance", vec![1, 2])]),
+            provenance: provenance(source_file),
+        };
+        let restored = serde_json::to_string_pretty(&metadata("old.epub")).unwrap();
+        let fresh = serde_json::to_string_pretty(&metadata("new.epub")).unwrap();
+
+        let restamped = replace_in(&restored, &provenance("new.epub")).unwrap().unwrap();
+        assert_eq!(restamped, fresh);
+        assert!(!restamped.contains("converted_at"));
+        assert_eq!(replace_in("{\n  \"title\": \"Book\"\n}", &provenance("new.epub")).unwrap(), None);
+    }
+}
@@ -0,0 +1,270 @@
+use crate::html;
+use crate::output;
+use crate::render;
+use crate::toc;
+use crate::{extract_metadata, BookMetadata};
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use epub::doc::EpubDoc;
+use markup5ever_rcdom::{Handle, NodeData};
+use serde::Serialize;
+use std::path::Path;
+
+/// How `quotes` writes what it found
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum QuotesFormat {
+    /// A Markdown collection, grouped by chapter
+    Markdown,
+    /// JSON with the book's title and authors and every quote
+    Json,
+}
+
+impl QuotesFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            QuotesFormat::Markdown => "md",
+            QuotesFormat::Json => "json",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum QuoteKind {
+    Blockquote,
+    Epigraph,
+    Dialogue,
+}
+
+#[derive(Debug, Serialize)]
+struct Quote {
+    kind: QuoteKind,
+    /// Paragraphs of the quotation
+    text: Vec<String>,
+    attribution: Option<String>,
+    /// Position of the chapter in the spine, counting from 1, as `--numbering spine` names it
+    chapter: usize,
+    chapter_title: Option<String>,
+    /// Path of the chapter's document inside the EPUB
+    source: String,
+    words: usize,
+}
+
+#[derive(Serialize)]
+struct Collection<'a> {
+    title: Option<&'a str>,
+    creators: &'a [String],
+    quotes: &'a [Quote],
+}
+
+/// Elements whose text is one paragraph of a quotation
+const PARAGRAPH_TAGS: &[&str] = &["p", "li", "dt", "dd", "h1", "h2", "h3", "h4", "h5", "h6", "pre"];
+
+/// Elements that may be an epigraph, besides `<blockquote>`
+const EPIGRAPH_TAGS: &[&str] = &["div", "aside", "section"];
+
+/// Opening and closing marks of dialogue. Single quotes are left out, since they can't be
+/// told apart from apostrophes.
+const DIALOGUE_MARKS: &[(char, char)] = &[('“', '”'), ('"', '"'), ('«', '»'), ('„', '“')];
+
+/// Collect the blockquotes and epigraphs of a book, and with `dialogue` the quoted speech
+/// in its paragraphs, with at least `min_words` words each. Writes them with the chapter
+/// each came from to `output_path`, or to stdout if it is `-`.
+pub fn extract_quotes(epub_path: &Path, output_path: &Path, format: QuotesFormat, min_words: usize, dialogue: bool) -> Result<()> {
+    let mut doc = EpubDoc::new(epub_path).context("Failed to open EPUB file")?;
+    toc::fill_from_nav(&mut doc);
+    let metadata = extract_metadata(&doc);
+    let labels = toc::labels_by_path(&doc.toc);
+
+    let sources: Vec<Option<String>> = doc
+        .spine
+        .iter()
+        .map(|spine| doc.resources.get(&spine.idref).map(|r| r.path.to_string_lossy().replace('\\', "/")))
+        .collect();
+
+    let mut quotes = Vec::new();
+    for (index, source) in sources.into_iter().enumerate() {
+        let Some(source) = source else { continue };
+        let Some(content) = doc.get_resource_str_by_path(&source) else { continue };
+        let dom = html::parse(&content);
+        let mut found = Found::default();
+        find(&dom.document, dialogue, &mut found);
+
+        let chapter_title = labels
+            .get(&toc::target_path(Path::new(&source)))
+            .cloned()
+            .or(found.heading)
+            .filter(|t| !t.is_empty());
+        for (kind, text, attribution) in found.quotes {
+            let words = text.iter().map(|p| p.split_whitespace().count()).sum();
+            if words == 0 || words < min_words {
+                continue;
+            }
+            quotes.push(Quote {
+                kind,
+                text,
+                attribution,
+                chapter: index + 1,
+                chapter_title: chapter_title.clone(),
+                source: source.clone(),
+                words,
+            });
+        }
+    }
+
+    let text = match format {
+        QuotesFormat::Markdown => to_markdown(&metadata, &quotes),
+        QuotesFormat::Json => {
+            let collection = Collection { title: metadata.title.as_deref(), creators: &metadata.creators, quotes: &quotes };
+            serde_json::to_string_pretty(&collection).context("Failed to serialize quotes")? + "\n"
+        }
+    };
+    if output_path == Path::new("-") {
+        print!("{}", text);
+        return Ok(());
+    }
+    output::write_atomic(output_path, text).with_context(|| format!("Failed to write {}", output_path.display()))?;
+    println!("Extracted {} quote(s) to {}", quotes.len(), output_path.display());
+    Ok(())
+}
+
+#[derive(Default)]
+struct Found {
+    /// First heading of the chapter, used when the TOC has no label for it
+    heading: Option<String>,
+    quotes: Vec<(QuoteKind, Vec<String>, Option<String>)>,
+}
+
+fn find(node: &Handle, dialogue: bool, found: &mut Found) {
+    for child in node.children.borrow().iter() {
+        let Some(tag) = html::tag_name(child) else { continue };
+        if matches!(tag.as_str(), "head" | "script" | "style") {
+            continue;
+        }
+        if found.heading.is_none() && matches!(tag.as_str(), "h1" | "h2" | "h3" | "h4" | "h5" | "h6") {
+            found.heading = Some(text(child));
+        }
+        let epigraph = render::is_epigraph(child);
+        if tag == "blockquote" || (epigraph && EPIGRAPH_TAGS.contains(&tag.as_str())) {
+            // A quotation nested in another is part of it
+            let mut paragraphs = Vec::new();
+            let mut attribution = None;
+            quotation(child, &mut paragraphs, &mut attribution);
+            let kind = if epigraph { QuoteKind::Epigraph } else { QuoteKind::Blockquote };
+            found.quotes.push((kind, paragraphs, attribution));
+            continue;
+        }
+        if dialogue && tag == "p" {
+            let speech = spoken(&text(child));
+            if !speech.is_empty() {
+                found.quotes.push((QuoteKind::Dialogue, vec![speech], None));
+            }
+            continue;
+        }
+        find(child, dialogue, found);
+    }
+}
+
+/// Paragraphs and attribution line of a blockquote or epigraph
+fn quotation(node: &Handle, paragraphs: &mut Vec<String>, attribution: &mut Option<String>) {
+    for child in node.children.borrow().iter() {
+        if let NodeData::Text { ref contents } = child.data {
+            let loose = contents.borrow().split_whitespace().collect::<Vec<_>>().join(" ");
+            if !loose.is_empty() {
+                paragraphs.push(loose);
+            }
+        }
+        let Some(tag) = html::tag_name(child) else { continue };
+        if render::is_attribution(child) {
+            let name = text(child);
+            let name = name.trim_start_matches(|c: char| matches!(c, '—' | '–' | '―' | '-') || c.is_whitespace());
+            if attribution.is_none() && !name.is_empty() {
+                *attribution = Some(name.to_string());
+            }
+        } else if PARAGRAPH_TAGS.contains(&tag.as_str()) {
+            let paragraph = text(child);
+            if !paragraph.is_empty() {
+                paragraphs.push(paragraph);
+            }
+        } else {
+            quotation(child, paragraphs, attribution);
+        }
+    }
+}
+
+/// Text of a node with whitespace collapsed, leaving out note references
+fn text(node: &Handle) -> String {
+    fn collect(node: &Handle, out: &mut String) {
+        match node.data {
+            NodeData::Text { ref contents } => out.push_str(&contents.borrow()),
+            NodeData::Element { .. } => {
+                let noteref = html::epub_type(node).is_some_and(|t| t.split_whitespace().any(|t| t == "noteref"))
+                    || html::attr(node, "role").is_some_and(|r| r == "doc-noteref");
+                if noteref || html::tag_name(node).as_deref() == Some("br") {
+                    out.push(' ');
+                    return;
+                }
+                for child in node.children.borrow().iter() {
+                    collect(child, out);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let mut raw = String::new();
+    collect(node, &mut raw);
+    raw.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// The quoted speech in a paragraph, the pieces joined: narration between them ("she
+/// said") is dropped
+fn spoken(paragraph: &str) -> String {
+    let mut pieces = Vec::new();
+    let mut rest = paragraph;
+    while let Some((start, (_, close))) = rest
+        .char_indices()
+        .find_map(|(i, c)| DIALOGUE_MARKS.iter().find(|(open, _)| *open == c).map(|m| (i, *m)))
+    {
+        let after = &rest[start + rest[start..].chars().next().map(char::len_utf8).unwrap_or(1)..];
+        let Some(end) = after.find(close) else { break };
+        let piece = after[..end].trim();
+        if !piece.is_empty() {
+            pieces.push(piece);
+        }
+        rest = &after[end + close.len_utf8()..];
+    }
+    pieces.join(" ")
+}
+
+fn to_markdown(metadata: &BookMetadata, quotes: &[Quote]) -> String {
+    let mut out = format!("# Quotes from {}\n", metadata.title.as_deref().unwrap_or("Unknown Title"));
+    if !metadata.creators.is_empty() {
+        out.push_str(&format!("\n*{}*\n", metadata.creators.join(", ")));
+    }
+    let mut chapter = None;
+    for quote in quotes {
+        if chapter != Some(quote.chapter) {
+            chapter = Some(quote.chapter);
+            match &quote.chapter_title {
+                Some(title) => out.push_str(&format!("\n## Chapter {}: {}\n", quote.chapter, title)),
+                None => out.push_str(&format!("\n## Chapter {}\n", quote.chapter)),
+            }
+        }
+        out.push('\n');
+        let mut lines: Vec<String> = quote.text.iter().map(|p| format!("> {}", p)).collect();
+        if let Some(attribution) = &quote.attribution {
+            lines.push(format!("> — {}", attribution));
+        }
+        out.push_str(&lines.join("\n>\n"));
+        out.push('\n');
+        let kind = match quote.kind {
+            QuoteKind::Blockquote => "Blockquote",
+            QuoteKind::Epigraph => "Epigraph",
+            QuoteKind::Dialogue => "Dialogue",
+        };
+        let unit = if quote.words == 1 { "word" } else { "words" };
+        out.push_str(&format!("\n*{}, {}, {} {}*\n", kind, quote.source, quote.words, unit));
+    }
+    out
+}
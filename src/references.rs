@@ -0,0 +1,191 @@
+use crate::html;
+use markup5ever_rcdom::Handle;
+use serde::Serialize;
+
+/// Headings that open a bibliography when the markup doesn't say so
+const BIBLIOGRAPHY_HEADINGS: &[&str] = &[
+    "bibliography",
+    "select bibliography",
+    "selected bibliography",
+    "references",
+    "works cited",
+    "literature cited",
+    "sources",
+    "further reading",
+];
+
+/// A citation from a bibliography or reference list, as listed in references.json
+#[derive(Debug, Serialize)]
+pub struct Reference {
+    /// Output chapter number the entry appears in; absent when its section was skipped
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chapter: Option<usize>,
+    /// Path of the source XHTML document inside the EPUB
+    pub source: String,
+    /// `id` of the entry, which notes in the text often link to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    /// The citation as printed
+    pub text: String,
+    /// First web link in the entry
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub doi: Option<String>,
+    /// First plausible publication year in the citation
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub year: Option<u16>,
+}
+
+/// Bibliography entries found in a document
+#[derive(Default)]
+struct Scan {
+    entries: Vec<Handle>,
+    /// Whether there is body text, headings aside, outside the bibliography sections
+    text_outside: bool,
+}
+
+/// Whether a parsed document is as a whole a bibliography, not a chapter that ends with one
+pub fn is_bibliography(document: &Handle) -> bool {
+    let found = scan(document);
+    !found.entries.is_empty() && !found.text_outside
+}
+
+/// Collect the entries of a chapter's bibliography sections: elements marked with
+/// `epub:type="bibliography"` (or `role="doc-bibliography"`), or the content under a heading
+/// such as "References". `chapter` is left unset for the caller to fill in.
+pub fn extract_references(content: &str, source: &str) -> Vec<Reference> {
+    if !might_have_bibliography(content) {
+        return Vec::new();
+    }
+    let dom = html::parse(content);
+    scan(&dom.document)
+        .entries
+        .iter()
+        .map(|entry| {
+            let text = html::text_content(entry);
+            let mut url = None;
+            html::walk(entry, &mut |node| {
+                if url.is_none() && html::tag_name(node).as_deref() == Some("a") {
+                    url = html::attr(node, "href").filter(|h| h.starts_with("http://") || h.starts_with("https://"));
+                }
+            });
+            Reference {
+                chapter: None,
+                source: source.to_string(),
+                id: html::attr(entry, "id").filter(|id| !id.is_empty()),
+                doi: find_doi(&text).or_else(|| url.as_deref().and_then(find_doi)),
+                year: find_year(&text),
+                url,
+                text,
+            }
+        })
+        .filter(|r| !r.text.is_empty())
+        .collect()
+}
+
+/// Cheap check that lets most chapters skip the parse
+pub fn might_have_bibliography(content: &str) -> bool {
+    let lower = content.to_ascii_lowercase();
+    lower.contains("bibliograph") || BIBLIOGRAPHY_HEADINGS.iter().any(|h| lower.contains(h))
+}
+
+fn scan(document: &Handle) -> Scan {
+    let mut found = Scan::default();
+    visit(document, false, &mut found);
+    found
+}
+
+fn visit(node: &Handle, in_bibliography: bool, found: &mut Scan) {
+    // Level of the bibliography heading whose section the following siblings belong to
+    let mut under_heading: Option<u8> = None;
+    for child in node.children.borrow().iter() {
+        let Some(tag) = html::tag_name(child) else {
+            if !in_bibliography && under_heading.is_none() && !html::text_content(child).is_empty() {
+                found.text_outside = true;
+            }
+            continue;
+        };
+        if tag == "head" {
+            continue;
+        }
+        if let Some(level) = heading_level(&tag) {
+            if under_heading.is_some_and(|open| level <= open) {
+                under_heading = None;
+            }
+            let named = is_bibliography_heading(&html::text_content(child));
+            if named && under_heading.is_none() {
+                under_heading = Some(level);
+            }
+            continue;
+        }
+        let inside = in_bibliography || under_heading.is_some() || is_marked(child);
+        if inside && is_entry(child, &tag) {
+            found.entries.push(child.clone());
+        } else {
+            visit(child, inside, found);
+        }
+    }
+}
+
+fn is_marked(node: &Handle) -> bool {
+    html::epub_type(node).is_some_and(|t| t.split_whitespace().any(|t| t == "bibliography"))
+        || html::attr(node, "role").as_deref() == Some("doc-bibliography")
+}
+
+/// List items and paragraphs inside a bibliography, or anything marked as an entry
+fn is_entry(node: &Handle, tag: &str) -> bool {
+    matches!(tag, "li" | "p")
+        || html::epub_type(node).is_some_and(|t| t.split_whitespace().any(|t| t == "biblioentry"))
+        || html::attr(node, "role").as_deref() == Some("doc-biblioentry")
+}
+
+fn heading_level(tag: &str) -> Option<u8> {
+    match tag.as_bytes() {
+        [b'h', n @ b'1'..=b'6'] => Some(n - b'0'),
+        _ => None,
+    }
+}
+
+/// "References", "7. Bibliography", "Works Cited:" and the like
+fn is_bibliography_heading(text: &str) -> bool {
+    let name = text
+        .trim_start_matches(|c: char| c.is_ascii_digit() || c == '.' || c.is_whitespace())
+        .trim_end_matches([':', '.'])
+        .trim()
+        .to_lowercase();
+    BIBLIOGRAPHY_HEADINGS.contains(&name.as_str())
+}
+
+/// A DOI (`10.1000/xyz123`) anywhere in the text, including inside a doi.org link
+fn find_doi(text: &str) -> Option<String> {
+    let mut from = 0;
+    while let Some(i) = text[from..].find("10.").map(|i| from + i) {
+        from = i + 3;
+        if text[..i].chars().next_back().is_some_and(|c| c.is_alphanumeric()) {
+            continue;
+        }
+        let rest = &text[i + 3..];
+        let registrant = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+        if !(4..=9).contains(&registrant) || !rest[registrant..].starts_with('/') {
+            continue;
+        }
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        let doi = text[i..i + 3 + end].trim_end_matches(['.', ',', ';', ')', ']']);
+        if doi.len() > 3 + registrant + 1 {
+            return Some(doi.to_string());
+        }
+    }
+    None
+}
+
+/// First standalone four-digit number that could be a publication year
+fn find_year(text: &str) -> Option<u16> {
+    let chars: Vec<char> = text.chars().collect();
+    (0..chars.len().saturating_sub(3)).find_map(|i| {
+        let bounded = (i == 0 || !chars[i - 1].is_ascii_digit()) && chars.get(i + 4).is_none_or(|c| !c.is_ascii_digit());
+        let digits: String = chars[i..i + 4].iter().collect();
+        let year: u16 = digits.parse().ok().filter(|_| bounded && digits.chars().all(|c| c.is_ascii_digit()))?;
+        (1450..=2100).contains(&year).then_some(year)
+    })
+}
@@ -0,0 +1,75 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Whether an input names a remote EPUB rather than a local path
+pub fn is_url(input: &str) -> bool {
+    let lower = input.get(..8).unwrap_or(input).to_ascii_lowercase();
+    lower.starts_with("http://") || lower.starts_with("https://")
+}
+
+/// Local file name for a downloaded book, taken from the last URL path segment
+fn file_name_for(url: &str) -> String {
+    let path = url.split(['?', '#']).next().unwrap_or(url);
+    let last = path.trim_end_matches('/').rsplit('/').next().unwrap_or_default();
+    let decoded = percent_encoding::percent_decode_str(last).decode_utf8_lossy();
+    let stem = crate::sanitize_filename(decoded.trim_end_matches(".epub"));
+    let stem = if stem.is_empty() { "book".to_string() } else { stem };
+    format!("{}.epub", stem)
+}
+
+/// Download `url` into `dir` via curl, returning the path of the saved file.
+/// The name comes from `name` or else the URL; a numeric suffix keeps it from
+/// clobbering earlier downloads.
+pub fn download(url: &str, name: Option<&str>, dir: &Path) -> Result<PathBuf> {
+    let name = match name.map(crate::sanitize_filename) {
+        Some(name) if !name.trim().is_empty() => format!("{}.epub", name.trim()),
+        _ => file_name_for(url),
+    };
+    let mut dest = dir.join(&name);
+    let mut n = 2;
+    while dest.exists() {
+        dest = dir.join(format!("{}-{}.epub", name.trim_end_matches(".epub"), n));
+        n += 1;
+    }
+
+    let output = Command::new("curl")
+        .args(["--silent", "--show-error", "--fail", "--location", "--output"])
+        .arg(&dest)
+        .arg(url)
+        .output()
+        .context("Failed to run curl")?;
+    if !output.status.success() {
+        let _ = fs::remove_file(&dest);
+        let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        anyhow::bail!("Failed to download {}: {}", url, if stderr.is_empty() { "no response" } else { &stderr });
+    }
+    Ok(dest)
+}
+
+/// Fetch a text document (such as a catalog feed) via curl
+pub fn fetch(url: &str) -> Result<String> {
+    let output = Command::new("curl")
+        .args(["--silent", "--show-error", "--fail", "--location"])
+        .arg(url)
+        .output()
+        .context("Failed to run curl")?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        anyhow::bail!("Failed to fetch {}: {}", url, if stderr.is_empty() { "no response" } else { &stderr });
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Read a list of URLs, one per line, ignoring blank lines and `#` comments
+pub fn read_url_list(path: &Path) -> Result<Vec<String>> {
+    let text = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read URL list {}", path.display()))?;
+    Ok(text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect())
+}
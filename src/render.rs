@@ -0,0 +1,721 @@
+use crate::callout::{self, CalloutStyle};
+use crate::html;
+use crate::overlay;
+use clap::ValueEnum;
+use html2md::{Handle, StructuredPrinter, TagHandler, TagHandlerFactory};
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// Markdown dialect the output is written for, where plain Markdown has no syntax
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Flavor {
+    /// GitHub Flavored Markdown: `~~strikethrough~~`, inline HTML for the rest
+    #[default]
+    Gfm,
+    /// CommonMark: inline HTML for anything beyond the core syntax
+    Commonmark,
+    /// Pandoc Markdown: `^superscript^`, `~subscript~` and `[text]{.class}` spans
+    Pandoc,
+    /// Obsidian: `==highlights==`, inline HTML for superscript and subscript
+    Obsidian,
+}
+
+/// How `<u>` and `<ins>` are written
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Underline {
+    /// `[text]{.underline}` for Pandoc, inline HTML otherwise
+    #[default]
+    Auto,
+    /// Keep the `<u>` / `<ins>` tags
+    Html,
+    /// Write as `*emphasis*`
+    Emphasis,
+    /// Drop the styling and keep the text
+    Plain,
+}
+
+/// How `<mark>` is written
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Highlight {
+    /// `==text==` for Obsidian, `[text]{.mark}` for Pandoc, inline HTML otherwise
+    #[default]
+    Auto,
+    /// Keep the `<mark>` tags
+    Html,
+    /// Write as `==text==`
+    Equals,
+    /// Drop the styling and keep the text
+    Plain,
+}
+
+/// Options for converting chapter XHTML to Markdown, beyond html2md's defaults
+#[derive(Debug, Clone, Default)]
+pub struct RenderOptions {
+    /// Markdown dialect to target
+    pub flavor: Flavor,
+    /// Mapping for underlined and inserted text
+    pub underline: Underline,
+    /// Mapping for highlighted text
+    pub highlight: Highlight,
+    /// Replace every scene break with this marker
+    pub scene_break: Option<String>,
+    /// Write notes, tips and warnings as callouts in this syntax
+    pub callouts: Option<CalloutStyle>,
+    /// Extra class (or `epub:type`) to callout type mappings, checked before the built-in ones
+    pub callout_classes: Vec<(String, String)>,
+    /// Turn print page breaks into `<a id="…"></a>` anchors
+    pub page_markers: bool,
+    /// Write the read-along markers put before timed paragraphs as HTML comments
+    pub read_along: bool,
+}
+
+/// A chapter converted to Markdown
+pub struct Rendered {
+    pub markdown: String,
+    /// Ids of the page-marker anchors written, in order
+    pub page_anchors: Vec<String>,
+}
+
+/// Elements that may mark a print page break
+const PAGE_MARKER_TAGS: &[&str] = &["span", "a", "div", "p", "hr"];
+
+/// Elements that may be scene breaks
+const SCENE_BREAK_TAGS: &[&str] = &["p", "div", "hr", "h1", "h2", "h3", "h4", "h5", "h6"];
+
+/// Elements that may hold a note, tip or warning
+const CALLOUT_TAGS: &[&str] = &["div", "aside", "section", "p"];
+
+/// Elements that may be an epigraph
+const EPIGRAPH_TAGS: &[&str] = &["div", "aside", "section"];
+
+/// Elements that may be the attribution of a quotation or epigraph
+const ATTRIBUTION_TAGS: &[&str] = &["p", "div", "footer"];
+
+/// Class names publishers give attribution lines
+const ATTRIBUTION_HINTS: &[&str] = &["attribution", "author", "source", "signature", "byline", "credit", "cite"];
+
+impl RenderOptions {
+    /// Tags these options handle differently from html2md
+    fn custom_tags(&self) -> Vec<&'static str> {
+        // html2md drops <u>, <mark> and <strike>, writes <ins> as bold, runs definition
+        // lists together, and turns <q> and <cite> into blockquotes
+        let mut tags = vec!["u", "ins", "mark", "strike", "dl", "dt", "dd", "q", "cite", "blockquote"];
+        tags.extend(EPIGRAPH_TAGS);
+        tags.extend(ATTRIBUTION_TAGS);
+        if self.flavor == Flavor::Commonmark {
+            tags.extend(["del", "s"]);
+        }
+        if self.scene_break.is_some() {
+            tags.push("body");
+            tags.extend(SCENE_BREAK_TAGS);
+        }
+        if self.callouts.is_some() {
+            tags.extend(CALLOUT_TAGS);
+        }
+        if self.page_markers {
+            tags.extend(PAGE_MARKER_TAGS);
+        }
+        if self.flavor == Flavor::Pandoc {
+            tags.extend(["sup", "sub"]);
+        }
+        if self.read_along {
+            tags.push(overlay::MARKER_TAG);
+        }
+        tags
+    }
+
+    /// Markup around an inline element's content, or None for tags that aren't inline styles
+    fn inline_markup(&self, tag: &'static str) -> Option<Wrap> {
+        let html = |tag: &str| Wrap::new(format!("<{}>", tag), format!("</{}>", tag));
+        Some(match tag {
+            "sup" => Wrap::new("^", "^").escaping_spaces(),
+            "sub" => Wrap::new("~", "~").escaping_spaces(),
+            "q" => Wrap::new("\"", "\""),
+            "cite" => Wrap::new("*", "*"),
+            // CommonMark has no strikethrough
+            "del" | "s" | "strike" if self.flavor == Flavor::Commonmark => html("del"),
+            "del" | "s" | "strike" => Wrap::new("~~", "~~"),
+            "u" | "ins" => match (self.underline, self.flavor) {
+                (Underline::Auto, Flavor::Pandoc) => Wrap::new("[", "]{.underline}"),
+                (Underline::Auto | Underline::Html, _) => html(tag),
+                (Underline::Emphasis, _) => Wrap::new("*", "*"),
+                (Underline::Plain, _) => Wrap::new("", ""),
+            },
+            "mark" => match (self.highlight, self.flavor) {
+                (Highlight::Auto, Flavor::Obsidian) | (Highlight::Equals, _) => Wrap::new("==", "=="),
+                (Highlight::Auto, Flavor::Pandoc) => Wrap::new("[", "]{.mark}"),
+                (Highlight::Auto | Highlight::Html, _) => html("mark"),
+                (Highlight::Plain, _) => Wrap::new("", ""),
+            },
+            _ => return None,
+        })
+    }
+}
+
+/// Convert a chapter's XHTML to Markdown
+pub fn to_markdown(content: &str, options: &RenderOptions) -> Rendered {
+    let tags = options.custom_tags();
+    let state = Rc::new(RenderState {
+        options: options.clone(),
+        body_start: Cell::new(0),
+        quote_depth: Cell::new(0),
+        page_anchors: RefCell::new(Vec::new()),
+    });
+    let mut handlers: HashMap<String, Box<dyn TagHandlerFactory>> = HashMap::new();
+    for tag in tags {
+        handlers.insert(tag.to_string(), Box::new(Factory { tag, state: state.clone() }));
+    }
+    let mut markdown = html2md::parse_html_custom(content, &handlers);
+
+    // A break closing the chapter separates nothing
+    if let Some(marker) = &options.scene_break {
+        if let Some(rest) = markdown.strip_suffix(marker.as_str()).filter(|r| r.ends_with("\n\n")) {
+            markdown.truncate(rest.trim_end().len());
+        }
+    }
+    let page_anchors = state.page_anchors.take();
+    Rendered { markdown, page_anchors }
+}
+
+/// State shared by the handlers of one conversion
+struct RenderState {
+    options: RenderOptions,
+    /// Where the body's Markdown starts, after the `<title>` html2md echoes
+    body_start: Cell<usize>,
+    /// Number of blockquotes and epigraphs currently open
+    quote_depth: Cell<usize>,
+    /// Page-marker anchors written so far
+    page_anchors: RefCell<Vec<String>>,
+}
+
+/// Creates the handler for one tag. html2md instantiates a fresh handler per element.
+struct Factory {
+    tag: &'static str,
+    state: Rc<RenderState>,
+}
+
+impl TagHandlerFactory for Factory {
+    fn instantiate(&self) -> Box<dyn TagHandler> {
+        if self.tag == "body" {
+            return Box::new(BodyHandler { state: self.state.clone() });
+        }
+        if self.tag == overlay::MARKER_TAG {
+            return Box::new(ReadAlongHandler);
+        }
+        if let Some(wrap) = self.state.options.inline_markup(self.tag) {
+            return Box::new(wrap);
+        }
+        if self.tag == "blockquote" {
+            return Box::new(QuoteHandler { state: self.state.clone(), inner: None, active: false, start: 0 });
+        }
+        if matches!(self.tag, "dl" | "dt" | "dd") {
+            return Box::new(DefinitionHandler {
+                tag: self.tag,
+                // Pandoc's syntax is shared by kramdown and PHP Markdown Extra
+                native: self.state.options.flavor == Flavor::Pandoc,
+                after_term: false,
+                start: 0,
+            });
+        }
+
+        // Block handlers wrap html2md's own, each deciding per element whether to step in
+        let options = &self.state.options;
+        let mut handler = builtin(self.tag);
+        if let Some(marker) = options.scene_break.as_ref().filter(|_| SCENE_BREAK_TAGS.contains(&self.tag)) {
+            handler = Box::new(SceneBreakHandler {
+                marker: marker.clone(),
+                state: self.state.clone(),
+                inner: handler,
+                replaced: false,
+            });
+        }
+        if let Some(style) = options.callouts.filter(|_| CALLOUT_TAGS.contains(&self.tag)) {
+            handler = Box::new(CalloutHandler {
+                style,
+                state: self.state.clone(),
+                inner: handler,
+                kind: None,
+                start: 0,
+            });
+        }
+        if EPIGRAPH_TAGS.contains(&self.tag) {
+            handler = Box::new(QuoteHandler { state: self.state.clone(), inner: Some(handler), active: false, start: 0 });
+        }
+        if ATTRIBUTION_TAGS.contains(&self.tag) {
+            handler = Box::new(AttributionHandler { state: self.state.clone(), inner: handler, active: false, start: 0 });
+        }
+        if options.page_markers && PAGE_MARKER_TAGS.contains(&self.tag) {
+            handler = Box::new(PageMarkerHandler { state: self.state.clone(), inner: handler, marked: false, number_only: false });
+        }
+        handler
+    }
+}
+
+/// Replaces print page breaks (`epub:type="pagebreak"` or `role="doc-pagebreak"`) with an
+/// empty anchor, keeping the element's id so links to the page still resolve
+struct PageMarkerHandler {
+    state: Rc<RenderState>,
+    inner: Box<dyn TagHandler>,
+    marked: bool,
+    /// Whether the marker holds only its page number, which is dropped. A self-closing
+    /// `<span .../>` is read as an open span holding the rest of its paragraph, which isn't.
+    number_only: bool,
+}
+
+impl TagHandler for PageMarkerHandler {
+    fn handle(&mut self, tag: &Handle, printer: &mut StructuredPrinter) {
+        let typed = html::epub_type(tag).is_some_and(|t| t.split_whitespace().any(|t| t == "pagebreak"));
+        self.marked = typed || html::attr(tag, "role").as_deref() == Some("doc-pagebreak");
+        if !self.marked {
+            return self.inner.handle(tag, printer);
+        }
+        let label = html::attr(tag, "title").or_else(|| html::attr(tag, "aria-label"));
+        let text = html::text_content(tag);
+        let text = text.trim();
+        self.number_only = match &label {
+            Some(label) => text.is_empty() || text == label.trim(),
+            // Arabic or Roman numerals
+            None => text.chars().all(|c| c.is_ascii_digit() || "ivxlcdmIVXLCDM".contains(c)),
+        };
+        let id = html::attr(tag, "id").filter(|id| !id.is_empty()).unwrap_or_else(|| {
+            let page = label.clone().unwrap_or_else(|| text.to_string());
+            let page: String = page.chars().filter(|c| c.is_alphanumeric()).collect();
+            format!("page-{}", page)
+        });
+        printer.append_str(&format!("<a id=\"{}\"></a>", id));
+        self.state.page_anchors.borrow_mut().push(id);
+    }
+
+    fn after_handle(&mut self, printer: &mut StructuredPrinter) {
+        if !self.marked {
+            self.inner.after_handle(printer);
+        }
+    }
+
+    fn skip_descendants(&self) -> bool {
+        // The page number printed inside the marker would otherwise end up in the text
+        (self.marked && self.number_only) || self.inner.skip_descendants()
+    }
+}
+
+/// Blockquotes, and epigraphs (`epub:type="epigraph"` or `class="epigraph"`), which are
+/// written as blockquotes. Nested quotes render first, so each level just prefixes its
+/// content with `> `.
+struct QuoteHandler {
+    state: Rc<RenderState>,
+    /// Handler for elements that turn out not to be epigraphs; None for `<blockquote>`
+    inner: Option<Box<dyn TagHandler>>,
+    active: bool,
+    start: usize,
+}
+
+impl TagHandler for QuoteHandler {
+    fn handle(&mut self, tag: &Handle, printer: &mut StructuredPrinter) {
+        self.active = self.inner.is_none() || is_epigraph(tag);
+        match &mut self.inner {
+            Some(inner) if !self.active => inner.handle(tag, printer),
+            _ => {
+                self.state.quote_depth.set(self.state.quote_depth.get() + 1);
+                printer.append_str("\n\n");
+                self.start = printer.data.len();
+            }
+        }
+    }
+
+    fn after_handle(&mut self, printer: &mut StructuredPrinter) {
+        if !self.active {
+            if let Some(inner) = &mut self.inner {
+                inner.after_handle(printer);
+            }
+            return;
+        }
+        self.state.quote_depth.set(self.state.quote_depth.get() - 1);
+        let content = printer.data[self.start..].trim().to_string();
+        printer.data.truncate(self.start);
+        // Runs of blank lines (at any nesting level) collapse into one
+        let mut previous: Option<&str> = None;
+        for line in content.lines().map(str::trim_end) {
+            let blank = line.chars().all(|c| c == '>' || c == ' ');
+            if blank && previous == Some(line) {
+                continue;
+            }
+            previous = blank.then_some(line);
+            printer.append_str(if line.is_empty() { ">" } else { "> " });
+            printer.append_str(line);
+            printer.append_str("\n");
+        }
+        printer.append_str("\n");
+    }
+
+    fn skip_descendants(&self) -> bool {
+        match &self.inner {
+            Some(inner) if !self.active => inner.skip_descendants(),
+            _ => false,
+        }
+    }
+}
+
+pub fn is_epigraph(tag: &Handle) -> bool {
+    [html::epub_type(tag), html::attr(tag, "class")]
+        .into_iter()
+        .flatten()
+        .any(|v| v.split_whitespace().any(|t| t.eq_ignore_ascii_case("epigraph")))
+}
+
+/// Attribution lines inside a quotation or epigraph (`<footer>`, or a class such as
+/// `attribution` or `author`), written as a trailing `— Name` line
+struct AttributionHandler {
+    state: Rc<RenderState>,
+    inner: Box<dyn TagHandler>,
+    active: bool,
+    start: usize,
+}
+
+impl TagHandler for AttributionHandler {
+    fn handle(&mut self, tag: &Handle, printer: &mut StructuredPrinter) {
+        self.active = self.state.quote_depth.get() > 0 && is_attribution(tag);
+        if self.active {
+            printer.append_str("\n\n");
+            self.start = printer.data.len();
+        } else {
+            self.inner.handle(tag, printer);
+        }
+    }
+
+    fn after_handle(&mut self, printer: &mut StructuredPrinter) {
+        if !self.active {
+            return self.inner.after_handle(printer);
+        }
+        let content = printer.data[self.start..].trim();
+        // Publishers often type the dash themselves; html2md escapes a leading hyphen
+        let name = content.trim_start_matches(|c: char| matches!(c, '—' | '–' | '―' | '-' | '\\') || c.is_whitespace());
+        let line = if name.is_empty() { String::new() } else { format!("— {}\n\n", name.split_whitespace().collect::<Vec<_>>().join(" ")) };
+        printer.data.truncate(self.start);
+        printer.append_str(&line);
+    }
+
+    fn skip_descendants(&self) -> bool {
+        !self.active && self.inner.skip_descendants()
+    }
+}
+
+pub fn is_attribution(tag: &Handle) -> bool {
+    if html::tag_name(tag).as_deref() == Some("footer") {
+        return true;
+    }
+    html::attr(tag, "class").is_some_and(|class| {
+        class
+            .split_whitespace()
+            .flat_map(|t| t.split(['-', '_']))
+            .any(|t| ATTRIBUTION_HINTS.iter().any(|h| t.eq_ignore_ascii_case(h)))
+    })
+}
+
+/// Writes a read-along marker as an HTML comment: on a line of its own before the
+/// paragraph, or at the start of a list item or table cell
+struct ReadAlongHandler;
+
+impl TagHandler for ReadAlongHandler {
+    fn handle(&mut self, tag: &Handle, printer: &mut StructuredPrinter) {
+        let comment = html::attr(tag, "data-comment").unwrap_or_default().replace("--", "- -");
+        if html::attr(tag, "data-inline").as_deref() == Some("true") {
+            printer.append_str(&format!("<!-- {} --> ", comment));
+        } else {
+            printer.append_str(&format!("\n\n<!-- {} -->\n\n", comment));
+        }
+    }
+
+    fn after_handle(&mut self, _printer: &mut StructuredPrinter) {}
+
+    fn skip_descendants(&self) -> bool {
+        true
+    }
+}
+
+/// Notes where the body begins
+struct BodyHandler {
+    state: Rc<RenderState>,
+}
+
+impl TagHandler for BodyHandler {
+    fn handle(&mut self, _tag: &Handle, printer: &mut StructuredPrinter) {
+        self.state.body_start.set(printer.data.len());
+    }
+
+    fn after_handle(&mut self, _printer: &mut StructuredPrinter) {}
+}
+
+/// Writes elements whose class or `epub:type` names a callout type as callouts,
+/// deferring to the wrapped handler for everything else
+struct CalloutHandler {
+    style: CalloutStyle,
+    state: Rc<RenderState>,
+    inner: Box<dyn TagHandler>,
+    kind: Option<String>,
+    start: usize,
+}
+
+impl TagHandler for CalloutHandler {
+    fn handle(&mut self, tag: &Handle, printer: &mut StructuredPrinter) {
+        let attributes: Vec<String> = [html::attr(tag, "class"), html::epub_type(tag)].into_iter().flatten().collect();
+        self.kind = callout::callout_type(&attributes, &self.state.options.callout_classes);
+        if self.kind.is_some() {
+            printer.append_str("\n\n");
+            self.start = printer.data.len();
+        } else {
+            self.inner.handle(tag, printer);
+        }
+    }
+
+    fn after_handle(&mut self, printer: &mut StructuredPrinter) {
+        let Some(kind) = &self.kind else {
+            return self.inner.after_handle(printer);
+        };
+        let content = printer.data[self.start..].trim().to_string();
+        printer.data.truncate(self.start);
+        if !content.is_empty() {
+            printer.append_str(&callout::format(self.style, kind, &content));
+            printer.append_str("\n");
+        }
+    }
+
+    fn skip_descendants(&self) -> bool {
+        self.kind.is_none() && self.inner.skip_descendants()
+    }
+}
+
+/// Handler for inline styles: puts `open` and `close` around the element's rendered
+/// content, inside any surrounding whitespace
+struct Wrap {
+    open: String,
+    close: String,
+    /// Backslash-escape spaces, which Pandoc superscript and subscript can't contain
+    escape_spaces: bool,
+    start: usize,
+}
+
+impl Wrap {
+    fn new(open: impl Into<String>, close: impl Into<String>) -> Self {
+        Self { open: open.into(), close: close.into(), escape_spaces: false, start: 0 }
+    }
+
+    fn escaping_spaces(self) -> Self {
+        Self { escape_spaces: true, ..self }
+    }
+}
+
+impl TagHandler for Wrap {
+    fn handle(&mut self, _tag: &Handle, printer: &mut StructuredPrinter) {
+        self.start = printer.data.len();
+    }
+
+    fn after_handle(&mut self, printer: &mut StructuredPrinter) {
+        let rendered = &printer.data[self.start..];
+        let Some(lead) = rendered.find(|c: char| !c.is_whitespace()) else { return };
+        let content = rendered.trim();
+        if self.escape_spaces && content.contains('\n') {
+            return;
+        }
+        let content = if self.escape_spaces { content.replace(' ', "\\ ") } else { content.to_string() };
+        let trailing = rendered[rendered.trim_end().len()..].to_string();
+
+        printer.data.truncate(self.start + lead);
+        printer.append_str(&self.open);
+        printer.append_str(&content);
+        printer.append_str(&self.close);
+        printer.append_str(&trailing);
+    }
+}
+
+/// Definition lists: `Term` / `:   Definition` where the flavor has the syntax,
+/// otherwise a bold term paragraph followed by its definition
+struct DefinitionHandler {
+    tag: &'static str,
+    native: bool,
+    /// Whether this element directly follows a `<dt>`
+    after_term: bool,
+    start: usize,
+}
+
+impl TagHandler for DefinitionHandler {
+    fn handle(&mut self, _tag: &Handle, printer: &mut StructuredPrinter) {
+        let siblings = printer.siblings.get(&printer.parent_chain.len());
+        self.after_term = siblings.and_then(|s| s.last()).is_some_and(|s| s == "dt");
+        printer.append_str("\n\n");
+        self.start = printer.data.len();
+    }
+
+    fn after_handle(&mut self, printer: &mut StructuredPrinter) {
+        let content = printer.data[self.start..].trim().to_string();
+        printer.data.truncate(self.start);
+        if content.is_empty() {
+            return;
+        }
+        match (self.tag, self.native) {
+            ("dt", true) => {
+                printer.append_str(&content);
+                printer.append_str("\n");
+            }
+            ("dt", false) => {
+                printer.append_str(&format!("**{}**", content));
+                printer.append_str("\n\n");
+            }
+            ("dd", true) => {
+                // Directly under the term, with continuation lines indented so
+                // multi-paragraph definitions stay attached
+                if self.after_term {
+                    let term_end = printer.data.trim_end().len();
+                    printer.data.truncate(term_end);
+                    printer.append_str("\n");
+                }
+                for (i, line) in content.lines().enumerate() {
+                    match (i, line.is_empty()) {
+                        (0, _) => printer.append_str(&format!(":   {}", line)),
+                        (_, true) => {}
+                        _ => printer.append_str(&format!("    {}", line)),
+                    }
+                    printer.append_str("\n");
+                }
+            }
+            _ => {
+                printer.append_str(&content);
+                printer.append_str("\n\n");
+            }
+        }
+    }
+}
+
+/// html2md's own handler for a tag we override, to fall back on
+fn builtin(tag: &str) -> Box<dyn TagHandler> {
+    match tag {
+        "div" | "section" | "header" | "footer" => Box::new(html2md::containers::ContainerHandler),
+        // html2md ignores <aside> itself and renders its content inline
+        "aside" => Box::new(html2md::dummy::DummyHandler),
+        "p" | "br" | "hr" => Box::new(html2md::paragraphs::ParagraphHandler::default()),
+        "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => Box::new(html2md::headers::HeaderHandler::default()),
+        "a" => Box::new(html2md::anchors::AnchorHandler::default()),
+        _ => Box::new(html2md::dummy::DummyHandler),
+    }
+}
+
+/// Class names and image file names publishers use for scene-break ornaments
+const SCENE_BREAK_HINTS: &[&str] = &[
+    "scene", "break", "separator", "divider", "ornament", "dinkus", "fleuron", "asterism", "flourish", "transition",
+];
+
+/// Writes the configured marker in place of anything that looks like a scene break,
+/// deferring to html2md's handler for everything else
+struct SceneBreakHandler {
+    marker: String,
+    state: Rc<RenderState>,
+    inner: Box<dyn TagHandler>,
+    replaced: bool,
+}
+
+impl TagHandler for SceneBreakHandler {
+    fn handle(&mut self, tag: &Handle, printer: &mut StructuredPrinter) {
+        let body = printer.data.get(self.state.body_start.get()..).unwrap_or_default().trim_end();
+        // Nothing to separate at the top of a chapter; consecutive breaks count as one
+        if !body.is_empty() && is_scene_break(tag) {
+            self.replaced = true;
+            if !body.ends_with(self.marker.as_str()) {
+                printer.append_str("\n\n");
+                printer.append_str(&self.marker);
+                printer.append_str("\n\n");
+            }
+        } else {
+            self.inner.handle(tag, printer);
+        }
+    }
+
+    fn after_handle(&mut self, printer: &mut StructuredPrinter) {
+        if !self.replaced {
+            self.inner.after_handle(printer);
+        }
+    }
+
+    fn skip_descendants(&self) -> bool {
+        self.replaced || self.inner.skip_descendants()
+    }
+}
+
+/// Whether an element is a scene break: a rule, an empty heading, a paragraph of
+/// ornamental symbols (`* * *`, `⁂`, `~`), or an ornament image or spacer marked as one
+pub fn is_scene_break(node: &Handle) -> bool {
+    let Some(tag) = html::tag_name(node) else { return false };
+    if tag == "hr" {
+        return true;
+    }
+
+    let text = html::text_content(node).replace('\u{A0}', " ");
+    let text = text.trim();
+    if text.chars().any(char::is_alphanumeric) || text.chars().count() > 20 {
+        return false;
+    }
+    let mut images = Vec::new();
+    let mut nested_blocks = 0;
+    for child in node.children.borrow().iter() {
+        html::walk(child, &mut |n| match html::tag_name(n).as_deref() {
+            Some("img" | "image") => images.push(n.clone()),
+            Some("p" | "div" | "table" | "ul" | "ol" | "blockquote" | "figure") => nested_blocks += 1,
+            _ => {}
+        });
+    }
+    // A container of several blocks is judged one block at a time
+    if nested_blocks > 1 {
+        return false;
+    }
+
+    let hinted = |value: Option<String>| {
+        value.is_some_and(|v| {
+            let v = v.to_ascii_lowercase();
+            // `pagebreak` and friends come from the print layout, not the story
+            !v.contains("page") && SCENE_BREAK_HINTS.iter().any(|h| v.contains(h))
+        })
+    };
+    let class_hint = hinted(html::attr(node, "class"));
+
+    if let Some(image) = images.first() {
+        // A picture on its own is an illustration unless it's marked as an ornament
+        let alt = html::attr(image, "alt").unwrap_or_default();
+        let src = html::attr(image, "src").or_else(|| html::attr(image, "href"));
+        let symbolic_alt = !alt.trim().is_empty() && !alt.chars().any(char::is_alphanumeric);
+        return images.len() == 1 && text.is_empty() && (class_hint || hinted(src) || hinted(html::attr(image, "class")) || symbolic_alt);
+    }
+    if tag.starts_with('h') {
+        // An empty heading, or one holding only symbols
+        return true;
+    }
+    // Symbols alone, or an empty spacer marked as a break
+    !text.is_empty() || class_hint
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(html: &str, page_markers: bool) -> String {
+        let options = RenderOptions { page_markers, ..Default::default() };
+        to_markdown(&format!("<html><body>{}</body></html>", html), &options).markdown
+    }
+
+    #[test]
+    fn self_closing_page_marker_keeps_the_text_after_it() {
+        let html = r#"<p>Text <span epub:type="pagebreak" id="page12" title="12"/>goes on after the break.</p>"#;
+        let marked = render(html, true);
+        assert!(marked.contains(r#"<a id="page12"></a>"#), "{}", marked);
+        assert!(marked.contains("goes on after the break."), "{}", marked);
+        assert!(render(html, false).contains("goes on after the break."));
+    }
+
+    #[test]
+    fn empty_page_marker_drops_only_its_number() {
+        let html = r#"<p>Before <span role="doc-pagebreak" id="p7" aria-label="7">7</span> after. <span epub:type="pagebreak" title="8"></span>End.</p>"#;
+        let marked = render(html, true);
+        assert!(marked.contains(r#"<a id="p7"></a>"#), "{}", marked);
+        assert!(marked.contains(r#"<a id="page-8"></a>"#), "{}", marked);
+        assert!(!marked.replace(r#"<a id="p7"></a>"#, "").contains('7'), "{}", marked);
+        assert!(marked.contains("Before") && marked.contains("after.") && marked.contains("End."), "{}", marked);
+    }
+}
@@ -0,0 +1,154 @@
+use crate::archive::Resources;
+use crate::check::ScratchDir;
+use crate::error::ConvertError;
+use crate::layout;
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::io::{BufReader, Write};
+use std::path::{Path, PathBuf};
+use xml::reader::{EventReader, XmlEvent};
+use zip::write::SimpleFileOptions;
+use zip::{ZipArchive, ZipWriter};
+
+const CONTAINER: &str = "META-INF/container.xml";
+
+/// One package document listed in `container.xml`
+#[derive(Debug, Clone)]
+pub struct Rendition {
+    /// Path of the package document inside the archive
+    pub path: String,
+    /// `rendition:label`, if given
+    pub label: Option<String>,
+    /// `rendition:language`, if given
+    pub language: Option<String>,
+    /// Whether the rendition is fixed layout, from `rendition:layout` on the rootfile or
+    /// in the package document
+    pub fixed: bool,
+}
+
+impl Rendition {
+    /// Short description for progress lines and errors
+    pub fn describe(&self) -> String {
+        let mut parts = vec![if self.fixed { "fixed layout" } else { "reflowable" }.to_string()];
+        parts.extend(self.language.clone());
+        parts.extend(self.label.as_ref().map(|l| format!("\"{}\"", l)));
+        format!("{} ({})", self.path, parts.join(", "))
+    }
+
+    /// Whether `--rendition` names this rendition, other than by position
+    fn matches(&self, selector: &str) -> bool {
+        let selector = selector.trim();
+        let language = self.language.as_deref().unwrap_or_default().to_ascii_lowercase();
+        let wanted = selector.to_ascii_lowercase();
+        self.path == selector
+            || self.label.as_deref().is_some_and(|l| l.eq_ignore_ascii_case(selector))
+            || (wanted == "reflowable" && !self.fixed)
+            || (matches!(wanted.as_str(), "fixed" | "pre-paginated") && self.fixed)
+            || (!language.is_empty() && (language == wanted || language.starts_with(&format!("{}-", wanted))))
+    }
+}
+
+/// The renditions in an EPUB's `container.xml`, in the order listed
+pub fn list(resources: &mut Resources) -> Result<Vec<Rendition>> {
+    let container = resources
+        .read_string(CONTAINER)
+        .and_then(|xml| xml.context("not in the archive"))
+        .context("Failed to read META-INF/container.xml")?;
+
+    let mut renditions = Vec::new();
+    for event in EventReader::new(container.as_bytes()).into_iter().flatten() {
+        let XmlEvent::StartElement { name, attributes, .. } = event else { continue };
+        if name.local_name != "rootfile" {
+            continue;
+        }
+        let attr = |key: &str| {
+            attributes
+                .iter()
+                .find(|a| a.name.local_name == key && (key == "full-path" || a.name.prefix.as_deref() == Some("rendition")))
+                .map(|a| a.value.trim().to_string())
+                .filter(|v| !v.is_empty())
+        };
+        let Some(path) = attr("full-path") else { continue };
+        let fixed = match attr("layout") {
+            Some(layout) => layout == "pre-paginated",
+            None => resources.read_string(&path)?.is_some_and(|opf| layout::pre_paginated(&opf)),
+        };
+        renditions.push(Rendition { path, label: attr("label"), language: attr("language"), fixed });
+    }
+    Ok(renditions)
+}
+
+/// Which rendition to convert: the one `selector` names (a 1-based position, package
+/// path, label, language, `reflowable` or `fixed`), or by default the first reflowable one
+pub fn choose(renditions: &[Rendition], selector: Option<&str>) -> Result<usize> {
+    let Some(selector) = selector else {
+        return Ok(renditions.iter().position(|r| !r.fixed).unwrap_or(0));
+    };
+    let by_position = selector.trim().parse::<usize>().ok().filter(|&n| n >= 1 && n <= renditions.len()).map(|n| n - 1);
+    if let Some(index) = by_position.or_else(|| renditions.iter().position(|r| r.matches(selector))) {
+        return Ok(index);
+    }
+    let available: Vec<String> = renditions.iter().enumerate().map(|(i, r)| format!("{}. {}", i + 1, r.describe())).collect();
+    anyhow::bail!("No rendition matches '{}'; the book has {}", selector, available.join("; "))
+}
+
+/// The EPUB to read a book's chosen rendition from
+pub struct Selected {
+    /// The rendition converted, when the book has several or one was asked for
+    pub rendition: Option<Rendition>,
+    /// Number of renditions in the book
+    pub count: usize,
+    /// Copy of the EPUB with the chosen rendition listed alone, when it isn't the first
+    copy: Option<(ScratchDir, PathBuf)>,
+}
+
+impl Selected {
+    /// Path to open: the original EPUB, or the copy made for a later rendition
+    pub fn path<'a>(&'a self, epub_path: &'a Path) -> &'a Path {
+        self.copy.as_ref().map(|(_, path)| path.as_path()).unwrap_or(epub_path)
+    }
+}
+
+/// Pick the rendition of `epub_path` to convert. EPUB readers (and the epub crate) use the
+/// first rootfile, so any other is converted from a copy whose `container.xml` lists only
+/// that one; entries are copied without being recompressed. `resources` is switched over
+/// to the copy.
+pub fn select(epub_path: &Path, resources: &mut Resources, selector: Option<&str>) -> Result<Selected> {
+    let renditions = list(resources)?;
+    if renditions.len() <= 1 && selector.is_none() {
+        return Ok(Selected { rendition: None, count: renditions.len(), copy: None });
+    }
+    let index = choose(&renditions, selector)?;
+    let rendition = renditions[index].clone();
+    let copy = if index == 0 { None } else { Some(copy_with_rootfile(epub_path, &rendition.path)?) };
+    if let Some((_, path)) = &copy {
+        resources.reopen(path)?;
+    }
+    Ok(Selected { rendition: Some(rendition), count: renditions.len(), copy })
+}
+
+fn copy_with_rootfile(epub_path: &Path, rootfile: &str) -> Result<(ScratchDir, PathBuf)> {
+    let scratch = ScratchDir::new("rendition")?;
+    let target = scratch.path().join(epub_path.file_name().unwrap_or_default());
+
+    let file = File::open(epub_path).context("Failed to open EPUB file")?;
+    let mut zip = ZipArchive::new(BufReader::new(file)).map_err(ConvertError::from).context("Failed to read EPUB archive")?;
+    let mut writer = ZipWriter::new(File::create(&target).context("Failed to create the rendition copy")?);
+    for i in 0..zip.len() {
+        let entry = zip.by_index_raw(i).map_err(ConvertError::from).context("Failed to read EPUB archive")?;
+        if entry.name() == CONTAINER {
+            continue;
+        }
+        writer.raw_copy_file(entry).context("Failed to write the rendition copy")?;
+    }
+    let escaped = rootfile.replace('&', "&amp;").replace('"', "&quot;").replace('<', "&lt;");
+    writer.start_file(CONTAINER, SimpleFileOptions::default()).context("Failed to write the rendition copy")?;
+    write!(
+        writer,
+        "<?xml version=\"1.0\"?>\n<container version=\"1.0\" xmlns=\"urn:oasis:names:tc:opendocument:xmlns:container\">\n  <rootfiles>\n    <rootfile full-path=\"{}\" media-type=\"application/oebps-package+xml\"/>\n  </rootfiles>\n</container>\n",
+        escaped
+    )
+    .context("Failed to write the rendition copy")?;
+    writer.finish().context("Failed to write the rendition copy")?;
+    Ok((scratch, target))
+}
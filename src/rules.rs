@@ -0,0 +1,320 @@
+use crate::config::{self, Table, Value};
+use crate::html;
+use anyhow::{Context, Result};
+use markup5ever_rcdom::Handle;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+/// Publisher value that makes a pack apply to every book
+const ANY_PUBLISHER: &str = "*";
+
+/// Inline styles a class can be mapped to, written as the element that has that style
+const STYLES: &[(&str, &str)] = &[
+    ("emphasis", "em"),
+    ("strong", "strong"),
+    ("code", "code"),
+    ("strikethrough", "del"),
+    ("underline", "u"),
+    ("highlight", "mark"),
+    ("superscript", "sup"),
+    ("subscript", "sub"),
+];
+
+/// A simple CSS selector: an optional tag name (or `*`) followed by any number of
+/// `.class`, `#id`, `[attr]`, `[attr=value]` and `[attr~=value]` tests, all of which
+/// must hold. There are no combinators; a rule sees one element at a time.
+#[derive(Debug, Clone)]
+pub struct Selector {
+    tag: Option<String>,
+    tests: Vec<Test>,
+}
+
+#[derive(Debug, Clone)]
+enum Test {
+    Class(String),
+    Id(String),
+    Has(String),
+    Equals(String, String),
+    Contains(String, String),
+}
+
+impl Selector {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let invalid = || format!("invalid selector '{}'", text);
+        let text = text.trim();
+        let name_end = |s: &str| s.find(|c: char| !(c.is_alphanumeric() || matches!(c, '-' | '_' | ':'))).unwrap_or(s.len());
+
+        let tag_len = name_end(text);
+        let tag = Some(text[..tag_len].to_ascii_lowercase()).filter(|t| !t.is_empty());
+        let mut rest = text.strip_prefix('*').unwrap_or(&text[tag_len..]);
+        let mut tests = Vec::new();
+        while let Some(c) = rest.chars().next() {
+            match c {
+                '.' | '#' => {
+                    let len = name_end(&rest[1..]);
+                    let name = &rest[1..1 + len];
+                    if name.is_empty() {
+                        return Err(invalid());
+                    }
+                    tests.push(if c == '.' { Test::Class(name.to_string()) } else { Test::Id(name.to_string()) });
+                    rest = &rest[1 + len..];
+                }
+                '[' => {
+                    let end = rest.find(']').ok_or_else(invalid)?;
+                    let inner = &rest[1..end];
+                    let test = if let Some((name, value)) = inner.split_once("~=") {
+                        Test::Contains(name.trim().to_string(), unquote(value))
+                    } else if let Some((name, value)) = inner.split_once('=') {
+                        Test::Equals(name.trim().to_string(), unquote(value))
+                    } else {
+                        Test::Has(inner.trim().to_string())
+                    };
+                    tests.push(test);
+                    rest = &rest[end + 1..];
+                }
+                _ => return Err(invalid()),
+            }
+        }
+        if tag.is_none() && tests.is_empty() && !text.starts_with('*') {
+            return Err(invalid());
+        }
+        Ok(Self { tag, tests })
+    }
+
+    pub fn matches(&self, node: &Handle) -> bool {
+        let Some(tag) = html::tag_name(node) else { return false };
+        if self.tag.as_ref().is_some_and(|t| *t != tag) {
+            return false;
+        }
+        let classes = html::attr(node, "class").unwrap_or_default();
+        self.tests.iter().all(|test| match test {
+            Test::Class(class) => classes.split_whitespace().any(|c| c == class),
+            Test::Id(id) => html::attr(node, "id").as_deref() == Some(id.as_str()),
+            Test::Has(name) => html::attr(node, name).is_some(),
+            Test::Equals(name, value) => html::attr(node, name).as_deref() == Some(value.as_str()),
+            Test::Contains(name, value) => html::attr(node, name).is_some_and(|v| v.split_whitespace().any(|t| t == value)),
+        })
+    }
+}
+
+fn unquote(value: &str) -> String {
+    value.trim().trim_matches(|c| c == '"' || c == '\'').to_string()
+}
+
+/// Cleanup rules for the books of one publisher (`[rules.<name>]` in a rules file)
+#[derive(Debug, Clone)]
+pub struct RulePack {
+    pub name: String,
+    /// Publisher names the pack applies to, matched case-insensitively as substrings
+    /// of the book's `publisher`; `*` matches every book
+    publishers: Vec<String>,
+    /// Elements removed along with their content
+    strip: Vec<Selector>,
+    /// Class names and the inline style elements with them get
+    styles: Vec<(String, &'static str)>,
+    /// Elements that are scene breaks
+    scene_breaks: Vec<Selector>,
+    /// Paragraph texts that are scene breaks
+    scene_break_text: Vec<String>,
+    /// Elements that are headings, and their level
+    headings: Vec<(Selector, u8)>,
+}
+
+impl RulePack {
+    fn from_table(table: Table, path: &Path) -> Result<Self> {
+        let mut pack = RulePack {
+            name: table.name,
+            publishers: Vec::new(),
+            strip: Vec::new(),
+            styles: Vec::new(),
+            scene_breaks: Vec::new(),
+            scene_break_text: Vec::new(),
+            headings: Vec::new(),
+        };
+        for (key, value, line) in table.entries {
+            let location = || format!("{}:{}: rules '{}'", path.display(), line, pack.name);
+            let strings = strings(value).with_context(|| format!("{}: '{}' takes a string or an array of strings", location(), key))?;
+            let selectors = || -> Result<Vec<Selector>> {
+                strings.iter().flat_map(|s| s.split(',')).map(|s| Selector::parse(s).map_err(|e| anyhow::anyhow!("{}: {}", location(), e))).collect()
+            };
+            match key.as_str() {
+                "publisher" => pack.publishers = strings.iter().map(|p| fold(p)).collect(),
+                "strip" => pack.strip = selectors()?,
+                "scene-breaks" => pack.scene_breaks = selectors()?,
+                "scene-break-text" => pack.scene_break_text = strings.iter().map(|t| fold(t)).collect(),
+                "headings" => {
+                    for mapping in &strings {
+                        let (selector, level) = mapping
+                            .rsplit_once('=')
+                            .filter(|(_, level)| matches!(level.trim().parse::<u8>(), Ok(1..=6)))
+                            .with_context(|| format!("{}: invalid heading mapping '{}' (expected SELECTOR=LEVEL, with a level from 1 to 6)", location(), mapping))?;
+                        let selector = Selector::parse(selector).map_err(|e| anyhow::anyhow!("{}: {}", location(), e))?;
+                        pack.headings.push((selector, level.trim().parse().expect("checked above")));
+                    }
+                }
+                "styles" => {
+                    for mapping in &strings {
+                        let (class, style) = mapping
+                            .split_once('=')
+                            .with_context(|| format!("{}: invalid style mapping '{}' (expected CLASS=STYLE)", location(), mapping))?;
+                        let Some(&(_, tag)) = STYLES.iter().find(|(name, _)| *name == style.trim()) else {
+                            let names: Vec<&str> = STYLES.iter().map(|(name, _)| *name).collect();
+                            anyhow::bail!("{}: unknown style '{}' (one of {})", location(), style.trim(), names.join(", "));
+                        };
+                        pack.styles.push((class.trim().to_string(), tag));
+                    }
+                }
+                _ => anyhow::bail!("{}: unknown key '{}'", location(), key),
+            }
+        }
+        if pack.publishers.is_empty() {
+            anyhow::bail!("{}: rules '{}' don't say which publisher they are for (set `publisher`, or \"*\" for every book)", path.display(), pack.name);
+        }
+        Ok(pack)
+    }
+
+    fn applies_to(&self, publisher: &str) -> bool {
+        let publisher = fold(publisher);
+        self.publishers.iter().any(|p| p == ANY_PUBLISHER || (!publisher.is_empty() && publisher.contains(p.as_str())))
+    }
+}
+
+/// A string value, or an array of them, as a list
+fn strings(value: Value) -> Result<Vec<String>> {
+    match value {
+        Value::String(s) => Ok(vec![s]),
+        Value::Array(items) => items
+            .into_iter()
+            .map(|item| match item {
+                Value::String(s) => Ok(s),
+                _ => Err(anyhow::anyhow!("not a string")),
+            })
+            .collect(),
+        _ => Err(anyhow::anyhow!("not a string")),
+    }
+}
+
+/// Lowercase with whitespace collapsed and typographic apostrophes made plain, for
+/// matching publisher names and paragraph texts however they were typed
+fn fold(text: &str) -> String {
+    text.replace(['\u{2019}', '\u{2018}'], "'").replace('\u{A0}', " ").to_lowercase().split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Every rule pack available to a run
+#[derive(Debug, Clone, Default)]
+pub struct RulePacks(Vec<RulePack>);
+
+impl RulePacks {
+    /// Load the rules files given with `--rules` (files, or directories of `.toml` files),
+    /// then unless `no_defaults` those in `$XDG_CONFIG_HOME/epub-to-md/rules/`
+    pub fn load(paths: &[PathBuf], no_defaults: bool) -> Result<Self> {
+        let mut files = Vec::new();
+        for path in paths {
+            if path.is_dir() {
+                files.extend(toml_files(path)?);
+            } else if path.is_file() {
+                files.push(path.clone());
+            } else {
+                anyhow::bail!("Rules file {} does not exist", path.display());
+            }
+        }
+        if !no_defaults {
+            if let Some(dir) = config::config_dir().map(|d| d.join("rules")).filter(|d| d.is_dir()) {
+                files.extend(toml_files(&dir)?);
+            }
+        }
+
+        let mut packs: Vec<RulePack> = Vec::new();
+        for file in files {
+            let text = fs::read_to_string(&file).with_context(|| format!("Failed to read rules file {}", file.display()))?;
+            for table in config::parse_tables(&text, &file)?.into_iter().filter(|t| t.kind == "rules") {
+                // The first definition of a name wins, so --rules can override the defaults
+                if !packs.iter().any(|p| p.name == table.name) {
+                    packs.push(RulePack::from_table(table, &file)?);
+                }
+            }
+        }
+        Ok(Self(packs))
+    }
+
+    /// The packs for a book from `publisher`
+    pub fn for_publisher(&self, publisher: Option<&str>) -> Vec<&RulePack> {
+        self.0.iter().filter(|pack| pack.applies_to(publisher.unwrap_or_default())).collect()
+    }
+}
+
+fn toml_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files: Vec<PathBuf> = fs::read_dir(dir)
+        .with_context(|| format!("Failed to read rules directory {}", dir.display()))?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "toml") && path.is_file())
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+/// Chapter XHTML with the packs' rules applied: stripped elements removed, scene breaks
+/// turned into `<hr/>`, heading elements retagged `<h1>`-`<h6>`, and elements with a
+/// mapped class wrapped in that style's element
+pub fn apply(content: &str, packs: &[&RulePack]) -> String {
+    let dom = html::parse(content);
+    rewrite(&dom.document, packs);
+    html::serialize(&dom)
+}
+
+fn rewrite(node: &Handle, packs: &[&RulePack]) {
+    let children: Vec<Handle> = node.children.take();
+    let mut kept = Vec::with_capacity(children.len());
+    for child in children {
+        if packs.iter().any(|pack| pack.strip.iter().any(|s| s.matches(&child))) {
+            continue;
+        }
+        if is_scene_break(&child, packs) {
+            let rule = html::element("hr", &[]);
+            rule.parent.set(Some(Rc::downgrade(node)));
+            kept.push(rule);
+            continue;
+        }
+        rewrite(&child, packs);
+        let level = packs.iter().flat_map(|pack| &pack.headings).find(|(s, _)| s.matches(&child)).map(|(_, level)| *level);
+        let child = match level {
+            Some(level) if html::tag_name(&child).as_deref() != Some(heading_tag(level)) => html::retag(&child, heading_tag(level)),
+            _ => child,
+        };
+        let classes = html::attr(&child, "class").unwrap_or_default();
+        let style = packs
+            .iter()
+            .flat_map(|pack| &pack.styles)
+            .find(|(class, _)| classes.split_whitespace().any(|c| c == class))
+            .map(|(_, tag)| *tag);
+        if let Some(tag) = style.filter(|tag| html::tag_name(&child).as_deref() != Some(tag)) {
+            let wrapper = html::element(tag, &[]);
+            let inner: Vec<Handle> = child.children.take();
+            for grandchild in &inner {
+                grandchild.parent.set(Some(Rc::downgrade(&wrapper)));
+            }
+            *wrapper.children.borrow_mut() = inner;
+            wrapper.parent.set(Some(Rc::downgrade(&child)));
+            child.children.borrow_mut().push(wrapper);
+        }
+        kept.push(child);
+    }
+    *node.children.borrow_mut() = kept;
+}
+
+fn heading_tag(level: u8) -> &'static str {
+    ["h1", "h2", "h3", "h4", "h5", "h6"][usize::from(level.clamp(1, 6)) - 1]
+}
+
+fn is_scene_break(node: &Handle, packs: &[&RulePack]) -> bool {
+    if html::tag_name(node).is_none() {
+        return false;
+    }
+    packs.iter().any(|pack| {
+        pack.scene_breaks.iter().any(|s| s.matches(node))
+            || (!pack.scene_break_text.is_empty()
+                && matches!(html::tag_name(node).as_deref(), Some("p" | "div"))
+                && pack.scene_break_text.contains(&fold(&html::text_content(node))))
+    })
+}
@@ -0,0 +1,81 @@
+use crate::archive::{ResourceLimits, Resources};
+use crate::html;
+use crate::isolate;
+use anyhow::{Context, Result};
+use epub::doc::EpubDoc;
+use std::io::{Read, Seek};
+use std::path::Path;
+
+/// Phrases retailers put on the last page of a preview
+const END_OF_SAMPLE: &[&str] = &[
+    "end of sample",
+    "end of this sample",
+    "end of the sample",
+    "end of the free sample",
+    "end of preview",
+    "end of this preview",
+    "end of the preview",
+    "you have reached the end of the sample",
+    "you've reached the end of the sample",
+    "enjoyed this sample",
+    "enjoy this sample",
+    "hope you enjoyed this preview",
+    "buy the full book",
+    "purchase the full book",
+    "buy the complete book",
+    "to continue reading, ",
+];
+
+/// Words in a title that mark the book as a preview
+const TITLE_MARKERS: &[&str] = &["sample", "preview", "excerpt"];
+
+/// Spine documents read from the end when looking for an end-of-sample page
+const FINAL_DOCUMENTS: usize = 3;
+
+/// At most this many spine documents makes a book tiny
+const TINY_SPINE: usize = 3;
+
+/// Why a book looks like a retailer sample rather than the full text, if it does: its
+/// title says sample or preview, one of its last pages reads "end of sample" or asks you
+/// to buy the book, or its spine is tiny and its last page mentions a sample or preview
+pub fn detect<R: Read + Seek>(doc: &EpubDoc<R>, resources: &mut Resources) -> Result<Option<String>> {
+    if let Some(title) = doc.mdata("title").map(|m| m.value.to_lowercase()) {
+        let words: Vec<&str> = title.split(|c: char| !c.is_alphanumeric()).collect();
+        if let Some(marker) = TITLE_MARKERS.iter().find(|m| words.contains(m)) {
+            return Ok(Some(format!("the title says \"{}\"", marker)));
+        }
+    }
+
+    let tiny = doc.spine.len() <= TINY_SPINE;
+    for (i, item) in doc.spine.iter().rev().take(FINAL_DOCUMENTS).enumerate() {
+        let Some(resource) = doc.resources.get(&item.idref) else { continue };
+        let Some(content) = resources.read_string(&resource.path.to_string_lossy())? else { continue };
+        let text = normalize_text(&html::text_content(&html::parse(&content).document));
+        if let Some(phrase) = END_OF_SAMPLE.iter().find(|p| text.contains(*p)) {
+            return Ok(Some(format!("a final page reads \"{}\"", phrase.trim_end_matches([',', ' ']))));
+        }
+        if tiny && i == 0 && (text.contains("sample") || text.contains("preview")) {
+            return Ok(Some(format!("only {} spine document(s), the last mentioning a sample or preview", doc.spine.len())));
+        }
+    }
+    Ok(None)
+}
+
+/// `detect` for a file, isolated like a conversion so a broken EPUB just isn't a sample.
+/// Reads are held to `limits`; a book over them is left for the conversion to refuse.
+pub fn detect_file(epub_path: &Path, limits: ResourceLimits) -> Option<String> {
+    let path = epub_path.to_path_buf();
+    isolate::run_isolated(None, &isolate::CancelToken::default(), move || {
+        limits.check_archive(&path)?;
+        let doc = EpubDoc::new(&path).context("Failed to open EPUB file")?;
+        detect(&doc, &mut Resources::open(&path, limits)?)
+    })
+    .ok()
+    .flatten()
+}
+
+/// Lowercase text with runs of whitespace collapsed and typographic apostrophes made
+/// plain, so phrases match however the page was typeset
+fn normalize_text(text: &str) -> String {
+    text.replace(['\u{2019}', '\u{2018}'], "'").to_lowercase().split_whitespace().collect::<Vec<_>>().join(" ")
+}
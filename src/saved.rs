@@ -0,0 +1,52 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::fs;
+use std::path::Path;
+
+/// A converted book's metadata.json, as read back for batch-level outputs
+#[derive(Debug, Deserialize)]
+pub struct SavedBook {
+    pub title: Option<String>,
+    #[serde(default)]
+    pub creators: Vec<String>,
+    pub language: Option<String>,
+    pub description: Option<String>,
+    #[serde(default)]
+    pub subjects: Vec<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub identifier: Option<String>,
+    pub publisher: Option<String>,
+    pub date: Option<String>,
+    pub rights: Option<String>,
+    #[serde(default)]
+    pub contributors: Vec<String>,
+    pub series: Option<String>,
+    pub series_index: Option<f64>,
+    pub word_count: Option<usize>,
+    #[serde(default)]
+    pub chapters: Vec<SavedChapter>,
+    /// Only counted, so their contents aren't read
+    #[serde(default)]
+    pub warnings: Vec<serde::de::IgnoredAny>,
+    /// Set when the book looks like a retailer sample
+    pub sample: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SavedChapter {
+    pub file: String,
+    /// Path of the XHTML file inside the EPUB
+    #[serde(default)]
+    pub source: String,
+}
+
+impl SavedBook {
+    pub fn load(output_dir: &Path) -> Result<Self> {
+        let path = output_dir.join("metadata.json");
+        let text = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        serde_json::from_str(&text)
+            .with_context(|| format!("Failed to parse {}", path.display()))
+    }
+}
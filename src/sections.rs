@@ -0,0 +1,161 @@
+use crate::archive::Resources;
+use crate::html;
+use crate::references;
+use crate::toc;
+use anyhow::Result;
+use clap::ValueEnum;
+use epub::doc::EpubDoc;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::io::{Read, Seek};
+use std::path::Path;
+use xml::reader::{EventReader, XmlEvent};
+
+/// Kinds of book section that get special handling
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, ValueEnum, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Section {
+    /// The back-of-book index
+    Index,
+    /// A document that is entirely a bibliography or reference list
+    Bibliography,
+    /// A table-of-contents page duplicating the navigation; skipped unless `--keep-toc-page`
+    #[value(skip)]
+    Toc,
+}
+
+/// What was done with a special section, as recorded in metadata.json
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Handling {
+    /// Left as converted
+    Kept,
+    /// Entries relinked to the converted chapters
+    Linked,
+    /// Left out of the output (`--skip`)
+    Skipped,
+}
+
+/// A special section found in the book
+#[derive(Debug, Clone, Serialize)]
+pub struct SectionReport {
+    pub section: Section,
+    /// Path of the XHTML file inside the EPUB
+    pub source: String,
+    pub handling: Handling,
+    /// Links rewritten to point at the converted chapters
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub links: Option<usize>,
+}
+
+/// Which special section a spine document is, if any. An index is marked with
+/// `epub:type="index"` (or `role="doc-index"`) or headed "Index"; a bibliography has no text
+/// outside its bibliography sections (see `references`).
+pub fn detect(content: &str) -> Option<Section> {
+    // Most chapters are neither, so skip the parse for them
+    let maybe_index = content.to_ascii_lowercase().contains("index");
+    if !maybe_index && !references::might_have_bibliography(content) {
+        return None;
+    }
+    let dom = html::parse(content);
+    let mut marked = false;
+    let mut first_heading: Option<String> = None;
+    html::walk(&dom.document, &mut |node| {
+        let Some(tag) = html::tag_name(node) else { return };
+        if matches!(tag.as_str(), "body" | "section" | "nav" | "div") {
+            let typed = html::epub_type(node).is_some_and(|t| t.split_whitespace().any(|t| t == "index"));
+            marked |= typed || html::attr(node, "role").as_deref() == Some("doc-index");
+        }
+        if first_heading.is_none() && matches!(tag.as_str(), "h1" | "h2" | "h3") {
+            first_heading = Some(html::text_content(node));
+        }
+    });
+    let headed = first_heading.is_some_and(|h| h.trim().eq_ignore_ascii_case("index"));
+    if maybe_index && (marked || headed) {
+        Some(Section::Index)
+    } else if references::is_bibliography(&dom.document) {
+        Some(Section::Bibliography)
+    } else {
+        None
+    }
+}
+
+/// Archive paths of the documents that are tables of contents: the EPUB3 navigation
+/// document (manifest `properties="nav"`) and pages the EPUB2 guide lists as `type="toc"`
+pub fn toc_pages<R: Read + Seek>(doc: &EpubDoc<R>, resources: &mut Resources) -> Result<HashSet<String>> {
+    let mut pages: HashSet<String> = doc
+        .resources
+        .values()
+        .filter(|item| item.properties.as_deref().is_some_and(|p| p.split_whitespace().any(|p| p == "nav")))
+        .map(|item| toc::target_path(&item.path))
+        .collect();
+
+    let root_file = &doc.root_file;
+    let Some(opf) = resources.read_string(&root_file.to_string_lossy())? else { return Ok(pages) };
+    let base = root_file.parent().unwrap_or(Path::new(""));
+    let mut in_guide = false;
+    for event in EventReader::new(opf.as_bytes()).into_iter().flatten() {
+        match event {
+            XmlEvent::StartElement { name, .. } if name.local_name == "guide" => in_guide = true,
+            XmlEvent::EndElement { name } if name.local_name == "guide" => in_guide = false,
+            XmlEvent::StartElement { name, attributes, .. } if in_guide && name.local_name == "reference" => {
+                let attr = |key: &str| attributes.iter().find(|a| a.name.local_name == key).map(|a| a.value.as_str());
+                if let (Some("toc"), Some(href)) = (attr("type"), attr("href")) {
+                    pages.insert(toc::target_path(&base.join(href)));
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(pages)
+}
+
+/// Output location of a converted spine document
+#[derive(Debug, Clone, Default)]
+pub struct LinkTarget {
+    /// Markdown file the document was written to; empty when it is the file being linked from
+    pub file: String,
+    /// Anchors (page markers) present in that file for this document
+    pub anchors: HashSet<String>,
+}
+
+/// Point an index's links (`[12](ch02.xhtml#page_12)`) at the converted chapter files,
+/// keeping the fragment when it names a page-marker anchor there. Links to documents
+/// not yet converted are left alone. Returns the new Markdown and the number of links rewritten.
+pub fn link_index(markdown: &str, source: &str, targets: &HashMap<String, LinkTarget>) -> (String, usize) {
+    let base = Path::new(source).parent().unwrap_or(Path::new(""));
+    let mut out = String::with_capacity(markdown.len());
+    let mut linked = 0;
+    let mut rest = markdown;
+    while let Some(i) = rest.find("](") {
+        let (before, after) = rest.split_at(i + 2);
+        out.push_str(before);
+        let Some(end) = after.find(|c: char| c == ')' || c.is_whitespace()) else {
+            rest = after;
+            continue;
+        };
+        let href = &after[..end];
+        let (path, fragment) = href.split_once('#').unwrap_or((href, ""));
+        let external = href.contains("://") || href.starts_with("mailto:");
+        let target = (!external && !path.is_empty())
+            .then(|| targets.get(&toc::target_path(&base.join(path))))
+            .flatten();
+        let anchored = target.is_some_and(|t| t.anchors.contains(fragment));
+        match target {
+            // In a combined file the anchor alone is enough, and without one there is nothing to point at
+            Some(target) if target.file.is_empty() && !anchored => out.push_str(href),
+            Some(target) => {
+                out.push_str(&target.file.replace(' ', "%20"));
+                if anchored {
+                    out.push('#');
+                    out.push_str(fragment);
+                }
+                linked += 1;
+            }
+            None => out.push_str(href),
+        }
+        rest = &after[end..];
+    }
+    out.push_str(rest);
+    (out, linked)
+}
@@ -0,0 +1,220 @@
+/// Abbreviations that end in a period without ending the sentence
+const ABBREVIATIONS: &[&str] = &[
+    "mr", "mrs", "ms", "dr", "prof", "sr", "jr", "st", "mt", "vs", "etc", "e.g", "i.e", "cf",
+    "no", "vol", "ch", "fig", "p", "pp", "ed", "eds", "gen", "col", "capt", "lt", "rev",
+];
+
+/// Terminators that end a sentence only when followed by whitespace
+const SPACED_TERMINATORS: &[char] = &['.', '!', '?', '…', '‼', '⁇', '⁈', '⁉'];
+
+/// Full-width terminators (CJK) that end a sentence even without following whitespace
+const FULLWIDTH_TERMINATORS: &[char] = &['。', '！', '？', '｡'];
+
+/// Closing quotes and brackets that belong to the sentence they follow
+const CLOSERS: &[char] = &[
+    '"', '\'', '”', '’', '»', '›', ')', ']', '}', '」', '』', '）', '】', '〉', '》', '*', '_',
+];
+
+/// Rewrite Markdown so every sentence in a paragraph sits on its own line.
+/// Headings, code blocks, tables and other structural lines are left untouched.
+pub fn sentence_per_line(markdown: &str) -> String {
+    let mut out = String::with_capacity(markdown.len() + markdown.len() / 16);
+    let mut in_fence = false;
+
+    for line in markdown.lines() {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_fence = !in_fence;
+        }
+        if in_fence || is_structural(line) {
+            out.push_str(line);
+            out.push('\n');
+            continue;
+        }
+
+        let (prefix, body) = split_prefix(line);
+        let continuation = continuation_prefix(prefix);
+        for (i, sentence) in split_sentences(body).iter().enumerate() {
+            out.push_str(if i == 0 { prefix } else { &continuation });
+            out.push_str(sentence);
+            out.push('\n');
+        }
+    }
+
+    if !markdown.ends_with('\n') {
+        out.pop();
+    }
+    out
+}
+
+/// Lines whose layout is meaningful and must not be re-flowed
+fn is_structural(line: &str) -> bool {
+    let trimmed = line.trim_start();
+    trimmed.is_empty()
+        || line.starts_with("    ")
+        || line.starts_with('\t')
+        || trimmed.starts_with('#')
+        || trimmed.starts_with('|')
+        || trimmed.starts_with('<')
+        || trimmed.starts_with("![")
+        || trimmed.chars().all(|c| matches!(c, '-' | '=' | '*' | '_' | ' '))
+}
+
+/// Split a line into its block prefix (blockquote markers, list marker) and text
+fn split_prefix(line: &str) -> (&str, &str) {
+    let bytes = line.as_bytes();
+    let mut i = 0;
+
+    // Blockquote markers
+    while i < bytes.len() && (bytes[i] == b'>' || bytes[i] == b' ') {
+        i += 1;
+    }
+
+    // One list marker: "-", "*", "+" or "1." followed by a space
+    let rest = &line[i..];
+    if let Some(after) = rest.strip_prefix("- ").or_else(|| rest.strip_prefix("* ")).or_else(|| rest.strip_prefix("+ ")) {
+        i = line.len() - after.len();
+    } else {
+        let digits = rest.bytes().take_while(u8::is_ascii_digit).count();
+        if digits > 0 && rest[digits..].starts_with(". ") {
+            i += digits + 2;
+        }
+    }
+
+    line.split_at(i)
+}
+
+/// Prefix for sentences after the first: quote markers are repeated, list markers become indentation
+fn continuation_prefix(prefix: &str) -> String {
+    let quote_len = prefix.bytes().take_while(|b| *b == b'>' || *b == b' ').count();
+    let (quote, marker) = prefix.split_at(quote_len);
+    format!("{}{}", quote, " ".repeat(marker.chars().count()))
+}
+
+/// Split paragraph text into sentences
+pub fn split_sentences(text: &str) -> Vec<&str> {
+    let chars: Vec<(usize, char)> = text.char_indices().collect();
+    let mut sentences = Vec::new();
+    let mut start = 0;
+    let mut i = 0;
+
+    while i < chars.len() {
+        let (_, c) = chars[i];
+        let fullwidth = FULLWIDTH_TERMINATORS.contains(&c);
+        if !fullwidth && !SPACED_TERMINATORS.contains(&c) {
+            i += 1;
+            continue;
+        }
+
+        // Absorb repeated terminators and closing quotes/brackets
+        let mut end = i + 1;
+        while end < chars.len()
+            && (SPACED_TERMINATORS.contains(&chars[end].1)
+                || FULLWIDTH_TERMINATORS.contains(&chars[end].1)
+                || CLOSERS.contains(&chars[end].1))
+        {
+            end += 1;
+        }
+
+        let followed_by_space = end < chars.len() && chars[end].1.is_whitespace();
+        // A period closing a quotation or bracket ends the sentence, even after a word
+        // such as "no" that could be an abbreviation
+        let closed = chars[i + 1..end].iter().any(|(_, c)| CLOSERS.contains(c));
+        let boundary = if fullwidth {
+            end < chars.len()
+        } else {
+            followed_by_space
+                && !(c == '.' && !closed && is_abbreviation(&text[start..chars[i].0]))
+                && next_starts_sentence(&chars[end..])
+        };
+
+        if boundary {
+            let cut = chars.get(end).map_or(text.len(), |(pos, _)| *pos);
+            let sentence = text[start..cut].trim();
+            if !sentence.is_empty() {
+                sentences.push(sentence);
+            }
+            start = cut;
+        }
+        i = end;
+    }
+
+    let tail = text[start..].trim();
+    if !tail.is_empty() || sentences.is_empty() {
+        sentences.push(tail);
+    }
+    sentences
+}
+
+/// Whether the word before a period is a known abbreviation or a single initial
+fn is_abbreviation(before: &str) -> bool {
+    let word = before
+        .rsplit(|c: char| c.is_whitespace() || c == '(' || c == '"' || c == '“')
+        .next()
+        .unwrap_or("");
+    let lower = word.to_lowercase();
+    let mut letters = word.chars();
+    let single_initial = matches!((letters.next(), letters.next()), (Some(l), None) if l.is_uppercase());
+    single_initial || ABBREVIATIONS.contains(&lower.as_str())
+}
+
+/// A new sentence starts with something other than a lowercase letter
+fn next_starts_sentence(rest: &[(usize, char)]) -> bool {
+    rest.iter()
+        .map(|(_, c)| *c)
+        .find(|c| !c.is_whitespace())
+        .is_some_and(|c| !c.is_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn abbreviations_and_initials_dont_end_a_sentence() {
+        assert_eq!(split_sentences("Mr. Smith met Dr. Jones. They talked."), ["Mr. Smith met Dr. Jones.", "They talked."]);
+        assert_eq!(split_sentences("Bring fruit, e.g. Apples or pears. Then leave."), ["Bring fruit, e.g. Apples or pears.", "Then leave."]);
+        assert_eq!(split_sentences("Written by J. R. Tolkien. Read it."), ["Written by J. R. Tolkien.", "Read it."]);
+        // Inside parentheses too
+        assert_eq!(split_sentences("It was short (cf. Moby Dick). Still good."), ["It was short (cf. Moby Dick).", "Still good."]);
+    }
+
+    #[test]
+    fn decimals_and_lowercase_continuations_stay_whole() {
+        assert_eq!(split_sentences("Pi is 3.14 or so. Close enough."), ["Pi is 3.14 or so.", "Close enough."]);
+        assert_eq!(split_sentences("Version 2.0.1 shipped. It broke."), ["Version 2.0.1 shipped.", "It broke."]);
+        assert_eq!(split_sentences("It cost $4.50 each. Cheap."), ["It cost $4.50 each.", "Cheap."]);
+    }
+
+    #[test]
+    fn ellipses_end_a_sentence_only_before_a_new_one() {
+        assert_eq!(split_sentences("Wait... and then nothing."), ["Wait... and then nothing."]);
+        assert_eq!(split_sentences("Wait... Then nothing."), ["Wait...", "Then nothing."]);
+        assert_eq!(split_sentences("Well… I suppose so."), ["Well…", "I suppose so."]);
+        assert_eq!(split_sentences("Really?! Yes."), ["Really?!", "Yes."]);
+    }
+
+    #[test]
+    fn closing_quotes_stay_with_their_sentence() {
+        assert_eq!(split_sentences("\"Stop!\" He ran. She didn't."), ["\"Stop!\"", "He ran.", "She didn't."]);
+        assert_eq!(split_sentences("She said “no.” Then she left."), ["She said “no.”", "Then she left."]);
+        assert_eq!(split_sentences("It was *over.* Done."), ["It was *over.*", "Done."]);
+        assert_eq!(split_sentences("(Quietly.) Next."), ["(Quietly.)", "Next."]);
+        // A quote a lowercase word follows is part of the sentence
+        assert_eq!(split_sentences("\"Stop!\" he said."), ["\"Stop!\" he said."]);
+    }
+
+    #[test]
+    fn fullwidth_terminators_need_no_space() {
+        assert_eq!(split_sentences("今日は晴れ。明日は雨。"), ["今日は晴れ。", "明日は雨。"]);
+    }
+
+    #[test]
+    fn paragraphs_are_split_and_structure_is_kept() {
+        let markdown = "# Title. Subtitle\n\nOne. Two.\n\n> Quoted. Again.\n- Item one. Item two.\n\n```\ncode. More code.\n```\n";
+        assert_eq!(
+            sentence_per_line(markdown),
+            "# Title. Subtitle\n\nOne.\nTwo.\n\n> Quoted.\n> Again.\n- Item one.\n  Item two.\n\n```\ncode. More code.\n```\n"
+        );
+    }
+}
@@ -0,0 +1,81 @@
+use crate::combined::CombinedFile;
+use crate::output::{Extension, Newline};
+use crate::saved::SavedBook;
+use crate::toc::SlugRules;
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A converted book and where its output lives
+struct Volume {
+    output_dir: PathBuf,
+    book: SavedBook,
+}
+
+/// Group converted books by series and write one combined Markdown file per series into
+/// `base`, volumes in series order, with contents flattened below `toc_depth` levels.
+/// Returns the files written.
+pub fn merge_series(output_dirs: &[PathBuf], base: &Path, toc_depth: Option<usize>, extension: Extension, newline: Newline, slug_rules: &SlugRules) -> Result<Vec<PathBuf>> {
+    let mut by_series: BTreeMap<String, Vec<Volume>> = BTreeMap::new();
+    for output_dir in output_dirs {
+        let book = SavedBook::load(output_dir)?;
+        if let Some(series) = book.series.clone() {
+            by_series.entry(series).or_default().push(Volume { output_dir: output_dir.clone(), book });
+        }
+    }
+
+    let mut written = Vec::new();
+    for (series, mut volumes) in by_series {
+        // Reading order: series index, then title for books without one
+        volumes.sort_by(|a, b| {
+            let index = |v: &Volume| v.book.series_index.unwrap_or(f64::MAX);
+            index(a)
+                .total_cmp(&index(b))
+                .then_with(|| a.book.title.cmp(&b.book.title))
+        });
+        written.push(write_series(&series, &volumes, base, toc_depth, extension, newline, slug_rules)?);
+    }
+    Ok(written)
+}
+
+fn write_series(series: &str, volumes: &[Volume], base: &Path, toc_depth: Option<usize>, extension: Extension, newline: Newline, slug_rules: &SlugRules) -> Result<PathBuf> {
+    let path = base.join(format!("{}.{}", crate::sanitize_filename(series), extension.as_str()));
+
+    let mut authors: Vec<&str> = Vec::new();
+    for creator in volumes.iter().flat_map(|v| &v.book.creators) {
+        if !authors.contains(&creator.as_str()) {
+            authors.push(creator);
+        }
+    }
+    let author = if authors.is_empty() { "Unknown Author".to_string() } else { authors.join(", ") };
+
+    let mut merged = CombinedFile::with_depth(&path, series, &author, 2)
+        .context("Failed to create merged series file")?;
+    merged.set_toc_depth(toc_depth);
+    merged.set_newline(newline);
+    merged.set_slug_rules(slug_rules);
+    for (n, volume) in (1..).zip(volumes) {
+        let title = volume.book.title.as_deref().unwrap_or("Untitled");
+        let label = match volume.book.series_index {
+            Some(index) => format!("Volume {}: {}", index, title),
+            None => format!("Volume {}: {}", n, title),
+        };
+        merged.add_heading(2, &label).context("Failed to write merged series file")?;
+
+        // Chapter files in reading order; the mapping may list a file more than once
+        let mut seen: Vec<&str> = Vec::new();
+        for chapter in &volume.book.chapters {
+            if seen.contains(&chapter.file.as_str()) {
+                continue;
+            }
+            seen.push(&chapter.file);
+            let file = crate::output::safe_join(&volume.output_dir, &chapter.file)?;
+            let markdown = fs::read_to_string(&file)
+                .with_context(|| format!("Failed to read {}", file.display()))?;
+            merged.add_chapter(&markdown).context("Failed to write merged series file")?;
+        }
+    }
+    merged.commit().context("Failed to write merged series file")?;
+    Ok(path)
+}
@@ -0,0 +1,111 @@
+use crate::html;
+use markup5ever_rcdom::{Handle, NodeData};
+use std::rc::Rc;
+
+/// Inline elements whose adjacent runs are merged into one, so `<em>Wo</em><em>rd</em>`
+/// doesn't come out as `*Wo**rd*`
+const MERGE_TAGS: &[&str] = &["span", "em", "i", "strong", "b", "u", "s", "del", "code", "small", "sup", "sub", "mark"];
+
+/// Elements a drop cap is set in
+const DROP_CAP_TAGS: &[&str] = &["span", "b", "strong", "i", "em", "big", "font", "small", "img"];
+
+/// Class names of drop caps, compared with `-` and `_` removed; matched as prefixes so
+/// `dropcap1` and `initial-letter` count too
+const DROP_CAP_CLASSES: &[&str] = &["dropcap", "dropinitial", "initial", "firstletter", "bigcap", "largecap", "bigletter", "raisedcap", "capdrop"];
+
+/// Most characters a drop cap holds: the letter, perhaps with an opening quote
+const MAX_DROP_CAP_CHARS: usize = 3;
+
+/// Chapter XHTML with drop caps reduced to plain text joined to the rest of their word,
+/// and adjacent runs of the same inline styling merged. None when there was nothing to do.
+pub fn merge(content: &str) -> Option<String> {
+    let dom = html::parse(content);
+    let changes = rewrite(&dom.document);
+    (changes > 0).then(|| html::serialize(&dom))
+}
+
+fn rewrite(node: &Handle) -> usize {
+    let mut changes = 0;
+    let children: Vec<Handle> = node.children.take();
+    let mut kept: Vec<Handle> = Vec::with_capacity(children.len());
+    // Set after a drop cap that starts a word, so the space a line break left before the
+    // rest of the word is dropped
+    let mut join_next = false;
+    for child in children {
+        if let Some(letter) = drop_cap_text(&child) {
+            let text = html::text(&letter);
+            text.parent.set(Some(Rc::downgrade(node)));
+            // "A" and "I" are words of their own, so the space after them is real
+            join_next = !matches!(letter.chars().last(), Some('A' | 'I' | 'O' | 'a' | 'i' | 'o'));
+            kept.push(text);
+            changes += 1;
+            continue;
+        }
+        if std::mem::take(&mut join_next) && trim_word_start(&child) {
+            changes += 1;
+        }
+        if let Some(previous) = kept.last().filter(|previous| mergeable(previous, &child)) {
+            let moved: Vec<Handle> = child.children.take();
+            for grandchild in &moved {
+                grandchild.parent.set(Some(Rc::downgrade(previous)));
+            }
+            previous.children.borrow_mut().extend(moved);
+            changes += 1;
+            continue;
+        }
+        kept.push(child);
+    }
+    for child in &kept {
+        changes += rewrite(child);
+    }
+    *node.children.borrow_mut() = kept;
+    changes
+}
+
+/// The text of a drop cap element: its letter, or the alt text of a drop-cap image
+fn drop_cap_text(node: &Handle) -> Option<String> {
+    let tag = html::tag_name(node)?;
+    if !DROP_CAP_TAGS.contains(&tag.as_str()) {
+        return None;
+    }
+    let classes = html::attr(node, "class").unwrap_or_default();
+    let is_drop_cap = classes.split_whitespace().any(|class| {
+        let name: String = class.chars().filter(|c| !matches!(c, '-' | '_')).collect::<String>().to_ascii_lowercase();
+        name == "dc" || DROP_CAP_CLASSES.iter().any(|prefix| name.starts_with(prefix))
+    });
+    if !is_drop_cap {
+        return None;
+    }
+    let text = if tag == "img" { html::attr(node, "alt").unwrap_or_default().trim().to_string() } else { html::text_content(node) };
+    let length = text.chars().count();
+    (length > 0 && length <= MAX_DROP_CAP_CHARS).then_some(text)
+}
+
+/// Drop the whitespace at the start of a text node (or the first text inside an inline
+/// element) when a lowercase letter follows it, as in `T</span>\nhe`
+fn trim_word_start(node: &Handle) -> bool {
+    match node.data {
+        NodeData::Text { ref contents } => {
+            let mut contents = contents.borrow_mut();
+            let trimmed = contents.trim_start();
+            if trimmed.len() == contents.len() || !trimmed.starts_with(|c: char| c.is_lowercase()) {
+                return false;
+            }
+            let trimmed = trimmed.to_string();
+            *contents = trimmed.as_str().into();
+            true
+        }
+        NodeData::Element { .. } if html::tag_name(node).is_some_and(|t| MERGE_TAGS.contains(&t.as_str())) => {
+            node.children.borrow().first().is_some_and(trim_word_start)
+        }
+        _ => false,
+    }
+}
+
+/// Whether two siblings are the same inline element with the same attributes
+fn mergeable(a: &Handle, b: &Handle) -> bool {
+    let (NodeData::Element { name: a_name, attrs: a_attrs, .. }, NodeData::Element { name: b_name, attrs: b_attrs, .. }) = (&a.data, &b.data) else {
+        return false;
+    };
+    a_name == b_name && MERGE_TAGS.contains(&a_name.local.as_ref()) && *a_attrs.borrow() == *b_attrs.borrow()
+}
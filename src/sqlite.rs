@@ -0,0 +1,432 @@
+use anyhow::{bail, Context, Result};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// Deepest b-tree walked, far past any real database; stops a corrupt file's page cycle
+const MAX_DEPTH: usize = 32;
+
+/// A column value
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Integer(i64),
+    Real(f64),
+    Text(String),
+    Blob(Vec<u8>),
+}
+
+impl Value {
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Value::Integer(n) => Some(*n),
+            Value::Real(f) => Some(*f as i64),
+            Value::Text(t) => t.trim().parse().ok(),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Integer(n) => Some(*n as f64),
+            Value::Real(f) => Some(*f),
+            Value::Text(t) => t.trim().parse().ok(),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::Text(t) => Some(t),
+            _ => None,
+        }
+    }
+}
+
+/// A row, by column name
+pub type Row = HashMap<String, Value>;
+
+/// Where a table's rows are stored, and its columns
+#[derive(Debug)]
+struct Table {
+    root: u32,
+    columns: Vec<String>,
+    /// Column aliasing the rowid (declared `INTEGER PRIMARY KEY`), stored as NULL
+    rowid_column: Option<usize>,
+}
+
+/// A read-only SQLite database, read whole into memory: enough of the file format to scan
+/// ordinary tables, as Calibre's `metadata.db` needs. Indexes, views and WITHOUT ROWID
+/// tables aren't read, and changes still in a `-wal` file aren't seen.
+#[derive(Debug)]
+pub struct Database {
+    data: Vec<u8>,
+    page_size: usize,
+    /// Bytes of each page in use, less the space reserved at its end
+    usable: usize,
+    tables: HashMap<String, Table>,
+}
+
+impl Database {
+    pub fn open(path: &Path) -> Result<Self> {
+        let data = fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        if data.len() < 100 || !data.starts_with(b"SQLite format 3\0") {
+            bail!("{} is not an SQLite database", path.display());
+        }
+        let page_size = match u16::from_be_bytes([data[16], data[17]]) {
+            1 => 65536,
+            n => usize::from(n),
+        };
+        if page_size < 512 || !page_size.is_power_of_two() {
+            bail!("{} has an invalid page size", path.display());
+        }
+        if u32::from_be_bytes([data[56], data[57], data[58], data[59]]) > 1 {
+            bail!("{} is not UTF-8 encoded", path.display());
+        }
+        let usable = page_size - usize::from(data[20]);
+        let mut db = Self { data, page_size, usable, tables: HashMap::new() };
+
+        // The schema table, rooted at page 1: type, name, tbl_name, rootpage, sql
+        let mut cells = Vec::new();
+        db.walk(1, 0, &mut cells)?;
+        for (_, payload) in cells {
+            let values = record(&payload)?;
+            let field = |i: usize| values.get(i).and_then(Value::as_str).unwrap_or_default();
+            let root = values.get(3).and_then(Value::as_i64).unwrap_or(0);
+            if field(0) != "table" || root <= 0 {
+                continue;
+            }
+            let (columns, rowid_column) = parse_columns(field(4));
+            db.tables.insert(field(1).to_string(), Table { root: root as u32, columns, rowid_column });
+        }
+        Ok(db)
+    }
+
+    pub fn has_table(&self, name: &str) -> bool {
+        self.tables.contains_key(name)
+    }
+
+    /// Every row of a table, in rowid order. Rows written before a column was added lack
+    /// it and read it as NULL.
+    pub fn rows(&self, name: &str) -> Result<Vec<Row>> {
+        let table = self.tables.get(name).with_context(|| format!("No table {} in the database", name))?;
+        let mut cells = Vec::new();
+        self.walk(table.root, 0, &mut cells)?;
+        cells
+            .into_iter()
+            .map(|(rowid, payload)| {
+                let mut values = record(&payload)?;
+                values.resize(table.columns.len(), Value::Null);
+                if let Some(i) = table.rowid_column.filter(|&i| values[i] == Value::Null) {
+                    values[i] = Value::Integer(rowid);
+                }
+                Ok(table.columns.iter().cloned().zip(values).collect())
+            })
+            .collect()
+    }
+
+    fn page(&self, number: u32) -> Result<&[u8]> {
+        let start = (number as usize).checked_sub(1).context("Invalid page number")? * self.page_size;
+        self.data.get(start..start + self.page_size).with_context(|| format!("Page {} is past the end of the database", number))
+    }
+
+    /// Collect the rowid and payload of every cell in a table b-tree
+    fn walk(&self, number: u32, depth: usize, cells: &mut Vec<(i64, Vec<u8>)>) -> Result<()> {
+        if depth > MAX_DEPTH {
+            bail!("Table b-tree is too deep; the database looks corrupt");
+        }
+        let page = self.page(number)?;
+        let header = if number == 1 { 100 } else { 0 };
+        let count = usize::from(u16::from_be_bytes([page[header + 3], page[header + 4]]));
+        let pointer = |i: usize, base: usize| -> Result<usize> {
+            let at = base + i * 2;
+            let bytes = slice(page, at, 2)?;
+            Ok(usize::from(u16::from_be_bytes([bytes[0], bytes[1]])))
+        };
+        match page[header] {
+            // Leaf: payload size, rowid, then the payload, spilling onto overflow pages
+            0x0D => {
+                for i in 0..count {
+                    let at = pointer(i, header + 8)?;
+                    let (size, at) = varint(page, at)?;
+                    let (rowid, at) = varint(page, at)?;
+                    cells.push((rowid as i64, self.payload(page, at, size as usize)?));
+                }
+            }
+            // Interior: each cell's left child, then the right-most child
+            0x05 => {
+                for i in 0..count {
+                    let at = pointer(i, header + 12)?;
+                    self.walk(be_u32(slice(page, at, 4)?), depth + 1, cells)?;
+                }
+                self.walk(be_u32(slice(page, header + 8, 4)?), depth + 1, cells)?;
+            }
+            kind => bail!("Unexpected b-tree page type {:#04x} in a table", kind),
+        }
+        Ok(())
+    }
+
+    /// A cell's payload, gathered from the page and any overflow pages after it
+    fn payload(&self, page: &[u8], at: usize, size: usize) -> Result<Vec<u8>> {
+        let max_local = self.usable - 35;
+        if size <= max_local {
+            return Ok(slice(page, at, size)?.to_vec());
+        }
+        let min_local = (self.usable - 12) * 32 / 255 - 23;
+        let spill = min_local + (size - min_local) % (self.usable - 4);
+        let local = if spill <= max_local { spill } else { min_local };
+        let mut payload = Vec::with_capacity(size);
+        payload.extend_from_slice(slice(page, at, local)?);
+        let mut next = be_u32(slice(page, at + local, 4)?);
+        let mut hops = 0;
+        while payload.len() < size {
+            hops += 1;
+            if next == 0 || hops > self.data.len() / self.page_size {
+                bail!("Overflow chain ends early; the database looks corrupt");
+            }
+            let overflow = self.page(next)?;
+            next = be_u32(&overflow[..4]);
+            let take = (size - payload.len()).min(self.usable - 4);
+            payload.extend_from_slice(&overflow[4..4 + take]);
+        }
+        Ok(payload)
+    }
+}
+
+fn slice(bytes: &[u8], at: usize, len: usize) -> Result<&[u8]> {
+    bytes.get(at..at + len).context("Cell runs past the end of its page; the database looks corrupt")
+}
+
+fn be_u32(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+/// A variable-length integer: up to eight bytes of seven bits, then one of eight
+fn varint(bytes: &[u8], at: usize) -> Result<(u64, usize)> {
+    let mut value = 0u64;
+    for i in 0..9 {
+        let byte = *bytes.get(at + i).context("Truncated varint; the database looks corrupt")?;
+        if i == 8 {
+            return Ok(((value << 8) | u64::from(byte), at + 9));
+        }
+        value = (value << 7) | u64::from(byte & 0x7F);
+        if byte & 0x80 == 0 {
+            return Ok((value, at + i + 1));
+        }
+    }
+    unreachable!()
+}
+
+/// The values of a record: a header of serial types, then their contents
+fn record(payload: &[u8]) -> Result<Vec<Value>> {
+    let (header_len, mut at) = varint(payload, 0)?;
+    let mut types = Vec::new();
+    while at < header_len as usize {
+        let (serial, next) = varint(payload, at)?;
+        types.push(serial);
+        at = next;
+    }
+    let mut body = header_len as usize;
+    let mut values = Vec::with_capacity(types.len());
+    for serial in types {
+        let (value, len) = match serial {
+            0 => (Value::Null, 0),
+            1..=6 => {
+                let len = [1, 2, 3, 4, 6, 8][serial as usize - 1];
+                let bytes = slice(payload, body, len)?;
+                let unsigned = bytes.iter().fold(0i64, |n, &b| (n << 8) | i64::from(b));
+                let shift = 64 - 8 * len as u32;
+                (Value::Integer((unsigned << shift) >> shift), len)
+            }
+            7 => (Value::Real(f64::from_bits(u64::from_be_bytes(slice(payload, body, 8)?.try_into()?))), 8),
+            8 => (Value::Integer(0), 0),
+            9 => (Value::Integer(1), 0),
+            10 | 11 => bail!("Reserved serial type in record; the database looks corrupt"),
+            n if n % 2 == 0 => {
+                let len = (n as usize - 12) / 2;
+                (Value::Blob(slice(payload, body, len)?.to_vec()), len)
+            }
+            n => {
+                let len = (n as usize - 13) / 2;
+                (Value::Text(String::from_utf8_lossy(slice(payload, body, len)?).into_owned()), len)
+            }
+        };
+        values.push(value);
+        body += len;
+    }
+    Ok(values)
+}
+
+/// Column names of a `CREATE TABLE` statement, and which one aliases the rowid
+fn parse_columns(sql: &str) -> (Vec<String>, Option<usize>) {
+    let (Some(start), Some(end)) = (sql.find('('), sql.rfind(')')) else { return (Vec::new(), None) };
+    let mut columns = Vec::new();
+    let mut rowid_column = None;
+    for definition in split_definitions(&sql[start + 1..end]) {
+        let definition = definition.trim();
+        let upper = definition.split_whitespace().collect::<Vec<_>>().join(" ").to_ascii_uppercase();
+        if ["CONSTRAINT", "PRIMARY", "UNIQUE", "CHECK", "FOREIGN"].iter().any(|k| upper.split(' ').next() == Some(*k)) {
+            continue;
+        }
+        let name = match definition.chars().next() {
+            Some(quote @ ('"' | '`' | '[')) => {
+                let close = if quote == '[' { ']' } else { quote };
+                definition[1..].split(close).next().unwrap_or_default().to_string()
+            }
+            _ => definition.split_whitespace().next().unwrap_or_default().to_string(),
+        };
+        if upper.contains(" INTEGER PRIMARY KEY") {
+            rowid_column = Some(columns.len());
+        }
+        columns.push(name);
+    }
+    (columns, rowid_column)
+}
+
+/// Split column definitions at the commas outside parentheses and quotes
+fn split_definitions(body: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth = 0usize;
+    let mut quote: Option<char> = None;
+    let mut start = 0;
+    for (i, c) in body.char_indices() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '\'' | '"' | '`') => quote = Some(c),
+            (None, '[') => quote = Some(']'),
+            (None, '(') => depth += 1,
+            (None, ')') => depth = depth.saturating_sub(1),
+            (None, ',') if depth == 0 => {
+                parts.push(&body[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&body[start..]);
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Made by scripts/sqlite_fixture.py, with 512-byte pages
+    fn fixture() -> Database {
+        Database::open(&Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/books.db")).unwrap()
+    }
+
+    fn book(rows: &[Row], id: i64) -> &Row {
+        rows.iter().find(|row| row["id"] == Value::Integer(id)).unwrap()
+    }
+
+    #[test]
+    fn varints_take_seven_bits_a_byte_then_eight() {
+        assert_eq!(varint(&[0x7F], 0).unwrap(), (127, 1));
+        assert_eq!(varint(&[0x00, 0x81, 0x00], 1).unwrap(), (128, 3));
+        assert_eq!(varint(&[0xFF; 9], 0).unwrap(), (u64::MAX, 9));
+        assert!(varint(&[0x80, 0x80], 0).is_err());
+    }
+
+    #[test]
+    fn records_decode_every_serial_type() {
+        // Header of eight serial types, then their contents
+        let mut payload = vec![9, 0, 1, 2, 7, 8, 9, 12 + 2 * 2, 13 + 2 * 3];
+        payload.extend_from_slice(&[0xFF, 0xFF, 0x7F]);
+        payload.extend_from_slice(&2.5f64.to_be_bytes());
+        payload.extend_from_slice(&[0xDE, 0xAD]);
+        payload.extend_from_slice(b"abc");
+        assert_eq!(
+            record(&payload).unwrap(),
+            [
+                Value::Null,
+                Value::Integer(-1),
+                Value::Integer(-129),
+                Value::Real(2.5),
+                Value::Integer(0),
+                Value::Integer(1),
+                Value::Blob(vec![0xDE, 0xAD]),
+                Value::Text("abc".to_string()),
+            ]
+        );
+        assert!(record(&[2, 10]).is_err());
+        // A value running past the end of the payload
+        assert!(record(&[2, 6, 0, 0]).is_err());
+    }
+
+    #[test]
+    fn column_definitions_skip_constraints_and_find_the_rowid_alias() {
+        let (columns, rowid) =
+            parse_columns(r#"CREATE TABLE t (id INTEGER PRIMARY KEY, "sort key" TEXT, [x] NUMERIC(10, 2), CONSTRAINT c CHECK (a, b))"#);
+        assert_eq!(columns, ["id", "sort key", "x"]);
+        assert_eq!(rowid, Some(0));
+    }
+
+    #[test]
+    fn the_schema_lists_tables_but_not_indexes() {
+        let db = fixture();
+        assert!(db.has_table("books"));
+        assert!(db.has_table("authors"));
+        assert!(!db.has_table("books_title"));
+        assert!(db.rows("missing").is_err());
+    }
+
+    #[test]
+    fn rows_come_in_rowid_order_across_interior_pages() {
+        let rows = fixture().rows("books").unwrap();
+        assert_eq!(rows.len(), 69);
+        let ids: Vec<i64> = rows.iter().map(|row| row["id"].as_i64().unwrap()).collect();
+        assert!(ids.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(ids.last(), Some(&(2i64.pow(40) + 7)));
+
+        let first = book(&rows, 1);
+        assert_eq!(first["title"].as_str(), Some("Book 1"));
+        assert_eq!(first["sort key"].as_str(), Some("book 001"));
+        assert_eq!(first["rating"], Value::Real(0.25));
+        assert_eq!(first["cover"], Value::Blob(vec![1, 0, 255]));
+        // Written before the column was added
+        assert_eq!(first["description"], Value::Null);
+        assert_eq!(book(&rows, 7)["sort key"], Value::Null);
+    }
+
+    #[test]
+    fn integers_of_every_width_keep_their_sign() {
+        let rows = fixture().rows("books").unwrap();
+        let pages = |id| book(&rows, id)["pages"].clone();
+        assert_eq!(pages(100), Value::Integer(0));
+        assert_eq!(pages(101), Value::Integer(1));
+        assert_eq!(pages(102), Value::Integer(-129));
+        assert_eq!(pages(103), Value::Integer(70000));
+        assert_eq!(pages(104), Value::Integer(-(1 << 31)));
+        assert_eq!(pages(105), Value::Integer(1 << 40));
+        assert_eq!(pages(106), Value::Integer(-(1 << 62)));
+    }
+
+    #[test]
+    fn long_values_are_gathered_from_overflow_pages() {
+        let rows = fixture().rows("books").unwrap();
+        let description = book(&rows, 200)["description"].as_str().unwrap().to_string();
+        let expected: String = (0..600).map(|i| format!("{:04} ", i)).collect();
+        assert_eq!(description, expected);
+
+        let authors = fixture().rows("authors").unwrap();
+        assert_eq!(authors[0]["name"].as_str(), Some("Ünïcödé Äuthor"));
+    }
+
+    #[test]
+    fn other_files_are_refused() {
+        let scratch = crate::check::ScratchDir::new("sqlite").unwrap();
+        let path = scratch.path().join("metadata.db");
+        fs::write(&path, vec![0u8; 1024]).unwrap();
+        assert!(Database::open(&path).is_err());
+
+        // A broken overflow chain is reported rather than read past
+        let mut data = fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/books.db")).unwrap();
+        for page in data.chunks_mut(512).skip(9).take(5) {
+            page[..4].copy_from_slice(&[0; 4]);
+        }
+        fs::write(&path, data).unwrap();
+        assert!(Database::open(&path).unwrap().rows("books").is_err());
+    }
+}
@@ -0,0 +1,150 @@
+use crate::html;
+use crate::render;
+use markup5ever_rcdom::{Handle, NodeData};
+
+/// Elements that are left unread: code, scripts, images and maths have nothing a speech
+/// engine can pronounce usefully
+const SILENT_TAGS: &[&str] = &["head", "script", "style", "pre", "img", "svg", "math", "audio", "video", "object", "iframe"];
+
+/// Elements that end the current paragraph
+const BLOCK_TAGS: &[&str] = &[
+    "html", "body", "p", "div", "section", "article", "aside", "header", "footer", "main", "nav", "blockquote", "ul",
+    "ol", "li", "dl", "dt", "dd", "figure", "table", "thead", "tbody", "tfoot", "tr", "hr", "address", "details",
+    "summary",
+];
+
+/// `epub:type` and `role` values of notes and references to them, which would interrupt
+/// the reading
+const NOTE_TYPES: &[&str] = &[
+    "noteref", "footnote", "footnotes", "endnote", "endnotes", "rearnote", "rearnotes", "doc-noteref",
+    "doc-footnote", "doc-endnote", "doc-endnotes",
+];
+
+/// Pauses around headings and at scene breaks
+const BEFORE_HEADING: &str = "<break time=\"1s\"/>";
+const AFTER_HEADING: &str = "<break time=\"700ms\"/>";
+const SCENE_BREAK: &str = "<break time=\"1500ms\"/>";
+
+/// Turn a chapter's XHTML into the body of an SSML document: paragraphs as `<p>`, headings
+/// emphasized between pauses, and emphasis kept. Footnotes and the references to them are
+/// dropped, figure captions are read in parentheses, and code and images are skipped.
+pub fn from_xhtml(content: &str) -> String {
+    let dom = html::parse(content);
+    let mut reader = Reader::default();
+    reader.block(&dom.document);
+    reader.flush();
+    reader.out
+}
+
+/// Wrap a chapter body in a `<speak>` document
+pub fn document(body: &str, language: Option<&str>) -> String {
+    let lang = language.map(|l| format!(" xml:lang=\"{}\"", escape(l.trim()))).unwrap_or_default();
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<speak version=\"1.0\" xmlns=\"http://www.w3.org/2001/10/synthesis\"{}>\n{}</speak>\n",
+        lang, body
+    )
+}
+
+#[derive(Default)]
+struct Reader {
+    out: String,
+    /// Inline SSML of the paragraph being collected
+    paragraph: String,
+}
+
+impl Reader {
+    fn block(&mut self, node: &Handle) {
+        for child in node.children.borrow().iter() {
+            match child.data {
+                NodeData::Text { ref contents } => push_text(&mut self.paragraph, &contents.borrow()),
+                NodeData::Element { .. } => self.element(child),
+                _ => {}
+            }
+        }
+    }
+
+    fn element(&mut self, node: &Handle) {
+        let tag = html::tag_name(node).unwrap_or_default();
+        if SILENT_TAGS.contains(&tag.as_str()) || is_note(node) {
+            return;
+        }
+        let heading = matches!(tag.as_str(), "h1" | "h2" | "h3" | "h4" | "h5" | "h6");
+        if (heading || BLOCK_TAGS.contains(&tag.as_str())) && render::is_scene_break(node) {
+            self.flush();
+            self.out.push_str(SCENE_BREAK);
+            self.out.push('\n');
+            return;
+        }
+        match tag.as_str() {
+            _ if heading => {
+                self.flush();
+                let text = html::text_content(node);
+                if !text.is_empty() {
+                    self.out.push_str(&format!(
+                        "{}\n<p><emphasis level=\"strong\">{}</emphasis></p>\n{}\n",
+                        BEFORE_HEADING,
+                        escape(&text),
+                        AFTER_HEADING
+                    ));
+                }
+            }
+            "figcaption" | "caption" => {
+                self.flush();
+                let text = html::text_content(node);
+                if !text.is_empty() {
+                    self.out.push_str(&format!("<p>({})</p>\n", escape(&text)));
+                }
+            }
+            "br" => self.paragraph.push(' '),
+            // A superscript that is only a number or mark is a note reference
+            "sup" if html::text_content(node).chars().all(|c| c.is_ascii_digit() || "*†‡§¶".contains(c)) => {}
+            "td" | "th" => {
+                self.block(node);
+                self.paragraph.push_str(", ");
+            }
+            "em" | "i" | "strong" | "b" => {
+                let mut inner = Reader::default();
+                inner.block(node);
+                let text = inner.paragraph.trim();
+                if !text.is_empty() {
+                    let level = if matches!(tag.as_str(), "strong" | "b") { "strong" } else { "moderate" };
+                    self.paragraph.push_str(&format!("<emphasis level=\"{}\">{}</emphasis>", level, text));
+                }
+                // Block content inside emphasis is rare, but keep it rather than lose it
+                self.out.push_str(&inner.out);
+            }
+            t if BLOCK_TAGS.contains(&t) => {
+                self.flush();
+                self.block(node);
+                self.flush();
+            }
+            _ => self.block(node),
+        }
+    }
+
+    /// End the current paragraph
+    fn flush(&mut self) {
+        let text = self.paragraph.split_whitespace().collect::<Vec<_>>().join(" ");
+        let text = text.trim_end_matches([',', ' ']);
+        if !text.is_empty() {
+            self.out.push_str(&format!("<p>{}</p>\n", text));
+        }
+        self.paragraph.clear();
+    }
+}
+
+fn is_note(node: &Handle) -> bool {
+    [html::epub_type(node), html::attr(node, "role")]
+        .iter()
+        .flatten()
+        .flat_map(|v| v.split_whitespace())
+        .any(|t| NOTE_TYPES.contains(&t))
+}
+
+fn push_text(out: &mut String, text: &str) {
+    out.push_str(&escape(text));
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
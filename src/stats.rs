@@ -0,0 +1,243 @@
+use crate::analysis;
+use crate::sentences;
+use serde::Serialize;
+use std::time::{Duration, Instant};
+
+/// Timing and volume counters for one book, reported with `--stats`
+#[derive(Debug, Default)]
+pub struct BookStats {
+    /// Time spent opening the EPUB and extracting metadata
+    pub open: Duration,
+    /// Time spent reading spine documents from the archive
+    pub read: Duration,
+    /// Time spent converting HTML to Markdown
+    pub render: Duration,
+    /// Time spent writing output files
+    pub write: Duration,
+    /// Number of chapters written
+    pub chapters: usize,
+    /// Total XHTML bytes read from the spine
+    pub html_bytes: usize,
+    /// Total Markdown bytes produced
+    pub markdown_bytes: usize,
+    /// Words in the Markdown produced
+    pub words: usize,
+}
+
+impl BookStats {
+    /// Run `f`, adding its wall-clock time to the counter chosen by `slot`
+    pub fn time<T>(slot: &mut Duration, f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let result = f();
+        *slot += start.elapsed();
+        result
+    }
+
+    /// One-line human-readable summary
+    pub fn summary(&self) -> String {
+        format!(
+            "open {:.1?}, read {:.1?}, render {:.1?}, write {:.1?} | {} chapter(s), {} word(s), {} KiB HTML -> {} KiB Markdown",
+            self.open,
+            self.read,
+            self.render,
+            self.write,
+            self.chapters,
+            self.words,
+            self.html_bytes / 1024,
+            self.markdown_bytes / 1024,
+        )
+    }
+}
+
+/// Count words: whitespace-separated tokens containing a letter or digit. Scripts written
+/// without spaces (Chinese, Japanese) count each ideograph or kana as a word.
+pub fn count_words(text: &str) -> usize {
+    let is_cjk = |c: char| {
+        matches!(c as u32, 0x3040..=0x30FF | 0x3400..=0x4DBF | 0x4E00..=0x9FFF | 0xF900..=0xFAFF)
+    };
+    text.split_whitespace()
+        .map(|token| {
+            let cjk = token.chars().filter(|&c| is_cjk(c)).count();
+            let other = token.chars().any(|c| c.is_alphanumeric() && !is_cjk(c));
+            cjk + usize::from(other)
+        })
+        .sum()
+}
+
+/// Sentences, words and syllables of some prose, from which its readability is scored
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TextCounts {
+    pub sentences: usize,
+    pub words: usize,
+    pub syllables: usize,
+    /// Words of three or more syllables
+    pub polysyllables: usize,
+}
+
+impl TextCounts {
+    /// Count the prose of a chapter: its paragraphs, quotes and list items, leaving out
+    /// headings, tables, code and images
+    pub fn of(markdown: &str) -> Self {
+        let mut counts = Self::default();
+        for paragraph in paragraphs(markdown) {
+            for sentence in sentences::split_sentences(&paragraph) {
+                let words: Vec<String> = analysis::words(sentence).filter(|w| w.chars().any(char::is_alphabetic)).collect();
+                if words.is_empty() {
+                    continue;
+                }
+                counts.sentences += 1;
+                counts.words += words.len();
+                for word in &words {
+                    let syllables = syllables(word);
+                    counts.syllables += syllables;
+                    counts.polysyllables += usize::from(syllables >= 3);
+                }
+            }
+        }
+        counts
+    }
+
+    pub fn add(&mut self, other: &TextCounts) {
+        self.sentences += other.sentences;
+        self.words += other.words;
+        self.syllables += other.syllables;
+        self.polysyllables += other.polysyllables;
+    }
+
+    /// Scores for the counted text, or None when it has no sentences
+    pub fn readability(&self) -> Option<Readability> {
+        if self.sentences == 0 {
+            return None;
+        }
+        let sentences = self.sentences as f64;
+        let words = self.words as f64;
+        let words_per_sentence = words / sentences;
+        let syllables_per_word = self.syllables as f64 / words;
+        Some(Readability {
+            sentences: self.sentences,
+            words: self.words,
+            average_sentence_length: round(words_per_sentence),
+            flesch_reading_ease: round(206.835 - 1.015 * words_per_sentence - 84.6 * syllables_per_word),
+            flesch_kincaid_grade: round(0.39 * words_per_sentence + 11.8 * syllables_per_word - 15.59),
+            smog: round(1.043 * (self.polysyllables as f64 * 30.0 / sentences).sqrt() + 3.1291),
+        })
+    }
+}
+
+/// Readability scores of a chapter or book, as written to stats.json. The formulas are
+/// calibrated for English prose.
+#[derive(Debug, Clone, Serialize)]
+pub struct Readability {
+    pub sentences: usize,
+    pub words: usize,
+    /// Words per sentence
+    pub average_sentence_length: f64,
+    /// 0 to 100, higher is easier
+    pub flesch_reading_ease: f64,
+    /// US school grade
+    pub flesch_kincaid_grade: f64,
+    /// US school grade; meant for samples of 30 sentences or more
+    pub smog: f64,
+}
+
+/// stats.json: a book's size and the readability of its text, as a whole and by chapter
+#[derive(Debug, Serialize)]
+pub struct StatsReport {
+    pub chapter_count: usize,
+    pub words: usize,
+    pub html_bytes: usize,
+    pub markdown_bytes: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub readability: Option<Readability>,
+    pub chapters: Vec<ChapterStats>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChapterStats {
+    pub chapter: usize,
+    #[serde(flatten, skip_serializing_if = "Option::is_none")]
+    pub readability: Option<Readability>,
+}
+
+impl StatsReport {
+    pub fn new(stats: &BookStats, chapters: &[(usize, TextCounts)]) -> Self {
+        let mut total = TextCounts::default();
+        for (_, counts) in chapters {
+            total.add(counts);
+        }
+        Self {
+            chapter_count: stats.chapters,
+            words: stats.words,
+            html_bytes: stats.html_bytes,
+            markdown_bytes: stats.markdown_bytes,
+            readability: total.readability(),
+            chapters: chapters.iter().map(|(chapter, counts)| ChapterStats { chapter: *chapter, readability: counts.readability() }).collect(),
+        }
+    }
+}
+
+fn round(score: f64) -> f64 {
+    (score * 100.0).round() / 100.0
+}
+
+/// Paragraph text of a chapter, one string per paragraph, without the lines that aren't prose
+fn paragraphs(markdown: &str) -> Vec<String> {
+    let mut paragraphs = Vec::new();
+    let mut current: Vec<&str> = Vec::new();
+    let mut in_fence = false;
+    for line in markdown.lines() {
+        let trimmed = line.trim();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_fence = !in_fence;
+            continue;
+        }
+        if in_fence || line.starts_with("    ") || line.starts_with('\t') {
+            continue;
+        }
+        // An underline makes the paragraph before it a heading
+        if !trimmed.is_empty() && (trimmed.chars().all(|c| c == '=') || (trimmed.chars().all(|c| c == '-') && current.len() == 1)) {
+            current.clear();
+            continue;
+        }
+        let structural = trimmed.starts_with('#') || trimmed.starts_with('|') || trimmed.starts_with('<') || trimmed.starts_with("![");
+        if trimmed.is_empty() || structural {
+            if !current.is_empty() {
+                paragraphs.push(analysis::prose_lines(&current.join(" ")).join(" "));
+                current.clear();
+            }
+            continue;
+        }
+        current.push(trimmed);
+    }
+    if !current.is_empty() {
+        paragraphs.push(analysis::prose_lines(&current.join(" ")).join(" "));
+    }
+    paragraphs
+}
+
+/// Syllables of an English word: its groups of vowels, less a silent final `e` (but not
+/// the `le` of "table") or an `ed` that isn't said ("walked", not "wanted"), at least one
+fn syllables(word: &str) -> usize {
+    let word = word.to_lowercase();
+    let chars: Vec<char> = word.chars().filter(|c| c.is_alphabetic()).collect();
+    let is_vowel = |c: char| matches!(c, 'a' | 'e' | 'i' | 'o' | 'u' | 'y');
+    let mut groups = 0;
+    let mut previous = false;
+    for &c in &chars {
+        let vowel = is_vowel(c);
+        if vowel && !previous {
+            groups += 1;
+        }
+        previous = vowel;
+    }
+    let n = chars.len();
+    if n > 2 && groups > 1 {
+        let (before, last) = (chars[n - 2], chars[n - 1]);
+        let silent_e = last == 'e' && !is_vowel(before) && !(before == 'l' && n > 3 && !is_vowel(chars[n - 3]));
+        let silent_ed = before == 'e' && last == 'd' && n > 3 && !matches!(chars[n - 3], 't' | 'd') && !is_vowel(chars[n - 3]);
+        if silent_e || silent_ed {
+            groups -= 1;
+        }
+    }
+    groups.max(1)
+}
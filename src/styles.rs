@@ -0,0 +1,103 @@
+use crate::archive::Resources;
+use crate::obfuscation::{self, Obfuscation};
+use crate::output;
+use anyhow::{Context, Result};
+use epub::doc::EpubDoc;
+use serde::Serialize;
+use std::fs;
+use std::io::{Read, Seek};
+use std::path::{Path, PathBuf};
+
+/// Directory under the book's output directory that `--extract-styles` writes to
+pub const STYLES_DIR: &str = "styles";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StyleKind {
+    Stylesheet,
+    Font,
+}
+
+/// A stylesheet or font copied out of the EPUB, as recorded in metadata.json
+#[derive(Debug, Serialize)]
+pub struct StyleEntry {
+    /// Manifest item id
+    pub id: String,
+    /// Path of the resource inside the EPUB
+    pub source: String,
+    /// Media type declared in the manifest
+    pub media_type: String,
+    pub kind: StyleKind,
+    /// Where it was written, relative to the output directory
+    pub file: String,
+    pub bytes: usize,
+    /// How the font was obfuscated in the EPUB; it is written restored
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deobfuscated: Option<Obfuscation>,
+}
+
+/// Copy the manifest's stylesheets and fonts into `styles/`. Paths are kept relative to the
+/// package document, so `url(../fonts/...)` references in the CSS still resolve, and
+/// obfuscated fonts are restored. Returns the entries, sorted by source path, the files
+/// written, and the obfuscated fonts left out because the book's identifier gives no key.
+pub fn extract<R: Read + Seek>(doc: &EpubDoc<R>, resources: &mut Resources, output_dir: &Path) -> Result<(Vec<StyleEntry>, Vec<PathBuf>, Vec<String>)> {
+    let mut items: Vec<(String, PathBuf, String, StyleKind)> = doc
+        .resources
+        .iter()
+        .filter_map(|(id, r)| style_kind(&r.mime, &r.path).map(|kind| (id.clone(), r.path.clone(), r.mime.clone(), kind)))
+        .collect();
+    items.sort_by(|a, b| a.1.cmp(&b.1));
+
+    let root = output_dir.join(STYLES_DIR);
+    if !items.is_empty() {
+        fs::create_dir_all(&root).context("Failed to create styles directory")?;
+    }
+    let obfuscated = resources.read_string(obfuscation::ENCRYPTION_FILE)?.map(|xml| obfuscation::obfuscated_resources(&xml)).unwrap_or_default();
+    let identifiers: Vec<String> = doc.metadata.iter().filter(|m| m.property == "identifier").map(|m| m.value.clone()).collect();
+    let mut entries = Vec::with_capacity(items.len());
+    let mut files = Vec::with_capacity(items.len());
+    let mut unrestored = Vec::new();
+    for (id, path, media_type, kind) in items {
+        let Some(mut content) = resources.read(&path.to_string_lossy())? else {
+            continue;
+        };
+        let source = path.to_string_lossy().into_owned();
+        let deobfuscated = obfuscated.get(&source).copied();
+        if let Some(method) = deobfuscated {
+            if !obfuscation::deobfuscate(&mut content, method, doc.unique_identifier.as_deref(), &identifiers) {
+                unrestored.push(source);
+                continue;
+            }
+        }
+        let relative = path.strip_prefix(&doc.root_base).unwrap_or(&path);
+        let href = relative.to_string_lossy().replace('\\', "/");
+        // Manifest paths come from the EPUB, so treat them as untrusted
+        let target = output::resource_path(&root, &href).context("Refusing to extract styles")?;
+        output::write_atomic(&target, &content).with_context(|| format!("Failed to write {}", target.display()))?;
+        entries.push(StyleEntry {
+            id,
+            source,
+            media_type,
+            kind,
+            file: format!("{}/{}", STYLES_DIR, href),
+            bytes: content.len(),
+            deobfuscated,
+        });
+        files.push(target);
+    }
+    Ok((entries, files, unrestored))
+}
+
+/// Whether a manifest item is a stylesheet or font. Font media types vary widely between
+/// EPUB versions and tools, so the file extension is checked as well.
+fn style_kind(mime: &str, path: &Path) -> Option<StyleKind> {
+    let extension = path.extension().map(|e| e.to_string_lossy().to_ascii_lowercase()).unwrap_or_default();
+    if mime == "text/css" || extension == "css" {
+        return Some(StyleKind::Stylesheet);
+    }
+    let font_mime = mime.starts_with("font/")
+        || mime.starts_with("application/font-")
+        || mime.starts_with("application/x-font-")
+        || mime == "application/vnd.ms-opentype";
+    (font_mime || matches!(extension.as_str(), "ttf" | "otf" | "woff" | "woff2")).then_some(StyleKind::Font)
+}
@@ -0,0 +1,119 @@
+use crate::catalog::csv_field;
+use crate::isolate;
+use crate::output;
+use crate::saved::SavedBook;
+use anyhow::{Context, Result};
+use epub::doc::EpubDoc;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use walkdir::WalkDir;
+
+/// How one book of a batch fared
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunStatus {
+    Converted,
+    /// Restored from the conversion cache
+    Cached,
+    Failed,
+    /// Excluded by `--filter`
+    Skipped,
+    /// Left out by `--skip-samples`
+    Sample,
+    /// Stopped part-way by an interrupt
+    Interrupted,
+    /// Not reached before an interrupt
+    NotStarted,
+}
+
+impl RunStatus {
+    pub fn label(self) -> &'static str {
+        match self {
+            RunStatus::Converted => "converted",
+            RunStatus::Cached => "cached",
+            RunStatus::Failed => "failed",
+            RunStatus::Skipped => "skipped",
+            RunStatus::Sample => "sample",
+            RunStatus::Interrupted => "interrupted",
+            RunStatus::NotStarted => "not_started",
+        }
+    }
+}
+
+/// One book's run, as the batch saw it
+pub struct BookRun<'a> {
+    /// The book's name in the `source` column, which differs from `epub_path` for one
+    /// unpacked from an archive
+    pub source: String,
+    pub epub_path: &'a Path,
+    pub output_dir: &'a Path,
+    pub status: RunStatus,
+    /// Wall-clock time of the conversion, including opening, writing and any retries
+    pub elapsed: Duration,
+    /// Conversions attempted, more than one when it was retried
+    pub attempts: usize,
+}
+
+const CSV_COLUMNS: &[&str] =
+    &["source", "output", "status", "attempts", "seconds", "input_bytes", "output_bytes", "chapters", "images", "warnings"];
+
+/// Write `summary.csv` into `base`, one row per book in the batch with its wall-clock
+/// time, input and output sizes, chapter count, number of images in the EPUB and number
+/// of warnings. Rows
+/// are ordered slowest first, so the books holding up a run are at the top.
+pub fn write_summary(runs: &[BookRun], base: &Path) -> Result<PathBuf> {
+    let mut rows: Vec<(Duration, Vec<String>)> = Vec::with_capacity(runs.len());
+    for run in runs {
+        let relative = |p: &Path| p.strip_prefix(base).unwrap_or(p).to_string_lossy().into_owned();
+        let written = matches!(run.status, RunStatus::Converted | RunStatus::Cached);
+        let count = |n: Option<u64>| n.map(|n| n.to_string()).unwrap_or_default();
+        let saved = written.then(|| SavedBook::load(run.output_dir).ok()).flatten();
+        let fields = vec![
+            run.source.clone(),
+            relative(run.output_dir),
+            run.status.label().to_string(),
+            run.attempts.to_string(),
+            if matches!(run.status, RunStatus::Skipped | RunStatus::Sample) { String::new() } else { format!("{:.3}", run.elapsed.as_secs_f64()) },
+            count(fs::metadata(run.epub_path).ok().map(|m| m.len())),
+            count(written.then(|| directory_size(run.output_dir))),
+            count(saved.as_ref().map(|b| b.chapters.len() as u64)),
+            count(image_count(run.epub_path)),
+            count(saved.as_ref().map(|b| b.warnings.len() as u64)),
+        ];
+        rows.push((run.elapsed, fields));
+    }
+    rows.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+
+    let mut csv = CSV_COLUMNS.join(",");
+    csv.push('\n');
+    for (_, fields) in &rows {
+        let row: Vec<String> = fields.iter().map(|f| csv_field(f)).collect();
+        csv.push_str(&row.join(","));
+        csv.push('\n');
+    }
+    let path = base.join("summary.csv");
+    output::write_atomic(&path, csv).context("Failed to write summary.csv")?;
+    Ok(path)
+}
+
+/// Total size of the files in a book's output directory
+fn directory_size(dir: &Path) -> u64 {
+    WalkDir::new(dir)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .filter_map(|e| e.metadata().ok())
+        .map(|m| m.len())
+        .sum()
+}
+
+/// Image resources in the EPUB's manifest, or None if it can't be opened. The book may be
+/// one that failed by crashing the parser, so it is opened in isolation.
+fn image_count(epub_path: &Path) -> Option<u64> {
+    let path = epub_path.to_path_buf();
+    isolate::run_isolated(None, &isolate::CancelToken::default(), move || {
+        let doc = EpubDoc::new(&path).context("Failed to open EPUB file")?;
+        Ok(doc.resources.values().filter(|r| r.mime.starts_with("image/")).count() as u64)
+    })
+    .ok()
+}
@@ -0,0 +1,151 @@
+/// BISAC major subjects, by the three-letter prefix of their codes
+const BISAC_SECTIONS: &[(&str, &str)] = &[
+    ("ANT", "Antiques & Collectibles"),
+    ("ARC", "Architecture"),
+    ("ART", "Art"),
+    ("BIB", "Bibles"),
+    ("BIO", "Biography & Autobiography"),
+    ("BOD", "Body, Mind & Spirit"),
+    ("BUS", "Business & Economics"),
+    ("CGN", "Comics & Graphic Novels"),
+    ("CKB", "Cooking"),
+    ("COM", "Computers"),
+    ("CRA", "Crafts & Hobbies"),
+    ("DES", "Design"),
+    ("DRA", "Drama"),
+    ("EDU", "Education"),
+    ("FAM", "Family & Relationships"),
+    ("FIC", "Fiction"),
+    ("FOR", "Foreign Language Study"),
+    ("GAM", "Games & Activities"),
+    ("GAR", "Gardening"),
+    ("HEA", "Health & Fitness"),
+    ("HIS", "History"),
+    ("HOM", "House & Home"),
+    ("HUM", "Humor"),
+    ("JNF", "Juvenile Nonfiction"),
+    ("JUV", "Juvenile Fiction"),
+    ("LAN", "Language Arts & Disciplines"),
+    ("LAW", "Law"),
+    ("LCO", "Literary Collections"),
+    ("LIT", "Literary Criticism"),
+    ("MAT", "Mathematics"),
+    ("MED", "Medical"),
+    ("MUS", "Music"),
+    ("NAT", "Nature"),
+    ("PER", "Performing Arts"),
+    ("PET", "Pets"),
+    ("PHI", "Philosophy"),
+    ("PHO", "Photography"),
+    ("POE", "Poetry"),
+    ("POL", "Political Science"),
+    ("PSY", "Psychology"),
+    ("REF", "Reference"),
+    ("REL", "Religion"),
+    ("SCI", "Science"),
+    ("SEL", "Self-Help"),
+    ("SOC", "Social Science"),
+    ("SPO", "Sports & Recreation"),
+    ("STU", "Study Aids"),
+    ("TEC", "Technology & Engineering"),
+    ("TRA", "Transportation"),
+    ("TRU", "True Crime"),
+    ("TRV", "Travel"),
+    ("YAF", "Young Adult Fiction"),
+    ("YAN", "Young Adult Nonfiction"),
+];
+
+/// Headings of the fiction codes books are most often filed under
+const BISAC_HEADINGS: &[(&str, &str)] = &[
+    ("FIC002000", "Action & Adventure"),
+    ("FIC004000", "Classics"),
+    ("FIC009000", "Fantasy / General"),
+    ("FIC010000", "Fairy Tales, Folk Tales, Legends & Mythology"),
+    ("FIC012000", "Ghost"),
+    ("FIC014000", "Historical / General"),
+    ("FIC015000", "Horror"),
+    ("FIC016000", "Humorous / General"),
+    ("FIC019000", "Literary"),
+    ("FIC022000", "Mystery & Detective / General"),
+    ("FIC024000", "Occult & Supernatural"),
+    ("FIC025000", "Psychological"),
+    ("FIC027000", "Romance / General"),
+    ("FIC028000", "Science Fiction / General"),
+    ("FIC029000", "Short Stories (single author)"),
+    ("FIC030000", "Thrillers / Suspense"),
+    ("FIC031000", "Thrillers / General"),
+    ("FIC032000", "War & Military"),
+    ("FIC033000", "Westerns"),
+    ("FIC055000", "Dystopian"),
+];
+
+/// Heading levels that say nothing about the book
+const FILLER: &[&str] = &["general", "other", "miscellaneous"];
+
+/// Parse a `--tag-map SUBJECT=TAG` mapping; an empty tag drops the subject
+pub fn parse_mapping(spec: &str) -> Result<(String, String), String> {
+    let (from, to) = spec
+        .split_once('=')
+        .ok_or_else(|| format!("invalid tag mapping '{}' (expected SUBJECT=TAG)", spec))?;
+    let from = slug(from);
+    if from.is_empty() {
+        return Err(format!("invalid tag mapping '{}' (expected SUBJECT=TAG)", spec));
+    }
+    Ok((from, slug(to)))
+}
+
+/// Clean tags for a book's subjects: BISAC codes are expanded to their headings,
+/// headings (`FICTION / Science Fiction / General`) and subject strings
+/// (`Paris (France) -- Fiction; Mystery`) are split into their parts, filler levels are
+/// dropped, and every part is lowercased and slugged (`science-fiction`). `mapping` then
+/// renames or, mapped to nothing, drops tags. Tags are listed once each, in the order the
+/// subjects give them.
+pub fn normalize(subjects: &[String], mapping: &[(String, String)]) -> Vec<String> {
+    let mut tags: Vec<String> = Vec::new();
+    for subject in subjects {
+        for part in subject.split(';').map(|s| expand_bisac(s.trim())) {
+            for level in part.split(" / ").flat_map(|s| s.split(" -- ")) {
+                let tag = slug(level);
+                if tag.is_empty() || FILLER.contains(&tag.as_str()) {
+                    continue;
+                }
+                let tag = match mapping.iter().find(|(from, _)| *from == tag) {
+                    Some((_, to)) => to.clone(),
+                    None => tag,
+                };
+                if !tag.is_empty() && !tags.contains(&tag) {
+                    tags.push(tag);
+                }
+            }
+        }
+    }
+    tags
+}
+
+/// A BISAC code (`FIC028000`) as its heading, and any other subject as it is
+fn expand_bisac(subject: &str) -> String {
+    let code = subject.to_ascii_uppercase();
+    let is_code = code.len() == 9 && code[..3].chars().all(|c| c.is_ascii_alphabetic()) && code[3..].chars().all(|c| c.is_ascii_digit());
+    let section = BISAC_SECTIONS.iter().find(|(prefix, _)| is_code && code.starts_with(prefix));
+    match section {
+        Some((_, section)) => {
+            let heading = BISAC_HEADINGS.iter().find(|(known, _)| *known == code);
+            match heading {
+                Some((_, heading)) => format!("{} / {}", section, heading),
+                None => section.to_string(),
+            }
+        }
+        None => subject.to_string(),
+    }
+}
+
+/// Lowercased words joined by hyphens, with `&` read as `and` and other punctuation dropped
+fn slug(text: &str) -> String {
+    let text = text.replace('&', " and ");
+    let words: Vec<String> = text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+    words.join("-")
+}
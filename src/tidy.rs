@@ -0,0 +1,224 @@
+/// Emphasis delimiters whose empty pairs (`** **`) are removed
+const DELIMITERS: &[&str] = &["**", "__", "~~", "=="];
+
+/// Inline HTML elements (as the renderer writes them for some flavors) removed when empty
+const INLINE_TAGS: &[&str] = &["sup", "sub", "u", "ins", "mark", "del", "s", "span", "small", "em", "strong", "b", "i"];
+
+/// Tidy rendered Markdown: empty inline markup left by hollow spans is removed, runs of
+/// spaces inside lines and of blank lines between blocks are collapsed, trailing
+/// whitespace is trimmed (keeping two spaces for hard line breaks), and empty list items
+/// are dropped. Code blocks and tables are left exactly as they are.
+pub fn tidy(markdown: &str) -> String {
+    let lines: Vec<&str> = markdown.lines().collect();
+    let mut out: Vec<String> = Vec::with_capacity(lines.len());
+    let mut in_fence = false;
+    for (i, line) in lines.iter().enumerate() {
+        let trimmed = line.trim();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_fence = !in_fence;
+            out.push(line.trim_end().to_string());
+            continue;
+        }
+        let previous_blank = out.last().is_none_or(|l| l.is_empty());
+        let indented_code = previous_blank && (line.starts_with("    ") || line.starts_with('\t')) && !is_list_context(&out);
+        if in_fence || indented_code || trimmed.starts_with('|') {
+            out.push(line.to_string());
+            continue;
+        }
+
+        let mut text = collapse_spaces(&remove_empty_markup(line));
+        let content = text.trim();
+        if matches!(content, "*" | "-" | "+") || is_empty_numbered_item(content) {
+            continue;
+        }
+        if content.is_empty() {
+            // A line emptied of its markup is one more blank line, and blank lines collapse
+            if out.last().is_some_and(|l| !l.is_empty()) {
+                out.push(String::new());
+            }
+            continue;
+        }
+
+        // A single stray space before a paragraph is left by hollow inline elements
+        if previous_blank && text.starts_with(' ') && !text.starts_with("  ") {
+            text.remove(0);
+        }
+        let hard_break = line.ends_with("  ") && lines.get(i + 1).is_some_and(|next| !next.trim().is_empty());
+        let mut text = text.trim_end().to_string();
+        if hard_break {
+            text.push_str("  ");
+        }
+        out.push(text);
+    }
+    while out.last().is_some_and(|l| l.is_empty()) {
+        out.pop();
+    }
+
+    let mut tidied = out.join("\n");
+    if markdown.ends_with('\n') {
+        tidied.push('\n');
+    }
+    tidied
+}
+
+/// Whether the block being written is a list, whose continuation lines are indented
+fn is_list_context(out: &[String]) -> bool {
+    out.iter().rev().find(|l| !l.is_empty()).is_some_and(|l| {
+        let t = l.trim_start();
+        l.starts_with(' ') || ["* ", "- ", "+ "].iter().any(|m| t.starts_with(m)) || is_numbered_item(t)
+    })
+}
+
+fn is_numbered_item(text: &str) -> bool {
+    text.split_once(". ").is_some_and(|(n, _)| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()))
+}
+
+fn is_empty_numbered_item(text: &str) -> bool {
+    text.strip_suffix('.').is_some_and(|n| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()))
+}
+
+/// A line with its empty emphasis pairs and empty inline HTML elements removed, outside
+/// code spans
+fn remove_empty_markup(line: &str) -> String {
+    let mut text = line.to_string();
+    for tag in INLINE_TAGS {
+        text = remove_empty_tags(&text, tag);
+    }
+    for delimiter in DELIMITERS {
+        text = remove_empty_pairs(&text, delimiter);
+    }
+    text
+}
+
+/// Remove `<tag></tag>` (with any attributes, and whitespace only inside)
+fn remove_empty_tags(line: &str, tag: &str) -> String {
+    let open = format!("<{}", tag);
+    let close = format!("</{}>", tag);
+    let mut out = String::with_capacity(line.len());
+    let mut rest = line;
+    while let Some(start) = rest.find(&open) {
+        let after_name = &rest[start + open.len()..];
+        let tag_end = after_name
+            .starts_with(['>', ' '])
+            .then(|| after_name.find('>'))
+            .flatten()
+            .filter(|&end| !after_name[..end].contains('<') && !after_name[..end].ends_with('/'));
+        let inner = tag_end.map(|end| &after_name[end + 1..]);
+        match inner.and_then(|inner| {
+            let content = inner.trim_start();
+            content.strip_prefix(close.as_str()).map(|after| (inner.len() > content.len(), after))
+        }) {
+            Some((spaced, after)) => {
+                out.push_str(&rest[..start]);
+                if spaced {
+                    out.push(' ');
+                }
+                rest = after;
+            }
+            None => {
+                out.push_str(&rest[..start + open.len()]);
+                rest = after_name;
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Remove pairs of `delimiter` with nothing but whitespace between them, such as the
+/// `** **` a bold span holding a space turns into. Only a pair standing apart from words
+/// counts, so `**a** **b**` keeps its markup.
+fn remove_empty_pairs(line: &str, delimiter: &str) -> String {
+    if !line.contains(delimiter) {
+        return line.to_string();
+    }
+    let marker = delimiter.chars().next().unwrap_or('*');
+    let stands_apart = |c: Option<char>| c.is_none_or(|c| !c.is_alphanumeric() && c != marker);
+    let mut out = String::with_capacity(line.len());
+    let mut rest = line;
+    while let Some(start) = rest.find(delimiter) {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + delimiter.len()..];
+        let content = after.trim_start();
+        let in_code = out.matches('`').count() % 2 == 1;
+        let closing = content.strip_prefix(delimiter).filter(|after_close| stands_apart(after_close.chars().next()));
+        match closing {
+            Some(after_close) if !in_code && stands_apart(out.chars().next_back()) => {
+                if after.len() > content.len() {
+                    out.push(' ');
+                }
+                rest = after_close;
+            }
+            _ => {
+                out.push_str(delimiter);
+                rest = after;
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Runs of spaces inside a line reduced to one, leaving its indentation and code spans
+fn collapse_spaces(line: &str) -> String {
+    let indent = line.len() - line.trim_start_matches(' ').len();
+    let mut out = String::with_capacity(line.len());
+    out.push_str(&line[..indent]);
+    let mut in_code = false;
+    let mut last_space = false;
+    for c in line[indent..].chars() {
+        if c == '`' {
+            in_code = !in_code;
+        }
+        if c == ' ' && last_space && !in_code {
+            continue;
+        }
+        last_space = c == ' ';
+        out.push(c);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blank_lines_and_spaces_collapse() {
+        assert_eq!(tidy("One  two   three.   \n\n\n\nNext.\n\n"), "One two three.\n\nNext.\n");
+        // Indentation is kept, and two spaces before a following line stay a hard break
+        assert_eq!(tidy("Line one  \nline two  \n\n  - nested  item"), "Line one  \nline two\n\n  - nested item");
+    }
+
+    #[test]
+    fn empty_markup_is_removed() {
+        assert_eq!(tidy("A ** ** gap and __ __ here."), "A gap and here.");
+        assert_eq!(tidy("x<sup></sup>y and <span class=\"a\"> </span>z"), "xy and z");
+        // Pairs next to words are real markup
+        assert_eq!(tidy("**a** **b**"), "**a** **b**");
+        // A line left holding only markup is a blank line
+        assert_eq!(tidy("Para.\n<em></em>\n\nNext."), "Para.\n\nNext.");
+        assert_eq!(tidy("<i> </i>Hollow start."), "Hollow start.");
+    }
+
+    #[test]
+    fn empty_list_items_are_dropped() {
+        assert_eq!(tidy("- one\n-\n* \n3.\n- two"), "- one\n- two");
+    }
+
+    #[test]
+    fn code_and_tables_are_left_as_rendered() {
+        let fenced = "```\na  **  **  b\n\n\n\n```\n";
+        assert_eq!(tidy(fenced), fenced);
+        assert_eq!(tidy("Text.\n\n    code  **  **\n"), "Text.\n\n    code  **  **\n");
+        assert_eq!(tidy("| a  | b |\n|----|---|"), "| a  | b |\n|----|---|");
+        assert_eq!(tidy("Use `a  ** **  b` here."), "Use `a  ** **  b` here.");
+    }
+
+    #[test]
+    fn tidying_twice_changes_nothing() {
+        let markdown = "# Title\n\n\nA ** ** b  c.  \nd\n\n-\n- item\n\n```\nx  y\n```\n";
+        let once = tidy(markdown);
+        assert_eq!(tidy(&once), once);
+    }
+}
@@ -0,0 +1,458 @@
+use crate::html;
+use clap::ValueEnum;
+use epub::doc::{EpubDoc, NavPoint};
+use markup5ever_rcdom::Handle;
+use std::collections::{HashMap, HashSet};
+use std::io::{Read, Seek};
+use std::path::{Path, PathBuf};
+
+/// Characters escaped in the fragment of a link to an Obsidian heading
+const OBSIDIAN_FRAGMENT: &percent_encoding::AsciiSet = &percent_encoding::CONTROLS.add(b' ').add(b'(').add(b')').add(b'<').add(b'>').add(b'%');
+
+/// How heading anchors are spelled, to match the viewer the Markdown is read in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum SlugStyle {
+    /// GitHub: lowercase, punctuation dropped, each space a hyphen, repeats numbered `-1`, `-2`
+    #[default]
+    Github,
+    /// GitLab: as GitHub, with runs of hyphens collapsed to one
+    Gitlab,
+    /// Obsidian: the heading text itself, less the characters its links can't hold
+    Obsidian,
+    /// Lowercase letters and digits, every other run of characters one --slug-separator
+    Custom,
+}
+
+/// `--slug-style`, with the separator custom slugs use
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SlugRules {
+    pub style: SlugStyle,
+    pub separator: String,
+}
+
+impl Default for SlugRules {
+    fn default() -> Self {
+        Self { style: SlugStyle::Github, separator: "-".to_string() }
+    }
+}
+
+impl SlugRules {
+    /// A heading's anchor, before any numbering of repeats
+    pub fn slug(&self, text: &str) -> String {
+        match self.style {
+            SlugStyle::Github => github_slug(text),
+            SlugStyle::Gitlab => {
+                let slug: String = text
+                    .trim()
+                    .to_lowercase()
+                    .chars()
+                    .filter_map(|c| match c {
+                        ' ' => Some('-'),
+                        c if c.is_alphanumeric() || c == '-' || c == '_' => Some(c),
+                        _ => None,
+                    })
+                    .collect();
+                let mut collapsed = String::with_capacity(slug.len());
+                for c in slug.chars() {
+                    if !(c == '-' && collapsed.ends_with('-')) {
+                        collapsed.push(c);
+                    }
+                }
+                collapsed
+            }
+            SlugStyle::Obsidian => {
+                let text: String = text.chars().filter(|c| !matches!(c, '#' | '^' | '[' | ']' | '|')).collect();
+                text.split_whitespace().collect::<Vec<_>>().join(" ")
+            }
+            SlugStyle::Custom => {
+                let lower = text.to_lowercase();
+                let words: Vec<&str> = lower.split(|c: char| !c.is_alphanumeric()).filter(|w| !w.is_empty()).collect();
+                words.join(&self.separator)
+            }
+        }
+    }
+
+    /// An anchor as written after the `#` of a link
+    pub fn fragment(&self, slug: &str) -> String {
+        match self.style {
+            SlugStyle::Obsidian => percent_encoding::utf8_percent_encode(slug, OBSIDIAN_FRAGMENT).to_string(),
+            _ => slug.to_string(),
+        }
+    }
+
+    /// How the `n`th repeat of an anchor is told apart, or None when repeats share it
+    fn numbered(&self, base: &str, n: usize) -> Option<String> {
+        match self.style {
+            SlugStyle::Obsidian => None,
+            SlugStyle::Custom => Some(format!("{}{}{}", base, self.separator, n)),
+            _ => Some(format!("{}-{}", base, n)),
+        }
+    }
+}
+
+/// Archive path a TOC entry points at, without its fragment and with `.`/`..` resolved
+pub fn target_path(content: &Path) -> String {
+    let raw = content.to_string_lossy();
+    let without_fragment = raw.split('#').next().unwrap_or_default();
+    let decoded = percent_encoding::percent_decode_str(without_fragment).decode_utf8_lossy();
+
+    let mut parts: Vec<&str> = Vec::new();
+    for segment in decoded.split(['/', '\\']) {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            s => parts.push(s),
+        }
+    }
+    parts.join("/")
+}
+
+/// Archive path of the EPUB3 navigation document (manifest `properties="nav"`), if any
+pub fn nav_document<R: Read + Seek>(doc: &EpubDoc<R>) -> Option<PathBuf> {
+    doc.resources
+        .values()
+        .find(|item| item.properties.as_deref().is_some_and(|p| p.split_whitespace().any(|p| p == "nav")))
+        .map(|item| item.path.clone())
+}
+
+/// Fill in the TOC of a book that has no NCX from its navigation document, which is all an
+/// EPUB3 book is required to have; the epub crate only reads the NCX
+pub fn fill_from_nav<R: Read + Seek>(doc: &mut EpubDoc<R>) {
+    if !doc.toc.is_empty() {
+        return;
+    }
+    let Some(path) = nav_document(doc) else { return };
+    if let Some(xhtml) = doc.get_resource_str_by_path(&path) {
+        doc.toc = from_nav(&xhtml, &path);
+    }
+}
+
+/// The entries of a navigation document's `toc` nav (or its first nav, when none is
+/// marked), as the NCX would give them: archive paths with any fragment, and a play
+/// order following the document. A heading without a link points where its first
+/// entry does.
+pub fn from_nav(xhtml: &str, path: &Path) -> Vec<NavPoint> {
+    let dom = html::parse(xhtml);
+    let mut navs = Vec::new();
+    html::walk(&dom.document, &mut |node| {
+        if html::tag_name(node).as_deref() == Some("nav") {
+            navs.push(node.clone());
+        }
+    });
+    let is_toc = |nav: &&Handle| html::epub_type(nav).is_some_and(|t| t.split_whitespace().any(|t| t == "toc"));
+    let Some(nav) = navs.iter().find(is_toc).or(navs.first()) else {
+        return Vec::new();
+    };
+    let base = path.parent().unwrap_or(Path::new(""));
+    let mut order = 0;
+    match child_element(nav, "ol") {
+        Some(list) => nav_points(&list, base, &mut order),
+        None => Vec::new(),
+    }
+}
+
+/// The first child element of `node` with the tag `tag`
+fn child_element(node: &Handle, tag: &str) -> Option<Handle> {
+    node.children.borrow().iter().find(|child| html::tag_name(child).as_deref() == Some(tag)).cloned()
+}
+
+/// The entries of a nav's `<ol>`, each an `<li>` holding an `<a>` or `<span>` label and
+/// perhaps an `<ol>` of its own
+fn nav_points(list: &Handle, base: &Path, order: &mut usize) -> Vec<NavPoint> {
+    let mut points = Vec::new();
+    for item in list.children.borrow().iter().filter(|child| html::tag_name(child).as_deref() == Some("li")) {
+        let label = child_element(item, "a").or_else(|| child_element(item, "span"));
+        let href = label.as_ref().and_then(|label| html::attr(label, "href"));
+        let play_order = *order;
+        *order += 1;
+        let children = child_element(item, "ol").map(|sublist| nav_points(&sublist, base, order)).unwrap_or_default();
+        let content = match href {
+            Some(href) => base.join(href),
+            None => match children.first() {
+                Some(first) => first.content.clone(),
+                None => continue,
+            },
+        };
+        points.push(NavPoint {
+            label: label.map(|label| html::text_content(&label)).unwrap_or_default(),
+            content,
+            children,
+            play_order: Some(play_order),
+        });
+    }
+    points
+}
+
+/// Map each spine document path to the label of the first TOC entry pointing into it
+pub fn labels_by_path(toc: &[NavPoint]) -> HashMap<String, String> {
+    fn visit(points: &[NavPoint], labels: &mut HashMap<String, String>) {
+        for point in points {
+            labels
+                .entry(target_path(&point.content))
+                .or_insert_with(|| point.label.trim().to_string());
+            visit(&point.children, labels);
+        }
+    }
+
+    let mut labels = HashMap::new();
+    visit(toc, &mut labels);
+    labels
+}
+
+/// Whether Markdown already opens with a heading. A single short line directly
+/// before the heading is tolerated, since html2md echoes the XHTML `<title>` there.
+pub fn starts_with_heading(markdown: &str) -> bool {
+    let lines: Vec<&str> = markdown.lines().map(str::trim_end).collect();
+    let is_heading = |i: usize| -> bool {
+        let Some(line) = lines.get(i) else { return false };
+        if line.trim_start().starts_with('#') {
+            return true;
+        }
+        // Setext heading: text underlined by === or ---
+        lines.get(i + 1).is_some_and(|next| {
+            let next = next.trim();
+            !line.trim().is_empty()
+                && next.len() >= 2
+                && (next.chars().all(|c| c == '=') || next.chars().all(|c| c == '-'))
+        })
+    };
+    let non_blank: Vec<usize> = (0..lines.len()).filter(|&i| !lines[i].trim().is_empty()).collect();
+
+    match non_blank.as_slice() {
+        [] => false,
+        [first, ..] if is_heading(*first) => true,
+        [first, second, ..] => {
+            let line = lines[*first].trim();
+            line.chars().count() <= 80 && !line.ends_with(['.', '!', '?', ':']) && is_heading(*second)
+        }
+        _ => false,
+    }
+}
+
+fn is_fence(line: &str) -> bool {
+    let t = line.trim_start();
+    t.starts_with("```") || t.starts_with("~~~")
+}
+
+/// Level and text of an ATX heading line such as `## Title`
+fn atx_heading(line: &str) -> Option<(usize, &str)> {
+    let hashes = line.len() - line.trim_start_matches('#').len();
+    let rest = &line[hashes..];
+    if !(1..=6).contains(&hashes) || !(rest.is_empty() || rest.starts_with(' ')) {
+        return None;
+    }
+    Some((hashes, rest.trim().trim_end_matches('#').trim_end()))
+}
+
+/// Setext underline level: `===` is 1, `---` is 2
+fn setext_level(line: &str) -> Option<usize> {
+    let t = line.trim();
+    if t.len() < 2 {
+        None
+    } else if t.chars().all(|c| c == '=') {
+        Some(1)
+    } else if t.chars().all(|c| c == '-') {
+        Some(2)
+    } else {
+        None
+    }
+}
+
+/// Demote every heading `by` levels (capped at 6), rewriting setext headings as ATX.
+/// Fenced code is left alone.
+pub fn shift_headings(markdown: &str, by: usize) -> String {
+    let lines: Vec<&str> = markdown.lines().collect();
+    let mut out = String::with_capacity(markdown.len() + 64);
+    let mut in_fence = false;
+    let mut i = 0;
+
+    while i < lines.len() {
+        let line = lines[i];
+        if is_fence(line) {
+            in_fence = !in_fence;
+        }
+        if !in_fence {
+            if let Some((level, text)) = atx_heading(line) {
+                out.push_str(&format!("{} {}\n", "#".repeat((level + by).min(6)), text));
+                i += 1;
+                continue;
+            }
+            let starts_paragraph = i == 0 || lines[i - 1].trim().is_empty();
+            let underline = lines.get(i + 1).and_then(|next| setext_level(next));
+            if let (true, Some(level), false) = (starts_paragraph, underline, line.trim().is_empty()) {
+                out.push_str(&format!("{} {}\n", "#".repeat((level + by).min(6)), line.trim()));
+                i += 2;
+                continue;
+            }
+        }
+        out.push_str(line);
+        out.push('\n');
+        i += 1;
+    }
+    if !markdown.ends_with('\n') {
+        out.pop();
+    }
+    out
+}
+
+/// ATX headings outside fenced code, as (level, plain text)
+pub fn headings(markdown: &str) -> Vec<(usize, String)> {
+    let mut found = Vec::new();
+    let mut in_fence = false;
+    for line in markdown.lines() {
+        if is_fence(line) {
+            in_fence = !in_fence;
+            continue;
+        }
+        if in_fence {
+            continue;
+        }
+        if let Some((level, text)) = atx_heading(line) {
+            let text = plain_text(text);
+            if !text.is_empty() {
+                found.push((level, text));
+            }
+        }
+    }
+    found
+}
+
+/// ATX and setext headings outside fenced code, as (line index, level, plain text)
+pub fn heading_lines(markdown: &str) -> Vec<(usize, usize, String)> {
+    let lines: Vec<&str> = markdown.lines().collect();
+    let mut found = Vec::new();
+    let mut in_fence = false;
+    for (i, line) in lines.iter().enumerate() {
+        if is_fence(line) {
+            in_fence = !in_fence;
+            continue;
+        }
+        if in_fence {
+            continue;
+        }
+        let heading = match atx_heading(line) {
+            Some((level, text)) => Some((level, text)),
+            None => {
+                let starts_paragraph = i == 0 || lines[i - 1].trim().is_empty();
+                let underline = lines.get(i + 1).and_then(|next| setext_level(next));
+                underline.filter(|_| starts_paragraph && !line.trim().is_empty()).map(|level| (level, line.trim()))
+            }
+        };
+        if let Some((level, text)) = heading {
+            let text = plain_text(text);
+            if !text.is_empty() {
+                found.push((i, level, text));
+            }
+        }
+    }
+    found
+}
+
+/// Heading text with inline link and emphasis markup removed
+fn plain_text(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            // `[label](target)` keeps only the label
+            ']' if chars.peek() == Some(&'(') => {
+                for c in chars.by_ref() {
+                    if c == ')' {
+                        break;
+                    }
+                }
+            }
+            '[' | ']' | '*' | '`' => {}
+            '\\' => out.extend(chars.next()),
+            c => out.push(c),
+        }
+    }
+    out.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// GitHub-style heading anchor: lowercase, punctuation dropped, spaces as hyphens
+pub fn github_slug(text: &str) -> String {
+    text.trim()
+        .to_lowercase()
+        .chars()
+        .filter_map(|c| match c {
+            ' ' => Some('-'),
+            c if c.is_alphanumeric() || c == '-' || c == '_' => Some(c),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Hands out unique anchors the way the viewer does, suffixing repeats with `-1`, `-2`,
+/// ... (GitHub's rules unless made `with_rules`). Obsidian links repeated headings to the
+/// first, so its anchors are handed out as they are.
+#[derive(Default)]
+pub struct SlugSet {
+    rules: SlugRules,
+    used: HashSet<String>,
+    repeats: HashMap<String, usize>,
+}
+
+impl SlugSet {
+    pub fn with_rules(rules: SlugRules) -> Self {
+        Self { rules, ..Self::default() }
+    }
+
+    pub fn claim(&mut self, text: &str) -> String {
+        let base = self.rules.slug(text);
+        let mut slug = base.clone();
+        while self.used.contains(&slug) {
+            let n = self.repeats.entry(base.clone()).or_default();
+            *n += 1;
+            match self.rules.numbered(&base, *n) {
+                Some(numbered) => slug = numbered,
+                None => break,
+            }
+        }
+        self.used.insert(slug.clone());
+        slug
+    }
+
+    /// A claimed anchor as written after the `#` of a link
+    pub fn fragment(&self, slug: &str) -> String {
+        self.rules.fragment(slug)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nav_entries_are_read_like_the_ncx() {
+        let nav = r#"<html xmlns:epub="http://www.idpf.org/2007/ops"><body>
+            <nav epub:type="landmarks"><ol><li><a href="cover.xhtml">Cover</a></li></ol></nav>
+            <nav epub:type="toc"><ol>
+              <li><span>Part One</span><ol>
+                <li><a href="text/ch1.xhtml">Chapter <em>1</em></a></li>
+                <li><a href="text/ch1.xhtml#s2">Section 2</a></li>
+              </ol></li>
+              <li><a href="../notes.xhtml">Notes</a></li>
+            </ol></nav>
+        </body></html>"#;
+        let toc = from_nav(nav, Path::new("OEBPS/nav.xhtml"));
+
+        let labels: Vec<&str> = toc.iter().map(|point| point.label.as_str()).collect();
+        assert_eq!(labels, ["Part One", "Notes"]);
+        // The heading points where its first entry does
+        assert_eq!(toc[0].content, Path::new("OEBPS/text/ch1.xhtml"));
+        assert_eq!(toc[0].children[0].label, "Chapter 1");
+        assert_eq!(toc[0].children[1].content, Path::new("OEBPS/text/ch1.xhtml#s2"));
+        assert_eq!(target_path(&toc[1].content), "notes.xhtml");
+        let orders: Vec<_> = [&toc[0], &toc[0].children[0], &toc[0].children[1], &toc[1]].iter().map(|p| p.play_order).collect();
+        assert_eq!(orders, [Some(0), Some(1), Some(2), Some(3)]);
+    }
+
+    #[test]
+    fn a_nav_document_without_entries_gives_no_toc() {
+        assert!(from_nav("<html><body><p>No navigation</p></body></html>", Path::new("nav.xhtml")).is_empty());
+        assert!(from_nav("<html><body><nav><ol></ol></nav></body></html>", Path::new("nav.xhtml")).is_empty());
+    }
+}
@@ -0,0 +1,728 @@
+use crate::digest::Sha256;
+use anyhow::{Context, Result};
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use rayon::prelude::*;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::Duration;
+use walkdir::WalkDir;
+
+/// Cloud storage service an output URL points at
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Provider {
+    S3,
+    Gcs,
+    Azure,
+}
+
+const SCHEMES: &[(&str, Provider)] = &[
+    ("s3://", Provider::S3),
+    ("gs://", Provider::Gcs),
+    ("gcs://", Provider::Gcs),
+    ("azure://", Provider::Azure),
+    ("az://", Provider::Azure),
+];
+
+/// Whether an `-o` value names object storage rather than a local directory
+pub fn is_object_url(output: &str) -> bool {
+    let lower = output.to_ascii_lowercase();
+    SCHEMES.iter().any(|(scheme, _)| lower.starts_with(scheme))
+}
+
+/// An object storage location: a bucket (an Azure container) and a key prefix in it
+#[derive(Debug, Clone)]
+pub struct Destination {
+    provider: Provider,
+    bucket: String,
+    prefix: String,
+}
+
+impl Destination {
+    pub fn parse(url: &str) -> Result<Self> {
+        let lower = url.to_ascii_lowercase();
+        let (scheme, provider) = SCHEMES
+            .iter()
+            .find(|(scheme, _)| lower.starts_with(scheme))
+            .with_context(|| format!("Not an object storage URL: {}", url))?;
+        let rest = &url[scheme.len()..];
+        let (bucket, prefix) = rest.split_once('/').unwrap_or((rest, ""));
+        if bucket.is_empty() {
+            anyhow::bail!("No bucket in {} (expected e.g. {}bucket/prefix)", url, scheme);
+        }
+        Ok(Self {
+            provider: *provider,
+            bucket: bucket.to_string(),
+            prefix: prefix.trim_matches('/').to_string(),
+        })
+    }
+
+        /// The URL as it is shown, in the provider's usual form
+    fn url(&self) -> String {
+        let scheme = match self.provider {
+            Provider::S3 => "s3",
+            Provider::Gcs => "gs",
+            Provider::Azure => "az",
+        };
+        match self.prefix.as_str() {
+            "" => format!("{}://{}", scheme, self.bucket),
+            prefix => format!("{}://{}/{}", scheme, self.bucket, prefix),
+        }
+    }
+
+    /// Object key for a file at `relative` (with `/` separators) under the uploaded directory
+    fn key(&self, relative: &str) -> String {
+        match self.prefix.as_str() {
+            "" => relative.to_string(),
+            prefix => format!("{}/{}", prefix, relative),
+        }
+    }
+}
+
+impl fmt::Display for Destination {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.url())
+    }
+}
+
+/// Files at least this big are uploaded in parts of this size: above S3's 5 MiB minimum
+/// and a multiple of the 256 KiB GCS wants for resumable chunks
+const PART_SIZE: usize = 8 * 1024 * 1024;
+
+/// Attempts at each request before the upload fails
+const ATTEMPTS: u32 = 5;
+
+/// Wait before the first retry, doubled for each one after
+const RETRY_DELAY: Duration = Duration::from_millis(500);
+
+/// Characters escaped in each segment of an object key
+const KEY_SEGMENT: &AsciiSet = &NON_ALPHANUMERIC.remove(b'-').remove(b'.').remove(b'_').remove(b'~');
+
+/// How requests to the provider are signed
+#[derive(Debug, Clone)]
+enum Auth {
+    /// AWS Signature Version 4, which curl computes
+    SigV4 { region: String, key_id: String, secret: String, token: Option<String> },
+    /// An OAuth access token
+    Bearer(String),
+    /// A shared access signature appended to every URL
+    Sas(String),
+}
+
+/// A client for one provider's REST API. Requests go through curl, as downloads do;
+/// failed ones are retried with backoff, and large files are uploaded in parts.
+#[derive(Debug, Clone)]
+struct Client {
+    provider: Provider,
+    /// Base URL the bucket lives under
+    endpoint: String,
+    auth: Auth,
+    part_size: usize,
+    retry_delay: Duration,
+}
+
+impl Client {
+    /// A client for `destination`, configured from the environment variables each
+    /// provider's own tools read
+    fn from_env(destination: &Destination) -> Result<Self> {
+        let var = |name: &str| std::env::var(name).ok().filter(|v| !v.is_empty());
+        let (endpoint, auth) = match destination.provider {
+            Provider::S3 => {
+                let region = var("AWS_REGION").or_else(|| var("AWS_DEFAULT_REGION")).unwrap_or_else(|| "us-east-1".to_string());
+                let (Some(key_id), Some(secret)) = (var("AWS_ACCESS_KEY_ID"), var("AWS_SECRET_ACCESS_KEY")) else {
+                    anyhow::bail!("Uploading to {} needs AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY", destination);
+                };
+                let endpoint = var("AWS_ENDPOINT_URL").unwrap_or_else(|| format!("https://s3.{}.amazonaws.com", region));
+                (endpoint, Auth::SigV4 { region, key_id, secret, token: var("AWS_SESSION_TOKEN") })
+            }
+            Provider::Gcs => {
+                let token = match var("GOOGLE_OAUTH_ACCESS_TOKEN") {
+                    Some(token) => token,
+                    None => gcloud_token().with_context(|| format!("Uploading to {} needs GOOGLE_OAUTH_ACCESS_TOKEN or a signed-in gcloud", destination))?,
+                };
+                let endpoint = var("STORAGE_EMULATOR_HOST").unwrap_or_else(|| "https://storage.googleapis.com".to_string());
+                (endpoint, Auth::Bearer(token))
+            }
+            Provider::Azure => {
+                let Some(sas) = var("AZURE_STORAGE_SAS_TOKEN") else {
+                    anyhow::bail!("Uploading to {} needs AZURE_STORAGE_SAS_TOKEN", destination);
+                };
+                let endpoint = match (var("AZURE_STORAGE_ENDPOINT"), var("AZURE_STORAGE_ACCOUNT")) {
+                    (Some(endpoint), _) => endpoint,
+                    (None, Some(account)) => format!("https://{}.blob.core.windows.net", account),
+                    (None, None) => anyhow::bail!("Uploading to {} needs AZURE_STORAGE_ACCOUNT", destination),
+                };
+                (endpoint, Auth::Sas(sas.trim_start_matches('?').to_string()))
+            }
+        };
+        Ok(Self {
+            provider: destination.provider,
+            endpoint: endpoint.trim_end_matches('/').to_string(),
+            auth,
+            part_size: PART_SIZE,
+            retry_delay: RETRY_DELAY,
+        })
+    }
+
+    /// Upload one file as `key` in `bucket`
+    fn upload_file(&self, path: &Path, bucket: &str, key: &str) -> Result<()> {
+        let size = fs::metadata(path)?.len();
+        let content_type = content_type(path);
+        if size < self.part_size as u64 {
+            let data = fs::read(path)?;
+            return self.put(bucket, key, content_type, &data);
+        }
+        let mut file = File::open(path)?;
+        match self.provider {
+            Provider::S3 => self.s3_multipart(&mut file, bucket, key, content_type),
+            Provider::Gcs => self.gcs_resumable(&mut file, size, bucket, key, content_type),
+            Provider::Azure => self.azure_blocks(&mut file, bucket, key, content_type),
+        }
+    }
+
+    /// Upload a small object in one request
+    fn put(&self, bucket: &str, key: &str, content_type: &str, data: &[u8]) -> Result<()> {
+        let content = format!("Content-Type: {}", content_type);
+        match self.provider {
+            Provider::S3 => self.send("PUT", &self.object_url(bucket, key, ""), &[&content], Some(data))?,
+            Provider::Gcs => self.send("POST", &self.gcs_upload_url(bucket, key, "media"), &[&content], Some(data))?,
+            Provider::Azure => self.send("PUT", &self.object_url(bucket, key, ""), &[&content, "x-ms-blob-type: BlockBlob"], Some(data))?,
+        };
+        Ok(())
+    }
+
+    fn s3_multipart(&self, file: &mut File, bucket: &str, key: &str, content_type: &str) -> Result<()> {
+        let content = format!("Content-Type: {}", content_type);
+        let started = self.send("POST", &self.object_url(bucket, key, "uploads"), &[&content], Some(&[]))?;
+        let id = xml_text(&started.body, "UploadId").context("No UploadId in the reply to starting a multipart upload")?;
+        let query = format!("uploadId={}", utf8_percent_encode(&id, KEY_SEGMENT));
+
+        let parts = (|| -> Result<String> {
+            let mut parts = String::new();
+            let mut number = 1;
+            while let Some(part) = read_part(file, self.part_size)? {
+                let url = self.object_url(bucket, key, &format!("partNumber={}&{}", number, query));
+                let reply = self.send("PUT", &url, &[], Some(&part))?;
+                let etag = reply.header("etag").context("No ETag in the reply to uploading a part")?;
+                parts.push_str(&format!("<Part><PartNumber>{}</PartNumber><ETag>{}</ETag></Part>", number, xml_escape(etag)));
+                number += 1;
+            }
+            Ok(parts)
+        })();
+        let completed = parts.and_then(|parts| {
+            let body = format!("<CompleteMultipartUpload>{}</CompleteMultipartUpload>", parts);
+            let reply = self.send("POST", &self.object_url(bucket, key, &query), &["Content-Type: application/xml"], Some(body.as_bytes()))?;
+            // S3 can fail a completion after it has started sending a 200
+            if reply.body.contains("<Error>") {
+                anyhow::bail!("Failed to complete the multipart upload: {}", reply.body.trim());
+            }
+            Ok(())
+        });
+        if completed.is_err() {
+            // Parts already stored are billed until the upload is aborted
+            let _ = self.send("DELETE", &self.object_url(bucket, key, &query), &[], None);
+        }
+        completed
+    }
+
+    fn gcs_resumable(&self, file: &mut File, size: u64, bucket: &str, key: &str, content_type: &str) -> Result<()> {
+        let content = format!("X-Upload-Content-Type: {}", content_type);
+        let started = self.send("POST", &self.gcs_upload_url(bucket, key, "resumable"), &[&content], Some(&[]))?;
+        let session = started.header("location").context("No session URL in the reply to starting a resumable upload")?.to_string();
+        let mut start = 0u64;
+        while let Some(chunk) = read_part(file, self.part_size)? {
+            let end = start + chunk.len() as u64;
+            let range = format!("Content-Range: bytes {}-{}/{}", start, end - 1, size);
+            // 308 asks for the next chunk; the last one gets 200 or 201
+            self.send("PUT", &session, &[&range], Some(&chunk))?;
+            start = end;
+        }
+        Ok(())
+    }
+
+    fn azure_blocks(&self, file: &mut File, container: &str, key: &str, content_type: &str) -> Result<()> {
+        let mut list = String::new();
+        let mut number = 0;
+        while let Some(block) = read_part(file, self.part_size)? {
+            // Block ids have to be the same length within a blob
+            let id = base64(format!("block-{:06}", number).as_bytes());
+            let url = self.object_url(container, key, &format!("comp=block&blockid={}", utf8_percent_encode(&id, KEY_SEGMENT)));
+            self.send("PUT", &url, &[], Some(&block))?;
+            list.push_str(&format!("<Latest>{}</Latest>", id));
+            number += 1;
+        }
+        let body = format!("<?xml version=\"1.0\" encoding=\"utf-8\"?><BlockList>{}</BlockList>", list);
+        let content = format!("x-ms-blob-content-type: {}", content_type);
+        self.send("PUT", &self.object_url(container, key, "comp=blocklist"), &[&content], Some(body.as_bytes()))?;
+        Ok(())
+    }
+
+    /// `{endpoint}/{bucket}/{key}`, with the query and, for Azure, the signature
+    fn object_url(&self, bucket: &str, key: &str, query: &str) -> String {
+        let key: Vec<String> = key.split('/').map(|segment| utf8_percent_encode(segment, KEY_SEGMENT).to_string()).collect();
+        self.with_query(format!("{}/{}/{}", self.endpoint, bucket, key.join("/")), query)
+    }
+
+    fn gcs_upload_url(&self, bucket: &str, key: &str, upload_type: &str) -> String {
+        format!(
+            "{}/upload/storage/v1/b/{}/o?uploadType={}&name={}",
+            self.endpoint,
+            bucket,
+            upload_type,
+            utf8_percent_encode(key, KEY_SEGMENT)
+        )
+    }
+
+    fn with_query(&self, url: String, query: &str) -> String {
+        let sas = match &self.auth {
+            Auth::Sas(sas) => sas.as_str(),
+            _ => "",
+        };
+        match (query, sas) {
+            ("", "") => url,
+            (query, "") | ("", query) => format!("{}?{}", url, query),
+            (query, sas) => format!("{}?{}&{}", url, query, sas),
+        }
+    }
+
+    /// Send a request, retrying transport errors, throttling and server errors with
+    /// backoff. Other error statuses fail at once.
+    fn send(&self, method: &str, url: &str, headers: &[&str], body: Option<&[u8]>) -> Result<Reply> {
+        let mut delay = self.retry_delay;
+        let mut attempt = 1;
+        loop {
+            let failure = match self.attempt(method, url, headers, body)? {
+                Ok(reply) if (200..400).contains(&reply.status) => return Ok(reply),
+                Ok(reply) if reply.status != 429 && reply.status < 500 => {
+                    anyhow::bail!("{} {} failed with status {}: {}", method, url, reply.status, reply.body.trim())
+                }
+                Ok(reply) => format!("status {}: {}", reply.status, reply.body.trim()),
+                Err(transport) => transport,
+            };
+            if attempt == ATTEMPTS {
+                anyhow::bail!("{} {} failed after {} attempts, the last with {}", method, url, attempt, failure);
+            }
+            thread::sleep(delay);
+            delay *= 2;
+            attempt += 1;
+        }
+    }
+
+    /// One run of curl: the reply, or a transport error worth retrying
+    fn attempt(&self, method: &str, url: &str, headers: &[&str], body: Option<&[u8]>) -> Result<Result<Reply, String>> {
+        let mut command = Command::new("curl");
+        // No `Expect: 100-continue` round trip before each body
+        command.args(["--silent", "--show-error", "--include", "--request", method, "--header", "Expect:"]);
+        for header in headers {
+            command.args(["--header", header]);
+        }
+        match &self.auth {
+            Auth::SigV4 { region, key_id, secret, token } => {
+                // curl signs the payload hash given here rather than hashing the body itself
+                let mut hash = Sha256::default();
+                hash.update(body.unwrap_or_default());
+                command.args(["--header", &format!("x-amz-content-sha256: {}", hash.finalize_hex())]);
+                if let Some(token) = token {
+                    command.args(["--header", &format!("x-amz-security-token: {}", token)]);
+                }
+                command.args(["--aws-sigv4", &format!("aws:amz:{}:s3", region), "--user", &format!("{}:{}", key_id, secret)]);
+            }
+            Auth::Bearer(token) => {
+                command.args(["--header", &format!("Authorization: Bearer {}", token)]);
+            }
+            Auth::Sas(_) => {}
+        }
+        if body.is_some() {
+            command.args(["--data-binary", "@-"]);
+        }
+        command.arg(url).stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::piped());
+
+        let mut child = command.spawn().context("Failed to run curl")?;
+        let mut stdin = child.stdin.take();
+        let output = thread::scope(|scope| {
+            scope.spawn(move || {
+                if let (Some(stdin), Some(body)) = (stdin.as_mut(), body) {
+                    let _ = stdin.write_all(body);
+                }
+            });
+            child.wait_with_output()
+        })
+        .context("Failed to run curl")?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+            return Ok(Err(if stderr.is_empty() { "no response".to_string() } else { stderr }));
+        }
+        Ok(Reply::parse(&output.stdout).ok_or_else(|| "an unreadable response".to_string()))
+    }
+}
+
+/// Status, headers and body of an HTTP response
+#[derive(Debug)]
+struct Reply {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: String,
+}
+
+impl Reply {
+    /// Parse curl's `--include` output, skipping any interim `1xx` responses
+    fn parse(raw: &[u8]) -> Option<Self> {
+        let text = String::from_utf8_lossy(raw);
+        let mut rest = text.as_ref();
+        loop {
+            let (head, body) = rest.split_once("\r\n\r\n").unwrap_or((rest, ""));
+            let mut lines = head.lines();
+            let status: u16 = lines.next()?.split_whitespace().nth(1)?.parse().ok()?;
+            if (100..200).contains(&status) {
+                rest = body;
+                continue;
+            }
+            let headers = lines
+                .filter_map(|line| line.split_once(':'))
+                .map(|(name, value)| (name.trim().to_ascii_lowercase(), value.trim().to_string()))
+                .collect();
+            return Some(Self { status, headers, body: body.to_string() });
+        }
+    }
+
+    /// Value of a header, by lowercase name
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|(n, _)| n == name).map(|(_, value)| value.as_str())
+    }
+}
+
+/// The next `size` bytes of `file`, fewer at its end, or None once it is used up
+fn read_part(file: &mut File, size: usize) -> Result<Option<Vec<u8>>> {
+    let mut part = Vec::with_capacity(size);
+    file.take(size as u64).read_to_end(&mut part)?;
+    Ok((!part.is_empty()).then_some(part))
+}
+
+/// Text of the first `<tag>` element in an XML reply
+fn xml_text(xml: &str, tag: &str) -> Option<String> {
+    let (_, rest) = xml.split_once(&format!("<{}>", tag))?;
+    let (text, _) = rest.split_once(&format!("</{}>", tag))?;
+    Some(text.to_string())
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// Content type an uploaded file is served with
+fn content_type(path: &Path) -> &'static str {
+    match path.extension().and_then(|e| e.to_str()).map(|e| e.to_ascii_lowercase()).as_deref() {
+        Some("md" | "markdown") => "text/markdown; charset=utf-8",
+        Some("txt") => "text/plain; charset=utf-8",
+        Some("json") => "application/json",
+        Some("html" | "xhtml") => "text/html; charset=utf-8",
+        Some("css") => "text/css",
+        Some("ssml" | "xml") => "application/xml",
+        Some("png") => "image/png",
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("svg") => "image/svg+xml",
+        Some("webp") => "image/webp",
+        _ => "application/octet-stream",
+    }
+}
+
+/// An access token from the gcloud CLI
+fn gcloud_token() -> Result<String> {
+    let output = Command::new("gcloud").args(["auth", "print-access-token"]).output().context("Failed to run gcloud")?;
+    let token = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if !output.status.success() || token.is_empty() {
+        anyhow::bail!("gcloud auth print-access-token failed: {}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(token)
+}
+
+/// Uploads a directory of converted books to object storage
+pub struct Uploader {
+    client: Client,
+    destination: Destination,
+}
+
+impl Uploader {
+    /// An uploader for `destination`, failing up front if the environment lacks its
+    /// credentials (`AWS_ACCESS_KEY_ID`, `GOOGLE_OAUTH_ACCESS_TOKEN`, `AZURE_STORAGE_SAS_TOKEN`
+    /// and the like)
+    pub fn new(destination: &Destination) -> Result<Self> {
+        Ok(Self { client: Client::from_env(destination)?, destination: destination.clone() })
+    }
+
+    /// Copy every file under `dir` to the destination, keeping relative paths as keys
+    /// under its prefix, and return how many there were. Files are uploaded in parallel
+    /// over the provider's REST API. Existing objects are overwritten; objects not in
+    /// `dir` are left alone.
+    pub fn upload_dir(&self, dir: &Path) -> Result<usize> {
+        let files: Vec<PathBuf> = WalkDir::new(dir)
+            .into_iter()
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().is_file())
+            .map(|e| e.into_path())
+            .collect();
+        files.par_iter().try_for_each(|path| {
+            let relative = path.strip_prefix(dir).unwrap_or(path).to_string_lossy().replace('\\', "/");
+            self.client
+                .upload_file(path, &self.destination.bucket, &self.destination.key(&relative))
+                .with_context(|| format!("Failed to upload {} to {}", relative, self.destination))
+        })?;
+        Ok(files.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::check::ScratchDir;
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpListener;
+    use std::time::Instant;
+
+    /// A request as the stub server received it
+    struct Request {
+        method: String,
+        target: String,
+        headers: Vec<(String, String)>,
+        body: Vec<u8>,
+    }
+
+    impl Request {
+        fn header(&self, name: &str) -> Option<&str> {
+            self.headers.iter().find(|(n, _)| n == name).map(|(_, value)| value.as_str())
+        }
+    }
+
+    fn reply(status: u16, headers: &[&str], body: &str) -> String {
+        let mut out = format!("HTTP/1.1 {} Stub\r\nContent-Length: {}\r\nConnection: close\r\n", status, body.len());
+        for header in headers {
+            out.push_str(&format!("{}\r\n", header));
+        }
+        out.push_str(&format!("\r\n{}", body));
+        out
+    }
+
+    /// A local HTTP server that answers one connection per scripted reply, in order, and
+    /// hands back what it was sent. The replies are given the server's URL. It gives up
+    /// after a few seconds without a connection.
+    fn stub(replies: impl FnOnce(&str) -> Vec<String>) -> (String, thread::JoinHandle<Vec<Request>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let replies = replies(&url);
+        listener.set_nonblocking(true).unwrap();
+        let server = thread::spawn(move || {
+            let mut requests = Vec::new();
+            for reply in replies {
+                let deadline = Instant::now() + Duration::from_secs(5);
+                let mut stream = loop {
+                    match listener.accept() {
+                        Ok((stream, _)) => break stream,
+                        Err(_) if Instant::now() < deadline => thread::sleep(Duration::from_millis(10)),
+                        Err(_) => return requests,
+                    }
+                };
+                stream.set_nonblocking(false).unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let mut parts = line.split_whitespace();
+                let (method, target) = (parts.next().unwrap().to_string(), parts.next().unwrap().to_string());
+                let mut headers = Vec::new();
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    let Some((name, value)) = line.trim_end().split_once(':') else { break };
+                    headers.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+                }
+                let length = headers.iter().find(|(n, _)| n == "content-length").map_or(0, |(_, v)| v.parse().unwrap());
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                stream.write_all(reply.as_bytes()).unwrap();
+                requests.push(Request { method, target, headers, body });
+            }
+            requests
+        });
+        (url, server)
+    }
+
+    fn client(provider: Provider, endpoint: &str, auth: Auth) -> Client {
+        Client { provider, endpoint: endpoint.to_string(), auth, part_size: 4, retry_delay: Duration::ZERO }
+    }
+
+    fn sigv4() -> Auth {
+        Auth::SigV4 { region: "eu-west-1".to_string(), key_id: "KEY".to_string(), secret: "SECRET".to_string(), token: None }
+    }
+
+    fn file(dir: &ScratchDir, name: &str, data: &[u8]) -> PathBuf {
+        let path = dir.path().join(name);
+        fs::write(&path, data).unwrap();
+        path
+    }
+
+    #[test]
+    fn s3_puts_are_signed_and_retried() {
+        let dir = ScratchDir::new("upload-test").unwrap();
+        let path = file(&dir, "a.md", b"abc");
+        let (url, server) = stub(|_| vec![reply(503, &[], "slow down"), reply(200, &[], "")]);
+        client(Provider::S3, &url, sigv4()).upload_file(&path, "bucket", "books/a b.md").unwrap();
+
+        let requests = server.join().unwrap();
+        assert_eq!(requests.len(), 2);
+        for request in &requests {
+            assert_eq!((request.method.as_str(), request.target.as_str()), ("PUT", "/bucket/books/a%20b.md"));
+            assert_eq!(request.body, b"abc");
+            assert_eq!(request.header("content-type"), Some("text/markdown; charset=utf-8"));
+            assert!(request.header("authorization").unwrap().starts_with("AWS4-HMAC-SHA256 Credential=KEY/"));
+            let mut hash = Sha256::default();
+            hash.update(b"abc");
+            assert_eq!(request.header("x-amz-content-sha256"), Some(hash.finalize_hex().as_str()));
+        }
+    }
+
+    #[test]
+    fn client_errors_fail_without_retrying() {
+        let dir = ScratchDir::new("upload-test").unwrap();
+        let path = file(&dir, "a.md", b"abc");
+        // A retry would find the server gone and fail with a connection error instead
+        let (url, server) = stub(|_| vec![reply(403, &[], "AccessDenied")]);
+        let error = client(Provider::S3, &url, sigv4()).upload_file(&path, "bucket", "a.md").unwrap_err();
+        assert!(error.to_string().contains("failed with status 403"), "{}", error);
+        assert_eq!(server.join().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn gives_up_after_the_last_attempt() {
+        let dir = ScratchDir::new("upload-test").unwrap();
+        let path = file(&dir, "a.md", b"abc");
+        let (url, server) = stub(|_| (0..ATTEMPTS).map(|_| reply(500, &[], "")).collect());
+        let error = client(Provider::S3, &url, sigv4()).upload_file(&path, "bucket", "a.md").unwrap_err();
+        assert!(error.to_string().contains(&format!("after {} attempts", ATTEMPTS)), "{}", error);
+        assert_eq!(server.join().unwrap().len(), ATTEMPTS as usize);
+    }
+
+    #[test]
+    fn s3_uploads_large_files_in_parts() {
+        let dir = ScratchDir::new("upload-test").unwrap();
+        let path = file(&dir, "big.bin", b"0123456789");
+        let (url, server) = stub(|_| vec![
+            reply(200, &[], "<InitiateMultipartUploadResult><UploadId>u1</UploadId></InitiateMultipartUploadResult>"),
+            reply(200, &["ETag: \"e1\""], ""),
+            reply(200, &["ETag: \"e2\""], ""),
+            reply(200, &["ETag: \"e3\""], ""),
+            reply(200, &[], "<CompleteMultipartUploadResult/>"),
+        ]);
+        client(Provider::S3, &url, sigv4()).upload_file(&path, "b", "big.bin").unwrap();
+
+        let requests = server.join().unwrap();
+        let targets: Vec<(&str, &str)> = requests.iter().map(|r| (r.method.as_str(), r.target.as_str())).collect();
+        assert_eq!(
+            targets,
+            [
+                ("POST", "/b/big.bin?uploads"),
+                ("PUT", "/b/big.bin?partNumber=1&uploadId=u1"),
+                ("PUT", "/b/big.bin?partNumber=2&uploadId=u1"),
+                ("PUT", "/b/big.bin?partNumber=3&uploadId=u1"),
+                ("POST", "/b/big.bin?uploadId=u1"),
+            ]
+        );
+        let parts: Vec<&[u8]> = requests[1..4].iter().map(|r| r.body.as_slice()).collect();
+        assert_eq!(parts, [b"0123".as_slice(), b"4567", b"89"]);
+        let complete = String::from_utf8_lossy(&requests[4].body);
+        assert!(complete.contains("<Part><PartNumber>3</PartNumber><ETag>&quot;e3&quot;</ETag></Part>"), "{}", complete);
+    }
+
+    #[test]
+    fn s3_aborts_a_failed_multipart_upload() {
+        let dir = ScratchDir::new("upload-test").unwrap();
+        let path = file(&dir, "big.bin", b"0123456789");
+        let (url, server) = stub(|_| vec![
+            reply(200, &[], "<UploadId>u1</UploadId>"),
+            reply(400, &[], "EntityTooSmall"),
+            reply(204, &[], ""),
+        ]);
+        assert!(client(Provider::S3, &url, sigv4()).upload_file(&path, "b", "big.bin").is_err());
+
+        let requests = server.join().unwrap();
+        let last = requests.last().unwrap();
+        assert_eq!((last.method.as_str(), last.target.as_str()), ("DELETE", "/b/big.bin?uploadId=u1"));
+    }
+
+    #[test]
+    fn gcs_uploads_large_files_in_resumable_chunks() {
+        let dir = ScratchDir::new("upload-test").unwrap();
+        let path = file(&dir, "big.bin", b"0123456789");
+        let (url, server) = stub(|url| {
+            vec![
+                reply(200, &[&format!("Location: {}/session/1", url)], ""),
+                reply(308, &[], ""),
+                reply(308, &[], ""),
+                reply(200, &[], "{}"),
+            ]
+        });
+        client(Provider::Gcs, &url, Auth::Bearer("token".to_string())).upload_file(&path, "b", "books/big.bin").unwrap();
+
+        let requests = server.join().unwrap();
+        assert_eq!(requests[0].method, "POST");
+        assert_eq!(requests[0].target, "/upload/storage/v1/b/b/o?uploadType=resumable&name=books%2Fbig.bin");
+        assert_eq!(requests[0].header("authorization"), Some("Bearer token"));
+        let chunks: Vec<(&str, &str, &[u8])> =
+            requests[1..].iter().map(|r| (r.target.as_str(), r.header("content-range").unwrap(), r.body.as_slice())).collect();
+        assert_eq!(
+            chunks,
+            [
+                ("/session/1", "bytes 0-3/10", b"0123".as_slice()),
+                ("/session/1", "bytes 4-7/10", b"4567"),
+                ("/session/1", "bytes 8-9/10", b"89"),
+            ]
+        );
+    }
+
+    #[test]
+    fn azure_signs_with_the_sas_and_commits_blocks() {
+        let dir = ScratchDir::new("upload-test").unwrap();
+        let small = file(&dir, "a.md", b"abc");
+        let big = file(&dir, "big.bin", b"01234567");
+        let (url, server) = stub(|_| (0..4).map(|_| reply(201, &[], "")).collect());
+        let client = client(Provider::Azure, &url, Auth::Sas("sv=1&sig=x".to_string()));
+        client.upload_file(&small, "books", "a.md").unwrap();
+        client.upload_file(&big, "books", "big.bin").unwrap();
+
+        let requests = server.join().unwrap();
+        assert_eq!(requests[0].target, "/books/a.md?sv=1&sig=x");
+        assert_eq!(requests[0].header("x-ms-blob-type"), Some("BlockBlob"));
+        assert_eq!(requests[1].target, "/books/big.bin?comp=block&blockid=YmxvY2stMDAwMDAw&sv=1&sig=x");
+        assert_eq!(requests[2].target, "/books/big.bin?comp=block&blockid=YmxvY2stMDAwMDAx&sv=1&sig=x");
+        assert_eq!(requests[3].target, "/books/big.bin?comp=blocklist&sv=1&sig=x");
+        let list = String::from_utf8_lossy(&requests[3].body);
+        assert!(list.ends_with("<BlockList><Latest>YmxvY2stMDAwMDAw</Latest><Latest>YmxvY2stMDAwMDAx</Latest></BlockList>"), "{}", list);
+    }
+
+    #[test]
+    fn encodes_base64() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64(b"block-000000"), "YmxvY2stMDAwMDAw");
+    }
+}
@@ -0,0 +1,201 @@
+use crate::analysis;
+use crate::catalog::csv_field;
+use clap::ValueEnum;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+
+/// How `--vocab` writes a book's vocabulary
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum VocabFormat {
+    /// vocabulary.csv, one row per lemma
+    Csv,
+    /// vocabulary.json, with each chapter's totals as well
+    Json,
+}
+
+impl VocabFormat {
+    pub fn file_name(self) -> &'static str {
+        match self {
+            VocabFormat::Csv => "vocabulary.csv",
+            VocabFormat::Json => "vocabulary.json",
+        }
+    }
+}
+
+/// Shortest base form an inflection is traced back to, so "us" isn't taken for the
+/// plural of "u"
+const MIN_LEMMA_LEN: usize = 3;
+
+/// English forms whose lemma can't be found by stripping a suffix
+const IRREGULAR: &[(&str, &str)] = &[
+    ("am", "be"), ("are", "be"), ("ate", "eat"), ("began", "begin"), ("begun", "begin"), ("been", "be"), ("being", "be"),
+    ("bought", "buy"), ("brought", "bring"), ("built", "build"), ("came", "come"), ("caught", "catch"), ("children", "child"),
+    ("chose", "choose"), ("chosen", "choose"), ("did", "do"), ("does", "do"), ("done", "do"), ("drank", "drink"),
+    ("drawn", "draw"), ("drew", "draw"), ("driven", "drive"), ("drove", "drive"), ("eaten", "eat"), ("fallen", "fall"),
+    ("feet", "foot"), ("fell", "fall"), ("felt", "feel"), ("fought", "fight"), ("found", "find"), ("flew", "fly"),
+    ("forgot", "forget"), ("forgotten", "forget"), ("gave", "give"), ("geese", "goose"), ("given", "give"), ("goes", "go"),
+    ("gone", "go"), ("got", "get"), ("gotten", "get"), ("grew", "grow"), ("grown", "grow"), ("had", "have"), ("has", "have"),
+    ("having", "have"), ("heard", "hear"), ("held", "hold"), ("is", "be"), ("kept", "keep"), ("knew", "know"),
+    ("known", "know"), ("knives", "knife"), ("led", "lead"), ("left", "leave"), ("lives", "life"), ("lost", "lose"),
+    ("made", "make"), ("meant", "mean"), ("men", "man"), ("met", "meet"), ("mice", "mouse"), ("paid", "pay"), ("ran", "run"),
+    ("rode", "ride"), ("rose", "rise"), ("said", "say"), ("sang", "sing"), ("sat", "sit"), ("saw", "see"), ("seen", "see"),
+    ("sent", "send"), ("shook", "shake"), ("slept", "sleep"), ("sold", "sell"), ("sought", "seek"), ("spoke", "speak"),
+    ("spoken", "speak"), ("stood", "stand"), ("struck", "strike"), ("taken", "take"), ("taught", "teach"), ("teeth", "tooth"),
+    ("thought", "think"), ("threw", "throw"), ("thrown", "throw"), ("told", "tell"), ("took", "take"),
+    ("understood", "understand"), ("was", "be"), ("went", "go"), ("were", "be"), ("wives", "wife"), ("women", "woman"),
+    ("won", "win"), ("wore", "wear"), ("worn", "wear"), ("wrote", "write"), ("written", "write"),
+];
+
+/// English words that look inflected but aren't
+const UNINFLECTED: &[&str] = &[
+    "always", "evening", "hers", "news", "perhaps", "series", "species", "towards", "afterwards", "whereas", "yes",
+];
+
+/// Collects a book's word counts chapter by chapter, for `--vocab`
+#[derive(Default)]
+pub struct VocabularyCounter {
+    words: usize,
+    /// Each chapter's count of each word, in lower case
+    chapters: Vec<(usize, HashMap<String, usize>)>,
+}
+
+/// vocabulary.json
+#[derive(Debug, Serialize)]
+pub struct Vocabulary {
+    /// Words in the converted text
+    pub words: usize,
+    /// Distinct lemmas
+    pub lemmas: usize,
+    /// Most frequent first
+    pub entries: Vec<Entry>,
+    pub chapters: Vec<ChapterVocabulary>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Entry {
+    pub rank: usize,
+    pub lemma: String,
+    pub count: usize,
+    /// How often each form of the lemma appears
+    pub forms: BTreeMap<String, usize>,
+    /// How often the lemma appears in each chapter, by chapter number
+    pub chapters: BTreeMap<usize, usize>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChapterVocabulary {
+    pub chapter: usize,
+    pub words: usize,
+    /// Distinct lemmas in the chapter
+    pub lemmas: usize,
+}
+
+impl VocabularyCounter {
+    pub fn add_chapter(&mut self, number: usize, markdown: &str) {
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        for line in analysis::prose_lines(markdown) {
+            for word in analysis::words(&line).filter(|w| w.chars().any(char::is_alphabetic)) {
+                *counts.entry(word.to_lowercase().replace('’', "'")).or_default() += 1;
+                self.words += 1;
+            }
+        }
+        self.chapters.push((number, counts));
+    }
+
+    /// Group the words into lemmas and rank them. English inflections are traced back to
+    /// the base form the book itself uses, so "walked" is counted under "walk" only when
+    /// "walk" appears too; in other languages each form is its own lemma.
+    pub fn finish(self, english: bool) -> Vocabulary {
+        let mut totals: HashMap<&str, usize> = HashMap::new();
+        for (_, counts) in &self.chapters {
+            for (word, count) in counts {
+                *totals.entry(word).or_default() += count;
+            }
+        }
+        let lemma_of: HashMap<&str, String> =
+            totals.keys().map(|&word| (word, if english { lemma(word, &totals) } else { word.to_string() })).collect();
+
+        let mut entries: HashMap<&str, Entry> = HashMap::new();
+        let mut chapters = Vec::with_capacity(self.chapters.len());
+        for (number, counts) in &self.chapters {
+            let mut lemmas: Vec<&str> = Vec::new();
+            for (word, &count) in counts {
+                let lemma = lemma_of[word.as_str()].as_str();
+                let entry = entries.entry(lemma).or_insert_with(|| Entry {
+                    rank: 0,
+                    lemma: lemma.to_string(),
+                    count: 0,
+                    forms: BTreeMap::new(),
+                    chapters: BTreeMap::new(),
+                });
+                entry.count += count;
+                *entry.forms.entry(word.clone()).or_default() += count;
+                *entry.chapters.entry(*number).or_default() += count;
+                lemmas.push(lemma);
+            }
+            lemmas.sort_unstable();
+            lemmas.dedup();
+            chapters.push(ChapterVocabulary { chapter: *number, words: counts.values().sum(), lemmas: lemmas.len() });
+        }
+
+        let mut entries: Vec<Entry> = entries.into_values().collect();
+        entries.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.lemma.cmp(&b.lemma)));
+        for (i, entry) in entries.iter_mut().enumerate() {
+            entry.rank = i + 1;
+        }
+        Vocabulary { words: self.words, lemmas: entries.len(), entries, chapters }
+    }
+}
+
+impl Vocabulary {
+    /// One row per lemma; forms and chapters are `form:count` pairs, separated by `; `
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("rank,lemma,count,chapters,forms\n");
+        let pairs = |pairs: Vec<String>| pairs.join("; ");
+        for entry in &self.entries {
+            let chapters = pairs(entry.chapters.iter().map(|(chapter, count)| format!("{}:{}", chapter, count)).collect());
+            let forms = pairs(entry.forms.iter().map(|(form, count)| format!("{}:{}", form, count)).collect());
+            csv.push_str(&format!("{},{},{},{},{}\n", entry.rank, csv_field(&entry.lemma), entry.count, csv_field(&chapters), csv_field(&forms)));
+        }
+        csv
+    }
+}
+
+/// The lemma of an English word: its irregular base, else the first base form its
+/// suffix could have been added to that also appears in `words`, else the word itself
+fn lemma(word: &str, words: &HashMap<&str, usize>) -> String {
+    if let Some((_, base)) = IRREGULAR.iter().find(|(form, _)| *form == word) {
+        return base.to_string();
+    }
+    if UNINFLECTED.contains(&word) {
+        return word.to_string();
+    }
+    let candidates: Vec<String> = if let Some(stem) = word.strip_suffix("ies") {
+        vec![format!("{}y", stem), format!("{}ie", stem)]
+    } else if let Some(stem) = word.strip_suffix("ied") {
+        vec![format!("{}y", stem)]
+    } else if let Some(stem) = word.strip_suffix("es") {
+        vec![format!("{}e", stem), stem.to_string()]
+    } else if let Some(stem) = word.strip_suffix('s').filter(|s| !s.ends_with(['s', 'u', 'i', '\''])) {
+        vec![stem.to_string()]
+    } else if let Some(stem) = word.strip_suffix("ed").or_else(|| word.strip_suffix("ing")) {
+        // "hoped" is "hope", "stopping" is "stop"
+        let mut candidates = vec![stem.to_string(), format!("{}e", stem)];
+        let mut chars = stem.chars().rev();
+        if let (Some(last), Some(before)) = (chars.next(), chars.next()) {
+            if last == before && !matches!(last, 'l' | 's' | 'z' | 'f') {
+                candidates.push(stem[..stem.len() - last.len_utf8()].to_string());
+            }
+        }
+        candidates
+    } else {
+        Vec::new()
+    };
+    // A stem with no vowel ("th" of "thing", "sh" of "shed") isn't a word the suffix was added to
+    let has_vowel = |s: &str| s.chars().any(|c| "aeiouy".contains(c));
+    candidates
+        .into_iter()
+        .filter(|c| c != word && c.chars().count() >= MIN_LEMMA_LEN && has_vowel(c.strip_suffix('e').unwrap_or(c)))
+        .find(|c| words.contains_key(c.as_str()))
+        .unwrap_or_else(|| word.to_string())
+}
@@ -0,0 +1,165 @@
+use crate::html;
+use crate::toc;
+use epub::doc::NavPoint;
+use markup5ever_rcdom::NodeData;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+/// Something lost or doubtful in a conversion that didn't stop it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WarningKind {
+    /// An image a chapter shows isn't in the archive
+    MissingImage,
+    /// An entity reference the parser didn't know, left in the text as written
+    UndecodableEntity,
+    /// A spine document that isn't valid UTF-8, so it was left out
+    InvalidEncoding,
+    /// A spine entry naming no manifest item, so it was left out
+    MissingManifestItem,
+    /// A spine document listed in the package but missing from the archive, so it was left out
+    MissingResource,
+    /// A spine item of media with no renderable fallback, so it was left out
+    UnsupportedMedia,
+    /// The book has no table of contents
+    EmptyToc,
+    /// A spine document rendering exactly like an earlier one, so it was left out
+    DuplicateChapter,
+    /// A table-of-contents entry listed after one the spine puts later
+    TocOrder,
+    /// A table-of-contents entry pointing at a document outside the spine
+    TocOutsideSpine,
+    /// External links `--check-links` found broken
+    BrokenLinks,
+}
+
+/// A warning about one book, as listed in metadata.json
+#[derive(Debug, Clone, Serialize)]
+pub struct Warning {
+    pub kind: WarningKind,
+    pub message: String,
+    /// Path inside the EPUB of the document it concerns
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+}
+
+impl Warning {
+    pub fn new(kind: WarningKind, message: impl Into<String>, source: Option<&str>) -> Self {
+        Self { kind, message: message.into(), source: source.map(str::to_string) }
+    }
+}
+
+/// Entity references shown in a warning before the rest are counted
+const ENTITY_SAMPLES: usize = 3;
+
+/// Warnings about one spine document: the images it shows that aren't among `archive`'s
+/// entries, and entity references the parser couldn't decode
+pub fn check_chapter(content: &str, source: &str, archive: &HashSet<String>) -> Vec<Warning> {
+    let dom = html::parse(content);
+    let dir = source.rsplit_once('/').map(|(dir, _)| dir).unwrap_or("");
+    let mut missing: Vec<String> = Vec::new();
+    let mut entities: Vec<String> = Vec::new();
+    html::walk(&dom.document, &mut |node| match node.data {
+        NodeData::Text { ref contents } => {
+            for entity in entity_refs(&contents.borrow()) {
+                if !entities.contains(&entity) {
+                    entities.push(entity);
+                }
+            }
+        }
+        NodeData::Element { .. } => {
+            let src = match html::tag_name(node).as_deref() {
+                Some("img") => html::attr(node, "src"),
+                // SVG images use href, or xlink:href whose prefix the HTML parser keeps
+                Some("image") => html::attr(node, "href").or_else(|| html::attr(node, "xlink:href")),
+                _ => None,
+            };
+            let Some(src) = src.map(|s| s.trim().to_string()).filter(|s| !s.is_empty()) else { return };
+            if src.contains("://") || src.starts_with("data:") {
+                return;
+            }
+            let path = toc::target_path(Path::new(&format!("{}/{}", dir, src.split('?').next().unwrap_or_default())));
+            if !archive.contains(&path) && !missing.contains(&path) {
+                missing.push(path);
+            }
+        }
+        _ => {}
+    });
+
+    let mut warnings: Vec<Warning> = missing
+        .into_iter()
+        .map(|path| Warning::new(WarningKind::MissingImage, format!("Image {} is missing from the archive", path), Some(source)))
+        .collect();
+    if !entities.is_empty() {
+        let mut shown = entities.iter().take(ENTITY_SAMPLES).cloned().collect::<Vec<_>>().join(", ");
+        if entities.len() > ENTITY_SAMPLES {
+            shown.push_str(&format!(" and {} more", entities.len() - ENTITY_SAMPLES));
+        }
+        let message = format!("Undecodable entity reference(s) left as text: {}", shown);
+        warnings.push(Warning::new(WarningKind::UndecodableEntity, message, Some(source)));
+    }
+    warnings
+}
+
+/// Warnings about a table of contents that disagrees with the spine: entries pointing at
+/// documents the spine doesn't list, whose text is never converted, and entries listed
+/// after one the spine has later, which puts the output in a different order than the
+/// contents suggest
+pub fn check_reading_order(toc: &[NavPoint], spine: &[String]) -> Vec<Warning> {
+    let mut positions: HashMap<&str, usize> = HashMap::new();
+    for (i, source) in spine.iter().enumerate() {
+        positions.entry(source.as_str()).or_insert(i);
+    }
+    let mut entries: Vec<(&str, String)> = Vec::new();
+    flatten(toc, &mut entries);
+
+    let mut warnings = Vec::new();
+    let mut outside: HashSet<String> = HashSet::new();
+    // Latest spine position listed so far, and the entry that listed it
+    let mut furthest: Option<(usize, &str)> = None;
+    for (label, path) in &entries {
+        if path.is_empty() {
+            continue;
+        }
+        let Some(&position) = positions.get(path.as_str()) else {
+            if outside.insert(path.clone()) {
+                let message = format!("Contents entry \"{}\" points at a document that isn't in the spine, so it isn't converted", label);
+                warnings.push(Warning::new(WarningKind::TocOutsideSpine, message, Some(path)));
+            }
+            continue;
+        };
+        match furthest {
+            Some((latest, after)) if position < latest => {
+                let message = format!("Contents entry \"{}\" is listed after \"{}\", but comes before it in the spine", label, after);
+                warnings.push(Warning::new(WarningKind::TocOrder, message, Some(path)));
+            }
+            _ => furthest = Some((position, label)),
+        }
+    }
+    warnings
+}
+
+/// Table-of-contents entries in the order they are listed, with their target documents
+fn flatten<'a>(points: &'a [NavPoint], out: &mut Vec<(&'a str, String)>) {
+    for point in points {
+        out.push((point.label.trim(), toc::target_path(&point.content)));
+        flatten(&point.children, out);
+    }
+}
+
+/// `&name;` sequences surviving in parsed text, which the parser didn't recognize
+fn entity_refs(text: &str) -> Vec<String> {
+    let mut found = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        let after = &rest[start + 1..];
+        let name_len = after.find(|c: char| !c.is_ascii_alphanumeric()).unwrap_or(after.len());
+        let name = &after[..name_len];
+        if name.starts_with(|c: char| c.is_ascii_alphabetic()) && after[name_len..].starts_with(';') {
+            found.push(format!("&{};", name));
+        }
+        rest = after;
+    }
+    found
+}